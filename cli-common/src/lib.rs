@@ -30,16 +30,21 @@ use std::borrow::Cow;
 use std::ffi::OsStr;
 use std::path::Path;
 use bp3d_fs::utils::PathExt;
-use log::LevelFilter;
+
+mod verbosity;
+mod libs;
+mod watch;
+mod report;
+mod response;
+
+pub use verbosity::{Directives, DirectiveError, Filtered, split_verbose_directives, TARGETS, verbosity_to_level};
+pub use libs::{dedup_libs, LibError};
+pub use watch::{interrupt_flag, Watcher, WatchError, DEFAULT_DEBOUNCE};
+pub use report::{Category, Report, report_error, run_cli};
+pub use response::{expand_response_files, ResponseError};
 
 pub fn alloc_verbosity_level(verbosity: u64) {
-    match verbosity {
-        0 => log::set_max_level(LevelFilter::Error),
-        1 => log::set_max_level(LevelFilter::Warn),
-        2 => log::set_max_level(LevelFilter::Info),
-        3 => log::set_max_level(LevelFilter::Debug),
-        _ => log::set_max_level(LevelFilter::Trace),
-    };
+    log::set_max_level(verbosity_to_level(verbosity));
 }
 
 pub fn init_bp3d_logger<F: FnOnce() -> i32>(f: F) {
@@ -48,6 +53,26 @@ pub fn init_bp3d_logger<F: FnOnce() -> i32>(f: F) {
     std::process::exit(res);
 }
 
+/// Same as init_bp3d_logger when no directive is scoped, otherwise installs a filtered
+/// stdout logger as bp3d-logger cannot be wrapped.
+pub fn init_logger<F: FnOnce() -> i32>(directives: Directives, f: F) {
+    let level = directives.max_level();
+    if !directives.is_scoped() {
+        return init_bp3d_logger(|| {
+            log::set_max_level(level);
+            f()
+        });
+    }
+    log::set_max_level(level);
+    let logger = Box::new(Filtered::new(verbosity::StdoutLogger, directives));
+    if log::set_logger(Box::leak(logger)).is_err() {
+        eprintln!("A logger is already installed, log directives are ignored");
+    }
+    let res = f();
+    log::logger().flush();
+    std::process::exit(res);
+}
+
 pub fn get_out_path(arg: Option<&OsStr>) -> Cow<Path> {
     arg.map(Path::new).unwrap_or(Path::new("a.out.bpx")).ensure_extension("bpx")
 }
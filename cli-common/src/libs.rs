@@ -0,0 +1,176 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use log::info;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LibError
+{
+    /// The library resolves to the output file.
+    Output(PathBuf)
+}
+
+impl Display for LibError
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+    {
+        match self {
+            LibError::Output(v) => write!(f, "output file cannot also be used as a library input ({})", v.display())
+        }
+    }
+}
+
+impl std::error::Error for LibError {}
+
+/// Identity of a file: paths leading to the same file, through symlinks or a different case on
+/// case-insensitive file systems, have the same identity.
+#[cfg(unix)]
+type FileId = (u64, u64);
+#[cfg(not(unix))]
+type FileId = PathBuf;
+
+#[cfg(unix)]
+fn file_id(path: &Path) -> Option<FileId>
+{
+    use std::os::unix::fs::MetadataExt;
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_id(path: &Path) -> Option<FileId>
+{
+    std::fs::canonicalize(path).ok()
+}
+
+/// Removes duplicate libraries and fails if one of them is the output file.
+///
+/// Paths which cannot be resolved are kept as is so that the error is reported when opening them.
+pub fn dedup_libs<'a>(libs: &[&'a Path], output: &Path) -> Result<Vec<&'a Path>, LibError>
+{
+    let output = file_id(output);
+    let mut ids: Vec<(FileId, &Path)> = Vec::new();
+    let mut res = Vec::with_capacity(libs.len());
+    for lib in libs {
+        let id = match file_id(lib) {
+            Some(v) => v,
+            None => {
+                res.push(*lib);
+                continue;
+            }
+        };
+        if output.as_ref() == Some(&id) {
+            return Err(LibError::Output(lib.into()));
+        }
+        if let Some((_, first)) = ids.iter().find(|(v, _)| *v == id) {
+            info!("Ignoring library {} (same file as {})", lib.display(), first.display());
+            continue;
+        }
+        ids.push((id, lib));
+        res.push(*lib);
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::path::{Path, PathBuf};
+    use crate::libs::{dedup_libs, LibError};
+
+    fn temp_dir(name: &str) -> PathBuf
+    {
+        let dir = std::env::temp_dir().join(format!("cli-common-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn duplicates()
+    {
+        let dir = temp_dir("duplicates");
+        let a = dir.join("a.bpx");
+        let b = dir.join("b.bpx");
+        std::fs::write(&a, b"a").unwrap();
+        std::fs::write(&b, b"b").unwrap();
+        let dotted = dir.join(".").join("a.bpx");
+        let missing = dir.join("missing.bpx");
+        let libs = [a.as_path(), b.as_path(), dotted.as_path(), missing.as_path()];
+        let res = dedup_libs(&libs, &dir.join("out.bpx")).unwrap();
+        assert_eq!(res, [a.as_path(), b.as_path(), missing.as_path()]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink()
+    {
+        let dir = temp_dir("symlink");
+        let a = dir.join("a.bpx");
+        let link = dir.join("link.bpx");
+        std::fs::write(&a, b"a").unwrap();
+        std::os::unix::fs::symlink(&a, &link).unwrap();
+        let res = dedup_libs(&[link.as_path(), a.as_path()], &dir.join("out.bpx")).unwrap();
+        assert_eq!(res, [link.as_path()]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn case_insensitive()
+    {
+        let dir = temp_dir("case");
+        let upper = dir.join("Common.bpx");
+        let lower = dir.join("common.bpx");
+        std::fs::write(&upper, b"a").unwrap();
+        let res = dedup_libs(&[upper.as_path(), lower.as_path()], &dir.join("out.bpx")).unwrap();
+        if lower.exists() {
+            //Case-insensitive file system: both names lead to the same file.
+            assert_eq!(res, [upper.as_path()]);
+        } else {
+            assert_eq!(res, [upper.as_path(), lower.as_path()]);
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn self_reference()
+    {
+        let dir = temp_dir("self");
+        let out = dir.join("out.bpx");
+        std::fs::write(&out, b"old").unwrap();
+        let lib = dir.join(".").join("out.bpx");
+        let err = dedup_libs(&[lib.as_path()], &out).unwrap_err();
+        assert_eq!(err, LibError::Output(lib.clone()));
+        assert!(err.to_string().starts_with("output file cannot also be used as a library input"));
+        //An output which does not exist yet cannot be a library.
+        assert!(dedup_libs(&[lib.as_path()], Path::new("does-not-exist.bpx")).is_ok());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
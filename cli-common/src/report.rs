@@ -0,0 +1,188 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Shared error reporting for the command line tools.
+//!
+//! Exit codes are stable so that scripts can tell failures apart:
+//!
+//! | Code | Category  | Meaning                                         |
+//! |------|-----------|-------------------------------------------------|
+//! | 0    |           | Success                                         |
+//! | 1    | Other     | Unclassified failure                            |
+//! | 2    | Usage     | Invalid command line arguments                  |
+//! | 3    | Input     | Invalid shader source or other input            |
+//! | 4    | Toolchain | The shader compiler (glslang) rejected the code |
+//! | 5    | Format    | Corrupt or unsupported BPX file                 |
+//! | 6    | Io        | A file could not be read or written             |
+
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use log::error;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Category
+{
+    Io,
+    Input,
+    Toolchain,
+    Format,
+    Usage,
+    Other
+}
+
+impl Category
+{
+    pub fn exit_code(&self) -> i32
+    {
+        match self {
+            Category::Other => 1,
+            Category::Usage => 2,
+            Category::Input => 3,
+            Category::Toolchain => 4,
+            Category::Format => 5,
+            Category::Io => 6
+        }
+    }
+}
+
+impl Display for Category
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+    {
+        match self {
+            Category::Io => f.write_str("io"),
+            Category::Input => f.write_str("input"),
+            Category::Toolchain => f.write_str("toolchain"),
+            Category::Format => f.write_str("format"),
+            Category::Usage => f.write_str("usage"),
+            Category::Other => f.write_str("other")
+        }
+    }
+}
+
+/// An error which can be reported by [run_cli].
+pub trait Report: Error
+{
+    fn category(&self) -> Category;
+}
+
+/// Logs an error followed by the chain of its sources.
+pub fn report_error(e: &dyn Error)
+{
+    error!("{}", e);
+    let mut source = e.source();
+    while let Some(v) = source {
+        error!("  caused by: {}", v);
+        source = v.source();
+    }
+}
+
+/// Runs a command, reporting its error if any, and returns the process exit code.
+///
+/// Meant to be called from the closure given to [init_logger](crate::init_logger).
+pub fn run_cli<E: Report, F: FnOnce() -> Result<(), E>>(f: F) -> i32
+{
+    match f() {
+        Ok(()) => 0,
+        Err(e) => {
+            report_error(&e);
+            e.category().exit_code()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::fmt::{Display, Formatter};
+    use super::*;
+
+    #[derive(Debug)]
+    enum TestError
+    {
+        Io(std::io::Error),
+        Syntax,
+        Usage
+    }
+
+    impl Display for TestError
+    {
+        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+        {
+            match self {
+                TestError::Io(e) => write!(f, "io error: {}", e),
+                TestError::Syntax => f.write_str("syntax error"),
+                TestError::Usage => f.write_str("bad argument")
+            }
+        }
+    }
+
+    impl Error for TestError
+    {
+        fn source(&self) -> Option<&(dyn Error + 'static)>
+        {
+            match self {
+                TestError::Io(e) => Some(e),
+                _ => None
+            }
+        }
+    }
+
+    impl Report for TestError
+    {
+        fn category(&self) -> Category
+        {
+            match self {
+                TestError::Io(_) => Category::Io,
+                TestError::Syntax => Category::Input,
+                TestError::Usage => Category::Usage
+            }
+        }
+    }
+
+    #[test]
+    fn exit_codes()
+    {
+        assert_eq!(run_cli::<TestError, _>(|| Ok(())), 0);
+        assert_eq!(run_cli(|| Err(TestError::Usage)), 2);
+        assert_eq!(run_cli(|| Err(TestError::Syntax)), 3);
+        let io = std::io::Error::new(std::io::ErrorKind::NotFound, "missing");
+        assert_eq!(run_cli(|| Err(TestError::Io(io))), 6);
+    }
+
+    #[test]
+    fn distinct_codes()
+    {
+        let all = [Category::Io, Category::Input, Category::Toolchain, Category::Format, Category::Usage, Category::Other];
+        let mut codes: Vec<i32> = all.iter().map(|v| v.exit_code()).collect();
+        codes.sort_unstable();
+        codes.dedup();
+        assert_eq!(codes.len(), all.len());
+        assert!(!codes.contains(&0));
+    }
+}
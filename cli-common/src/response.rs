@@ -0,0 +1,203 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+
+//! Response files, used to pass more arguments than the command line of the OS allows.
+//!
+//! An argument of the form `@<path>` is replaced by the content of the file at `<path>`, one
+//! argument per line. Blank lines and lines starting with `#` are skipped and surrounding
+//! whitespace is trimmed. A line wrapped in double quotes is taken literally, which keeps
+//! surrounding whitespace and allows arguments starting with `#` or `@`. Response files may
+//! reference other response files; relative paths are relative to the working directory.
+
+use std::ffi::OsString;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use crate::report::{Category, Report};
+
+#[derive(Debug)]
+pub enum ResponseError
+{
+    /// A response file could not be read; the location is the line referencing a nested file.
+    Io(PathBuf, Option<(PathBuf, usize)>, std::io::Error),
+    UnterminatedQuote(PathBuf, usize),
+    /// A response file references itself, directly or through other response files.
+    Recursive(PathBuf, usize, PathBuf)
+}
+
+impl Display for ResponseError
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+    {
+        match self {
+            ResponseError::Io(path, None, e) => write!(f, "failed to read response file {}: {}", path.display(), e),
+            ResponseError::Io(path, Some((file, line)), e) => write!(f, "{}:{}: failed to read response file {}: {}", file.display(), line, path.display(), e),
+            ResponseError::UnterminatedQuote(file, line) => write!(f, "{}:{}: unterminated quote", file.display(), line),
+            ResponseError::Recursive(file, line, path) => write!(f, "{}:{}: recursive response file {}", file.display(), line, path.display())
+        }
+    }
+}
+
+impl std::error::Error for ResponseError {}
+
+impl Report for ResponseError
+{
+    fn category(&self) -> Category
+    {
+        match self {
+            ResponseError::Io(_, _, _) => Category::Io,
+            _ => Category::Usage
+        }
+    }
+}
+
+fn response_file(arg: &str) -> Option<&Path>
+{
+    arg.strip_prefix('@').filter(|v| !v.is_empty()).map(Path::new)
+}
+
+fn expand_file(path: &Path, from: Option<(&Path, usize)>, stack: &mut Vec<PathBuf>, out: &mut Vec<OsString>) -> Result<(), ResponseError>
+{
+    let io_error = |e| ResponseError::Io(path.into(), from.map(|(file, line)| (file.into(), line)), e);
+    let id = std::fs::canonicalize(path).map_err(io_error)?;
+    let content = std::fs::read_to_string(path).map_err(io_error)?;
+    stack.push(id);
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(quoted) = line.strip_prefix('"') {
+            let arg = quoted.strip_suffix('"').ok_or_else(|| ResponseError::UnterminatedQuote(path.into(), i + 1))?;
+            out.push(arg.into());
+            continue;
+        }
+        match response_file(line) {
+            Some(nested) => {
+                if std::fs::canonicalize(nested).map(|id| stack.contains(&id)).unwrap_or(false) {
+                    return Err(ResponseError::Recursive(path.into(), i + 1, nested.into()));
+                }
+                expand_file(nested, Some((path, i + 1)), stack, out)?;
+            },
+            None => out.push(line.into())
+        }
+    }
+    stack.pop();
+    Ok(())
+}
+
+/// Replaces every `@<path>` argument by the arguments listed in the response file at `<path>`.
+///
+/// The first argument is the program name and is never expanded.
+pub fn expand_response_files(mut args: impl Iterator<Item = OsString>) -> Result<Vec<OsString>, ResponseError>
+{
+    let mut res: Vec<OsString> = args.next().into_iter().collect();
+    let mut stack = Vec::new();
+    for arg in args {
+        match arg.to_str().and_then(response_file) {
+            Some(path) => expand_file(path, None, &mut stack, &mut res)?,
+            None => res.push(arg)
+        }
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::ffi::OsString;
+    use std::path::{Path, PathBuf};
+    use crate::response::{expand_response_files, ResponseError};
+
+    fn temp_dir(name: &str) -> PathBuf
+    {
+        let dir = std::env::temp_dir().join(format!("cli-common-rsp-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn expand(args: &[&str]) -> Result<Vec<OsString>, ResponseError>
+    {
+        expand_response_files(args.iter().map(OsString::from))
+    }
+
+    fn at(path: &Path) -> String
+    {
+        format!("@{}", path.display())
+    }
+
+    #[test]
+    fn nested()
+    {
+        let dir = temp_dir("nested");
+        let libs = dir.join("libs.rsp");
+        let units = dir.join("units.rsp");
+        std::fs::write(&libs, "# libraries\n--lib=common.bpx\n\n--lib=lighting.bpx\n").unwrap();
+        std::fs::write(&units, format!("{}\r\nshaders/a.sal\n  shaders/b.sal  \n", at(&libs))).unwrap();
+        let res = expand(&["shaderc", "@", "-t", "GL40", &at(&units), "c.sal"]).unwrap();
+        assert_eq!(res, ["shaderc", "@", "-t", "GL40", "--lib=common.bpx", "--lib=lighting.bpx", "shaders/a.sal", "shaders/b.sal", "c.sal"]);
+        //The program name is never a response file.
+        assert_eq!(expand(&[&at(&units)]).unwrap(), [at(&units).as_str()]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn quoting()
+    {
+        let dir = temp_dir("quoting");
+        let file = dir.join("args.rsp");
+        std::fs::write(&file, "\"my shaders/a b.sal\"\n\"#not a comment\"\n\"@not-a-file\"\n\" padded \"\n").unwrap();
+        let res = expand(&["shaderc", &at(&file)]).unwrap();
+        assert_eq!(res, ["shaderc", "my shaders/a b.sal", "#not a comment", "@not-a-file", " padded "]);
+        std::fs::write(&file, "a.sal\n\"b.sal\n").unwrap();
+        let err = expand(&["shaderc", &at(&file)]).unwrap_err();
+        assert!(matches!(&err, ResponseError::UnterminatedQuote(path, 2) if path == &file));
+        assert_eq!(err.to_string(), format!("{}:2: unterminated quote", file.display()));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn recursion()
+    {
+        let dir = temp_dir("recursion");
+        let a = dir.join("a.rsp");
+        let b = dir.join("b.rsp");
+        std::fs::write(&a, format!("a.sal\n{}\n", at(&b))).unwrap();
+        std::fs::write(&b, format!("b.sal\n\n{}\n", at(&a))).unwrap();
+        let err = expand(&["shaderc", &at(&a)]).unwrap_err();
+        assert!(matches!(&err, ResponseError::Recursive(file, 3, path) if file == &b && path == &a));
+        //Using the same response file twice is not a recursion.
+        std::fs::write(&b, "b.sal\n").unwrap();
+        assert_eq!(expand(&["shaderc", &at(&b), &at(&b)]).unwrap(), ["shaderc", "b.sal", "b.sal"]);
+        let missing = dir.join("missing.rsp");
+        std::fs::write(&a, at(&missing)).unwrap();
+        let err = expand(&["shaderc", &at(&a)]).unwrap_err();
+        assert!(err.to_string().starts_with(&format!("{}:1: failed to read response file {}", a.display(), missing.display())));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,364 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use std::ffi::OsString;
+use std::fmt::{Display, Formatter};
+use std::io::Write;
+use std::str::FromStr;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+/// Log targets used by the compilers; a scope also matches any target nested under it (`a::b`).
+pub const TARGETS: &[&str] = &["relocation", "translation", "compile", "link", "write"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DirectiveError
+{
+    Empty,
+    Level(String),
+    Scope(String)
+}
+
+impl Display for DirectiveError
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+    {
+        match self {
+            DirectiveError::Empty => f.write_str("empty log directive"),
+            DirectiveError::Level(v) => write!(f, "unknown log level '{}'", v),
+            DirectiveError::Scope(v) => write!(f, "invalid log scope '{}'", v)
+        }
+    }
+}
+
+impl std::error::Error for DirectiveError {}
+
+pub fn verbosity_to_level(verbosity: u64) -> LevelFilter
+{
+    match verbosity {
+        0 => LevelFilter::Error,
+        1 => LevelFilter::Warn,
+        2 => LevelFilter::Info,
+        3 => LevelFilter::Debug,
+        _ => LevelFilter::Trace
+    }
+}
+
+/// Per target log levels parsed from directives such as `relocation=trace,compile=warn`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Directives
+{
+    default: LevelFilter,
+    scopes: Vec<(String, LevelFilter)>
+}
+
+impl Directives
+{
+    /// Creates a set of directives with no scope and the global level given by the number of `-v`.
+    pub fn new(verbosity: u64) -> Directives
+    {
+        Directives {
+            default: verbosity_to_level(verbosity),
+            scopes: Vec::new()
+        }
+    }
+
+    /// Adds a comma separated list of `[<scope>=]<level>` directives; a directive without
+    /// scope replaces the global level.
+    pub fn add(&mut self, directives: &str) -> Result<(), DirectiveError>
+    {
+        for directive in directives.split(',').map(str::trim) {
+            if directive.is_empty() {
+                return Err(DirectiveError::Empty);
+            }
+            let (scope, level) = match directive.split_once('=') {
+                Some((scope, level)) => (Some(scope.trim()), level.trim()),
+                None => (None, directive)
+            };
+            let level = LevelFilter::from_str(level).map_err(|_| DirectiveError::Level(level.into()))?;
+            match scope {
+                None => self.default = level,
+                Some(scope) => {
+                    if scope.is_empty() || scope.contains(char::is_whitespace) {
+                        return Err(DirectiveError::Scope(scope.into()));
+                    }
+                    match self.scopes.iter_mut().find(|(name, _)| name == scope) {
+                        Some(v) => v.1 = level,
+                        None => self.scopes.push((scope.into(), level))
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns true if at least one directive applies to a specific scope.
+    pub fn is_scoped(&self) -> bool
+    {
+        !self.scopes.is_empty()
+    }
+
+    /// The most verbose level of all directives, to be passed to log::set_max_level.
+    pub fn max_level(&self) -> LevelFilter
+    {
+        self.scopes.iter().map(|(_, level)| *level).fold(self.default, Ord::max)
+    }
+
+    pub fn level(&self, target: &str) -> LevelFilter
+    {
+        //The longest matching scope wins such that `a::b=off` can refine `a=trace`.
+        self.scopes.iter()
+            .filter(|(name, _)| target == name
+                || (target.starts_with(name.as_str()) && target[name.len()..].starts_with("::")))
+            .max_by_key(|(name, _)| name.len())
+            .map(|(_, level)| *level)
+            .unwrap_or(self.default)
+    }
+
+    pub fn enabled(&self, target: &str, level: Level) -> bool
+    {
+        level <= self.level(target)
+    }
+}
+
+// The argument following a bare -v is only a directive if it can not be mistaken for an input file.
+fn is_directive(arg: &str) -> bool
+{
+    arg.contains('=') || LevelFilter::from_str(arg).is_ok()
+}
+
+/// Removes the directives given to `-v`/`--verbose` (`-v relocation=trace,glsl=warn` or
+/// `--verbose=relocation=trace`) from the arguments and returns them, such that only plain `-v`
+/// flags are left to the argument parser.
+///
+/// The argument following `-v` is only taken if it contains `=` or is a level name, so that
+/// `-v shader.glsl` still passes `shader.glsl` as an input. Scanning stops at `--`.
+pub fn split_verbose_directives(args: Vec<OsString>) -> Result<(Vec<OsString>, Vec<String>), DirectiveError>
+{
+    let mut res = Vec::with_capacity(args.len());
+    let mut directives = Vec::new();
+    let mut args = args.into_iter().peekable();
+    while let Some(arg) = args.next() {
+        let directive = match arg.to_str() {
+            Some("--") => {
+                res.push(arg);
+                res.extend(args);
+                break;
+            },
+            Some("-v" | "--verbose") => match args.peek().and_then(|v| v.to_str()).is_some_and(is_directive) {
+                true => args.next().and_then(|v| v.into_string().ok()),
+                false => None
+            },
+            Some(v) => v.strip_prefix("--verbose=").map(String::from),
+            None => None
+        };
+        match directive {
+            Some(v) => {
+                Directives::new(0).add(&v)?;
+                directives.push(v);
+            },
+            None => res.push(arg)
+        }
+    }
+    Ok((res, directives))
+}
+
+/// A logger which only forwards records enabled by a set of directives.
+pub struct Filtered<L: Log>
+{
+    inner: L,
+    directives: Directives
+}
+
+impl<L: Log> Filtered<L>
+{
+    pub fn new(inner: L, directives: Directives) -> Filtered<L>
+    {
+        Filtered {
+            inner,
+            directives
+        }
+    }
+
+    pub fn into_inner(self) -> L
+    {
+        self.inner
+    }
+}
+
+impl<L: Log> Log for Filtered<L>
+{
+    fn enabled(&self, metadata: &Metadata) -> bool
+    {
+        self.directives.enabled(metadata.target(), metadata.level()) && self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record)
+    {
+        if self.enabled(record.metadata()) {
+            self.inner.log(record);
+        }
+    }
+
+    fn flush(&self)
+    {
+        self.inner.flush();
+    }
+}
+
+pub(crate) struct StdoutLogger;
+
+impl Log for StdoutLogger
+{
+    fn enabled(&self, _: &Metadata) -> bool
+    {
+        true
+    }
+
+    fn log(&self, record: &Record)
+    {
+        let stdout = std::io::stdout();
+        let mut out = stdout.lock();
+        let _ = writeln!(out, "<{}> [{}] {}", record.target(), record.level(), record.args());
+    }
+
+    fn flush(&self)
+    {
+        let _ = std::io::stdout().flush();
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::ffi::OsString;
+    use std::sync::Mutex;
+    use log::{Level, LevelFilter, Log, Record};
+    use crate::verbosity::{DirectiveError, Directives, Filtered, split_verbose_directives};
+
+    #[derive(Default)]
+    struct MemoryLogger(Mutex<Vec<(String, Level, String)>>);
+
+    impl Log for MemoryLogger
+    {
+        fn enabled(&self, _: &log::Metadata) -> bool
+        {
+            true
+        }
+
+        fn log(&self, record: &Record)
+        {
+            self.0.lock().unwrap().push((record.target().into(), record.level(), record.args().to_string()));
+        }
+
+        fn flush(&self) {}
+    }
+
+    fn emit(logger: &dyn Log, target: &str, level: Level)
+    {
+        logger.log(&Record::builder()
+            .target(target)
+            .level(level)
+            .args(format_args!("{}", target))
+            .build());
+    }
+
+    #[test]
+    fn parse()
+    {
+        let mut directives = Directives::new(0);
+        directives.add("relocation=trace, compile=warn").unwrap();
+        assert!(directives.is_scoped());
+        assert_eq!(directives.level("relocation"), LevelFilter::Trace);
+        assert_eq!(directives.level("compile"), LevelFilter::Warn);
+        assert_eq!(directives.level("write"), LevelFilter::Error);
+        assert_eq!(directives.max_level(), LevelFilter::Trace);
+        directives.add("info,compile=off").unwrap();
+        assert_eq!(directives.level("write"), LevelFilter::Info);
+        assert_eq!(directives.level("compile"), LevelFilter::Off);
+        assert_eq!(directives.add("relocation=loud"), Err(DirectiveError::Level("loud".into())));
+        assert_eq!(directives.add("=debug"), Err(DirectiveError::Scope("".into())));
+        assert_eq!(directives.add("debug,,"), Err(DirectiveError::Empty));
+    }
+
+    fn split(args: &[&str]) -> Result<(Vec<OsString>, Vec<String>), DirectiveError>
+    {
+        split_verbose_directives(args.iter().map(OsString::from).collect())
+    }
+
+    #[test]
+    fn verbose_directives()
+    {
+        let (args, directives) = split(&["shaderc", "-v", "relocation=trace,glsl=warn", "-vv", "--verbose", "a.glsl",
+            "--verbose=debug", "-v", "-t", "GL42"]).unwrap();
+        assert_eq!(args, ["shaderc", "-vv", "--verbose", "a.glsl", "-v", "-t", "GL42"]);
+        assert_eq!(directives, ["relocation=trace,glsl=warn", "debug"]);
+        let (args, directives) = split(&["shaderc", "--", "-v", "trace"]).unwrap();
+        assert_eq!(args, ["shaderc", "--", "-v", "trace"]);
+        assert!(directives.is_empty());
+        assert_eq!(split(&["shaderc", "-v", "relocation=loud"]).unwrap_err(), DirectiveError::Level("loud".into()));
+    }
+
+    #[test]
+    fn plain_verbosity()
+    {
+        let directives = Directives::new(3);
+        assert!(!directives.is_scoped());
+        assert_eq!(directives.max_level(), LevelFilter::Debug);
+        assert_eq!(directives.level("relocation"), LevelFilter::Debug);
+        assert_eq!(Directives::new(12).max_level(), LevelFilter::Trace);
+    }
+
+    #[test]
+    fn nested_scopes()
+    {
+        let mut directives = Directives::new(0);
+        directives.add("bp3d_shaderc=debug,bp3d_shaderc::targets::gl=off").unwrap();
+        assert_eq!(directives.level("bp3d_shaderc::targets::basic"), LevelFilter::Debug);
+        assert_eq!(directives.level("bp3d_shaderc::targets::gl::core"), LevelFilter::Off);
+        assert_eq!(directives.level("bp3d_shaderc_x"), LevelFilter::Error);
+    }
+
+    #[test]
+    fn filtering()
+    {
+        let mut directives = Directives::new(1);
+        directives.add("relocation=trace,compile=error").unwrap();
+        let logger = Filtered::new(MemoryLogger::default(), directives);
+        emit(&logger, "relocation", Level::Trace);
+        emit(&logger, "compile", Level::Info);
+        emit(&logger, "compile", Level::Error);
+        emit(&logger, "write", Level::Warn);
+        emit(&logger, "write", Level::Info);
+        let records = logger.into_inner().0.into_inner().unwrap();
+        assert_eq!(records, vec![
+            ("relocation".into(), Level::Trace, "relocation".into()),
+            ("compile".into(), Level::Error, "compile".into()),
+            ("write".into(), Level::Warn, "write".into())
+        ]);
+    }
+}
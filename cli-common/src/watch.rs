@@ -0,0 +1,211 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Polling file watcher used by the watch mode of the command line tools.
+
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+/// Interval between two scans of the watched files.
+pub const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Default time without any further change before a batch of changes is reported.
+pub const DEFAULT_DEBOUNCE: Duration = Duration::from_millis(250);
+
+#[derive(Debug)]
+pub enum WatchError
+{
+    Handler(ctrlc::Error)
+}
+
+impl Display for WatchError
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+    {
+        match self {
+            WatchError::Handler(e) => write!(f, "failed to install Ctrl-C handler: {}", e)
+        }
+    }
+}
+
+impl std::error::Error for WatchError {}
+
+/// Installs a Ctrl-C handler and returns the flag it sets; can only be called once per process.
+pub fn interrupt_flag() -> Result<Arc<AtomicBool>, WatchError>
+{
+    let flag = Arc::new(AtomicBool::new(false));
+    let handler = flag.clone();
+    ctrlc::set_handler(move || handler.store(true, Ordering::SeqCst)).map_err(WatchError::Handler)?;
+    Ok(flag)
+}
+
+type Stamp = Option<(Option<SystemTime>, u64)>;
+
+fn stamp(path: &PathBuf) -> Stamp
+{
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.modified().ok(), metadata.len()))
+}
+
+/// Watches files by comparing their modification time and size.
+pub struct Watcher
+{
+    stamps: BTreeMap<PathBuf, Stamp>,
+    interrupted: Arc<AtomicBool>,
+    debounce: Duration
+}
+
+impl Watcher
+{
+    pub fn new(interrupted: Arc<AtomicBool>) -> Watcher
+    {
+        Watcher {
+            stamps: BTreeMap::new(),
+            interrupted,
+            debounce: DEFAULT_DEBOUNCE
+        }
+    }
+
+    pub fn set_debounce(&mut self, debounce: Duration)
+    {
+        self.debounce = debounce;
+    }
+
+    /// Replaces the set of watched files.
+    ///
+    /// Files which were already watched keep their previous state so that changes made while
+    /// rebuilding are still reported by the next call to [wait](Watcher::wait).
+    pub fn watch<I: IntoIterator<Item = PathBuf>>(&mut self, paths: I)
+    {
+        let mut stamps = BTreeMap::new();
+        for path in paths {
+            let stamp = self.stamps.remove(&path).unwrap_or_else(|| stamp(&path));
+            stamps.insert(path, stamp);
+        }
+        self.stamps = stamps;
+    }
+
+    pub fn paths(&self) -> impl Iterator<Item = &PathBuf>
+    {
+        self.stamps.keys()
+    }
+
+    fn scan(&mut self, changed: &mut Vec<PathBuf>) -> bool
+    {
+        let mut found = false;
+        for (path, old) in self.stamps.iter_mut() {
+            let new = stamp(path);
+            if new != *old {
+                *old = new;
+                found = true;
+                if !changed.contains(path) {
+                    changed.push(path.clone());
+                }
+            }
+        }
+        found
+    }
+
+    fn sleep(&self, duration: Duration) -> bool
+    {
+        let mut remaining = duration;
+        while !remaining.is_zero() {
+            if self.interrupted.load(Ordering::SeqCst) {
+                return false;
+            }
+            let step = remaining.min(POLL_INTERVAL);
+            std::thread::sleep(step);
+            remaining -= step;
+        }
+        !self.interrupted.load(Ordering::SeqCst)
+    }
+
+    /// Blocks until watched files changed and no further change happened for the debounce time,
+    /// then returns the changed files. Returns None once interrupted.
+    pub fn wait(&mut self) -> Option<Vec<PathBuf>>
+    {
+        let mut changed = Vec::new();
+        loop {
+            if !self.sleep(POLL_INTERVAL) {
+                return None;
+            }
+            if self.scan(&mut changed) {
+                break;
+            }
+        }
+        loop {
+            if !self.sleep(self.debounce) {
+                return None;
+            }
+            if !self.scan(&mut changed) {
+                return Some(changed);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use crate::watch::Watcher;
+
+    #[test]
+    fn changes()
+    {
+        let dir = std::env::temp_dir().join(format!("cli-common-watch-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let a = dir.join("a.glsl");
+        let b = dir.join("b.glsl");
+        std::fs::write(&a, "a").unwrap();
+        std::fs::write(&b, "b").unwrap();
+        let interrupted = Arc::new(AtomicBool::new(false));
+        let mut watcher = Watcher::new(interrupted.clone());
+        watcher.set_debounce(Duration::from_millis(10));
+        watcher.watch([a.clone(), b.clone()]);
+        std::fs::write(&b, "b changed").unwrap();
+        assert_eq!(watcher.wait(), Some(vec![b.clone()]));
+        //A file which disappears is a change too.
+        std::fs::remove_file(&a).unwrap();
+        assert_eq!(watcher.wait(), Some(vec![a.clone()]));
+        //Watching again must not forget changes which have not been reported yet.
+        std::fs::write(&b, "b changed again").unwrap();
+        watcher.watch([b.clone()]);
+        assert_eq!(watcher.paths().count(), 1);
+        assert_eq!(watcher.wait(), Some(vec![b.clone()]));
+        interrupted.store(true, Ordering::SeqCst);
+        assert_eq!(watcher.wait(), None);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
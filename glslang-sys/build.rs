@@ -141,6 +141,21 @@ fn build_glslang(proj: &Path, builder: &mut Build, compiler: &Tool)
     append_files(builder, &root, GLSLLANG_SOURCES);
 }
 
+fn build_hlsl(proj: &Path, builder: &mut Build)
+{
+    let root = proj.join("hlsl");
+    const SOURCES: &[&str] = &[
+        "hlslAttributes.cpp",
+        "hlslGrammar.cpp",
+        "hlslOpMap.cpp",
+        "hlslParseHelper.cpp",
+        "hlslParseables.cpp",
+        "hlslScanContext.cpp",
+        "hlslTokenStream.cpp"
+    ];
+    append_files(builder, &root, SOURCES);
+}
+
 fn build_ogl(proj: &Path, builder: &mut Build)
 {
     let root = proj.join("OGLCompilersDLL");
@@ -184,7 +199,10 @@ fn main()
     }
     builder.include(&generated_include_dir);
     builder.include(&proj);
+    //The HLSL front-end is only linked into ShaderLang when this is defined.
+    builder.define("ENABLE_HLSL", None);
     build_glslang(proj, &mut builder, &compiler);
+    build_hlsl(proj, &mut builder);
     build_ogl(proj, &mut builder);
     build_spirv(proj, &mut builder);
     //Add part of standalone EXE in order to get DefaultTBuiltinResource
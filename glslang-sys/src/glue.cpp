@@ -63,6 +63,60 @@ public:
     }
 };
 
+struct IncludeResultC
+{
+    const char *name;
+    const char *data;
+    size_t length;
+    void *userData;
+};
+
+typedef bool (*IncludeCallback)(void *ctx, const char *headerName, const char *includerName, size_t depth,
+                                bool system, IncludeResultC *result);
+typedef void (*ReleaseCallback)(void *ctx, void *userData);
+
+// Forwards include requests to C callbacks; a callback returning false means the header was not
+// found while an empty name carries an error message in data.
+class CallbackIncluder : public TShader::Includer
+{
+private:
+    void *ctx;
+    IncludeCallback include;
+    ReleaseCallback release;
+
+    IncludeResult *resolve(const char *headerName, const char *includerName, size_t depth, bool system)
+    {
+        IncludeResultC result = {nullptr, nullptr, 0, nullptr};
+        if (!this->include(this->ctx, headerName, includerName, depth, system, &result))
+            return nullptr;
+        return new IncludeResult(result.name, result.data, result.length, result.userData);
+    }
+
+public:
+    CallbackIncluder(void *ctx, IncludeCallback include, ReleaseCallback release)
+        : ctx(ctx), include(include), release(release)
+    {
+    }
+
+    IncludeResult *includeSystem(const char *headerName, const char *includerName, size_t depth) override
+    {
+        return this->resolve(headerName, includerName, depth, true);
+    }
+
+    IncludeResult *includeLocal(const char *headerName, const char *includerName, size_t depth) override
+    {
+        return this->resolve(headerName, includerName, depth, false);
+    }
+
+    void releaseInclude(IncludeResult *result) override
+    {
+        if (result == nullptr)
+            return;
+        this->release(this->ctx, result->userData);
+        delete result;
+    }
+};
+
 extern "C"
 {
 
@@ -277,6 +331,12 @@ void TShader_setEnvInputVulkanRulesRelaxed(void *self)
     shader->setEnvInputVulkanRulesRelaxed();
 }
 
+void TShader_setHlslIoMapping(void *self, bool hlslIoMap)
+{
+    auto *shader = (TShader *) self;
+    shader->setHlslIoMapping(hlslIoMap);
+}
+
 bool TShader_getEnvInputVulkanRulesRelaxed(void *self)
 {
     auto *shader = (TShader *) self;
@@ -291,6 +351,16 @@ bool TShader_parse(void *self, const TBuiltInResource *res, int defaultVersion,
                          messages);
 }
 
+bool TShader_parseWithIncluder(void *self, const TBuiltInResource *res, int defaultVersion, EProfile defaultProfile,
+                               bool forceDefaultVersionAndProfile, bool forwardCompatible, EShMessages messages,
+                               void *ctx, IncludeCallback include, ReleaseCallback release)
+{
+    auto *shader = (TShader *) self;
+    CallbackIncluder includer(ctx, include, release);
+    return shader->parse(res, defaultVersion, defaultProfile, forceDefaultVersionAndProfile, forwardCompatible,
+                         messages, includer);
+}
+
 bool
 TShader_parse1(void *self, const TBuiltInResource *res, int defaultVersion, bool forwardCompatible,
                EShMessages messages)
@@ -364,6 +434,12 @@ void* TProgram_getIntermediate(void *self, EShLanguage stage)
     return prog->getIntermediate(stage);
 }
 
+bool TProgram_mapIO(void *self)
+{
+    auto *prog = (TProgram *) self;
+    return prog->mapIO();
+}
+
 bool TProgram_buildReflection(void *self, int opts)
 {
     auto *prog = (TProgram *) self;
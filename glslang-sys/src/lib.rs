@@ -229,15 +229,39 @@ pub struct TProgram(c_void);
 #[repr(transparent)]
 pub struct SpvContext(c_void);
 
+/// Header resolved by an [IncludeCallback]; an empty `name` reports `data` as an error message.
 #[repr(C)]
+pub struct IncludeResult
+{
+    pub name: *const c_char,
+    pub data: *const c_char,
+    pub length: usize,
+    pub userData: *mut c_void
+}
+
+/// Resolves a header; returns false if it was not found.
+pub type IncludeCallback = unsafe extern "C" fn(
+    ctx: *mut c_void,
+    headerName: *const c_char,
+    includerName: *const c_char,
+    depth: usize,
+    system: bool,
+    result: *mut IncludeResult
+) -> bool;
+
+/// Releases the `userData` of a header once glslang is done with it.
+pub type ReleaseCallback = unsafe extern "C" fn(ctx: *mut c_void, userData: *mut c_void);
+
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
 pub struct SpvOptions
 {
-    generateDebugInfo: bool,
-    stripDebugInfo: bool,
-    disableOptimizer: bool,
-    optimizeSize: bool,
-    disassemble: bool,
-    validate: bool
+    pub generateDebugInfo: bool,
+    pub stripDebugInfo: bool,
+    pub disableOptimizer: bool,
+    pub optimizeSize: bool,
+    pub disassemble: bool,
+    pub validate: bool
 }
 
 extern "C" {
@@ -291,6 +315,7 @@ extern "C" {
     pub fn TShader_setEnvTarget(this: *const TShader, lang: EShTargetLanguage, version: EShTargetLanguageVersion);
     pub fn TShader_getStrings(this: *const TShader, s: *mut *const *const c_char, n: *mut c_int);
     pub fn TShader_setEnvInputVulkanRulesRelaxed(this: *const TShader);
+    pub fn TShader_setHlslIoMapping(this: *const TShader, hlslIoMap: bool);
     pub fn TShader_getEnvTargetHlslFunctionality1(this: *const TShader) -> bool;
     pub fn TShader_getEnvInputVulkanRulesRelaxed(this: *const TShader) -> bool;
     pub fn TShader_parse(
@@ -302,6 +327,18 @@ extern "C" {
         forwardCompatible: bool,
         messages: EShMessages
     ) -> bool;
+    pub fn TShader_parseWithIncluder(
+        this: *const TShader,
+        res: *const limits::TBuiltInResource,
+        defaultVersion: c_int,
+        defaultProfile: versions::EProfile,
+        forceDefaultVersionAndProfile: bool,
+        forwardCompatible: bool,
+        messages: EShMessages,
+        ctx: *mut c_void,
+        include: IncludeCallback,
+        release: ReleaseCallback
+    ) -> bool;
     pub fn TShader_parse1(
         this: *const TShader,
         res: *const limits::TBuiltInResource,
@@ -318,6 +355,7 @@ extern "C" {
     pub fn TProgram_destroy(this: *const TProgram);
     pub fn TProgram_addShader(this: *const TProgram, shader: *const TShader);
     pub fn TProgram_link(this: *const TProgram, messages: EShMessages) -> bool;
+    pub fn TProgram_mapIO(this: *const TProgram) -> bool;
     pub fn TProgram_buildReflection(this: *const TProgram, opts: EShReflectionOptions) -> bool;
     pub fn TProgram_getInfoLog(this: *const TProgram) -> *const c_char;
     pub fn TProgram_getInfoDebugLog(this: *const TProgram) -> *const c_char;
@@ -0,0 +1,218 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+
+//! Structured diagnostics parsed from the info logs of glslang.
+
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Severity
+{
+    Error,
+    Warning,
+    Note
+}
+
+impl Display for Severity
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+    {
+        match self {
+            Severity::Error => f.write_str("error"),
+            Severity::Warning => f.write_str("warning"),
+            Severity::Note => f.write_str("note")
+        }
+    }
+}
+
+/// Part of the shader a diagnostic refers to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source
+{
+    /// A part added with a name.
+    Named(String),
+    /// A part added without a name, glslang reports it by its index.
+    Unnamed(usize)
+}
+
+impl Display for Source
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+    {
+        match self {
+            Source::Named(name) => f.write_str(name),
+            Source::Unnamed(index) => write!(f, "{}", index)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic
+{
+    pub severity: Severity,
+    /// None for diagnostics which are not located, such as most linker errors.
+    pub source: Option<Source>,
+    pub line: Option<u32>,
+    /// Message, continuation lines included and separated by '\n'.
+    pub message: String
+}
+
+impl Display for Diagnostic
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+    {
+        match (&self.source, self.line) {
+            (Some(source), Some(line)) => write!(f, "{}: {}:{}: {}", self.severity, source, line, self.message),
+            _ => write!(f, "{}: {}", self.severity, self.message)
+        }
+    }
+}
+
+const PREFIXES: &[(&str, Severity)] = &[
+    ("ERROR: ", Severity::Error),
+    ("INTERNAL ERROR: ", Severity::Error),
+    ("UNIMPLEMENTED: ", Severity::Error),
+    ("WARNING: ", Severity::Warning),
+    ("NOTE: ", Severity::Note)
+];
+
+/// Splits `<source>:<line>: <message>`; the name of a source may itself contain ':'.
+fn split_location(msg: &str) -> Option<(Source, u32, &str)>
+{
+    for (i, _) in msg.match_indices(": ") {
+        let (source, line) = match msg[..i].rsplit_once(':') {
+            Some(v) => v,
+            None => continue
+        };
+        let line = match line.parse() {
+            Ok(v) if !source.is_empty() => v,
+            _ => continue
+        };
+        let source = match source.parse() {
+            Ok(index) => Source::Unnamed(index),
+            Err(_) => Source::Named(source.into())
+        };
+        return Some((source, line, &msg[i + 2..]));
+    }
+    None
+}
+
+//The last line of a failed compilation only counts the errors reported above it.
+fn is_summary(msg: &str) -> bool
+{
+    msg.ends_with("No code generated.")
+}
+
+/// Parses an info log of glslang.
+///
+/// Lines which do not start with a severity continue the message of the previous diagnostic;
+/// lines before the first diagnostic are ignored.
+pub fn parse_info_log(log: &str) -> Vec<Diagnostic>
+{
+    let mut diagnostics: Vec<Diagnostic> = Vec::new();
+    let mut continued = false;
+    for line in log.lines() {
+        let line = line.trim_end();
+        let entry = PREFIXES.iter().find_map(|(prefix, severity)| line.strip_prefix(prefix).map(|v| (*severity, v)));
+        match entry {
+            Some((_, msg)) if is_summary(msg) => continued = false,
+            Some((severity, msg)) => {
+                let (source, line, message) = match split_location(msg) {
+                    Some((source, line, message)) => (Some(source), Some(line), message),
+                    None => (None, None, msg)
+                };
+                diagnostics.push(Diagnostic {
+                    severity,
+                    source,
+                    line,
+                    message: message.trim().into()
+                });
+                continued = true;
+            },
+            None if line.is_empty() => (),
+            None => {
+                if let (true, Some(last)) = (continued, diagnostics.last_mut()) {
+                    last.message.push('\n');
+                    last.message.push_str(line.trim_start());
+                }
+            }
+        }
+    }
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::diagnostic::{parse_info_log, Diagnostic, Severity, Source};
+
+    #[test]
+    fn compile_log()
+    {
+        let log = "ERROR: 0:3: 'foo' : undeclared identifier \n\
+                   ERROR: shader.glsl:12: 'assign' :  cannot convert from ' const float' to ' temp 4-component vector of float'\n\
+                   WARNING: C:\\shaders\\common.glsl:4: '#extension' : extension not supported: GL_FOO\n\
+                   ERROR: 2 compilation errors.  No code generated.\n\n\n";
+        let diagnostics = parse_info_log(log);
+        assert_eq!(diagnostics.len(), 3);
+        assert_eq!(diagnostics[0], Diagnostic {
+            severity: Severity::Error,
+            source: Some(Source::Unnamed(0)),
+            line: Some(3),
+            message: "'foo' : undeclared identifier".into()
+        });
+        assert_eq!(diagnostics[1].source, Some(Source::Named("shader.glsl".into())));
+        assert_eq!(diagnostics[1].line, Some(12));
+        assert_eq!(diagnostics[2].severity, Severity::Warning);
+        assert_eq!(diagnostics[2].source, Some(Source::Named("C:\\shaders\\common.glsl".into())));
+        assert_eq!(diagnostics[2].to_string(), "warning: C:\\shaders\\common.glsl:4: '#extension' : extension not supported: GL_FOO");
+    }
+
+    #[test]
+    fn link_log()
+    {
+        let log = "ERROR: Linking vertex stage: Missing entry point: Each stage requires one entry point\n\
+                   \n\
+                   ERROR: Linking vertex and fragment stages: Output type mismatch for varying: Color\n    \
+                   vertex stage: \" smooth out highp 3-component vector of float Color\"\n    \
+                   fragment stage: \" smooth in highp 4-component vector of float Color\"\n\
+                   \n\
+                   INTERNAL ERROR: unable to allocate\n";
+        let diagnostics = parse_info_log(log);
+        assert_eq!(diagnostics.len(), 3);
+        assert_eq!(diagnostics[0].source, None);
+        assert_eq!(diagnostics[0].message, "Linking vertex stage: Missing entry point: Each stage requires one entry point");
+        assert_eq!(diagnostics[1].message.lines().count(), 3);
+        assert!(diagnostics[1].message.ends_with("fragment stage: \" smooth in highp 4-component vector of float Color\""));
+        assert_eq!(diagnostics[1].to_string().lines().next(), Some("error: Linking vertex and fragment stages: Output type mismatch for varying: Color"));
+        assert_eq!(diagnostics[2].severity, Severity::Error);
+        assert!(parse_info_log("").is_empty());
+        assert!(parse_info_log("Vertex:\nsome banner\n").is_empty());
+    }
+}
@@ -37,6 +37,9 @@ use glslang_sys::{
     EShLangTessEvaluation,
     EShLangVertex,
     EShLanguage,
+    EShSource,
+    EShSourceGlsl,
+    EShSourceHlsl,
     EShTargetClientVersion,
     EShTargetLangNone,
     EShTargetLanguage,
@@ -56,7 +59,7 @@ use glslang_sys::{
     EShTargetVulkan_1_2
 };
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Stage
 {
     Vertex,
@@ -80,6 +83,24 @@ impl Stage
     }
 }
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Source
+{
+    Glsl,
+    Hlsl
+}
+
+impl Source
+{
+    pub fn into(self) -> EShSource
+    {
+        match self {
+            Source::Glsl => EShSourceGlsl,
+            Source::Hlsl => EShSourceHlsl
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug)]
 pub enum Client
 {
@@ -150,6 +171,7 @@ impl TargetVersion
 pub struct Environment
 {
     stage: Stage,
+    source: Source,
     dialect: Option<Client>,
     dialect_version: i32,
     client: Option<Client>,
@@ -163,6 +185,7 @@ impl Environment
     {
         Environment {
             stage,
+            source: Source::Glsl,
             dialect: None,
             dialect_version: 0,
             client: None,
@@ -182,6 +205,7 @@ impl Environment
         let dv = dialect_version.unwrap_or(300);
         Environment {
             stage,
+            source: Source::Glsl,
             dialect: Some(dialect),
             dialect_version: dv,
             client: Some(Client::Vulkan),
@@ -190,11 +214,28 @@ impl Environment
         }
     }
 
+    /// Creates an environment reading HLSL instead of GLSL; the messages of the shader should
+    /// also include [read_hlsl](crate::shader::Messages::read_hlsl).
+    pub fn new_vulkan_hlsl(stage: Stage, client_version: ClientVersion, spirv: TargetVersion) -> Environment
+    {
+        Environment {
+            stage,
+            source: Source::Hlsl,
+            dialect: Some(Client::Vulkan),
+            //glslang only knows HLSL input semantics version 100.
+            dialect_version: 100,
+            client: Some(Client::Vulkan),
+            client_version: Some(client_version),
+            spirv: Some(spirv)
+        }
+    }
+
     pub fn new_opengl(stage: Stage, dialect: Client, dialect_version: Option<i32>) -> Environment
     {
         let dv = dialect_version.unwrap_or(300);
         Environment {
             stage,
+            source: Source::Glsl,
             dialect: Some(dialect),
             dialect_version: dv,
             client: Some(Client::OpenGL),
@@ -203,11 +244,32 @@ impl Environment
         }
     }
 
+    /// Creates an OpenGL environment generating SPIR-V for ARB_gl_spirv.
+    pub fn new_opengl_spirv(stage: Stage, dialect_version: Option<i32>, spirv: TargetVersion) -> Environment
+    {
+        Environment {
+            spirv: Some(spirv),
+            ..Environment::new_opengl(stage, Client::OpenGL, dialect_version)
+        }
+    }
+
+    /// Returns a copy of this environment targeting a different stage.
+    pub fn with_stage(mut self, stage: Stage) -> Environment
+    {
+        self.stage = stage;
+        self
+    }
+
     pub fn get_stage(&self) -> EShLanguage
     {
         self.stage.into()
     }
 
+    pub fn get_source(&self) -> EShSource
+    {
+        self.source.into()
+    }
+
     pub fn get_dialect(&self) -> EShClient
     {
         if let Some(c) = self.dialect {
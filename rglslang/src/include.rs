@@ -0,0 +1,197 @@
+// Copyright (c) 2021, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+
+use std::{
+    ffi::{CStr, CString},
+    fmt::{Debug, Formatter},
+    os::raw::{c_char, c_void},
+    panic::AssertUnwindSafe,
+    sync::Arc
+};
+
+use glslang_sys::IncludeResult;
+
+/// Enables `#include` in GLSL sources; added to the preamble when an includer is set.
+pub(crate) const INCLUDE_EXTENSION: &str = "#extension GL_GOOGLE_include_directive : enable\n";
+
+/// Kind of an `#include` directive.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum IncludeType<'a>
+{
+    /// `#include "name"`; carries the name of the including source.
+    Relative(&'a str),
+
+    /// `#include <name>`, also used when a relative include could not be resolved.
+    System
+}
+
+/// Source code of a resolved include.
+#[derive(Clone, Debug)]
+pub struct ResolvedInclude
+{
+    /// Name of the include, passed back as the including source of nested includes.
+    pub name: String,
+    pub content: String
+}
+
+impl ResolvedInclude
+{
+    pub fn new<T: Into<String>, T1: Into<String>>(name: T, content: T1) -> ResolvedInclude
+    {
+        ResolvedInclude {
+            name: name.into(),
+            content: content.into()
+        }
+    }
+}
+
+pub(crate) type IncludeFn = dyn Fn(&str, IncludeType) -> Option<ResolvedInclude> + Send + Sync;
+
+#[derive(Clone)]
+pub(crate) struct Includer(pub Arc<IncludeFn>);
+
+impl Debug for Includer
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+    {
+        f.write_str("Includer")
+    }
+}
+
+/// State of an includer during a single parse.
+pub(crate) struct IncludeContext<'a>
+{
+    includer: &'a IncludeFn,
+    //Names of the sources currently being included, starting with the root source.
+    stack: Vec<String>,
+    //Error of a failed relative include, reported if the system include fails too.
+    error: Option<String>
+}
+
+impl<'a> IncludeContext<'a>
+{
+    pub fn new(includer: &'a Includer) -> IncludeContext<'a>
+    {
+        IncludeContext {
+            includer: &*includer.0,
+            stack: Vec::new(),
+            error: None
+        }
+    }
+
+    fn resolve(&mut self, header: &str, includer: &str, depth: usize, system: bool) -> Result<Option<ResolvedInclude>, String>
+    {
+        //glslang passes the depth of the new include; everything deeper belongs to a previous include.
+        if depth <= 1 {
+            self.stack.clear();
+            self.stack.push(includer.into());
+        } else {
+            self.stack.truncate(depth);
+        }
+        let ty = match system {
+            true => IncludeType::System,
+            false => IncludeType::Relative(includer)
+        };
+        let res = match std::panic::catch_unwind(AssertUnwindSafe(|| (self.includer)(header, ty))) {
+            Ok(Some(v)) => v,
+            Ok(None) => return Ok(None),
+            Err(_) => return Err(format!("includer panicked while resolving '{}'", header))
+        };
+        if self.stack.contains(&res.name) {
+            return Err(format!("include cycle: {} -> {}", self.stack.join(" -> "), res.name));
+        }
+        Ok(Some(res))
+    }
+}
+
+struct IncludeData
+{
+    name: CString,
+    content: String
+}
+
+unsafe fn write_result(result: *mut IncludeResult, name: CString, content: String) -> bool
+{
+    let data = Box::new(IncludeData { name, content });
+    (*result).name = data.name.as_ptr();
+    (*result).data = data.content.as_ptr() as _;
+    (*result).length = data.content.len();
+    (*result).userData = Box::into_raw(data) as _;
+    true
+}
+
+pub(crate) unsafe extern "C" fn include_callback(
+    ctx: *mut c_void,
+    header: *const c_char,
+    includer: *const c_char,
+    depth: usize,
+    system: bool,
+    result: *mut IncludeResult
+) -> bool
+{
+    let ctx = &mut *(ctx as *mut IncludeContext);
+    let header = CStr::from_ptr(header).to_string_lossy();
+    let includer = CStr::from_ptr(includer).to_string_lossy();
+    let res = ctx.resolve(&header, &includer, depth, system).and_then(|v| match v {
+        Some(v) => match CString::new(v.name.clone()) {
+            Ok(name) => Ok(Some((v.name, name, v.content))),
+            Err(_) => Err(format!("include name '{}' contains a nul byte", v.name))
+        },
+        None => Ok(None)
+    });
+    match res {
+        Ok(Some((name, cname, content))) => {
+            ctx.error = None;
+            ctx.stack.push(name);
+            write_result(result, cname, content)
+        },
+        //glslang retries a failed relative include as a system include.
+        Ok(None) if !system => {
+            ctx.error = None;
+            false
+        },
+        Err(e) if !system => {
+            ctx.error = Some(e);
+            false
+        },
+        //An empty name reports the content as an error message.
+        Ok(None) => {
+            let e = ctx.error.take().unwrap_or_else(|| format!("include '{}' not found", header));
+            write_result(result, CString::default(), e)
+        },
+        Err(e) => write_result(result, CString::default(), e)
+    }
+}
+
+pub(crate) unsafe extern "C" fn release_callback(_: *mut c_void, data: *mut c_void)
+{
+    if !data.is_null() {
+        drop(Box::from_raw(data as *mut IncludeData));
+    }
+}
@@ -26,37 +26,108 @@
 // NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
 // SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
-use std::sync::{
-    atomic::{AtomicBool, Ordering},
-    Once
-};
+use std::sync::{Mutex, MutexGuard, Once};
 
 use glslang_sys::{finalize_process, initialize_process};
 
-static INIT: Once = Once::new();
-static END: Once = Once::new();
+/// Number of live [Instance]s, glslang is initialized while it is not 0.
+static CLIENTS: Mutex<usize> = Mutex::new(0);
 
-static FLAG: AtomicBool = AtomicBool::new(false);
+#[cfg(test)]
+static INIT: Once = Once::new();
 
+pub mod diagnostic;
 pub mod environment;
+pub mod include;
 pub mod program;
+pub mod reflection;
 pub mod shader;
+pub mod spirv;
 
-pub fn main<T, F: FnOnce() -> T>(f: F) -> T
+/// Keeps glslang initialized while the tests run so that it is not finalized and initialized
+/// again between two tests.
+#[cfg(test)]
+pub(crate) fn test_init()
+{
+    INIT.call_once(|| std::mem::forget(instance()));
+}
+
+fn clients() -> MutexGuard<'static, usize>
+{
+    //The count is only updated once glslang has been initialized or finalized, a panic while the
+    //lock is held can't leave it out of sync.
+    CLIENTS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// A handle on the glslang process state, glslang stays initialized as long as one instance is
+/// alive.
+pub struct Instance
 {
-    let flag = FLAG.load(Ordering::Relaxed);
-    if flag {
-        panic!("Cannot run glslang twice!");
+    _private: ()
+}
+
+impl Drop for Instance
+{
+    fn drop(&mut self)
+    {
+        let mut clients = clients();
+        *clients -= 1;
+        if *clients == 0 {
+            unsafe {
+                finalize_process();
+            }
+        }
     }
-    INIT.call_once(|| {
+}
+
+/// Acquires glslang, initializing it if no other instance is alive.
+///
+/// Instances may be acquired and dropped concurrently from any thread: initialization and
+/// finalization never overlap.
+pub fn instance() -> Instance
+{
+    let mut clients = clients();
+    if *clients == 0 {
         unsafe {
             initialize_process();
         }
-        FLAG.store(true, Ordering::Relaxed);
-    });
-    let t = f();
-    END.call_once(|| unsafe {
-        finalize_process();
-    });
-    t
+    }
+    *clients += 1;
+    Instance { _private: () }
+}
+
+/// Runs f with glslang initialized, see [instance].
+pub fn main<T, F: FnOnce() -> T>(f: F) -> T
+{
+    let _instance = instance();
+    f()
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::environment::{Client, Environment, Stage};
+    use crate::shader::{Builder, Messages, Part, Profile};
+
+    #[test]
+    fn concurrent_instances()
+    {
+        let threads: Vec<_> = (0..8).map(|i| std::thread::spawn(move || {
+            for _ in 0..4 {
+                let _instance = crate::instance();
+                let shader = Builder::new(Environment::new_opengl(Stage::Vertex, Client::OpenGL, Some(330)))
+                    .messages(Messages::new())
+                    .entry_point("main")
+                    .source_entry_point("main")
+                    .default_version(330)
+                    .default_profile(Profile::Core)
+                    .add_part(Part::new(format!("#version 330 core\nvoid main() {{ gl_Position = vec4({}.0); }}\n", i)))
+                    .parse();
+                assert!(shader.check(), "{}", shader.get_info_log());
+            }
+        })).collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+    }
 }
@@ -26,7 +26,7 @@
 // NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
 // SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
-use std::{borrow::Cow, ffi::CStr};
+use std::{borrow::Cow, ffi::CStr, os::raw::c_void};
 
 use glslang_sys::{
     EShMessages,
@@ -49,12 +49,19 @@ use glslang_sys::{
     TProgram_destroy,
     TProgram_getInfoDebugLog,
     TProgram_getInfoLog,
+    TProgram_getIntermediate,
     TProgram_link,
+    TProgram_mapIO,
     TShader,
     TShader_destroy
 };
 
-use crate::shader::{unwrap_messages, unwrap_shader, Messages, Shader};
+use crate::{
+    diagnostic::{parse_info_log, Diagnostic},
+    environment::Stage,
+    reflection::Reflection,
+    shader::{unwrap_messages, unwrap_shader, Messages, Shader}
+};
 
 pub struct ReflectionOptions
 {
@@ -130,7 +137,8 @@ pub struct Builder
     shaders: Vec<*const TShader>,
     low_level: *const TProgram,
     reflection: Option<EShReflectionOptions>,
-    messages: EShMessages
+    messages: EShMessages,
+    map_io: bool
 }
 
 impl Builder
@@ -142,7 +150,8 @@ impl Builder
                 shaders: Vec::new(),
                 low_level: TProgram_create(),
                 reflection: None,
-                messages: EShMsgDefault
+                messages: EShMsgDefault,
+                map_io: false
             }
         }
     }
@@ -169,22 +178,31 @@ impl Builder
         self
     }
 
+    /// Maps the bindings and locations of the program after linking; this applies the binding
+    /// shifts and auto-mapping settings of its shaders.
+    pub fn map_io(mut self) -> Self
+    {
+        self.map_io = true;
+        self
+    }
+
     pub fn link(self) -> Program
     {
-        unsafe {
-            let mut flag = true;
-            if let Some(opts) = self.reflection {
-                flag = TProgram_buildReflection(self.low_level, opts);
-            }
-            if flag {
-                flag = TProgram_link(self.low_level, self.messages);
-            }
-            Program {
-                shaders: self.shaders,
-                low_level: self.low_level,
-                valid: flag
-            }
+        let mut valid = unsafe { TProgram_link(self.low_level, self.messages) };
+        if valid && self.map_io {
+            valid = unsafe { TProgram_mapIO(self.low_level) };
         }
+        let mut program = Program {
+            valid,
+            shaders: self.shaders,
+            low_level: self.low_level,
+            reflection: None
+        };
+        //glslang can only reflect a program once it is linked.
+        if let Some(opts) = self.reflection {
+            program.valid = program.build_reflection(opts);
+        }
+        program
     }
 }
 
@@ -192,6 +210,7 @@ pub struct Program
 {
     shaders: Vec<*const TShader>,
     low_level: *const TProgram,
+    reflection: Option<EShReflectionOptions>,
     valid: bool
 }
 
@@ -205,6 +224,12 @@ impl Program
         }
     }
 
+    /// Returns the errors and warnings of the info log.
+    pub fn diagnostics(&self) -> Vec<Diagnostic>
+    {
+        parse_info_log(&self.get_info_log())
+    }
+
     pub fn get_info_debug_log(&self) -> Cow<str>
     {
         unsafe {
@@ -217,6 +242,37 @@ impl Program
     {
         self.valid
     }
+
+    fn build_reflection(&mut self, opts: EShReflectionOptions) -> bool
+    {
+        if !self.valid {
+            return false;
+        }
+        if let Some(built) = self.reflection {
+            return built == opts;
+        }
+        let flag = unsafe { TProgram_buildReflection(self.low_level, opts) };
+        if flag {
+            self.reflection = Some(opts);
+        }
+        flag
+    }
+
+    /// Returns the reflection data of the program; glslang builds it only once so this returns
+    /// None if the program is not linked or was already reflected with different options.
+    pub fn reflect(&mut self, options: ReflectionOptions) -> Option<Reflection<'_>>
+    {
+        self.build_reflection(options.opts).then(|| Reflection::new(self.low_level))
+    }
+
+    pub(crate) fn get_intermediate(&self, stage: Stage) -> Option<*const c_void>
+    {
+        if !self.valid {
+            return None;
+        }
+        let intermediate = unsafe { TProgram_getIntermediate(self.low_level, stage.into()) };
+        (!intermediate.is_null()).then(|| intermediate)
+    }
 }
 
 impl Drop for Program
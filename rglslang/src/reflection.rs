@@ -0,0 +1,253 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use std::{ffi::CStr, marker::PhantomData, os::raw::c_char};
+
+use glslang_sys::{
+    EShLangFragmentMask,
+    EShLangGeometryMask,
+    EShLangTessControlMask,
+    EShLangTessEvaluationMask,
+    EShLangVertexMask,
+    EShLanguageMask,
+    TProgram,
+    TProgram_getAttributeName,
+    TProgram_getAttributeType,
+    TProgram_getNumLiveAttributes,
+    TProgram_getNumLiveUniformBlocks,
+    TProgram_getNumLiveUniformVariables,
+    TProgram_getUniformArraySize,
+    TProgram_getUniformBinding,
+    TProgram_getUniformBlockBinding,
+    TProgram_getUniformBlockName,
+    TProgram_getUniformBlockSize,
+    TProgram_getUniformBufferOffset,
+    TProgram_getUniformName,
+    TProgram_getUniformStages,
+    TProgram_getUniformType
+};
+
+use crate::{environment::Stage, program::Program};
+
+/// A live uniform variable; members of uniform blocks are reported with their offset in the block.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Uniform
+{
+    pub name: String,
+    pub binding: Option<u32>,
+    pub gl_type: u32,
+    pub array_size: u32,
+    pub offset: Option<u32>,
+    pub stages: Vec<Stage>
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UniformBlock
+{
+    pub name: String,
+    pub binding: Option<u32>,
+    pub size: u32
+}
+
+/// A live vertex shader input.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Attribute
+{
+    pub name: String,
+    pub gl_type: u32
+}
+
+fn mask(stage: Stage) -> EShLanguageMask
+{
+    match stage {
+        Stage::Vertex => EShLangVertexMask,
+        Stage::Pixel => EShLangFragmentMask,
+        Stage::Geometry => EShLangGeometryMask,
+        Stage::Hull => EShLangTessControlMask,
+        Stage::Domain => EShLangTessEvaluationMask
+    }
+}
+
+// glslang reports -1 for anything which isn't set.
+fn optional(value: i32) -> Option<u32>
+{
+    (value >= 0).then(|| value as u32)
+}
+
+unsafe fn string(ptr: *const c_char) -> String
+{
+    match ptr.is_null() {
+        true => String::new(),
+        false => CStr::from_ptr(ptr).to_string_lossy().into_owned()
+    }
+}
+
+/// Reflection data of a linked program, obtained from [Program::reflect](Program::reflect).
+pub struct Reflection<'a>
+{
+    low_level: *const TProgram,
+    useless: PhantomData<&'a Program>
+}
+
+impl<'a> Reflection<'a>
+{
+    pub(crate) fn new(low_level: *const TProgram) -> Reflection<'a>
+    {
+        Reflection {
+            low_level,
+            useless: PhantomData
+        }
+    }
+
+    pub fn uniforms(&self) -> impl Iterator<Item = Uniform> + '_
+    {
+        let count = unsafe { TProgram_getNumLiveUniformVariables(self.low_level) };
+        (0..count).map(move |i| unsafe {
+            let stages = TProgram_getUniformStages(self.low_level, i);
+            Uniform {
+                name: string(TProgram_getUniformName(self.low_level, i)),
+                binding: optional(TProgram_getUniformBinding(self.low_level, i)),
+                gl_type: TProgram_getUniformType(self.low_level, i) as u32,
+                array_size: TProgram_getUniformArraySize(self.low_level, i) as u32,
+                offset: optional(TProgram_getUniformBufferOffset(self.low_level, i)),
+                stages: [Stage::Vertex, Stage::Hull, Stage::Domain, Stage::Geometry, Stage::Pixel].into_iter()
+                    .filter(|v| stages & mask(*v) != 0)
+                    .collect()
+            }
+        })
+    }
+
+    pub fn uniform_blocks(&self) -> impl Iterator<Item = UniformBlock> + '_
+    {
+        let count = unsafe { TProgram_getNumLiveUniformBlocks(self.low_level) };
+        (0..count).map(move |i| unsafe {
+            UniformBlock {
+                name: string(TProgram_getUniformBlockName(self.low_level, i)),
+                binding: optional(TProgram_getUniformBlockBinding(self.low_level, i)),
+                size: TProgram_getUniformBlockSize(self.low_level, i) as u32
+            }
+        })
+    }
+
+    pub fn attributes(&self) -> impl Iterator<Item = Attribute> + '_
+    {
+        let count = unsafe { TProgram_getNumLiveAttributes(self.low_level) };
+        (0..count).map(move |i| unsafe {
+            Attribute {
+                name: string(TProgram_getAttributeName(self.low_level, i)),
+                gl_type: TProgram_getAttributeType(self.low_level, i) as u32
+            }
+        })
+    }
+
+    /// Returns the first live uniform with the given name.
+    pub fn uniform(&self, name: &str) -> Option<Uniform>
+    {
+        self.uniforms().find(|v| v.name == name)
+    }
+
+    /// Returns the first live uniform block with the given name.
+    pub fn uniform_block(&self, name: &str) -> Option<UniformBlock>
+    {
+        self.uniform_blocks().find(|v| v.name == name)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::{
+        environment::{Client, Environment, Stage},
+        program::{Builder, ReflectionOptions},
+        shader::{Part, Profile}
+    };
+
+    const GL_FLOAT_VEC3: u32 = 0x8B51;
+    const GL_FLOAT_VEC4: u32 = 0x8B52;
+    const GL_SAMPLER_2D: u32 = 0x8B5E;
+
+    const VERTEX: &str = "#version 420 core
+layout (location = 0) in vec3 Position;
+layout (binding = 1, std140) uniform Material { vec4 Material_Tint; vec4 Material_Color; };
+void main() { gl_Position = vec4(Position, 1.0) * Material_Color; }
+";
+
+    const PIXEL: &str = "#version 420 core
+layout (binding = 1, std140) uniform Material { vec4 Material_Tint; vec4 Material_Color; };
+layout (binding = 2) uniform sampler2D BaseTexture;
+out vec4 FragColor;
+void main() { FragColor = texture(BaseTexture, vec2(0.5)) * Material_Color; }
+";
+
+    fn parse(stage: Stage, code: &str) -> crate::shader::Shader
+    {
+        let shader = crate::shader::Builder::new(Environment::new_opengl(stage, Client::OpenGL, Some(420)))
+            .entry_point("main")
+            .source_entry_point("main")
+            .default_version(420)
+            .default_profile(Profile::Core)
+            .add_part(Part::new(code))
+            .parse();
+        assert!(shader.check(), "{}", shader.get_info_log());
+        shader
+    }
+
+    #[test]
+    fn reflect()
+    {
+        crate::test_init();
+        let mut program = Builder::new()
+            .add_shader(parse(Stage::Vertex, VERTEX))
+            .add_shader(parse(Stage::Pixel, PIXEL))
+            .link();
+        assert!(program.check(), "{}", program.get_info_log());
+        let reflection = program.reflect(ReflectionOptions::new()).unwrap();
+        let blocks: Vec<_> = reflection.uniform_blocks().collect();
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(blocks[0].name, "Material");
+        assert_eq!(blocks[0].binding, Some(1));
+        assert_eq!(blocks[0].size, 32);
+        let texture = reflection.uniform("BaseTexture").unwrap();
+        assert_eq!(texture.binding, Some(2));
+        assert_eq!(texture.gl_type, GL_SAMPLER_2D);
+        assert_eq!(texture.offset, None);
+        assert_eq!(texture.stages, [Stage::Pixel]);
+        let color = reflection.uniform("Material_Color").unwrap();
+        assert_eq!(color.gl_type, GL_FLOAT_VEC4);
+        assert_eq!(color.offset, Some(16));
+        assert_eq!(color.array_size, 1);
+        assert_eq!(color.stages, [Stage::Vertex, Stage::Pixel]);
+        let attributes: Vec<_> = reflection.attributes().collect();
+        assert_eq!(attributes.len(), 1);
+        assert_eq!(attributes[0].name, "Position");
+        assert_eq!(attributes[0].gl_type, GL_FLOAT_VEC3);
+        //Reflection is built once per program.
+        assert!(program.reflect(ReflectionOptions::new()).is_some());
+        assert!(program.reflect(ReflectionOptions::new().all_block_variables()).is_none());
+    }
+}
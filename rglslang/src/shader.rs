@@ -29,7 +29,8 @@
 use std::{
     borrow::Cow,
     ffi::{CStr, CString},
-    os::raw::c_char
+    os::raw::c_char,
+    sync::Arc
 };
 
 use glslang_sys::{
@@ -39,16 +40,27 @@ use glslang_sys::{
     EShMsgAST,
     EShMsgDebugInfo,
     EShMsgDefault,
+    EShMsgHlslEnable16BitTypes,
+    EShMsgHlslOffsets,
+    EShMsgReadHlsl,
     EShMsgRelaxedErrors,
+    EShMsgSpvRules,
     EShMsgSuppressWarnings,
-    EShSourceGlsl,
+    EShMsgVulkanRules,
     EShTexSampTransUpgradeTextureRemoveSampler,
     EbsCount,
     EbsNone,
     EbsPushConstant,
     EbsStorageBuffer,
     EbsUniform,
+    EResImage,
+    EResSampler,
+    EResSsbo,
+    EResTexture,
+    EResUav,
+    EResUbo,
     TBlockStorageClass,
+    TResourceType,
     TShader,
     TShader_addBlockStorageOverride,
     TShader_addUniformLocationOverride,
@@ -57,6 +69,7 @@ use glslang_sys::{
     TShader_getInfoDebugLog,
     TShader_getInfoLog,
     TShader_parse,
+    TShader_parseWithIncluder,
     TShader_setAtomicCounterBlockBinding,
     TShader_setAtomicCounterBlockName,
     TShader_setAtomicCounterBlockSet,
@@ -70,10 +83,13 @@ use glslang_sys::{
     TShader_setGlobalUniformBinding,
     TShader_setGlobalUniformBlockName,
     TShader_setGlobalUniformSet,
+    TShader_setHlslIoMapping,
     TShader_setInvertY,
     TShader_setNanMinMaxClamp,
     TShader_setNoStorageFormat,
     TShader_setPreamble,
+    TShader_setShiftBinding,
+    TShader_setShiftBindingForSet,
     TShader_setSourceEntryPoint,
     TShader_setStringsWithLengthsAndNames,
     TShader_setTextureSamplerTransformMode,
@@ -81,7 +97,9 @@ use glslang_sys::{
     TShader_setUniqueId
 };
 
-use crate::environment::Environment;
+use crate::diagnostic::{parse_info_log, Diagnostic};
+use crate::environment::{Environment, Stage};
+use crate::include::{include_callback, release_callback, IncludeContext, IncludeType, Includer, ResolvedInclude, INCLUDE_EXTENSION};
 
 #[derive(Copy, Clone, Debug)]
 pub enum Profile
@@ -129,6 +147,33 @@ impl BlockStorageClass
     }
 }
 
+/// Kind of resource a binding shift applies to.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ResourceType
+{
+    Sampler,
+    Texture,
+    Image,
+    Ubo,
+    Ssbo,
+    Uav
+}
+
+impl ResourceType
+{
+    pub fn into(self) -> TResourceType
+    {
+        match self {
+            ResourceType::Sampler => EResSampler,
+            ResourceType::Texture => EResTexture,
+            ResourceType::Image => EResImage,
+            ResourceType::Ubo => EResUbo,
+            ResourceType::Ssbo => EResSsbo,
+            ResourceType::Uav => EResUav
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Part
 {
@@ -143,6 +188,15 @@ impl Part
         self.code
     }
 
+    pub fn code(&self) -> &str {
+        &self.code
+    }
+
+    /// Returns the name of this shader part, None if the part is unnamed or the name is not UTF-8.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_ref().and_then(|v| v.to_str().ok())
+    }
+
     pub fn new<T: AsRef<str>>(code: T) -> Part
     {
         return Part {
@@ -167,13 +221,9 @@ struct ShaderStorage
     code_len_arr: Vec<i32>,
     code_arr: Vec<*const c_char>,
     name_arr: Vec<*const c_char>,
-    preamble: Option<CString>,
-    entry_point: Option<CString>,
-    source_entry_point_name: Option<CString>,
-    global_uniform_block_name: Option<CString>,
-    atomic_counter_block_name: Option<CString>,
-    uniform_location_overrides: Vec<CString>,
-    block_storage_overrides: Vec<CString>
+    //glslang keeps pointers to the strings of the config (preamble, entry points, etc.) so the
+    //config must live as long as the shader.
+    _config: Option<ShaderConfig>
 }
 
 impl ShaderStorage
@@ -192,6 +242,7 @@ impl ShaderStorage
     }
 }
 
+#[derive(Copy, Clone, Debug)]
 pub struct Messages
 {
     messages: EShMessages
@@ -237,223 +288,264 @@ impl Messages
         self.messages |= EShMsgAST;
         self
     }
+
+    pub fn spirv_rules(mut self) -> Self
+    {
+        self.messages |= EShMsgSpvRules;
+        self
+    }
+
+    pub fn vulkan_rules(mut self) -> Self
+    {
+        self.messages |= EShMsgVulkanRules;
+        self
+    }
+
+    pub fn read_hlsl(mut self) -> Self
+    {
+        self.messages |= EShMsgReadHlsl;
+        self
+    }
+
+    pub fn hlsl_offsets(mut self) -> Self
+    {
+        self.messages |= EShMsgHlslOffsets;
+        self
+    }
+
+    pub fn hlsl_enable_16bit_types(mut self) -> Self
+    {
+        self.messages |= EShMsgHlslEnable16BitTypes;
+        self
+    }
 }
 
-pub struct Builder
+/// Settings shared by any number of shaders.
+///
+/// A config is built once (for example once per target) and then applied to each new shader
+/// using [Builder::from_config](Builder::from_config).
+#[derive(Clone, Debug)]
+pub struct ShaderConfig
 {
-    storage: ShaderStorage,
-    low_level: *const TShader,
     env: Environment,
+    messages: Messages,
     default_version: i32,
     default_profile: Profile,
     forward_compatible: bool,
     force_default_version_and_profile: bool,
-    messages: EShMessages
+    preamble: Option<CString>,
+    entry_point: Option<CString>,
+    source_entry_point: Option<CString>,
+    global_uniform_block_name: Option<CString>,
+    atomic_counter_block_name: Option<CString>,
+    block_storage_overrides: Vec<(CString, BlockStorageClass)>,
+    uniform_location_overrides: Vec<(CString, i32)>,
+    //Resource type, base and descriptor set (None for all sets).
+    binding_shifts: Vec<(ResourceType, u32, Option<u32>)>,
+    unique_id: Option<u64>,
+    auto_map_bindings: bool,
+    auto_map_locations: bool,
+    uniform_location_base: Option<i32>,
+    invert_y: bool,
+    no_storage_format: bool,
+    nan_min_max_clamp: bool,
+    combined_texture_sampler: bool,
+    global_uniform_set: Option<u32>,
+    global_uniform_binding: Option<u32>,
+    atomic_counter_block_set: Option<u32>,
+    atomic_counter_block_binding: Option<u32>,
+    vulkan_rules_relaxed: bool,
+    hlsl_io_mapping: bool,
+    includer: Option<Includer>
 }
 
-impl Builder
+impl ShaderConfig
 {
-    pub fn new(env: Environment) -> Builder
+    pub fn new(env: Environment) -> ShaderConfig
     {
-        unsafe {
-            Builder {
-                storage: ShaderStorage::default(),
-                low_level: TShader_create(env.get_stage()),
-                env,
-                default_profile: Profile::None,
-                default_version: 300,
-                forward_compatible: true,
-                force_default_version_and_profile: false,
-                messages: EShMsgDefault
-            }
+        ShaderConfig {
+            env,
+            messages: Messages::new(),
+            default_version: 300,
+            default_profile: Profile::None,
+            forward_compatible: true,
+            force_default_version_and_profile: false,
+            preamble: None,
+            entry_point: None,
+            source_entry_point: None,
+            global_uniform_block_name: None,
+            atomic_counter_block_name: None,
+            block_storage_overrides: Vec::new(),
+            uniform_location_overrides: Vec::new(),
+            binding_shifts: Vec::new(),
+            unique_id: None,
+            auto_map_bindings: false,
+            auto_map_locations: false,
+            uniform_location_base: None,
+            invert_y: false,
+            no_storage_format: false,
+            nan_min_max_clamp: false,
+            combined_texture_sampler: false,
+            global_uniform_set: None,
+            global_uniform_binding: None,
+            atomic_counter_block_set: None,
+            atomic_counter_block_binding: None,
+            vulkan_rules_relaxed: false,
+            hlsl_io_mapping: false,
+            includer: None
         }
     }
 
     pub fn preamble<T: AsRef<str>>(mut self, preamble: T) -> Self
     {
-        self.storage.preamble = Some(CString::new(preamble.as_ref()).unwrap());
-        unsafe {
-            TShader_setPreamble(self.low_level, self.storage.preamble.as_ref().unwrap().as_ptr());
-        }
+        self.preamble = Some(CString::new(preamble.as_ref()).unwrap());
         self
     }
 
     pub fn entry_point<T: AsRef<str>>(mut self, name: T) -> Self
     {
-        self.storage.entry_point = Some(CString::new(name.as_ref()).unwrap());
-        unsafe {
-            TShader_setEntryPoint(self.low_level, self.storage.entry_point.as_ref().unwrap().as_ptr());
-        }
+        self.entry_point = Some(CString::new(name.as_ref()).unwrap());
         self
     }
 
     pub fn source_entry_point<T: AsRef<str>>(mut self, name: T) -> Self
     {
-        self.storage.source_entry_point_name = Some(CString::new(name.as_ref()).unwrap());
-        unsafe {
-            TShader_setSourceEntryPoint(
-                self.low_level,
-                self.storage.source_entry_point_name.as_ref().unwrap().as_ptr()
-            );
-        }
+        self.source_entry_point = Some(CString::new(name.as_ref()).unwrap());
         self
     }
 
     pub fn global_uniform_block_name<T: AsRef<str>>(mut self, name: T) -> Self
     {
-        self.storage.global_uniform_block_name = Some(CString::new(name.as_ref()).unwrap());
-        unsafe {
-            TShader_setGlobalUniformBlockName(
-                self.low_level,
-                self.storage.global_uniform_block_name.as_ref().unwrap().as_ptr()
-            );
-        }
+        self.global_uniform_block_name = Some(CString::new(name.as_ref()).unwrap());
         self
     }
 
     pub fn atomic_counter_block_name<T: AsRef<str>>(mut self, name: T) -> Self
     {
-        self.storage.atomic_counter_block_name = Some(CString::new(name.as_ref()).unwrap());
-        unsafe {
-            TShader_setAtomicCounterBlockName(
-                self.low_level,
-                self.storage.atomic_counter_block_name.as_ref().unwrap().as_ptr()
-            );
-        }
+        self.atomic_counter_block_name = Some(CString::new(name.as_ref()).unwrap());
         self
     }
 
     pub fn add_block_storage_override<T: AsRef<str>>(mut self, name: T, backing: BlockStorageClass) -> Self
     {
-        self.storage
-            .block_storage_overrides
-            .push(CString::new(name.as_ref()).unwrap());
-        let name = self.storage.block_storage_overrides.last().unwrap();
-        unsafe {
-            TShader_addBlockStorageOverride(self.low_level, name.as_ptr(), backing.into());
-        }
+        self.block_storage_overrides
+            .push((CString::new(name.as_ref()).unwrap(), backing));
         self
     }
 
     pub fn add_uniform_location_override<T: AsRef<str>>(mut self, name: T, loc: i32) -> Self
     {
-        self.storage
-            .uniform_location_overrides
-            .push(CString::new(name.as_ref()).unwrap());
-        let name = self.storage.uniform_location_overrides.last().unwrap();
-        unsafe {
-            TShader_addUniformLocationOverride(self.low_level, name.as_ptr(), loc);
-        }
+        self.uniform_location_overrides
+            .push((CString::new(name.as_ref()).unwrap(), loc));
         self
     }
 
-    pub fn add_part(mut self, p: Part) -> Self
+    /// Shifts the bindings of all resources of the given type by base; shifts are applied when
+    /// the program is linked with [map_io](crate::program::Builder::map_io).
+    pub fn shift_binding(mut self, res: ResourceType, base: u32) -> Self
     {
-        self.storage.parts.push(p);
+        self.binding_shifts.push((res, base, None));
         self
     }
 
-    pub fn unique_id(self, id: u64) -> Self
+    /// Same as [shift_binding](ShaderConfig::shift_binding) but only for resources of the given
+    /// descriptor set.
+    pub fn shift_binding_for_set(mut self, res: ResourceType, base: u32, set: u32) -> Self
     {
-        unsafe {
-            TShader_setUniqueId(self.low_level, id);
-        }
+        self.binding_shifts.push((res, base, Some(set)));
         self
     }
 
-    pub fn auto_map_bindings(self) -> Self
+    pub fn unique_id(mut self, id: u64) -> Self
     {
-        unsafe {
-            TShader_setAutoMapBindings(self.low_level, true);
-        }
+        self.unique_id = Some(id);
         self
     }
 
-    pub fn auto_map_locations(self) -> Self
+    pub fn auto_map_bindings(mut self) -> Self
     {
-        unsafe {
-            TShader_setAutoMapLocations(self.low_level, true);
-        }
+        self.auto_map_bindings = true;
         self
     }
 
-    pub fn uniform_location_base(self, base: i32) -> Self
+    pub fn auto_map_locations(mut self) -> Self
     {
-        unsafe {
-            TShader_setUniformLocationBase(self.low_level, base);
-        }
+        self.auto_map_locations = true;
         self
     }
 
-    pub fn invert_y(self) -> Self
+    pub fn uniform_location_base(mut self, base: i32) -> Self
     {
-        unsafe {
-            TShader_setInvertY(self.low_level, true);
-        }
+        self.uniform_location_base = Some(base);
         self
     }
 
-    pub fn no_storage_format(self) -> Self
+    pub fn invert_y(mut self) -> Self
     {
-        unsafe {
-            TShader_setNoStorageFormat(self.low_level, true);
-        }
+        self.invert_y = true;
         self
     }
 
-    pub fn nan_min_max_clamp(self) -> Self
+    pub fn no_storage_format(mut self) -> Self
     {
-        unsafe {
-            TShader_setNanMinMaxClamp(self.low_level, true);
-        }
+        self.no_storage_format = true;
         self
     }
 
-    pub fn use_combined_texture_sampler(self) -> Self
+    pub fn nan_min_max_clamp(mut self) -> Self
     {
-        unsafe {
-            TShader_setTextureSamplerTransformMode(self.low_level, EShTexSampTransUpgradeTextureRemoveSampler);
-        }
+        self.nan_min_max_clamp = true;
         self
     }
 
-    pub fn global_uniform_set(self, set: u32) -> Self
+    pub fn use_combined_texture_sampler(mut self) -> Self
     {
-        unsafe {
-            TShader_setGlobalUniformSet(self.low_level, set);
-        }
+        self.combined_texture_sampler = true;
         self
     }
 
-    pub fn global_uniform_binding(self, binding: u32) -> Self
+    pub fn global_uniform_set(mut self, set: u32) -> Self
     {
-        unsafe {
-            TShader_setGlobalUniformBinding(self.low_level, binding);
-        }
+        self.global_uniform_set = Some(set);
         self
     }
 
-    pub fn atomic_counter_block_set(self, set: u32) -> Self
+    pub fn global_uniform_binding(mut self, binding: u32) -> Self
     {
-        unsafe {
-            TShader_setAtomicCounterBlockSet(self.low_level, set);
-        }
+        self.global_uniform_binding = Some(binding);
         self
     }
 
-    pub fn atomic_counter_block_binding(self, binding: u32) -> Self
+    pub fn atomic_counter_block_set(mut self, set: u32) -> Self
     {
-        unsafe {
-            TShader_setAtomicCounterBlockBinding(self.low_level, binding);
-        }
+        self.atomic_counter_block_set = Some(set);
         self
     }
 
-    pub fn vulkan_rules_relaxed(self) -> Self
+    pub fn atomic_counter_block_binding(mut self, binding: u32) -> Self
     {
-        unsafe {
-            TShader_setEnvInputVulkanRulesRelaxed(self.low_level);
-        }
+        self.atomic_counter_block_binding = Some(binding);
+        self
+    }
+
+    pub fn vulkan_rules_relaxed(mut self) -> Self
+    {
+        self.vulkan_rules_relaxed = true;
+        self
+    }
+
+    /// Maps HLSL register bindings and semantics through the HLSL rules of the IO mapper.
+    pub fn hlsl_io_mapping(mut self) -> Self
+    {
+        self.hlsl_io_mapping = true;
+        self
+    }
+
+    /// Resolves `#include` directives through the given callback; the include extension is
+    /// enabled through the preamble.
+    pub fn includer<F: Fn(&str, IncludeType) -> Option<ResolvedInclude> + Send + Sync + 'static>(mut self, f: F) -> Self
+    {
+        self.includer = Some(Includer(Arc::new(f)));
         self
     }
 
@@ -483,47 +575,366 @@ impl Builder
 
     pub fn messages(mut self, msgs: Messages) -> Self
     {
-        self.messages = msgs.messages;
+        self.messages = msgs;
+        self
+    }
+
+    /// Changes the stage of the environment, leaving every other setting untouched.
+    pub fn stage(mut self, stage: Stage) -> Self
+    {
+        self.env = self.env.with_stage(stage);
+        self
+    }
+
+    pub fn env(&self) -> &Environment
+    {
+        &self.env
+    }
+
+    unsafe fn apply(&self, shader: *const TShader)
+    {
+        if let Some(v) = &self.preamble {
+            TShader_setPreamble(shader, v.as_ptr());
+        }
+        if let Some(v) = &self.entry_point {
+            TShader_setEntryPoint(shader, v.as_ptr());
+        }
+        if let Some(v) = &self.source_entry_point {
+            TShader_setSourceEntryPoint(shader, v.as_ptr());
+        }
+        if let Some(v) = &self.global_uniform_block_name {
+            TShader_setGlobalUniformBlockName(shader, v.as_ptr());
+        }
+        if let Some(v) = &self.atomic_counter_block_name {
+            TShader_setAtomicCounterBlockName(shader, v.as_ptr());
+        }
+        for (name, backing) in &self.block_storage_overrides {
+            TShader_addBlockStorageOverride(shader, name.as_ptr(), (*backing).into());
+        }
+        for (name, loc) in &self.uniform_location_overrides {
+            TShader_addUniformLocationOverride(shader, name.as_ptr(), *loc);
+        }
+        for (res, base, set) in &self.binding_shifts {
+            match set {
+                Some(set) => TShader_setShiftBindingForSet(shader, (*res).into(), *base, *set),
+                None => TShader_setShiftBinding(shader, (*res).into(), *base)
+            }
+        }
+        if let Some(id) = self.unique_id {
+            TShader_setUniqueId(shader, id);
+        }
+        if self.auto_map_bindings {
+            TShader_setAutoMapBindings(shader, true);
+        }
+        if self.auto_map_locations {
+            TShader_setAutoMapLocations(shader, true);
+        }
+        if let Some(base) = self.uniform_location_base {
+            TShader_setUniformLocationBase(shader, base);
+        }
+        if self.invert_y {
+            TShader_setInvertY(shader, true);
+        }
+        if self.no_storage_format {
+            TShader_setNoStorageFormat(shader, true);
+        }
+        if self.nan_min_max_clamp {
+            TShader_setNanMinMaxClamp(shader, true);
+        }
+        if self.combined_texture_sampler {
+            TShader_setTextureSamplerTransformMode(shader, EShTexSampTransUpgradeTextureRemoveSampler);
+        }
+        if let Some(set) = self.global_uniform_set {
+            TShader_setGlobalUniformSet(shader, set);
+        }
+        if let Some(binding) = self.global_uniform_binding {
+            TShader_setGlobalUniformBinding(shader, binding);
+        }
+        if let Some(set) = self.atomic_counter_block_set {
+            TShader_setAtomicCounterBlockSet(shader, set);
+        }
+        if let Some(binding) = self.atomic_counter_block_binding {
+            TShader_setAtomicCounterBlockBinding(shader, binding);
+        }
+        if self.vulkan_rules_relaxed {
+            TShader_setEnvInputVulkanRulesRelaxed(shader);
+        }
+        if self.hlsl_io_mapping {
+            TShader_setHlslIoMapping(shader, true);
+        }
+        TShader_setEnvInput(
+            shader,
+            self.env.get_source(),
+            self.env.get_stage(),
+            self.env.get_dialect(),
+            self.env.get_dialect_version()
+        );
+        TShader_setEnvClient(shader, self.env.get_client(), self.env.get_client_version());
+        TShader_setEnvTarget(
+            shader,
+            self.env.get_target_language(),
+            self.env.get_target_language_version()
+        );
+    }
+}
+
+pub struct Builder
+{
+    storage: ShaderStorage,
+    config: ShaderConfig
+}
+
+impl Builder
+{
+    pub fn new(env: Environment) -> Builder
+    {
+        Builder::from_config(&ShaderConfig::new(env))
+    }
+
+    pub fn from_config(config: &ShaderConfig) -> Builder
+    {
+        Builder {
+            storage: ShaderStorage::default(),
+            config: config.clone()
+        }
+    }
+
+    pub fn add_part(mut self, p: Part) -> Self
+    {
+        self.storage.parts.push(p);
+        self
+    }
+
+    pub fn preamble<T: AsRef<str>>(mut self, preamble: T) -> Self
+    {
+        self.config = self.config.preamble(preamble);
+        self
+    }
+
+    pub fn entry_point<T: AsRef<str>>(mut self, name: T) -> Self
+    {
+        self.config = self.config.entry_point(name);
+        self
+    }
+
+    pub fn source_entry_point<T: AsRef<str>>(mut self, name: T) -> Self
+    {
+        self.config = self.config.source_entry_point(name);
+        self
+    }
+
+    pub fn global_uniform_block_name<T: AsRef<str>>(mut self, name: T) -> Self
+    {
+        self.config = self.config.global_uniform_block_name(name);
+        self
+    }
+
+    pub fn atomic_counter_block_name<T: AsRef<str>>(mut self, name: T) -> Self
+    {
+        self.config = self.config.atomic_counter_block_name(name);
+        self
+    }
+
+    pub fn add_block_storage_override<T: AsRef<str>>(mut self, name: T, backing: BlockStorageClass) -> Self
+    {
+        self.config = self.config.add_block_storage_override(name, backing);
+        self
+    }
+
+    pub fn add_uniform_location_override<T: AsRef<str>>(mut self, name: T, loc: i32) -> Self
+    {
+        self.config = self.config.add_uniform_location_override(name, loc);
+        self
+    }
+
+    pub fn shift_binding(mut self, res: ResourceType, base: u32) -> Self
+    {
+        self.config = self.config.shift_binding(res, base);
+        self
+    }
+
+    pub fn shift_binding_for_set(mut self, res: ResourceType, base: u32, set: u32) -> Self
+    {
+        self.config = self.config.shift_binding_for_set(res, base, set);
+        self
+    }
+
+    pub fn unique_id(mut self, id: u64) -> Self
+    {
+        self.config = self.config.unique_id(id);
+        self
+    }
+
+    pub fn auto_map_bindings(mut self) -> Self
+    {
+        self.config = self.config.auto_map_bindings();
+        self
+    }
+
+    pub fn auto_map_locations(mut self) -> Self
+    {
+        self.config = self.config.auto_map_locations();
+        self
+    }
+
+    pub fn uniform_location_base(mut self, base: i32) -> Self
+    {
+        self.config = self.config.uniform_location_base(base);
+        self
+    }
+
+    pub fn invert_y(mut self) -> Self
+    {
+        self.config = self.config.invert_y();
+        self
+    }
+
+    pub fn no_storage_format(mut self) -> Self
+    {
+        self.config = self.config.no_storage_format();
+        self
+    }
+
+    pub fn nan_min_max_clamp(mut self) -> Self
+    {
+        self.config = self.config.nan_min_max_clamp();
+        self
+    }
+
+    pub fn use_combined_texture_sampler(mut self) -> Self
+    {
+        self.config = self.config.use_combined_texture_sampler();
+        self
+    }
+
+    pub fn global_uniform_set(mut self, set: u32) -> Self
+    {
+        self.config = self.config.global_uniform_set(set);
+        self
+    }
+
+    pub fn global_uniform_binding(mut self, binding: u32) -> Self
+    {
+        self.config = self.config.global_uniform_binding(binding);
+        self
+    }
+
+    pub fn atomic_counter_block_set(mut self, set: u32) -> Self
+    {
+        self.config = self.config.atomic_counter_block_set(set);
+        self
+    }
+
+    pub fn atomic_counter_block_binding(mut self, binding: u32) -> Self
+    {
+        self.config = self.config.atomic_counter_block_binding(binding);
+        self
+    }
+
+    pub fn vulkan_rules_relaxed(mut self) -> Self
+    {
+        self.config = self.config.vulkan_rules_relaxed();
+        self
+    }
+
+    pub fn hlsl_io_mapping(mut self) -> Self
+    {
+        self.config = self.config.hlsl_io_mapping();
+        self
+    }
+
+    pub fn includer<F: Fn(&str, IncludeType) -> Option<ResolvedInclude> + Send + Sync + 'static>(mut self, f: F) -> Self
+    {
+        self.config = self.config.includer(f);
+        self
+    }
+
+    pub fn default_version(mut self, version: i32) -> Self
+    {
+        self.config = self.config.default_version(version);
+        self
+    }
+
+    pub fn default_profile(mut self, profile: Profile) -> Self
+    {
+        self.config = self.config.default_profile(profile);
+        self
+    }
+
+    pub fn forward_incompatible(mut self) -> Self
+    {
+        self.config = self.config.forward_incompatible();
+        self
+    }
+
+    pub fn force_default_version_and_profile(mut self) -> Self
+    {
+        self.config = self.config.force_default_version_and_profile();
+        self
+    }
+
+    pub fn messages(mut self, msgs: Messages) -> Self
+    {
+        self.config = self.config.messages(msgs);
+        self
+    }
+
+    pub fn stage(mut self, stage: Stage) -> Self
+    {
+        self.config = self.config.stage(stage);
         self
     }
 
     pub fn parse(mut self) -> Shader
     {
+        if self.config.includer.is_some() {
+            let preamble = self.config.preamble.take()
+                .map(|v| v.into_string().unwrap())
+                .unwrap_or_default();
+            self.config.preamble = Some(CString::new(format!("{}{}", INCLUDE_EXTENSION, preamble)).unwrap());
+        }
         unsafe {
-            TShader_setEnvInput(
-                self.low_level,
-                EShSourceGlsl,
-                self.env.get_stage(),
-                self.env.get_dialect(),
-                self.env.get_dialect_version()
-            );
-            TShader_setEnvClient(self.low_level, self.env.get_client(), self.env.get_client_version());
-            TShader_setEnvTarget(
-                self.low_level,
-                self.env.get_target_language(),
-                self.env.get_target_language_version()
-            );
+            let low_level = TShader_create(self.config.env.get_stage());
+            self.config.apply(low_level);
             self.storage.build_code_name_arr();
             TShader_setStringsWithLengthsAndNames(
-                self.low_level,
+                low_level,
                 self.storage.code_arr.as_ptr(),
                 self.storage.code_len_arr.as_ptr(),
                 self.storage.name_arr.as_ptr(),
                 self.storage.code_arr.len() as _
             );
-            let flag = TShader_parse(
-                self.low_level,
-                TBuiltInResource_default(),
-                self.default_version,
-                self.default_profile.into(),
-                self.force_default_version_and_profile,
-                self.forward_compatible,
-                self.messages
-            );
+            let flag = match &self.config.includer {
+                Some(includer) => {
+                    let mut ctx = IncludeContext::new(includer);
+                    TShader_parseWithIncluder(
+                        low_level,
+                        TBuiltInResource_default(),
+                        self.config.default_version,
+                        self.config.default_profile.into(),
+                        self.config.force_default_version_and_profile,
+                        self.config.forward_compatible,
+                        self.config.messages.messages,
+                        &mut ctx as *mut IncludeContext as _,
+                        include_callback,
+                        release_callback
+                    )
+                },
+                None => TShader_parse(
+                    low_level,
+                    TBuiltInResource_default(),
+                    self.config.default_version,
+                    self.config.default_profile.into(),
+                    self.config.force_default_version_and_profile,
+                    self.config.forward_compatible,
+                    self.config.messages.messages
+                )
+            };
+            self.storage._config = Some(self.config);
             Shader {
                 valid: flag,
                 _storage: self.storage,
-                low_level: self.low_level
+                low_level
             }
         }
     }
@@ -546,6 +957,12 @@ impl Shader
         }
     }
 
+    /// Returns the errors and warnings of the info log.
+    pub fn diagnostics(&self) -> Vec<Diagnostic>
+    {
+        parse_info_log(&self.get_info_log())
+    }
+
     pub fn get_info_debug_log(&self) -> Cow<str>
     {
         unsafe {
@@ -585,3 +1002,106 @@ pub(crate) fn unwrap_messages(m: Messages) -> EShMessages
 {
     m.messages
 }
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::environment::{Client, ClientVersion, TargetVersion};
+
+    const VALID: &str = "#version 330 core\nvoid main() { gl_Position = vec4(1.0); }\n";
+    const INVALID: &str = "#version 330 core\nvoid main() { gl_Position = vec3(1.0); }\n";
+
+    fn fluent(stage: Stage, code: &str) -> Shader
+    {
+        Builder::new(Environment::new_opengl(stage, Client::OpenGL, Some(330)))
+            .messages(Messages::new())
+            .entry_point("main")
+            .source_entry_point("main")
+            .default_version(330)
+            .default_profile(Profile::Core)
+            .add_part(Part::new(code))
+            .parse()
+    }
+
+    #[test]
+    fn shared_config()
+    {
+        crate::test_init();
+        let config = ShaderConfig::new(Environment::new_opengl(Stage::Pixel, Client::OpenGL, Some(330)))
+            .messages(Messages::new())
+            .entry_point("main")
+            .source_entry_point("main")
+            .default_version(330)
+            .default_profile(Profile::Core);
+        for code in [VALID, INVALID] {
+            let expected = fluent(Stage::Vertex, code);
+            let a = Builder::from_config(&config).stage(Stage::Vertex).add_part(Part::new(code)).parse();
+            let b = Builder::from_config(&config).stage(Stage::Vertex).add_part(Part::new(code)).parse();
+            assert_eq!(a.check(), expected.check());
+            assert_eq!(b.check(), expected.check());
+            assert_eq!(a.get_info_log(), expected.get_info_log());
+            assert_eq!(b.get_info_log(), expected.get_info_log());
+        }
+    }
+
+    //Resolves includes from a fixed set of files, relative includes first look next to the includer.
+    fn includes(files: &'static [(&'static str, &'static str)], code: &str) -> Shader
+    {
+        Builder::new(Environment::new_opengl(Stage::Vertex, Client::OpenGL, Some(330)))
+            .entry_point("main")
+            .source_entry_point("main")
+            .default_version(330)
+            .default_profile(Profile::Core)
+            .includer(move |name, ty| {
+                let relative = match ty {
+                    IncludeType::Relative(includer) => includer.rsplit_once('/').map(|(dir, _)| format!("{}/{}", dir, name)),
+                    IncludeType::System => None
+                };
+                [relative, Some(name.into())].into_iter().flatten()
+                    .find_map(|path| files.iter().find(|(v, _)| *v == path))
+                    .map(|(path, content)| ResolvedInclude::new(*path, *content))
+            })
+            .add_part(Part::new_with_name(code, "shaders/main.glsl"))
+            .parse()
+    }
+
+    #[test]
+    fn include()
+    {
+        crate::test_init();
+        const FILES: &[(&str, &str)] = &[
+            ("shaders/common.glsl", "#include \"lighting.glsl\"\nvec4 origin() { return vec4(light()); }\n"),
+            ("shaders/lighting.glsl", "float light() { return 1.0; }\n"),
+            ("lighting.glsl", "#error wrong lighting.glsl\n"),
+            ("shaders/a.glsl", "#include \"b.glsl\"\n"),
+            ("shaders/b.glsl", "#include \"a.glsl\"\n")
+        ];
+        let shader = includes(FILES, "#version 330 core\n#include \"common.glsl\"\nvoid main() { gl_Position = origin(); }\n");
+        assert!(shader.check(), "{}", shader.get_info_log());
+        let shader = includes(FILES, "#version 330 core\n#include \"missing.glsl\"\nvoid main() {}\n");
+        assert!(!shader.check());
+        assert!(shader.get_info_log().contains("include 'missing.glsl' not found"), "{}", shader.get_info_log());
+        let shader = includes(FILES, "#version 330 core\n#include \"a.glsl\"\nvoid main() {}\n");
+        assert!(!shader.check());
+        let log = shader.get_info_log();
+        assert!(log.contains("include cycle: shaders/main.glsl -> shaders/a.glsl -> shaders/b.glsl -> shaders/a.glsl"), "{}", log);
+    }
+
+    #[test]
+    fn hlsl()
+    {
+        crate::test_init();
+        let env = Environment::new_vulkan_hlsl(Stage::Pixel, ClientVersion::Vulkan11, TargetVersion::Spv13);
+        let shader = Builder::new(env)
+            .messages(Messages::new().read_hlsl().spirv_rules().vulkan_rules())
+            .entry_point("main")
+            .default_version(100)
+            .hlsl_io_mapping()
+            .add_part(Part::new("float4 main() : SV_Target { return 1; }\n"))
+            .parse();
+        let log = shader.get_info_log();
+        assert!(shader.check(), "{}", log);
+        assert!(!log.contains("ERROR"));
+    }
+}
@@ -0,0 +1,289 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use std::{ffi::CStr, os::raw::c_void};
+
+use glslang_sys::{
+    SpvContext,
+    SpvContext_create,
+    SpvContext_destroy,
+    SpvContext_fromGlslang,
+    SpvContext_getData,
+    SpvContext_getLog,
+    SpvContext_getSize,
+    SpvOptions
+};
+
+use crate::{environment::Stage, program::Program};
+
+pub const SPIRV_MAGIC: u32 = 0x07230203;
+
+pub struct SpvBuilder
+{
+    opts: SpvOptions
+}
+
+impl Default for SpvBuilder
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
+
+impl SpvBuilder
+{
+    pub fn new() -> SpvBuilder
+    {
+        SpvBuilder {
+            opts: SpvOptions {
+                disableOptimizer: true,
+                ..Default::default()
+            }
+        }
+    }
+
+    pub fn debug_info(mut self) -> Self
+    {
+        self.opts.generateDebugInfo = true;
+        self
+    }
+
+    pub fn strip_debug_info(mut self) -> Self
+    {
+        self.opts.stripDebugInfo = true;
+        self
+    }
+
+    pub fn optimize_size(mut self) -> Self
+    {
+        self.opts.disableOptimizer = false;
+        self.opts.optimizeSize = true;
+        self
+    }
+
+    pub fn validate(mut self) -> Self
+    {
+        self.opts.validate = true;
+        self
+    }
+
+    /// Appends a textual disassembly of the module to the log.
+    pub fn disassemble(mut self) -> Self
+    {
+        self.opts.disassemble = true;
+        self
+    }
+
+    /// Converts a stage of a linked program to SPIR-V, None if the program is not valid or the stage
+    /// is not part of the program.
+    pub fn build(&self, program: &Program, stage: Stage) -> Option<SpvBinary>
+    {
+        let intermediate = program.get_intermediate(stage)?;
+        unsafe { Some(SpvBinary::new(intermediate, &self.opts)) }
+    }
+}
+
+/// SPIR-V module generated by a [SpvBuilder](SpvBuilder).
+pub struct SpvBinary
+{
+    low_level: *const SpvContext,
+    log: String
+}
+
+impl SpvBinary
+{
+    unsafe fn new(intermediate: *const c_void, opts: &SpvOptions) -> SpvBinary
+    {
+        let low_level = SpvContext_create();
+        SpvContext_fromGlslang(low_level, intermediate, opts);
+        let log = CStr::from_ptr(SpvContext_getLog(low_level)).to_string_lossy().into_owned();
+        SpvBinary { low_level, log }
+    }
+
+    pub fn as_words(&self) -> &[u32]
+    {
+        unsafe {
+            let size = SpvContext_getSize(self.low_level);
+            if size == 0 {
+                return &[];
+            }
+            std::slice::from_raw_parts(SpvContext_getData(self.low_level), size)
+        }
+    }
+
+    /// Returns the module as bytes in native endianness.
+    pub fn as_bytes(&self) -> &[u8]
+    {
+        let words = self.as_words();
+        unsafe { std::slice::from_raw_parts(words.as_ptr() as *const u8, std::mem::size_of_val(words)) }
+    }
+
+    /// Returns the messages reported during the conversion, including the disassembly if requested.
+    pub fn log(&self) -> &str
+    {
+        &self.log
+    }
+}
+
+impl Drop for SpvBinary
+{
+    fn drop(&mut self)
+    {
+        unsafe {
+            SpvContext_destroy(self.low_level);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::{
+        environment::{Client, ClientVersion, Environment, TargetVersion},
+        program::Builder,
+        shader::{Messages, Part, Profile, ResourceType, ShaderConfig}
+    };
+
+    #[test]
+    fn vertex()
+    {
+        crate::test_init();
+        let env = Environment::new_vulkan(Stage::Vertex, Client::Vulkan, Some(100), ClientVersion::Vulkan11, TargetVersion::Spv13);
+        let config = ShaderConfig::new(env)
+            .messages(Messages::new().spirv_rules().vulkan_rules())
+            .entry_point("main")
+            .default_version(450)
+            .default_profile(Profile::Core);
+        let shader = crate::shader::Builder::from_config(&config)
+            .add_part(Part::new("#version 450\nvoid main() { gl_Position = vec4(1.0); }\n"))
+            .parse();
+        assert!(shader.check(), "{}", shader.get_info_log());
+        let program = Builder::new().messages(Messages::new().spirv_rules().vulkan_rules()).add_shader(shader).link();
+        assert!(program.check(), "{}", program.get_info_log());
+        let spirv = SpvBuilder::new().disassemble().build(&program, Stage::Vertex).unwrap();
+        assert_eq!(spirv.as_words()[0], SPIRV_MAGIC);
+        assert_eq!(spirv.as_bytes().len(), spirv.as_words().len() * 4);
+        assert_eq!(spirv.as_bytes()[..4], SPIRV_MAGIC.to_ne_bytes());
+        assert!(SpvBuilder::new().build(&program, Stage::Pixel).is_none());
+    }
+
+    /// Returns the values of all Binding decorations of a SPIR-V module.
+    fn bindings(words: &[u32]) -> Vec<u32>
+    {
+        const OP_DECORATE: u32 = 71;
+        const DECORATION_BINDING: u32 = 33;
+        let mut bindings = Vec::new();
+        //Skip the header: magic, version, generator, bound and schema.
+        let mut i = 5;
+        while i < words.len() {
+            let (count, opcode) = ((words[i] >> 16) as usize, words[i] & 0xFFFF);
+            if opcode == OP_DECORATE && count == 4 && words[i + 2] == DECORATION_BINDING {
+                bindings.push(words[i + 3]);
+            }
+            i += count.max(1);
+        }
+        bindings.sort();
+        bindings
+    }
+
+    #[test]
+    fn shift_binding()
+    {
+        crate::test_init();
+        let env = Environment::new_vulkan(Stage::Pixel, Client::Vulkan, Some(100), ClientVersion::Vulkan11, TargetVersion::Spv13);
+        let config = ShaderConfig::new(env)
+            .messages(Messages::new().spirv_rules().vulkan_rules())
+            .entry_point("main")
+            .default_version(450)
+            .default_profile(Profile::Core);
+        let code = "#version 450
+layout (binding = 0) uniform Globals { vec4 Color; };
+layout (binding = 0) uniform sampler2D Base;
+layout (location = 0) out vec4 FragColor;
+void main() { FragColor = Color * texture(Base, vec2(0.0)); }
+";
+        let compile = |config: &ShaderConfig| {
+            let shader = crate::shader::Builder::from_config(config)
+                .add_part(Part::new(code))
+                .parse();
+            assert!(shader.check(), "{}", shader.get_info_log());
+            let program = Builder::new()
+                .messages(Messages::new().spirv_rules().vulkan_rules())
+                .add_shader(shader)
+                .map_io()
+                .link();
+            assert!(program.check(), "{}", program.get_info_log());
+            bindings(SpvBuilder::new().build(&program, Stage::Pixel).unwrap().as_words())
+        };
+        assert_eq!(compile(&config), [0, 0]);
+        let shifted = config.clone()
+            .shift_binding(ResourceType::Texture, 4)
+            .shift_binding_for_set(ResourceType::Ubo, 8, 0);
+        assert_eq!(compile(&shifted), [4, 8]);
+    }
+
+    /// Returns the names attached by OpName instructions of a SPIR-V module.
+    fn names(words: &[u32]) -> Vec<String>
+    {
+        const OP_NAME: u32 = 5;
+        let mut names = Vec::new();
+        let mut i = 5;
+        while i < words.len() {
+            let (count, opcode) = ((words[i] >> 16) as usize, words[i] & 0xFFFF);
+            if opcode == OP_NAME {
+                let bytes: Vec<u8> = words[i + 2..i + count].iter().flat_map(|v| v.to_le_bytes()).take_while(|v| *v != 0).collect();
+                names.push(String::from_utf8(bytes).unwrap());
+            }
+            i += count.max(1);
+        }
+        names
+    }
+
+    #[test]
+    fn opengl()
+    {
+        crate::test_init();
+        let env = Environment::new_opengl_spirv(Stage::Vertex, Some(100), TargetVersion::Spv10);
+        let config = ShaderConfig::new(env)
+            .messages(Messages::new().spirv_rules())
+            .entry_point("main")
+            .default_version(460)
+            .default_profile(Profile::Core);
+        let shader = crate::shader::Builder::from_config(&config)
+            .add_part(Part::new("#version 460 core\nlayout (location = 0) in vec4 Position;\nvoid main() { gl_Position = Position; }\n"))
+            .parse();
+        assert!(shader.check(), "{}", shader.get_info_log());
+        let program = Builder::new().messages(Messages::new().spirv_rules()).add_shader(shader).link();
+        assert!(program.check(), "{}", program.get_info_log());
+        let spirv = SpvBuilder::new().debug_info().build(&program, Stage::Vertex).unwrap();
+        assert_eq!(spirv.as_words()[0], SPIRV_MAGIC);
+        assert!(names(spirv.as_words()).iter().any(|v| v == "Position"));
+    }
+}
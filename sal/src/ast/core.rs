@@ -26,6 +26,7 @@
 // NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
 // SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
+use std::collections::HashSet;
 use std::vec::Vec;
 
 use phf::phf_map;
@@ -38,25 +39,29 @@ use crate::{
     parser::tree
 };
 use crate::ast::tree::ArrayType;
-use crate::ast::{RefResolver, Visitor};
-use crate::parser::tree::{Property, Struct, Use, VariableList};
+use crate::ast::{RefResolver, Strictness, Visitor};
+use crate::ast::strict::{check_property, check_struct, check_varlist, check_varying};
+use crate::parser::tree::{ConstantInt, Property, Resource, Struct, Use, VariableList};
 
 fn parse_vec_base<T>(ptype: &str) -> Result<ast::VectorType, TypeError<T>>
 {
-    let size = match &ptype[3..ptype.len() - 1].parse::<u8>() {
+    let (size, suffix) = ptype[3..].split_at(ptype[3..].find(|c: char| !c.is_ascii_digit()).unwrap_or(ptype.len() - 3));
+    let size = match size.parse::<u8>() {
         Err(e) => {
-            return Err(TypeError::VectorSize(e.clone()));
+            return Err(TypeError::VectorSize(e));
         },
-        Ok(v) => *v
+        Ok(v) => v
     };
-    let item = match &ptype[ptype.len() - 1..] {
+    let item = match suffix {
         "f" => ast::BaseType::Float,
         "d" => ast::BaseType::Double,
         "u" => ast::BaseType::Uint,
         "i" => ast::BaseType::Int,
         "b" => ast::BaseType::Bool,
+        "i64" => ast::BaseType::Int64,
+        "u64" => ast::BaseType::Uint64,
         _ => {
-            return Err(TypeError::UnknownVector(ptype[ptype.len() - 1..].into()));
+            return Err(TypeError::UnknownVector(suffix.into()));
         }
     };
     Ok(ast::VectorType { item, size })
@@ -82,9 +87,17 @@ fn try_parse_matrix<T>(ptype: &str) -> Result<Option<ast::PropertyType<T>>, Type
 
 fn try_parse_texture<A: RefResolver>(ptype: &str, ptype_attr: Option<&str>, ast: &A) -> Result<Option<ast::PropertyType<A::Key>>, TypeError<A::Key>>
 {
-    if let Some(subtype) = ptype_attr {
+    if let Some(attr) = ptype_attr {
         return match ptype {
-            "Texture2D" | "Texture3D" | "Texture2DArray" | "TextureCube" => {
+            "Texture1D" | "Texture2D" | "Texture3D" | "Texture2DArray" | "TextureCube" | "Texture2DMS" => {
+                //The sample count of multisample textures follows the sample type (ex: vec4f:4).
+                let (subtype, samples) = match attr.split_once(':') {
+                    Some((subtype, samples)) => (subtype, Some(samples)),
+                    None => (attr, None)
+                };
+                if samples.is_some() != (ptype == "Texture2DMS") {
+                    return Err(TypeError::SampleCount([ptype, attr].join(":")));
+                }
                 let ttype = match parse_type(subtype, None, None, ast)? {
                     ast::PropertyType::Scalar(t) => ast::TextureType::Scalar(t),
                     ast::PropertyType::Vector(t) => ast::TextureType::Vector(t),
@@ -92,10 +105,15 @@ fn try_parse_texture<A: RefResolver>(ptype: &str, ptype_attr: Option<&str>, ast:
                 };
                 unsafe {
                     match ptype {
+                        "Texture1D" => Ok(Some(ast::PropertyType::Texture1D(ttype))),
                         "Texture2D" => Ok(Some(ast::PropertyType::Texture2D(ttype))),
                         "Texture3D" => Ok(Some(ast::PropertyType::Texture3D(ttype))),
                         "Texture2DArray" => Ok(Some(ast::PropertyType::Texture2DArray(ttype))),
                         "TextureCube" => Ok(Some(ast::PropertyType::TextureCube(ttype))),
+                        "Texture2DMS" => match samples.and_then(|v| v.parse::<u32>().ok()) {
+                            Some(samples) if samples > 0 => Ok(Some(ast::PropertyType::Texture2DMS(ttype, samples))),
+                            _ => Err(TypeError::SampleCount([ptype, attr].join(":")))
+                        },
                         _ => std::hint::unreachable_unchecked()
                     }
                 }
@@ -106,13 +124,19 @@ fn try_parse_texture<A: RefResolver>(ptype: &str, ptype_attr: Option<&str>, ast:
     Ok(None)
 }
 
-fn try_parse_array<A: RefResolver>(ptype: &str, ptype_arr: Option<u32>, ast: &A) -> Result<Option<ast::PropertyType<A::Key>>, TypeError<A::Key>>
+fn try_parse_array<A: RefResolver>(ptype: &str, ptype_arr: Option<u32>, ptype_attr: Option<&str>, ast: &A) -> Result<Option<ast::PropertyType<A::Key>>, TypeError<A::Key>>
 {
     if let Some(size) = ptype_arr {
-        let item = match parse_type(ptype, None, None, ast)? {
+        let item = match parse_type(ptype, None, ptype_attr, ast)? {
             ast::PropertyType::Vector(t) => ast::ArrayItemType::Vector(t),
             ast::PropertyType::Matrix(t) => ast::ArrayItemType::Matrix(t),
             ast::PropertyType::StructRef(t) => ast::ArrayItemType::StructRef(t),
+            ast::PropertyType::Texture2D(t) => ast::ArrayItemType::Texture2D(t),
+            ast::PropertyType::Texture3D(t) => ast::ArrayItemType::Texture3D(t),
+            ast::PropertyType::TextureCube(t) => ast::ArrayItemType::TextureCube(t),
+            ast::PropertyType::Sampler | ast::PropertyType::SamplerCmp => return Err(TypeError::SamplerArray(ptype.into())),
+            t @ (ast::PropertyType::Texture1D(_) | ast::PropertyType::Texture2DMS(..)) => return Err(TypeError::Banned(t)),
+            ast::PropertyType::Texture2DArray(_) | ast::PropertyType::Array(_) => return Err(TypeError::NestedArray(ptype.into())),
             _ => return Err(TypeError::Unknown(ptype.into()))
         };
         Ok(Some(ast::PropertyType::Array(ArrayType {
@@ -126,17 +150,20 @@ fn try_parse_array<A: RefResolver>(ptype: &str, ptype_arr: Option<u32>, ast: &A)
 
 fn parse_type<A: RefResolver>(ptype: &str, ptype_arr: Option<u32>, ptype_attr: Option<&str>, ast: &A) -> Result<ast::PropertyType<A::Key>, TypeError<A::Key>>
 {
+    if let Some(elem) = try_parse_array(ptype, ptype_arr, ptype_attr, ast)? {
+        return Ok(elem)
+    }
     match ptype {
         "Sampler" => Ok(ast::PropertyType::Sampler),
+        "SamplerCmp" => Ok(ast::PropertyType::SamplerCmp),
         "float" => Ok(ast::PropertyType::Scalar(ast::BaseType::Float)),
         "double" => Ok(ast::PropertyType::Scalar(ast::BaseType::Double)),
         "int" => Ok(ast::PropertyType::Scalar(ast::BaseType::Int)),
         "uint" => Ok(ast::PropertyType::Scalar(ast::BaseType::Uint)),
         "bool" => Ok(ast::PropertyType::Scalar(ast::BaseType::Bool)),
+        "int64" => Ok(ast::PropertyType::Scalar(ast::BaseType::Int64)),
+        "uint64" => Ok(ast::PropertyType::Scalar(ast::BaseType::Uint64)),
         _ => {
-            if let Some(elem) = try_parse_array(ptype, ptype_arr, ast)? {
-                return Ok(elem)
-            }
             if let Some(elem) = try_parse_matrix(ptype)? {
                 return Ok(elem);
             }
@@ -153,15 +180,39 @@ fn parse_type<A: RefResolver>(ptype: &str, ptype_arr: Option<u32>, ptype_attr: O
     }
 }
 
-fn parse_attribute<T>(pattr: Option<String>) -> Result<Option<ast::Attribute>, TypeError<T>>
+fn parse_attribute<T>(pattr: Option<tree::Attribute>) -> Result<Option<ast::Attribute>, TypeError<T>>
 {
     if pattr.is_none() {
         return Ok(None);
     }
-    let val = pattr.unwrap();
+    let tree::Attribute { name: val, value } = pattr.unwrap();
+    if value.is_some() {
+        return Err(TypeError::AttributeValue(val));
+    }
     if val == "Pack" {
         return Ok(Some(ast::Attribute::Pack));
     }
+    if val == "Shared" {
+        return Ok(Some(ast::Attribute::Shared(None)));
+    }
+    if let Some(slot) = val.strip_prefix("SHARED_") {
+        let slot = slot.parse::<u32>().map_err(TypeError::AttributeShared)?;
+        return Ok(Some(ast::Attribute::Shared(Some(slot))));
+    }
+    if let Some(location) = val.strip_prefix("LOCATION_") {
+        let location = location.parse::<u32>().map_err(|e| TypeError::AttributeLocation(e))?;
+        return Ok(Some(ast::Attribute::Location(location)));
+    }
+    if let Some(target) = val.strip_prefix("TARGET_") {
+        let target = target.parse::<u32>().map_err(TypeError::AttributeTarget)?;
+        return Ok(Some(ast::Attribute::Target(target)));
+    }
+    if let Some(name) = val.strip_prefix("SEMANTIC_") {
+        if name.is_empty() {
+            return Err(TypeError::AttributeSemantic(val));
+        }
+        return Ok(Some(ast::Attribute::Semantic(name.into())));
+    }
     if val.starts_with("ORDER_") {
         let order = &val[6..].parse::<u32>().map_err(|e| TypeError::AttributeOrder(e))?;
         Ok(Some(ast::Attribute::Order(*order)))
@@ -170,39 +221,166 @@ fn parse_attribute<T>(pattr: Option<String>) -> Result<Option<ast::Attribute>, T
     }
 }
 
+fn parse_deprecation(deprecated: Option<tree::Attribute>) -> Option<String>
+{
+    deprecated.map(|v| v.value.unwrap_or_default())
+}
+
+//Strings are never valid default values, say so instead of reporting a generic mismatch.
+fn default_mismatch<T>(value: tree::Value, pname: &str) -> TypeError<T>
+{
+    match value {
+        tree::Value::String(_) => TypeError::DefaultType(pname.into(), ValueType::String),
+        _ => TypeError::DefaultMismatch(pname.into())
+    }
+}
+
+fn parse_default_values<T>(item: ast::BaseType, values: Vec<tree::Value>, pname: &str) -> Result<ast::DefaultValue, TypeError<T>>
+{
+    let mismatch = |v: tree::Value| default_mismatch(v, pname);
+    match item {
+        ast::BaseType::Float | ast::BaseType::Double => values.into_iter().map(|v| match v {
+            tree::Value::Float(f) => Ok(f as f64),
+            tree::Value::Int(i) => Ok(i as f64),
+            v => Err(mismatch(v))
+        }).collect::<Result<_, _>>().map(ast::DefaultValue::Float),
+        ast::BaseType::Int | ast::BaseType::Int64 => values.into_iter().map(|v| match v {
+            tree::Value::Int(i) => Ok(i as i64),
+            v => Err(mismatch(v))
+        }).collect::<Result<_, _>>().map(ast::DefaultValue::Int),
+        ast::BaseType::Uint | ast::BaseType::Uint64 => values.into_iter().map(|v| match v {
+            tree::Value::Int(i) if i >= 0 => Ok(i as u64),
+            v => Err(mismatch(v))
+        }).collect::<Result<_, _>>().map(ast::DefaultValue::Uint),
+        ast::BaseType::Bool => values.into_iter().map(|v| match v {
+            tree::Value::Bool(b) => Ok(b),
+            v => Err(mismatch(v))
+        }).collect::<Result<_, _>>().map(ast::DefaultValue::Bool)
+    }
+}
+
+fn parse_default<T>(ptype: &ast::PropertyType<T>, default: tree::DefaultValue, pname: &str) -> Result<ast::DefaultValue, TypeError<T>>
+{
+    match (ptype, default) {
+        (ast::PropertyType::Scalar(t), tree::DefaultValue::Value(v)) => parse_default_values(*t, vec![v], pname),
+        (ast::PropertyType::Vector(v), tree::DefaultValue::Constructor(name, values)) => {
+            if name != format!("vec{}{}", v.size, v.item.get_suffix()) {
+                return Err(TypeError::DefaultMismatch(pname.into()));
+            }
+            if values.len() != v.size as usize {
+                return Err(TypeError::DefaultSize {
+                    name: pname.into(),
+                    expected: v.size,
+                    actual: values.len()
+                });
+            }
+            parse_default_values(v.item, values, pname)
+        },
+        (ast::PropertyType::Vector(_), tree::DefaultValue::Value(v)) => Err(default_mismatch(v, pname)),
+        (ast::PropertyType::Scalar(_), _) => Err(TypeError::DefaultMismatch(pname.into())),
+        _ => Err(TypeError::DefaultNotAllowed(pname.into()))
+    }
+}
+
 fn parse_prop<A: RefResolver>(p: tree::Property, ast: &A) -> Result<ast::Property<A::Key>, TypeError<A::Key>>
 {
     let ptype = parse_type(&p.ptype, p.ptype_arr, p.ptype_attr.as_deref(), ast)?;
+    let default = match p.default {
+        Some(v) => Some(parse_default(&ptype, v, &p.pname)?),
+        None => None
+    };
     Ok(ast::Property {
         ptype,
         pname: p.pname,
-        pattr: parse_attribute(p.pattr)?
+        pattr: parse_attribute(p.pattr)?,
+        deprecated: parse_deprecation(p.deprecated),
+        pformat: None,
+        default,
+        span: p.span
     })
 }
 
-fn parse_struct<A: RefResolver, F: Fn(&ast::PropertyType<A::Key>) -> bool>(s: tree::Struct, is_further_banned: F, ast: &A) -> Result<ast::Struct<A::Key>, TypeError<A::Key>>
+/// Returns a function attaching the given location to an error.
+fn at<T, E, R: Into<Error<T, E>>>(span: tree::Span) -> impl Fn(R) -> Error<T, E>
+{
+    move |e| e.into().at(span)
+}
+
+/// Explicit locations must not overlap, including the consecutive locations used by matrices.
+fn check_locations<T, E>(s: &ast::Struct<T>) -> Result<(), Error<T, E>>
+{
+    let mut used = HashSet::new();
+    for p in &s.props {
+        if let Some(location) = p.get_location() {
+            for l in location..location + p.get_location_count() {
+                if !used.insert(l) {
+                    return Err(Error::Type(TypeError::LocationOverlap(p.pname.clone(), l)).at(p.span));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Default values are only meaningful on constant buffer members.
+fn check_no_default<T>(p: &tree::Property) -> Result<(), TypeError<T>>
+{
+    match p.default {
+        Some(_) => Err(TypeError::DefaultNotAllowed(p.pname.clone())),
+        None => Ok(())
+    }
+}
+
+/// Returns the properties inherited by a struct.
+fn parse_base<A: RefResolver>(s: &tree::Struct, ast: &A) -> Result<Vec<ast::Property<A::Key>>, TypeError<A::Key>>
+{
+    let name = match &s.base {
+        Some(v) => v,
+        None => return Ok(Vec::new())
+    };
+    if name == &s.name {
+        return Err(TypeError::InheritanceCycle(name.clone()));
+    }
+    let base = ast.resolve_struct(name).ok_or_else(|| TypeError::UnknownBase(name.clone()))?;
+    if base.vertex_format {
+        return Err(TypeError::VertexFormatInheritance(name.clone()));
+    }
+    Ok(base.props)
+}
+
+fn parse_struct<A: RefResolver, E, F: Fn(&ast::PropertyType<A::Key>) -> bool>(s: tree::Struct, is_further_banned: F, ast: &A) -> Result<ast::Struct<A::Key>, Error<A::Key, E>>
 {
     let mut plist = Vec::new();
+    let inherited = parse_base(&s, ast).map_err(at(s.span))?;
+    //Inherited properties are checked again as the base may allow types this struct does not.
+    let props = inherited.into_iter().map(|p| (p.span, Ok(p)))
+        .chain(s.props.into_iter().map(|v| (v.span, parse_prop(v, ast))));
 
-    for v in s.props {
-        let p = parse_prop(v, ast)?;
+    for (span, p) in props {
+        let p = p.map_err(at(span))?;
         match p.ptype {
             ast::PropertyType::Sampler
+            | ast::PropertyType::SamplerCmp
+            | ast::PropertyType::Texture1D(_)
             | ast::PropertyType::Texture2D(_)
             | ast::PropertyType::Texture3D(_)
             | ast::PropertyType::Texture2DArray(_)
-            | ast::PropertyType::TextureCube(_) => return Err(TypeError::Banned(p.ptype)),
+            | ast::PropertyType::TextureCube(_)
+            | ast::PropertyType::Texture2DMS(..) => return Err(TypeError::Banned(p.ptype)).map_err(at(span)),
+            ast::PropertyType::Array(ref a) if a.item.is_texture() => return Err(TypeError::Banned(p.ptype)).map_err(at(span)),
             _ => ()
         };
         if is_further_banned(&p.ptype) {
-            return Err(TypeError::Banned(p.ptype));
+            return Err(TypeError::Banned(p.ptype)).map_err(at(span));
         }
         plist.push(p);
     }
     Ok(ast::Struct {
         name: s.name,
-        attr: parse_attribute(s.attr)?,
-        props: plist
+        attr: parse_attribute(s.attr).map_err(at(s.span))?,
+        deprecated: parse_deprecation(s.deprecated),
+        props: plist,
+        span: s.span
     })
 }
 
@@ -244,6 +422,39 @@ static CULLINGMODE: phf::Map<&'static str, ast::CullingMode> = phf_map! {
     "Disabled" => ast::CullingMode::Disabled
 };
 
+static COMPAREFUNC: phf::Map<&'static str, ast::CompareFunc> = phf_map! {
+    "Never" => ast::CompareFunc::Never,
+    "Less" => ast::CompareFunc::Less,
+    "Equal" => ast::CompareFunc::Equal,
+    "LessEqual" => ast::CompareFunc::LessEqual,
+    "Greater" => ast::CompareFunc::Greater,
+    "NotEqual" => ast::CompareFunc::NotEqual,
+    "GreaterEqual" => ast::CompareFunc::GreaterEqual,
+    "Always" => ast::CompareFunc::Always
+};
+
+static STENCILOP: phf::Map<&'static str, ast::StencilOp> = phf_map! {
+    "Keep" => ast::StencilOp::Keep,
+    "Zero" => ast::StencilOp::Zero,
+    "Replace" => ast::StencilOp::Replace,
+    "Increment" => ast::StencilOp::Increment,
+    "IncrementWrap" => ast::StencilOp::IncrementWrap,
+    "Decrement" => ast::StencilOp::Decrement,
+    "DecrementWrap" => ast::StencilOp::DecrementWrap,
+    "Invert" => ast::StencilOp::Invert
+};
+
+static FILTER: phf::Map<&'static str, ast::Filter> = phf_map! {
+    "Nearest" => ast::Filter::Nearest,
+    "Linear" => ast::Filter::Linear
+};
+
+static ADDRESSMODE: phf::Map<&'static str, ast::AddressMode> = phf_map! {
+    "Repeat" => ast::AddressMode::Repeat,
+    "MirroredRepeat" => ast::AddressMode::MirroredRepeat,
+    "Clamp" => ast::AddressMode::Clamp
+};
+
 fn parse_enum<T: Copy>(value: tree::Value, map: &phf::Map<&'static str, T>) -> Result<T, ValueError>
 {
     if let tree::Value::Identifier(id) = value {
@@ -270,6 +481,30 @@ fn parse_bool(value: tree::Value) -> Result<bool, ValueError>
     }
 }
 
+fn parse_uint(value: tree::Value) -> Result<u32, ValueError>
+{
+    match value {
+        tree::Value::Int(i) if i >= 0 => Ok(i as u32),
+        _ => Err(ValueError::Unexpected {
+            expected: ValueType::Int,
+            actual: value
+        })
+    }
+}
+
+//Integer literals are accepted so that `DepthBias = 1;` does not require writing `1.0`.
+fn parse_float(value: tree::Value) -> Result<f32, ValueError>
+{
+    match value {
+        tree::Value::Float(f) => Ok(f),
+        tree::Value::Int(i) => Ok(i as f32),
+        _ => Err(ValueError::Unexpected {
+            expected: ValueType::Float,
+            actual: value
+        })
+    }
+}
+
 type VarParseFunc<T> = fn(obj: &mut T, value: tree::Value) -> Result<(), ValueError>;
 
 static VARLIST_BLENDFUNC: phf::Map<&'static str, VarParseFunc<ast::BlendfuncStatement>> = phf_map! {
@@ -316,6 +551,11 @@ static VARLIST_PIPELINE: phf::Map<&'static str, VarParseFunc<ast::PipelineStatem
         obj.depth_write_enable = parse_bool(val)?;
         Ok(())
     },
+    "DepthFunc" => |obj, val|
+    {
+        obj.depth_func = parse_enum(val, &COMPAREFUNC)?;
+        Ok(())
+    },
     "ScissorEnable" => |obj, val|
     {
         obj.scissor_enable = parse_bool(val)?;
@@ -330,19 +570,110 @@ static VARLIST_PIPELINE: phf::Map<&'static str, VarParseFunc<ast::PipelineStatem
     {
         obj.culling_mode = parse_enum(val, &CULLINGMODE)?;
         return Ok(());
+    },
+    "LayeredRendering" => |obj, val|
+    {
+        obj.layered_rendering = parse_bool(val)?;
+        Ok(())
+    },
+    "ViewportCount" => |obj, val|
+    {
+        obj.viewport_count = parse_uint(val)?;
+        Ok(())
+    },
+    "StencilEnable" => |obj, val|
+    {
+        obj.stencil_enable = parse_bool(val)?;
+        Ok(())
+    },
+    "StencilFunc" => |obj, val|
+    {
+        obj.stencil_func = parse_enum(val, &COMPAREFUNC)?;
+        Ok(())
+    },
+    "StencilPassOp" => |obj, val|
+    {
+        obj.stencil_pass_op = parse_enum(val, &STENCILOP)?;
+        Ok(())
+    },
+    "StencilFailOp" => |obj, val|
+    {
+        obj.stencil_fail_op = parse_enum(val, &STENCILOP)?;
+        Ok(())
+    },
+    "StencilRef" => |obj, val|
+    {
+        let value = parse_uint(val)?;
+        if value > 255 {
+            return Err(ValueError::OutOfRange { value, min: 0, max: 255 });
+        }
+        obj.stencil_ref = value;
+        Ok(())
+    },
+    "DepthBias" => |obj, val|
+    {
+        obj.depth_bias = parse_float(val)?;
+        Ok(())
+    },
+    "SlopeScaledDepthBias" => |obj, val|
+    {
+        obj.slope_scaled_depth_bias = parse_float(val)?;
+        Ok(())
+    }
+};
+
+static VARLIST_SAMPLERSTATE: phf::Map<&'static str, VarParseFunc<ast::SamplerStateStatement>> = phf_map! {
+    "MinFilter" => |obj, val|
+    {
+        obj.min_filter = parse_enum(val, &FILTER)?;
+        Ok(())
+    },
+    "MagFilter" => |obj, val|
+    {
+        obj.mag_filter = parse_enum(val, &FILTER)?;
+        Ok(())
+    },
+    "AddressU" => |obj, val|
+    {
+        obj.address_u = parse_enum(val, &ADDRESSMODE)?;
+        Ok(())
+    },
+    "AddressV" => |obj, val|
+    {
+        obj.address_v = parse_enum(val, &ADDRESSMODE)?;
+        Ok(())
+    },
+    "AddressW" => |obj, val|
+    {
+        obj.address_w = parse_enum(val, &ADDRESSMODE)?;
+        Ok(())
+    },
+    "Anisotropy" => |obj, val|
+    {
+        let value = parse_uint(val)?;
+        if !(1..=16).contains(&value) {
+            return Err(ValueError::OutOfRange { value, min: 1, max: 16 });
+        }
+        obj.anisotropy = value;
+        Ok(())
     }
 };
 
 fn parse_varlist<T: ast::VarlistStatement>(
     varlist: tree::VariableList,
+    base: Option<T>,
     map: &phf::Map<&'static str, VarParseFunc<T>>
 ) -> Result<T, ValueError>
 {
-    let mut obj = T::new(varlist.name);
+    //Variables not listed keep the value of the base, if any, instead of the default.
+    let mut obj = match base {
+        Some(base) => T::inherit(varlist.name, base),
+        None => T::new(varlist.name)
+    };
 
     for v in varlist.vars {
         if let Some(func) = map.get(&*v.name) {
-            func(&mut obj, v.value)?;
+            func(&mut obj, v.value).map_err(|e| ValueError::Variable(v.name, Box::new(e)))?;
         } else {
             return Err(ValueError::UnknownVariable(v.name));
         }
@@ -354,19 +685,31 @@ pub struct AstBuilder<V, A>
 {
     //statements: Vec<Statement>,
     visitor: V,
-    ast: A
+    ast: A,
+    strictness: Strictness
 }
 
 impl<A: RefResolver, V: Visitor<A>> AstBuilder<V, A>
 {
     pub fn new(ast: A, visitor: V) -> AstBuilder<V, A>
+    {
+        AstBuilder::with_strictness(ast, visitor, Strictness::Default)
+    }
+
+    pub fn with_strictness(ast: A, visitor: V, strictness: Strictness) -> AstBuilder<V, A>
     {
         AstBuilder {
             visitor,
-            ast
+            ast,
+            strictness
         }
     }
 
+    fn is_strict(&self) -> bool
+    {
+        self.strictness == Strictness::Strict
+    }
+
     pub fn into_inner(self) -> A
     {
         self.ast
@@ -378,33 +721,118 @@ impl<A: RefResolver, V: Visitor<A>> crate::parser::Visitor for AstBuilder<V, A>
     type Error = Error<A::Key, V::Error>;
 
     fn visit_constant(&mut self, val: Property) -> Result<(), Self::Error> {
-        let prop = parse_prop(val, &self.ast)?;
+        let span = val.span;
+        if self.is_strict() {
+            check_property(&val, false).map_err(at(span))?;
+        }
+        check_no_default(&val).map_err(at(span))?;
+        let prop = parse_prop(val, &self.ast).map_err(at(span))?;
         self.visitor.visit_constant(&mut self.ast, prop).map_err(Error::Visitor)?;
         Ok(())
     }
 
+    fn visit_constant_int(&mut self, val: ConstantInt) -> Result<(), Self::Error> {
+        let ty = match &*val.ptype {
+            "uint" if val.value < 0 => return Err(Error::Type(TypeError::DefaultMismatch(val.name)).at(val.span)),
+            "uint" => ast::BaseType::Uint,
+            _ => ast::BaseType::Int
+        };
+        let constant = ast::ConstantInt {
+            name: val.name,
+            ty,
+            value: val.value,
+            span: val.span
+        };
+        self.visitor.visit_constant_int(&mut self.ast, constant).map_err(Error::Visitor)?;
+        Ok(())
+    }
+
     fn visit_constant_buffer(&mut self, val: Struct) -> Result<(), Self::Error> {
+        if self.is_strict() {
+            check_struct(&val).map_err(at(val.span))?;
+        }
         let st = parse_struct(val, |_| false, &self.ast)?;
         self.visitor.visit_constant_buffer(&mut self.ast, st).map_err(Error::Visitor)?;
         Ok(())
     }
 
     fn visit_output(&mut self, val: Property) -> Result<(), Self::Error> {
-        let prop = parse_prop(val, &self.ast)?;
+        let span = val.span;
+        if self.is_strict() {
+            check_property(&val, false).map_err(at(span))?;
+        }
+        check_no_default(&val).map_err(at(span))?;
+        let pformat = match &val.pformat {
+            Some(name) => Some(ast::TargetFormat::from_name(name).ok_or_else(|| TypeError::TargetFormat(name.clone())).map_err(at(span))?),
+            None => None
+        };
+        let prop = ast::Property { pformat, ..parse_prop(val, &self.ast).map_err(at(span))? };
         match prop.ptype {
             ast::PropertyType::Sampler
+            | ast::PropertyType::SamplerCmp
+            | ast::PropertyType::Texture1D(_)
             | ast::PropertyType::Texture2D(_)
             | ast::PropertyType::Texture3D(_)
             | ast::PropertyType::Texture2DArray(_)
             | ast::PropertyType::TextureCube(_)
-            | ast::PropertyType::Matrix(_) => return Err(Error::Type(TypeError::Banned(prop.ptype))),
+            | ast::PropertyType::Texture2DMS(..)
+            | ast::PropertyType::Matrix(_) => return Err(Error::Type(TypeError::Banned(prop.ptype)).at(span)),
+            ast::PropertyType::Array(ref a) if a.item.is_texture() => return Err(Error::Type(TypeError::Banned(prop.ptype)).at(span)),
             _ => ()
         };
         self.visitor.visit_output(&mut self.ast, prop).map_err(Error::Visitor)?;
         Ok(())
     }
 
+    fn visit_varying(&mut self, val: Property) -> Result<(), Self::Error> {
+        let span = val.span;
+        if self.is_strict() {
+            check_varying(&val).map_err(at(span))?;
+        }
+        check_no_default(&val).map_err(at(span))?;
+        let mut prop = parse_prop(val, &self.ast).map_err(at(span))?;
+        match prop.ptype {
+            ast::PropertyType::Sampler
+            | ast::PropertyType::SamplerCmp
+            | ast::PropertyType::Texture1D(_)
+            | ast::PropertyType::Texture2D(_)
+            | ast::PropertyType::Texture3D(_)
+            | ast::PropertyType::Texture2DArray(_)
+            | ast::PropertyType::TextureCube(_)
+            | ast::PropertyType::Texture2DMS(..)
+            | ast::PropertyType::StructRef(_)
+            | ast::PropertyType::Array(_) => return Err(Error::Type(TypeError::Banned(prop.ptype)).at(span)),
+            _ => ()
+        };
+        if let Some(ast::Attribute::Identifier(name)) = &prop.pattr {
+            let interpolation = ast::Interpolation::from_name(name)
+                .ok_or_else(|| TypeError::AttributeInterpolation(name.clone()))
+                .map_err(at(span))?;
+            prop.pattr = Some(ast::Attribute::Interpolation(interpolation));
+        }
+        let integer = match &prop.ptype {
+            ast::PropertyType::Scalar(t) => *t,
+            ast::PropertyType::Vector(v) | ast::PropertyType::Matrix(v) => v.item,
+            _ => ast::BaseType::Float
+        };
+        let flat = prop.pattr.as_ref().and_then(|v| v.get_interpolation()) == Some(ast::Interpolation::Flat);
+        if !matches!(integer, ast::BaseType::Float | ast::BaseType::Double) && !flat {
+            return Err(Error::Type(TypeError::FlatRequired(prop.pname)).at(span));
+        }
+        self.visitor.visit_varying(&mut self.ast, prop).map_err(Error::Visitor)?;
+        Ok(())
+    }
+
     fn visit_vertex_format(&mut self, val: Struct) -> Result<(), Self::Error> {
+        if self.is_strict() {
+            check_struct(&val).map_err(at(val.span))?;
+        }
+        if val.base.is_some() {
+            return Err(Error::Type(TypeError::VertexFormatInheritance(val.name)).at(val.span));
+        }
+        for p in &val.props {
+            check_no_default(p).map_err(at(p.span))?;
+        }
         let st = parse_struct(val, |v| {
             match v {
                 ast::PropertyType::Matrix(_) |
@@ -413,26 +841,69 @@ impl<A: RefResolver, V: Visitor<A>> crate::parser::Visitor for AstBuilder<V, A>
                 _ => true
             }
         }, &self.ast)?;
+        check_locations(&st)?;
         self.visitor.visit_vertex_format(&mut self.ast, st).map_err(Error::Visitor)?;
         Ok(())
     }
 
+    fn visit_root_constants(&mut self, val: Struct) -> Result<(), Self::Error> {
+        if self.is_strict() {
+            check_struct(&val).map_err(at(val.span))?;
+        }
+        let st = parse_struct(val, |v| {
+            !matches!(v, ast::PropertyType::Matrix(_) | ast::PropertyType::Vector(_) | ast::PropertyType::Scalar(_))
+        }, &self.ast)?;
+        self.visitor.visit_root_constants(&mut self.ast, st).map_err(Error::Visitor)?;
+        Ok(())
+    }
+
     fn visit_use(&mut self, val: Use) -> Result<(), Self::Error> {
-        self.visitor.visit_use(&mut self.ast, val.module, val.member).map_err(Error::Visitor)?;
+        self.visitor.visit_use(&mut self.ast, val.path, val.member, val.span).map_err(Error::Visitor)?;
+        Ok(())
+    }
+
+    fn visit_resource(&mut self, val: Resource) -> Result<(), Self::Error> {
+        let res = ast::ResourceStatement {
+            name: val.name,
+            path: val.path
+        };
+        self.visitor.visit_resource(&mut self.ast, res).map_err(Error::Visitor)?;
         Ok(())
     }
 
     fn visit_pipeline(&mut self, val: VariableList) -> Result<(), Self::Error> {
-        let vl = parse_varlist(val, &VARLIST_PIPELINE)?;
+        let span = val.span;
+        if self.is_strict() {
+            check_varlist(&val).map_err(at(span))?;
+        }
+        let base = match &val.base {
+            Some(name) => Some(self.ast.resolve_pipeline(name).ok_or_else(|| ValueError::UnknownPreset(name.clone())).map_err(at(span))?),
+            None => None
+        };
+        let vl = parse_varlist(val, base, &VARLIST_PIPELINE).map_err(at(span))?;
         self.visitor.visit_pipeline(&mut self.ast , vl).map_err(Error::Visitor)?;
         Ok(())
     }
 
     fn visit_blendfunc(&mut self, val: VariableList) -> Result<(), Self::Error> {
-        let vl = parse_varlist(val, &VARLIST_BLENDFUNC)?;
+        let span = val.span;
+        if self.is_strict() {
+            check_varlist(&val).map_err(at(span))?;
+        }
+        let vl = parse_varlist(val, None, &VARLIST_BLENDFUNC).map_err(at(span))?;
         self.visitor.visit_blendfunc(&mut self.ast, vl).map_err(Error::Visitor)?;
         Ok(())
     }
+
+    fn visit_samplerstate(&mut self, val: VariableList) -> Result<(), Self::Error> {
+        let span = val.span;
+        if self.is_strict() {
+            check_varlist(&val).map_err(at(span))?;
+        }
+        let vl = parse_varlist(val, None, &VARLIST_SAMPLERSTATE).map_err(at(span))?;
+        self.visitor.visit_samplerstate(&mut self.ast, vl).map_err(Error::Visitor)?;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -446,12 +917,16 @@ mod tests
                 BlendFactor,
                 BlendOperator,
                 BlendfuncStatement,
+                CompareFunc,
                 CullingMode,
                 PipelineStatement,
                 Property,
                 PropertyType,
                 RenderMode,
+                ResourceStatement,
+                SamplerStateStatement,
                 Statement,
+                StencilOp,
                 Struct,
                 TextureType,
                 VectorType
@@ -460,8 +935,8 @@ mod tests
         lexer::Lexer,
         parser::Parser
     };
-    use crate::ast::RefResolver;
-    use crate::ast::tree::{ArrayItemType, Attribute};
+    use crate::ast::{BaseStruct, RefResolver, StrictnessRule};
+    use crate::ast::tree::{AddressMode, ArrayItemType, Attribute, DefaultValue, Filter, Interpolation, TargetFormat, VarlistStatement};
 
     struct VecVisitor {}
 
@@ -471,6 +946,27 @@ mod tests
         fn resolve_struct_ref(&self, name: &str) -> Option<Self::Key> {
             Some(name.into())
         }
+
+        fn resolve_pipeline(&self, name: &str) -> Option<PipelineStatement> {
+            self.iter().find_map(|v| match v {
+                Statement::Pipeline(p) if p.name == name => Some(p.clone()),
+                _ => None
+            })
+        }
+
+        fn resolve_struct(&self, name: &str) -> Option<BaseStruct<Self::Key>> {
+            self.iter().find_map(|v| match v {
+                Statement::ConstantBuffer(s) | Statement::RootConstants(s) if s.name == name => Some(BaseStruct {
+                    props: s.props.clone(),
+                    vertex_format: false
+                }),
+                Statement::VertexFormat(s) if s.name == name => Some(BaseStruct {
+                    props: s.props.clone(),
+                    vertex_format: true
+                }),
+                _ => None
+            })
+        }
     }
 
     impl Visitor<Vec<Statement>> for VecVisitor {
@@ -481,11 +977,21 @@ mod tests
             Ok(())
         }
 
+        fn visit_constant_int(&mut self, ast: &mut Vec<Statement>, val: ast::ConstantInt) -> Result<(), Self::Error> {
+            ast.push(Statement::ConstantInt(val));
+            Ok(())
+        }
+
         fn visit_output(&mut self, ast: &mut Vec<Statement>, val: Property) -> Result<(), Self::Error> {
             ast.push(Statement::Output(val));
             Ok(())
         }
 
+        fn visit_varying(&mut self, ast: &mut Vec<Statement>, val: Property) -> Result<(), Self::Error> {
+            ast.push(Statement::Varying(val));
+            Ok(())
+        }
+
         fn visit_constant_buffer(&mut self, ast: &mut Vec<Statement>, val: Struct) -> Result<(), Self::Error> {
             ast.push(Statement::ConstantBuffer(val));
             Ok(())
@@ -496,6 +1002,11 @@ mod tests
             Ok(())
         }
 
+        fn visit_root_constants(&mut self, ast: &mut Vec<Statement>, val: Struct) -> Result<(), Self::Error> {
+            ast.push(Statement::RootConstants(val));
+            Ok(())
+        }
+
         fn visit_pipeline(&mut self, ast: &mut Vec<Statement>, val: PipelineStatement) -> Result<(), Self::Error> {
             ast.push(Statement::Pipeline(val));
             Ok(())
@@ -506,12 +1017,22 @@ mod tests
             Ok(())
         }
 
+        fn visit_samplerstate(&mut self, ast: &mut Vec<Statement>, val: SamplerStateStatement) -> Result<(), Self::Error> {
+            ast.push(Statement::SamplerState(val));
+            Ok(())
+        }
+
+        fn visit_resource(&mut self, ast: &mut Vec<Statement>, val: ResourceStatement) -> Result<(), Self::Error> {
+            ast.push(Statement::Resource(val));
+            Ok(())
+        }
+
         fn visit_noop(&mut self, ast: &mut Vec<Statement>) -> Result<(), Self::Error> {
             ast.push(Statement::Noop);
             Ok(())
         }
 
-        fn visit_use(&mut self, ast: &mut Vec<Statement>, _: String, _: String) -> Result<(), Self::Error> {
+        fn visit_use(&mut self, ast: &mut Vec<Statement>, _: Vec<String>, _: String, _: tree::Span) -> Result<(), Self::Error> {
             self.visit_noop(ast)
         }
     }
@@ -538,12 +1059,20 @@ mod tests
             Statement::Constant(Property {
                 pname: "DeltaTime".into(),
                 ptype: PropertyType::Scalar(BaseType::Float),
-                pattr: None
+                pattr: None,
+                deprecated: None,
+                pformat: None,
+                default: None,
+                span: Default::default()
             }),
             Statement::Constant(Property {
                 pname: "FrameCount".into(),
                 ptype: PropertyType::Scalar(BaseType::Uint),
-                pattr: None
+                pattr: None,
+                deprecated: None,
+                pformat: None,
+                default: None,
+                span: Default::default()
             }),
             Statement::Constant(Property {
                 pname: "ModelViewMatrix".into(),
@@ -551,7 +1080,11 @@ mod tests
                     item: BaseType::Float,
                     size: 3
                 }),
-                pattr: None
+                pattr: None,
+                deprecated: None,
+                pformat: None,
+                default: None,
+                span: Default::default()
             }),
             Statement::Constant(Property {
                 pname: "ProjectionMatrix".into(),
@@ -559,11 +1092,16 @@ mod tests
                     item: BaseType::Float,
                     size: 3
                 }),
-                pattr: None
+                pattr: None,
+                deprecated: None,
+                pformat: None,
+                default: None,
+                span: Default::default()
             }),
             Statement::ConstantBuffer(Struct {
                 name: "PerMaterial".into(),
                 attr: None,
+                deprecated: None,
                 props: vec![
                     Property {
                         pname: "BaseColor".into(),
@@ -571,14 +1109,23 @@ mod tests
                             item: BaseType::Float,
                             size: 4
                         }),
-                        pattr: None
+                        pattr: None,
+                        deprecated: None,
+                        pformat: None,
+                        default: None,
+                        span: Default::default()
                     },
                     Property {
                         pname: "UvMultiplier".into(),
                         ptype: PropertyType::Scalar(BaseType::Float),
-                        pattr: None
+                        pattr: None,
+                        deprecated: None,
+                        pformat: None,
+                        default: None,
+                        span: Default::default()
                     },
-                ]
+                ],
+                span: Default::default()
             }),
         ];
         assert_eq!(ast, expected_ast);
@@ -606,7 +1153,11 @@ mod tests
             Statement::Constant(Property {
                 pname: "BaseSampler".into(),
                 ptype: PropertyType::Sampler,
-                pattr: None
+                pattr: None,
+                deprecated: None,
+                pformat: None,
+                default: None,
+                span: Default::default()
             }),
             Statement::Constant(Property {
                 pname: "BaseTexture".into(),
@@ -614,16 +1165,25 @@ mod tests
                     item: BaseType::Float,
                     size: 4
                 })),
-                pattr: Some(Attribute::Identifier("BaseSampler".into()))
+                pattr: Some(Attribute::Identifier("BaseSampler".into())),
+                deprecated: None,
+                pformat: None,
+                default: None,
+                span: Default::default()
             }),
             Statement::Constant(Property {
                 pname: "NoiseTexture".into(),
                 ptype: PropertyType::Texture2D(TextureType::Scalar(BaseType::Float)),
-                pattr: Some(Attribute::Identifier("BaseSampler".into()))
+                pattr: Some(Attribute::Identifier("BaseSampler".into())),
+                deprecated: None,
+                pformat: None,
+                default: None,
+                span: Default::default()
             }),
             Statement::ConstantBuffer(Struct {
                 name: "PerMaterial".into(),
                 attr: Some(Attribute::Order(1)),
+                deprecated: None,
                 props: vec![
                     Property {
                         pname: "BaseColor".into(),
@@ -631,19 +1191,32 @@ mod tests
                             item: BaseType::Float,
                             size: 4
                         }),
-                        pattr: None
+                        pattr: None,
+                        deprecated: None,
+                        pformat: None,
+                        default: None,
+                        span: Default::default()
                     },
                     Property {
                         pname: "Specular".into(),
                         ptype: PropertyType::Scalar(BaseType::Float),
-                        pattr: Some(Attribute::Pack)
+                        pattr: Some(Attribute::Pack),
+                        deprecated: None,
+                        pformat: None,
+                        default: None,
+                        span: Default::default()
                     },
                     Property {
                         pname: "UvMultiplier".into(),
                         ptype: PropertyType::Scalar(BaseType::Float),
-                        pattr: Some(Attribute::Pack)
+                        pattr: Some(Attribute::Pack),
+                        deprecated: None,
+                        pformat: None,
+                        default: None,
+                        span: Default::default()
                     },
-                ]
+                ],
+                span: Default::default()
             }),
         ];
         assert_eq!(ast, expected_ast);
@@ -664,6 +1237,7 @@ mod tests
             Statement::ConstantBuffer(Struct {
                 name: "Light".into(),
                 attr: Some(Attribute::Pack),
+                deprecated: None,
                 props: vec![
                     Property {
                         pname: "color".into(),
@@ -671,23 +1245,37 @@ mod tests
                             size: 4,
                             item: BaseType::Float
                         }),
-                        pattr: None
+                        pattr: None,
+                        deprecated: None,
+                        pformat: None,
+                        default: None,
+                        span: Default::default()
                     },
                     Property {
                         pname: "attenuation".into(),
                         ptype: PropertyType::Scalar(BaseType::Float),
-                        pattr: None
+                        pattr: None,
+                        deprecated: None,
+                        pformat: None,
+                        default: None,
+                        span: Default::default()
                     }
-                ]
+                ],
+                span: Default::default()
             }),
             Statement::ConstantBuffer(Struct {
                 name: "Lighting".into(),
                 attr: None,
+                deprecated: None,
                 props: vec![
                     Property {
                         pname: "count".into(),
                         ptype: PropertyType::Scalar(BaseType::Uint),
-                        pattr: None
+                        pattr: None,
+                        deprecated: None,
+                        pformat: None,
+                        default: None,
+                        span: Default::default()
                     },
                     Property {
                         pname: "lights".into(),
@@ -696,8 +1284,13 @@ mod tests
                             item: ArrayItemType::StructRef("Light".into())
                         }),
                         pattr: None,
+                        deprecated: None,
+                        pformat: None,
+                        default: None,
+                        span: Default::default()
                     }
-                ]
+                ],
+                span: Default::default()
             })
         ];
         assert_eq!(ast, expected_ast);
@@ -719,7 +1312,11 @@ mod tests
                 item: BaseType::Float,
                 size: 4
             }),
-            pattr: None
+            pattr: None,
+            deprecated: None,
+            pformat: None,
+            default: None,
+            span: Default::default()
         })];
         assert_eq!(ast, expected_ast);
     }
@@ -740,14 +1337,20 @@ mod tests
         let expected_ast = vec![Statement::VertexFormat(Struct {
             name: "Vertex".into(),
             attr: None,
+            deprecated: None,
             props: vec![Property {
                 pname: "Pos".into(),
                 ptype: PropertyType::Vector(VectorType {
                     item: BaseType::Float,
                     size: 3
                 }),
-                pattr: None
-            }]
+                pattr: None,
+                deprecated: None,
+                pformat: None,
+                default: None,
+                span: Default::default()
+            }],
+            span: Default::default()
         })];
         assert_eq!(ast, expected_ast);
     }
@@ -773,13 +1376,169 @@ mod tests
             name: "Test".into(),
             depth_enable: true,
             depth_write_enable: true,
+            depth_func: CompareFunc::Less,
             scissor_enable: false,
             render_mode: RenderMode::Triangles,
-            culling_mode: CullingMode::BackFace
+            culling_mode: CullingMode::BackFace,
+            layered_rendering: false,
+            viewport_count: 1,
+            stencil_enable: false,
+            stencil_func: CompareFunc::Always,
+            stencil_pass_op: StencilOp::Keep,
+            stencil_fail_op: StencilOp::Keep,
+            stencil_ref: 0,
+            depth_bias: 0.0,
+            slope_scaled_depth_bias: 0.0
         })];
         assert_eq!(ast, expected_ast);
     }
 
+    #[test]
+    fn pipeline_state()
+    {
+        let source_code = b"
+            pipeline Decal
+            {
+                DepthFunc = LessEqual;
+                StencilEnable = true;
+                StencilFunc = Equal;
+                StencilPassOp = Replace;
+                StencilFailOp = DecrementWrap;
+                StencilRef = 128;
+                DepthBias = -2;
+                SlopeScaledDepthBias = 1.5;
+            }
+            pipeline Default { CullingMode = BackFace; }
+        ";
+        let mut lexer = Lexer::new();
+        lexer.process(source_code).unwrap();
+        let mut parser = Parser::new(lexer);
+        let ast = parser.parse(AstBuilder::new(Vec::new(), VecVisitor {})).unwrap().into_inner();
+        match &ast[0] {
+            Statement::Pipeline(p) => {
+                assert_eq!(p.depth_func, CompareFunc::LessEqual);
+                assert!(p.stencil_enable);
+                assert_eq!(p.stencil_func, CompareFunc::Equal);
+                assert_eq!(p.stencil_pass_op, StencilOp::Replace);
+                assert_eq!(p.stencil_fail_op, StencilOp::DecrementWrap);
+                assert_eq!(p.stencil_ref, 128);
+                assert_eq!(p.depth_bias, -2.0);
+                assert_eq!(p.slope_scaled_depth_bias, 1.5);
+            },
+            _ => panic!("expected pipeline statement")
+        }
+        assert_eq!(ast[1], Statement::Pipeline(PipelineStatement::new("Default".into())));
+        match &ast[1] {
+            Statement::Pipeline(p) => {
+                assert_eq!(p.depth_func, CompareFunc::Less);
+                assert!(!p.stencil_enable);
+                assert_eq!(p.stencil_func, CompareFunc::Always);
+                assert_eq!((p.stencil_pass_op, p.stencil_fail_op), (StencilOp::Keep, StencilOp::Keep));
+                assert_eq!((p.depth_bias, p.slope_scaled_depth_bias), (0.0, 0.0));
+            },
+            _ => panic!("expected pipeline statement")
+        }
+        let err = parse_with(b"pipeline Broken { DepthFunc = Sometimes; }", Strictness::Default).unwrap_err();
+        assert_eq!(err.to_string(), "value error: invalid value for DepthFunc: unknown enum (Sometimes)");
+        let err = parse_with(b"pipeline Broken { StencilRef = 256; }", Strictness::Default).unwrap_err();
+        assert!(matches!(err, Error::Value(ValueError::Variable(name, _)) if name == "StencilRef"));
+        let err = parse_with(b"pipeline Broken { DepthBias = true; }", Strictness::Default).unwrap_err();
+        assert!(matches!(err, Error::Value(ValueError::Variable(name, _)) if name == "DepthBias"));
+    }
+
+    #[test]
+    fn layered_pipeline()
+    {
+        let source_code = b"
+            pipeline Stereo
+            {
+                LayeredRendering = true;
+                ViewportCount = 2;
+            }
+        ";
+        let mut lexer = Lexer::new();
+        lexer.process(source_code).unwrap();
+        let mut parser = Parser::new(lexer);
+        let ast = parser.parse(AstBuilder::new(Vec::new(), VecVisitor {})).unwrap().into_inner();
+        match &ast[0] {
+            Statement::Pipeline(p) => {
+                assert!(p.layered_rendering);
+                assert_eq!(p.viewport_count, 2);
+            },
+            _ => panic!("expected pipeline statement")
+        }
+        let source_code = b"pipeline Broken { ViewportCount = true; }";
+        let mut lexer = Lexer::new();
+        lexer.process(source_code).unwrap();
+        let mut parser = Parser::new(lexer);
+        assert!(parser.parse(AstBuilder::new(Vec::new(), VecVisitor {})).is_err());
+    }
+
+    #[test]
+    fn inherited_pipeline()
+    {
+        let source_code = b"
+            pipeline OpaqueDefault
+            {
+                DepthWriteEnable = false;
+                CullingMode = FrontFace;
+                ViewportCount = 2;
+            }
+            pipeline Main : OpaqueDefault
+            {
+                CullingMode = BackFace;
+                ScissorEnable = true;
+            }
+        ";
+        let mut lexer = Lexer::new();
+        lexer.process(source_code).unwrap();
+        let mut parser = Parser::new(lexer);
+        let ast = parser.parse(AstBuilder::new(Vec::new(), VecVisitor {})).unwrap().into_inner();
+        assert_eq!(ast[1], Statement::Pipeline(PipelineStatement {
+            name: "Main".into(),
+            depth_enable: true,
+            depth_write_enable: false,
+            depth_func: CompareFunc::Less,
+            scissor_enable: true,
+            render_mode: RenderMode::Triangles,
+            culling_mode: CullingMode::BackFace,
+            layered_rendering: false,
+            viewport_count: 2,
+            stencil_enable: false,
+            stencil_func: CompareFunc::Always,
+            stencil_pass_op: StencilOp::Keep,
+            stencil_fail_op: StencilOp::Keep,
+            stencil_ref: 0,
+            depth_bias: 0.0,
+            slope_scaled_depth_bias: 0.0
+        }));
+        let source_code = b"pipeline Main : Missing { CullingMode = Disabled; }";
+        let mut lexer = Lexer::new();
+        lexer.process(source_code).unwrap();
+        let mut parser = Parser::new(lexer);
+        assert!(parser.parse(AstBuilder::new(Vec::new(), VecVisitor {})).is_err());
+        let source_code = b"pipeline Base { DepthEnable = false; } pipeline Broken : Base { ViewportCount = true; }";
+        let mut lexer = Lexer::new();
+        lexer.process(source_code).unwrap();
+        let mut parser = Parser::new(lexer);
+        assert!(parser.parse(AstBuilder::new(Vec::new(), VecVisitor {})).is_err());
+    }
+
+    #[test]
+    fn resource()
+    {
+        let source_code = b"resource \"curves.bin\" as CurveLut;";
+        let mut lexer = Lexer::new();
+        lexer.process(source_code).unwrap();
+        let mut parser = Parser::new(lexer);
+        let ast = parser.parse(AstBuilder::new(Vec::new(), VecVisitor {})).unwrap().into_inner();
+        assert_eq!(ast, vec![Statement::Resource(ResourceStatement {
+            name: "CurveLut".into(),
+            path: "curves.bin".into()
+        })]);
+        assert_eq!(ast[0].get_name(), Some("CurveLut"));
+    }
+
     #[test]
     fn blendfunc_output()
     {
@@ -807,7 +1566,11 @@ mod tests
                     item: BaseType::Float,
                     size: 4
                 }),
-                pattr: None
+                pattr: None,
+                deprecated: None,
+                pformat: None,
+                default: None,
+                span: Default::default()
             }),
             Statement::Blendfunc(BlendfuncStatement {
                 name: "FragColor".into(),
@@ -821,4 +1584,606 @@ mod tests
         ];
         assert_eq!(ast, expected_ast);
     }
+
+    #[test]
+    fn samplerstate()
+    {
+        let source_code = b"
+            const Sampler BaseSampler;
+
+            samplerstate BaseSampler
+            {
+                MinFilter = Linear;
+                MagFilter = Nearest;
+                AddressU = Repeat;
+                AddressV = Clamp;
+                Anisotropy = 16;
+            }
+        ";
+        let mut lexer = Lexer::new();
+        lexer.process(source_code).unwrap();
+        let mut parser = Parser::new(lexer);
+        let ast = parser.parse(AstBuilder::new(Vec::new(), VecVisitor {})).unwrap().into_inner();
+        assert_eq!(ast[1], Statement::SamplerState(SamplerStateStatement {
+            name: "BaseSampler".into(),
+            min_filter: Filter::Linear,
+            mag_filter: Filter::Nearest,
+            address_u: AddressMode::Repeat,
+            address_v: AddressMode::Clamp,
+            address_w: AddressMode::Repeat,
+            anisotropy: 16
+        }));
+        assert_eq!(ast[1].get_name(), Some("BaseSampler"));
+        for source_code in [&b"samplerstate S { Anisotropy = 32; }"[..], b"samplerstate S { MinFilter = Cubic; }", b"samplerstate S : Base { }"] {
+            let mut lexer = Lexer::new();
+            lexer.process(source_code).unwrap();
+            let mut parser = Parser::new(lexer);
+            assert!(parser.parse(AstBuilder::new(Vec::new(), VecVisitor {})).is_err());
+        }
+    }
+
+    #[test]
+    fn deprecated_attribute()
+    {
+        let source_code = b"
+            output vec4f Color : deprecated(\"use FragColor\");
+            const struct Legacy : ORDER_2 : deprecated { float Value; }
+        ";
+        let mut lexer = Lexer::new();
+        lexer.process(source_code).unwrap();
+        let mut parser = Parser::new(lexer);
+        let ast = parser.parse(AstBuilder::new(Vec::new(), VecVisitor {})).unwrap().into_inner();
+        let expected_ast = vec![
+            Statement::Output(Property {
+                pname: "Color".into(),
+                ptype: PropertyType::Vector(VectorType {
+                    item: BaseType::Float,
+                    size: 4
+                }),
+                pattr: None,
+                deprecated: Some("use FragColor".into()),
+                pformat: None,
+                default: None,
+                span: Default::default()
+            }),
+            Statement::ConstantBuffer(Struct {
+                name: "Legacy".into(),
+                attr: Some(Attribute::Order(2)),
+                deprecated: Some(String::new()),
+                props: vec![Property {
+                    pname: "Value".into(),
+                    ptype: PropertyType::Scalar(BaseType::Float),
+                    pattr: None,
+                    deprecated: None,
+                    pformat: None,
+                    default: None,
+                    span: Default::default()
+                }],
+                span: Default::default()
+            }),
+        ];
+        assert_eq!(ast, expected_ast);
+    }
+
+    #[test]
+    fn unexpected_attribute_value()
+    {
+        let source_code = b"const float Value : Pack(\"oops\");";
+        let mut lexer = Lexer::new();
+        lexer.process(source_code).unwrap();
+        let mut parser = Parser::new(lexer);
+        match parser.parse(AstBuilder::new(Vec::new(), VecVisitor {})) {
+            Err(crate::parser::error::ParserOrVisitor::Visitor(Error::Located(_, e))) => match *e {
+                Error::Type(TypeError::AttributeValue(name)) => assert_eq!(name, "Pack"),
+                _ => panic!("expected an attribute value error")
+            },
+            _ => panic!("expected an attribute value error")
+        }
+    }
+
+    #[test]
+    fn shared_attribute()
+    {
+        let source_code = b"
+            const struct Camera : Shared { mat4f View; }
+            const struct Frame : SHARED_9 { float Time; }
+        ";
+        let mut lexer = Lexer::new();
+        lexer.process(source_code).unwrap();
+        let mut parser = Parser::new(lexer);
+        let ast = parser.parse(AstBuilder::new(Vec::new(), VecVisitor {})).unwrap().into_inner();
+        let attrs: Vec<Option<Attribute>> = ast.into_iter().map(|v| match v {
+            Statement::ConstantBuffer(v) => v.attr,
+            _ => None
+        }).collect();
+        assert_eq!(attrs, vec![Some(Attribute::Shared(None)), Some(Attribute::Shared(Some(9)))]);
+    }
+
+    #[test]
+    fn semantic_attribute()
+    {
+        let source_code = b"
+            vformat struct Vertex { vec3f Pos : SEMANTIC_POSITION; vec2f Uv; }
+            output vec4f Color : SEMANTIC_SV_Target1;
+        ";
+        let mut lexer = Lexer::new();
+        lexer.process(source_code).unwrap();
+        let mut parser = Parser::new(lexer);
+        let ast = parser.parse(AstBuilder::new(Vec::new(), VecVisitor {})).unwrap().into_inner();
+        let semantics: Vec<Option<String>> = ast.into_iter().flat_map(|v| match v {
+            Statement::VertexFormat(v) => v.props.into_iter().map(|v| v.pattr).collect(),
+            Statement::Output(v) => vec![v.pattr],
+            _ => Vec::new()
+        }).map(|v| v.as_ref().and_then(|v| v.get_semantic()).map(String::from)).collect();
+        assert_eq!(semantics, [Some("POSITION".into()), None, Some("SV_Target1".into())]);
+        let err = parse_with(b"output vec4f Color : SEMANTIC_;", Strictness::Default).unwrap_err();
+        assert!(matches!(err, Error::Type(TypeError::AttributeSemantic(_))));
+    }
+
+    #[test]
+    fn location_attribute()
+    {
+        let ast = parse_with(b"vformat struct Vertex { vec3f Pos : LOCATION_0; mat4f Model : LOCATION_3; vec2f Uv; }", Strictness::Strict).unwrap();
+        let locations: Vec<Option<u32>> = match &ast[0] {
+            Statement::VertexFormat(v) => v.props.iter().map(|v| v.get_location()).collect(),
+            _ => panic!("expected a vertex format")
+        };
+        assert_eq!(locations, [Some(0), Some(3), None]);
+        let err = parse_with(b"vformat struct Vertex { vec3f Pos : LOCATION_1; vec3f Normal : LOCATION_1; }", Strictness::Default).unwrap_err();
+        assert!(matches!(err, Error::Type(TypeError::LocationOverlap(name, 1)) if name == "Normal"));
+        //A mat4f takes four consecutive locations.
+        let err = parse_with(b"vformat struct Vertex { mat4f Model : LOCATION_0; vec3f Pos : LOCATION_3; }", Strictness::Default).unwrap_err();
+        assert!(matches!(err, Error::Type(TypeError::LocationOverlap(name, 3)) if name == "Pos"));
+        let err = parse_with(b"vformat struct Vertex { vec3f Pos : LOCATION_x; }", Strictness::Default).unwrap_err();
+        assert!(matches!(err, Error::Type(TypeError::AttributeLocation(_))));
+    }
+
+    #[test]
+    fn target_attribute()
+    {
+        let ast = parse_with(b"output vec4f Albedo : TARGET_0; output vec4f Normal : TARGET_1 : RGBA16F; output vec4f Color;", Strictness::Strict).unwrap();
+        let targets: Vec<(Option<u32>, Option<TargetFormat>)> = ast.iter().map(|v| match v {
+            Statement::Output(v) => (v.pattr.as_ref().and_then(|v| v.get_target()), v.pformat),
+            _ => panic!("expected an output")
+        }).collect();
+        assert_eq!(targets, [(Some(0), None), (Some(1), Some(TargetFormat::Rgba16F)), (None, None)]);
+        let err = parse_with(b"output vec4f Normal : TARGET_1 : RGBA12;", Strictness::Default).unwrap_err();
+        assert!(matches!(err, Error::Type(TypeError::TargetFormat(name)) if name == "RGBA12"));
+        let err = parse_with(b"output vec4f Normal : TARGET_;", Strictness::Default).unwrap_err();
+        assert!(matches!(err, Error::Type(TypeError::AttributeTarget(_))));
+    }
+
+    #[test]
+    fn varyings()
+    {
+        let source_code = b"
+            varying vec2f Uv;
+            varying vec3f Normal : NoPerspective;
+            varying uint Layer : Flat;
+        ";
+        let ast = parse_with(source_code, Strictness::Strict).unwrap();
+        let expected_ast = vec![
+            Statement::Varying(Property {
+                pname: "Uv".into(),
+                ptype: PropertyType::Vector(VectorType {
+                    item: BaseType::Float,
+                    size: 2
+                }),
+                pattr: None,
+                deprecated: None,
+                pformat: None,
+                default: None,
+                span: Default::default()
+            }),
+            Statement::Varying(Property {
+                pname: "Normal".into(),
+                ptype: PropertyType::Vector(VectorType {
+                    item: BaseType::Float,
+                    size: 3
+                }),
+                pattr: Some(Attribute::Interpolation(Interpolation::NoPerspective)),
+                deprecated: None,
+                pformat: None,
+                default: None,
+                span: Default::default()
+            }),
+            Statement::Varying(Property {
+                pname: "Layer".into(),
+                ptype: PropertyType::Scalar(BaseType::Uint),
+                pattr: Some(Attribute::Interpolation(Interpolation::Flat)),
+                deprecated: None,
+                pformat: None,
+                default: None,
+                span: Default::default()
+            })
+        ];
+        assert_eq!(ast, expected_ast);
+        let err = parse_with(b"varying vec2f Uv : Linear;", Strictness::Default).unwrap_err();
+        assert!(matches!(err, Error::Type(TypeError::AttributeInterpolation(_))));
+        let err = parse_with(b"varying uint Layer;", Strictness::Default).unwrap_err();
+        assert!(matches!(err, Error::Type(TypeError::FlatRequired(_))));
+        let err = parse_with(b"varying Texture2D:vec4f Tex;", Strictness::Default).unwrap_err();
+        assert!(matches!(err, Error::Type(TypeError::Banned(_))));
+    }
+
+    #[test]
+    fn texture_arrays()
+    {
+        let ast = parse_with(b"const Texture2D:vec4f[8] ShadowMaps : ShadowSampler;", Strictness::Strict).unwrap();
+        let expected_ast = vec![Statement::Constant(Property {
+            pname: "ShadowMaps".into(),
+            ptype: PropertyType::Array(ArrayType {
+                size: 8,
+                item: ArrayItemType::Texture2D(TextureType::Vector(VectorType {
+                    item: BaseType::Float,
+                    size: 4
+                }))
+            }),
+            pattr: Some(Attribute::Identifier("ShadowSampler".into())),
+            deprecated: None,
+            pformat: None,
+            default: None,
+            span: Default::default()
+        })];
+        assert_eq!(ast, expected_ast);
+        let err = parse_with(b"const Sampler[4] Samplers;", Strictness::Default).unwrap_err();
+        assert!(matches!(err, Error::Type(TypeError::SamplerArray(_))));
+        let err = parse_with(b"const Texture2DArray:vec4f[4] Layers;", Strictness::Default).unwrap_err();
+        assert!(matches!(err, Error::Type(TypeError::NestedArray(_))));
+        let err = parse_with(b"const struct Material { Texture2D[2]:float Maps; }", Strictness::Default).unwrap_err();
+        assert!(matches!(err, Error::Type(TypeError::Banned(_))));
+    }
+
+    #[test]
+    fn texture_kinds()
+    {
+        let source_code = b"
+            const SamplerCmp ShadowSampler;
+            const Texture1D:float Gradient : ShadowSampler;
+            const Texture2DMS:vec4f:4 SceneColor;
+            const Texture2D:float ShadowMap : ShadowSampler;
+        ";
+        let ast = parse_with(source_code, Strictness::Strict).unwrap();
+        let prop = |name: &str, ptype, pattr: Option<&str>| Statement::Constant(Property {
+            pname: name.into(),
+            ptype,
+            pattr: pattr.map(|v| Attribute::Identifier(v.into())),
+            deprecated: None,
+            pformat: None,
+            default: None,
+            span: Default::default()
+        });
+        let vec4f = TextureType::Vector(VectorType {
+            item: BaseType::Float,
+            size: 4
+        });
+        let expected_ast = vec![
+            prop("ShadowSampler", PropertyType::SamplerCmp, None),
+            prop("Gradient", PropertyType::Texture1D(TextureType::Scalar(BaseType::Float)), Some("ShadowSampler")),
+            prop("SceneColor", PropertyType::Texture2DMS(vec4f, 4), None),
+            prop("ShadowMap", PropertyType::Texture2D(TextureType::Scalar(BaseType::Float)), Some("ShadowSampler"))
+        ];
+        assert_eq!(ast, expected_ast);
+        assert_eq!(PropertyType::<usize>::Texture2DMS(vec4f, 4).to_string(), "Texture2DMS<vec4f, 4>");
+        for code in [&b"const Texture2DMS:vec4f Scene;"[..], b"const Texture2DMS:vec4f:0 Scene;", b"const Texture2D:vec4f:4 Scene;"] {
+            let err = parse_with(code, Strictness::Default).unwrap_err();
+            assert!(matches!(err, Error::Type(TypeError::SampleCount(_))));
+        }
+        let err = parse_with(b"const SamplerCmp[4] Samplers;", Strictness::Default).unwrap_err();
+        assert!(matches!(err, Error::Type(TypeError::SamplerArray(_))));
+        let err = parse_with(b"const Texture1D:float[4] Gradients;", Strictness::Default).unwrap_err();
+        assert!(matches!(err, Error::Type(TypeError::Banned(PropertyType::Texture1D(_)))));
+        for code in [&b"const struct Material { SamplerCmp Shadow; }"[..], b"const struct Material { Texture2DMS:vec4f:2 Scene; }",
+            b"vformat struct Vertex { Texture1D:float Gradient; }", b"output SamplerCmp Shadow;"] {
+            let err = parse_with(code, Strictness::Default).unwrap_err();
+            assert!(matches!(err.into_inner(), Error::Type(TypeError::Banned(_))));
+        }
+    }
+
+    #[test]
+    fn root_constants()
+    {
+        let source_code = b"
+            rootconst struct Root
+            {
+                mat4f ModelView;
+                float Time;
+            }
+        ";
+        let ast = parse_with(source_code, Strictness::Strict).unwrap();
+        let expected_ast = vec![Statement::RootConstants(Struct {
+            name: "Root".into(),
+            attr: None,
+            deprecated: None,
+            props: vec![
+                Property {
+                    pname: "ModelView".into(),
+                    ptype: PropertyType::Matrix(VectorType {
+                        item: BaseType::Float,
+                        size: 4
+                    }),
+                    pattr: None,
+                    deprecated: None,
+                    pformat: None,
+                    default: None,
+                    span: Default::default()
+                },
+                Property {
+                    pname: "Time".into(),
+                    ptype: PropertyType::Scalar(BaseType::Float),
+                    pattr: None,
+                    deprecated: None,
+                    pformat: None,
+                    default: None,
+                    span: Default::default()
+                }
+            ],
+            span: Default::default()
+        })];
+        assert_eq!(ast, expected_ast);
+        let err = parse_with(b"rootconst struct Root { vec4f[4] Values; }", Strictness::Default).unwrap_err();
+        assert!(matches!(err, Error::Type(TypeError::Banned(_))));
+    }
+
+    #[test]
+    fn int64_types()
+    {
+        let source_code = b"
+            const uint64 ParticleBaseOffset;
+            const int64 Delta;
+            const vec2u64 Range;
+            const vec4i64 Offsets;
+        ";
+        let mut lexer = Lexer::new();
+        lexer.process(source_code).unwrap();
+        let mut parser = Parser::new(lexer);
+        let ast = parser.parse(AstBuilder::new(Vec::new(), VecVisitor {})).unwrap().into_inner();
+        let types: Vec<PropertyType<String>> = ast.into_iter().map(|v| match v {
+            Statement::Constant(v) => v.ptype,
+            _ => panic!("expected a constant")
+        }).collect();
+        assert_eq!(types, vec![
+            PropertyType::Scalar(BaseType::Uint64),
+            PropertyType::Scalar(BaseType::Int64),
+            PropertyType::Vector(VectorType { item: BaseType::Uint64, size: 2 }),
+            PropertyType::Vector(VectorType { item: BaseType::Int64, size: 4 })
+        ]);
+        assert_eq!(types[2].to_string(), "vec2u64");
+        let mut lexer = Lexer::new();
+        lexer.process(b"const vec2u32 Range;").unwrap();
+        let mut parser = Parser::new(lexer);
+        match parser.parse(AstBuilder::new(Vec::new(), VecVisitor {})) {
+            Err(crate::parser::error::ParserOrVisitor::Visitor(Error::Located(_, e))) => match *e {
+                Error::Type(TypeError::UnknownVector(suffix)) => assert_eq!(suffix, "u32"),
+                _ => panic!("expected an unknown vector error")
+            },
+            _ => panic!("expected an unknown vector error")
+        }
+    }
+
+    #[test]
+    fn default_values()
+    {
+        let source_code = b"
+            const struct Material
+            {
+                float Specular = 1;
+                uint Flags = 3;
+                bool Lit = true;
+                vec3f Tint = vec3f(1, 0.5, 0.25);
+                vec2i Offset = vec2i(-1, 2);
+                float Roughness;
+            }
+        ";
+        let ast = parse_with(source_code, Strictness::Default).unwrap();
+        let defaults: Vec<Option<DefaultValue>> = match ast.into_iter().next() {
+            Some(Statement::ConstantBuffer(v)) => v.props.into_iter().map(|v| v.default).collect(),
+            _ => panic!("expected a constant buffer")
+        };
+        assert_eq!(defaults, vec![
+            Some(DefaultValue::Float(vec![1.0])),
+            Some(DefaultValue::Uint(vec![3])),
+            Some(DefaultValue::Bool(vec![true])),
+            Some(DefaultValue::Float(vec![1.0, 0.5, 0.25])),
+            Some(DefaultValue::Int(vec![-1, 2])),
+            None
+        ]);
+        let err = parse_with(b"const struct M { uint Flags = -1; }", Strictness::Default).unwrap_err();
+        assert!(matches!(err, Error::Type(TypeError::DefaultMismatch(_))));
+        let err = parse_with(b"const struct M { int Count = 1.5; }", Strictness::Default).unwrap_err();
+        assert!(matches!(err, Error::Type(TypeError::DefaultMismatch(_))));
+        let err = parse_with(b"const struct M { vec4f Color = vec3f(1, 1, 1); }", Strictness::Default).unwrap_err();
+        assert!(matches!(err, Error::Type(TypeError::DefaultMismatch(_))));
+        let err = parse_with(b"const struct M { vec4f Color = vec4f(1, 1, 1); }", Strictness::Default).unwrap_err();
+        assert!(matches!(err, Error::Type(TypeError::DefaultSize { expected: 4, actual: 3, .. })));
+        let err = parse_with(b"const struct M { mat4f View = 1; }", Strictness::Default).unwrap_err();
+        assert!(matches!(err, Error::Type(TypeError::DefaultNotAllowed(_))));
+        let err = parse_with(b"const Sampler Base = 0;", Strictness::Default).unwrap_err();
+        assert!(matches!(err, Error::Type(TypeError::DefaultNotAllowed(_))));
+        let err = parse_with(b"const float Time = 0;", Strictness::Default).unwrap_err();
+        assert!(matches!(err, Error::Type(TypeError::DefaultNotAllowed(_))));
+        let err = parse_with(b"vformat struct Vertex { vec3f Pos = vec3f(0, 0, 0); }", Strictness::Default).unwrap_err();
+        assert!(matches!(err, Error::Type(TypeError::DefaultNotAllowed(_))));
+    }
+
+    fn parse_with(source_code: &[u8], strictness: Strictness) -> Result<Vec<Statement>, Error<String, ()>>
+    {
+        let mut lexer = Lexer::new();
+        lexer.process(source_code).unwrap();
+        let mut parser = Parser::new(lexer);
+        match parser.parse(AstBuilder::with_strictness(Vec::new(), VecVisitor {}, strictness)) {
+            Ok(v) => Ok(v.into_inner()),
+            Err(crate::parser::error::ParserOrVisitor::Visitor(e)) => Err(e.into_inner()),
+            Err(crate::parser::error::ParserOrVisitor::Parser(e)) => panic!("unexpected parser error: {}", e),
+            Err(crate::parser::error::ParserOrVisitor::Panic(e)) => panic!("unexpected panic: {}", e)
+        }
+    }
+
+    #[test]
+    fn strict_rules()
+    {
+        let cases: [(StrictnessRule, &[u8]); 13] = [
+            (StrictnessRule::IdentifierAttribute, b"const float Value : BaseSampler;"),
+            (StrictnessRule::IdentifierAttribute, b"const struct Material : BaseSampler { float Value; }"),
+            (StrictnessRule::MisplacedAttribute, b"const struct Material { float Specular : Pack; }"),
+            (StrictnessRule::MisplacedAttribute, b"const struct Material { float Specular : ORDER_1; }"),
+            (StrictnessRule::MisplacedAttribute, b"vformat struct Vertex : SEMANTIC_POSITION { vec3f Pos; }"),
+            (StrictnessRule::TypeLikeStructRef, b"const Float Value;"),
+            (StrictnessRule::TypeLikeStructRef, b"const Texture2D BaseTexture;"),
+            (StrictnessRule::IgnoredSampleType, b"const float:vec4f Value;"),
+            (StrictnessRule::LeadingZeros, b"const struct Material : ORDER_01 { float Value; }"),
+            (StrictnessRule::LeadingZeros, b"const vec04f Color;"),
+            (StrictnessRule::MisplacedAttribute, b"const struct Material { vec4f Color : TARGET_0; }"),
+            (StrictnessRule::VariableMember, b"pipeline Main { DepthEnable::Front = true; }"),
+            (StrictnessRule::DuplicateVariable, b"pipeline Main { DepthEnable = true; DepthEnable = false; }")
+        ];
+        for (rule, source_code) in cases {
+            let source = String::from_utf8_lossy(source_code);
+            assert!(parse_with(source_code, Strictness::Default).is_ok(), "default mode rejected: {}", source);
+            match parse_with(source_code, Strictness::Strict) {
+                Err(Error::Strict(e)) => assert_eq!(e.rule, rule, "wrong rule for: {}", source),
+                _ => panic!("strict mode accepted: {}", source)
+            }
+        }
+    }
+
+    #[test]
+    fn strict_valid()
+    {
+        let source_code = b"
+            const Sampler BaseSampler;
+            const Texture2D:vec4f BaseTexture : BaseSampler;
+            const float Time : ORDER_0;
+            const struct Light : Pack { vec4f color; float attenuation; }
+            const struct PerMaterial : ORDER_10 { vec4f BaseColor; Light[4] Lights; }
+            const struct Camera : SHARED_9 { mat4f View; }
+            output vec4f Color : deprecated(\"use FragColor\");
+            output vec4f Normal : SEMANTIC_SV_Target1;
+            vformat struct Vertex { vec3f Pos : SEMANTIC_POSITION; }
+            pipeline Main { DepthEnable = true; CullingMode = BackFace; }
+        ";
+        let strict = parse_with(source_code, Strictness::Strict).unwrap();
+        assert_eq!(strict, parse_with(source_code, Strictness::Default).unwrap());
+    }
+
+    #[test]
+    fn located_errors()
+    {
+        let source_code = b"
+            const struct Light {
+                vec4f Color;
+                Sampler Shadow;
+            }
+        ";
+        let mut lexer = Lexer::new();
+        lexer.process(source_code).unwrap();
+        let mut parser = Parser::new(lexer);
+        let err = match parser.parse(AstBuilder::new(Vec::new(), VecVisitor {})) {
+            Err(crate::parser::error::ParserOrVisitor::Visitor(e)) => e,
+            _ => panic!("expected a visitor error")
+        };
+        //Errors in a struct point at the offending member rather than the struct.
+        assert_eq!(err.span(), Some(tree::Span::new(4, 24)));
+        assert_eq!(err.to_string(), "4:24 type error: forbidden property type (Sampler)");
+        assert!(matches!(err.into_inner(), Error::Type(TypeError::Banned(PropertyType::Sampler))));
+    }
+
+    #[test]
+    fn inheritance()
+    {
+        let source_code = b"
+            const struct CommonHeader : Pack { mat4f ViewProj; float Time; }
+            const struct PerView extends CommonHeader : Pack { vec3f CameraPos; }
+            const struct PerObject extends PerView : ORDER_1 { mat4f Model; }
+        ";
+        let ast = parse_with(source_code, Strictness::Default).unwrap();
+        let names = |s: &Statement| match s {
+            Statement::ConstantBuffer(s) => s.props.iter().map(|v| v.pname.clone()).collect::<Vec<_>>(),
+            _ => panic!("expected a constant buffer")
+        };
+        assert_eq!(names(&ast[1]), ["ViewProj", "Time", "CameraPos"]);
+        assert_eq!(names(&ast[2]), ["ViewProj", "Time", "CameraPos", "Model"]);
+        match &ast[2] {
+            Statement::ConstantBuffer(s) => {
+                //Attributes are not inherited.
+                assert_eq!(s.attr, Some(Attribute::Order(1)));
+                assert_eq!(s.props[0].ptype, PropertyType::Matrix(VectorType { item: BaseType::Float, size: 4 }));
+            },
+            _ => unreachable!()
+        }
+    }
+
+    #[test]
+    fn inheritance_errors()
+    {
+        let err = parse_with(b"const struct A extends A { float X; }", Strictness::Default).unwrap_err();
+        assert!(matches!(err, Error::Type(TypeError::InheritanceCycle(name)) if name == "A"));
+        //A base must be declared first, which also rules out longer cycles.
+        let err = parse_with(b"
+            const struct A extends B { float X; }
+            const struct B extends A { float Y; }
+        ", Strictness::Default).unwrap_err();
+        assert!(matches!(err, Error::Type(TypeError::UnknownBase(name)) if name == "B"));
+        let err = parse_with(b"
+            vformat struct Vertex { vec3f Pos; }
+            const struct A extends Vertex { float X; }
+        ", Strictness::Default).unwrap_err();
+        assert!(matches!(err, Error::Type(TypeError::VertexFormatInheritance(name)) if name == "Vertex"));
+        let err = parse_with(b"
+            const struct A { float X; }
+            vformat struct Vertex extends A { vec3f Pos; }
+        ", Strictness::Default).unwrap_err();
+        assert!(matches!(err, Error::Type(TypeError::VertexFormatInheritance(name)) if name == "Vertex"));
+        //Inherited properties must also be valid in the derived struct.
+        let err = parse_with(b"
+            const struct Light : Pack { vec4f Color; }
+            const struct A : ORDER_1 { Light L; }
+            rootconst struct Root extends A { float X; }
+        ", Strictness::Default).unwrap_err();
+        assert!(matches!(err, Error::Type(TypeError::Banned(PropertyType::StructRef(_)))));
+    }
+
+    #[test]
+    fn constant_ints()
+    {
+        let ast = parse_with(b"
+            const uint MAX_LIGHTS = 32;
+            const int Bias = -2;
+            const struct Lights { vec4f[MAX_LIGHTS] Colors; }
+        ", Strictness::Strict).unwrap();
+        assert_eq!(ast[0], Statement::ConstantInt(ast::ConstantInt {
+            name: "MAX_LIGHTS".into(),
+            ty: BaseType::Uint,
+            value: 32,
+            span: Default::default()
+        }));
+        assert!(matches!(&ast[1], Statement::ConstantInt(c) if c.ty == BaseType::Int && c.value == -2));
+        match &ast[2] {
+            Statement::ConstantBuffer(s) => assert!(matches!(&s.props[0].ptype, PropertyType::Array(a) if a.size == 32)),
+            _ => unreachable!()
+        }
+        let err = parse_with(b"const uint Count = -1;", Strictness::Default).unwrap_err();
+        assert!(matches!(err, Error::Type(TypeError::DefaultMismatch(name)) if name == "Count"));
+        //Other constants still reject default values.
+        let err = parse_with(b"const float Scale = 2;", Strictness::Default).unwrap_err();
+        assert!(matches!(err, Error::Type(TypeError::DefaultNotAllowed(name)) if name == "Scale"));
+    }
+
+    #[test]
+    fn string_values()
+    {
+        let unexpected = |code: &[u8]| match parse_with(code, Strictness::Default).unwrap_err() {
+            Error::Value(ValueError::Variable(_, e)) => *e,
+            e => panic!("unexpected error {:?}", e)
+        };
+        let err = unexpected(b"pipeline P { DepthEnable = \"yes\"; }");
+        assert!(matches!(&err, ValueError::Unexpected { expected: ValueType::Bool, actual } if ValueType::of(actual) == ValueType::String));
+        assert_eq!(err.to_string(), "unexpected string value (expected bool, got String(\"yes\"))");
+        let err = unexpected(b"blendfunc B { ColorOp = \"Add\"; }");
+        assert!(matches!(&err, ValueError::Unexpected { expected: ValueType::Enum, actual } if ValueType::of(actual) == ValueType::String));
+        let err = parse_with(b"const struct S { float X = \"one\"; }", Strictness::Default).unwrap_err();
+        assert!(matches!(err, Error::Type(TypeError::DefaultType(name, ValueType::String)) if name == "X"));
+        let err = parse_with(b"const struct S { vec2f X = vec2f(\"one\", 2); }", Strictness::Default).unwrap_err();
+        assert!(matches!(err, Error::Type(TypeError::DefaultType(name, ValueType::String)) if name == "X"));
+    }
 }
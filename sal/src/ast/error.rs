@@ -29,16 +29,33 @@
 use std::{fmt::Debug, num::ParseIntError};
 use std::fmt::{Display, Formatter};
 
-use crate::{ast::tree as ast, parser::tree};
+use crate::{ast::tree as ast, parser::tree::{self, Span}};
+use crate::ast::StrictError;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum ValueType
 {
     Bool,
     Float,
     Int,
     Enum,
-    Identifier
+    Identifier,
+    String
+}
+
+impl ValueType
+{
+    /// Returns the type of a value; identifiers are reported as such even where an enum is expected.
+    pub fn of(value: &tree::Value) -> ValueType
+    {
+        match value {
+            tree::Value::Int(_) => ValueType::Int,
+            tree::Value::Float(_) => ValueType::Float,
+            tree::Value::Bool(_) => ValueType::Bool,
+            tree::Value::Identifier(_) => ValueType::Identifier,
+            tree::Value::String(_) => ValueType::String
+        }
+    }
 }
 
 impl Display for ValueType
@@ -50,7 +67,8 @@ impl Display for ValueType
             ValueType::Float => f.write_str("float"),
             ValueType::Int => f.write_str("int"),
             ValueType::Enum => f.write_str("enum"),
-            ValueType::Identifier => f.write_str("identifier")
+            ValueType::Identifier => f.write_str("identifier"),
+            ValueType::String => f.write_str("string")
         }
     }
 }
@@ -59,11 +77,36 @@ impl Display for ValueType
 pub enum TypeError<T>
 {
     AttributeOrder(ParseIntError),
+    AttributeValue(String),
+    AttributeShared(ParseIntError),
+    AttributeSemantic(String),
+    AttributeInterpolation(String),
+    AttributeLocation(ParseIntError),
+    AttributeTarget(ParseIntError),
+    TargetFormat(String),
+    LocationOverlap(String, u32),
+    FlatRequired(String),
     VectorSize(ParseIntError),
     UnknownVector(String),
     UnknownTexture(String),
+    SampleCount(String),
     Unknown(String),
-    Banned(ast::PropertyType<T>)
+    Banned(ast::PropertyType<T>),
+    SamplerArray(String),
+    NestedArray(String),
+    DefaultNotAllowed(String),
+    DefaultMismatch(String),
+    /// A default value of a type which no property accepts, such as a string.
+    DefaultType(String, ValueType),
+    DefaultSize
+    {
+        name: String,
+        expected: u8,
+        actual: usize
+    },
+    UnknownBase(String),
+    InheritanceCycle(String),
+    VertexFormatInheritance(String)
 }
 
 impl<T: Display> Display for TypeError<T>
@@ -72,11 +115,30 @@ impl<T: Display> Display for TypeError<T>
     {
         match self {
             TypeError::AttributeOrder(e) => write!(f, "failed to parse order attribute ({})", e),
+            TypeError::AttributeShared(e) => write!(f, "failed to parse shared slot attribute ({})", e),
+            TypeError::AttributeValue(s) => write!(f, "attribute {} does not take a value", s),
+            TypeError::AttributeSemantic(s) => write!(f, "missing semantic name in attribute {}", s),
+            TypeError::AttributeInterpolation(s) => write!(f, "unknown interpolation qualifier {} (expected Smooth, Flat or NoPerspective)", s),
+            TypeError::AttributeLocation(e) => write!(f, "failed to parse location attribute ({})", e),
+            TypeError::AttributeTarget(e) => write!(f, "failed to parse render target attribute ({})", e),
+            TypeError::TargetFormat(s) => write!(f, "unknown render target format {}", s),
+            TypeError::LocationOverlap(s, l) => write!(f, "location {} of vertex format member {} is already in use", l, s),
+            TypeError::FlatRequired(s) => write!(f, "integer varying {} must use the Flat interpolation", s),
             TypeError::VectorSize(e) => write!(f, "failed to parse vector size ({})", e),
             TypeError::UnknownVector(s) => write!(f, "unknown vector type ({})", s),
             TypeError::UnknownTexture(s) => write!(f, "unknown texture type ({})", s),
+            TypeError::SampleCount(s) => write!(f, "invalid sample count, only multisample textures take one and it must be at least 1 ({})", s),
             TypeError::Unknown(s) => write!(f, "unknown type ({})", s),
-            TypeError::Banned(t) => write!(f, "forbidden property type ({})", t)
+            TypeError::Banned(t) => write!(f, "forbidden property type ({})", t),
+            TypeError::SamplerArray(s) => write!(f, "arrays of samplers are not supported ({})", s),
+            TypeError::NestedArray(s) => write!(f, "arrays of arrays are not supported ({})", s),
+            TypeError::DefaultNotAllowed(s) => write!(f, "property {} does not accept a default value", s),
+            TypeError::DefaultMismatch(s) => write!(f, "default value of property {} does not match its type", s),
+            TypeError::DefaultType(s, t) => write!(f, "default value of property {} can't be a {}", s, t),
+            TypeError::DefaultSize { name, expected, actual } => write!(f, "default value of property {} has {} components (expected {})", name, actual, expected),
+            TypeError::UnknownBase(s) => write!(f, "unknown base struct ({})", s),
+            TypeError::InheritanceCycle(s) => write!(f, "struct {} extends itself", s),
+            TypeError::VertexFormatInheritance(s) => write!(f, "vertex format {} cannot take part in struct inheritance", s)
         }
     }
 }
@@ -86,6 +148,15 @@ pub enum ValueError
 {
     UnknownEnum(String),
     UnknownVariable(String),
+    UnknownPreset(String),
+    /// The value assigned to a variable of a variable list is invalid.
+    Variable(String, Box<ValueError>),
+    OutOfRange
+    {
+        value: u32,
+        min: u32,
+        max: u32
+    },
     Unexpected
     {
         expected: ValueType,
@@ -100,7 +171,10 @@ impl Display for ValueError
         match self {
             ValueError::UnknownEnum(e) => write!(f, "unknown enum ({})", e),
             ValueError::UnknownVariable(v) => write!(f, "unknown variable ({})", v),
-            ValueError::Unexpected { expected, actual } => write!(f, "unexpected value (expected {}, got {:?})", expected, actual),
+            ValueError::UnknownPreset(v) => write!(f, "unknown pipeline preset ({})", v),
+            ValueError::Variable(v, e) => write!(f, "invalid value for {}: {}", v, e),
+            ValueError::OutOfRange { value, min, max } => write!(f, "value out of range (expected {} to {}, got {})", min, max, value),
+            ValueError::Unexpected { expected, actual } => write!(f, "unexpected {} value (expected {}, got {:?})", ValueType::of(actual), expected, actual),
         }
     }
 }
@@ -110,7 +184,39 @@ pub enum Error<T, E>
 {
     Type(TypeError<T>),
     Value(ValueError),
-    Visitor(E)
+    Strict(StrictError),
+    Visitor(E),
+    /// An error attached to the node of the source code which caused it.
+    Located(Span, Box<Error<T, E>>)
+}
+
+impl<T, E> Error<T, E>
+{
+    /// Attaches a location to this error, an error which is already located keeps its location.
+    pub fn at(self, span: Span) -> Self
+    {
+        match self {
+            Error::Located(..) => self,
+            e => Error::Located(span, Box::new(e))
+        }
+    }
+
+    pub fn span(&self) -> Option<Span>
+    {
+        match self {
+            Error::Located(span, _) => Some(*span),
+            _ => None
+        }
+    }
+
+    /// Returns this error without its location.
+    pub fn into_inner(self) -> Self
+    {
+        match self {
+            Error::Located(_, e) => *e,
+            e => e
+        }
+    }
 }
 
 impl<T, E> From<TypeError<T>> for Error<T, E>
@@ -129,6 +235,14 @@ impl<T, E> From<ValueError> for Error<T, E>
     }
 }
 
+impl<T, E> From<StrictError> for Error<T, E>
+{
+    fn from(e: StrictError) -> Self
+    {
+        Self::Strict(e)
+    }
+}
+
 impl<T: Display, E: Debug> Display for Error<T, E>
 {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
@@ -136,7 +250,9 @@ impl<T: Display, E: Debug> Display for Error<T, E>
         match self {
             Error::Type(e) => write!(f, "type error: {}", e),
             Error::Value(e) => write!(f, "value error: {}", e),
-            Error::Visitor(e) => write!(f, "visitor error: {:?}", e)
+            Error::Strict(e) => write!(f, "strict error: {}", e),
+            Error::Visitor(e) => write!(f, "visitor error: {:?}", e),
+            Error::Located(span, e) => write!(f, "{} {}", span, e)
         }
     }
 }
@@ -26,23 +26,55 @@
 // NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
 // SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
-use crate::ast::tree::{BlendfuncStatement, PipelineStatement, Property, Struct};
+use crate::ast::tree::{BlendfuncStatement, ConstantInt, PipelineStatement, Property, ResourceStatement, SamplerStateStatement, Span, Struct};
+
+/// A struct which a struct declared after it may extend.
+pub struct BaseStruct<T> {
+    /// Properties of the struct, including the ones it inherits itself.
+    pub props: Vec<Property<T>>,
+    pub vertex_format: bool
+}
 
 pub trait RefResolver {
     type Key;
     fn resolve_struct_ref(&self, name: &str) -> Option<Self::Key>;
+
+    /// Resolves the base of a struct declared with `extends`.
+    ///
+    /// Only structs declared before the derived struct can be resolved, so inheritance can only
+    /// be cyclic when a struct extends itself.
+    fn resolve_struct(&self, _name: &str) -> Option<BaseStruct<Self::Key>> {
+        None
+    }
+
+    /// Resolves a pipeline preset a local pipeline may inherit from.
+    fn resolve_pipeline(&self, _name: &str) -> Option<PipelineStatement> {
+        None
+    }
 }
 
+/// Receives the statements of an [AstBuilder](crate::ast::AstBuilder).
+///
+/// # Panics
+///
+/// Visit functions run inside the parser visitor, so a panic is reported the same way, as
+/// [ParserOrVisitor::Panic](crate::parser::error::ParserOrVisitor::Panic). The AST may then contain
+/// a partially visited statement.
 pub trait Visitor<A: RefResolver> {
     type Error;
     fn visit_constant(&mut self, ast: &mut A, val: Property<A::Key>) -> Result<(), Self::Error>;
+    fn visit_constant_int(&mut self, ast: &mut A, val: ConstantInt) -> Result<(), Self::Error>;
     fn visit_output(&mut self, ast: &mut A, val: Property<A::Key>) -> Result<(), Self::Error>;
+    fn visit_varying(&mut self, ast: &mut A, val: Property<A::Key>) -> Result<(), Self::Error>;
     fn visit_constant_buffer(&mut self, ast: &mut A, val: Struct<A::Key>) -> Result<(), Self::Error>;
     fn visit_vertex_format(&mut self, ast: &mut A, val: Struct<A::Key>) -> Result<(), Self::Error>;
+    fn visit_root_constants(&mut self, ast: &mut A, val: Struct<A::Key>) -> Result<(), Self::Error>;
     fn visit_pipeline(&mut self, ast: &mut A, val: PipelineStatement) -> Result<(), Self::Error>;
     fn visit_blendfunc(&mut self, ast: &mut A, val: BlendfuncStatement) -> Result<(), Self::Error>;
+    fn visit_samplerstate(&mut self, ast: &mut A, val: SamplerStateStatement) -> Result<(), Self::Error>;
+    fn visit_resource(&mut self, ast: &mut A, val: ResourceStatement) -> Result<(), Self::Error>;
     fn visit_noop(&mut self, ast: &mut A) -> Result<(), Self::Error>;
-    fn visit_use(&mut self, ast: &mut A, module: String, member: String) -> Result<(), Self::Error>;
+    fn visit_use(&mut self, ast: &mut A, path: Vec<String>, member: String, span: Span) -> Result<(), Self::Error>;
 }
 
 impl<'a, A: RefResolver, T: Visitor<A>> Visitor<A> for &'a mut T {
@@ -52,10 +84,18 @@ impl<'a, A: RefResolver, T: Visitor<A>> Visitor<A> for &'a mut T {
         (*self).visit_constant(ast, val)
     }
 
+    fn visit_constant_int(&mut self, ast: &mut A, val: ConstantInt) -> Result<(), Self::Error> {
+        (*self).visit_constant_int(ast, val)
+    }
+
     fn visit_output(&mut self, ast: &mut A, val: Property<A::Key>) -> Result<(), Self::Error> {
         (*self).visit_output(ast, val)
     }
 
+    fn visit_varying(&mut self, ast: &mut A, val: Property<A::Key>) -> Result<(), Self::Error> {
+        (*self).visit_varying(ast, val)
+    }
+
     fn visit_constant_buffer(&mut self, ast: &mut A, val: Struct<A::Key>) -> Result<(), Self::Error> {
         (*self).visit_constant_buffer(ast, val)
     }
@@ -64,6 +104,10 @@ impl<'a, A: RefResolver, T: Visitor<A>> Visitor<A> for &'a mut T {
         (*self).visit_vertex_format(ast, val)
     }
 
+    fn visit_root_constants(&mut self, ast: &mut A, val: Struct<A::Key>) -> Result<(), Self::Error> {
+        (*self).visit_root_constants(ast, val)
+    }
+
     fn visit_pipeline(&mut self, ast: &mut A, val: PipelineStatement) -> Result<(), Self::Error> {
         (*self).visit_pipeline(ast, val)
     }
@@ -72,11 +116,19 @@ impl<'a, A: RefResolver, T: Visitor<A>> Visitor<A> for &'a mut T {
         (*self).visit_blendfunc(ast, val)
     }
 
+    fn visit_samplerstate(&mut self, ast: &mut A, val: SamplerStateStatement) -> Result<(), Self::Error> {
+        (*self).visit_samplerstate(ast, val)
+    }
+
+    fn visit_resource(&mut self, ast: &mut A, val: ResourceStatement) -> Result<(), Self::Error> {
+        (*self).visit_resource(ast, val)
+    }
+
     fn visit_noop(&mut self, ast: &mut A) -> Result<(), Self::Error> {
         (*self).visit_noop(ast)
     }
 
-    fn visit_use(&mut self, ast: &mut A, module: String, member: String) -> Result<(), Self::Error> {
-        (*self).visit_use(ast, module, member)
+    fn visit_use(&mut self, ast: &mut A, path: Vec<String>, member: String, span: Span) -> Result<(), Self::Error> {
+        (*self).visit_use(ast, path, member, span)
     }
 }
@@ -29,8 +29,10 @@
 mod core;
 pub mod error;
 mod interface;
+mod strict;
 pub mod tree;
 
 pub use interface::*;
+pub use strict::{Strictness, StrictnessRule, StrictError};
 
 pub use self::core::AstBuilder;
@@ -0,0 +1,253 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use std::fmt::{Display, Formatter};
+
+use crate::ast::tree::Interpolation;
+use crate::parser::tree;
+
+/// How the AST builder reacts to the lenient syntax listed in [StrictnessRule].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Strictness
+{
+    /// Accept the lenient syntax for compatibility with existing shaders.
+    #[default]
+    Default,
+
+    /// Reject any lenient syntax.
+    Strict
+}
+
+/// Lenient behaviors accepted by default and turned into errors in strict mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StrictnessRule
+{
+    /// A free-form attribute (ex: `: BaseSampler`) only means something on a texture, where it
+    /// names the sampler; anywhere else it is kept and ignored.
+    IdentifierAttribute,
+
+    /// `Pack` and `Shared` only apply to a struct and `ORDER_x` has no effect on a struct member,
+    /// yet both are accepted there.
+    MisplacedAttribute,
+
+    /// A type name which looks like a built-in type (ex: `Float`, `Vec4f` or a texture without
+    /// sample type) is silently resolved as a struct reference.
+    TypeLikeStructRef,
+
+    /// A sample type (ex: `float:vec4f`) on anything else than a texture is dropped.
+    IgnoredSampleType,
+
+    /// Numbers in `ORDER_x`, `SHARED_x` and vector/matrix sizes accept leading zeros.
+    LeadingZeros,
+
+    /// The member of a namespaced pipeline or blendfunc variable (ex: `DepthEnable::Front`)
+    /// is dropped.
+    VariableMember,
+
+    /// A variable assigned more than once in the same block, the last value silently wins.
+    DuplicateVariable
+}
+
+impl StrictnessRule
+{
+    pub fn name(&self) -> &'static str
+    {
+        match self {
+            StrictnessRule::IdentifierAttribute => "identifier-attribute",
+            StrictnessRule::MisplacedAttribute => "misplaced-attribute",
+            StrictnessRule::TypeLikeStructRef => "type-like-struct-ref",
+            StrictnessRule::IgnoredSampleType => "ignored-sample-type",
+            StrictnessRule::LeadingZeros => "leading-zeros",
+            StrictnessRule::VariableMember => "variable-member",
+            StrictnessRule::DuplicateVariable => "duplicate-variable"
+        }
+    }
+}
+
+impl Display for StrictnessRule
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+    {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct StrictError
+{
+    pub rule: StrictnessRule,
+    pub message: String
+}
+
+impl StrictError
+{
+    fn new(rule: StrictnessRule, message: String) -> StrictError
+    {
+        StrictError { rule, message }
+    }
+}
+
+impl Display for StrictError
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+    {
+        write!(f, "{} (strict rule {})", self.message, self.rule)
+    }
+}
+
+const SCALARS: &[&str] = &["Sampler", "SamplerCmp", "float", "double", "int", "uint", "bool", "int64", "uint64"];
+const TEXTURES: &[&str] = &["Texture1D", "Texture2D", "Texture3D", "Texture2DArray", "TextureCube", "Texture2DMS"];
+
+fn has_leading_zeros(num: &str) -> bool
+{
+    num.len() > 1 && num.starts_with('0')
+}
+
+fn check_type(p: &tree::Property) -> Result<(), StrictError>
+{
+    let ptype = &*p.ptype;
+    let is_texture = TEXTURES.contains(&ptype);
+    if let Some(subtype) = &p.ptype_attr {
+        if !is_texture {
+            return Err(StrictError::new(StrictnessRule::IgnoredSampleType,
+                format!("sample type '{}' has no effect on non texture type '{}'", subtype, ptype)));
+        }
+    } else if is_texture {
+        return Err(StrictError::new(StrictnessRule::TypeLikeStructRef,
+            format!("texture type '{}' requires a sample type (ex: {}:vec4f)", ptype, ptype)));
+    }
+    if ptype.starts_with("vec") || ptype.starts_with("mat") {
+        let size: String = ptype[3..].chars().take_while(|c| c.is_ascii_digit()).collect();
+        if has_leading_zeros(&size) {
+            return Err(StrictError::new(StrictnessRule::LeadingZeros,
+                format!("size of vector type '{}' has leading zeros", ptype)));
+        }
+        return Ok(());
+    }
+    if SCALARS.contains(&ptype) || is_texture {
+        return Ok(());
+    }
+    let lower = ptype.to_ascii_lowercase();
+    let builtin = SCALARS.iter().chain(TEXTURES).find(|v| v.eq_ignore_ascii_case(ptype)).map(|v| String::from(*v));
+    let vector = (lower.starts_with("vec") || lower.starts_with("mat"))
+        && lower[3..].starts_with(|c: char| c.is_ascii_digit());
+    if let Some(builtin) = builtin.or_else(|| vector.then_some(lower)) {
+        return Err(StrictError::new(StrictnessRule::TypeLikeStructRef,
+            format!("'{}' is resolved as a struct but looks like the built-in type '{}'", ptype, builtin)));
+    }
+    Ok(())
+}
+
+fn check_attribute(attr: &tree::Attribute, on_struct: bool, on_member: bool, on_texture: bool) -> Result<(), StrictError>
+{
+    let name = &*attr.name;
+    let number = name.strip_prefix("ORDER_").or_else(|| name.strip_prefix("SHARED_"))
+        .or_else(|| name.strip_prefix("LOCATION_")).or_else(|| name.strip_prefix("TARGET_"));
+    if let Some(number) = number {
+        if has_leading_zeros(number) {
+            return Err(StrictError::new(StrictnessRule::LeadingZeros,
+                format!("number in attribute '{}' has leading zeros", name)));
+        }
+    }
+    if name.starts_with("SEMANTIC_") {
+        if on_struct {
+            return Err(StrictError::new(StrictnessRule::MisplacedAttribute,
+                format!("attribute '{}' only applies to a property", name)));
+        }
+    } else if name.starts_with("LOCATION_") {
+        if !on_member {
+            return Err(StrictError::new(StrictnessRule::MisplacedAttribute,
+                format!("attribute '{}' only applies to a vertex format member", name)));
+        }
+    } else if name.starts_with("TARGET_") {
+        if on_struct || on_member {
+            return Err(StrictError::new(StrictnessRule::MisplacedAttribute,
+                format!("attribute '{}' only applies to an output", name)));
+        }
+    } else if name == "Pack" || name == "Shared" || name.starts_with("SHARED_") {
+        if !on_struct {
+            return Err(StrictError::new(StrictnessRule::MisplacedAttribute,
+                format!("attribute '{}' only applies to a struct", name)));
+        }
+    } else if name.starts_with("ORDER_") {
+        if on_member {
+            return Err(StrictError::new(StrictnessRule::MisplacedAttribute,
+                format!("attribute '{}' has no effect on a struct member", name)));
+        }
+    } else if !on_texture {
+        return Err(StrictError::new(StrictnessRule::IdentifierAttribute,
+            format!("unknown attribute '{}' (only textures accept a sampler name)", name)));
+    }
+    Ok(())
+}
+
+pub(crate) fn check_property(p: &tree::Property, member: bool) -> Result<(), StrictError>
+{
+    check_type(p)?;
+    if let Some(attr) = &p.pattr {
+        let on_texture = !member && p.ptype_attr.is_some() && TEXTURES.contains(&&*p.ptype);
+        check_attribute(attr, false, member, on_texture)?;
+    }
+    Ok(())
+}
+
+pub(crate) fn check_varying(p: &tree::Property) -> Result<(), StrictError>
+{
+    check_type(p)?;
+    match &p.pattr {
+        //Interpolation qualifiers are checked when building the varying.
+        Some(attr) if Interpolation::from_name(&attr.name).is_none() => check_attribute(attr, false, false, false),
+        _ => Ok(())
+    }
+}
+
+pub(crate) fn check_struct(s: &tree::Struct) -> Result<(), StrictError>
+{
+    if let Some(attr) = &s.attr {
+        check_attribute(attr, true, false, false)?;
+    }
+    for p in &s.props {
+        check_property(p, true)?;
+    }
+    Ok(())
+}
+
+pub(crate) fn check_varlist(v: &tree::VariableList) -> Result<(), StrictError>
+{
+    for (i, var) in v.vars.iter().enumerate() {
+        if let Some(member) = &var.member {
+            return Err(StrictError::new(StrictnessRule::VariableMember,
+                format!("member '{}' of variable '{}' in '{}' is ignored", member, var.name, v.name)));
+        }
+        if v.vars[..i].iter().any(|v| v.name == var.name) {
+            return Err(StrictError::new(StrictnessRule::DuplicateVariable,
+                format!("variable '{}' is assigned more than once in '{}'", var.name, v.name)));
+        }
+    }
+    Ok(())
+}
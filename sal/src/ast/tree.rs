@@ -29,11 +29,17 @@
 use std::fmt::{Display, Formatter};
 use serde::{Serialize, Deserialize};
 
+pub use crate::parser::tree::Span;
+
 pub trait VarlistStatement
 {
     fn new(name: String) -> Self;
+    fn inherit(name: String, base: Self) -> Self;
 }
 
+//Base types are serialized by index in shader pack symbols: new variants must be appended.
+//Int64 and Uint64 were added after the first release of the symbol format, older readers fail to
+//decode symbols which use them.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
 pub enum BaseType
 {
@@ -41,7 +47,9 @@ pub enum BaseType
     Float,
     Uint,
     Bool,
-    Double
+    Double,
+    Int64,
+    Uint64
 }
 
 impl BaseType
@@ -53,20 +61,31 @@ impl BaseType
             BaseType::Float => "float",
             BaseType::Uint => "uint",
             BaseType::Bool => "bool",
-            BaseType::Double => "double"
+            BaseType::Double => "double",
+            BaseType::Int64 => "int64",
+            BaseType::Uint64 => "uint64"
         }
     }
 
-    pub fn get_char(&self) -> char
+    /// Returns the suffix identifying this base type in vector and matrix type names.
+    pub fn get_suffix(&self) -> &'static str
     {
         match self {
-            BaseType::Int => 'i',
-            BaseType::Float => 'f',
-            BaseType::Uint => 'u',
-            BaseType::Bool => 'b',
-            BaseType::Double => 'd'
+            BaseType::Int => "i",
+            BaseType::Float => "f",
+            BaseType::Uint => "u",
+            BaseType::Bool => "b",
+            BaseType::Double => "d",
+            BaseType::Int64 => "i64",
+            BaseType::Uint64 => "u64"
         }
     }
+
+    /// Returns true if this base type is a 64-bit integer.
+    pub fn is_int64(&self) -> bool
+    {
+        matches!(self, BaseType::Int64 | BaseType::Uint64)
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
@@ -88,19 +107,39 @@ pub enum ArrayItemType<T>
 {
     Vector(VectorType),
     Matrix(VectorType),
-    StructRef(T)
+    StructRef(T),
+    Texture2D(TextureType),
+    Texture3D(TextureType),
+    TextureCube(TextureType)
 }
 
 impl<T: Copy> Copy for ArrayItemType<T> {}
 
+impl<T> ArrayItemType<T>
+{
+    pub fn is_texture(&self) -> bool
+    {
+        matches!(self, ArrayItemType::Texture2D(_) | ArrayItemType::Texture3D(_) | ArrayItemType::TextureCube(_))
+    }
+}
+
 impl<T: Display> Display for ArrayItemType<T>
 {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
     {
+        let mut fmt_texture_type = |name: &'static str, t: &TextureType| {
+            match t {
+                TextureType::Scalar(s) => write!(f, "{}<{}>", name, s.get_name()),
+                TextureType::Vector(v) => write!(f, "{}<vec{}{}>", name, v.size, v.item.get_suffix())
+            }
+        };
         match self {
-            ArrayItemType::Vector(v) => write!(f, "vec{}{}", v.size, v.item.get_char()),
-            ArrayItemType::Matrix(m) => write!(f, "mat{}{}", m.size, m.item.get_char()),
-            ArrayItemType::StructRef(s) => write!(f, "StructRef({})", s)
+            ArrayItemType::Vector(v) => write!(f, "vec{}{}", v.size, v.item.get_suffix()),
+            ArrayItemType::Matrix(m) => write!(f, "mat{}{}", m.size, m.item.get_suffix()),
+            ArrayItemType::StructRef(s) => write!(f, "StructRef({})", s),
+            ArrayItemType::Texture2D(t) => fmt_texture_type("Texture2D", t),
+            ArrayItemType::Texture3D(t) => fmt_texture_type("Texture3D", t),
+            ArrayItemType::TextureCube(t) => fmt_texture_type("TextureCube", t)
         }
     }
 }
@@ -121,10 +160,15 @@ pub enum PropertyType<T>
     Vector(VectorType),
     Matrix(VectorType),
     Sampler,
+    /// Comparison sampler, used to sample depth textures for shadow mapping.
+    SamplerCmp,
+    Texture1D(TextureType),
     Texture2D(TextureType),
     Texture3D(TextureType),
     Texture2DArray(TextureType),
     TextureCube(TextureType),
+    /// Multisample 2D texture with its number of samples.
+    Texture2DMS(TextureType, u32),
     StructRef(T),
     Array(ArrayType<T>)
 }
@@ -138,30 +182,76 @@ impl<T: Display> Display for PropertyType<T>
         let mut fmt_texture_type = |name: &'static str, t: &TextureType| {
             match t {
                 TextureType::Scalar(s) => write!(f, "{}<{}>", name, s.get_name()),
-                TextureType::Vector(v) => write!(f, "{}<vec{}{}>", name, v.size, v.item.get_char())
+                TextureType::Vector(v) => write!(f, "{}<vec{}{}>", name, v.size, v.item.get_suffix())
             }
         };
         match self {
             PropertyType::Scalar(s) => write!(f, "{}", s.get_name()),
-            PropertyType::Vector(v) => write!(f, "vec{}{}", v.size, v.item.get_char()),
-            PropertyType::Matrix(m) => write!(f, "mat{}{}", m.size, m.item.get_char()),
+            PropertyType::Vector(v) => write!(f, "vec{}{}", v.size, v.item.get_suffix()),
+            PropertyType::Matrix(m) => write!(f, "mat{}{}", m.size, m.item.get_suffix()),
             PropertyType::Sampler => f.write_str("Sampler"),
+            PropertyType::SamplerCmp => f.write_str("SamplerCmp"),
+            PropertyType::Texture1D(t) => fmt_texture_type("Texture1D", t),
             PropertyType::Texture2D(t) => fmt_texture_type("Texture2D", t),
             PropertyType::Texture3D(t) => fmt_texture_type("Texture3D", t),
             PropertyType::Texture2DArray(t) => fmt_texture_type("Texture2DArray", t),
             PropertyType::TextureCube(t) => fmt_texture_type("TextureCube", t),
+            PropertyType::Texture2DMS(t, samples) => match t {
+                TextureType::Scalar(s) => write!(f, "Texture2DMS<{}, {}>", s.get_name(), samples),
+                TextureType::Vector(v) => write!(f, "Texture2DMS<vec{}{}, {}>", v.size, v.item.get_suffix(), samples)
+            },
             PropertyType::StructRef(s) => write!(f, "StructRef({})", s),
             PropertyType::Array(a) => write!(f, "{}[{}]", a.item, a.size)
         }
     }
 }
 
+/// Interpolation qualifier of a varying.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Interpolation
+{
+    Smooth,
+    Flat,
+    NoPerspective
+}
+
+impl Interpolation
+{
+    pub fn from_name(name: &str) -> Option<Self>
+    {
+        match name {
+            "Smooth" => Some(Interpolation::Smooth),
+            "Flat" => Some(Interpolation::Flat),
+            "NoPerspective" => Some(Interpolation::NoPerspective),
+            _ => None
+        }
+    }
+
+    pub fn get_name(&self) -> &'static str
+    {
+        match self {
+            Interpolation::Smooth => "Smooth",
+            Interpolation::Flat => "Flat",
+            Interpolation::NoPerspective => "NoPerspective"
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Attribute
 {
     Identifier(String),
     Order(u32),
-    Pack
+    Pack,
+    Shared(Option<u32>),
+    /// HLSL semantic of a vertex format member or an output (`SEMANTIC_TEXCOORD0`).
+    Semantic(String),
+    /// Interpolation of a varying (`Flat`, `NoPerspective` or `Smooth`).
+    Interpolation(Interpolation),
+    /// Explicit vertex attribute location of a vertex format member (`LOCATION_3`).
+    Location(u32),
+    /// Render target index of an output (`TARGET_1`).
+    Target(u32)
 }
 
 impl Attribute
@@ -171,27 +261,243 @@ impl Attribute
         match self {
             Attribute::Identifier(_) => None,
             Attribute::Order(o) => Some(*o),
-            Attribute::Pack => None
+            Attribute::Pack => None,
+            Attribute::Shared(_) => None,
+            Attribute::Semantic(_) => None,
+            Attribute::Interpolation(_) => None,
+            Attribute::Location(_) => None,
+            Attribute::Target(_) => None
+        }
+    }
+
+    pub fn get_semantic(&self) -> Option<&str>
+    {
+        match self {
+            Attribute::Semantic(name) => Some(name),
+            _ => None
+        }
+    }
+
+    pub fn get_interpolation(&self) -> Option<Interpolation>
+    {
+        match self {
+            Attribute::Interpolation(i) => Some(*i),
+            _ => None
+        }
+    }
+
+    pub fn get_location(&self) -> Option<u32>
+    {
+        match self {
+            Attribute::Location(l) => Some(*l),
+            _ => None
+        }
+    }
+
+    pub fn get_target(&self) -> Option<u32>
+    {
+        match self {
+            Attribute::Target(t) => Some(*t),
+            _ => None
         }
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// Format hint of an output, which the engine uses to validate the attachments of a render pass.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TargetFormat
+{
+    R8,
+    Rg8,
+    Rgba8,
+    Rgba8Srgb,
+    Rgb10A2,
+    R11G11B10F,
+    R16F,
+    Rg16F,
+    Rgba16F,
+    R32F,
+    Rg32F,
+    Rgba32F,
+    R32I,
+    Rg32I,
+    Rgba32I,
+    R32Ui,
+    Rg32Ui,
+    Rgba32Ui
+}
+
+impl TargetFormat
+{
+    pub fn from_name(name: &str) -> Option<Self>
+    {
+        match name {
+            "R8" => Some(TargetFormat::R8),
+            "RG8" => Some(TargetFormat::Rg8),
+            "RGBA8" => Some(TargetFormat::Rgba8),
+            "RGBA8_SRGB" => Some(TargetFormat::Rgba8Srgb),
+            "RGB10A2" => Some(TargetFormat::Rgb10A2),
+            "R11G11B10F" => Some(TargetFormat::R11G11B10F),
+            "R16F" => Some(TargetFormat::R16F),
+            "RG16F" => Some(TargetFormat::Rg16F),
+            "RGBA16F" => Some(TargetFormat::Rgba16F),
+            "R32F" => Some(TargetFormat::R32F),
+            "RG32F" => Some(TargetFormat::Rg32F),
+            "RGBA32F" => Some(TargetFormat::Rgba32F),
+            "R32I" => Some(TargetFormat::R32I),
+            "RG32I" => Some(TargetFormat::Rg32I),
+            "RGBA32I" => Some(TargetFormat::Rgba32I),
+            "R32UI" => Some(TargetFormat::R32Ui),
+            "RG32UI" => Some(TargetFormat::Rg32Ui),
+            "RGBA32UI" => Some(TargetFormat::Rgba32Ui),
+            _ => None
+        }
+    }
+
+    pub fn get_name(&self) -> &'static str
+    {
+        match self {
+            TargetFormat::R8 => "R8",
+            TargetFormat::Rg8 => "RG8",
+            TargetFormat::Rgba8 => "RGBA8",
+            TargetFormat::Rgba8Srgb => "RGBA8_SRGB",
+            TargetFormat::Rgb10A2 => "RGB10A2",
+            TargetFormat::R11G11B10F => "R11G11B10F",
+            TargetFormat::R16F => "R16F",
+            TargetFormat::Rg16F => "RG16F",
+            TargetFormat::Rgba16F => "RGBA16F",
+            TargetFormat::R32F => "R32F",
+            TargetFormat::Rg32F => "RG32F",
+            TargetFormat::Rgba32F => "RGBA32F",
+            TargetFormat::R32I => "R32I",
+            TargetFormat::Rg32I => "RG32I",
+            TargetFormat::Rgba32I => "RGBA32I",
+            TargetFormat::R32Ui => "R32UI",
+            TargetFormat::Rg32Ui => "RG32UI",
+            TargetFormat::Rgba32Ui => "RGBA32UI"
+        }
+    }
+}
+
+/// Default value of a constant buffer member, one entry per vector component.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum DefaultValue
+{
+    Float(Vec<f64>),
+    Int(Vec<i64>),
+    Uint(Vec<u64>),
+    Bool(Vec<bool>)
+}
+
+impl PartialEq for DefaultValue
+{
+    fn eq(&self, other: &Self) -> bool
+    {
+        match (self, other) {
+            //Compare bit patterns so that DefaultValue can implement Eq.
+            (DefaultValue::Float(a), DefaultValue::Float(b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.to_bits() == b.to_bits())
+            },
+            (DefaultValue::Int(a), DefaultValue::Int(b)) => a == b,
+            (DefaultValue::Uint(a), DefaultValue::Uint(b)) => a == b,
+            (DefaultValue::Bool(a), DefaultValue::Bool(b)) => a == b,
+            _ => false
+        }
+    }
+}
+
+impl Eq for DefaultValue {}
+
+#[derive(Debug, Clone)]
 pub struct Property<T = String>
 {
     pub ptype: PropertyType<T>,
     pub pname: String,
-    pub pattr: Option<Attribute>
+    pub pattr: Option<Attribute>,
+    /// Message of the `deprecated` attribute, empty when none is given.
+    pub deprecated: Option<String>,
+    /// Format hint of an output (`output vec4f Normal : TARGET_1 : RGBA16F;`), always None for other properties.
+    pub pformat: Option<TargetFormat>,
+    pub default: Option<DefaultValue>,
+    pub span: Span
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+//Spans only locate a node in the source code, two nodes parsed from different places are still equal.
+impl<T: PartialEq> PartialEq for Property<T>
+{
+    fn eq(&self, other: &Self) -> bool
+    {
+        self.ptype == other.ptype
+            && self.pname == other.pname
+            && self.pattr == other.pattr
+            && self.deprecated == other.deprecated
+            && self.pformat == other.pformat
+            && self.default == other.default
+    }
+}
+
+impl<T: Eq> Eq for Property<T> {}
+
+impl<T> Property<T>
+{
+    pub fn get_location(&self) -> Option<u32>
+    {
+        self.pattr.as_ref().and_then(|v| v.get_location())
+    }
+
+    /// Returns the number of consecutive vertex attribute locations used by this property; a
+    /// matrix uses one location per column.
+    pub fn get_location_count(&self) -> u32
+    {
+        match &self.ptype {
+            PropertyType::Matrix(v) => v.size as u32,
+            _ => 1
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Struct<T = String>
 {
     pub name: String,
     pub attr: Option<Attribute>,
-    pub props: Vec<Property<T>>
+    /// See [Property::deprecated].
+    pub deprecated: Option<String>,
+    pub props: Vec<Property<T>>,
+    pub span: Span
+}
+
+impl<T: PartialEq> PartialEq for Struct<T>
+{
+    fn eq(&self, other: &Self) -> bool
+    {
+        self.name == other.name && self.attr == other.attr && self.deprecated == other.deprecated
+            && self.props == other.props
+    }
+}
+
+impl<T: Eq> Eq for Struct<T> {}
+
+/// Integer constant declared with an initializer, such as `const uint MAX_LIGHTS = 32;`.
+#[derive(Debug, Clone)]
+pub struct ConstantInt
+{
+    pub name: String,
+    pub ty: BaseType,
+    pub value: i32,
+    pub span: Span
+}
+
+impl PartialEq for ConstantInt
+{
+    fn eq(&self, other: &Self) -> bool
+    {
+        self.name == other.name && self.ty == other.ty && self.value == other.value
+    }
 }
 
+impl Eq for ConstantInt {}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum RenderMode
 {
@@ -200,6 +506,18 @@ pub enum RenderMode
     Patches
 }
 
+impl RenderMode
+{
+    pub fn get_name(&self) -> &'static str
+    {
+        match self {
+            RenderMode::Triangles => "Triangles",
+            RenderMode::Wireframe => "Wireframe",
+            RenderMode::Patches => "Patches"
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum CullingMode
 {
@@ -208,17 +526,125 @@ pub enum CullingMode
     Disabled
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+impl CullingMode
+{
+    pub fn get_name(&self) -> &'static str
+    {
+        match self {
+            CullingMode::BackFace => "BackFace",
+            CullingMode::FrontFace => "FrontFace",
+            CullingMode::Disabled => "Disabled"
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CompareFunc
+{
+    Never,
+    Less,
+    Equal,
+    LessEqual,
+    Greater,
+    NotEqual,
+    GreaterEqual,
+    Always
+}
+
+impl CompareFunc
+{
+    pub fn get_name(&self) -> &'static str
+    {
+        match self {
+            CompareFunc::Never => "Never",
+            CompareFunc::Less => "Less",
+            CompareFunc::Equal => "Equal",
+            CompareFunc::LessEqual => "LessEqual",
+            CompareFunc::Greater => "Greater",
+            CompareFunc::NotEqual => "NotEqual",
+            CompareFunc::GreaterEqual => "GreaterEqual",
+            CompareFunc::Always => "Always"
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StencilOp
+{
+    Keep,
+    Zero,
+    Replace,
+    Increment,
+    IncrementWrap,
+    Decrement,
+    DecrementWrap,
+    Invert
+}
+
+impl StencilOp
+{
+    pub fn get_name(&self) -> &'static str
+    {
+        match self {
+            StencilOp::Keep => "Keep",
+            StencilOp::Zero => "Zero",
+            StencilOp::Replace => "Replace",
+            StencilOp::Increment => "Increment",
+            StencilOp::IncrementWrap => "IncrementWrap",
+            StencilOp::Decrement => "Decrement",
+            StencilOp::DecrementWrap => "DecrementWrap",
+            StencilOp::Invert => "Invert"
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct PipelineStatement
 {
     pub name: String,
     pub depth_enable: bool,
     pub depth_write_enable: bool,
+    pub depth_func: CompareFunc,
     pub scissor_enable: bool,
     pub render_mode: RenderMode,
-    pub culling_mode: CullingMode
+    pub culling_mode: CullingMode,
+    pub layered_rendering: bool,
+    pub viewport_count: u32,
+    pub stencil_enable: bool,
+    pub stencil_func: CompareFunc,
+    pub stencil_pass_op: StencilOp,
+    pub stencil_fail_op: StencilOp,
+    pub stencil_ref: u32,
+    pub depth_bias: f32,
+    pub slope_scaled_depth_bias: f32
 }
 
+impl PartialEq for PipelineStatement
+{
+    fn eq(&self, other: &Self) -> bool
+    {
+        self.name == other.name
+            && self.depth_enable == other.depth_enable
+            && self.depth_write_enable == other.depth_write_enable
+            && self.depth_func == other.depth_func
+            && self.scissor_enable == other.scissor_enable
+            && self.render_mode == other.render_mode
+            && self.culling_mode == other.culling_mode
+            && self.layered_rendering == other.layered_rendering
+            && self.viewport_count == other.viewport_count
+            && self.stencil_enable == other.stencil_enable
+            && self.stencil_func == other.stencil_func
+            && self.stencil_pass_op == other.stencil_pass_op
+            && self.stencil_fail_op == other.stencil_fail_op
+            && self.stencil_ref == other.stencil_ref
+            //Compare bit patterns so that PipelineStatement can implement Eq.
+            && self.depth_bias.to_bits() == other.depth_bias.to_bits()
+            && self.slope_scaled_depth_bias.to_bits() == other.slope_scaled_depth_bias.to_bits()
+    }
+}
+
+impl Eq for PipelineStatement {}
+
 impl VarlistStatement for PipelineStatement
 {
     fn new(name: String) -> Self
@@ -227,9 +653,27 @@ impl VarlistStatement for PipelineStatement
             name,
             depth_enable: true,
             depth_write_enable: true,
+            depth_func: CompareFunc::Less,
             scissor_enable: false,
             render_mode: RenderMode::Triangles,
-            culling_mode: CullingMode::BackFace
+            culling_mode: CullingMode::BackFace,
+            layered_rendering: false,
+            viewport_count: 1,
+            stencil_enable: false,
+            stencil_func: CompareFunc::Always,
+            stencil_pass_op: StencilOp::Keep,
+            stencil_fail_op: StencilOp::Keep,
+            stencil_ref: 0,
+            depth_bias: 0.0,
+            slope_scaled_depth_bias: 0.0
+        }
+    }
+
+    fn inherit(name: String, base: Self) -> Self
+    {
+        PipelineStatement {
+            name,
+            ..base
         }
     }
 }
@@ -254,6 +698,30 @@ pub enum BlendFactor
     OneMinusSrc1Alpha
 }
 
+impl BlendFactor
+{
+    pub fn get_name(&self) -> &'static str
+    {
+        match self {
+            BlendFactor::Zero => "Zero",
+            BlendFactor::One => "One",
+            BlendFactor::SrcColor => "SrcColor",
+            BlendFactor::OneMinusSrcColor => "OneMinusSrcColor",
+            BlendFactor::SrcAlpha => "SrcAlpha",
+            BlendFactor::OneMinusSrcAlpha => "OneMinusSrcAlpha",
+            BlendFactor::DstColor => "DstColor",
+            BlendFactor::OneMinusDstColor => "OneMinusDstColor",
+            BlendFactor::DstAlpha => "DstAlpha",
+            BlendFactor::OneMinusDstAlpha => "OneMinusDstAlpha",
+            BlendFactor::SrcAlphaSaturate => "SrcAlphaSaturate",
+            BlendFactor::Src1Color => "Src1Color",
+            BlendFactor::OneMinusSrc1Color => "OneMinusSrc1Color",
+            BlendFactor::Src1Alpha => "Src1Alpha",
+            BlendFactor::OneMinusSrc1Alpha => "OneMinusSrc1Alpha"
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum BlendOperator
 {
@@ -264,6 +732,28 @@ pub enum BlendOperator
     Max
 }
 
+impl BlendOperator
+{
+    pub fn get_name(&self) -> &'static str
+    {
+        match self {
+            BlendOperator::Add => "Add",
+            BlendOperator::Subtract => "Sub",
+            BlendOperator::InverseSubtract => "InvSub",
+            BlendOperator::Min => "Min",
+            BlendOperator::Max => "Max"
+        }
+    }
+}
+
+/// A binary file embedded in the shader pack under the given name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResourceStatement
+{
+    pub name: String,
+    pub path: String
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct BlendfuncStatement
 {
@@ -290,17 +780,105 @@ impl VarlistStatement for BlendfuncStatement
             alpha_op: BlendOperator::Add
         }
     }
+
+    fn inherit(name: String, base: Self) -> Self
+    {
+        BlendfuncStatement {
+            name,
+            ..base
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Filter
+{
+    Nearest,
+    Linear
+}
+
+impl Filter
+{
+    pub fn get_name(&self) -> &'static str
+    {
+        match self {
+            Filter::Nearest => "Nearest",
+            Filter::Linear => "Linear"
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AddressMode
+{
+    Repeat,
+    MirroredRepeat,
+    Clamp
+}
+
+impl AddressMode
+{
+    pub fn get_name(&self) -> &'static str
+    {
+        match self {
+            AddressMode::Repeat => "Repeat",
+            AddressMode::MirroredRepeat => "MirroredRepeat",
+            AddressMode::Clamp => "Clamp"
+        }
+    }
+}
+
+/// Filtering and addressing of the Sampler constant with the same name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SamplerStateStatement
+{
+    pub name: String,
+    pub min_filter: Filter,
+    pub mag_filter: Filter,
+    pub address_u: AddressMode,
+    pub address_v: AddressMode,
+    pub address_w: AddressMode,
+    pub anisotropy: u32
+}
+
+impl VarlistStatement for SamplerStateStatement
+{
+    fn new(name: String) -> Self
+    {
+        SamplerStateStatement {
+            name,
+            min_filter: Filter::Linear,
+            mag_filter: Filter::Linear,
+            address_u: AddressMode::Repeat,
+            address_v: AddressMode::Repeat,
+            address_w: AddressMode::Repeat,
+            anisotropy: 1
+        }
+    }
+
+    fn inherit(name: String, base: Self) -> Self
+    {
+        SamplerStateStatement {
+            name,
+            ..base
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Statement<T = String>
 {
     Constant(Property<T>),
+    ConstantInt(ConstantInt),
     ConstantBuffer(Struct<T>),
     Output(Property<T>),
+    Varying(Property<T>),
     VertexFormat(Struct<T>),
+    RootConstants(Struct<T>),
     Pipeline(PipelineStatement),
     Blendfunc(BlendfuncStatement),
+    SamplerState(SamplerStateStatement),
+    Resource(ResourceStatement),
     Noop // Used to represent a statement to ignore in the parse tree
 }
 
@@ -310,12 +888,30 @@ impl<T> Statement<T>
     {
         match self {
             Statement::Constant(v) => Some(&v.pname),
+            Statement::ConstantInt(v) => Some(&v.name),
             Statement::ConstantBuffer(v) => Some(&v.name),
             Statement::Output(v) => Some(&v.pname),
+            Statement::Varying(v) => Some(&v.pname),
             Statement::VertexFormat(v) => Some(&v.name),
+            Statement::RootConstants(v) => Some(&v.name),
             Statement::Pipeline(v) => Some(&v.name),
             Statement::Blendfunc(v) => Some(&v.name),
+            Statement::SamplerState(v) => Some(&v.name),
+            Statement::Resource(v) => Some(&v.name),
             Statement::Noop => None
         }
     }
+
+    pub fn get_deprecation(&self) -> Option<&str>
+    {
+        match self {
+            Statement::Constant(v) => v.deprecated.as_deref(),
+            Statement::ConstantBuffer(v) => v.deprecated.as_deref(),
+            Statement::Output(v) => v.deprecated.as_deref(),
+            Statement::Varying(v) => v.deprecated.as_deref(),
+            Statement::VertexFormat(v) => v.deprecated.as_deref(),
+            Statement::RootConstants(v) => v.deprecated.as_deref(),
+            _ => None
+        }
+    }
 }
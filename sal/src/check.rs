@@ -0,0 +1,305 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Standalone validation of SAL library modules.
+//!
+//! Modules are only parsed when a shader imports one of their members, so errors in unused
+//! members go unnoticed and errors in used members are reported at the importing shader.
+//! [check_module] validates every member of a module against the rules of the contexts it may
+//! be imported into and reports errors at the module's own lines.
+
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+
+use crate::ast::error::Error;
+use crate::ast::tree::{Attribute, BlendfuncStatement, ConstantInt, PipelineStatement, Property, ResourceStatement, SamplerStateStatement, Span, Struct};
+use crate::ast::{AstBuilder, BaseStruct, RefResolver, Visitor};
+use crate::lexer::Lexer;
+use crate::parser::error::ParserOrVisitor;
+use crate::parser::Parser;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic
+{
+    pub file: String,
+    pub line: usize,
+    pub col: usize,
+    pub message: String
+}
+
+impl Display for Diagnostic
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+    {
+        write!(f, "{}:{}:{}: {}", self.file, self.line, self.col, self.message)
+    }
+}
+
+#[derive(Debug)]
+enum ModuleError
+{
+    Use(Vec<String>, String),
+    Duplicate(String)
+}
+
+impl Display for ModuleError
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+    {
+        match self {
+            ModuleError::Use(path, member) => write!(f, "use of {}::{} in a module is ignored: modules cannot import", path.join("::"), member),
+            ModuleError::Duplicate(name) => write!(f, "member '{}' is declared more than once; only the first one can be imported", name)
+        }
+    }
+}
+
+/// Self-contained resolver: references only resolve to what the module itself declares.
+#[derive(Default)]
+struct ModuleAst
+{
+    members: HashSet<String>,
+    packed_structs: HashSet<String>,
+    //Structs with whether they are a vertex format
+    structs: Vec<(Struct<String>, bool)>,
+    pipelines: Vec<PipelineStatement>
+}
+
+impl ModuleAst
+{
+    fn declare(&mut self, name: &str) -> Result<(), ModuleError>
+    {
+        if !self.members.insert(name.into()) {
+            return Err(ModuleError::Duplicate(name.into()));
+        }
+        Ok(())
+    }
+}
+
+impl RefResolver for ModuleAst
+{
+    type Key = String;
+
+    fn resolve_struct_ref(&self, name: &str) -> Option<Self::Key>
+    {
+        self.packed_structs.get(name).cloned()
+    }
+
+    fn resolve_pipeline(&self, name: &str) -> Option<PipelineStatement>
+    {
+        self.pipelines.iter().find(|v| v.name == name).cloned()
+    }
+
+    fn resolve_struct(&self, name: &str) -> Option<BaseStruct<Self::Key>>
+    {
+        self.structs.iter().find(|(v, _)| v.name == name).map(|(v, vertex_format)| BaseStruct {
+            props: v.props.clone(),
+            vertex_format: *vertex_format
+        })
+    }
+}
+
+struct ModuleVisitor;
+
+impl Visitor<ModuleAst> for ModuleVisitor
+{
+    type Error = ModuleError;
+
+    fn visit_constant(&mut self, ast: &mut ModuleAst, val: Property<String>) -> Result<(), Self::Error>
+    {
+        ast.declare(&val.pname)
+    }
+
+    fn visit_constant_int(&mut self, ast: &mut ModuleAst, val: ConstantInt) -> Result<(), Self::Error>
+    {
+        ast.declare(&val.name)
+    }
+
+    fn visit_output(&mut self, ast: &mut ModuleAst, val: Property<String>) -> Result<(), Self::Error>
+    {
+        ast.declare(&val.pname)
+    }
+
+    fn visit_varying(&mut self, ast: &mut ModuleAst, val: Property<String>) -> Result<(), Self::Error>
+    {
+        ast.declare(&val.pname)
+    }
+
+    fn visit_constant_buffer(&mut self, ast: &mut ModuleAst, val: Struct<String>) -> Result<(), Self::Error>
+    {
+        ast.declare(&val.name)?;
+        if val.attr == Some(Attribute::Pack) {
+            ast.packed_structs.insert(val.name.clone());
+        }
+        ast.structs.push((val, false));
+        Ok(())
+    }
+
+    fn visit_vertex_format(&mut self, ast: &mut ModuleAst, val: Struct<String>) -> Result<(), Self::Error>
+    {
+        ast.declare(&val.name)?;
+        ast.structs.push((val, true));
+        Ok(())
+    }
+
+    fn visit_root_constants(&mut self, ast: &mut ModuleAst, val: Struct<String>) -> Result<(), Self::Error>
+    {
+        ast.declare(&val.name)?;
+        ast.structs.push((val, false));
+        Ok(())
+    }
+
+    fn visit_pipeline(&mut self, ast: &mut ModuleAst, val: PipelineStatement) -> Result<(), Self::Error>
+    {
+        ast.declare(&val.name)?;
+        ast.pipelines.push(val);
+        Ok(())
+    }
+
+    fn visit_blendfunc(&mut self, ast: &mut ModuleAst, val: BlendfuncStatement) -> Result<(), Self::Error>
+    {
+        ast.declare(&val.name)
+    }
+
+    fn visit_samplerstate(&mut self, _: &mut ModuleAst, _: SamplerStateStatement) -> Result<(), Self::Error>
+    {
+        //A sampler state shares the name of its sampler and cannot be imported on its own.
+        Ok(())
+    }
+
+    fn visit_resource(&mut self, ast: &mut ModuleAst, val: ResourceStatement) -> Result<(), Self::Error>
+    {
+        ast.declare(&val.name)
+    }
+
+    fn visit_noop(&mut self, _: &mut ModuleAst) -> Result<(), Self::Error>
+    {
+        Ok(())
+    }
+
+    fn visit_use(&mut self, _: &mut ModuleAst, path: Vec<String>, member: String, _: Span) -> Result<(), Self::Error>
+    {
+        Err(ModuleError::Use(path, member))
+    }
+}
+
+/// Checks all members of a SAL module, returning one diagnostic per invalid member. Checking
+/// stops at the first syntax error as the rest of the module cannot be delimited reliably.
+pub fn check_module<T: AsRef<[u8]>>(file: &str, source: T) -> Vec<Diagnostic>
+{
+    let diagnostic = |line, col, message| Diagnostic {
+        file: file.into(),
+        line,
+        col,
+        message
+    };
+    let mut lexer = Lexer::new();
+    if let Err(e) = lexer.process(source.as_ref()) {
+        return vec![diagnostic(e.line, e.col, format!("lexer error: {:?}", e.etype))];
+    }
+    let mut diagnostics = Vec::new();
+    let mut parser = Parser::new(lexer);
+    let mut builder = AstBuilder::new(ModuleAst::default(), ModuleVisitor);
+    loop {
+        match parser.parse(&mut builder) {
+            Ok(_) => break,
+            Err(ParserOrVisitor::Parser(e)) => {
+                diagnostics.push(diagnostic(e.line, e.col, format!("parser error: {}", e.etype)));
+                break;
+            },
+            Err(ParserOrVisitor::Panic(e)) => {
+                diagnostics.push(diagnostic(e.line, e.col, format!("internal error: visitor panicked: {}", e.message)));
+                break;
+            },
+            //The statement has been consumed: resume with the next member.
+            Err(ParserOrVisitor::Visitor(e)) => {
+                let (line, col) = parser.statement_position();
+                let message = match e {
+                    Error::Visitor(e) => e.to_string(),
+                    e => e.to_string()
+                };
+                diagnostics.push(diagnostic(line, col, message));
+            }
+        }
+    }
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::check::check_module;
+
+    #[test]
+    fn valid_module()
+    {
+        let source = b"
+            const struct Light : Pack
+            {
+                vec4f Color;
+                vec3f Direction;
+            }
+            const struct Lighting
+            {
+                Light Sun;
+                float Ambient;
+            }
+            const Texture2D:vec4f ShadowMap;
+            pipeline OpaqueDefault { CullingMode = BackFace; }
+            pipeline Transparent : OpaqueDefault { DepthWriteEnable = false; }
+        ";
+        assert_eq!(check_module("lighting.sal", source), Vec::new());
+    }
+
+    #[test]
+    fn invalid_members()
+    {
+        let source = b"const struct Camera
+{
+    mat4f View;
+}
+const struct Material
+{
+    vec4f BaseColor;
+    Texture2D:vec4f Albedo;
+}
+const struct Camera { float Fov; }
+use Other::Thing;
+const Missing Broken;
+";
+        let diagnostics = check_module("common.sal", source);
+        let lines: Vec<usize> = diagnostics.iter().map(|v| v.line).collect();
+        assert_eq!(lines, [5, 10, 11, 12]);
+        assert!(diagnostics.iter().all(|v| v.file == "common.sal"));
+        assert!(diagnostics[0].to_string().starts_with("common.sal:5:"));
+        assert!(diagnostics[0].message.contains("forbidden property type"));
+        assert!(diagnostics[1].message.contains("'Camera'"));
+        let diagnostics = check_module("broken.sal", b"const struct Camera { mat4f View; }\nconst float");
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].message.starts_with("parser error"));
+    }
+}
@@ -0,0 +1,747 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+
+//! Pretty-printer for SAL and builders to construct AST nodes without going through the parser.
+
+use std::fmt::Display;
+
+use crate::ast::tree::{
+    ArrayItemType,
+    Attribute,
+    BlendfuncStatement,
+    CompareFunc,
+    ConstantInt,
+    CullingMode,
+    DefaultValue,
+    PipelineStatement,
+    Property,
+    PropertyType,
+    RenderMode,
+    SamplerStateStatement,
+    Statement,
+    StencilOp,
+    Struct,
+    TextureType,
+    VarlistStatement
+};
+
+const INDENT: &str = "    ";
+
+fn texture_subtype(t: &TextureType) -> String
+{
+    match t {
+        TextureType::Scalar(s) => s.get_name().into(),
+        TextureType::Vector(v) => format!("vec{}{}", v.size, v.item.get_suffix())
+    }
+}
+
+fn texture_type(name: &str, t: &TextureType) -> String
+{
+    format!("{}:{}", name, texture_subtype(t))
+}
+
+fn array_item_type<T: Display>(item: &ArrayItemType<T>) -> String
+{
+    match item {
+        ArrayItemType::Vector(v) => format!("vec{}{}", v.size, v.item.get_suffix()),
+        ArrayItemType::Matrix(m) => format!("mat{}{}", m.size, m.item.get_suffix()),
+        ArrayItemType::StructRef(s) => s.to_string(),
+        ArrayItemType::Texture2D(t) => texture_type("Texture2D", t),
+        ArrayItemType::Texture3D(t) => texture_type("Texture3D", t),
+        ArrayItemType::TextureCube(t) => texture_type("TextureCube", t)
+    }
+}
+
+/// Returns the type of a property as written in SAL code, array size included.
+pub fn property_type<T: Display>(ptype: &PropertyType<T>) -> String
+{
+    match ptype {
+        PropertyType::Scalar(s) => s.get_name().into(),
+        PropertyType::Vector(v) => format!("vec{}{}", v.size, v.item.get_suffix()),
+        PropertyType::Matrix(m) => format!("mat{}{}", m.size, m.item.get_suffix()),
+        PropertyType::Sampler => "Sampler".into(),
+        PropertyType::SamplerCmp => "SamplerCmp".into(),
+        PropertyType::Texture1D(t) => texture_type("Texture1D", t),
+        PropertyType::Texture2D(t) => texture_type("Texture2D", t),
+        PropertyType::Texture3D(t) => texture_type("Texture3D", t),
+        PropertyType::Texture2DArray(t) => texture_type("Texture2DArray", t),
+        PropertyType::TextureCube(t) => texture_type("TextureCube", t),
+        PropertyType::Texture2DMS(t, samples) => format!("{}:{}", texture_type("Texture2DMS", t), samples),
+        PropertyType::StructRef(s) => s.to_string(),
+        PropertyType::Array(a) => format!("{}[{}]", array_item_type(&a.item), a.size)
+    }
+}
+
+fn string(value: &str) -> String
+{
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Returns an attribute as written in SAL code, without the leading colon.
+pub fn attribute(attr: &Attribute) -> String
+{
+    match attr {
+        Attribute::Identifier(name) => name.clone(),
+        Attribute::Order(order) => format!("ORDER_{}", order),
+        Attribute::Pack => "Pack".into(),
+        Attribute::Shared(None) => "Shared".into(),
+        Attribute::Shared(Some(slot)) => format!("SHARED_{}", slot),
+        Attribute::Semantic(name) => format!("SEMANTIC_{}", name),
+        Attribute::Interpolation(i) => i.get_name().into(),
+        Attribute::Location(location) => format!("LOCATION_{}", location),
+        Attribute::Target(target) => format!("TARGET_{}", target)
+    }
+}
+
+fn deprecation(msg: &str) -> String
+{
+    match msg.is_empty() {
+        true => "deprecated".into(),
+        false => format!("deprecated({})", string(msg))
+    }
+}
+
+//Float literals are lexed as f32 and never use an exponent; values which are exact f32 are printed
+//with the shortest digits reading back the same f32.
+fn float(value: f64) -> String
+{
+    let mut s = match value as f32 {
+        v if v as f64 == value => v.to_string(),
+        _ => value.to_string()
+    };
+    if !s.contains('.') {
+        s.push_str(".0");
+    }
+    s
+}
+
+fn default_values(value: &DefaultValue) -> Vec<String>
+{
+    match value {
+        DefaultValue::Float(v) => v.iter().map(|v| float(*v)).collect(),
+        DefaultValue::Int(v) => v.iter().map(|v| v.to_string()).collect(),
+        DefaultValue::Uint(v) => v.iter().map(|v| v.to_string()).collect(),
+        DefaultValue::Bool(v) => v.iter().map(|v| v.to_string()).collect()
+    }
+}
+
+fn default_value<T: Display>(ptype: &PropertyType<T>, value: &DefaultValue) -> String
+{
+    let values = default_values(value);
+    match ptype {
+        PropertyType::Vector(_) => format!("{}({})", property_type(ptype), values.join(", ")),
+        _ => values.join(", ")
+    }
+}
+
+/// Returns a property as written in SAL code, without the statement keyword.
+///
+/// The format hint of an output is only printed after an attribute, as SAL has no syntax for a
+/// format without an attribute.
+pub fn property<T: Display>(prop: &Property<T>) -> String
+{
+    let mut s = format!("{} {}", property_type(&prop.ptype), prop.pname);
+    if let Some(attr) = &prop.pattr {
+        s.push_str(" : ");
+        s.push_str(&attribute(attr));
+    }
+    if let Some(msg) = &prop.deprecated {
+        s.push_str(" : ");
+        s.push_str(&deprecation(msg));
+    }
+    if let Some(format) = prop.pformat.as_ref().filter(|_| prop.pattr.is_some() || prop.deprecated.is_some()) {
+        s.push_str(" : ");
+        s.push_str(format.get_name());
+    }
+    if let Some(value) = &prop.default {
+        s.push_str(" = ");
+        s.push_str(&default_value(&prop.ptype, value));
+    }
+    s.push(';');
+    s
+}
+
+fn block(header: String, lines: impl Iterator<Item = String>) -> String
+{
+    let mut s = header;
+    s.push_str("\n{\n");
+    for line in lines {
+        s.push_str(INDENT);
+        s.push_str(&line);
+        s.push('\n');
+    }
+    s.push_str("}\n");
+    s
+}
+
+fn structure<T: Display>(keyword: &str, st: &Struct<T>) -> String
+{
+    let mut header = match &st.attr {
+        Some(attr) => format!("{} struct {} : {}", keyword, st.name, attribute(attr)),
+        None => format!("{} struct {}", keyword, st.name)
+    };
+    if let Some(msg) = &st.deprecated {
+        header.push_str(" : ");
+        header.push_str(&deprecation(msg));
+    }
+    block(header, st.props.iter().map(property))
+}
+
+fn varlist(keyword: &str, name: &str, vars: &[(&str, String)]) -> String
+{
+    block(format!("{} {}", keyword, name), vars.iter().map(|(name, value)| format!("{} = {};", name, value)))
+}
+
+fn pipeline(p: &PipelineStatement) -> String
+{
+    varlist("pipeline", &p.name, &[
+        ("DepthEnable", p.depth_enable.to_string()),
+        ("DepthWriteEnable", p.depth_write_enable.to_string()),
+        ("DepthFunc", p.depth_func.get_name().into()),
+        ("ScissorEnable", p.scissor_enable.to_string()),
+        ("RenderMode", p.render_mode.get_name().into()),
+        ("CullingMode", p.culling_mode.get_name().into()),
+        ("LayeredRendering", p.layered_rendering.to_string()),
+        ("ViewportCount", p.viewport_count.to_string()),
+        ("StencilEnable", p.stencil_enable.to_string()),
+        ("StencilFunc", p.stencil_func.get_name().into()),
+        ("StencilPassOp", p.stencil_pass_op.get_name().into()),
+        ("StencilFailOp", p.stencil_fail_op.get_name().into()),
+        ("StencilRef", p.stencil_ref.to_string()),
+        ("DepthBias", float(p.depth_bias as f64)),
+        ("SlopeScaledDepthBias", float(p.slope_scaled_depth_bias as f64))
+    ])
+}
+
+fn blendfunc(b: &BlendfuncStatement) -> String
+{
+    varlist("blendfunc", &b.name, &[
+        ("SrcColor", b.src_color.get_name().into()),
+        ("DstColor", b.dst_color.get_name().into()),
+        ("SrcAlpha", b.src_alpha.get_name().into()),
+        ("DstAlpha", b.dst_alpha.get_name().into()),
+        ("ColorOp", b.color_op.get_name().into()),
+        ("AlphaOp", b.alpha_op.get_name().into())
+    ])
+}
+
+fn samplerstate(s: &SamplerStateStatement) -> String
+{
+    varlist("samplerstate", &s.name, &[
+        ("MinFilter", s.min_filter.get_name().into()),
+        ("MagFilter", s.mag_filter.get_name().into()),
+        ("AddressU", s.address_u.get_name().into()),
+        ("AddressV", s.address_v.get_name().into()),
+        ("AddressW", s.address_w.get_name().into()),
+        ("Anisotropy", s.anisotropy.to_string())
+    ])
+}
+
+fn constant_int(c: &ConstantInt) -> String
+{
+    format!("const {} {} = {};\n", c.ty.get_name(), c.name, c.value)
+}
+
+/// Returns a statement as written in SAL code; [Noop](Statement::Noop) prints as an empty string.
+pub fn statement<T: Display>(stmt: &Statement<T>) -> String
+{
+    match stmt {
+        Statement::Constant(p) => format!("const {}\n", property(p)),
+        Statement::ConstantInt(c) => constant_int(c),
+        Statement::ConstantBuffer(s) => structure("const", s),
+        Statement::Output(p) => format!("output {}\n", property(p)),
+        Statement::Varying(p) => format!("varying {}\n", property(p)),
+        Statement::VertexFormat(s) => structure("vformat", s),
+        Statement::RootConstants(s) => structure("rootconst", s),
+        Statement::Pipeline(p) => pipeline(p),
+        Statement::Blendfunc(b) => blendfunc(b),
+        Statement::SamplerState(s) => samplerstate(s),
+        Statement::Resource(r) => format!("resource {} as {};\n", string(&r.path), r.name),
+        Statement::Noop => String::new()
+    }
+}
+
+fn is_block<T>(stmt: &Statement<T>) -> bool
+{
+    matches!(stmt, Statement::ConstantBuffer(_)
+        | Statement::VertexFormat(_)
+        | Statement::RootConstants(_)
+        | Statement::Pipeline(_)
+        | Statement::Blendfunc(_)
+        | Statement::SamplerState(_))
+}
+
+/// Prints statements as SAL code in canonical formatting: one statement per line, blocks separated
+/// by an empty line and indented by 4 spaces, every variable of a varlist in a fixed order.
+///
+/// Parsing the result gives back the same statements, except for [Noop](Statement::Noop) which
+/// prints nothing. Base structs and pipelines are not printed: members and variables are written
+/// out in full. Floats must be finite as SAL has no syntax for infinities and NaN.
+pub fn to_sal_string<T: Display>(statements: &[Statement<T>]) -> String
+{
+    let mut s = String::new();
+    let mut prev: Option<&Statement<T>> = None;
+    for stmt in statements.iter().filter(|v| !matches!(v, Statement::Noop)) {
+        if prev.map(|v| is_block(v) || is_block(stmt)).unwrap_or(false) {
+            s.push('\n');
+        }
+        s.push_str(&statement(stmt));
+        prev = Some(stmt);
+    }
+    s
+}
+
+/// Builds a [Struct] to be used as a constant buffer, vertex format or root constants statement.
+pub struct StructBuilder<T = String>
+{
+    inner: Struct<T>
+}
+
+impl<T> StructBuilder<T>
+{
+    pub fn new(name: impl Into<String>) -> StructBuilder<T>
+    {
+        StructBuilder {
+            inner: Struct {
+                name: name.into(),
+                attr: None,
+                deprecated: None,
+                props: Vec::new(),
+                span: Default::default()
+            }
+        }
+    }
+
+    pub fn attr(mut self, attr: Attribute) -> Self
+    {
+        self.inner.attr = Some(attr);
+        self
+    }
+
+    pub fn prop(self, ptype: PropertyType<T>, pname: impl Into<String>) -> Self
+    {
+        self.prop_with(ptype, pname, None, None)
+    }
+
+    pub fn prop_with(mut self, ptype: PropertyType<T>, pname: impl Into<String>, pattr: Option<Attribute>, default: Option<DefaultValue>) -> Self
+    {
+        self.inner.props.push(Property {
+            ptype,
+            pname: pname.into(),
+            pattr,
+            deprecated: None,
+            pformat: None,
+            default,
+            span: Default::default()
+        });
+        self
+    }
+
+    pub fn build(self) -> Struct<T>
+    {
+        self.inner
+    }
+}
+
+/// Builds a [PipelineStatement]; variables which are not set keep the SAL defaults.
+pub struct PipelineBuilder
+{
+    inner: PipelineStatement
+}
+
+macro_rules! pipeline_setters {
+    ($($name: ident: $ty: ty),*) => {
+        $(
+            pub fn $name(mut self, value: $ty) -> Self
+            {
+                self.inner.$name = value;
+                self
+            }
+        )*
+    };
+}
+
+impl PipelineBuilder
+{
+    pub fn new(name: impl Into<String>) -> PipelineBuilder
+    {
+        PipelineBuilder {
+            inner: PipelineStatement::new(name.into())
+        }
+    }
+
+    /// Starts from the variables of a base pipeline, like `pipeline Name : Base`.
+    pub fn inherit(name: impl Into<String>, base: PipelineStatement) -> PipelineBuilder
+    {
+        PipelineBuilder {
+            inner: PipelineStatement::inherit(name.into(), base)
+        }
+    }
+
+    pipeline_setters!(
+        depth_enable: bool,
+        depth_write_enable: bool,
+        depth_func: CompareFunc,
+        scissor_enable: bool,
+        render_mode: RenderMode,
+        culling_mode: CullingMode,
+        layered_rendering: bool,
+        viewport_count: u32,
+        stencil_enable: bool,
+        stencil_func: CompareFunc,
+        stencil_pass_op: StencilOp,
+        stencil_fail_op: StencilOp,
+        stencil_ref: u32,
+        depth_bias: f32,
+        slope_scaled_depth_bias: f32
+    );
+
+    pub fn build(self) -> PipelineStatement
+    {
+        self.inner
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::ast::{BaseStruct, RefResolver, Strictness, Visitor};
+    use crate::ast::tree::{
+        AddressMode,
+        ArrayType,
+        BaseType,
+        BlendFactor,
+        BlendOperator,
+        Filter,
+        Interpolation,
+        ResourceStatement,
+        Span,
+        TargetFormat,
+        VectorType
+    };
+    use crate::utils::{auto_lexer_parser, AutoError};
+
+    struct Fixture(Vec<Statement>);
+
+    impl RefResolver for Fixture {
+        type Key = String;
+
+        fn resolve_struct_ref(&self, name: &str) -> Option<Self::Key> {
+            Some(name.into())
+        }
+
+        fn resolve_struct(&self, name: &str) -> Option<BaseStruct<Self::Key>> {
+            self.0.iter().find_map(|v| match v {
+                Statement::ConstantBuffer(s) | Statement::RootConstants(s) if s.name == name => Some(BaseStruct {
+                    props: s.props.clone(),
+                    vertex_format: false
+                }),
+                Statement::VertexFormat(s) if s.name == name => Some(BaseStruct {
+                    props: s.props.clone(),
+                    vertex_format: true
+                }),
+                _ => None
+            })
+        }
+
+        fn resolve_pipeline(&self, name: &str) -> Option<PipelineStatement> {
+            self.0.iter().find_map(|v| match v {
+                Statement::Pipeline(p) if p.name == name => Some(p.clone()),
+                _ => None
+            })
+        }
+    }
+
+    struct Collector;
+
+    impl Visitor<Fixture> for Collector {
+        type Error = ();
+
+        fn visit_constant(&mut self, ast: &mut Fixture, val: Property) -> Result<(), Self::Error> {
+            ast.0.push(Statement::Constant(val));
+            Ok(())
+        }
+
+        fn visit_constant_int(&mut self, ast: &mut Fixture, val: ConstantInt) -> Result<(), Self::Error> {
+            ast.0.push(Statement::ConstantInt(val));
+            Ok(())
+        }
+
+        fn visit_output(&mut self, ast: &mut Fixture, val: Property) -> Result<(), Self::Error> {
+            ast.0.push(Statement::Output(val));
+            Ok(())
+        }
+
+        fn visit_varying(&mut self, ast: &mut Fixture, val: Property) -> Result<(), Self::Error> {
+            ast.0.push(Statement::Varying(val));
+            Ok(())
+        }
+
+        fn visit_constant_buffer(&mut self, ast: &mut Fixture, val: Struct) -> Result<(), Self::Error> {
+            ast.0.push(Statement::ConstantBuffer(val));
+            Ok(())
+        }
+
+        fn visit_vertex_format(&mut self, ast: &mut Fixture, val: Struct) -> Result<(), Self::Error> {
+            ast.0.push(Statement::VertexFormat(val));
+            Ok(())
+        }
+
+        fn visit_root_constants(&mut self, ast: &mut Fixture, val: Struct) -> Result<(), Self::Error> {
+            ast.0.push(Statement::RootConstants(val));
+            Ok(())
+        }
+
+        fn visit_pipeline(&mut self, ast: &mut Fixture, val: PipelineStatement) -> Result<(), Self::Error> {
+            ast.0.push(Statement::Pipeline(val));
+            Ok(())
+        }
+
+        fn visit_blendfunc(&mut self, ast: &mut Fixture, val: BlendfuncStatement) -> Result<(), Self::Error> {
+            ast.0.push(Statement::Blendfunc(val));
+            Ok(())
+        }
+
+        fn visit_samplerstate(&mut self, ast: &mut Fixture, val: SamplerStateStatement) -> Result<(), Self::Error> {
+            ast.0.push(Statement::SamplerState(val));
+            Ok(())
+        }
+
+        fn visit_resource(&mut self, ast: &mut Fixture, val: ResourceStatement) -> Result<(), Self::Error> {
+            ast.0.push(Statement::Resource(val));
+            Ok(())
+        }
+
+        fn visit_noop(&mut self, ast: &mut Fixture) -> Result<(), Self::Error> {
+            ast.0.push(Statement::Noop);
+            Ok(())
+        }
+
+        fn visit_use(&mut self, ast: &mut Fixture, _: Vec<String>, _: String, _: Span) -> Result<(), Self::Error> {
+            self.visit_noop(ast)
+        }
+    }
+
+    fn parse(code: &[u8]) -> Result<Vec<Statement>, AutoError<String, ()>>
+    {
+        let ast = auto_lexer_parser(code, Fixture(Vec::new()), Collector, Strictness::Default)?;
+        Ok(ast.0.into_iter().filter(|v| !matches!(v, Statement::Noop)).collect())
+    }
+
+    fn assert_round_trip(statements: &[Statement])
+    {
+        let code = to_sal_string(statements);
+        let parsed = parse(code.as_bytes()).unwrap_or_else(|e| panic!("{}\n{}", e, code));
+        assert_eq!(parsed, statements, "{}", code);
+    }
+
+    // Extracts the byte string literals of the test module of a source file.
+    fn fixtures(source: &str) -> Vec<Vec<u8>>
+    {
+        let source = &source[source.find("#[cfg(test)]").unwrap()..];
+        let chars: Vec<char> = source.chars().collect();
+        let mut fixtures = Vec::new();
+        let mut i = 1;
+        while i + 1 < chars.len() {
+            let prev = chars[i - 1];
+            if chars[i] != 'b' || chars[i + 1] != '"' || prev.is_alphanumeric() || prev == '_' || prev == '"' {
+                i += 1;
+                continue;
+            }
+            let mut fixture = String::new();
+            i += 2;
+            while chars[i] != '"' {
+                if chars[i] == '\\' {
+                    i += 1;
+                    match chars[i] {
+                        'n' => fixture.push('\n'),
+                        't' => fixture.push('\t'),
+                        c => fixture.push(c)
+                    }
+                } else {
+                    fixture.push(chars[i]);
+                }
+                i += 1;
+            }
+            fixtures.push(fixture.into_bytes());
+            i += 1;
+        }
+        fixtures
+    }
+
+    #[test]
+    fn round_trip_fixtures()
+    {
+        let mut count = 0;
+        for source in [include_str!("parser/core.rs"), include_str!("ast/core.rs")] {
+            //Fixtures of error cases don't build an AST and are skipped.
+            for statements in fixtures(source).iter().filter_map(|v| parse(v).ok()) {
+                assert_round_trip(&statements);
+                count += 1;
+            }
+        }
+        assert!(count >= 50, "only {} fixtures round-tripped", count);
+    }
+
+    #[test]
+    fn builders()
+    {
+        let vec4f = VectorType { item: BaseType::Float, size: 4 };
+        let statements = vec![
+            Statement::ConstantInt(ConstantInt { name: "MAX_LIGHTS".into(), ty: BaseType::Uint, value: 8, span: Default::default() }),
+            Statement::ConstantBuffer(StructBuilder::new("Material")
+                .attr(Attribute::Order(1))
+                .prop_with(PropertyType::Vector(vec4f), "BaseColor", None, Some(DefaultValue::Float(vec![1.0, 0.5, 0.0, 1.0])))
+                .prop_with(PropertyType::Scalar(BaseType::Float), "Roughness", Some(Attribute::Pack), Some(DefaultValue::Float(vec![0.25])))
+                .prop(PropertyType::Array(ArrayType { size: 8, item: ArrayItemType::Vector(vec4f) }), "Lights")
+                .build()),
+            Statement::Constant(Property {
+                ptype: PropertyType::Array(ArrayType { size: 4, item: ArrayItemType::Texture2D(TextureType::Vector(vec4f)) }),
+                pname: "Layers".into(),
+                pattr: Some(Attribute::Identifier("BaseSampler".into())),
+                deprecated: None,
+                pformat: None,
+                default: None,
+                span: Default::default()
+            }),
+            Statement::Constant(Property {
+                ptype: PropertyType::Texture2DMS(TextureType::Scalar(BaseType::Float), 4),
+                pname: "Depth".into(),
+                pattr: None,
+                deprecated: Some("use DepthMS".into()),
+                pformat: None,
+                default: None,
+                span: Default::default()
+            }),
+            Statement::Varying(Property {
+                ptype: PropertyType::Scalar(BaseType::Uint),
+                pname: "InstanceId".into(),
+                pattr: Some(Attribute::Interpolation(Interpolation::Flat)),
+                deprecated: None,
+                pformat: None,
+                default: None,
+                span: Default::default()
+            }),
+            Statement::Output(Property {
+                ptype: PropertyType::Vector(vec4f),
+                pname: "Color".into(),
+                pattr: Some(Attribute::Target(0)),
+                deprecated: Some(String::new()),
+                pformat: Some(TargetFormat::Rgba16F),
+                default: None,
+                span: Default::default()
+            }),
+            Statement::Pipeline(PipelineBuilder::new("Main")
+                .depth_func(CompareFunc::LessEqual)
+                .culling_mode(CullingMode::Disabled)
+                .depth_bias(-0.5)
+                .build()),
+            Statement::Blendfunc(BlendfuncStatement {
+                color_op: BlendOperator::InverseSubtract,
+                src_color: BlendFactor::SrcAlpha,
+                ..BlendfuncStatement::new("Color".into())
+            }),
+            Statement::SamplerState(SamplerStateStatement {
+                address_u: AddressMode::Clamp,
+                min_filter: Filter::Nearest,
+                ..SamplerStateStatement::new("BaseSampler".into())
+            }),
+            Statement::Resource(ResourceStatement { name: "CurveLut".into(), path: "luts/curves.bin".into() }),
+            Statement::Resource(ResourceStatement { name: "Notes".into(), path: "C:\\notes \"v2\".txt".into() })
+        ];
+        let expected = "const uint MAX_LIGHTS = 8;
+
+const struct Material : ORDER_1
+{
+    vec4f BaseColor = vec4f(1.0, 0.5, 0.0, 1.0);
+    float Roughness : Pack = 0.25;
+    vec4f[8] Lights;
+}
+
+const Texture2D:vec4f[4] Layers : BaseSampler;
+const Texture2DMS:float:4 Depth : deprecated(\"use DepthMS\");
+varying uint InstanceId : Flat;
+output vec4f Color : TARGET_0 : deprecated : RGBA16F;
+
+pipeline Main
+{
+    DepthEnable = true;
+    DepthWriteEnable = true;
+    DepthFunc = LessEqual;
+    ScissorEnable = false;
+    RenderMode = Triangles;
+    CullingMode = Disabled;
+    LayeredRendering = false;
+    ViewportCount = 1;
+    StencilEnable = false;
+    StencilFunc = Always;
+    StencilPassOp = Keep;
+    StencilFailOp = Keep;
+    StencilRef = 0;
+    DepthBias = -0.5;
+    SlopeScaledDepthBias = 0.0;
+}
+
+blendfunc Color
+{
+    SrcColor = SrcAlpha;
+    DstColor = Zero;
+    SrcAlpha = One;
+    DstAlpha = Zero;
+    ColorOp = InvSub;
+    AlphaOp = Add;
+}
+
+samplerstate BaseSampler
+{
+    MinFilter = Nearest;
+    MagFilter = Linear;
+    AddressU = Clamp;
+    AddressV = Repeat;
+    AddressW = Repeat;
+    Anisotropy = 1;
+}
+
+resource \"luts/curves.bin\" as CurveLut;
+resource \"C:\\\\notes \\\"v2\\\".txt\" as Notes;
+";
+        assert_eq!(to_sal_string(&statements), expected);
+        assert_round_trip(&statements);
+    }
+
+    #[test]
+    fn floats()
+    {
+        assert_eq!(float(0.1f32 as f64), "0.1");
+        assert_eq!(float(2.0), "2.0");
+        assert_eq!(float(-1e-7f32 as f64), "-0.0000001");
+        assert_eq!(float(1e20f32 as f64), "100000000000000000000.0");
+    }
+}
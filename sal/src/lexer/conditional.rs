@@ -0,0 +1,186 @@
+// Copyright (c) 2021, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Conditional compilation: `#if IDENT`, `#else` and `#endif` lines evaluated against a set of
+//! externally defined identifiers before tokenizing.
+//!
+//! Lines of a branch which is not taken as well as the directive lines themselves are blanked out
+//! so that line numbers of the remaining code are left untouched. A `#` line which does not match
+//! one of the directives exactly is left alone as it is a comment. Lines starting inside a block
+//! comment are never directives.
+
+use std::borrow::Cow;
+
+use crate::lexer::{
+    error::Error,
+    token::{CHR_COMMENT, CHR_ESCAPE, CHR_NL, CHR_STRING, STR_BLOCK_COMMENT_END, STR_BLOCK_COMMENT_START}
+};
+
+/// Maximum number of nested `#if` blocks.
+pub const MAX_DEPTH: usize = 32;
+
+enum Directive<'a>
+{
+    If(&'a str),
+    Else,
+    Endif
+}
+
+fn is_identifier(s: &str) -> bool
+{
+    let mut chars = s.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn parse_directive(line: &[u8]) -> Option<Directive<'_>>
+{
+    let line = std::str::from_utf8(line).ok()?;
+    let mut words = line.split_whitespace();
+    let directive = match words.next()? {
+        "#if" => Directive::If(words.next().filter(|v| is_identifier(v))?),
+        "#else" => Directive::Else,
+        "#endif" => Directive::Endif,
+        _ => return None
+    };
+    //Allow a trailing comment after the directive.
+    match words.next() {
+        Some(v) if !v.starts_with('#') => None,
+        _ => Some(directive)
+    }
+}
+
+// Returns true if a block comment is still open at the end of `line`, `open` telling whether one
+// was open at its start. Like the lexer, `#` comments out the rest of the line and strings are
+// skipped.
+fn block_comment_open(line: &[u8], mut open: bool) -> bool
+{
+    let mut pos = 0;
+    while pos < line.len() {
+        if open {
+            if line[pos..].starts_with(STR_BLOCK_COMMENT_END) {
+                open = false;
+                pos += STR_BLOCK_COMMENT_END.len();
+                continue;
+            }
+        } else if line[pos..].starts_with(STR_BLOCK_COMMENT_START) {
+            open = true;
+            pos += STR_BLOCK_COMMENT_START.len();
+            continue;
+        } else if line[pos] == CHR_COMMENT {
+            break;
+        } else if line[pos] == CHR_STRING {
+            let mut escaped = false;
+            pos += 1;
+            while pos < line.len() && (escaped || line[pos] != CHR_STRING) {
+                escaped = !escaped && line[pos] == CHR_ESCAPE;
+                pos += 1;
+            }
+        }
+        pos += 1;
+    }
+    open
+}
+
+struct Block
+{
+    line: usize,
+    col: usize,
+    // True if the enclosing blocks are all taken.
+    parent: bool,
+    taken: bool,
+    has_else: bool
+}
+
+/// Evaluates the conditional directives of `code`; `first_line` is the line number of the first
+/// line of `code`, used for error reporting.
+pub fn filter<'a>(code: &'a [u8], defines: &[String], first_line: usize) -> Result<Cow<'a, [u8]>, Error>
+{
+    let mut blocks: Vec<Block> = Vec::new();
+    let mut out = Vec::with_capacity(code.len());
+    let mut found = false;
+    let mut in_comment = false;
+    for (index, line) in code.split(|v| *v == CHR_NL).enumerate() {
+        if index > 0 {
+            out.push(CHR_NL);
+        }
+        let active = blocks.last().map(|v| v.parent && v.taken).unwrap_or(true);
+        let directive = match in_comment {
+            true => None,
+            false => parse_directive(line)
+        };
+        let directive = match directive {
+            Some(v) => v,
+            None => {
+                //Blanked lines are never seen by the lexer, nor are the comments they open.
+                if active {
+                    in_comment = block_comment_open(line, in_comment);
+                    out.extend_from_slice(line);
+                }
+                continue;
+            }
+        };
+        found = true;
+        let line_num = first_line + index;
+        let col = line.iter().position(|v| *v == b'#').unwrap_or(0) + 1;
+        match directive {
+            Directive::If(name) => {
+                if blocks.len() >= MAX_DEPTH {
+                    return Err(Error::nesting_too_deep(line_num, col));
+                }
+                blocks.push(Block {
+                    line: line_num,
+                    col,
+                    parent: active,
+                    taken: defines.iter().any(|v| v == name),
+                    has_else: false
+                });
+            },
+            Directive::Else => {
+                let block = match blocks.last_mut() {
+                    Some(v) if !v.has_else => v,
+                    _ => return Err(Error::unexpected_directive(line_num, col, "#else"))
+                };
+                block.taken = !block.taken;
+                block.has_else = true;
+            },
+            Directive::Endif => {
+                if blocks.pop().is_none() {
+                    return Err(Error::unexpected_directive(line_num, col, "#endif"));
+                }
+            }
+        }
+    }
+    if let Some(block) = blocks.pop() {
+        return Err(Error::unterminated_if(block.line, block.col));
+    }
+    match found {
+        true => Ok(Cow::Owned(out)),
+        false => Ok(Cow::Borrowed(code))
+    }
+}
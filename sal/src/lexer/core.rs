@@ -28,9 +28,11 @@
 
 use std::{collections::VecDeque, str::from_utf8_unchecked};
 
+use once_cell::sync::Lazy;
 use regex::bytes::Regex;
 
 use crate::lexer::{
+    conditional,
     error::Error,
     token::{
         Token,
@@ -40,17 +42,30 @@ use crate::lexer::{
         CHR_ARRAY_END,
         CHR_BREAK,
         CHR_COLON,
+        CHR_COMMA,
         CHR_COMMENT,
         CHR_EQ,
+        CHR_ESCAPE,
         CHR_NL,
+        CHR_PAREN_END,
+        CHR_PAREN_START,
+        CHR_STRING,
+        STR_BLOCK_COMMENT_END,
+        STR_BLOCK_COMMENT_START,
         STR_BLENDFUNC,
+        STR_SAMPLERSTATE,
         STR_CONST,
         STR_FALSE,
         STR_OUTPUT,
         STR_PIPELINE,
+        STR_RESOURCE,
+        STR_ROOTCONST,
+        STR_AS,
+        STR_EXTENDS,
         STR_STRUCT,
         STR_TRUE,
         STR_USE,
+        STR_VARYING,
         STR_VFORMAT
     }
 };
@@ -83,13 +98,23 @@ fn check_keyword(substr: &[u8]) -> Option<Token>
             STR_PIPELINE => Some(Token::Pipeline),
             STR_VFORMAT => Some(Token::Vformat),
             STR_BLENDFUNC => Some(Token::Blendfunc),
+            STR_SAMPLERSTATE => Some(Token::SamplerState),
             STR_USE => Some(Token::Use),
             STR_OUTPUT => Some(Token::Output),
+            STR_VARYING => Some(Token::Varying),
+            STR_ROOTCONST => Some(Token::RootConst),
+            STR_RESOURCE => Some(Token::Resource),
+            STR_AS => Some(Token::As),
+            STR_EXTENDS => Some(Token::Extends),
             _ => None
         }
     }
 }
 
+static INT: Lazy<Regex> = Lazy::new(|| Regex::new(r"^-?\d+$").unwrap());
+static FLOAT: Lazy<Regex> = Lazy::new(|| Regex::new(r"^-?\d*\.\d+$").unwrap());
+static IDENTIFIER: Lazy<Regex> = Lazy::new(|| Regex::new(r"^[A-Za-z_][A-Za-z0-9_]*$").unwrap());
+
 fn check_litteral(substr: &[u8]) -> Option<Token>
 {
     if substr == STR_TRUE {
@@ -97,15 +122,13 @@ fn check_litteral(substr: &[u8]) -> Option<Token>
     } else if substr == STR_FALSE {
         return Some(Token::Bool(false));
     }
-    let int = Regex::new(r"^-?\d+$").unwrap();
-    let float = Regex::new(r"^-?\d*\.\d+$").unwrap();
-    if int.is_match(substr) {
+    if INT.is_match(substr) {
         //SAFETY: If we get there and that we don't have a valid int well then regex crate is broken!
         unsafe {
             return Some(Token::Int(from_utf8_unchecked(substr).parse().unwrap()));
         }
     }
-    if float.is_match(substr) {
+    if FLOAT.is_match(substr) {
         //SAFETY: If we get there and that we don't have a valid float well then regex crate is broken!
         unsafe {
             return Some(Token::Float(from_utf8_unchecked(substr).parse().unwrap()));
@@ -116,8 +139,7 @@ fn check_litteral(substr: &[u8]) -> Option<Token>
 
 fn check_identifier(substr: &[u8]) -> Option<Token>
 {
-    let re = Regex::new(r"^([a-zA-Z]|_)([a-zA-Z]|\d|_)*$").unwrap();
-    if re.is_match(substr) {
+    if IDENTIFIER.is_match(substr) {
         //SAFETY: If we get there but substr is not valid UTF8 well then regex crate is broken!
         unsafe {
             return Some(Token::Identifier(from_utf8_unchecked(substr).into()));
@@ -136,6 +158,9 @@ fn check_terminator(chr: u8) -> Option<Token>
             CHR_COLON => Some(Token::Colon),
             CHR_ARRAY_START => Some(Token::ArrayStart),
             CHR_ARRAY_END => Some(Token::ArrayEnd),
+            CHR_PAREN_START => Some(Token::ParenStart),
+            CHR_PAREN_END => Some(Token::ParenEnd),
+            CHR_COMMA => Some(Token::Comma),
             _ => None
         }
     }
@@ -164,7 +189,8 @@ pub struct Lexer
     cur_token: (usize, usize),
     cur_line: usize,
     cur_column: usize,
-    in_comment: bool
+    in_comment: bool,
+    defines: Vec<String>
 }
 
 impl Default for Lexer
@@ -184,7 +210,17 @@ impl Lexer
             cur_token: (0, 0),
             cur_column: 0,
             cur_line: 1,
-            in_comment: false
+            in_comment: false,
+            defines: Vec::new()
+        }
+    }
+
+    /// Creates a lexer which evaluates `#if` directives against the given defined identifiers.
+    pub fn with_defines(defines: &[String]) -> Lexer
+    {
+        Lexer {
+            defines: defines.into(),
+            ..Self::new()
         }
     }
 
@@ -223,7 +259,73 @@ impl Lexer
         Ok(())
     }
 
+    // Reads a string literal starting right after the opening quote at pos, returns the
+    // position right after the closing quote. A backslash escapes a quote or a backslash, it is
+    // kept as is before any other character so that Windows paths need no escaping.
+    fn parse_string(&mut self, pos: usize, code: &[u8]) -> Result<usize, Error>
+    {
+        let (line, col) = (self.cur_line, self.cur_column);
+        let mut value = Vec::new();
+        let mut escaped = false;
+        for (i, chr) in code[pos..].iter().enumerate() {
+            if *chr == CHR_NL {
+                self.cur_line += 1;
+                self.cur_column = 0;
+            } else {
+                self.cur_column += 1;
+            }
+            match *chr {
+                CHR_STRING if !escaped => {
+                    self.tokens.push_back(TokenEntry {
+                        token: Token::String(String::from_utf8_lossy(&value).into()),
+                        line,
+                        col
+                    });
+                    return Ok(pos + i + 1);
+                },
+                CHR_ESCAPE if !escaped => escaped = true,
+                chr => {
+                    if escaped && chr != CHR_STRING && chr != CHR_ESCAPE {
+                        value.push(CHR_ESCAPE);
+                    }
+                    value.push(chr);
+                    escaped = false;
+                }
+            }
+        }
+        Err(Error::unterminated_string(line, col))
+    }
+
+    // Skips a block comment starting at pos (on the opening slash), returns the position right
+    // after the closing slash. Block comments do not nest: the first */ ends the comment.
+    fn parse_block_comment(&mut self, pos: usize, code: &[u8]) -> Result<usize, Error>
+    {
+        let (line, col) = (self.cur_line, self.cur_column);
+        let start = pos + STR_BLOCK_COMMENT_START.len();
+        let end = code[start..]
+            .windows(STR_BLOCK_COMMENT_END.len())
+            .position(|v| v == STR_BLOCK_COMMENT_END)
+            .map(|v| v + start + STR_BLOCK_COMMENT_END.len())
+            .ok_or_else(|| Error::unterminated_comment(line, col))?;
+        for chr in &code[pos + 1..end] {
+            if *chr == CHR_NL {
+                self.cur_line += 1;
+                self.cur_column = 0;
+            } else {
+                self.cur_column += 1;
+            }
+        }
+        Ok(end)
+    }
+
+    /// Tokenizes `code`; conditional blocks must be closed within the same call.
     pub fn process(&mut self, code: &[u8]) -> Result<(), Error>
+    {
+        let code = conditional::filter(code, &self.defines, self.cur_line)?;
+        self.process_filtered(&code)
+    }
+
+    fn process_filtered(&mut self, code: &[u8]) -> Result<(), Error>
     {
         self.cur_token = (0, 0);
         loop {
@@ -245,6 +347,18 @@ impl Lexer
                 self.cur_column = 0;
             }
             if !self.in_comment {
+                if code[pos2 - 1..].starts_with(STR_BLOCK_COMMENT_START) {
+                    self.parse_token(pos1, pos2 - 1, code)?;
+                    pos2 = self.parse_block_comment(pos2 - 1, code)?;
+                    self.cur_token = (pos2, pos2);
+                    continue;
+                }
+                if code[pos2 - 1] == CHR_STRING {
+                    self.parse_token(pos1, pos2 - 1, code)?;
+                    pos2 = self.parse_string(pos2, code)?;
+                    self.cur_token = (pos2, pos2);
+                    continue;
+                }
                 if let Some(tok) = check_terminator(code[pos2 - 1]) {
                     self.parse_token(pos1, pos2 - 1, code)?;
                     pos1 = pos2; //This should be +1 but somehow there's a strange thing here
@@ -734,6 +848,208 @@ const mat4f ModelView;
         );
     }
 
+    fn lex(source_code: &[u8]) -> Vec<TokenEntry>
+    {
+        let mut lexer = Lexer::new();
+        lexer.process(source_code).unwrap();
+        lexer.eliminate_whitespace();
+        lexer.into_tokens().into_iter().collect()
+    }
+
+    #[test]
+    fn block_comments()
+    {
+        let toks: Vec<Token> = lex(b"const /*packed*/ float X;\nconst/* a /* b */float/**/Y;")
+            .into_iter()
+            .map(|TokenEntry { token, .. }| token)
+            .collect();
+        assert_eq!(
+            toks,
+            vec![
+                Token::Const,
+                Token::Identifier("float".into()),
+                Token::Identifier("X".into()),
+                Token::Break,
+                Token::Const,
+                Token::Identifier("float".into()),
+                Token::Identifier("Y".into()),
+                Token::Break
+            ]
+        );
+    }
+
+    #[test]
+    fn multi_line_block_comment_positions()
+    {
+        //Tokens must be reported at the same positions as if the comments were blanked out.
+        let positions = |code: &[u8]| -> Vec<(Token, usize, usize)> {
+            lex(code).into_iter().map(|v| (v.token, v.line, v.col)).collect()
+        };
+        let commented = positions(b"/* first\n   second # not a line comment\n*/ const float X; /* tail */\nconst float Y;");
+        let blank = positions(b"        \n                              \n   const float X;           \nconst float Y;");
+        assert_eq!(commented.len(), 8);
+        assert_eq!(commented, blank);
+    }
+
+    #[test]
+    fn unterminated_block_comment()
+    {
+        let mut lexer = Lexer::new();
+        let err = lexer.process(b"const float X;\n  const /* packed\n float Y;").unwrap_err();
+        assert_eq!(err, Error::unterminated_comment(2, 9));
+    }
+
+    #[test]
+    fn strings()
+    {
+        let toks: Vec<(Token, usize, usize)> = lex(b"Name =\"a b\";\nNote = \"say \\\"hi\\\" \\\\ C:\\dir\"; X : deprecated(\"old\"),")
+            .into_iter()
+            .map(|v| (v.token, v.line, v.col))
+            .collect();
+        assert_eq!(
+            toks,
+            vec![
+                (Token::Identifier("Name".into()), 1, 5),
+                (Token::Eq, 1, 7),
+                (Token::String("a b".into()), 1, 7),
+                (Token::Break, 1, 12),
+                (Token::Identifier("Note".into()), 2, 5),
+                (Token::Eq, 2, 7),
+                (Token::String("say \"hi\" \\ C:\\dir".into()), 2, 8),
+                (Token::Break, 2, 30),
+                (Token::Identifier("X".into()), 2, 33),
+                (Token::Colon, 2, 34),
+                (Token::Identifier("deprecated".into()), 2, 46),
+                (Token::ParenStart, 2, 46),
+                (Token::String("old".into()), 2, 47),
+                (Token::ParenEnd, 2, 52),
+                (Token::Comma, 2, 53)
+            ]
+        );
+    }
+
+    #[test]
+    fn unterminated_string()
+    {
+        let mut lexer = Lexer::new();
+        let err = lexer.process(b"const float X;\n  A = \"open\n;").unwrap_err();
+        assert_eq!(err, Error::unterminated_string(2, 7));
+        //An escaped quote does not close the string.
+        let err = Lexer::new().process(b"A = \"open\\\";").unwrap_err();
+        assert_eq!(err, Error::unterminated_string(1, 5));
+    }
+
+    #[test]
+    fn identifiers()
+    {
+        let mut lexer = Lexer::new();
+        lexer.process(b"const vec4f base_color;\nconst float _Private = 12;").unwrap();
+        lexer.eliminate_whitespace();
+        lexer.eliminate_breaks();
+        let toks: Vec<Token> = lexer.into_tokens().into_iter().map(|v| v.token).collect();
+        assert_eq!(toks, [
+            Token::Const,
+            Token::Identifier("vec4f".into()),
+            Token::Identifier("base_color".into()),
+            Token::Const,
+            Token::Identifier("float".into()),
+            Token::Identifier("_Private".into()),
+            Token::Eq,
+            Token::Int(12)
+        ]);
+        //Characters between Z and a in ASCII are not part of identifiers.
+        for source_code in [&b"const float\n  foo^bar;"[..], b"const float\n  foo\\bar;", b"const float\n  foo`bar;"] {
+            //Like other tokens, the position is the one of the character which ends the token.
+            let err = Lexer::new().process(source_code).unwrap_err();
+            assert_eq!(err, Error::unidentified_token(2, 10, &source_code[14..21]));
+        }
+        //] ends an array size so it splits identifiers.
+        let mut lexer = Lexer::new();
+        lexer.process(b"foo]bar").unwrap();
+        let toks: Vec<Token> = lexer.into_tokens().into_iter().map(|v| v.token).collect();
+        assert_eq!(toks, [Token::Identifier("foo".into()), Token::ArrayEnd, Token::Identifier("bar".into())]);
+    }
+
+    fn lex_with_defines(source_code: &[u8], defines: &[&str]) -> Result<Vec<(Token, usize)>, Error>
+    {
+        let defines: Vec<String> = defines.iter().map(|v| v.to_string()).collect();
+        let mut lexer = Lexer::with_defines(&defines);
+        lexer.process(source_code)?;
+        lexer.eliminate_whitespace();
+        lexer.eliminate_breaks();
+        Ok(lexer.into_tokens().into_iter().map(|v| (v.token, v.line)).collect())
+    }
+
+    #[test]
+    fn conditionals()
+    {
+        let source_code = b"const float A;
+#if HIGH
+    #if SHADOWS # soft shadows
+    const float B;
+    #else
+    const float C @ $;
+    #endif
+#else
+    const ~~ D;
+#endif
+const float E;";
+        let names = |defines: &[&str]| -> Vec<(String, usize)> {
+            lex_with_defines(source_code, defines).unwrap().into_iter().filter_map(|(tok, line)| match tok {
+                Token::Identifier(v) if v != "float" => Some((v, line)),
+                _ => None
+            }).collect()
+        };
+        let names1 = |v: &[(&str, usize)]| -> Vec<(String, usize)> {
+            v.iter().map(|(v, line)| (v.to_string(), *line)).collect()
+        };
+        assert_eq!(names(&["HIGH", "SHADOWS"]), names1(&[("A", 1), ("B", 4), ("E", 11)]));
+        assert!(lex_with_defines(source_code, &["HIGH"]).is_err());
+        assert!(lex_with_defines(source_code, &[]).is_err());
+        assert_eq!(names(&["HIGH", "SHADOWS", "LOW"]), names(&["HIGH", "SHADOWS"]));
+        //Without defines the directives are plain comments.
+        let toks = lex_with_defines(b"#if you want\nconst float A;\n#endif of comment\nconst float B;", &[]).unwrap();
+        assert_eq!(toks.len(), 6);
+    }
+
+    #[test]
+    fn conditionals_in_block_comments()
+    {
+        let source_code = b"/* disabled:
+#if HIGH
+#endif */
+const float A; /* \"*/
+#if HIGH
+const float B; /*
+#else */
+#endif
+const \"/*\" C;
+#if HIGH # /*
+const float D;
+#endif";
+        let toks = lex_with_defines(source_code, &[]).unwrap();
+        assert!(toks.contains(&(Token::Identifier("A".into()), 4)));
+        assert!(toks.contains(&(Token::String("/*".into()), 9)));
+        assert!(!toks.iter().any(|(tok, _)| matches!(tok, Token::Identifier(v) if v == "B" || v == "D")));
+        let toks = lex_with_defines(source_code, &["HIGH"]).unwrap();
+        assert!(toks.contains(&(Token::Identifier("B".into()), 6)));
+        assert!(toks.contains(&(Token::Identifier("D".into()), 11)));
+        //An unbalanced #if hidden in a comment is not an error either.
+        assert!(lex_with_defines(b"/*\n#if HIGH\n*/\nconst float A;", &["HIGH"]).is_ok());
+    }
+
+    #[test]
+    fn conditional_errors()
+    {
+        assert_eq!(lex_with_defines(b"const float A;\n  #if HIGH\n#if LOW\n#endif\n", &[]).unwrap_err(), Error::unterminated_if(2, 3));
+        assert_eq!(lex_with_defines(b"#if HIGH\n#else\n#else\n#endif", &[]).unwrap_err(), Error::unexpected_directive(3, 1, "#else"));
+        assert_eq!(lex_with_defines(b"#endif", &[]).unwrap_err(), Error::unexpected_directive(1, 1, "#endif"));
+        let nested = "#if A\n".repeat(conditional::MAX_DEPTH) + &"#endif\n".repeat(conditional::MAX_DEPTH);
+        assert!(lex_with_defines(nested.as_bytes(), &["A"]).is_ok());
+        let nested = "#if A\n".repeat(conditional::MAX_DEPTH + 1);
+        assert_eq!(lex_with_defines(nested.as_bytes(), &["A"]).unwrap_err(), Error::nesting_too_deep(conditional::MAX_DEPTH + 1, 1));
+    }
+
     proptest! {
         #[test]
         fn random_input_no_panic(s in "//PC*")
@@ -32,6 +32,11 @@ use std::fmt::{Debug, Display, Formatter};
 pub enum Type
 {
     UnidentifiedToken(Vec<u8>),
+    UnterminatedComment,
+    UnterminatedString,
+    UnterminatedIf,
+    UnexpectedDirective(&'static str),
+    NestingTooDeep,
     Eof
 }
 
@@ -41,6 +46,11 @@ impl Debug for Type
     {
         match self {
             Type::UnidentifiedToken(v) => write!(f, "UnidentifiedToken({})", String::from_utf8_lossy(v).as_ref()),
+            Type::UnterminatedComment => f.write_str("UnterminatedComment"),
+            Type::UnterminatedString => f.write_str("UnterminatedString"),
+            Type::UnterminatedIf => f.write_str("UnterminatedIf"),
+            Type::UnexpectedDirective(v) => write!(f, "UnexpectedDirective({})", v),
+            Type::NestingTooDeep => f.write_str("NestingTooDeep"),
             Type::Eof => f.write_str("Eof")
         }
     }
@@ -65,6 +75,58 @@ impl Error
         }
     }
 
+    /// Creates an error for a block comment which is never closed; line and col point to the
+    /// start of the comment.
+    pub fn unterminated_comment(line: usize, col: usize) -> Self
+    {
+        Self {
+            line,
+            col,
+            etype: Type::UnterminatedComment
+        }
+    }
+
+    /// Creates an error for a string literal which is never closed; line and col point to the
+    /// opening quote.
+    pub fn unterminated_string(line: usize, col: usize) -> Self
+    {
+        Self {
+            line,
+            col,
+            etype: Type::UnterminatedString
+        }
+    }
+
+    /// Creates an error for an `#if` which is never closed by an `#endif`; line and col point to
+    /// the opening `#if`.
+    pub fn unterminated_if(line: usize, col: usize) -> Self
+    {
+        Self {
+            line,
+            col,
+            etype: Type::UnterminatedIf
+        }
+    }
+
+    /// Creates an error for an `#else` or `#endif` which does not match any open `#if`.
+    pub fn unexpected_directive(line: usize, col: usize, directive: &'static str) -> Self
+    {
+        Self {
+            line,
+            col,
+            etype: Type::UnexpectedDirective(directive)
+        }
+    }
+
+    pub fn nesting_too_deep(line: usize, col: usize) -> Self
+    {
+        Self {
+            line,
+            col,
+            etype: Type::NestingTooDeep
+        }
+    }
+
     pub fn eof(line: usize, col: usize) -> Self
     {
         Self {
@@ -32,9 +32,15 @@ pub const STR_CONST: &[u8] = b"const";
 pub const STR_STRUCT: &[u8] = b"struct";
 pub const STR_PIPELINE: &[u8] = b"pipeline";
 pub const STR_BLENDFUNC: &[u8] = b"blendfunc";
+pub const STR_SAMPLERSTATE: &[u8] = b"samplerstate";
 pub const STR_VFORMAT: &[u8] = b"vformat";
 pub const STR_USE: &[u8] = b"use";
 pub const STR_OUTPUT: &[u8] = b"output";
+pub const STR_VARYING: &[u8] = b"varying";
+pub const STR_ROOTCONST: &[u8] = b"rootconst";
+pub const STR_RESOURCE: &[u8] = b"resource";
+pub const STR_AS: &[u8] = b"as";
+pub const STR_EXTENDS: &[u8] = b"extends";
 pub const STR_TRUE: &[u8] = b"true";
 pub const STR_FALSE: &[u8] = b"false";
 
@@ -46,6 +52,13 @@ pub const CHR_COMMENT: u8 = b'#';
 pub const CHR_COLON: u8 = b':';
 pub const CHR_ARRAY_START: u8 = b'[';
 pub const CHR_ARRAY_END: u8 = b']';
+pub const CHR_PAREN_START: u8 = b'(';
+pub const CHR_PAREN_END: u8 = b')';
+pub const CHR_COMMA: u8 = b',';
+pub const CHR_STRING: u8 = b'"';
+pub const CHR_ESCAPE: u8 = b'\\';
+pub const STR_BLOCK_COMMENT_START: &[u8] = b"/*";
+pub const STR_BLOCK_COMMENT_END: &[u8] = b"*/";
 
 pub const CHR_NL: u8 = b'\n';
 
@@ -62,13 +75,23 @@ pub enum Type
     BlockEnd,
     ArrayStart,
     ArrayEnd,
+    ParenStart,
+    ParenEnd,
+    Comma,
     Output,
+    Varying,
+    RootConst,
+    Resource,
+    As,
+    Extends,
     Bool,
     Int,
     Float,
+    String,
     Identifier,
     Colon,
     Blendfunc,
+    SamplerState,
     Whitespace,
     Break,
     Combined(Vec<Type>)
@@ -88,17 +111,27 @@ impl Type
             Type::BlockStart => "'{'",
             Type::BlockEnd => "'}'",
             Type::Output => "output",
+            Type::Varying => "varying",
+            Type::RootConst => "rootconst",
+            Type::Resource => "resource",
+            Type::As => "as",
+            Type::Extends => "extends",
             Type::Bool => "bool",
             Type::Int => "int",
             Type::Float => "float",
             Type::Identifier => "identifier",
             Type::Colon => "':'",
             Type::Blendfunc => "blendfunc",
+            Type::SamplerState => "samplerstate",
             Type::Whitespace => "whitespace",
             Type::Break => "';'",
             Type::Combined(_) => "combined",
             Type::ArrayStart => "'['",
-            Type::ArrayEnd => "']'"
+            Type::ArrayEnd => "']'",
+            Type::ParenStart => "'('",
+            Type::ParenEnd => "')'",
+            Type::Comma => "','",
+            Type::String => "string"
         }
     }
 
@@ -139,13 +172,23 @@ pub enum Token
     BlockEnd,
     ArrayStart,
     ArrayEnd,
+    ParenStart,
+    ParenEnd,
+    Comma,
     Output,
+    Varying,
+    RootConst,
+    Resource,
+    As,
+    Extends,
     Bool(bool),
     Int(i32),
     Float(f32),
+    String(String),
     Identifier(String),
     Colon,
     Blendfunc,
+    SamplerState,
     Whitespace,
     Break
 }
@@ -165,13 +208,23 @@ impl Token
             Token::BlockEnd => Type::BlockEnd,
             Token::ArrayStart => Type::ArrayStart,
             Token::ArrayEnd => Type::ArrayEnd,
+            Token::ParenStart => Type::ParenStart,
+            Token::ParenEnd => Type::ParenEnd,
+            Token::Comma => Type::Comma,
             Token::Output => Type::Output,
+            Token::Varying => Type::Varying,
+            Token::RootConst => Type::RootConst,
+            Token::Resource => Type::Resource,
+            Token::As => Type::As,
+            Token::Extends => Type::Extends,
             Token::Bool(_) => Type::Bool,
             Token::Int(_) => Type::Int,
             Token::Float(_) => Type::Float,
+            Token::String(_) => Type::String,
             Token::Identifier(_) => Type::Identifier,
             Token::Colon => Type::Colon,
             Token::Blendfunc => Type::Blendfunc,
+            Token::SamplerState => Type::SamplerState,
             Token::Whitespace => Type::Whitespace,
             Token::Break => Type::Break
         }
@@ -186,6 +239,15 @@ impl Token
         }
     }
 
+    pub fn string(self) -> Option<String>
+    {
+        if let Token::String(s) = self {
+            Some(s)
+        } else {
+            None
+        }
+    }
+
     pub fn bool(self) -> Option<bool>
     {
         if let Token::Bool(b) = self {
@@ -222,6 +284,7 @@ impl Display for Token
             Token::Bool(b) => write!(formatter, "bool({})", b),
             Token::Int(i) => write!(formatter, "int({})", i),
             Token::Float(f) => write!(formatter, "float({})", f),
+            Token::String(s) => write!(formatter, "string({:?})", s),
             Token::Identifier(s) => write!(formatter, "identifier({})", s),
             _ => formatter.write_str(self.get_type().name())
         }
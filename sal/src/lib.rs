@@ -27,6 +27,8 @@
 // SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
 pub mod ast;
+pub mod check;
+pub mod fmt;
 pub mod lexer;
 pub mod parser;
 pub mod preprocessor;
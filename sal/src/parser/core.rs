@@ -26,7 +26,8 @@
 // NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
 // SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
+use std::panic::AssertUnwindSafe;
 
 use crate::{
     lexer::{
@@ -39,14 +40,23 @@ use crate::{
         tree
     }
 };
-use crate::parser::error::ParserOrVisitor;
+use crate::parser::error::{ParserOrVisitor, VisitorPanic};
 use crate::parser::Visitor;
 
 pub struct Parser
 {
     tokens: VecDeque<TokenEntry>,
     cur_line: usize,
-    cur_column: usize
+    cur_column: usize,
+    stmt_line: usize,
+    stmt_column: usize,
+    stmt_index: usize,
+    /// Block depth of the current statement, used to find its end after a syntax error.
+    depth: usize,
+    /// True when the last token consumed ends the current statement.
+    at_boundary: bool,
+    /// Constants declared so far, with their value when they are integer constants.
+    constants: HashMap<String, Option<i32>>
 }
 
 impl Parser
@@ -57,10 +67,28 @@ impl Parser
         Parser {
             tokens: lexer.into_tokens(),
             cur_line: 0,
-            cur_column: 0
+            cur_column: 0,
+            stmt_line: 0,
+            stmt_column: 0,
+            stmt_index: 0,
+            depth: 0,
+            at_boundary: false,
+            constants: HashMap::new()
         }
     }
 
+    /// Returns the line and column of the first token of the last statement parsed.
+    pub fn statement_position(&self) -> (usize, usize)
+    {
+        (self.stmt_line, self.stmt_column)
+    }
+
+    /// Returns the position of the last token popped.
+    fn span(&self) -> tree::Span
+    {
+        tree::Span::new(self.cur_line, self.cur_column)
+    }
+
     fn pop_expect(&mut self, ttype: TokenType) -> Result<Token, Error>
     {
         let token = self.pop()?;
@@ -83,29 +111,119 @@ impl Parser
         if let Some(entry) = self.tokens.pop_front() {
             self.cur_column = entry.col;
             self.cur_line = entry.line;
+            self.track(&entry.token);
             Ok(entry.token)
         } else {
             Err(Error::new(self.cur_line, self.cur_column, Type::Eof))
         }
     }
 
+    /// Updates the block depth of the current statement with a token which was just consumed.
+    fn track(&mut self, token: &Token)
+    {
+        self.at_boundary = match token {
+            Token::BlockStart => {
+                self.depth += 1;
+                false
+            },
+            Token::BlockEnd => {
+                self.depth = self.depth.saturating_sub(1);
+                self.depth == 0
+            },
+            Token::Break => self.depth == 0,
+            _ => false
+        };
+    }
+
+    /// Skips the remaining tokens of a statement which failed to parse: up to the next ';' at
+    /// top level or the '}' closing the statement.
+    fn recover(&mut self)
+    {
+        while !self.at_boundary {
+            match self.tokens.pop_front() {
+                Some(entry) => self.track(&entry.token),
+                None => break
+            }
+        }
+    }
+
     fn try_parse_use(&mut self, token: &Token) -> Result<Option<tree::Use>, Error>
     {
         if token == &Token::Use {
+            let span = tree::Span::new(self.stmt_line, self.stmt_column);
             let token = self.pop_expect(TokenType::Identifier)?;
-            let module = token.identifier().unwrap(); // SAFETY: we have tested for identifier in pop_expect so no panic possible here!
+            let mut path = vec![token.identifier().unwrap()]; // SAFETY: we have tested for identifier in pop_expect so no panic possible here!
             self.pop_expect(TokenType::Colon)?;
             self.pop_expect(TokenType::Colon)?;
             let token = self.pop_expect(TokenType::Identifier)?;
-            let member = token.identifier().unwrap(); // SAFETY: we have tested for identifier in pop_expect so no panic possible here!
+            let mut member = token.identifier().unwrap(); // SAFETY: we have tested for identifier in pop_expect so no panic possible here!
+            loop {
+                let token = self.pop()?;
+                match token.get_type() {
+                    TokenType::Break => break,
+                    TokenType::Colon => {
+                        self.pop_expect(TokenType::Colon)?;
+                        let token = self.pop_expect(TokenType::Identifier)?;
+                        path.push(std::mem::replace(&mut member, token.identifier().unwrap())); // SAFETY: we have tested for identifier in pop_expect so no panic possible here!
+                    },
+                    _ => return Err(Error::new(
+                        self.cur_line,
+                        self.cur_column,
+                        Type::UnexpectedToken {
+                            expected: TokenType::Break,
+                            actual: token
+                        }
+                    ))
+                }
+            }
+            Ok(Some(tree::Use { path, member, span }))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn try_parse_resource(&mut self, token: &Token) -> Result<Option<tree::Resource>, Error>
+    {
+        if token == &Token::Resource {
+            let token = self.pop_expect(TokenType::String)?;
+            let path = token.string().unwrap(); // SAFETY: we have tested for string in pop_expect so no panic possible here!
+            self.pop_expect(TokenType::As)?;
+            let token = self.pop_expect(TokenType::Identifier)?;
+            let name = token.identifier().unwrap(); // SAFETY: we have tested for identifier in pop_expect so no panic possible here!
             self.pop_expect(TokenType::Break)?;
-            Ok(Some(tree::Use { module, member }))
+            Ok(Some(tree::Resource { path, name }))
         } else {
             Ok(None)
         }
     }
 
-    fn parse_prop_type(&mut self, token: Token) -> Result<(String, Option<String>), Error>
+    fn parse_array_size(&mut self) -> Result<u32, Error>
+    {
+        let token = self.pop()?;
+        let val = match token {
+            Token::Int(val) => val,
+            Token::Identifier(name) => match self.constants.get(&name) {
+                Some(Some(val)) => *val,
+                Some(None) => return Err(Error::new(self.cur_line, self.cur_column, Type::NotIntConstant(name))),
+                None => return Err(Error::new(self.cur_line, self.cur_column, Type::UnknownConstant(name)))
+            },
+            _ => return Err(Error::new(
+                self.cur_line,
+                self.cur_column,
+                Type::UnexpectedToken {
+                    expected: TokenType::combined([TokenType::Int, TokenType::Identifier]),
+                    actual: token
+                }
+            ))
+        };
+        if val < 0 {
+            return Err(Error::new(self.cur_line, self.cur_column, Type::NegativeArraySize(val)));
+        }
+        self.pop_expect(TokenType::ArrayEnd)?;
+        Ok(val as u32)
+    }
+
+    fn parse_prop_type(&mut self, token: Token, mut ptype_arr: Option<u32>) -> Result<(String, Option<String>, Option<u32>), Error>
     {
         let mut ptype_attr = None;
         let pname;
@@ -113,9 +231,35 @@ impl Parser
             Token::Identifier(n) => pname = n,
             Token::Colon => {
                 let token = self.pop_expect(TokenType::Identifier)?;
-                ptype_attr = Some(token.identifier().unwrap()); // SAFETY: we have tested for identifier in pop_expect so no panic possible here!
-                let token = self.pop_expect(TokenType::Identifier)?;
-                pname = token.identifier().unwrap();
+                let mut attr = token.identifier().unwrap(); // SAFETY: we have tested for identifier in pop_expect so no panic possible here!
+                let mut token = self.pop()?;
+                if token == Token::Colon {
+                    //Sample count of a multisample texture (ex: Texture2DMS:vec4f:4).
+                    let samples = self.pop_expect(TokenType::Int)?.int().unwrap(); // SAFETY: we have tested for int in pop_expect so no panic possible here!
+                    attr = format!("{}:{}", attr, samples);
+                    token = self.pop()?;
+                }
+                ptype_attr = Some(attr);
+                if token == Token::ArrayStart {
+                    if ptype_arr.is_some() {
+                        return Err(Error::new(self.cur_line, self.cur_column, Type::NestedArray));
+                    }
+                    ptype_arr = Some(self.parse_array_size()?);
+                    token = self.pop()?;
+                }
+                pname = match token {
+                    Token::Identifier(n) => n,
+                    _ => {
+                        return Err(Error::new(
+                            self.cur_line,
+                            self.cur_column,
+                            Type::UnexpectedToken {
+                                expected: TokenType::Identifier,
+                                actual: token
+                            }
+                        ))
+                    }
+                };
             },
             _ => {
                 return Err(Error::new(
@@ -128,43 +272,102 @@ impl Parser
                 ))
             },
         };
-        Ok((pname, ptype_attr))
+        Ok((pname, ptype_attr, ptype_arr))
     }
 
-    fn parse_property(&mut self) -> Result<tree::Property, Error>
+    fn parse_attribute(&mut self) -> Result<tree::Attribute, Error>
     {
         let token = self.pop_expect(TokenType::Identifier)?;
+        let name = token.identifier().unwrap(); // SAFETY: we have tested for identifier in pop_expect so no panic possible here!
+        let mut value = None;
+        if let Some(TokenEntry { token, .. }) = self.tokens.front() {
+            if token == &Token::ParenStart {
+                self.pop()?;
+                let token = self.pop_expect(TokenType::String)?;
+                value = Some(token.string().unwrap()); // SAFETY: we have tested for string in pop_expect so no panic possible here!
+                self.pop_expect(TokenType::ParenEnd)?;
+            }
+        }
+        Ok(tree::Attribute { name, value })
+    }
+
+    fn next_is_deprecated(&self) -> bool
+    {
+        matches!(self.tokens.front(), Some(TokenEntry { token: Token::Identifier(name), .. }) if name == "deprecated")
+    }
+
+    /// Parses the attributes of a symbol following a colon: an attribute optionally followed by
+    /// `: deprecated`, or `deprecated` alone. Returns the attribute, the deprecation and the token
+    /// following them.
+    fn parse_attributes(&mut self) -> Result<(Option<tree::Attribute>, Option<tree::Attribute>, Token), Error>
+    {
+        let attr = self.parse_attribute()?;
+        let mut token = self.pop()?;
+        let (attr, mut deprecated) = match attr.name == "deprecated" {
+            true => (None, Some(attr)),
+            false => (Some(attr), None)
+        };
+        if token == Token::Colon && self.next_is_deprecated() && deprecated.is_none() {
+            deprecated = Some(self.parse_attribute()?);
+            token = self.pop()?;
+        }
+        if token == Token::Colon && self.next_is_deprecated() {
+            return Err(Error::new(self.cur_line, self.cur_column, Type::MisplacedDeprecated));
+        }
+        Ok((attr, deprecated, token))
+    }
+
+    /// Parses a property; `output` allows a second attribute holding the format hint of the render target.
+    fn parse_property(&mut self, output: bool) -> Result<tree::Property, Error>
+    {
+        let token = self.pop_expect(TokenType::Identifier)?;
+        let span = self.span();
         let ptype = token.identifier().unwrap(); // SAFETY: we have tested for identifier in pop_expect so no panic possible here!
-        let mut ptype_arr = None;
         let token = self.pop()?;
-        let (pname, ptype_attr) = match token {
+        let (pname, ptype_attr, ptype_arr) = match token {
             Token::ArrayStart => {
-                let array_size = self.pop_expect(TokenType::Int)?;
-                let val = array_size.int().unwrap();
-                if val < 0 {
-                    return Err(Error::new(self.cur_line, self.cur_column, Type::NegativeArraySize(val)));
-                }
-                ptype_arr = Some(val as u32); // SAFETY: we have tested for int in pop_expect so no panic possible here!
-                self.pop_expect(TokenType::ArrayEnd)?;
+                let size = self.parse_array_size()?;
                 let token = self.pop()?;
-                self.parse_prop_type(token)?
+                if token == Token::ArrayStart {
+                    return Err(Error::new(self.cur_line, self.cur_column, Type::NestedArray));
+                }
+                self.parse_prop_type(token, Some(size))?
             },
-            _ => self.parse_prop_type(token)?
+            _ => self.parse_prop_type(token, None)?
         };
-        let token = self.pop()?;
-        let pattr = match token {
-            Token::Colon => {
-                let token = self.pop_expect(TokenType::Identifier)?;
+        let mut token = self.pop()?;
+        let mut pattr = None;
+        let mut deprecated = None;
+        let mut pformat = None;
+        if token == Token::Colon {
+            (pattr, deprecated, token) = self.parse_attributes()?;
+            //Only the format of an output may follow a deprecation.
+            if token == Token::Colon && !output && deprecated.is_some() {
+                return Err(Error::new(self.cur_line, self.cur_column, Type::MisplacedDeprecated));
+            }
+            if output && token == Token::Colon {
+                let token1 = self.pop_expect(TokenType::Identifier)?;
+                pformat = token1.identifier(); // SAFETY: we have tested for identifier in pop_expect so this is always Some!
+                token = self.pop()?;
+            }
+        }
+        let default = match token {
+            Token::Eq => {
+                let value = self.parse_default_value()?;
                 self.pop_expect(TokenType::Break)?;
-                Some(token.identifier().unwrap()) // SAFETY: we have tested for identifier in pop_expect so no panic possible here!
+                Some(value)
             },
             Token::Break => None,
             _ => {
+                let expected = match pattr {
+                    Some(_) if !output || pformat.is_some() => TokenType::combined([TokenType::Eq, TokenType::Break]),
+                    _ => TokenType::combined([TokenType::Colon, TokenType::Eq, TokenType::Break])
+                };
                 return Err(Error::new(
                     self.cur_line,
                     self.cur_column,
                     Type::UnexpectedToken {
-                        expected: TokenType::combined([TokenType::Colon, TokenType::Break]),
+                        expected,
                         actual: token
                     }
                 ))
@@ -175,19 +378,68 @@ impl Parser
             ptype,
             ptype_attr,
             ptype_arr,
-            pattr
+            pattr,
+            deprecated,
+            pformat,
+            default,
+            span
         })
     }
 
+    fn parse_default_value(&mut self) -> Result<tree::DefaultValue, Error>
+    {
+        let name = match self.parse_pipeline_val()? {
+            tree::Value::Identifier(name) => name,
+            value => return Ok(tree::DefaultValue::Value(value))
+        };
+        self.pop_expect(TokenType::ParenStart)?;
+        let mut values = Vec::new();
+        loop {
+            values.push(self.parse_pipeline_val()?);
+            let token = self.pop()?;
+            match token {
+                Token::Comma => (),
+                Token::ParenEnd => break,
+                _ => return Err(Error::new(
+                    self.cur_line,
+                    self.cur_column,
+                    Type::UnexpectedToken {
+                        expected: TokenType::combined([TokenType::Comma, TokenType::ParenEnd]),
+                        actual: token
+                    }
+                ))
+            }
+        }
+        Ok(tree::DefaultValue::Constructor(name, values))
+    }
+
     fn try_parse_output(&mut self, token: &Token) -> Result<Option<tree::Property>, Error>
     {
         if token == &Token::Output {
-            let prop = self.parse_property()?;
+            let prop = self.parse_property(true)?;
             return Ok(Some(prop));
         }
         Ok(None)
     }
 
+    fn try_parse_varying(&mut self, token: &Token) -> Result<Option<tree::Property>, Error>
+    {
+        if token == &Token::Varying {
+            let prop = self.parse_property(false)?;
+            return Ok(Some(prop));
+        }
+        Ok(None)
+    }
+
+    fn try_parse_root_constants(&mut self, token: &Token) -> Result<Option<tree::Struct>, Error>
+    {
+        if token == &Token::RootConst {
+            let st = self.parse_struct()?;
+            return Ok(Some(st));
+        }
+        Ok(None)
+    }
+
     fn check_block_end(&mut self) -> Result<bool, Error>
     {
         if let Some(TokenEntry { token, .. }) = self.tokens.front() {
@@ -203,34 +455,50 @@ impl Parser
     {
         self.pop_expect(TokenType::Struct)?;
         let token = self.pop_expect(TokenType::Identifier)?;
+        let span = self.span();
         let name = token.identifier().unwrap(); // SAFETY: we have tested for identifier in pop_expect so no panic possible here!
-        let token = self.pop()?;
-        let attr;
-        match token {
+        let mut token = self.pop()?;
+        let mut base = None;
+        if token == Token::Extends {
+            let token1 = self.pop_expect(TokenType::Identifier)?;
+            base = token1.identifier(); // SAFETY: we have tested for identifier in pop_expect so this is always Some!
+            token = self.pop()?;
+        }
+        let (attr, deprecated) = match token {
             Token::Colon => {
-                let ident = self.pop_expect(TokenType::Identifier)?;
-                attr = Some(ident.identifier().unwrap()); // SAFETY: we have tested for identifier in pop_expect so no panic possible here!
-                self.pop_expect(TokenType::BlockStart)?;
+                let (attr, deprecated, token) = self.parse_attributes()?;
+                match token {
+                    Token::BlockStart => (),
+                    Token::Colon if deprecated.is_some() => return Err(Error::new(self.cur_line, self.cur_column, Type::MisplacedDeprecated)),
+                    _ => return Err(Error::new(self.cur_line, self.cur_column, Type::UnexpectedToken {
+                        expected: TokenType::BlockStart,
+                        actual: token
+                    }))
+                }
+                (attr, deprecated)
             },
-            Token::BlockStart => attr = None,
+            Token::BlockStart => (None, None),
             _ => return Err(Error::new(
                 self.cur_line,
                 self.cur_column,
                 Type::UnexpectedToken {
-                    expected: TokenType::combined([TokenType::Colon, TokenType::BlockStart]),
+                    expected: match base {
+                        Some(_) => TokenType::combined([TokenType::Colon, TokenType::BlockStart]),
+                        None => TokenType::combined([TokenType::Extends, TokenType::Colon, TokenType::BlockStart])
+                    },
                     actual: token
                 }
             ))
-        }
+        };
         let mut props = Vec::new();
         loop {
-            let prop = self.parse_property()?;
+            let prop = self.parse_property(false)?;
             props.push(prop);
             if self.check_block_end()? {
                 break;
             }
         }
-        Ok(tree::Struct { name, attr, props })
+        Ok(tree::Struct { name, base, attr, deprecated, props, span })
     }
 
     fn try_parse_const(&mut self, token: &Token) -> Result<Option<tree::Root>, Error>
@@ -241,8 +509,8 @@ impl Parser
                     let st = self.parse_struct()?;
                     return Ok(Some(tree::Root::ConstantBuffer(st)));
                 } else {
-                    let prop = self.parse_property()?;
-                    return Ok(Some(tree::Root::Constant(prop)));
+                    let prop = self.parse_property(false)?;
+                    return Ok(Some(self.constant_root(prop)));
                 }
             }
             return Err(Error::new(self.cur_line, self.cur_column, Type::Eof));
@@ -250,6 +518,41 @@ impl Parser
         Ok(None)
     }
 
+    fn constant_root(&mut self, prop: tree::Property) -> tree::Root
+    {
+        let is_int = (prop.ptype == "int" || prop.ptype == "uint")
+            && prop.ptype_arr.is_none()
+            && prop.ptype_attr.is_none()
+            && prop.pattr.is_none();
+        match prop.default {
+            Some(tree::DefaultValue::Value(tree::Value::Int(value))) if is_int => {
+                self.constants.insert(prop.pname.clone(), Some(value));
+                tree::Root::ConstantInt(tree::ConstantInt {
+                    ptype: prop.ptype,
+                    name: prop.pname,
+                    value,
+                    span: prop.span
+                })
+            },
+            _ => {
+                self.constants.insert(prop.pname.clone(), None);
+                tree::Root::Constant(prop)
+            }
+        }
+    }
+
+    /// Replaces an identifier naming an integer constant by the value of that constant.
+    fn resolve_value(&self, value: tree::Value) -> tree::Value
+    {
+        match value {
+            tree::Value::Identifier(name) => match self.constants.get(&name) {
+                Some(Some(val)) => tree::Value::Int(*val),
+                _ => tree::Value::Identifier(name)
+            },
+            value => value
+        }
+    }
+
     fn try_parse_vformat(&mut self, token: &Token) -> Result<Option<tree::Struct>, Error>
     {
         if token == &Token::Vformat {
@@ -267,6 +570,7 @@ impl Parser
             Token::Int(i) => Ok(tree::Value::Int(i)),
             Token::Bool(b) => Ok(tree::Value::Bool(b)),
             Token::Identifier(s) => Ok(tree::Value::Identifier(s)),
+            Token::String(s) => Ok(tree::Value::String(s)),
             _ => Err(Error::new(
                 self.cur_line,
                 self.cur_column,
@@ -275,7 +579,8 @@ impl Parser
                         TokenType::Float,
                         TokenType::Int,
                         TokenType::Bool,
-                        TokenType::Identifier
+                        TokenType::Identifier,
+                        TokenType::String
                     ]),
                     actual: token
                 }
@@ -291,6 +596,7 @@ impl Parser
         match token {
             Token::Eq => {
                 let value = self.parse_pipeline_val()?;
+                let value = self.resolve_value(value);
                 self.pop_expect(TokenType::Break)?;
                 Ok(tree::Variable {
                     name,
@@ -304,6 +610,7 @@ impl Parser
                 let member = token.identifier().unwrap(); // SAFETY: we have tested for identifier in pop_expect so no panic possible here!
                 self.pop_expect(TokenType::Eq)?;
                 let value = self.parse_pipeline_val()?;
+                let value = self.resolve_value(value);
                 self.pop_expect(TokenType::Break)?;
                 Ok(tree::Variable {
                     name,
@@ -322,26 +629,50 @@ impl Parser
         }
     }
 
-    fn parse_varlist(&mut self) -> Result<tree::VariableList, Error>
+    fn parse_varlist(&mut self, inherit: bool) -> Result<tree::VariableList, Error>
     {
         let token = self.pop_expect(TokenType::Identifier)?;
+        let span = self.span();
         let name = token.identifier().unwrap(); // SAFETY: we have tested for identifier in pop_expect so no panic possible here!
-        self.pop_expect(TokenType::BlockStart)?;
+        let token = self.pop()?;
+        let base;
+        match token {
+            Token::Colon if inherit => {
+                let token = self.pop_expect(TokenType::Identifier)?;
+                base = Some(token.identifier().unwrap()); // SAFETY: we have tested for identifier in pop_expect so no panic possible here!
+                self.pop_expect(TokenType::BlockStart)?;
+            },
+            Token::BlockStart => base = None,
+            _ => return Err(Error::new(
+                self.cur_line,
+                self.cur_column,
+                Type::UnexpectedToken {
+                    expected: match inherit {
+                        true => TokenType::combined([TokenType::Colon, TokenType::BlockStart]),
+                        false => TokenType::BlockStart
+                    },
+                    actual: token
+                }
+            ))
+        }
         let mut vars = Vec::new();
-        loop {
-            let var = self.parse_var()?;
-            vars.push(var);
-            if self.check_block_end()? {
-                break;
+        //A varlist inheriting from a base may leave every variable to the base.
+        if base.is_none() || !self.check_block_end()? {
+            loop {
+                let var = self.parse_var()?;
+                vars.push(var);
+                if self.check_block_end()? {
+                    break;
+                }
             }
         }
-        Ok(tree::VariableList { name, vars })
+        Ok(tree::VariableList { name, base, vars, span })
     }
 
     fn try_parse_pipeline(&mut self, token: &Token) -> Result<Option<tree::VariableList>, Error>
     {
         if token == &Token::Pipeline {
-            let varlist = self.parse_varlist()?;
+            let varlist = self.parse_varlist(true)?;
             return Ok(Some(varlist));
         }
         Ok(None)
@@ -350,45 +681,109 @@ impl Parser
     fn try_parse_blendfunc(&mut self, token: &Token) -> Result<Option<tree::VariableList>, Error>
     {
         if token == &Token::Blendfunc {
-            let varlist = self.parse_varlist()?;
+            let varlist = self.parse_varlist(false)?;
             return Ok(Some(varlist));
         }
         Ok(None)
     }
 
+    fn try_parse_samplerstate(&mut self, token: &Token) -> Result<Option<tree::VariableList>, Error>
+    {
+        if token == &Token::SamplerState {
+            let varlist = self.parse_varlist(false)?;
+            return Ok(Some(varlist));
+        }
+        Ok(None)
+    }
+
+    /// Calls the visitor on the statement which was just parsed, converting a panic into an error.
+    fn visit<V: Visitor, F: FnOnce(&mut V) -> Result<(), V::Error>>(&mut self, visitor: &mut V, f: F) -> Result<(), ParserOrVisitor<V::Error>>
+    {
+        let index = self.stmt_index;
+        //The statement is fully consumed before the visitor runs so that the token queue is
+        //positioned on the next statement whatever the outcome.
+        self.stmt_index += 1;
+        //AssertUnwindSafe: the parser itself is not touched by the visitor and the visitor is
+        //dropped by parse on error; see the panic policy of Visitor for borrowed visitors.
+        match std::panic::catch_unwind(AssertUnwindSafe(|| f(visitor))) {
+            Ok(res) => res.map_err(ParserOrVisitor::Visitor),
+            Err(payload) => Err(ParserOrVisitor::Panic(VisitorPanic::new(index, self.stmt_line, self.stmt_column, payload)))
+        }
+    }
+
+    fn parse_statement<V: Visitor>(&mut self, v: TokenEntry, visitor: &mut V) -> Result<(), ParserOrVisitor<V::Error>>
+    {
+        self.stmt_line = v.line;
+        self.stmt_column = v.col;
+        self.depth = 0;
+        self.track(&v.token);
+        if let Some(elem) = self.try_parse_use(&v.token).map_err(ParserOrVisitor::Parser)? {
+            self.visit(visitor, |v| v.visit_use(elem))?;
+        } else if let Some(elem) = self.try_parse_resource(&v.token).map_err(ParserOrVisitor::Parser)? {
+            self.visit(visitor, |v| v.visit_resource(elem))?;
+        } else if let Some(elem) = self.try_parse_output(&v.token).map_err(ParserOrVisitor::Parser)? {
+            self.visit(visitor, |v| v.visit_output(elem))?;
+        } else if let Some(elem) = self.try_parse_varying(&v.token).map_err(ParserOrVisitor::Parser)? {
+            self.visit(visitor, |v| v.visit_varying(elem))?;
+        } else if let Some(elem) = self.try_parse_vformat(&v.token).map_err(ParserOrVisitor::Parser)? {
+            self.visit(visitor, |v| v.visit_vertex_format(elem))?;
+        } else if let Some(elem) = self.try_parse_root_constants(&v.token).map_err(ParserOrVisitor::Parser)? {
+            self.visit(visitor, |v| v.visit_root_constants(elem))?;
+        } else if let Some(elem) = self.try_parse_pipeline(&v.token).map_err(ParserOrVisitor::Parser)? {
+            self.visit(visitor, |v| v.visit_pipeline(elem))?;
+        } else if let Some(elem) = self.try_parse_blendfunc(&v.token).map_err(ParserOrVisitor::Parser)? {
+            self.visit(visitor, |v| v.visit_blendfunc(elem))?;
+        } else if let Some(elem) = self.try_parse_samplerstate(&v.token).map_err(ParserOrVisitor::Parser)? {
+            self.visit(visitor, |v| v.visit_samplerstate(elem))?;
+        } else if let Some(elem) = self.try_parse_const(&v.token).map_err(ParserOrVisitor::Parser)? {
+            self.visit(visitor, |v| match elem {
+                tree::Root::Constant(elem) => v.visit_constant(elem),
+                tree::Root::ConstantInt(elem) => v.visit_constant_int(elem),
+                tree::Root::ConstantBuffer(elem) => v.visit_constant_buffer(elem),
+                //SAFETY: this can't be reached as try_parse_const returns either constant, integer constant or constant buffer
+                _ => unsafe { std::hint::unreachable_unchecked() }
+            })?;
+        } else {
+            return Err(ParserOrVisitor::Parser(Error::new(v.line, v.col, Type::UnknownToken(v.token))));
+        }
+        Ok(())
+    }
+
     pub fn parse<V: Visitor>(&mut self, mut visitor: V) -> Result<V, ParserOrVisitor<V::Error>>
     {
         while let Some(v) = self.tokens.pop_front() {
-            if let Some(elem) = self.try_parse_use(&v.token).map_err(ParserOrVisitor::Parser)? {
-                visitor.visit_use(elem).map_err(ParserOrVisitor::Visitor)?;
-            } else if let Some(elem) = self.try_parse_output(&v.token).map_err(ParserOrVisitor::Parser)? {
-                visitor.visit_output(elem).map_err(ParserOrVisitor::Visitor)?;
-            } else if let Some(elem) = self.try_parse_vformat(&v.token).map_err(ParserOrVisitor::Parser)? {
-                visitor.visit_vertex_format(elem).map_err(ParserOrVisitor::Visitor)?;
-            } else if let Some(elem) = self.try_parse_pipeline(&v.token).map_err(ParserOrVisitor::Parser)? {
-                visitor.visit_pipeline(elem).map_err(ParserOrVisitor::Visitor)?;
-            } else if let Some(elem) = self.try_parse_blendfunc(&v.token).map_err(ParserOrVisitor::Parser)? {
-                visitor.visit_blendfunc(elem).map_err(ParserOrVisitor::Visitor)?;
-            } else if let Some(elem) = self.try_parse_const(&v.token).map_err(ParserOrVisitor::Parser)? {
-                match elem {
-                    tree::Root::Constant(elem) => visitor.visit_constant(elem),
-                    tree::Root::ConstantBuffer(elem) => visitor.visit_constant_buffer(elem),
-                    //SAFETY: this can't be reached as try_parse_const returns either constant or constant buffer
-                    _ => unsafe { std::hint::unreachable_unchecked() }
-                }.map_err(ParserOrVisitor::Visitor)?;
-            } else {
-                return Err(ParserOrVisitor::Parser(Error::new(v.line, v.col, Type::UnknownToken(v.token))));
-            }
+            self.parse_statement(v, &mut visitor)?;
         }
         Ok(visitor)
     }
+
+    /// Parses all statements, recovering from syntax errors: the remaining tokens of a statement
+    /// which fails to parse are skipped and parsing continues with the next statement.
+    ///
+    /// Returns every syntax error found. A visitor error or panic still stops parsing, it is
+    /// returned along with the syntax errors found before it.
+    pub fn parse_all<V: Visitor>(&mut self, mut visitor: V) -> (Result<V, ParserOrVisitor<V::Error>>, Vec<Error>)
+    {
+        let mut errors = Vec::new();
+        while let Some(v) = self.tokens.pop_front() {
+            match self.parse_statement(v, &mut visitor) {
+                Ok(()) => (),
+                Err(ParserOrVisitor::Parser(e)) => {
+                    errors.push(e);
+                    self.recover();
+                },
+                Err(e) => return (Err(e), errors)
+            }
+        }
+        (Ok(visitor), errors)
+    }
 }
 
 #[cfg(test)]
 mod tests
 {
     use super::*;
-    use crate::parser::tree::{Property, Root, Struct, Use, Value, Variable, VariableList};
+    use crate::parser::tree::{Attribute, ConstantInt, DefaultValue, Property, Resource, Root, Span, Struct, Use, Value, Variable, VariableList};
     use crate::parser::VecVisitor;
 
     #[test]
@@ -414,49 +809,76 @@ mod tests
                 pname: "DeltaTime".into(),
                 ptype: "float".into(),
                 ptype_arr: None,
+                deprecated: None,
                 pattr: None,
-                ptype_attr: None
+                ptype_attr: None,
+                pformat: None,
+                default: None,
+                span: Default::default()
             }),
             Root::Constant(Property {
                 pname: "FrameCount".into(),
                 ptype: "uint".into(),
                 ptype_arr: None,
+                deprecated: None,
                 pattr: None,
-                ptype_attr: None
+                ptype_attr: None,
+                pformat: None,
+                default: None,
+                span: Default::default()
             }),
             Root::Constant(Property {
                 pname: "ModelViewMatrix".into(),
                 ptype: "mat3f".into(),
                 ptype_arr: None,
+                deprecated: None,
                 pattr: None,
-                ptype_attr: None
+                ptype_attr: None,
+                pformat: None,
+                default: None,
+                span: Default::default()
             }),
             Root::Constant(Property {
                 pname: "ProjectionMatrix".into(),
                 ptype: "mat3f".into(),
                 ptype_arr: None,
+                deprecated: None,
                 pattr: None,
-                ptype_attr: None
+                ptype_attr: None,
+                pformat: None,
+                default: None,
+                span: Default::default()
             }),
             Root::ConstantBuffer(Struct {
                 name: "PerMaterial".into(),
+                base: None,
+                deprecated: None,
                 attr: None,
                 props: vec![
                     Property {
                         pname: "BaseColor".into(),
                         ptype: "vec4f".into(),
                         ptype_arr: None,
+                        deprecated: None,
                         pattr: None,
-                        ptype_attr: None
+                        ptype_attr: None,
+                        pformat: None,
+                        default: None,
+                        span: Default::default()
                     },
                     Property {
                         pname: "UvMultiplier".into(),
                         ptype: "float".into(),
                         ptype_arr: None,
+                        deprecated: None,
                         pattr: None,
-                        ptype_attr: None
+                        ptype_attr: None,
+                        pformat: None,
+                        default: None,
+                        span: Default::default()
                     },
-                ]
+                ],
+                span: Default::default()
             }),
         ];
         assert_eq!(roots, expected_roots);
@@ -486,49 +908,76 @@ mod tests
                 pname: "BaseSampler".into(),
                 ptype: "Sampler".into(),
                 ptype_arr: None,
+                deprecated: None,
                 pattr: None,
-                ptype_attr: None
+                ptype_attr: None,
+                pformat: None,
+                default: None,
+                span: Default::default()
             }),
             Root::Constant(Property {
                 pname: "BaseTexture".into(),
                 ptype: "Texture2D".into(),
                 ptype_arr: None,
+                deprecated: None,
                 pattr: Some("BaseSampler".into()),
-                ptype_attr: Some("vec4f".into())
+                ptype_attr: Some("vec4f".into()),
+                pformat: None,
+                default: None,
+                span: Default::default()
             }),
             Root::Constant(Property {
                 pname: "NoiseTexture".into(),
                 ptype: "Texture2D".into(),
                 ptype_arr: None,
+                deprecated: None,
                 pattr: Some("BaseSampler".into()),
-                ptype_attr: Some("float".into())
+                ptype_attr: Some("float".into()),
+                pformat: None,
+                default: None,
+                span: Default::default()
             }),
             Root::ConstantBuffer(Struct {
                 name: "PerMaterial".into(),
+                base: None,
+                deprecated: None,
                 attr: Some("ORDER_1".into()),
                 props: vec![
                     Property {
                         pname: "BaseColor".into(),
                         ptype: "vec4f".into(),
                         ptype_arr: None,
+                        deprecated: None,
                         pattr: None,
-                        ptype_attr: None
+                        ptype_attr: None,
+                        pformat: None,
+                        default: None,
+                        span: Default::default()
                     },
                     Property {
                         pname: "Specular".into(),
                         ptype: "float".into(),
                         ptype_arr: None,
+                        deprecated: None,
                         pattr: Some("Pack".into()),
-                        ptype_attr: None
+                        ptype_attr: None,
+                        pformat: None,
+                        default: None,
+                        span: Default::default()
                     },
                     Property {
                         pname: "UvMultiplier".into(),
                         ptype: "float".into(),
                         ptype_arr: None,
+                        deprecated: None,
                         pattr: Some("Pack".into()),
-                        ptype_attr: None
+                        ptype_attr: None,
+                        pformat: None,
+                        default: None,
+                        span: Default::default()
                     },
-                ]
+                ],
+                span: Default::default()
             }),
         ];
         assert_eq!(roots, expected_roots);
@@ -549,49 +998,110 @@ mod tests
         let expected_roots = vec![
             Root::ConstantBuffer(Struct {
                 name: "Light".into(),
+                base: None,
+                deprecated: None,
                 attr: Some("Pack".into()),
                 props: vec![
                     Property {
                         pname: "color".into(),
                         ptype: "vec4f".into(),
                         ptype_arr: None,
+                        deprecated: None,
                         pattr: None,
-                        ptype_attr: None
+                        ptype_attr: None,
+                        pformat: None,
+                        default: None,
+                        span: Default::default()
                     },
                     Property {
                         pname: "attenuation".into(),
                         ptype: "float".into(),
                         ptype_arr: None,
+                        deprecated: None,
                         pattr: None,
-                        ptype_attr: None
+                        ptype_attr: None,
+                        pformat: None,
+                        default: None,
+                        span: Default::default()
                     }
-                ]
+                ],
+                span: Default::default()
             }),
             Root::ConstantBuffer(Struct {
                 name: "Lighting".into(),
+                base: None,
+                deprecated: None,
                 attr: None,
                 props: vec![
                     Property {
                         pname: "count".into(),
                         ptype: "uint".into(),
                         ptype_arr: None,
+                        deprecated: None,
                         pattr: None,
-                        ptype_attr: None
+                        ptype_attr: None,
+                        pformat: None,
+                        default: None,
+                        span: Default::default()
                     },
                     Property {
                         pname: "lights".into(),
                         ptype: "Light".into(),
                         ptype_arr: Some(32),
+                        deprecated: None,
                         pattr: None,
-                        ptype_attr: None
+                        ptype_attr: None,
+                        pformat: None,
+                        default: None,
+                        span: Default::default()
                     }
-                ]
+                ],
+                span: Default::default()
             })
         ];
         assert_eq!(roots, expected_roots);
         assert!(parser.tokens.is_empty());
     }
 
+    #[test]
+    fn parser_texture_arrays()
+    {
+        let source_code = b"
+            const Texture2D:vec4f[8] ShadowMaps : ShadowSampler;
+        ";
+        let mut lexer = Lexer::new();
+        lexer.process(source_code).unwrap();
+        let mut parser = Parser::new(lexer);
+        let roots = parser.parse(VecVisitor::new()).unwrap().into_inner();
+        let expected_roots = vec![
+            Root::Constant(Property {
+                pname: "ShadowMaps".into(),
+                ptype: "Texture2D".into(),
+                ptype_arr: Some(8),
+                deprecated: None,
+                pattr: Some(Attribute {
+                    name: "ShadowSampler".into(),
+                    value: None
+                }),
+                ptype_attr: Some("vec4f".into()),
+                pformat: None,
+                default: None,
+                span: Default::default()
+            })
+        ];
+        assert_eq!(roots, expected_roots);
+        let source_code = b"
+            const Texture2D[2]:vec4f[8] ShadowMaps;
+        ";
+        let mut lexer = Lexer::new();
+        lexer.process(source_code).unwrap();
+        let mut parser = Parser::new(lexer);
+        match parser.parse(VecVisitor::new()) {
+            Err(ParserOrVisitor::Parser(e)) => assert_eq!(e.etype, Type::NestedArray),
+            _ => panic!("expected a parser error")
+        }
+    }
+
     #[test]
     fn basic_output()
     {
@@ -606,8 +1116,193 @@ mod tests
             pname: "FragColor".into(),
             ptype: "vec4f".into(),
             ptype_arr: None,
+            deprecated: None,
             pattr: None,
-            ptype_attr: None
+            ptype_attr: None,
+            pformat: None,
+            default: None,
+            span: Default::default()
+        })];
+        assert_eq!(roots, expected_roots);
+        assert!(parser.tokens.is_empty());
+    }
+
+    #[test]
+    fn output_format()
+    {
+        let source_code = b"
+            output vec4f Normal : TARGET_1 : RGBA16F;
+        ";
+        let mut lexer = Lexer::new();
+        lexer.process(source_code).unwrap();
+        let mut parser = Parser::new(lexer);
+        let roots = parser.parse(VecVisitor::new()).unwrap().into_inner();
+        let expected_roots = vec![Root::Output(Property {
+            pname: "Normal".into(),
+            ptype: "vec4f".into(),
+            ptype_arr: None,
+            deprecated: None,
+            pattr: Some("TARGET_1".into()),
+            pformat: Some("RGBA16F".into()),
+            ptype_attr: None,
+            default: None,
+            span: Default::default()
+        })];
+        assert_eq!(roots, expected_roots);
+        //Only outputs take a second attribute.
+        let mut lexer = Lexer::new();
+        lexer.process(b"varying vec4f Normal : Flat : RGBA16F;").unwrap();
+        let mut parser = Parser::new(lexer);
+        match parser.parse(VecVisitor::new()) {
+            Err(ParserOrVisitor::Parser(e)) => assert!(matches!(e.etype, Type::UnexpectedToken { actual: Token::Colon, .. })),
+            _ => panic!("expected a parser error")
+        }
+    }
+
+    #[test]
+    fn basic_varying()
+    {
+        let source_code = b"
+            varying vec2f Uv;
+            varying vec3f Normal : Flat;
+        ";
+        let mut lexer = Lexer::new();
+        lexer.process(source_code).unwrap();
+        let mut parser = Parser::new(lexer);
+        let roots = parser.parse(VecVisitor::new()).unwrap().into_inner();
+        let expected_roots = vec![
+            Root::Varying(Property {
+                pname: "Uv".into(),
+                ptype: "vec2f".into(),
+                ptype_arr: None,
+                deprecated: None,
+                pattr: None,
+                ptype_attr: None,
+                pformat: None,
+                default: None,
+                span: Default::default()
+            }),
+            Root::Varying(Property {
+                pname: "Normal".into(),
+                ptype: "vec3f".into(),
+                ptype_arr: None,
+                deprecated: None,
+                pattr: Some("Flat".into()),
+                ptype_attr: None,
+                pformat: None,
+                default: None,
+                span: Default::default()
+            })
+        ];
+        assert_eq!(roots, expected_roots);
+        assert!(parser.tokens.is_empty());
+    }
+
+    #[test]
+    fn basic_root_constants()
+    {
+        let source_code = b"
+            rootconst struct Root
+            {
+                mat4f ModelView;
+            }
+        ";
+        let mut lexer = Lexer::new();
+        lexer.process(source_code).unwrap();
+        let mut parser = Parser::new(lexer);
+        let roots = parser.parse(VecVisitor::new()).unwrap().into_inner();
+        let expected_roots = vec![Root::RootConstants(Struct {
+            name: "Root".into(),
+            base: None,
+            deprecated: None,
+            attr: None,
+            props: vec![Property {
+                pname: "ModelView".into(),
+                ptype: "mat4f".into(),
+                ptype_arr: None,
+                deprecated: None,
+                pattr: None,
+                ptype_attr: None,
+                pformat: None,
+                default: None,
+                span: Default::default()
+            }],
+            span: Default::default()
+        })];
+        assert_eq!(roots, expected_roots);
+        assert!(parser.tokens.is_empty());
+    }
+
+    #[test]
+    fn extends()
+    {
+        let source_code = b"
+            const struct PerObject extends CommonHeader : ORDER_1 { mat4f Model; }
+            const struct PerView extends CommonHeader { vec3f CameraPos; }
+        ";
+        let mut lexer = Lexer::new();
+        lexer.process(source_code).unwrap();
+        let mut parser = Parser::new(lexer);
+        let roots = parser.parse(VecVisitor::new()).unwrap().into_inner();
+        match &roots[..] {
+            [Root::ConstantBuffer(a), Root::ConstantBuffer(b)] => {
+                assert_eq!(a.base.as_deref(), Some("CommonHeader"));
+                assert_eq!(a.attr, Some("ORDER_1".into()));
+                assert_eq!(b.base.as_deref(), Some("CommonHeader"));
+                assert_eq!(b.attr, None);
+            },
+            _ => panic!("expected 2 constant buffers")
+        }
+        let mut lexer = Lexer::new();
+        lexer.process(b"const struct A extends { float X; }").unwrap();
+        let res = Parser::new(lexer).parse(VecVisitor::new());
+        assert!(matches!(res, Err(ParserOrVisitor::Parser(Error { etype: Type::UnexpectedToken { actual: Token::BlockStart, .. }, .. }))));
+    }
+
+    #[test]
+    fn default_values()
+    {
+        let source_code = b"
+            const struct Material
+            {
+                float Specular = 0.5;
+                vec4f BaseColor : Pack = vec4f(1, 1, 1, 1);
+            }
+        ";
+        let mut lexer = Lexer::new();
+        lexer.process(source_code).unwrap();
+        let mut parser = Parser::new(lexer);
+        let roots = parser.parse(VecVisitor::new()).unwrap().into_inner();
+        let expected_roots = vec![Root::ConstantBuffer(Struct {
+            name: "Material".into(),
+            base: None,
+            deprecated: None,
+            attr: None,
+            props: vec![
+                Property {
+                    pname: "Specular".into(),
+                    ptype: "float".into(),
+                    ptype_arr: None,
+                    deprecated: None,
+                    pattr: None,
+                    ptype_attr: None,
+                    pformat: None,
+                    default: Some(DefaultValue::Value(Value::Float(0.5))),
+                    span: Default::default()
+                },
+                Property {
+                    pname: "BaseColor".into(),
+                    ptype: "vec4f".into(),
+                    ptype_arr: None,
+                    deprecated: None,
+                    pattr: Some("Pack".into()),
+                    ptype_attr: None,
+                    pformat: None,
+                    default: Some(DefaultValue::Constructor("vec4f".into(), vec![Value::Int(1); 4])),
+                    span: Default::default()
+                }
+            ],
+            span: Default::default()
         })];
         assert_eq!(roots, expected_roots);
         assert!(parser.tokens.is_empty());
@@ -628,14 +1323,21 @@ mod tests
         let roots = parser.parse(VecVisitor::new()).unwrap().into_inner();
         let expected_roots = vec![Root::VertexFormat(Struct {
             name: "Vertex".into(),
+            base: None,
+            deprecated: None,
             attr: None,
             props: vec![Property {
                 pname: "Pos".into(),
                 ptype: "vec3f".into(),
                 ptype_arr: None,
+                deprecated: None,
                 pattr: None,
-                ptype_attr: None
-            }]
+                ptype_attr: None,
+                pformat: None,
+                default: None,
+                span: Default::default()
+            }],
+            span: Default::default()
         })];
         assert_eq!(roots, expected_roots);
         assert!(parser.tokens.is_empty());
@@ -653,10 +1355,74 @@ mod tests
         let roots = parser.parse(VecVisitor::new()).unwrap().into_inner();
         let expected_roots = vec![Root::Use(Use {
             member: "test".into(),
-            module: "module".into()
+            path: vec!["module".into()],
+            span: Span::default()
+        })];
+        assert_eq!(roots, expected_roots);
+        assert!(parser.tokens.is_empty());
+    }
+
+    #[test]
+    fn nested_use()
+    {
+        let source_code = b"
+            use engine::lighting::PerLight;
+        ";
+        let mut lexer = Lexer::new();
+        lexer.process(source_code).unwrap();
+        let mut parser = Parser::new(lexer);
+        let roots = parser.parse(VecVisitor::new()).unwrap().into_inner();
+        let expected_roots = vec![Root::Use(Use {
+            member: "PerLight".into(),
+            path: vec!["engine".into(), "lighting".into()],
+            span: Span::default()
+        })];
+        assert_eq!(roots, expected_roots);
+        match &roots[0] {
+            Root::Use(u) => assert_eq!(u.span, Span::new(2, 16)),
+            _ => unreachable!()
+        }
+    }
+
+    #[test]
+    fn empty_use_segment()
+    {
+        let source_code = b"
+            const float Alpha;
+            use a::::b;
+        ";
+        let mut lexer = Lexer::new();
+        lexer.process(source_code).unwrap();
+        let mut parser = Parser::new(lexer);
+        match parser.parse(VecVisitor::new()) {
+            Err(ParserOrVisitor::Parser(e)) => {
+                assert_eq!((e.line, e.col), (3, 20));
+                assert!(matches!(e.etype, Type::UnexpectedToken { expected: TokenType::Identifier, actual: Token::Colon }));
+            },
+            _ => panic!("expected a parser error")
+        }
+    }
+
+    #[test]
+    fn basic_resource()
+    {
+        let source_code = b"
+            resource \"luts/curves.bin\" as CurveLut;
+        ";
+        let mut lexer = Lexer::new();
+        lexer.process(source_code).unwrap();
+        let mut parser = Parser::new(lexer);
+        let roots = parser.parse(VecVisitor::new()).unwrap().into_inner();
+        let expected_roots = vec![Root::Resource(Resource {
+            path: "luts/curves.bin".into(),
+            name: "CurveLut".into()
         })];
         assert_eq!(roots, expected_roots);
         assert!(parser.tokens.is_empty());
+        let mut lexer = Lexer::new();
+        lexer.process(b"resource \"curves.bin\" CurveLut;").unwrap();
+        let mut parser = Parser::new(lexer);
+        assert!(parser.parse(VecVisitor::new()).is_err());
     }
 
     #[test]
@@ -677,6 +1443,7 @@ mod tests
         let roots = parser.parse(VecVisitor::new()).unwrap().into_inner();
         let expected_roots = vec![Root::Pipeline(VariableList {
             name: "Test".into(),
+            base: None,
             vars: vec![
                 Variable {
                     member: None,
@@ -698,12 +1465,47 @@ mod tests
                     name: "Val4".into(),
                     value: Value::Identifier("AnIdent".into())
                 },
-            ]
+            ],
+            span: Default::default()
         })];
         assert_eq!(roots, expected_roots);
         assert!(parser.tokens.is_empty());
     }
 
+    #[test]
+    fn string_varlist()
+    {
+        let source_code = b"
+            pipeline Test
+            {
+                Label = \"hello world\";
+                Path =\"shaders/{a;b}\";
+            }
+        ";
+        let mut lexer = Lexer::new();
+        lexer.process(source_code).unwrap();
+        let mut parser = Parser::new(lexer);
+        let roots = parser.parse(VecVisitor::new()).unwrap().into_inner();
+        let expected_roots = vec![Root::Pipeline(VariableList {
+            name: "Test".into(),
+            base: None,
+            vars: vec![
+                Variable {
+                    member: None,
+                    name: "Label".into(),
+                    value: Value::String("hello world".into())
+                },
+                Variable {
+                    member: None,
+                    name: "Path".into(),
+                    value: Value::String("shaders/{a;b}".into())
+                }
+            ],
+            span: Default::default()
+        })];
+        assert_eq!(roots, expected_roots);
+    }
+
     #[test]
     fn complex_varlist()
     {
@@ -721,6 +1523,7 @@ mod tests
         let roots = parser.parse(VecVisitor::new()).unwrap().into_inner();
         let expected_roots = vec![Root::Pipeline(VariableList {
             name: "Test".into(),
+            base: None,
             vars: vec![
                 Variable {
                     member: Some("member1".into()),
@@ -737,9 +1540,304 @@ mod tests
                     name: "Val2".into(),
                     value: Value::Int(12)
                 },
-            ]
+            ],
+            span: Default::default()
         })];
         assert_eq!(roots, expected_roots);
         assert!(parser.tokens.is_empty());
     }
+
+    #[test]
+    fn inherited_varlist()
+    {
+        let source_code = b"
+            pipeline Main : OpaqueDefault
+            {
+                CullingMode = Disabled;
+            }
+            pipeline Copy : OpaqueDefault { }
+        ";
+        let mut lexer = Lexer::new();
+        lexer.process(source_code).unwrap();
+        let mut parser = Parser::new(lexer);
+        let roots = parser.parse(VecVisitor::new()).unwrap().into_inner();
+        let expected_roots = vec![
+            Root::Pipeline(VariableList {
+                name: "Main".into(),
+                base: Some("OpaqueDefault".into()),
+                vars: vec![Variable {
+                    member: None,
+                    name: "CullingMode".into(),
+                    value: Value::Identifier("Disabled".into())
+                }],
+                span: Default::default()
+            }),
+            Root::Pipeline(VariableList {
+                name: "Copy".into(),
+                base: Some("OpaqueDefault".into()),
+                vars: Vec::new(),
+                span: Default::default()
+            })
+        ];
+        assert_eq!(roots, expected_roots);
+        assert!(parser.tokens.is_empty());
+        for source_code in [&b"pipeline Empty { }"[..], b"blendfunc Add : Base { ColorOp = Add; }"] {
+            let mut lexer = Lexer::new();
+            lexer.process(source_code).unwrap();
+            let mut parser = Parser::new(lexer);
+            assert!(parser.parse(VecVisitor::new()).is_err());
+        }
+    }
+
+    #[test]
+    fn deprecated_attribute()
+    {
+        let source_code = b"
+            const float Time : deprecated(\"use FrameTime instead\");
+            const struct PerFrame : ORDER_1 : deprecated(\"use PerView; will be removed\") { float Exposure; }
+            output vec4f Color : ORDER_1 : deprecated : RGBA8;
+        ";
+        let mut lexer = Lexer::new();
+        lexer.process(source_code).unwrap();
+        let mut parser = Parser::new(lexer);
+        let roots = parser.parse(VecVisitor::new()).unwrap().into_inner();
+        let expected_roots = vec![
+            Root::Constant(Property {
+                pname: "Time".into(),
+                ptype: "float".into(),
+                ptype_arr: None,
+                pattr: None,
+                deprecated: Some(Attribute {
+                    name: "deprecated".into(),
+                    value: Some("use FrameTime instead".into())
+                }),
+                ptype_attr: None,
+                pformat: None,
+                default: None,
+                span: Default::default()
+            }),
+            Root::ConstantBuffer(Struct {
+                name: "PerFrame".into(),
+                base: None,
+                attr: Some("ORDER_1".into()),
+                deprecated: Some(Attribute {
+                    name: "deprecated".into(),
+                    value: Some("use PerView; will be removed".into())
+                }),
+                props: vec![Property {
+                    pname: "Exposure".into(),
+                    ptype: "float".into(),
+                    ptype_arr: None,
+                    pattr: None,
+                    deprecated: None,
+                    ptype_attr: None,
+                    pformat: None,
+                    default: None,
+                    span: Default::default()
+                }],
+                span: Default::default()
+            }),
+            Root::Output(Property {
+                pname: "Color".into(),
+                ptype: "vec4f".into(),
+                ptype_arr: None,
+                pattr: Some("ORDER_1".into()),
+                deprecated: Some("deprecated".into()),
+                ptype_attr: None,
+                pformat: Some("RGBA8".into()),
+                default: None,
+                span: Default::default()
+            })
+        ];
+        assert_eq!(roots, expected_roots);
+        assert!(parser.tokens.is_empty());
+        //deprecated comes last and only once.
+        for source_code in [
+            &b"const struct S : deprecated : Pack { float A; }"[..],
+            b"const float X : deprecated : ORDER_1;",
+            b"const float X : ORDER_1 : deprecated : deprecated;",
+            b"output vec4f Color : deprecated : deprecated;"
+        ] {
+            let mut lexer = Lexer::new();
+            lexer.process(source_code).unwrap();
+            match Parser::new(lexer).parse(VecVisitor::new()) {
+                Err(ParserOrVisitor::Parser(e)) => assert_eq!(e.etype, Type::MisplacedDeprecated),
+                _ => panic!("expected a parser error")
+            }
+        }
+        //The format of a deprecated output is not an attribute.
+        let mut lexer = Lexer::new();
+        lexer.process(b"output vec4f Color : deprecated : RGBA8;").unwrap();
+        let roots = Parser::new(lexer).parse(VecVisitor::new()).unwrap().into_inner();
+        assert!(matches!(&roots[0], Root::Output(p) if p.pformat.as_deref() == Some("RGBA8") && p.deprecated.is_some()));
+    }
+
+    struct PanicVisitor
+    {
+        count: usize
+    }
+
+    impl PanicVisitor
+    {
+        fn visit(&mut self) -> Result<(), ()>
+        {
+            self.count += 1;
+            if self.count == 3 {
+                panic!("statement {} rejected", self.count);
+            }
+            Ok(())
+        }
+    }
+
+    impl Visitor for PanicVisitor
+    {
+        type Error = ();
+
+        fn visit_constant(&mut self, _: Property) -> Result<(), ()> { self.visit() }
+        fn visit_constant_int(&mut self, _: ConstantInt) -> Result<(), ()> { self.visit() }
+        fn visit_constant_buffer(&mut self, _: Struct) -> Result<(), ()> { self.visit() }
+        fn visit_output(&mut self, _: Property) -> Result<(), ()> { self.visit() }
+        fn visit_varying(&mut self, _: Property) -> Result<(), ()> { self.visit() }
+        fn visit_vertex_format(&mut self, _: Struct) -> Result<(), ()> { self.visit() }
+        fn visit_root_constants(&mut self, _: Struct) -> Result<(), ()> { self.visit() }
+        fn visit_use(&mut self, _: Use) -> Result<(), ()> { self.visit() }
+        fn visit_resource(&mut self, _: Resource) -> Result<(), ()> { self.visit() }
+        fn visit_pipeline(&mut self, _: VariableList) -> Result<(), ()> { self.visit() }
+        fn visit_blendfunc(&mut self, _: VariableList) -> Result<(), ()> { self.visit() }
+        fn visit_samplerstate(&mut self, _: VariableList) -> Result<(), ()> { self.visit() }
+    }
+
+    #[test]
+    fn visitor_panic()
+    {
+        let source_code = b"
+            const float A;
+            const float B;
+            const struct C { float Value; }
+            output vec4f D;
+        ";
+        let mut lexer = Lexer::new();
+        lexer.process(source_code).unwrap();
+        let mut parser = Parser::new(lexer);
+        match parser.parse(PanicVisitor { count: 0 }) {
+            Err(ParserOrVisitor::Panic(e)) => {
+                assert_eq!(e.statement, 2);
+                assert_eq!(e.line, 4);
+                assert_eq!(e.message, "statement 3 rejected");
+            },
+            _ => panic!("expected a visitor panic")
+        }
+        //The panicking statement has been consumed: parsing resumes with the next one.
+        let roots = parser.parse(VecVisitor::new()).unwrap().into_inner();
+        assert_eq!(roots.len(), 1);
+        assert!(matches!(roots[0], Root::Output(_)));
+        assert!(parser.tokens.is_empty());
+    }
+
+    #[test]
+    fn spans()
+    {
+        let source_code = b"
+            const float A;
+            const struct B {
+                vec4f Color;
+            }
+            pipeline C { DepthEnable = true; }
+        ";
+        let mut lexer = Lexer::new();
+        lexer.process(source_code).unwrap();
+        let mut parser = Parser::new(lexer);
+        let roots = parser.parse(VecVisitor::new()).unwrap().into_inner();
+        match &roots[..] {
+            [Root::Constant(a), Root::ConstantBuffer(b), Root::Pipeline(c)] => {
+                assert_eq!(a.span, Span::new(2, 24));
+                assert_eq!(b.span, Span::new(3, 27));
+                assert_eq!(b.props[0].span, Span::new(4, 22));
+                assert_eq!(c.span, Span::new(6, 23));
+            },
+            _ => panic!("unexpected roots: {:?}", roots)
+        }
+    }
+
+    #[test]
+    fn constant_ints()
+    {
+        let source_code = b"
+            const uint MAX_LIGHTS = 32;
+            const int Offset = -1;
+            const struct Lights
+            {
+                vec4f[MAX_LIGHTS] Colors;
+            }
+            blendfunc Blend { SrcColor = Offset; }
+        ";
+        let mut lexer = Lexer::new();
+        lexer.process(source_code).unwrap();
+        let mut parser = Parser::new(lexer);
+        let roots = parser.parse(VecVisitor::new()).unwrap().into_inner();
+        match &roots[..] {
+            [Root::ConstantInt(a), Root::ConstantInt(b), Root::ConstantBuffer(c), Root::Blendfunc(d)] => {
+                assert_eq!(a, &ConstantInt { ptype: "uint".into(), name: "MAX_LIGHTS".into(), value: 32, span: Default::default() });
+                assert_eq!(b.value, -1);
+                assert_eq!(c.props[0].ptype_arr, Some(32));
+                assert_eq!(d.vars[0].value, Value::Int(-1));
+            },
+            _ => panic!("unexpected roots: {:?}", roots)
+        }
+    }
+
+    #[test]
+    fn constant_int_errors()
+    {
+        let cases: [(&[u8], Type); 3] = [
+            (b"const struct A { vec4f[N] B; } const uint N = 4;", Type::UnknownConstant("N".into())),
+            (b"const float N; const struct A { vec4f[N] B; }", Type::NotIntConstant("N".into())),
+            (b"const uint N = 2.5; const struct A { vec4f[N] B; }", Type::NotIntConstant("N".into()))
+        ];
+        for (source_code, etype) in cases {
+            let mut lexer = Lexer::new();
+            lexer.process(source_code).unwrap();
+            let mut parser = Parser::new(lexer);
+            match parser.parse(VecVisitor::new()) {
+                Err(ParserOrVisitor::Parser(e)) => assert_eq!(e.etype, etype),
+                _ => panic!("expected a parser error")
+            }
+        }
+    }
+
+    #[test]
+    fn recovery()
+    {
+        let source_code = b"
+            const float A;
+            const float;
+            const struct B {
+                vec4f Color;
+                float 3;
+            }
+            const float C;
+            pipeline D { DepthEnable = ; }
+            const vec4f E;
+        ";
+        let mut lexer = Lexer::new();
+        lexer.process(source_code).unwrap();
+        let mut parser = Parser::new(lexer);
+        let (roots, errors) = parser.parse_all(VecVisitor::new());
+        let positions: Vec<(usize, usize)> = errors.iter().map(|e| (e.line, e.col)).collect();
+        assert_eq!(positions, [(3, 24), (6, 24), (9, 40)]);
+        assert!(matches!(&errors[1].etype, Type::UnexpectedToken { actual: Token::Int(3), .. }));
+        //Strict parsing stops at the first error.
+        let mut lexer = Lexer::new();
+        lexer.process(source_code).unwrap();
+        match Parser::new(lexer).parse(VecVisitor::new()) {
+            Err(ParserOrVisitor::Parser(e)) => assert_eq!(e, errors[0]),
+            _ => panic!("expected a parser error")
+        }
+        let roots = roots.unwrap().into_inner();
+        let names: Vec<&str> = roots.iter().map(|v| match v {
+            Root::Constant(v) => v.pname.as_str(),
+            _ => panic!("unexpected root: {:?}", v)
+        }).collect();
+        assert_eq!(names, ["A", "C", "E"]);
+    }
 }
@@ -33,6 +33,7 @@ pub struct UnexpectedToken
     pub expected: Token
 }*/
 
+use std::any::Any;
 use std::fmt::{Debug, Display, Formatter};
 use crate::lexer::token::{Token, Type as TokenType};
 
@@ -46,6 +47,14 @@ pub enum Type
     },
     UnknownToken(Token),
     NegativeArraySize(i32),
+    NestedArray,
+    /// An array size refers to a constant which is not declared before it.
+    UnknownConstant(String),
+    /// An array size refers to a constant which is not an integer constant.
+    NotIntConstant(String),
+    /// A `deprecated` attribute which does not follow the other attribute of the symbol or which is
+    /// repeated.
+    MisplacedDeprecated,
     Eof
 }
 
@@ -57,7 +66,11 @@ impl Display for Type
             Type::UnexpectedToken { actual, expected } => write!(f, "unexpected token (expected {}, got {})", expected, actual),
             Type::UnknownToken(token) => write!(f, "unknown token ({})", token),
             Type::Eof => f.write_str("unexpected EOF"),
-            Type::NegativeArraySize(i) => write!(f, "negative array size ({})", i)
+            Type::NegativeArraySize(i) => write!(f, "negative array size ({})", i),
+            Type::NestedArray => f.write_str("arrays of arrays are not supported"),
+            Type::UnknownConstant(name) => write!(f, "unknown constant '{}' (constants must be declared before use)", name),
+            Type::NotIntConstant(name) => write!(f, "constant '{}' is not an integer constant", name),
+            Type::MisplacedDeprecated => f.write_str("deprecated must be the last attribute of a symbol")
         }
     }
 }
@@ -86,9 +99,49 @@ impl Display for Error
     }
 }
 
+/// A panic raised by a visitor, see the panic policy of [Visitor](crate::parser::Visitor).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VisitorPanic
+{
+    /// Index of the statement being visited, counted from the first statement of the parser.
+    pub statement: usize,
+    pub line: usize,
+    pub col: usize,
+    pub message: String
+}
+
+impl VisitorPanic
+{
+    pub fn new(statement: usize, line: usize, col: usize, payload: Box<dyn Any + Send>) -> Self
+    {
+        let message = match payload.downcast::<String>() {
+            Ok(v) => *v,
+            Err(payload) => match payload.downcast_ref::<&str>() {
+                Some(v) => String::from(*v),
+                None => String::from("unknown panic payload")
+            }
+        };
+        Self {
+            statement,
+            line,
+            col,
+            message
+        }
+    }
+}
+
+impl Display for VisitorPanic
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+    {
+        write!(f, "{}:{} visitor panicked on statement {}: {}", self.line, self.col, self.statement, self.message)
+    }
+}
+
 #[derive(Debug)]
 pub enum ParserOrVisitor<T>
 {
     Parser(Error),
-    Visitor(T)
+    Visitor(T),
+    Panic(VisitorPanic)
 }
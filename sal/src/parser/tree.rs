@@ -26,29 +26,161 @@
 // NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
 // SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
+use std::fmt::{Display, Formatter};
+
+/// Position of a node in the source code, as reported by the lexer for its first token.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Span
+{
+    pub line: usize,
+    pub col: usize
+}
+
+impl Span
+{
+    pub fn new(line: usize, col: usize) -> Self
+    {
+        Self { line, col }
+    }
+}
+
+impl Display for Span
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+    {
+        write!(f, "{}:{}", self.line, self.col)
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Attribute
+{
+    pub name: String,
+    pub value: Option<String>
+}
+
+impl From<String> for Attribute
+{
+    fn from(name: String) -> Self
+    {
+        Attribute { name, value: None }
+    }
+}
+
+impl From<&str> for Attribute
+{
+    fn from(name: &str) -> Self
+    {
+        Attribute::from(String::from(name))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DefaultValue
+{
+    Value(Value),
+    /// Vector constructor such as `vec4f(1, 1, 1, 1)`.
+    Constructor(String, Vec<Value>)
+}
+
+#[derive(Debug, Clone)]
 pub struct Property
 {
     pub ptype: String,
+    /// Everything after the first colon of the type: the sample type of a texture (ex: `vec4f`),
+    /// followed by the sample count for multisample textures (ex: `vec4f:4`).
     pub ptype_attr: Option<String>,
     pub ptype_arr: Option<u32>,
     pub pname: String,
-    pub pattr: Option<String>
+    pub pattr: Option<Attribute>,
+    /// The `deprecated` attribute, which may follow the other attribute (`: ORDER_1 : deprecated`).
+    pub deprecated: Option<Attribute>,
+    /// Second attribute of an output: the format hint of the render target (ex: `RGBA16F`).
+    pub pformat: Option<String>,
+    pub default: Option<DefaultValue>,
+    pub span: Span
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+//Spans only locate a node in the source code, two nodes parsed from different places are still equal.
+impl PartialEq for Property
+{
+    fn eq(&self, other: &Self) -> bool
+    {
+        self.ptype == other.ptype
+            && self.ptype_attr == other.ptype_attr
+            && self.ptype_arr == other.ptype_arr
+            && self.pname == other.pname
+            && self.pattr == other.pattr
+            && self.deprecated == other.deprecated
+            && self.pformat == other.pformat
+            && self.default == other.default
+    }
+}
+
+/// Integer constant with an initializer, such as `const uint MAX_LIGHTS = 32;`.
+#[derive(Debug, Clone)]
+pub struct ConstantInt
+{
+    pub ptype: String,
+    pub name: String,
+    pub value: i32,
+    pub span: Span
+}
+
+impl PartialEq for ConstantInt
+{
+    fn eq(&self, other: &Self) -> bool
+    {
+        self.ptype == other.ptype && self.name == other.name && self.value == other.value
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Struct
 {
     pub name: String,
+    /// Struct whose properties are placed before the properties of this struct.
+    pub base: Option<String>,
     pub props: Vec<Property>,
-    pub attr: Option<String>
+    pub attr: Option<Attribute>,
+    /// See [Property::deprecated].
+    pub deprecated: Option<Attribute>,
+    pub span: Span
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+impl PartialEq for Struct
+{
+    fn eq(&self, other: &Self) -> bool
+    {
+        self.name == other.name && self.base == other.base && self.props == other.props && self.attr == other.attr
+            && self.deprecated == other.deprecated
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Use
 {
-    pub module: String,
-    pub member: String
+    /// Module path segments, e.g. `["engine", "lighting"]` for `use engine::lighting::PerLight;`.
+    pub path: Vec<String>,
+    pub member: String,
+    pub span: Span
+}
+
+impl PartialEq for Use
+{
+    fn eq(&self, other: &Self) -> bool
+    {
+        self.path == other.path && self.member == other.member
+    }
+}
+
+impl Eq for Use {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Resource
+{
+    pub path: String,
+    pub name: String
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -57,7 +189,8 @@ pub enum Value
     Int(i32),
     Float(f32),
     Bool(bool),
-    Identifier(String)
+    Identifier(String),
+    String(String)
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -68,21 +201,36 @@ pub struct Variable
     pub value: Value
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone)]
 pub struct VariableList
 {
     pub name: String,
-    pub vars: Vec<Variable>
+    pub base: Option<String>,
+    pub vars: Vec<Variable>,
+    pub span: Span
+}
+
+impl PartialEq for VariableList
+{
+    fn eq(&self, other: &Self) -> bool
+    {
+        self.name == other.name && self.base == other.base && self.vars == other.vars
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Root
 {
     Constant(Property),
+    ConstantInt(ConstantInt),
     ConstantBuffer(Struct),
     Output(Property),
+    Varying(Property),
     VertexFormat(Struct),
+    RootConstants(Struct),
     Use(Use),
+    Resource(Resource),
     Pipeline(VariableList),
-    Blendfunc(VariableList)
+    Blendfunc(VariableList),
+    SamplerState(VariableList)
 }
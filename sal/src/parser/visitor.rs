@@ -26,19 +26,32 @@
 // NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
 // SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
-use crate::parser::tree::{Property, Struct, Use, VariableList};
+use crate::parser::tree::{ConstantInt, Property, Resource, Struct, Use, VariableList};
 use super::tree;
 
+/// Receives the statements produced by [Parser](crate::parser::Parser).
+///
+/// # Panics
+///
+/// A panic raised by a visit function is caught by the parser and returned as
+/// [ParserOrVisitor::Panic](crate::parser::error::ParserOrVisitor::Panic); the parser stays usable
+/// and resumes with the next statement. The visitor itself is dropped with the error unless it was
+/// passed by reference, in which case it may be left partially updated.
 pub trait Visitor
 {
     type Error;
     fn visit_constant(&mut self, val: tree::Property) -> Result<(), Self::Error>;
+    fn visit_constant_int(&mut self, val: tree::ConstantInt) -> Result<(), Self::Error>;
     fn visit_constant_buffer(&mut self, val: tree::Struct) -> Result<(), Self::Error>;
     fn visit_output(&mut self, val: tree::Property) -> Result<(), Self::Error>;
+    fn visit_varying(&mut self, val: tree::Property) -> Result<(), Self::Error>;
     fn visit_vertex_format(&mut self, val: tree::Struct) -> Result<(), Self::Error>;
+    fn visit_root_constants(&mut self, val: tree::Struct) -> Result<(), Self::Error>;
     fn visit_use(&mut self, val: tree::Use) -> Result<(), Self::Error>;
+    fn visit_resource(&mut self, val: tree::Resource) -> Result<(), Self::Error>;
     fn visit_pipeline(&mut self, val: tree::VariableList) -> Result<(), Self::Error>;
     fn visit_blendfunc(&mut self, val: tree::VariableList) -> Result<(), Self::Error>;
+    fn visit_samplerstate(&mut self, val: tree::VariableList) -> Result<(), Self::Error>;
 }
 
 impl<'a, T: Visitor> Visitor for &'a mut T {
@@ -48,6 +61,10 @@ impl<'a, T: Visitor> Visitor for &'a mut T {
         (*self).visit_constant(val)
     }
 
+    fn visit_constant_int(&mut self, val: ConstantInt) -> Result<(), Self::Error> {
+        (*self).visit_constant_int(val)
+    }
+
     fn visit_constant_buffer(&mut self, val: Struct) -> Result<(), Self::Error> {
         (*self).visit_constant_buffer(val)
     }
@@ -56,14 +73,26 @@ impl<'a, T: Visitor> Visitor for &'a mut T {
         (*self).visit_output(val)
     }
 
+    fn visit_varying(&mut self, val: Property) -> Result<(), Self::Error> {
+        (*self).visit_varying(val)
+    }
+
     fn visit_vertex_format(&mut self, val: Struct) -> Result<(), Self::Error> {
         (*self).visit_vertex_format(val)
     }
 
+    fn visit_root_constants(&mut self, val: Struct) -> Result<(), Self::Error> {
+        (*self).visit_root_constants(val)
+    }
+
     fn visit_use(&mut self, val: Use) -> Result<(), Self::Error> {
         (*self).visit_use(val)
     }
 
+    fn visit_resource(&mut self, val: Resource) -> Result<(), Self::Error> {
+        (*self).visit_resource(val)
+    }
+
     fn visit_pipeline(&mut self, val: VariableList) -> Result<(), Self::Error> {
         (*self).visit_pipeline(val)
     }
@@ -71,6 +100,10 @@ impl<'a, T: Visitor> Visitor for &'a mut T {
     fn visit_blendfunc(&mut self, val: VariableList) -> Result<(), Self::Error> {
         (*self).visit_blendfunc(val)
     }
+
+    fn visit_samplerstate(&mut self, val: VariableList) -> Result<(), Self::Error> {
+        (*self).visit_samplerstate(val)
+    }
 }
 
 pub struct VecVisitor
@@ -102,6 +135,11 @@ impl Visitor for VecVisitor
         Ok(())
     }
 
+    fn visit_constant_int(&mut self, val: ConstantInt) -> Result<(), Self::Error> {
+        self.tree.push(tree::Root::ConstantInt(val));
+        Ok(())
+    }
+
     fn visit_constant_buffer(&mut self, val: Struct) -> Result<(), Self::Error> {
         self.tree.push(tree::Root::ConstantBuffer(val));
         Ok(())
@@ -112,16 +150,31 @@ impl Visitor for VecVisitor
         Ok(())
     }
 
+    fn visit_varying(&mut self, val: Property) -> Result<(), Self::Error> {
+        self.tree.push(tree::Root::Varying(val));
+        Ok(())
+    }
+
     fn visit_vertex_format(&mut self, val: Struct) -> Result<(), Self::Error> {
         self.tree.push(tree::Root::VertexFormat(val));
         Ok(())
     }
 
+    fn visit_root_constants(&mut self, val: Struct) -> Result<(), Self::Error> {
+        self.tree.push(tree::Root::RootConstants(val));
+        Ok(())
+    }
+
     fn visit_use(&mut self, val: Use) -> Result<(), Self::Error> {
         self.tree.push(tree::Root::Use(val));
         Ok(())
     }
 
+    fn visit_resource(&mut self, val: Resource) -> Result<(), Self::Error> {
+        self.tree.push(tree::Root::Resource(val));
+        Ok(())
+    }
+
     fn visit_pipeline(&mut self, val: VariableList) -> Result<(), Self::Error> {
         self.tree.push(tree::Root::Pipeline(val));
         Ok(())
@@ -131,4 +184,9 @@ impl Visitor for VecVisitor
         self.tree.push(tree::Root::Blendfunc(val));
         Ok(())
     }
+
+    fn visit_samplerstate(&mut self, val: VariableList) -> Result<(), Self::Error> {
+        self.tree.push(tree::Root::SamplerState(val));
+        Ok(())
+    }
 }
@@ -32,7 +32,7 @@ use crate::{
     lexer::Lexer,
     parser::Parser
 };
-use crate::ast::{AstBuilder, RefResolver, Visitor};
+use crate::ast::{AstBuilder, RefResolver, Strictness, Visitor};
 use crate::parser::error::ParserOrVisitor;
 
 #[derive(Debug)]
@@ -40,7 +40,10 @@ pub enum AutoError<T, E>
 {
     Lexer(crate::lexer::error::Error),
     Parser(crate::parser::error::Error),
-    Ast(crate::ast::error::Error<T, E>)
+    /// Every syntax error of the code, returned by [auto_parser_all].
+    Syntax(Vec<crate::parser::error::Error>),
+    Ast(crate::ast::error::Error<T, E>),
+    Panic(crate::parser::error::VisitorPanic)
 }
 
 impl<T: Display, E: Debug> Display for AutoError<T, E>
@@ -50,7 +53,10 @@ impl<T: Display, E: Debug> Display for AutoError<T, E>
         match self {
             AutoError::Lexer(e) => write!(f, "lexer error: {}", e),
             AutoError::Parser(e) => write!(f, "parser error: {}", e),
-            AutoError::Ast(e) => write!(f, "ast generation error: {}", e)
+            AutoError::Syntax(errors) => write!(f, "{} syntax error(s): {}", errors.len(),
+                                                errors.iter().map(|v| v.to_string()).collect::<Vec<_>>().join("; ")),
+            AutoError::Ast(e) => write!(f, "ast generation error: {}", e),
+            AutoError::Panic(e) => write!(f, "internal error: {}", e)
         }
     }
 }
@@ -60,7 +66,8 @@ impl<T, E> From<crate::parser::error::ParserOrVisitor<crate::ast::error::Error<T
     fn from(e: ParserOrVisitor<crate::ast::error::Error<T, E>>) -> Self {
         match e {
             ParserOrVisitor::Visitor(e) => AutoError::Ast(e),
-            ParserOrVisitor::Parser(e) => AutoError::Parser(e)
+            ParserOrVisitor::Parser(e) => AutoError::Parser(e),
+            ParserOrVisitor::Panic(e) => AutoError::Panic(e)
         }
     }
 }
@@ -68,21 +75,41 @@ impl<T, E> From<crate::parser::error::ParserOrVisitor<crate::ast::error::Error<T
 pub fn auto_lexer_parser<T: AsRef<[u8]>, A: RefResolver, V: Visitor<A>>(
     buf: T,
     ast: A,
-    visitor: V
+    visitor: V,
+    strictness: Strictness
 ) -> Result<A, AutoError<A::Key, V::Error>>
 {
     let mut lexer = Lexer::new();
     lexer.process(buf.as_ref()).map_err(AutoError::Lexer)?;
-    auto_parser(lexer, ast, visitor)
+    auto_parser(lexer, ast, visitor, strictness)
 }
 
 pub fn auto_parser<A: RefResolver, V: Visitor<A>>(
     lexer: Lexer,
     ast: A,
-    visitor: V
+    visitor: V,
+    strictness: Strictness
 ) -> Result<A, AutoError<A::Key, V::Error>>
 {
     let mut parser = Parser::new(lexer);
-    let ast = parser.parse(AstBuilder::new(ast, visitor))?.into_inner();
+    let ast = parser.parse(AstBuilder::with_strictness(ast, visitor, strictness))?.into_inner();
     Ok(ast)
 }
+
+/// Same as [auto_parser] but reports every syntax error of the code instead of the first one.
+///
+/// Syntax errors take precedence over AST errors, which are often a consequence of them.
+pub fn auto_parser_all<A: RefResolver, V: Visitor<A>>(
+    lexer: Lexer,
+    ast: A,
+    visitor: V,
+    strictness: Strictness
+) -> Result<A, AutoError<A::Key, V::Error>>
+{
+    let mut parser = Parser::new(lexer);
+    let (res, errors) = parser.parse_all(AstBuilder::with_strictness(ast, visitor, strictness));
+    if !errors.is_empty() {
+        return Err(AutoError::Syntax(errors));
+    }
+    Ok(res?.into_inner())
+}
@@ -0,0 +1,2 @@
+layout (std140) uniform Material { vec4 Material_Color;ivec2 Material_Tiling;};
+layout (std140) uniform Light { vec3 Light_Direction;float Light_Intensity;};
@@ -0,0 +1,2 @@
+layout (set = 0, binding = 1, std140) uniform Material { vec4 Material_Color;ivec2 Material_Tiling;};
+layout (set = 0, binding = 2, std140) uniform Light { vec3 Light_Direction;float Light_Intensity;};
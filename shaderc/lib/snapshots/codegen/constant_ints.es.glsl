@@ -0,0 +1,4 @@
+precision highp float;
+#define MAX_LIGHTS 32
+#define Bias -2
+layout (location = 0) out  vec4 FragColor;layout (location = 1) out uint FragId;
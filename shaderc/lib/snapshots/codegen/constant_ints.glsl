@@ -0,0 +1,3 @@
+#define MAX_LIGHTS 32
+#define Bias -2
+layout (location = 0) out  vec4 FragColor;layout (location = 1) out uint FragId;
@@ -0,0 +1,9 @@
+precision highp float;
+layout (location = 0) in  vec3 Vertex_Position;layout (location = 1) in  vec2 Vertex_Uv;
+layout (binding = 0, std140) uniform __Root { mat4 ModelView;float Time;};
+layout (location = 0) out  vec4 FragColor;layout (location = 1) out uint FragId;
+struct PointLight { vec3 Position;float Radius;};
+layout (binding = 1, std140) uniform Lights {PointLight Lights_Points[8]; mat4 Lights_Bones[4];};
+layout (binding = 0) uniform mediump sampler2D BaseTexture;
+layout (binding = 1) uniform highp sampler2DArray Shadows;
+layout (binding = 2) uniform mediump samplerCube Sky;
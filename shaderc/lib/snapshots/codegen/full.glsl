@@ -0,0 +1,8 @@
+layout (location = 0) in  vec3 Vertex_Position;layout (location = 1) in  vec2 Vertex_Uv;
+layout (std140) uniform __Root { mat4 ModelView;float Time;};
+layout (location = 0) out  vec4 FragColor;layout (location = 1) out uint FragId;
+struct PointLight { vec3 Position;float Radius;};
+layout (std140) uniform Lights {PointLight Lights_Points[8]; mat4 Lights_Bones[4];};
+uniform sampler2D BaseTexture;
+uniform sampler2DArray Shadows;
+uniform samplerCube Sky;
@@ -0,0 +1,2 @@
+precision highp float;
+layout (location = 0) out  vec4 FragColor;layout (location = 1) out uint FragId;
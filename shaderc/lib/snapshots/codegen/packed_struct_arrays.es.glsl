@@ -0,0 +1,3 @@
+precision highp float;
+struct PointLight { vec3 Position;float Radius;};
+layout (binding = 1, std140) uniform Lights {PointLight Lights_Points[8]; mat4 Lights_Bones[4];};
@@ -0,0 +1,2 @@
+struct PointLight { vec3 Position;float Radius;};
+layout (std140) uniform Lights {PointLight Lights_Points[8]; mat4 Lights_Bones[4];};
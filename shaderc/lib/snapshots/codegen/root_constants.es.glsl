@@ -0,0 +1,2 @@
+precision highp float;
+layout (binding = 0, std140) uniform __Root { mat4 ModelView;float Time;};
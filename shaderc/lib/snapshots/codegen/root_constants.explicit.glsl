@@ -0,0 +1 @@
+layout (binding = 0, std140) uniform __Root { mat4 ModelView;float Time;};
@@ -0,0 +1 @@
+layout (std140) uniform __Root { mat4 ModelView;float Time;};
@@ -0,0 +1 @@
+layout (push_constant, std140) uniform __Root { mat4 ModelView;float Time;};
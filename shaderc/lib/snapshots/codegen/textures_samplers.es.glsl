@@ -0,0 +1,4 @@
+precision highp float;
+layout (binding = 0) uniform mediump sampler2D BaseTexture;
+layout (binding = 1) uniform highp sampler2DArray Shadows;
+layout (binding = 2) uniform mediump samplerCube Sky;
@@ -0,0 +1,3 @@
+layout (binding = 0) uniform sampler2D BaseTexture;
+layout (binding = 1) uniform sampler2DArray Shadows;
+layout (binding = 2) uniform samplerCube Sky;
@@ -0,0 +1,3 @@
+uniform sampler2D BaseTexture;
+uniform sampler2DArray Shadows;
+uniform samplerCube Sky;
@@ -0,0 +1,3 @@
+layout (set = 0, binding = 0) uniform sampler2D BaseTexture;
+layout (set = 0, binding = 1) uniform sampler2DArray Shadows;
+layout (set = 0, binding = 2) uniform samplerCube Sky;
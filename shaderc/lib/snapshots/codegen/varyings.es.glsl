@@ -0,0 +1,3 @@
+precision highp float;
+in Varyings { vec2 Uv;noperspective vec3 Normal;flat uint Layer;} In[];
+out Varyings { vec2 Uv;noperspective vec3 Normal;flat uint Layer;} Out;
@@ -0,0 +1,2 @@
+in Varyings { vec2 Uv;noperspective vec3 Normal;flat uint Layer;} In[];
+out Varyings { vec2 Uv;noperspective vec3 Normal;flat uint Layer;} Out;
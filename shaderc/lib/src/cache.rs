@@ -0,0 +1,157 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! In-memory cache of loaded units, used by watch mode to only reload the units which changed.
+
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use crate::config::Unit;
+use crate::targets::basic::ShaderToSal;
+
+/// Size and modification time of a file, None for both if the file could not be read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileStamp
+{
+    pub modified: Option<SystemTime>,
+    pub size: Option<u64>
+}
+
+impl FileStamp
+{
+    pub fn get(path: &Path) -> FileStamp
+    {
+        match std::fs::metadata(path) {
+            Ok(v) => FileStamp {
+                modified: v.modified().ok(),
+                size: Some(v.len())
+            },
+            Err(_) => FileStamp {
+                modified: None,
+                size: None
+            }
+        }
+    }
+}
+
+struct Entry
+{
+    stamps: Vec<(PathBuf, FileStamp)>,
+    value: Vec<ShaderToSal>
+}
+
+impl Entry
+{
+    fn is_fresh(&self) -> bool
+    {
+        self.stamps.iter().all(|(path, stamp)| FileStamp::get(path) == *stamp)
+    }
+}
+
+/// Loaded units keyed by unit, each entry is valid as long as the path, modification time and
+/// size of all the files it was loaded from are unchanged.
+///
+/// Clones share the same entries. Warnings reported while loading a unit are not reported again
+/// when the unit is reused.
+#[derive(Clone, Default)]
+pub struct UnitCache
+{
+    entries: Arc<Mutex<HashMap<String, Entry>>>
+}
+
+fn key(unit: &Unit) -> String
+{
+    format!("{:?}", unit)
+}
+
+impl UnitCache
+{
+    pub fn new() -> UnitCache
+    {
+        UnitCache::default()
+    }
+
+    /// Returns a copy of the cached unit, None if it is not cached or one of its files changed.
+    pub(crate) fn get(&self, unit: &Unit) -> Option<Vec<ShaderToSal>>
+    {
+        let mut entries = self.entries.lock().unwrap();
+        let key = key(unit);
+        match entries.get(&key) {
+            Some(entry) if entry.is_fresh() => Some(entry.value.clone()),
+            Some(_) => {
+                entries.remove(&key);
+                None
+            },
+            None => None
+        }
+    }
+
+    pub(crate) fn insert(&self, unit: &Unit, value: &[ShaderToSal])
+    {
+        let mut stamps: Vec<(PathBuf, FileStamp)> = value.iter().flat_map(|v| v.dependencies.iter())
+            .map(|v| (v.clone(), FileStamp::get(v)))
+            .collect();
+        stamps.sort_by(|a, b| a.0.cmp(&b.0));
+        stamps.dedup_by(|a, b| a.0 == b.0);
+        self.entries.lock().unwrap().insert(key(unit), Entry {
+            stamps,
+            value: value.to_vec()
+        });
+    }
+
+    /// Returns all files the cached units were loaded from, sorted and without duplicates.
+    pub fn dependencies(&self) -> Vec<PathBuf>
+    {
+        let mut deps: Vec<PathBuf> = self.entries.lock().unwrap().values()
+            .flat_map(|v| v.stamps.iter().map(|(path, _)| path.clone()))
+            .collect();
+        deps.sort();
+        deps.dedup();
+        deps
+    }
+
+    pub fn len(&self) -> usize
+    {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.len() == 0
+    }
+}
+
+impl Debug for UnitCache
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+    {
+        write!(f, "UnitCache({} unit(s))", self.len())
+    }
+}
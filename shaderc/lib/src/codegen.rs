@@ -0,0 +1,287 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Public access to the SAL to GLSL translation used by the GL targets.
+//!
+//! The output of [translate_sal_to_glsl] is guaranteed to have the following properties:
+//! - declarations are emitted by group in this order: integer constants, vertex format inputs,
+//!   root constants, outputs, varyings, packed structures, constant buffers and finally textures;
+//!   groups are separated by a new line and empty groups are omitted,
+//! - integer constants are emitted as `#define NAME value`, one per line,
+//! - within a group, declarations keep the order of the statement collections in the AST,
+//! - root constants are emulated by a std140 uniform block named `__Root` which only contains the
+//!   properties of the root constants layout up to the last one used by the stage,
+//! - members of constant buffers and vertex formats are prefixed by the name of their structure
+//!   (ex: `Material_Color`),
+//! - when `explicit_bindings` is true, constant buffers and textures are given a
+//!   `layout (binding = N)` qualifier from their slot and `__Root` is bound to binding 0; otherwise
+//!   no binding is emitted and bindings must be assigned at runtime,
+//! - with [Bindings::Vulkan], bindings are qualified by `set = 0` and `__Root` is a push constant
+//!   block instead,
+//! - with [Bindings::Es], bindings are explicit, the output starts with `precision highp float;`
+//!   and textures are given a `mediump` (float vector textures) or `highp` precision qualifier,
+//! - varyings are emitted as an `in Varyings {...} In;` and/or an `out Varyings {...} Out;`
+//!   interface block depending on the position of the stage in the pipeline; blocks of stages
+//!   which process several vertices at once are arrays (`In[]`),
+//! - samplers do not produce any declaration as GLSL combines them with textures.
+
+pub use crate::targets::basic::{BasicAst, Slot};
+pub use crate::targets::basic::ast::VaryingInterface;
+pub use crate::targets::sal_to_glsl::{translate_sal_to_glsl, translate_sal_to_glsl_mapped, Bindings, Error, SourceMap};
+
+#[cfg(test)]
+mod tests
+{
+    use std::path::PathBuf;
+    use bp3d_sal::ast::tree::{ArrayItemType, ArrayType, Attribute, BaseType, ConstantInt, Interpolation, Property, PropertyType, Struct, TextureType, VectorType};
+    use crate::codegen::{BasicAst, Bindings, Error, Slot, translate_sal_to_glsl, VaryingInterface};
+
+    fn prop(name: &str, ptype: PropertyType<usize>) -> Property<usize>
+    {
+        Property {
+            pname: name.into(),
+            ptype,
+            pattr: None,
+            deprecated: None,
+            pformat: None,
+            default: None,
+            span: Default::default()
+        }
+    }
+
+    fn vec(item: BaseType, size: u8) -> PropertyType<usize>
+    {
+        PropertyType::Vector(VectorType { item, size })
+    }
+
+    fn slot<T>(inner: T, slot: u32) -> Slot<T>
+    {
+        let slot1 = Slot::new(inner);
+        slot1.slot.set(slot);
+        slot1
+    }
+
+    fn root_layout() -> Struct<usize>
+    {
+        Struct {
+            name: "__Root".into(),
+            attr: None,
+            deprecated: None,
+            props: vec![
+                prop("ModelView", PropertyType::Matrix(VectorType { item: BaseType::Float, size: 4 })),
+                prop("Time", PropertyType::Scalar(BaseType::Float)),
+                prop("Frame", PropertyType::Scalar(BaseType::Uint))
+            ],
+            span: Default::default()
+        }
+    }
+
+    fn cbuffers() -> BasicAst
+    {
+        let mut ast = BasicAst::new();
+        ast.cbuffers.push(slot(Struct {
+            name: "Material".into(),
+            attr: None,
+            deprecated: None,
+            props: vec![prop("Color", vec(BaseType::Float, 4)), prop("Tiling", vec(BaseType::Int, 2))],
+            span: Default::default()
+        }, 1));
+        ast.cbuffers.push(slot(Struct {
+            name: "Light".into(),
+            attr: None,
+            deprecated: None,
+            props: vec![prop("Direction", vec(BaseType::Float, 3)), prop("Intensity", PropertyType::Scalar(BaseType::Float))],
+            span: Default::default()
+        }, 2));
+        ast
+    }
+
+    fn packed_struct_arrays() -> BasicAst
+    {
+        let mut ast = BasicAst::new();
+        let id = ast.push_packed_struct("PointLight".into(), Struct {
+            name: "PointLight".into(),
+            attr: None,
+            deprecated: None,
+            props: vec![prop("Position", vec(BaseType::Float, 3)), prop("Radius", PropertyType::Scalar(BaseType::Float))],
+            span: Default::default()
+        });
+        ast.cbuffers.push(slot(Struct {
+            name: "Lights".into(),
+            attr: None,
+            deprecated: None,
+            props: vec![
+                prop("Points", PropertyType::Array(ArrayType { size: 8, item: ArrayItemType::StructRef(id) })),
+                prop("Bones", PropertyType::Array(ArrayType { size: 4, item: ArrayItemType::Matrix(VectorType { item: BaseType::Float, size: 4 }) }))
+            ],
+            span: Default::default()
+        }, 1));
+        ast
+    }
+
+    fn textures_samplers() -> BasicAst
+    {
+        let mut ast = BasicAst::new();
+        ast.objects.push(slot(prop("BaseSampler", PropertyType::Sampler), 0));
+        ast.objects.push(slot(prop("BaseTexture", PropertyType::Texture2D(TextureType::Vector(VectorType { item: BaseType::Float, size: 4 }))), 0));
+        ast.objects.push(slot(prop("Shadows", PropertyType::Texture2DArray(TextureType::Scalar(BaseType::Float))), 1));
+        ast.objects.push(slot(prop("Sky", PropertyType::TextureCube(TextureType::Vector(VectorType { item: BaseType::Float, size: 4 }))), 2));
+        ast
+    }
+
+    fn outputs() -> BasicAst
+    {
+        let mut ast = BasicAst::new();
+        ast.outputs.push(slot(prop("FragColor", vec(BaseType::Float, 4)), 0));
+        ast.outputs.push(slot(prop("FragId", PropertyType::Scalar(BaseType::Uint)), 1));
+        ast
+    }
+
+    fn root_constants() -> BasicAst
+    {
+        let mut ast = BasicAst::new();
+        // Frame is not used by the stage so it must not be emitted.
+        ast.root_constants.push(Slot::new(prop("ModelView", PropertyType::Matrix(VectorType { item: BaseType::Float, size: 4 }))));
+        ast.root_constants.push(Slot::new(prop("Time", PropertyType::Scalar(BaseType::Float))));
+        ast
+    }
+
+    fn full() -> BasicAst
+    {
+        let mut ast = packed_struct_arrays();
+        ast.vformat = Some(Struct {
+            name: "Vertex".into(),
+            attr: None,
+            deprecated: None,
+            props: vec![prop("Position", vec(BaseType::Float, 3)), prop("Uv", vec(BaseType::Float, 2))],
+            span: Default::default()
+        });
+        ast.root_constants = root_constants().root_constants;
+        ast.outputs = outputs().outputs;
+        ast.objects = textures_samplers().objects;
+        ast
+    }
+
+    //Passthrough geometry stage: reads the varyings of every input vertex and writes them again.
+    fn varyings() -> BasicAst
+    {
+        let mut ast = BasicAst::new();
+        ast.varyings.push(prop("Uv", vec(BaseType::Float, 2)));
+        ast.varyings.push(Property {
+            pattr: Some(Attribute::Interpolation(Interpolation::NoPerspective)),
+            deprecated: None,
+            ..prop("Normal", vec(BaseType::Float, 3))
+        });
+        ast.varyings.push(Property {
+            pattr: Some(Attribute::Interpolation(Interpolation::Flat)),
+            deprecated: None,
+            ..prop("Layer", PropertyType::Scalar(BaseType::Uint))
+        });
+        ast.varying_interface = VaryingInterface {
+            input: Some(true),
+            output: Some(false)
+        };
+        ast
+    }
+
+    fn constant_ints() -> BasicAst
+    {
+        let mut ast = outputs();
+        ast.constant_ints.push(Slot::new(ConstantInt { name: "MAX_LIGHTS".into(), ty: BaseType::Uint, value: 32, span: Default::default() }));
+        ast.constant_ints.push(Slot::new(ConstantInt { name: "Bias".into(), ty: BaseType::Int, value: -2, span: Default::default() }));
+        ast
+    }
+
+    type Fixture = fn() -> BasicAst;
+
+    const FIXTURES: &[(&str, Fixture)] = &[
+        ("cbuffers", cbuffers),
+        ("packed_struct_arrays", packed_struct_arrays),
+        ("textures_samplers", textures_samplers),
+        ("outputs", outputs),
+        ("root_constants", root_constants),
+        ("varyings", varyings),
+        ("constant_ints", constant_ints),
+        ("full", full)
+    ];
+
+    #[test]
+    fn snapshots()
+    {
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("snapshots/codegen");
+        let bless = std::env::var_os("BLESS").is_some();
+        let mut failures = Vec::new();
+        for (name, fixture) in FIXTURES {
+            for bindings in [Bindings::Implicit, Bindings::Explicit, Bindings::Vulkan, Bindings::Es] {
+                let glsl = translate_sal_to_glsl(bindings, &root_layout(), &fixture()).unwrap() + "\n";
+                let file = match bindings {
+                    Bindings::Implicit => dir.join(format!("{}.glsl", name)),
+                    Bindings::Explicit => dir.join(format!("{}.explicit.glsl", name)),
+                    Bindings::Vulkan => dir.join(format!("{}.vulkan.glsl", name)),
+                    Bindings::Es => dir.join(format!("{}.es.glsl", name))
+                };
+                if bless {
+                    std::fs::create_dir_all(&dir).unwrap();
+                    std::fs::write(&file, &glsl).unwrap();
+                } else if std::fs::read_to_string(&file).ok().as_deref() != Some(&*glsl) {
+                    failures.push(format!("{}:\n{}", file.display(), glsl));
+                }
+            }
+        }
+        assert!(failures.is_empty(), "codegen snapshots differ (run with BLESS=1 to update):\n{}", failures.join("\n"));
+    }
+
+    fn shadow_texture(ptype: PropertyType<usize>) -> BasicAst
+    {
+        let mut ast = BasicAst::new();
+        ast.objects.push(slot(prop("ShadowSampler", PropertyType::SamplerCmp), 0));
+        ast.objects.push(slot(Property {
+            pattr: Some(Attribute::Identifier("ShadowSampler".into())),
+            deprecated: None,
+            ..prop("ShadowMap", ptype)
+        }, 0));
+        ast
+    }
+
+    #[test]
+    fn texture_kinds()
+    {
+        let mut ast = shadow_texture(PropertyType::Texture2D(TextureType::Scalar(BaseType::Float)));
+        ast.objects.push(slot(prop("Gradient", PropertyType::Texture1D(TextureType::Vector(VectorType { item: BaseType::Float, size: 4 }))), 1));
+        ast.objects.push(slot(prop("Scene", PropertyType::Texture2DMS(TextureType::Vector(VectorType { item: BaseType::Float, size: 4 }), 4)), 2));
+        let glsl = translate_sal_to_glsl(Bindings::Explicit, &root_layout(), &ast).unwrap();
+        assert_eq!(glsl, "layout (binding = 0) uniform sampler2DShadow ShadowMap;\n\
+                          layout (binding = 1) uniform sampler1D Gradient;\n\
+                          layout (binding = 2) uniform sampler2DMS Scene;");
+        let err = translate_sal_to_glsl(Bindings::Es, &root_layout(), &ast).unwrap_err();
+        assert!(matches!(err, Error::EsTexture(name, _) if name == "Gradient"));
+        let ast = shadow_texture(PropertyType::Texture3D(TextureType::Scalar(BaseType::Float)));
+        let err = translate_sal_to_glsl(Bindings::Explicit, &root_layout(), &ast).unwrap_err();
+        assert!(matches!(err, Error::ShadowSampler(texture, sampler) if texture == "ShadowMap" && sampler == "ShadowSampler"));
+    }
+}
@@ -26,16 +26,86 @@
 // NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
 // SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
-use std::path::Path;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use crate::cache::UnitCache;
+use crate::depfile::Depfile;
+use crate::repro::Sources;
+use crate::Stage;
+use crate::warnings::Reporter;
 
-#[derive(Debug)]
+/// Default cap in bytes on the extended data of a single symbol.
+pub const DEFAULT_MAX_EXT_DATA_SIZE: usize = 65536;
+
+/// Default cap in bytes on the size of a single embedded resource.
+pub const DEFAULT_MAX_RESOURCE_SIZE: usize = 1 << 20;
+
+/// Compression of the sections written by shaderc in output packages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CompressionPreference
+{
+    None,
+    Fast,
+    Best
+}
+
+impl Default for CompressionPreference
+{
+    fn default() -> Self
+    {
+        CompressionPreference::None
+    }
+}
+
+impl CompressionPreference
+{
+    pub const NAMES: &'static [&'static str] = &["none", "fast", "best"];
+
+    pub fn from_name(name: &str) -> Option<CompressionPreference>
+    {
+        match name {
+            "none" => Some(CompressionPreference::None),
+            "fast" => Some(CompressionPreference::Fast),
+            "best" => Some(CompressionPreference::Best),
+            _ => None
+        }
+    }
+
+    pub fn name(&self) -> &'static str
+    {
+        match self {
+            CompressionPreference::None => "none",
+            CompressionPreference::Fast => "fast",
+            CompressionPreference::Best => "best"
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub enum Unit<'a>
 {
     Path(&'a Path),
+    /// A path unit with an explicit stage; it must agree with the `#stage` directive of the unit
+    /// if there is one.
+    PathWithStage(Stage, &'a Path),
     Injected(&'a str)
 }
 
-#[derive(Debug)]
+impl<'a> Unit<'a>
+{
+    /// Returns the explicitly declared stage of this unit, if any.
+    pub fn stage(&self) -> Option<Stage>
+    {
+        match self {
+            Unit::PathWithStage(stage, _) => Some(*stage),
+            _ => None
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Config<'a>
 {
     pub units: Vec<Unit<'a>>,
@@ -43,6 +113,125 @@ pub struct Config<'a>
     pub output: &'a Path,
     pub n_threads: usize,
     pub minify: bool,
+    pub strip_comments: bool,
     pub optimize: bool,
-    pub debug: bool
+    pub debug: bool,
+    pub deny_deprecated: bool,
+    pub deterministic: bool,
+    pub no_vertex_input: bool,
+    /// Skip the check of the varyings declared by consecutive stages.
+    pub no_interface_check: bool,
+    pub strict_sal: bool,
+    pub require_symbols: Option<&'a Path>,
+    pub max_ext_data_size: usize,
+    pub max_resource_size: usize,
+    pub compression: CompressionPreference,
+    pub force_write: bool,
+    pub warnings: Reporter,
+    /// Dependency file written next to a successful build.
+    pub depfile: Option<Depfile<'a>>,
+    /// JSON binding manifest written by targets producing shader packs.
+    pub manifest: Option<&'a Path>,
+    /// Directories searched by `#include` directives of GLSL code, after the directory of the
+    /// including file.
+    pub glsl_includes: Vec<&'a Path>,
+    /// Downgrade conflicting re-declarations of a symbol across stages to a warning, keeping the
+    /// first declaration (legacy behavior).
+    pub allow_duplicate_symbols: bool,
+    /// Identifiers defined for `#if` blocks of SAL code and as `#define IDENT 1` in GLSL code.
+    pub defines: Vec<String>,
+    /// Units loaded by a previous build, reused if none of their files changed.
+    pub unit_cache: Option<UnitCache>,
+    /// Prefixes rewritten in the paths persisted in outputs, as `(from, to)` pairs; the last
+    /// matching pair wins.
+    pub path_prefix_map: Vec<(&'a Path, &'a Path)>,
+    /// Timestamp recorded in the debug information of deterministic builds; filled from
+    /// SOURCE_DATE_EPOCH when the build starts.
+    pub source_date_epoch: Option<u64>,
+    /// Sources generated or resolved by the build, collected for a reproducer bundle.
+    pub sources: Option<Sources>
+}
+
+impl<'a> Config<'a>
+{
+    /// Returns a configuration building `units` into `output` single-threaded with the default
+    /// settings: no library, no optional pass and the default size caps.
+    pub fn new(units: Vec<Unit<'a>>, output: &'a Path) -> Config<'a>
+    {
+        Config {
+            units,
+            libs: Vec::new(),
+            output,
+            n_threads: 1,
+            minify: false,
+            strip_comments: false,
+            optimize: false,
+            debug: false,
+            deny_deprecated: false,
+            deterministic: false,
+            no_vertex_input: false,
+            no_interface_check: false,
+            strict_sal: false,
+            require_symbols: None,
+            max_ext_data_size: DEFAULT_MAX_EXT_DATA_SIZE,
+            max_resource_size: DEFAULT_MAX_RESOURCE_SIZE,
+            compression: CompressionPreference::None,
+            force_write: false,
+            warnings: Reporter::new(),
+            depfile: None,
+            manifest: None,
+            glsl_includes: Vec::new(),
+            allow_duplicate_symbols: false,
+            defines: Vec::new(),
+            unit_cache: None,
+            path_prefix_map: Vec::new(),
+            source_date_epoch: None,
+            sources: None
+        }
+    }
+
+    /// Returns a path as persisted in outputs, rewritten by `path_prefix_map`.
+    pub fn map_path(&self, path: &Path) -> PathBuf
+    {
+        self.path_prefix_map.iter().rev()
+            .find_map(|(from, to)| path.strip_prefix(from).ok().map(|v| match v.as_os_str().is_empty() {
+                true => to.to_path_buf(),
+                false => to.join(v)
+            }))
+            .unwrap_or_else(|| path.into())
+    }
+
+    /// Lists the settings of the build by name, as recorded in the debug information of shader
+    /// packs; settings which do not affect the output (thread count, output paths) are omitted.
+    pub fn options(&self) -> BTreeMap<String, String>
+    {
+        let paths = |list: &[&Path]| format!("{:?}", list.iter().map(|v| self.map_path(v).display().to_string()).collect::<Vec<_>>());
+        let mut options = BTreeMap::new();
+        for (name, value) in [
+            ("minify", self.minify),
+            ("strip_comments", self.strip_comments),
+            ("optimize", self.optimize),
+            ("debug", self.debug),
+            ("deny_deprecated", self.deny_deprecated),
+            ("deterministic", self.deterministic),
+            ("no_vertex_input", self.no_vertex_input),
+            ("no_interface_check", self.no_interface_check),
+            ("strict_sal", self.strict_sal),
+            ("allow_duplicate_symbols", self.allow_duplicate_symbols)
+        ] {
+            options.insert(name.into(), value.to_string());
+        }
+        options.insert("max_ext_data_size".into(), self.max_ext_data_size.to_string());
+        options.insert("max_resource_size".into(), self.max_resource_size.to_string());
+        options.insert("compression".into(), self.compression.name().into());
+        options.insert("defines".into(), format!("{:?}", self.defines));
+        options.insert("warnings".into(), format!("{:?}", self.warnings.settings()));
+        options.insert("libs".into(), paths(&self.libs));
+        options.insert("glsl_includes".into(), paths(&self.glsl_includes));
+        options.insert("require_symbols".into(), self.require_symbols.map(|v| self.map_path(v).display().to_string()).unwrap_or_else(|| "none".into()));
+        if let Some(timestamp) = self.source_date_epoch {
+            options.insert("source_date_epoch".into(), timestamp.to_string());
+        }
+        options
+    }
 }
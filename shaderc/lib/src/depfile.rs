@@ -0,0 +1,126 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use std::collections::BTreeSet;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Collects the files read by a build and writes them as a Makefile dependency file.
+///
+/// Clones share the same list so that the file can be written once the build which recorded the
+/// dependencies is over.
+#[derive(Debug, Clone)]
+pub struct Depfile<'a>
+{
+    path: &'a Path,
+    deps: Arc<Mutex<BTreeSet<PathBuf>>>
+}
+
+impl<'a> Depfile<'a>
+{
+    pub fn new(path: &'a Path) -> Depfile<'a>
+    {
+        Depfile {
+            path,
+            deps: Arc::new(Mutex::new(BTreeSet::new()))
+        }
+    }
+
+    pub fn path(&self) -> &'a Path
+    {
+        self.path
+    }
+
+    pub fn record<P: Into<PathBuf>>(&self, path: P)
+    {
+        self.deps.lock().unwrap().insert(path.into());
+    }
+
+    /// Returns all recorded dependencies, sorted and without duplicates.
+    pub fn dependencies(&self) -> Vec<PathBuf>
+    {
+        self.deps.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Formats the dependency file with `target` as the only target.
+    pub fn to_makefile(&self, target: &Path) -> String
+    {
+        let mut out = escape(target);
+        out.push(':');
+        for v in self.deps.lock().unwrap().iter() {
+            //Write to a String cannot fail.
+            write!(out, " \\\n  {}", escape(v)).unwrap();
+        }
+        out.push('\n');
+        out
+    }
+
+    pub fn write(&self, target: &Path) -> std::io::Result<()>
+    {
+        std::fs::write(self.path, self.to_makefile(target))
+    }
+}
+
+/// Escapes a path for use in a Makefile rule.
+fn escape(path: &Path) -> String
+{
+    let path = path.to_string_lossy();
+    let mut out = String::with_capacity(path.len());
+    for c in path.chars() {
+        match c {
+            ' ' | '#' => {
+                out.push('\\');
+                out.push(c);
+            },
+            '$' => out.push_str("$$"),
+            _ => out.push(c)
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::path::Path;
+    use crate::depfile::Depfile;
+
+    #[test]
+    fn makefile()
+    {
+        let depfile = Depfile::new(Path::new("out.d"));
+        depfile.record("shaders/vertex.glsl");
+        depfile.record("libs/my lib.bpx");
+        depfile.record("shaders/vertex.glsl");
+        depfile.record("$cost#1.glsl");
+        assert_eq!(depfile.dependencies().len(), 3);
+        assert_eq!(depfile.to_makefile(Path::new("build/out pack.bpx")),
+                   "build/out\\ pack.bpx: \\\n  $$cost\\#1.glsl \\\n  libs/my\\ lib.bpx \\\n  shaders/vertex.glsl\n");
+    }
+}
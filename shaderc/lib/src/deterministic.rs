@@ -0,0 +1,169 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Support for reproducible builds (--deterministic).
+
+use std::path::PathBuf;
+use thiserror::Error;
+use crate::Compiler;
+use crate::config::{Config, Unit};
+
+pub const SOURCE_DATE_EPOCH: &str = "SOURCE_DATE_EPOCH";
+
+#[derive(Debug, Error)]
+pub enum Error
+{
+    #[error("invalid {}: '{0}' is not a unix timestamp", SOURCE_DATE_EPOCH)]
+    InvalidEpoch(String),
+    #[error("output {0:?} depends on the number of threads")]
+    Mismatch(PathBuf),
+    #[error("{0:?} would be persisted as an absolute path (use --path-prefix-map)")]
+    AbsolutePath(PathBuf)
+}
+
+/// Returns the timestamp to record in persisted metadata: SOURCE_DATE_EPOCH when set, 0 otherwise.
+pub fn timestamp() -> Result<u64, Error>
+{
+    match std::env::var(SOURCE_DATE_EPOCH) {
+        Ok(v) => v.trim().parse().map_err(|_| Error::InvalidEpoch(v)),
+        Err(_) => Ok(0)
+    }
+}
+
+/// Checks that the paths persisted in the output are relative once mapped; only debug builds
+/// persist paths, the units being recorded by the names of their sources.
+pub fn check_paths(config: &Config) -> Result<(), Error>
+{
+    if !config.debug {
+        return Ok(());
+    }
+    let units = config.units.iter().filter_map(|v| match v {
+        Unit::Path(path) | Unit::PathWithStage(_, path) => Some(path),
+        Unit::Injected(_) => None
+    });
+    let paths = config.libs.iter().chain(&config.glsl_includes).chain(&config.require_symbols).chain(units);
+    for path in paths {
+        let mapped = config.map_path(path);
+        if mapped.is_absolute() {
+            return Err(Error::AbsolutePath(mapped));
+        }
+    }
+    Ok(())
+}
+
+/// Rebuilds the output of a multi-threaded build single-threaded and checks that both outputs
+/// are byte-identical.
+pub fn self_check(compiler: &Compiler, config: &Config) -> Result<(), Box<dyn std::error::Error>>
+{
+    let mut reference = config.output.as_os_str().to_owned();
+    reference.push(".check");
+    let reference = PathBuf::from(reference);
+    let mut config1 = config.clone();
+    config1.n_threads = 1;
+    config1.output = &reference;
+    //The warnings of the rebuild are the same as the first build's, do not count them twice.
+    config1.warnings = config.warnings.fork();
+    config1.unit_cache = None;
+    config1.manifest = None;
+    let res = compiler.run_once(config1).and_then(|_| {
+        let same = std::fs::read(config.output)? == std::fs::read(&reference)?;
+        Ok(same)
+    });
+    let _ = std::fs::remove_file(&reference);
+    if !res? {
+        return Err(Error::Mismatch(config.output.into()).into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::path::Path;
+    use crate::{Compiler, Config, Unit};
+    use crate::deterministic::{check_paths, Error};
+    use crate::testgen::{generate, Limits};
+
+    fn config<'a>(units: Vec<Unit<'a>>, output: &'a Path, n_threads: usize, debug: bool) -> Config<'a>
+    {
+        Config {
+            n_threads,
+            debug,
+            deterministic: true,
+            ..Config::new(units, output)
+        }
+    }
+
+    #[test]
+    fn byte_identical_builds()
+    {
+        let program = generate(42, &Limits::default());
+        for debug in [false, true] {
+            let mut outputs = Vec::new();
+            //Builds from different directories only agree once their paths are mapped.
+            for (i, n_threads) in [1, 4].into_iter().enumerate() {
+                let dir = std::env::temp_dir().join(format!("shaderc-deterministic-{}-{}-{}", std::process::id(), debug, i));
+                std::fs::create_dir_all(&dir).unwrap();
+                let (vertex, pixel) = program.write(&dir).unwrap();
+                let output = dir.join("out.bpx");
+                let mut config = config(vec![Unit::Path(&vertex), Unit::Path(&pixel)], &output, n_threads, debug);
+                config.path_prefix_map.push((dir.as_path(), Path::new("src")));
+                Compiler::get("GL40").unwrap().run(config).unwrap();
+                let data = std::fs::read(&output).unwrap();
+                let host = dir.to_string_lossy();
+                assert!(!data.windows(host.len()).any(|v| v == host.as_bytes()));
+                outputs.push(data);
+                std::fs::remove_dir_all(&dir).unwrap();
+            }
+            assert!(outputs[0] == outputs[1]);
+        }
+    }
+
+    #[test]
+    fn absolute_paths()
+    {
+        let root = std::env::temp_dir();
+        let include = root.join("include");
+        let output = root.join("out.bpx");
+        let mut config = config(Vec::new(), &output, 1, true);
+        config.glsl_includes.push(&include);
+        assert!(matches!(check_paths(&config), Err(Error::AbsolutePath(v)) if v == include));
+        let unit = root.join("vertex.glsl");
+        config.glsl_includes.clear();
+        config.units.push(Unit::Path(&unit));
+        assert!(matches!(check_paths(&config), Err(Error::AbsolutePath(v)) if v == unit));
+        config.glsl_includes.push(&include);
+        config.path_prefix_map.push((root.as_path(), Path::new(".")));
+        assert!(check_paths(&config).is_ok());
+        assert_eq!(config.options()["glsl_includes"], format!("{:?}", [Path::new(".").join("include").display().to_string()]));
+        //Release builds persist no paths.
+        config.path_prefix_map.clear();
+        config.debug = false;
+        assert!(check_paths(&config).is_ok());
+    }
+}
@@ -0,0 +1,139 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Classification of build errors, used by the command line to pick an exit code.
+
+use std::error::Error;
+use crate::targets::basic::{preprocessor, sal_compiler, shader_to_sal, shaderlib, useresolver};
+use crate::targets::gl::{bpx, contract, core};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FailureKind
+{
+    /// A file could not be read or written.
+    Io,
+    /// The shader sources or another input file are invalid.
+    Input,
+    /// glslang rejected the generated code.
+    Toolchain,
+    /// A BPX file (shader library or output) is corrupt or could not be encoded.
+    Format,
+    Other
+}
+
+fn shaderlib_kind(e: &shaderlib::Error) -> FailureKind
+{
+    match e {
+        shaderlib::Error::Io(_) => FailureKind::Io,
+        shaderlib::Error::Bpx(_) => FailureKind::Format,
+        shaderlib::Error::WrongKind(_, _) => FailureKind::Input
+    }
+}
+
+fn core_kind(e: &core::Error) -> FailureKind
+{
+    match e {
+        core::Error::Compiler | core::Error::Linker | core::Error::Spirv => FailureKind::Toolchain,
+        //Stages are ordered, the kind of the first failed stage is the kind of the build.
+        core::Error::Stages(errors) => errors.first().map(|(_, e)| core_kind(e)).unwrap_or(FailureKind::Input),
+        _ => FailureKind::Input
+    }
+}
+
+/// Returns the kind of an error returned by [Compiler::run](crate::Compiler::run).
+pub fn classify(e: &(dyn Error + 'static)) -> FailureKind
+{
+    if e.is::<std::io::Error>() {
+        return FailureKind::Io;
+    }
+    if let Some(e) = e.downcast_ref::<core::Error>() {
+        return core_kind(e);
+    }
+    if let Some(e) = e.downcast_ref::<bpx::Error>() {
+        return match e {
+            bpx::Error::Bpx(_) | bpx::Error::Serde(_, _) | bpx::Error::Size(_, _) | bpx::Error::Symbol { .. }
+            | bpx::Error::Closed | bpx::Error::Core(_) | bpx::Error::SectionOpen(_) => FailureKind::Format,
+            bpx::Error::Io(_) => FailureKind::Io,
+            _ => FailureKind::Input
+        };
+    }
+    if let Some(e) = e.downcast_ref::<shader_to_sal::Error>() {
+        return match e {
+            shader_to_sal::Error::Io(_) => FailureKind::Io,
+            shader_to_sal::Error::ShaderLib(e) => shaderlib_kind(e),
+            shader_to_sal::Error::Preprocessor(preprocessor::Error::Io(_)) => FailureKind::Io,
+            shader_to_sal::Error::Preprocessor(preprocessor::Error::ShaderLib(e)) => shaderlib_kind(e),
+            _ => FailureKind::Input
+        };
+    }
+    if let Some(e) = e.downcast_ref::<shaderlib::Error>() {
+        return shaderlib_kind(e);
+    }
+    if let Some(e) = e.downcast_ref::<contract::Error>() {
+        return match e {
+            contract::Error::Io(_) => FailureKind::Io,
+            _ => FailureKind::Input
+        };
+    }
+    if e.is::<::bpx::package::error::Error>() || e.is::<::bpx::core::error::Error>() {
+        return FailureKind::Format;
+    }
+    if e.is::<sal_compiler::Error>() || e.is::<useresolver::Error>() || e.is::<preprocessor::Error>()
+        || e.is::<crate::stage::Error>() || e.is::<crate::warnings::Error>()
+        || e.is::<crate::deterministic::Error>() {
+        return FailureKind::Input;
+    }
+    FailureKind::Other
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::error::Error;
+    use crate::failure::{classify, FailureKind};
+    use crate::targets::gl::{bpx, core};
+
+    fn kind<E: Error + 'static>(e: E) -> FailureKind
+    {
+        let e: Box<dyn Error> = Box::new(e);
+        classify(&*e)
+    }
+
+    #[test]
+    fn kinds()
+    {
+        assert_eq!(kind(std::io::Error::new(std::io::ErrorKind::NotFound, "missing")), FailureKind::Io);
+        assert_eq!(kind(core::Error::Compiler), FailureKind::Toolchain);
+        assert_eq!(kind(core::Error::Int64), FailureKind::Input);
+        assert_eq!(kind(core::Error::Stages(vec![(crate::Stage::Vertex, core::Error::Compiler), (crate::Stage::Pixel, core::Error::Int64)])), FailureKind::Toolchain);
+        assert_eq!(kind(bpx::Error::Closed), FailureKind::Format);
+        assert_eq!(kind(bpx::Error::IllegalObject), FailureKind::Input);
+        assert_eq!(kind(crate::warnings::Error::Denied(2)), FailureKind::Input);
+        assert_eq!(kind(std::fmt::Error), FailureKind::Other);
+    }
+}
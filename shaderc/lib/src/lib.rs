@@ -26,20 +26,49 @@
 // NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
 // SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
+use std::collections::BTreeMap;
 use std::error::Error;
 use phf::phf_map;
 
 mod targets;
 mod config;
+pub mod stage;
+pub mod repro;
+pub mod deterministic;
+pub mod codegen;
+pub mod warnings;
+pub mod preview;
+pub mod depfile;
+pub mod cache;
+pub mod failure;
+mod output;
+#[cfg(any(test, feature = "testgen"))]
+pub mod testgen;
 
 pub use config::*;
+pub use stage::Stage;
+pub use output::Output;
+pub use preview::{compile_stage_preview, PreviewEnv};
+pub use bp3d_symbols::TargetInfo;
 
 type TargetFunc = fn(Config) -> Result<(), Box<dyn Error>>;
+type PreviewFunc = fn(&Config, BTreeMap<Stage, targets::basic::ShaderStage>, Stage) -> Result<preview::PreviewResult, preview::CompileError>;
 
-static TARGETS: phf::Map<&'static str, TargetFunc> = phf_map! {
-    "LIB" => targets::lib::build,
-    "GL40" => targets::gl40::build,
-    "GL42" => targets::gl42::build
+struct TargetEntry
+{
+    build: TargetFunc,
+    info: fn() -> TargetInfo,
+    preview: Option<PreviewFunc>
+}
+
+static TARGETS: phf::Map<&'static str, TargetEntry> = phf_map! {
+    "LIB" => TargetEntry { build: targets::lib::build, info: targets::lib::info, preview: None },
+    "GL40" => TargetEntry { build: targets::gl40::build, info: targets::gl40::info, preview: Some(targets::gl40::preview) },
+    "GL42" => TargetEntry { build: targets::gl42::build, info: targets::gl42::info, preview: Some(targets::gl42::preview) },
+    "ES31" => TargetEntry { build: targets::es31::build, info: targets::es31::info, preview: Some(targets::es31::preview) },
+    //SPIR-V modules are only generated after linking, which previews never do.
+    "GL46" => TargetEntry { build: targets::gl46::build, info: targets::gl46::info, preview: None },
+    "VK" => TargetEntry { build: targets::vk::build, info: targets::vk::info, preview: None }
 };
 
 pub struct Compiler
@@ -50,7 +79,7 @@ pub struct Compiler
 impl Compiler {
     /// Returns an instance of a compiler, None if no compiler exists for the required target.
     pub fn get(target_name: &str) -> Option<Compiler> {
-        TARGETS.get(target_name).map(|v| Compiler { func: *v })
+        TARGETS.get(target_name).map(|v| Compiler { func: v.build })
     }
 
     /// List available compiler targets.
@@ -58,8 +87,111 @@ impl Compiler {
         TARGETS.keys().map(|v| *v)
     }
 
-    /// Run the compiler with the given config.
-    pub fn run(&self, config: Config) -> Result<(), Box<dyn Error>> {
+    /// Returns the capabilities of a target, None if no compiler exists for it.
+    pub fn info(target_name: &str) -> Option<TargetInfo> {
+        TARGETS.get(target_name).map(|v| (v.info)())
+    }
+
+    /// Returns the capabilities of all available targets, sorted by name.
+    pub fn list() -> impl Iterator<Item = TargetInfo> {
+        let mut names: Vec<&'static str> = Self::list_targets().collect();
+        names.sort();
+        names.into_iter().map(|v| (TARGETS[v].info)())
+    }
+
+    fn run_once(&self, config: Config) -> Result<(), Box<dyn Error>> {
         (self.func)(config)
     }
+
+    /// Run the compiler with the given config; the output is only replaced once the build succeeded
+    /// and, unless `force_write` is set, only if its content changed.
+    pub fn run(&self, config: Config) -> Result<Output, Box<dyn Error>> {
+        let temp = output::temp_path(config.output);
+        let force_write = config.force_write;
+        let dest = config.output;
+        let warnings = config.warnings.clone();
+        let depfile = config.depfile.clone();
+        let res = self.build(Config { output: &temp, ..config });
+        warnings.flush();
+        if let Err(e) = res.and_then(|_| Ok(warnings.check()?)) {
+            let _ = std::fs::remove_file(&temp);
+            return Err(e);
+        }
+        let output = output::finish(&temp, dest, force_write)?;
+        if let Some(depfile) = depfile {
+            depfile.write(dest)?;
+        }
+        Ok(output)
+    }
+
+    fn build(&self, mut config: Config) -> Result<(), Box<dyn Error>> {
+        if !config.deterministic {
+            return self.run_once(config);
+        }
+        config.source_date_epoch = Some(deterministic::timestamp()?);
+        deterministic::check_paths(&config)?;
+        let check = if config.n_threads > 1 { Some(config.clone()) } else { None };
+        self.run_once(config)?;
+        if let Some(config) = check {
+            deterministic::self_check(self, &config)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use bp3d_symbols::TargetList;
+    use crate::Compiler;
+
+    #[test]
+    fn target_info()
+    {
+        let names: Vec<String> = Compiler::list().map(|v| v.name).collect();
+        assert_eq!(names, ["ES31", "GL40", "GL42", "GL46", "LIB", "VK"]);
+        for name in Compiler::list_targets() {
+            let info = Compiler::info(name).unwrap();
+            assert_eq!(info.name, name);
+            assert_eq!(info.api.is_some(), info.version.is_some());
+            //Targets producing shaders always support at least one viewport.
+            assert_eq!(info.api.is_some(), info.max_viewports > 0);
+            //Only OpenGL (ES) targets ship GLSL which the optimization pass works on.
+            assert_eq!(info.supports_optimize, matches!(info.api.as_deref(), Some("OpenGL" | "OpenGL ES")));
+            assert_eq!(info.supports_minify, info.supports_optimize);
+            assert_eq!(info.api.is_some(), info.bpx_target.is_some());
+            assert_eq!(info.api.is_some(), !info.supported_stages.is_empty());
+        }
+        assert!(Compiler::info("GL42").unwrap().supports_int64);
+        assert!(!Compiler::info("GL40").unwrap().supports_int64);
+        let vk = Compiler::info("VK").unwrap();
+        assert!(vk.supports_spirv);
+        assert_eq!(vk.payload_format, "SPIR-V");
+        assert_eq!(vk.bpx_target.as_deref(), Some("VK10"));
+        assert_eq!(vk.supported_stages, ["vertex", "hull", "domain", "geometry", "pixel"]);
+        assert!(!Compiler::info("LIB").unwrap().supports_debug);
+        assert!(!Compiler::info("GL42").unwrap().supports_spirv);
+        let gl46 = Compiler::info("GL46").unwrap();
+        assert_eq!(gl46.api.as_deref(), Some("OpenGL"));
+        assert!(gl46.supports_spirv);
+        assert_eq!(gl46.payload_format, "SPIR-V");
+        assert_eq!(gl46.bpx_target.as_deref(), Some("GL46"));
+        let es31 = Compiler::info("ES31").unwrap();
+        assert_eq!(es31.api.as_deref(), Some("OpenGL ES"));
+        assert_eq!(es31.payload_format, "GLSL");
+        assert!(Compiler::info("VK13").is_none());
+        let list = TargetList::new(Compiler::list().collect());
+        let json = serde_json::to_value(&list).unwrap();
+        assert_eq!(json["schema_version"], 1);
+        assert_eq!(json["targets"][2]["version"], "4.2");
+        assert_eq!(serde_json::from_value::<TargetList>(json).unwrap(), list);
+        //Lists written before the stage and BPX target fields existed still deserialize.
+        let old = serde_json::json!({"schema_version": 1, "targets": [{
+            "name": "GL40", "api": "OpenGL", "version": "4.0", "supports_debug": true, "supports_optimize": true,
+            "supports_spirv": false, "supports_compute": false, "supports_int64": false,
+            "payload_format": "GLSL", "max_viewports": 16
+        }]});
+        let old: TargetList = serde_json::from_value(old).unwrap();
+        assert!(old.targets[0].supported_stages.is_empty());
+    }
 }
@@ -0,0 +1,118 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use std::ffi::OsString;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// What happened to the output of a successful build.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Output
+{
+    /// The output was (re)written.
+    Written,
+    /// The output already had the same content and was left untouched.
+    Unchanged
+}
+
+pub fn temp_path(output: &Path) -> PathBuf
+{
+    let mut path = OsString::from(output.as_os_str());
+    path.push(".tmp");
+    PathBuf::from(path)
+}
+
+fn same_content(temp: &Path, output: &Path) -> std::io::Result<bool>
+{
+    // Packs carry no timestamp nor build id so identical symbols and code always produce identical
+    // bytes.
+    let old = match std::fs::read(output) {
+        Ok(v) => v,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e)
+    };
+    Ok(old == std::fs::read(temp)?)
+}
+
+/// Moves the freshly built pack at `temp` to `output` unless `output` already has the same content.
+pub fn finish(temp: &Path, output: &Path, force_write: bool) -> std::io::Result<Output>
+{
+    if !force_write && same_content(temp, output)? {
+        std::fs::remove_file(temp)?;
+        return Ok(Output::Unchanged);
+    }
+    std::fs::rename(temp, output)?;
+    Ok(Output::Written)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::fs::File;
+    use std::path::Path;
+    use std::time::{Duration, SystemTime};
+    use crate::{Compiler, Config, Output, Unit};
+
+    fn build(unit: &Path, output: &Path, force_write: bool) -> Output
+    {
+        let config = Config {
+            force_write,
+            ..Config::new(vec![Unit::Path(unit)], output)
+        };
+        Compiler::get("LIB").unwrap().run(config).unwrap()
+    }
+
+    fn age(output: &Path) -> SystemTime
+    {
+        let old = SystemTime::now() - Duration::from_secs(3600);
+        File::options().write(true).open(output).unwrap().set_modified(old).unwrap();
+        std::fs::metadata(output).unwrap().modified().unwrap()
+    }
+
+    #[test]
+    fn unchanged()
+    {
+        let dir = std::env::temp_dir().join(format!("shaderc-output-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let unit = dir.join("unit.sal");
+        let output = dir.join("out.bpx");
+        std::fs::write(&unit, "const float Value;").unwrap();
+        assert_eq!(build(&unit, &output, false), Output::Written);
+        let mtime = age(&output);
+        assert_eq!(build(&unit, &output, false), Output::Unchanged);
+        assert_eq!(std::fs::metadata(&output).unwrap().modified().unwrap(), mtime);
+        assert!(!super::temp_path(&output).exists());
+        assert_eq!(build(&unit, &output, true), Output::Written);
+        assert_ne!(std::fs::metadata(&output).unwrap().modified().unwrap(), mtime);
+        let mtime = age(&output);
+        std::fs::write(&unit, "const int Value;").unwrap();
+        assert_eq!(build(&unit, &output, false), Output::Written);
+        assert_ne!(std::fs::metadata(&output).unwrap().modified().unwrap(), mtime);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,341 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Compilation of a single stage for editor live previews.
+//!
+//! A [PreviewEnv] loads the rest of the project once; every call to [compile_stage_preview] then
+//! only parses and compiles the previewed stage against it.
+
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use bp3d_sal::utils::AutoError;
+use thiserror::Error;
+use crate::config::Config;
+use crate::{PreviewFunc, Stage, TARGETS};
+use crate::targets::basic::{BasicAst, load_source_to_sal, link_varyings, load_stages, ShaderStage, test_shared_bindings, test_symbols, VisitorError};
+use crate::targets::basic::shader_to_sal::Error as SourceError;
+use crate::warnings::Level;
+
+/// Name of the part holding the GLSL code of the previewed stage.
+pub const PREVIEW_PART: &str = "preview";
+
+#[derive(Debug, Error)]
+pub enum CompileError
+{
+    #[error("target '{0}' does not support stage previews")]
+    UnsupportedTarget(String),
+    #[error("stage error: {0}")]
+    Stage(crate::stage::Error),
+    #[error("failed to load the preview environment: {0}")]
+    Environment(Box<dyn std::error::Error>),
+    #[error("failed to load the previewed stage: {0}")]
+    Source(Box<dyn std::error::Error>)
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Severity
+{
+    Error,
+    Warning
+}
+
+/// Code a diagnostic refers to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Origin
+{
+    /// The SAL string given to [compile_stage_preview].
+    Sal,
+    /// The GLSL string given to [compile_stage_preview].
+    Glsl,
+    /// Generated code, includes or the rest of the project.
+    Other
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic
+{
+    pub severity: Severity,
+    pub origin: Origin,
+    /// Line (starting at 1) in the string designated by origin, None if unknown.
+    pub line: Option<usize>,
+    pub message: String
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PreviewResult
+{
+    pub diagnostics: Vec<Diagnostic>,
+    /// GLSL translation of the SAL statements, None if they could not be translated.
+    pub glsl: Option<String>,
+    /// Code of the stage as it would be written in a shader pack, None if compilation failed.
+    pub payload: Option<Vec<u8>>
+}
+
+impl PreviewResult
+{
+    fn error(origin: Origin, line: Option<usize>, message: String) -> PreviewResult
+    {
+        PreviewResult {
+            diagnostics: vec![Diagnostic {
+                severity: Severity::Error,
+                origin,
+                line,
+                message
+            }],
+            glsl: None,
+            payload: None
+        }
+    }
+
+    pub(crate) fn sal_error<T: Display>(e: T) -> PreviewResult
+    {
+        PreviewResult::error(Origin::Sal, None, e.to_string())
+    }
+
+    /// Builds a result from the info log of glslang.
+    pub(crate) fn from_glslang(glsl: String, log: &str, payload: Option<Vec<u8>>) -> PreviewResult
+    {
+        PreviewResult {
+            diagnostics: log.lines().filter_map(parse_log_line).collect(),
+            glsl: Some(glsl),
+            payload
+        }
+    }
+
+    pub fn is_success(&self) -> bool
+    {
+        self.payload.is_some()
+    }
+}
+
+fn parse_log_line(line: &str) -> Option<Diagnostic>
+{
+    let (severity, msg) = match line.split_once(": ")? {
+        ("ERROR", msg) => (Severity::Error, msg),
+        ("WARNING", msg) => (Severity::Warning, msg),
+        _ => return None
+    };
+    //Locations are reported as '<part name>:<line>: '.
+    let location = msg.split_once(':').and_then(|(name, rest)| {
+        let (line, rest) = rest.split_once(':')?;
+        Some((name, line.parse::<usize>().ok()?, rest.trim()))
+    });
+    let (origin, line, message) = match location {
+        Some((PREVIEW_PART, line, message)) => (Origin::Glsl, Some(line), message),
+        Some(_) => (Origin::Other, None, msg),
+        //Summary of the error count.
+        None if msg.ends_with("No code generated.") => return None,
+        None => (Origin::Other, None, msg)
+    };
+    Some(Diagnostic {
+        severity,
+        origin,
+        line,
+        message: message.into()
+    })
+}
+
+/// Number of lines inserted by compile_stage_preview before the SAL code (`#stage` and `#sal`).
+const SAL_PREFIX_LINES: usize = 2;
+
+fn sal_line(line: usize) -> Option<usize>
+{
+    Some(line.saturating_sub(SAL_PREFIX_LINES))
+}
+
+fn sal_diagnostic(e: AutoError<usize, VisitorError>) -> PreviewResult
+{
+    match e {
+        AutoError::Lexer(e) => PreviewResult::error(Origin::Sal, sal_line(e.line), format!("{:?}", e.etype)),
+        AutoError::Parser(e) => PreviewResult::error(Origin::Sal, sal_line(e.line), e.etype.to_string()),
+        AutoError::Syntax(errors) => PreviewResult {
+            diagnostics: errors.into_iter().map(|e| Diagnostic {
+                severity: Severity::Error,
+                origin: Origin::Sal,
+                line: sal_line(e.line),
+                message: e.etype.to_string()
+            }).collect(),
+            glsl: None,
+            payload: None
+        },
+        AutoError::Panic(e) => PreviewResult::error(Origin::Sal, sal_line(e.line), format!("internal error: {}", e.message)),
+        e => PreviewResult::sal_error(e)
+    }
+}
+
+/// Symbols of a project a stage is previewed against.
+pub struct PreviewEnv<'a>
+{
+    target: &'static str,
+    preview: PreviewFunc,
+    config: Config<'a>,
+    stages: BTreeMap<Stage, BasicAst>
+}
+
+impl<'a> PreviewEnv<'a>
+{
+    /// Loads and merges the units of config, usually the other stages of the project.
+    ///
+    /// Settings of config apply to all previews; its output is never written.
+    pub fn load(target: &str, config: Config<'a>) -> Result<PreviewEnv<'a>, CompileError>
+    {
+        let (target, preview) = TARGETS.get_entry(target)
+            .and_then(|(name, v)| Some((*name, v.preview?)))
+            .ok_or_else(|| CompileError::UnsupportedTarget(target.into()))?;
+        let stages = load_stages(&config).map_err(CompileError::Environment)?;
+        Ok(PreviewEnv {
+            target,
+            preview,
+            stages: stages.into_iter().map(|(k, v)| (k, v.statements)).collect(),
+            config
+        })
+    }
+
+    pub fn target(&self) -> &'static str
+    {
+        self.target
+    }
+}
+
+/// Compiles a single stage from its SAL statements and GLSL body.
+///
+/// Errors in the given code are reported as diagnostics; nothing is linked nor written.
+pub fn compile_stage_preview(env: &PreviewEnv, stage: Stage, sal: &str, glsl: &str) -> Result<PreviewResult, CompileError>
+{
+    let config = Config {
+        units: Vec::new(),
+        warnings: env.config.warnings.fork(),
+        ..env.config.clone()
+    };
+    //Directives are commented out by the preprocessor; #line restores the numbering of the body.
+    let source = format!("#stage {}\n#sal\n{}\n#sal\n#line 1\n{}", stage.name(), sal, glsl);
+    let mut result = match load_source_to_sal(PREVIEW_PART, &source, &config) {
+        //The stage directive comes first so the first section is always the previewed stage.
+        Ok(mut units) => {
+            let unit = units.remove(0);
+            let mut stages: BTreeMap<Stage, ShaderStage> = env.stages.iter()
+                .map(|(k, v)| (*k, ShaderStage {
+                    statements: v.clone(),
+                    strings: Vec::new(),
+                    sources: Vec::new()
+                }))
+                .collect();
+            stages.insert(stage, ShaderStage {
+                statements: unit.statements,
+                strings: unit.strings,
+                sources: Vec::new()
+            });
+            //Other stages have no code in a preview: only link varyings, usage is not checked.
+            match test_symbols(&stages).and_then(|_| test_shared_bindings(&stages)).and_then(|_| link_varyings(&mut stages)) {
+                Ok(()) => (env.preview)(&config, stages, stage)?,
+                Err(e) => PreviewResult::sal_error(e)
+            }
+        },
+        Err(SourceError::Sal(e)) => sal_diagnostic(e),
+        Err(e) => return Err(CompileError::Source(e.into()))
+    };
+    for v in config.warnings.records() {
+        result.diagnostics.push(Diagnostic {
+            severity: match v.level {
+                Level::Error => Severity::Error,
+                _ => Severity::Warning
+            },
+            origin: Origin::Other,
+            line: None,
+            message: format!("{} [{}]", v.message, v.kind)
+        });
+    }
+    if result.diagnostics.iter().any(|v| v.severity == Severity::Error) {
+        result.payload = None;
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::path::Path;
+    use crate::{Compiler, Config, Stage};
+    use crate::preview::{compile_stage_preview, CompileError, Origin, PreviewEnv, Severity};
+
+    const SAL: &str = "output vec4f FragColor : ORDER_0;";
+
+    fn config<'a>(output: &'a Path) -> Config<'a>
+    {
+        Config::new(Vec::new(), output)
+    }
+
+    #[test]
+    fn glsl_error()
+    {
+        let output = Path::new("unused.bpx");
+        let env = PreviewEnv::load("GL42", config(output)).unwrap();
+        let res = compile_stage_preview(&env, Stage::Pixel, SAL, "void main()\n{\n    FragColor = vec4(1.0);\n    Undeclared = 1.0;\n}\n").unwrap();
+        assert!(!res.is_success());
+        assert!(res.glsl.unwrap().contains("FragColor"));
+        let diag = res.diagnostics.iter().find(|v| v.severity == Severity::Error).unwrap();
+        assert_eq!(diag.origin, Origin::Glsl);
+        assert_eq!(diag.line, Some(4));
+        assert!(diag.message.contains("Undeclared"));
+    }
+
+    #[test]
+    fn sal_error()
+    {
+        let output = Path::new("unused.bpx");
+        let env = PreviewEnv::load("GL40", config(output)).unwrap();
+        let res = compile_stage_preview(&env, Stage::Pixel, "\noutput vec4f FragColor : ORDER_0", "void main() {}\n").unwrap();
+        assert!(res.glsl.is_none());
+        assert_eq!(res.diagnostics.len(), 1);
+        assert_eq!(res.diagnostics[0].origin, Origin::Sal);
+        //Lines are relative to the SAL code of the preview, not to the generated unit.
+        assert_eq!(res.diagnostics[0].line, Some(2));
+    }
+
+    #[test]
+    fn valid()
+    {
+        let output = Path::new("unused.bpx");
+        for target in Compiler::list_targets() {
+            let env = match PreviewEnv::load(target, config(output)) {
+                Ok(v) => v,
+                Err(CompileError::UnsupportedTarget(_)) => {
+                    assert!(Compiler::info(target).unwrap().api.is_none());
+                    continue;
+                },
+                Err(e) => panic!("{}", e)
+            };
+            let res = compile_stage_preview(&env, Stage::Pixel, SAL, "void main()\n{\n    FragColor = vec4(1.0);\n}\n").unwrap();
+            assert!(res.diagnostics.is_empty(), "{:?}", res.diagnostics);
+            assert!(res.glsl.unwrap().contains("FragColor"));
+            let payload = String::from_utf8(res.payload.unwrap()).unwrap();
+            assert!(payload.contains("FragColor = vec4(1.0);"));
+            assert!(matches!(compile_stage_preview(&env, Stage::Compute, "", ""), Err(CompileError::Stage(_))));
+        }
+    }
+}
@@ -0,0 +1,495 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Reproducer bundles: a single tar archive holding everything needed to re-run a failed build
+//! without access to the original file system.
+//!
+//! Next to the inputs, bundles hold the sources the build got to generate or resolve before it
+//! failed: the code of each stage as handed to glslang (`stages/`) and the text of each SAL module
+//! resolved from the shader libraries (`modules/`).
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use bpx::macros::impl_err_conversion;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use crate::config::{CompressionPreference, Config, DEFAULT_MAX_EXT_DATA_SIZE, DEFAULT_MAX_RESOURCE_SIZE, Unit};
+use crate::warnings::Reporter;
+use crate::Stage;
+pub use bp3d_symbols::ErrorReport;
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+const MANIFEST: &str = "manifest.toml";
+const ERROR_REPORT: &str = "error.json";
+
+#[derive(Debug, Error)]
+pub enum Error
+{
+    #[error("io error: {0}")]
+    Io(std::io::Error),
+    #[error("manifest encode error: {0}")]
+    TomlEncode(toml::ser::Error),
+    #[error("manifest decode error: {0}")]
+    TomlDecode(toml::de::Error),
+    #[error("error report encode error: {0}")]
+    Json(serde_json::Error),
+    #[error("shader unit '{0}' has no file name")]
+    InvalidUnit(PathBuf),
+    #[error("bundle entry '{0}' escapes the bundle root")]
+    InvalidEntry(String),
+    #[error("warning settings error: {0}")]
+    Warnings(crate::warnings::Error)
+}
+
+impl_err_conversion!(
+    Error {
+        std::io::Error => Io,
+        toml::ser::Error => TomlEncode,
+        toml::de::Error => TomlDecode,
+        serde_json::Error => Json,
+        crate::warnings::Error => Warnings
+    }
+);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestUnit
+{
+    pub path: Option<String>,
+    pub injected: Option<String>,
+    /// Stage declared for a path unit, see [Unit::PathWithStage].
+    #[serde(default)]
+    pub stage: Option<String>
+}
+
+fn default_max_ext_data_size() -> usize
+{
+    DEFAULT_MAX_EXT_DATA_SIZE
+}
+
+fn default_max_resource_size() -> usize
+{
+    DEFAULT_MAX_RESOURCE_SIZE
+}
+
+/// The effective configuration of the failed build; all paths are relative to the bundle root.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest
+{
+    pub version: String,
+    pub target: String,
+    pub output: String,
+    pub n_threads: usize,
+    pub minify: bool,
+    #[serde(default)]
+    pub strip_comments: bool,
+    pub optimize: bool,
+    pub debug: bool,
+    pub deny_deprecated: bool,
+    #[serde(default)]
+    pub deterministic: bool,
+    #[serde(default)]
+    pub no_vertex_input: bool,
+    #[serde(default)]
+    pub no_interface_check: bool,
+    #[serde(default)]
+    pub strict_sal: bool,
+    #[serde(default)]
+    pub allow_duplicate_symbols: bool,
+    #[serde(default)]
+    pub defines: Vec<String>,
+    #[serde(default)]
+    pub require_symbols: Option<String>,
+    #[serde(default = "default_max_ext_data_size")]
+    pub max_ext_data_size: usize,
+    #[serde(default = "default_max_resource_size")]
+    pub max_resource_size: usize,
+    #[serde(default)]
+    pub compression: CompressionPreference,
+    #[serde(default)]
+    pub warnings: Vec<String>,
+    pub libs: Vec<String>,
+    pub units: Vec<ManifestUnit>
+}
+
+/// Collects the sources generated or resolved by a build for its reproducer bundle.
+///
+/// Clones share the same lists so that the sources can be bundled once the build which recorded
+/// them failed.
+#[derive(Debug, Clone, Default)]
+pub struct Sources
+{
+    stages: Arc<Mutex<BTreeMap<Stage, String>>>,
+    modules: Arc<Mutex<BTreeMap<String, Vec<u8>>>>
+}
+
+impl Sources
+{
+    pub fn new() -> Sources
+    {
+        Sources::default()
+    }
+
+    /// Records the code of a stage as handed to glslang.
+    pub fn record_stage(&self, stage: Stage, code: &str)
+    {
+        self.stages.lock().unwrap().insert(stage, code.into());
+    }
+
+    /// Records the text of a SAL module under its object name in the shader libraries.
+    pub fn record_module(&self, object: &str, data: &[u8])
+    {
+        self.modules.lock().unwrap().insert(object.into(), data.into());
+    }
+
+    pub fn stages(&self) -> Vec<(Stage, String)>
+    {
+        self.stages.lock().unwrap().iter().map(|(k, v)| (*k, v.clone())).collect()
+    }
+
+    pub fn modules(&self) -> Vec<(String, Vec<u8>)>
+    {
+        self.modules.lock().unwrap().iter().map(|(k, v)| (k.clone(), v.clone())).collect()
+    }
+}
+
+/// Removes an extracted bundle when dropped.
+struct ExtractedDir(PathBuf);
+
+impl Drop for ExtractedDir
+{
+    fn drop(&mut self)
+    {
+        if let Err(e) = std::fs::remove_dir_all(&self.0) {
+            debug!("Failed to remove replay directory: {}", e);
+        }
+    }
+}
+
+fn file_name(path: &Path) -> Result<String, Error>
+{
+    path.file_name()
+        .map(|v| v.to_string_lossy().into_owned())
+        .ok_or_else(|| Error::InvalidUnit(path.into()))
+}
+
+fn append_data<W: std::io::Write>(builder: &mut tar::Builder<W>, name: &str, data: &[u8], mtime: u64) -> Result<(), Error>
+{
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as _);
+    header.set_mode(0o644);
+    header.set_mtime(mtime);
+    header.set_cksum();
+    builder.append_data(&mut header, name, data)?;
+    Ok(())
+}
+
+/// Writes a reproducer bundle for a build of the given target which failed with the given error.
+pub fn write_bundle(path: &Path, target: &str, config: &Config, error: &(dyn std::error::Error + 'static)) -> Result<(), Error>
+{
+    let mut builder = tar::Builder::new(BufWriter::new(File::create(path)?));
+    //Deterministic builds must not leak file system metadata (mtime, owner) into the bundle.
+    let mtime = if config.deterministic {
+        Some(crate::deterministic::timestamp().unwrap_or(0))
+    } else {
+        None
+    };
+    let append_file = |builder: &mut tar::Builder<BufWriter<File>>, src: &Path, name: &str| -> Result<(), Error> {
+        match mtime {
+            Some(mtime) => append_data(builder, name, &std::fs::read(src)?, mtime),
+            None => Ok(builder.append_path_with_name(src, name)?)
+        }
+    };
+    let mut manifest = Manifest {
+        version: VERSION.into(),
+        target: target.into(),
+        output: file_name(config.output).unwrap_or_else(|_| "a.out".into()),
+        n_threads: config.n_threads,
+        minify: config.minify,
+        strip_comments: config.strip_comments,
+        optimize: config.optimize,
+        debug: config.debug,
+        deny_deprecated: config.deny_deprecated,
+        deterministic: config.deterministic,
+        no_vertex_input: config.no_vertex_input,
+        no_interface_check: config.no_interface_check,
+        strict_sal: config.strict_sal,
+        allow_duplicate_symbols: config.allow_duplicate_symbols,
+        defines: config.defines.clone(),
+        require_symbols: None,
+        max_ext_data_size: config.max_ext_data_size,
+        max_resource_size: config.max_resource_size,
+        compression: config.compression,
+        warnings: config.warnings.settings(),
+        libs: Vec::new(),
+        units: Vec::new()
+    };
+    if let Some(path) = config.require_symbols {
+        let name = format!("contract/{}", file_name(path)?);
+        append_file(&mut builder, path, &name)?;
+        manifest.require_symbols = Some(name);
+    }
+    for (i, lib) in config.libs.iter().enumerate() {
+        let name = format!("libs/{}/{}", i, file_name(lib)?);
+        append_file(&mut builder, lib, &name)?;
+        manifest.libs.push(name);
+    }
+    for (i, unit) in config.units.iter().enumerate() {
+        match unit {
+            Unit::Path(path) | Unit::PathWithStage(_, path) => {
+                let name = format!("units/{}/{}", i, file_name(path)?);
+                append_file(&mut builder, path, &name)?;
+                manifest.units.push(ManifestUnit {
+                    path: Some(name),
+                    injected: None,
+                    stage: unit.stage().map(|v| v.name().into())
+                });
+            },
+            Unit::Injected(vname) => manifest.units.push(ManifestUnit {
+                path: None,
+                injected: Some(String::from(*vname)),
+                stage: None
+            })
+        }
+    }
+    if let Some(sources) = &config.sources {
+        for (stage, code) in sources.stages() {
+            append_data(&mut builder, &format!("stages/{}.glsl", stage.name()), code.as_bytes(), mtime.unwrap_or(0))?;
+        }
+        for (object, data) in sources.modules() {
+            append_data(&mut builder, &format!("modules/{}", object), &data, mtime.unwrap_or(0))?;
+        }
+    }
+    append_data(&mut builder, MANIFEST, toml::to_string(&manifest)?.as_bytes(), mtime.unwrap_or(0))?;
+    let report = ErrorReport::new(VERSION, target, error);
+    append_data(&mut builder, ERROR_REPORT, &serde_json::to_vec_pretty(&report)?, mtime.unwrap_or(0))?;
+    builder.into_inner()?;
+    Ok(())
+}
+
+/// An extracted reproducer bundle ready to be replayed.
+pub struct Replay
+{
+    root: ExtractedDir,
+    output: PathBuf,
+    libs: Vec<PathBuf>,
+    paths: Vec<Option<PathBuf>>,
+    require_symbols: Option<PathBuf>,
+    warnings: Reporter,
+    pub manifest: Manifest,
+    pub report: ErrorReport
+}
+
+impl Replay
+{
+    /// Extracts the bundle at `path` into `root`, which is removed when the replay is dropped or
+    /// if the bundle is invalid.
+    pub fn open(path: &Path, root: &Path) -> Result<Replay, Error>
+    {
+        let mut archive = tar::Archive::new(BufReader::new(File::open(path)?));
+        let dir = ExtractedDir(root.into());
+        archive.unpack(root)?;
+        let resolve = |name: &str| -> Result<PathBuf, Error> {
+            let path = Path::new(name);
+            if path.is_absolute() || path.components().any(|v| v == std::path::Component::ParentDir) {
+                return Err(Error::InvalidEntry(name.into()));
+            }
+            Ok(root.join(path))
+        };
+        let mut manifest = String::new();
+        File::open(root.join(MANIFEST))?.read_to_string(&mut manifest)?;
+        let manifest: Manifest = toml::from_str(&manifest)?;
+        let report: ErrorReport = serde_json::from_reader(BufReader::new(File::open(root.join(ERROR_REPORT))?))?;
+        let libs = manifest.libs.iter().map(|v| resolve(v)).collect::<Result<Vec<PathBuf>, Error>>()?;
+        let require_symbols = manifest.require_symbols.as_deref().map(|v| resolve(v)).transpose()?;
+        let paths = manifest.units.iter()
+            .map(|v| v.path.as_deref().map(|v| resolve(v)).transpose())
+            .collect::<Result<Vec<Option<PathBuf>>, Error>>()?;
+        let mut warnings = Reporter::new();
+        for setting in &manifest.warnings {
+            warnings.configure(setting)?;
+        }
+        Ok(Replay {
+            output: resolve(&manifest.output)?,
+            root: dir,
+            libs,
+            paths,
+            require_symbols,
+            warnings,
+            manifest,
+            report
+        })
+    }
+
+    pub fn root(&self) -> &Path
+    {
+        &self.root.0
+    }
+
+    /// Returns the build configuration stored in the bundle.
+    pub fn config(&self) -> Config
+    {
+        let units = self.manifest.units.iter().zip(self.paths.iter()).filter_map(|(unit, path)| {
+            match path {
+                Some(path) => match unit.stage.as_deref().and_then(Stage::from_name) {
+                    Some(stage) => Some(Unit::PathWithStage(stage, path)),
+                    None => Some(Unit::Path(path))
+                },
+                None => unit.injected.as_deref().map(Unit::Injected)
+            }
+        }).collect();
+        Config {
+            units,
+            libs: self.libs.iter().map(|v| &**v).collect(),
+            output: &self.output,
+            n_threads: self.manifest.n_threads,
+            minify: self.manifest.minify,
+            strip_comments: self.manifest.strip_comments,
+            optimize: self.manifest.optimize,
+            debug: self.manifest.debug,
+            deny_deprecated: self.manifest.deny_deprecated,
+            deterministic: self.manifest.deterministic,
+            no_vertex_input: self.manifest.no_vertex_input,
+            no_interface_check: self.manifest.no_interface_check,
+            strict_sal: self.manifest.strict_sal,
+            require_symbols: self.require_symbols.as_deref(),
+            max_ext_data_size: self.manifest.max_ext_data_size,
+            max_resource_size: self.manifest.max_resource_size,
+            compression: self.manifest.compression,
+            force_write: false,
+            warnings: self.warnings.clone(),
+            depfile: None,
+            manifest: None,
+            glsl_includes: Vec::new(),
+            allow_duplicate_symbols: self.manifest.allow_duplicate_symbols,
+            defines: self.manifest.defines.clone(),
+            unit_cache: None,
+            //Files of the bundle persist as they would in a build from its root.
+            path_prefix_map: vec![(self.root(), Path::new("."))],
+            source_date_epoch: None,
+            sources: None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::fs::File;
+    use std::io::{BufWriter, Read};
+    use std::path::Path;
+    use bpx::package;
+    use bpx::package::Package;
+    use crate::{Compiler, Config, Unit};
+    use crate::repro::{Replay, Sources, write_bundle};
+
+    #[test]
+    fn replay_reproduces_error()
+    {
+        let dir = std::env::temp_dir().join(format!("shaderc-repro-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let shader = dir.join("broken.glsl");
+        std::fs::write(&shader, "#stage vertex\n#sal\nconst float [ Broken;\n#sal\nvoid main() {}\n").unwrap();
+        let output = dir.join("broken.bpx");
+        let bundle = dir.join("bundle.tar");
+        let config = Config::new(vec![Unit::Path(&shader)], &output);
+        let err = Compiler::get("GL40").unwrap().run(config.clone()).unwrap_err();
+        write_bundle(&bundle, "GL40", &config, &*err).unwrap();
+        std::fs::remove_file(&shader).unwrap();
+        let replay = Replay::open(&bundle, &dir.join("replay")).unwrap();
+        assert_eq!(replay.report.message, err.to_string());
+        let compiler = Compiler::get(&replay.manifest.target).unwrap();
+        let err1 = compiler.run(replay.config()).unwrap_err();
+        assert_eq!(err1.to_string(), err.to_string());
+        assert!(replay.root().starts_with(Path::new(&dir)));
+        drop(replay);
+        assert!(!dir.join("replay").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn bundle_sources()
+    {
+        let dir = std::env::temp_dir().join(format!("shaderc-repro-sources-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let lib = dir.join("lib.bpx");
+        let module: &[u8] = b"const struct PerLight { float Intensity; }";
+        {
+            let mut pack = Package::create(BufWriter::new(File::create(&lib).unwrap()), package::Builder::new()
+                .type_code(*b"SL")).unwrap();
+            pack.objects_mut().unwrap().create("engine/lighting", module).unwrap();
+            pack.save().unwrap();
+        }
+        let shader = dir.join("broken.glsl");
+        std::fs::write(&shader, "#stage vertex\n#sal\nuse engine::lighting::PerLight;\n#sal\nvoid main() { Broken; }\n").unwrap();
+        let output = dir.join("broken.bpx");
+        let bundle = dir.join("bundle.tar");
+        let config = Config {
+            libs: vec![lib.as_path()],
+            no_vertex_input: true,
+            sources: Some(Sources::new()),
+            ..Config::new(vec![Unit::Path(&shader)], &output)
+        };
+        let err = Compiler::get("GL40").unwrap().run(config.clone()).unwrap_err();
+        write_bundle(&bundle, "GL40", &config, &*err).unwrap();
+        let mut archive = tar::Archive::new(File::open(&bundle).unwrap());
+        let mut entries = Vec::new();
+        for entry in archive.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let mut data = Vec::new();
+            entry.read_to_end(&mut data).unwrap();
+            entries.push((entry.path().unwrap().to_string_lossy().into_owned(), data));
+        }
+        let entry = |name: &str| entries.iter().find(|(v, _)| v == name).map(|(_, data)| data.clone());
+        assert_eq!(entry("modules/engine/lighting").unwrap(), module);
+        let stage = String::from_utf8(entry("stages/vertex.glsl").unwrap()).unwrap();
+        assert!(stage.contains("PerLight") && stage.contains("Broken;"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn invalid_bundle_is_removed()
+    {
+        let dir = std::env::temp_dir().join(format!("shaderc-repro-invalid-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let bundle = dir.join("bundle.tar");
+        {
+            let mut builder = tar::Builder::new(File::create(&bundle).unwrap());
+            super::append_data(&mut builder, "units/0/shader.glsl", b"void main() {}", 0).unwrap();
+            builder.finish().unwrap();
+        }
+        let root = dir.join("replay");
+        assert!(Replay::open(&bundle, &root).is_err());
+        assert!(!root.exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
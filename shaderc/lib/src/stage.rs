@@ -0,0 +1,197 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use std::fmt::{Display, Formatter};
+use thiserror::Error;
+
+/// A shader stage, independent of the container format and of the shader compiler.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Stage
+{
+    Vertex,
+    Hull,
+    Domain,
+    Geometry,
+    Pixel,
+    Compute,
+    /// Reserved: not yet supported by any target.
+    Task,
+    /// Reserved: not yet supported by any target.
+    Mesh
+}
+
+#[derive(Debug, Error)]
+pub enum Error
+{
+    #[error("shader stage '{0}' is not supported by {1}")]
+    Unsupported(Stage, &'static str)
+}
+
+impl Stage
+{
+    pub const ALL: [Stage; 8] = [
+        Stage::Vertex,
+        Stage::Hull,
+        Stage::Domain,
+        Stage::Geometry,
+        Stage::Pixel,
+        Stage::Compute,
+        Stage::Task,
+        Stage::Mesh
+    ];
+
+    pub fn name(&self) -> &'static str
+    {
+        match self {
+            Stage::Vertex => "vertex",
+            Stage::Hull => "hull",
+            Stage::Domain => "domain",
+            Stage::Geometry => "geometry",
+            Stage::Pixel => "pixel",
+            Stage::Compute => "compute",
+            Stage::Task => "task",
+            Stage::Mesh => "mesh"
+        }
+    }
+
+    /// Parses a stage name as written in a `#stage` directive.
+    pub fn from_name(name: &str) -> Option<Stage>
+    {
+        Stage::ALL.into_iter().find(|v| v.name() == name)
+    }
+
+    pub fn to_bpx(self) -> Result<bpx::shader::Stage, Error>
+    {
+        match self {
+            Stage::Vertex => Ok(bpx::shader::Stage::Vertex),
+            Stage::Hull => Ok(bpx::shader::Stage::Hull),
+            Stage::Domain => Ok(bpx::shader::Stage::Domain),
+            Stage::Geometry => Ok(bpx::shader::Stage::Geometry),
+            Stage::Pixel => Ok(bpx::shader::Stage::Pixel),
+            _ => Err(Error::Unsupported(self, "bpx"))
+        }
+    }
+
+    pub fn to_glslang(self) -> Result<rglslang::environment::Stage, Error>
+    {
+        match self {
+            Stage::Vertex => Ok(rglslang::environment::Stage::Vertex),
+            Stage::Hull => Ok(rglslang::environment::Stage::Hull),
+            Stage::Domain => Ok(rglslang::environment::Stage::Domain),
+            Stage::Geometry => Ok(rglslang::environment::Stage::Geometry),
+            Stage::Pixel => Ok(rglslang::environment::Stage::Pixel),
+            _ => Err(Error::Unsupported(self, "glslang"))
+        }
+    }
+}
+
+impl TryFrom<bpx::shader::Stage> for Stage
+{
+    type Error = Error;
+
+    fn try_from(value: bpx::shader::Stage) -> Result<Self, Self::Error>
+    {
+        match value {
+            bpx::shader::Stage::Vertex => Ok(Stage::Vertex),
+            bpx::shader::Stage::Hull => Ok(Stage::Hull),
+            bpx::shader::Stage::Domain => Ok(Stage::Domain),
+            bpx::shader::Stage::Geometry => Ok(Stage::Geometry),
+            bpx::shader::Stage::Pixel => Ok(Stage::Pixel)
+        }
+    }
+}
+
+impl TryFrom<rglslang::environment::Stage> for Stage
+{
+    type Error = Error;
+
+    fn try_from(value: rglslang::environment::Stage) -> Result<Self, Self::Error>
+    {
+        match value {
+            rglslang::environment::Stage::Vertex => Ok(Stage::Vertex),
+            rglslang::environment::Stage::Hull => Ok(Stage::Hull),
+            rglslang::environment::Stage::Domain => Ok(Stage::Domain),
+            rglslang::environment::Stage::Geometry => Ok(Stage::Geometry),
+            rglslang::environment::Stage::Pixel => Ok(Stage::Pixel)
+        }
+    }
+}
+
+impl Display for Stage
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+    {
+        f.write_str(self.name())
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::stage::{Error, Stage};
+
+    const SUPPORTED: [Stage; 5] = [Stage::Vertex, Stage::Hull, Stage::Domain, Stage::Geometry, Stage::Pixel];
+
+    #[test]
+    fn names()
+    {
+        for stage in Stage::ALL {
+            assert_eq!(Stage::from_name(stage.name()), Some(stage));
+            assert_eq!(stage.to_string(), stage.name());
+        }
+        assert_eq!(Stage::from_name("fragment"), None);
+    }
+
+    #[test]
+    fn bpx_round_trip()
+    {
+        for stage in SUPPORTED {
+            assert_eq!(Stage::try_from(stage.to_bpx().unwrap()).unwrap(), stage);
+        }
+    }
+
+    #[test]
+    fn glslang_round_trip()
+    {
+        for stage in SUPPORTED {
+            assert_eq!(Stage::try_from(stage.to_glslang().unwrap()).unwrap(), stage);
+        }
+    }
+
+    #[test]
+    fn unsupported()
+    {
+        for stage in [Stage::Compute, Stage::Task, Stage::Mesh] {
+            let err = stage.to_bpx().unwrap_err();
+            assert!(matches!(err, Error::Unsupported(s, "bpx") if s == stage));
+            assert_eq!(err.to_string(), format!("shader stage '{}' is not supported by bpx", stage.name()));
+            let err = stage.to_glslang().unwrap_err();
+            assert!(matches!(err, Error::Unsupported(s, "glslang") if s == stage));
+        }
+    }
+}
@@ -28,10 +28,24 @@
 
 // SAL AST structure.
 
-use std::collections::HashMap;
-use bp3d_sal::ast::RefResolver;
-use bp3d_sal::ast::tree::{BlendfuncStatement, PipelineStatement, Property, Struct};
+use std::collections::{HashMap, HashSet};
+use bp3d_sal::ast::{BaseStruct, RefResolver};
+use bp3d_sal::ast::tree::{BlendfuncStatement, ConstantInt, PipelineStatement, Property, SamplerStateStatement, Struct};
+use bp3d_symbols::Resource;
+use crate::targets::basic::shader_to_sal::Slot;
 
+/// Interface blocks a stage declares for the varyings, computed by
+/// [link_varyings](crate::targets::basic::link_varyings).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct VaryingInterface
+{
+    /// Input block of the stage; true if it is an array with one element per input vertex.
+    pub input: Option<bool>,
+    /// Output block of the stage; true if it is an array with one element per output vertex.
+    pub output: Option<bool>
+}
+
+#[derive(Clone)]
 pub struct Ast<
     Pc = Property<usize>, Po = Property<usize>, Pb = Property<usize>,
     Sc = Struct<usize>, Sp = Struct<usize>, Sb = Struct<usize>, Sv = Struct<usize>
@@ -45,17 +59,32 @@ pub struct Ast<
 // Sv the struct type for vertex formats
 {
     pub root_constants_layout: Option<Sc>,
+    //True when the root constants layout comes from a rootconst block instead of an ORDER_0 constant buffer
+    pub root_constants_explicit: bool,
     pub packed_structs: Vec<Sp>,
     //Root constants/push constants, emulated by global uniform buffer in GL targets
     pub root_constants: Vec<Pc>,
+    //Integer constants, emitted as preprocessor defines in GL targets
+    pub constant_ints: Vec<Slot<ConstantInt>>,
     //Fragment shader outputs/render target outputs
     pub outputs: Vec<Po>,
+    //Inter-stage variables, identical in all stages once linked
+    pub varyings: Vec<Property<usize>>,
+    pub varying_interface: VaryingInterface,
     //Samplers and textures
     pub objects: Vec<Pb>,
     pub cbuffers: Vec<Sb>,
     pub vformat: Option<Sv>,
     pub pipeline: Option<PipelineStatement>,
+    //Pipelines imported with use; local pipelines may inherit from them
+    pub presets: Vec<PipelineStatement>,
     pub blendfuncs: Vec<BlendfuncStatement>,
+    //Sampler configurations, each one matches the Sampler object with the same name
+    pub samplerstates: Vec<SamplerStateStatement>,
+    //Binary resources embedded in the shader pack
+    pub resources: Vec<Resource>,
+    //Use statements (module, member) already expanded into this AST
+    uses: HashSet<(String, String)>,
     packed_structs_by_name: HashMap<String, usize>,
     offset_packed_structs: usize
 }
@@ -64,14 +93,22 @@ impl<Pc, Po, Pb, Sc, Sp, Sb, Sv> Ast<Pc, Po, Pb, Sc, Sp, Sb, Sv> {
     pub fn new() -> Ast<Pc, Po, Pb, Sc, Sp, Sb, Sv> {
         Ast {
             root_constants_layout: None,
+            root_constants_explicit: false,
             packed_structs: Vec::new(),
             root_constants: Vec::new(),
+            constant_ints: Vec::new(),
             outputs: Vec::new(),
+            varyings: Vec::new(),
+            varying_interface: VaryingInterface::default(),
             objects: Vec::new(),
             cbuffers: Vec::new(),
             vformat: None,
             pipeline: None,
+            presets: Vec::new(),
             blendfuncs: Vec::new(),
+            samplerstates: Vec::new(),
+            resources: Vec::new(),
+            uses: HashSet::new(),
             packed_structs_by_name: HashMap::new(),
             offset_packed_structs: 0
         }
@@ -131,6 +168,15 @@ impl<Pc, Po, Pb, Sc, Sp, Sb, Sv> Ast<Pc, Po, Pb, Sc, Sp, Sb, Sv> {
         })
     }*/
 
+    /// Records that `module::member` is expanded into this AST; returns false if it already was.
+    pub fn mark_use(&mut self, module: &str, member: &str) -> bool {
+        self.uses.insert((module.into(), member.into()))
+    }
+
+    pub fn take_uses(&mut self) -> HashSet<(String, String)> {
+        std::mem::take(&mut self.uses)
+    }
+
     pub fn push_packed_struct(&mut self, name: String, st: Sp) -> usize {
         let id = self.packed_structs.len();
         self.packed_structs.push(st);
@@ -143,10 +189,24 @@ impl<Pc, Po, Pb, Sc, Sp, Sb, Sv> Ast<Pc, Po, Pb, Sc, Sp, Sb, Sv> {
     }
 }
 
-impl<Pc, Po, Pb, Sc, Sp, Sb, Sv> RefResolver for Ast<Pc, Po, Pb, Sc, Sp, Sb, Sv> {
+impl<Pc, Po, Pb> RefResolver for Ast<Pc, Po, Pb, Struct<usize>, Struct<usize>, Slot<Struct<usize>>, Struct<usize>> {
     type Key = usize;
 
     fn resolve_struct_ref(&self, name: &str) -> Option<Self::Key> {
         self.packed_structs_by_name.get(name).copied()
     }
+
+    fn resolve_pipeline(&self, name: &str) -> Option<PipelineStatement> {
+        self.presets.iter().find(|v| v.name == name).cloned()
+    }
+
+    fn resolve_struct(&self, name: &str) -> Option<BaseStruct<Self::Key>> {
+        let base = |st: &Struct<usize>, vertex_format| BaseStruct { props: st.props.clone(), vertex_format };
+        self.packed_structs.iter()
+            .chain(self.cbuffers.iter().map(|v| &v.inner))
+            .chain(self.root_constants_layout.iter())
+            .find(|v| v.name == name)
+            .map(|v| base(v, false))
+            .or_else(|| self.vformat.as_ref().filter(|v| v.name == name).map(|v| base(v, true)))
+    }
 }
@@ -29,34 +29,74 @@
 pub mod preprocessor;
 pub mod shaderlib;
 pub mod useresolver;
-mod shader_to_sal;
+pub mod shader_to_sal;
 pub mod sal_compiler;
 pub mod ast;
+pub mod parts;
 
 use std::collections::BTreeMap;
-use bpx::shader::Stage;
+use crate::Stage;
 use log::{debug, info};
 pub use shader_to_sal::*;
 pub use sal_compiler::*;
 use crate::config::Config;
 use std::error::Error;
+use bp3d_symbols::TargetInfo;
+
+/// Loads all units of the config and merges them into stages.
+pub fn load_stages(config: &Config) -> Result<BTreeMap<Stage, ShaderStage>, Box<dyn Error>>
+{
+    info!(target: "translation", "Running initial shader decomposition phase...");
+    let shaders = load_pass(config)?;
+    debug!(target: "translation", "Found {} shaders", shaders.len());
+    if let Some(depfile) = &config.depfile {
+        config.libs.iter().for_each(|v| depfile.record(*v));
+        shaders.iter().flat_map(|v| v.dependencies.iter()).for_each(|v| depfile.record(v));
+    }
+    test_vertex_formats(&shaders, !config.no_vertex_input)?;
+    test_root_constants(&shaders, &config.warnings)?;
+    order_cbuffers(shaders.iter().flat_map(|v| v.statements.cbuffers.iter().map(move |c| (&*v.name, &c.inner))))?;
+    info!(target: "translation", "Merging shader stages");
+    let mut stages = merge_stages(shaders, &config.warnings);
+    info!(target: "translation", "Testing SAL symbols...");
+    test_symbols(&stages)?;
+    test_shared_bindings(&stages)?;
+    info!(target: "translation", "Linking varyings...");
+    link_varyings(&mut stages)?;
+    if !config.no_interface_check {
+        info!(target: "translation", "Testing stage interfaces...");
+        test_interfaces(&stages)?;
+    }
+    test_varyings(&stages, &config.warnings);
+    test_vformat_locations(&stages, &config.warnings);
+    Ok(stages)
+}
 
 pub trait Target
 {
     type CompileOutput;
 
+    /// Capabilities of the target environment passed to stage_prologue and stage_epilogue.
+    type Caps;
+
+    /// Code injected after the version and extension directives, before the SAL translation.
+    fn stage_prologue(&self, _stage: Stage, _caps: &Self::Caps) -> Option<String> {
+        None
+    }
+
+    /// Code injected after user code.
+    fn stage_epilogue(&self, _stage: Stage, _caps: &Self::Caps) -> Option<String> {
+        None
+    }
+
     fn pre_process(&self, config: &Config) -> Result<BTreeMap<Stage, ShaderStage>, Box<dyn Error>> {
-        info!("Running initial shader decomposition phase...");
-        let shaders = load_pass(&config)?;
-        debug!("Found {} shaders", shaders.len());
-        info!("Merging shader stages");
-        let stages = merge_stages(shaders);
-        info!("Testing SAL symbols...");
-        test_symbols(&stages)?;
-        Ok(stages)
+        load_stages(config)
     }
 
-    fn relocate_bindings(&self, stages: &mut BTreeMap<Stage, ShaderStage>) -> Result<(), Box<dyn Error>>;
+    /// Describes the capabilities of the target.
+    fn info(&self) -> TargetInfo;
+
+    fn relocate_bindings(&self, config: &Config, stages: &mut BTreeMap<Stage, ShaderStage>) -> Result<(), Box<dyn Error>>;
 
     fn test_bindings(&self, stages: &BTreeMap<Stage, ShaderStage>) -> Result<(), Box<dyn Error>>;
 
@@ -65,17 +105,17 @@ pub trait Target
     fn write_finish(&self, config: &Config, out: Self::CompileOutput) -> Result<(), Box<dyn Error>>;
 
     fn run(&self, config: &Config) -> Result<(), Box<dyn Error>> {
-        info!("Applying pre-processor...");
+        info!(target: "translation", "Applying pre-processor...");
         let mut stages = self.pre_process(config)?;
-        info!("Applying binding relocations...");
-        self.relocate_bindings(&mut stages)?;
-        info!("Testing binding relocations...");
+        info!(target: "relocation", "Applying binding relocations...");
+        self.relocate_bindings(config, &mut stages)?;
+        info!(target: "relocation", "Testing binding relocations...");
         self.test_bindings(&stages)?;
-        info!("Compiling and linking...");
+        info!(target: "compile", "Compiling and linking...");
         let out = self.compile_link(config, stages)?;
-        info!("Writing {}...", config.output.display());
+        info!(target: "write", "Writing {}...", config.output.display());
         self.write_finish(config, out)?;
-        info!("Shader pack built: {}", config.output.display());
+        info!(target: "write", "Shader pack built: {}", config.output.display());
         Ok(())
     }
 }
@@ -0,0 +1,194 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Assembly of the source parts of a shader stage.
+//!
+//! Parts are always assembled in the following order:
+//! 1. version directive,
+//! 2. extension directives,
+//! 3. defines ([Config::defines](crate::config::Config::defines)),
+//! 4. target prologue (Target::stage_prologue),
+//! 5. SAL translation,
+//! 6. user code,
+//! 7. target epilogue (Target::stage_epilogue).
+//!
+//! Each injected part is named so that compiler diagnostics can be mapped back to their origin.
+
+use crate::Stage;
+use rglslang::shader::Part;
+use crate::targets::basic::Target;
+
+pub const PART_VERSION: &str = "__internal_glsl_version__";
+pub const PART_EXTENSIONS: &str = "__internal_glsl_extensions__";
+pub const PART_DEFINES: &str = "__internal_defines__";
+pub const PART_PROLOGUE: &str = "__internal_prologue__";
+pub const PART_SAL: &str = "__internal_sal__";
+pub const PART_EPILOGUE: &str = "__internal_epilogue__";
+
+/// Returns the name to show in diagnostics for a part, internal parts are given synthetic names.
+pub fn part_display_name(name: &str) -> &str
+{
+    match name {
+        PART_VERSION => "<version>",
+        PART_EXTENSIONS => "<extensions>",
+        PART_DEFINES => "<defines>",
+        PART_PROLOGUE => "<prologue>",
+        PART_SAL => "<sal>",
+        PART_EPILOGUE => "<epilogue>",
+        _ => name
+    }
+}
+
+pub struct StageSource<'a>
+{
+    pub version: String,
+    pub extensions: &'a [&'a str],
+    pub defines: &'a [String],
+    pub sal: String,
+    pub user: Vec<Part>
+}
+
+pub fn assemble_stage_parts<T: Target + ?Sized>(target: &T, caps: &T::Caps, stage: Stage, src: StageSource) -> Vec<Part>
+{
+    let mut parts = Vec::with_capacity(src.user.len() + 6);
+    parts.push(Part::new_with_name(src.version, PART_VERSION));
+    if !src.extensions.is_empty() {
+        let code: String = src.extensions.iter().map(|v| format!("#extension {} : require\n", v)).collect();
+        parts.push(Part::new_with_name(code, PART_EXTENSIONS));
+    }
+    if !src.defines.is_empty() {
+        let code: String = src.defines.iter().map(|v| format!("#define {} 1\n", v)).collect();
+        parts.push(Part::new_with_name(code, PART_DEFINES));
+    }
+    if let Some(code) = target.stage_prologue(stage, caps) {
+        parts.push(Part::new_with_name(code, PART_PROLOGUE));
+    }
+    parts.push(Part::new_with_name(src.sal, PART_SAL));
+    parts.extend(src.user);
+    if let Some(code) = target.stage_epilogue(stage, caps) {
+        parts.push(Part::new_with_name(code, PART_EPILOGUE));
+    }
+    parts
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::collections::BTreeMap;
+    use std::error::Error;
+    use crate::Stage;
+    use rglslang::shader::Part;
+    use bp3d_symbols::TargetInfo;
+    use crate::config::Config;
+    use crate::targets::basic::{ShaderStage, Target};
+    use crate::targets::basic::parts::{assemble_stage_parts, part_display_name, PART_DEFINES, PART_EPILOGUE, PART_EXTENSIONS, PART_PROLOGUE, PART_SAL, PART_VERSION, StageSource};
+
+    struct MockTarget;
+
+    impl Target for MockTarget {
+        type CompileOutput = ();
+        type Caps = bool;
+
+        fn stage_prologue(&self, stage: Stage, caps: &bool) -> Option<String> {
+            if *caps {
+                Some(format!("// prologue {:?}\n", stage))
+            } else {
+                None
+            }
+        }
+
+        fn stage_epilogue(&self, _: Stage, _: &bool) -> Option<String> {
+            Some("// epilogue\n".into())
+        }
+
+        fn info(&self) -> TargetInfo {
+            crate::targets::lib::info()
+        }
+
+        fn relocate_bindings(&self, _: &Config, _: &mut BTreeMap<Stage, ShaderStage>) -> Result<(), Box<dyn Error>> {
+            Ok(())
+        }
+
+        fn test_bindings(&self, _: &BTreeMap<Stage, ShaderStage>) -> Result<(), Box<dyn Error>> {
+            Ok(())
+        }
+
+        fn compile_link(&self, _: &Config, _: BTreeMap<Stage, ShaderStage>) -> Result<Self::CompileOutput, Box<dyn Error>> {
+            Ok(())
+        }
+
+        fn write_finish(&self, _: &Config, _: Self::CompileOutput) -> Result<(), Box<dyn Error>> {
+            Ok(())
+        }
+    }
+
+    fn source() -> StageSource<'static>
+    {
+        StageSource {
+            version: "#version 400 core\n".into(),
+            extensions: &["GL_ARB_separate_shader_objects"],
+            defines: &[],
+            sal: "uniform float Test;\n".into(),
+            user: vec![Part::new_with_name("void main() {}\n", "user.glsl")]
+        }
+    }
+
+    #[test]
+    fn part_order()
+    {
+        let parts = assemble_stage_parts(&MockTarget, &true, Stage::Pixel, source());
+        let names: Vec<&str> = parts.iter().map(|v| v.name().unwrap()).collect();
+        assert_eq!(names, [PART_VERSION, PART_EXTENSIONS, PART_PROLOGUE, PART_SAL, "user.glsl", PART_EPILOGUE]);
+        assert_eq!(parts[2].code(), "// prologue Pixel\n");
+        let code: String = parts.into_iter().map(|v| v.into_code()).collect();
+        assert!(code.starts_with("#version 400 core\n#extension GL_ARB_separate_shader_objects : require\n// prologue Pixel\n"));
+        let parts = assemble_stage_parts(&MockTarget, &false, Stage::Pixel, source());
+        assert!(parts.iter().all(|v| v.name() != Some(PART_PROLOGUE)));
+    }
+
+    #[test]
+    fn defines()
+    {
+        let defines = ["HIGH".to_string(), "SHADOWS".to_string()];
+        let parts = assemble_stage_parts(&MockTarget, &true, Stage::Pixel, StageSource {
+            defines: &defines,
+            ..source()
+        });
+        let names: Vec<&str> = parts.iter().map(|v| v.name().unwrap()).collect();
+        assert_eq!(names, [PART_VERSION, PART_EXTENSIONS, PART_DEFINES, PART_PROLOGUE, PART_SAL, "user.glsl", PART_EPILOGUE]);
+        assert_eq!(parts[2].code(), "#define HIGH 1\n#define SHADOWS 1\n");
+    }
+
+    #[test]
+    fn display_names()
+    {
+        assert_eq!(part_display_name(PART_VERSION), "<version>");
+        assert_eq!(part_display_name(PART_SAL), "<sal>");
+        assert_eq!(part_display_name("user.glsl"), "user.glsl");
+    }
+}
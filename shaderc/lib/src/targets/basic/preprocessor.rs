@@ -29,17 +29,21 @@
 use std::{io::Write, path::Path};
 use std::fmt::{Display, Formatter};
 
-use bpx::{macros::impl_err_conversion, shader::Stage};
+use bpx::macros::impl_err_conversion;
 use log::{debug, trace};
 use bp3d_sal::preprocessor::Handler;
 
 use crate::targets::basic::shaderlib::ShaderLib;
+use crate::Stage;
 
 #[derive(Debug)]
 pub enum Error
 {
     Io(std::io::Error),
     UnknownStage(String),
+    DuplicateStage(Stage),
+    /// A SAL block opened at the given line after GLSL code in a file with several stage sections.
+    LateSal(usize),
     ShaderLib(crate::targets::basic::shaderlib::Error),
     NullInclude,
     IncludeNotFound(String)
@@ -59,6 +63,8 @@ impl Display for Error
         match self {
             Error::Io(e) => write!(f, "io error: {}", e),
             Error::UnknownStage(s) => write!(f, "unknown shader stage '{}'", s),
+            Error::DuplicateStage(s) => write!(f, "duplicate '#stage {}' section", s),
+            Error::LateSal(line) => write!(f, "line {}: SAL block after GLSL code in a file with several stage sections (SAL applies to all stages and must come first)", line),
             Error::ShaderLib(e) => write!(f, "error in shader lib: {}", e),
             Error::NullInclude => f.write_str("include does not have a value"),
             Error::IncludeNotFound(i) => write!(f, "include '{}' not found", i)
@@ -66,15 +72,29 @@ impl Display for Error
     }
 }
 
+/// A `#stage` section of a unit, which extends from its directive to the next one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Section
+{
+    pub stage: Stage,
+    /// Index of the line of the `#stage` directive.
+    pub line: usize
+}
+
 pub struct BasicPreprocessor<'a>
 {
     pub sal_code: Vec<u8>,
     pub includes: Vec<(String, Box<[u8]>)>,
+    /// Shader libraries which provided at least one include.
+    pub opened: Vec<&'a Path>,
     pub src_code: Vec<String>,
     shader_libs: Vec<ShaderLib<'a>>,
-    pub stage: Option<Stage>,
+    pub sections: Vec<Section>,
     line_is_directive: bool,
-    using_sal: bool
+    line_is_sal: bool,
+    using_sal: bool,
+    has_code: bool,
+    late_sal: Option<usize>
 }
 
 impl<'a> BasicPreprocessor<'a>
@@ -84,11 +104,25 @@ impl<'a> BasicPreprocessor<'a>
         Self {
             sal_code: Vec::new(),
             includes: Vec::new(),
+            opened: Vec::new(),
             src_code: Vec::new(),
             shader_libs: libs.into_iter().map(|l| ShaderLib::new(l)).collect(),
-            stage: None,
+            sections: Vec::new(),
             line_is_directive: false,
-            using_sal: false
+            line_is_sal: false,
+            using_sal: false,
+            has_code: false,
+            late_sal: None
+        }
+    }
+
+    /// Checks that SAL blocks only appear before GLSL code in units with several sections: SAL is
+    /// shared by all sections so it must not look like it belongs to one of them.
+    pub fn check_sections(&self) -> Result<(), Error>
+    {
+        match self.late_sal {
+            Some(line) if self.sections.len() > 1 => Err(Error::LateSal(line + 1)),
+            _ => Ok(())
         }
     }
 }
@@ -99,17 +133,17 @@ impl<'a> Handler for BasicPreprocessor<'a>
 
     fn directive(&mut self, name: &str, value: Option<&str>) -> Result<(), Self::Error>
     {
-        debug!("Found directive #{} {:?}", name, value);
+        debug!(target: "translation", "Found directive #{} {:?}", name, value);
         match name {
             "stage" => {
                 let value = value.unwrap_or("");
-                self.stage = Some(match value {
-                    "vertex" => Stage::Vertex,
-                    "hull" => Stage::Hull,
-                    "domain" => Stage::Domain,
-                    "geometry" => Stage::Geometry,
-                    "pixel" => Stage::Pixel,
-                    _ => return Err(Error::UnknownStage(value.into()))
+                let stage = Stage::from_name(value).ok_or_else(|| Error::UnknownStage(value.into()))?;
+                if self.sections.iter().any(|v| v.stage == stage) {
+                    return Err(Error::DuplicateStage(stage));
+                }
+                self.sections.push(Section {
+                    stage,
+                    line: self.src_code.len()
                 });
             },
             "include" => {
@@ -118,15 +152,23 @@ impl<'a> Handler for BasicPreprocessor<'a>
                 for v in &mut self.shader_libs {
                     if let Some(obj) = v.try_load(value)? {
                         self.includes.push((value.into(), obj.into_boxed_slice()));
+                        if !self.opened.contains(&v.path()) {
+                            self.opened.push(v.path());
+                        }
                         flag = true;
-                        debug!("Successfully resolved include {}", value);
+                        debug!(target: "translation", "Successfully resolved include {}", value);
                     }
                 }
                 if !flag {
                     return Err(Error::IncludeNotFound(value.into()));
                 }
             },
-            "sal" => self.using_sal = !self.using_sal,
+            "sal" => {
+                self.using_sal = !self.using_sal;
+                if self.using_sal && self.has_code && self.late_sal.is_none() {
+                    self.late_sal = Some(self.src_code.len());
+                }
+            },
             _ => return Ok(())
         };
         self.line_is_directive = true;
@@ -135,20 +177,32 @@ impl<'a> Handler for BasicPreprocessor<'a>
 
     fn sal_code(&mut self, content: &str) -> Result<(), Self::Error>
     {
-        trace!("SAL> {}", content);
+        trace!(target: "translation", "SAL> {}", content);
         self.sal_code.write_all(content.as_bytes())?;
         self.sal_code.push(b'\n');
+        self.line_is_sal = true;
         Ok(())
     }
 
     fn code_line(&mut self, mut line: String) -> Result<(), Self::Error>
     {
+        //Every line of the unit has a line in the SAL code so that SAL spans are unit lines.
+        let is_sal = std::mem::take(&mut self.line_is_sal);
+        if !is_sal {
+            self.sal_code.push(b'\n');
+        }
+        //Comments and directives may come before SAL blocks in any unit.
+        let trimmed = line.trim();
+        if !is_sal && !self.using_sal && !self.line_is_directive && !trimmed.is_empty()
+            && !trimmed.starts_with("//") && !trimmed.starts_with('#') {
+            self.has_code = true;
+        }
         if self.line_is_directive || self.using_sal {
             line.insert_str(0, "//");
             self.line_is_directive = false;
         }
         if !self.using_sal {
-            trace!("{}", line);
+            trace!(target: "translation", "{}", line);
         }
         self.src_code.push(line);
         Ok(())
@@ -27,119 +27,481 @@
 // SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
 use std::collections::{BTreeMap, HashMap, HashSet};
-use bpx::shader::Stage;
-use log::{debug, error, warn};
-use bp3d_sal::ast::tree::{Attribute, PropertyType, Struct};
-use crate::targets::basic::{BasicAst, ShaderToSal};
+use std::collections::btree_map::Entry;
+use crate::Stage;
+use log::{debug, error, info};
+use bp3d_sal::ast::tree::{Attribute, Property, PropertyType, Struct};
+use regex::Regex;
+use crate::targets::basic::{BasicAst, ShaderToSal, Slot};
+use crate::targets::basic::ast::VaryingInterface;
+use crate::warnings::{Reporter, WarningKind};
 use thiserror::Error;
 
 #[derive(Debug, Error)]
 pub enum Error {
-    #[error("multiple definitions of binding {0}")]
-    RedefinedBinding(u32),
-    #[error("multiple definitions of the same symbol")]
-    RedefinedSymbol,
+    #[error("{location}: '{name}' redefines binding {slot}")]
+    RedefinedBinding
+    {
+        name: String,
+        slot: u32,
+        location: String
+    },
+    #[error("{location}: multiple definitions of symbol '{name}'")]
+    RedefinedSymbol
+    {
+        name: String,
+        location: String
+    },
     #[error("unable to locate root constants layout")]
-    NoRootConstants
+    NoRootConstants,
+    #[error("multiple root constants layouts declared: {}", .0.join(", "))]
+    DuplicateRootConstants(Vec<String>),
+    #[error("shared constant buffers '{first}' and '{second}' both map to slot {slot}, give one of them an explicit SHARED_n slot")]
+    SharedSlotConflict
+    {
+        first: String,
+        second: String,
+        slot: u32
+    },
+    #[error("shared constant buffer '{0}' uses slot {1} which is outside of the shared slot range")]
+    SharedSlotOutOfRange(String, u32),
+    #[error("constant buffer '{0}' uses slot {1} which is reserved for shared constant buffers")]
+    ReservedSlot(String, u32),
+    #[error("vertex format '{name}' declared in unit '{unit}' which is a {stage} shader, vertex formats are only allowed in vertex shaders")]
+    VertexFormatStage
+    {
+        name: String,
+        unit: String,
+        stage: Stage
+    },
+    #[error("no vertex format declared in the vertex stage (use --no-vertex-input for vertex pulling)")]
+    MissingVertexFormat,
+    #[error("vertex format '{0}' declared but vertex input is disabled by --no-vertex-input")]
+    UnexpectedVertexFormat(String),
+    #[error("multiple vertex formats declared: {}", .0.join(", "))]
+    DuplicateVertexFormat(Vec<String>),
+    #[error("semantic '{semantic}' is used by both '{first}' and '{second}'")]
+    DuplicateSemantic
+    {
+        semantic: String,
+        first: String,
+        second: String
+    },
+    #[error("varying '{0}' is declared with different types or interpolations across stages")]
+    RedefinedVarying(String),
+    #[error("'{name}' is read by the {consumer} stage but never written by the {producer} stage (use --no-interface-check to allow it)")]
+    MissingVarying
+    {
+        name: String,
+        producer: Stage,
+        consumer: Stage
+    },
+    #[error("'{name}' is written as {produced} by the {producer} stage but read as {consumed} by the {consumer} stage")]
+    VaryingTypeMismatch
+    {
+        name: String,
+        producer: Stage,
+        produced: String,
+        consumer: Stage,
+        consumed: String
+    },
+    #[error("sampler state '{0}' does not match any declared Sampler constant")]
+    UnknownSamplerState(String),
+    #[error("constant buffers '{first}' in '{first_unit}' and '{second}' in '{second_unit}' both use ORDER_{order}")]
+    DuplicateOrder
+    {
+        order: u32,
+        first: String,
+        first_unit: String,
+        second: String,
+        second_unit: String
+    },
+    #[error("constant buffer '{name}' in '{unit}' uses ORDER_{order} which leaves slot {expected} unused, explicit orders must be contiguous when mixed with implicitly ordered constant buffers such as '{implicit}' in '{implicit_unit}'")]
+    OrderGap
+    {
+        name: String,
+        unit: String,
+        order: u32,
+        expected: u32,
+        implicit: String,
+        implicit_unit: String
+    },
+    #[error("constant buffer '{0}' is bound to slot {1} but the target only supports {2} uniform buffer bindings")]
+    CBufferLimit(String, u32, u32),
+    #[error("{location}: output '{name}' uses render target {target} which is already used by '{first}'")]
+    DuplicateTarget
+    {
+        name: String,
+        target: u32,
+        first: String,
+        location: String
+    }
+}
+
+/// First constant buffer slot reserved for shared constant buffers.
+pub const SHARED_SLOT_BASE: u32 = 8;
+
+/// Number of constant buffer slots reserved for shared constant buffers.
+pub const SHARED_SLOT_COUNT: u32 = 8;
+
+pub fn is_shared_slot(slot: u32) -> bool
+{
+    (SHARED_SLOT_BASE..SHARED_SLOT_BASE + SHARED_SLOT_COUNT).contains(&slot)
+}
+
+/// Derives the slot of a shared constant buffer without an explicit slot from its name (FNV-1a).
+pub fn shared_slot(name: &str) -> u32
+{
+    let mut hash: u32 = 0x811c9dc5;
+    for b in name.as_bytes() {
+        hash ^= *b as u32;
+        hash = hash.wrapping_mul(0x01000193);
+    }
+    SHARED_SLOT_BASE + hash % SHARED_SLOT_COUNT
+}
+
+fn get_shared_slot<T>(st: &Struct<T>) -> Option<u32>
+{
+    match &st.attr {
+        Some(Attribute::Shared(slot)) => Some(slot.unwrap_or_else(|| shared_slot(&st.name))),
+        _ => None
+    }
+}
+
+/// Checks that vertex formats are only declared by vertex units and that the vertex stage has exactly
+/// one, or none when `vertex_input` is false.
+pub fn test_vertex_formats(shaders: &[ShaderToSal], vertex_input: bool) -> Result<(), Error>
+{
+    let mut decls = Vec::new();
+    for shader in shaders {
+        if let Some(name) = shader.vformats.first() {
+            if shader.stage != Stage::Vertex {
+                return Err(Error::VertexFormatStage {
+                    name: name.clone(),
+                    unit: shader.name.clone(),
+                    stage: shader.stage
+                });
+            }
+        }
+        decls.extend(shader.vformats.iter().map(|v| format!("'{}' in {}", v, shader.name)));
+    }
+    if decls.len() > 1 {
+        return Err(Error::DuplicateVertexFormat(decls));
+    }
+    if !shaders.iter().any(|v| v.stage == Stage::Vertex) {
+        return Ok(());
+    }
+    match (vertex_input, decls.is_empty()) {
+        (true, true) => Err(Error::MissingVertexFormat),
+        (false, false) => {
+            let name = shaders.iter().find_map(|v| v.vformats.first()).unwrap();
+            Err(Error::UnexpectedVertexFormat(name.clone()))
+        },
+        _ => Ok(())
+    }
+}
+
+/// Checks that the units of a program declare at most one rootconst block; a block imported by
+/// several units counts once. ORDER_0 constant buffers are ignored when a rootconst block exists.
+pub fn test_root_constants(shaders: &[ShaderToSal], warnings: &Reporter) -> Result<(), Error>
+{
+    let mut decls: Vec<(&str, &str)> = Vec::new();
+    for shader in shaders {
+        for name in &shader.rootconsts {
+            if !decls.iter().any(|(v, _)| v == name) {
+                decls.push((name, &shader.name));
+            }
+        }
+    }
+    if decls.len() > 1 {
+        return Err(Error::DuplicateRootConstants(decls.iter().map(|(name, unit)| format!("'{}' in {}", name, unit)).collect()));
+    }
+    if let Some((name, _)) = decls.first() {
+        for shader in shaders {
+            match &shader.statements.root_constants_layout {
+                Some(v) if !shader.statements.root_constants_explicit => {
+                    warnings.report_in(WarningKind::Duplicate, &shader.name, format!("Ignoring ORDER_0 constant buffer '{}' in favor of root constants layout '{}'", v.name, name));
+                },
+                _ => ()
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Computes the slot of each constant buffer, identified by its name, from the constant buffers
+/// of all units or stages of a program paired with the unit or stage they come from.
+///
+/// Explicit ORDER_n slots must be unique; when some constant buffers have no ORDER_n they must also
+/// be contiguous from slot 1 (or 0), and the other constant buffers take the following slots in
+/// declaration order. Shared constant buffers keep their SHARED_n slot and are not part of the result.
+pub fn order_cbuffers<'a, 'b>(cbuffers: impl IntoIterator<Item = (&'b str, &'a Struct<usize>)>) -> Result<Vec<(&'a str, u32)>, Error>
+{
+    let mut explicit: BTreeMap<u32, (&str, &str)> = BTreeMap::new();
+    let mut implicit: Vec<(&str, &str)> = Vec::new();
+    let mut names = HashSet::new();
+    for (unit, st) in cbuffers {
+        if get_shared_slot(st).is_some() || !names.insert(&*st.name) {
+            continue;
+        }
+        match st.attr {
+            Some(Attribute::Order(order)) => {
+                if let Some((first, first_unit)) = explicit.get(&order) {
+                    return Err(Error::DuplicateOrder {
+                        order,
+                        first: (*first).into(),
+                        first_unit: (*first_unit).into(),
+                        second: st.name.clone(),
+                        second_unit: unit.into()
+                    });
+                }
+                explicit.insert(order, (&*st.name, unit));
+            },
+            _ => implicit.push((&*st.name, unit))
+        }
+    }
+    if let Some((implicit_name, implicit_unit)) = implicit.first() {
+        let mut expected = explicit.keys().next().map(|v| (*v).min(1)).unwrap_or(1);
+        for (order, (name, unit)) in &explicit {
+            while is_shared_slot(expected) {
+                expected += 1;
+            }
+            if *order != expected {
+                return Err(Error::OrderGap {
+                    name: (*name).into(),
+                    unit: (*unit).into(),
+                    order: *order,
+                    expected,
+                    implicit: (*implicit_name).into(),
+                    implicit_unit: (*implicit_unit).into()
+                });
+            }
+            expected += 1;
+        }
+    }
+    let mut slot = explicit.keys().next_back().map(|v| v + 1).unwrap_or(1);
+    let mut orders: Vec<(&str, u32)> = explicit.into_iter().map(|(order, (name, _))| (name, order)).collect();
+    for (name, _) in implicit {
+        while is_shared_slot(slot) {
+            slot += 1;
+        }
+        orders.push((name, slot));
+        slot += 1;
+    }
+    Ok(orders)
 }
 
 pub struct ShaderStage
 {
     pub statements: BasicAst,
-    pub strings: Vec<rglslang::shader::Part>
+    pub strings: Vec<rglslang::shader::Part>,
+    /// Text of the units merged into the stage as written, named after the unit.
+    pub sources: Vec<rglslang::shader::Part>
 }
 
 #[derive(Debug)]
 pub enum BindingType
 {
-    Texture,
+    /// A texture or an array of textures, carrying the number of array elements (1 for a single texture).
+    Texture(u32),
     Sampler,
     CBuf
 }
 
-pub fn merge_stages(shaders: Vec<ShaderToSal>) -> BTreeMap<Stage, ShaderStage>
+fn texture_count(ptype: &PropertyType<usize>) -> u32
+{
+    match ptype {
+        PropertyType::Array(a) => a.size,
+        _ => 1
+    }
+}
+
+pub fn merge_stages(shaders: Vec<ShaderToSal>, warnings: &Reporter) -> BTreeMap<Stage, ShaderStage>
 {
     let mut map = BTreeMap::new();
     for v in shaders {
-        if !map.contains_key(&v.stage) {
-            map.insert(v.stage, ShaderStage {
-                statements: v.statements,
-                strings: v.strings
-            });
-        } else {
-            let stage = map.get_mut(&v.stage).unwrap();
-            stage.strings.extend(v.strings);
-            stage.statements.extend(v.statements);
+        let source = rglslang::shader::Part::new_with_name(v.source, &v.name);
+        //Statements are moved into their stage, never cloned.
+        match map.entry(v.stage) {
+            Entry::Vacant(e) => {
+                e.insert(ShaderStage {
+                    statements: v.statements,
+                    strings: v.strings,
+                    sources: vec![source]
+                });
+            },
+            Entry::Occupied(mut e) => {
+                let stage = e.get_mut();
+                stage.strings.extend(v.strings);
+                stage.sources.push(source);
+                stage.statements.extend(v.statements, warnings);
+            }
         }
     }
     map
 }
 
-pub fn relocate_bindings<'a, F: FnMut(&'a str, BindingType, Option<u32>, u32) -> u32>(stages: &'a BTreeMap<Stage, ShaderStage>, mut func: F)
+/// Assigns the render target index of each output of a stage: outputs with a TARGET_n (or ORDER_n)
+/// attribute keep their index and the other outputs take the free indices in declaration order.
+pub fn relocate_outputs(outputs: &[Slot<Property<usize>>]) -> Result<(), Error>
 {
+    let explicit: Vec<Option<u32>> = outputs.iter()
+        .map(|v| v.inner.pattr.as_ref().and_then(|v| v.get_target().or_else(|| v.get_order())))
+        .collect();
+    let mut used: HashMap<u32, &str> = HashMap::new();
+    for (v, target) in outputs.iter().zip(&explicit) {
+        let target = match target {
+            Some(v) => *v,
+            None => continue
+        };
+        if let Some(first) = used.insert(target, &v.inner.pname) {
+            return Err(Error::DuplicateTarget {
+                name: v.inner.pname.clone(),
+                target,
+                first: first.into(),
+                location: v.location(v.inner.span)
+            });
+        }
+        v.slot.set(target);
+        v.external.set(true);
+    }
+    let mut target = 0;
+    for (v, _) in outputs.iter().zip(&explicit).filter(|(_, target)| target.is_none()) {
+        while used.contains_key(&target) {
+            target += 1;
+        }
+        debug!(target: "relocation", "Output {} : {}", v.inner.pname, target);
+        v.slot.set(target);
+        target += 1;
+    }
+    Ok(())
+}
+
+fn binding_kind(t: &BindingType) -> &'static str
+{
+    match t {
+        BindingType::Texture(_) => "texture",
+        BindingType::Sampler => "sampler",
+        BindingType::CBuf => "cbuffer"
+    }
+}
+
+/// Relocates the bindings of all stages; `func` receives the name, type and explicit slot of each
+/// symbol once and returns its slot.
+///
+/// Constant buffers always receive their slot from [order_cbuffers] and are relocated first. Textures
+/// and samplers are then relocated independently of the order of stages and declarations: symbols with
+/// an ORDER_n attribute first, by slot, then the other symbols by name. As targets give implicit
+/// symbols the lowest free slots, adding a symbol never moves the symbols whose names sort before it.
+pub fn relocate_bindings<'a, F: FnMut(&'a str, BindingType, Option<u32>) -> u32>(stages: &'a BTreeMap<Stage, ShaderStage>, mut func: F) -> Result<(), Error>
+{
+    for v in stages.values() {
+        relocate_outputs(&v.statements.outputs)?;
+    }
+    let orders = order_cbuffers(stages.iter()
+        .flat_map(|(stage, v)| v.statements.cbuffers.iter().map(move |v| (stage.name(), &v.inner))))?;
+    for (name, slot) in &orders {
+        info!(target: "relocation", "Constant buffer {} ordered at slot {}", name, slot);
+    }
+    let orders: HashMap<&str, u32> = orders.into_iter().collect();
     let mut map = HashMap::new();
-    stages.iter().for_each(|(_, v)| {
-        for v in &v.statements.cbuffers {
-            let mut cbuf_func = || {
-                if let Some(attr) = &v.inner.attr {
-                    if let Attribute::Order(slot) = attr {
+    for v in stages.values().flat_map(|v| v.statements.cbuffers.iter()) {
+        let fsk;
+        if let Some(slot) = map.get(&v.inner.name) {
+            fsk = *slot;
+        } else {
+            let slot = match get_shared_slot(&v.inner) {
+                Some(slot) => {
+                    v.external.set(true);
+                    slot
+                },
+                None => {
+                    if let Some(Attribute::Order(_)) = v.inner.attr {
                         v.external.set(true);
-                        return func(&v.inner.name, BindingType::CBuf, Some(*slot), v.slot.get());
                     }
+                    orders[&*v.inner.name]
                 }
-                func(&v.inner.name, BindingType::CBuf, None, v.slot.get())
             };
-            let fsk;
-            if let Some(slot) = map.get(&v.inner.name) {
-                fsk = *slot;
-            } else {
-                fsk = cbuf_func();
-                map.insert(&v.inner.name, fsk);
-            }
-            debug!("CBuffer {} : {}", v.inner.name, fsk);
-            v.slot.set(fsk);
+            fsk = func(&v.inner.name, BindingType::CBuf, Some(slot));
+            map.insert(&v.inner.name, fsk);
         }
-        for v in &v.statements.objects {
-            let mut prop_func = |t: BindingType| {
-                if let Some(attr) = &v.inner.pattr {
-                    if let Attribute::Order(slot) = attr {
-                        v.external.set(true);
-                        return func(&v.inner.pname, t, Some(*slot), v.slot.get());
-                    }
-                }
-                func(&v.inner.pname, t, None, v.slot.get())
-            };
-            let fsk;
-            if let Some(slot) = map.get(&v.inner.pname) {
-                fsk = *slot;
-            } else {
-                fsk = match v.inner.ptype {
-                    PropertyType::Sampler => prop_func(BindingType::Sampler),
-                    _ => prop_func(BindingType::Texture)
-                };
-                map.insert(&v.inner.pname, fsk);
-            }
-            debug!("Object {:?} {} : {}", v.inner.ptype, v.inner.pname, fsk);
-            v.slot.set(fsk);
+        debug!(target: "relocation", "CBuffer {} : {}", v.inner.name, fsk);
+        v.slot.set(fsk);
+    }
+    let mut table: Vec<(&str, &str, u32)> = map.iter().map(|(&name, &slot)| ("cbuffer", name.as_str(), slot)).collect();
+    //The same symbol may be declared by several stages.
+    let mut names = HashSet::new();
+    let mut objects: Vec<(Option<u32>, &Slot<Property<usize>>)> = Vec::new();
+    for v in stages.values().flat_map(|v| v.statements.objects.iter()) {
+        if !names.insert(&v.inner.pname) {
+            continue;
         }
-    });
+        let order = match v.inner.pattr {
+            Some(Attribute::Order(slot)) => Some(slot),
+            _ => None
+        };
+        objects.push((order, v));
+    }
+    objects.sort_by(|(a, v), (b, v1)| (a.is_none(), a, &v.inner.pname).cmp(&(b.is_none(), b, &v1.inner.pname)));
+    for (order, v) in objects {
+        let t = match v.inner.ptype {
+            PropertyType::Sampler | PropertyType::SamplerCmp => BindingType::Sampler,
+            _ => BindingType::Texture(texture_count(&v.inner.ptype))
+        };
+        let kind = binding_kind(&t);
+        let fsk = func(&v.inner.pname, t, order);
+        table.push((kind, v.inner.pname.as_str(), fsk));
+        map.insert(&v.inner.pname, fsk);
+    }
+    for v in stages.values().flat_map(|v| v.statements.objects.iter()) {
+        if let Some(Attribute::Order(_)) = v.inner.pattr {
+            v.external.set(true);
+        }
+        let fsk = map[&v.inner.pname];
+        debug!(target: "relocation", "Object {:?} {} : {}", v.inner.ptype, v.inner.pname, fsk);
+        v.slot.set(fsk);
+    }
+    table.sort_by_key(|(kind, name, slot)| (*kind, *slot, *name));
+    for (kind, name, slot) in table {
+        info!(target: "relocation", "{} {} -> {}", kind, name, slot);
+    }
+    Ok(())
+}
+
+fn redefined_binding(slot: &Slot<Property<usize>>) -> Error
+{
+    Error::RedefinedBinding {
+        name: slot.inner.pname.clone(),
+        slot: slot.slot.get(),
+        location: slot.location(slot.inner.span)
+    }
 }
 
 pub fn test_bindings<F: FnMut(BindingType, u32) -> bool>(stages: &BTreeMap<Stage, ShaderStage>, mut func: F) -> Result<(), Error>
 {
     let mut map = HashMap::new();
     for v in stages.values() {
-        if v.statements.root_constants_layout.is_some() && !func(BindingType::CBuf, 0) {
-            error!("Redefinition of root constants layout");
-            return Err(Error::RedefinedBinding(0))
+        if let Some(layout) = &v.statements.root_constants_layout {
+            if !func(BindingType::CBuf, 0) {
+                error!(target: "relocation", "Redefinition of root constants layout");
+                return Err(Error::RedefinedBinding {
+                    name: layout.name.clone(),
+                    slot: 0,
+                    location: layout.span.to_string()
+                });
+            }
         }
         for slot in &v.statements.cbuffers {
             if map.contains_key(&slot.inner.name) {
                 continue;
             }
             if !func(BindingType::CBuf, slot.slot.get()) {
-                error!("Constant buffer '{}' is attempting to relocate to {} which is already in use!", slot.inner.name, slot.slot.get());
-                return Err(Error::RedefinedBinding(slot.slot.get()));
+                error!(target: "relocation", "Constant buffer '{}' is attempting to relocate to {} which is already in use!", slot.inner.name, slot.slot.get());
+                return Err(Error::RedefinedBinding {
+                    name: slot.inner.name.clone(),
+                    slot: slot.slot.get(),
+                    location: slot.location(slot.inner.span)
+                });
             }
             map.insert(&slot.inner.name, slot.slot.get());
         }
@@ -147,15 +509,15 @@ pub fn test_bindings<F: FnMut(BindingType, u32) -> bool>(stages: &BTreeMap<Stage
             if map.contains_key(&slot.inner.pname) {
                 continue;
             }
-            if slot.inner.ptype != PropertyType::Sampler {
+            if matches!(slot.inner.ptype, PropertyType::Sampler | PropertyType::SamplerCmp) {
                 if !func(BindingType::Sampler, slot.slot.get()) {
-                    error!("Sampler '{}' is attempting to relocate to {} which is already in use!", slot.inner.pname, slot.slot.get());
-                    return Err(Error::RedefinedBinding(slot.slot.get()));
+                    error!(target: "relocation", "Sampler '{}' is attempting to relocate to {} which is already in use!", slot.inner.pname, slot.slot.get());
+                    return Err(redefined_binding(slot));
                 }
             } else {
-                if !func(BindingType::Texture, slot.slot.get()) {
-                    warn!("Texture '{}' is attempting to relocate to {} which is already in use!", slot.inner.pname, slot.slot.get());
-                    return Err(Error::RedefinedBinding(slot.slot.get()));
+                if !func(BindingType::Texture(texture_count(&slot.inner.ptype)), slot.slot.get()) {
+                    error!(target: "relocation", "Texture '{}' is attempting to relocate to {} which is already in use!", slot.inner.pname, slot.slot.get());
+                    return Err(redefined_binding(slot));
                 }
             }
             map.insert(&slot.inner.pname, slot.slot.get());
@@ -170,28 +532,650 @@ pub fn test_symbols(stages: &BTreeMap<Stage, ShaderStage>) -> Result<(), Error>
         let mut set = HashSet::new();
         for v in &v.statements.cbuffers {
             if !set.insert(&v.inner.name) {
-                error!("Multiple definitions of symbol '{}'", v.inner.name);
-                return Err(Error::RedefinedSymbol);
+                error!(target: "translation", "Multiple definitions of symbol '{}'", v.inner.name);
+                return Err(Error::RedefinedSymbol {
+                    name: v.inner.name.clone(),
+                    location: v.location(v.inner.span)
+                });
             }
         }
         for v in &v.statements.objects {
             if !set.insert(&v.inner.pname) {
-                error!("Multiple definitions of symbol '{}'", v.inner.pname);
-                return Err(Error::RedefinedSymbol);
+                error!(target: "translation", "Multiple definitions of symbol '{}'", v.inner.pname);
+                return Err(Error::RedefinedSymbol {
+                    name: v.inner.pname.clone(),
+                    location: v.location(v.inner.span)
+                });
+            }
+        }
+        //Integer constants become preprocessor defines, they may not shadow any other symbol.
+        for v in &v.statements.constant_ints {
+            if !set.insert(&v.inner.name) {
+                error!(target: "translation", "Multiple definitions of symbol '{}'", v.inner.name);
+                return Err(Error::RedefinedSymbol {
+                    name: v.inner.name.clone(),
+                    location: v.location(v.inner.span)
+                });
+            }
+        }
+        if let Some(vformat) = &v.statements.vformat {
+            vformat_semantics(vformat)?;
+        }
+        for state in &v.statements.samplerstates {
+            let sampler = v.statements.objects.iter()
+                .find(|v| v.inner.pname == state.name && matches!(v.inner.ptype, PropertyType::Sampler | PropertyType::SamplerCmp));
+            if sampler.is_none() {
+                error!(target: "translation", "Sampler state '{}' does not match any declared Sampler constant", state.name);
+                return Err(Error::UnknownSamplerState(state.name.clone()));
             }
         }
     }
     Ok(())
 }
 
-pub fn get_root_constants_layout(stages: &mut BTreeMap<Stage, ShaderStage>) -> Result<Struct<usize>, Error>
+/// Normalizes a semantic for comparisons: semantics are case-insensitive and a missing index means 0.
+fn semantic_key(semantic: &str) -> String
 {
-    let root_constants_layout = stages.iter_mut().find(|(_, v)| {
-        if let Some(_) = &v.statements.root_constants_layout {
-            true
-        } else {
-            false
+    let mut key = semantic.to_ascii_uppercase();
+    if !key.ends_with(|c: char| c.is_ascii_digit()) {
+        key.push('0');
+    }
+    key
+}
+
+fn test_semantics<'a>(items: impl Iterator<Item = (&'a str, &'a str)>) -> Result<(), Error>
+{
+    let mut keys: HashMap<String, &str> = HashMap::new();
+    for (name, semantic) in items {
+        if let Some(first) = keys.insert(semantic_key(semantic), name) {
+            return Err(Error::DuplicateSemantic {
+                semantic: semantic.into(),
+                first: first.into(),
+                second: name.into()
+            });
+        }
+    }
+    Ok(())
+}
+
+fn get_semantic(p: &Property<usize>) -> Option<&str>
+{
+    p.pattr.as_ref().and_then(|v| v.get_semantic())
+}
+
+/// Returns the HLSL semantic of each member of a vertex format. Members without a SEMANTIC_x
+/// attribute are numbered TEXCOORDn in declaration order, skipping indices taken by explicit
+/// semantics.
+pub fn vformat_semantics(vformat: &Struct<usize>) -> Result<Vec<String>, Error>
+{
+    let explicit: HashSet<String> = vformat.props.iter().filter_map(get_semantic).map(semantic_key).collect();
+    let mut index = 0;
+    let semantics: Vec<String> = vformat.props.iter().map(|v| match get_semantic(v) {
+        Some(semantic) => semantic.into(),
+        None => {
+            while explicit.contains(&format!("TEXCOORD{}", index)) {
+                index += 1;
+            }
+            index += 1;
+            format!("TEXCOORD{}", index - 1)
+        }
+    }).collect();
+    test_semantics(vformat.props.iter().map(|v| &*v.pname).zip(semantics.iter().map(|v| &**v)))?;
+    Ok(semantics)
+}
+
+/// Returns the vertex attribute location of each member of a vertex format. Members without a
+/// LOCATION_x attribute take the lowest free locations in declaration order; a matrix takes one
+/// location per column.
+pub fn vformat_locations(vformat: &Struct<usize>) -> Vec<u32>
+{
+    let mut used: HashSet<u32> = vformat.props.iter()
+        .filter_map(|v| v.get_location().map(|l| l..l + v.get_location_count()))
+        .flatten()
+        .collect();
+    vformat.props.iter().map(|v| match v.get_location() {
+        Some(location) => location,
+        None => {
+            let count = v.get_location_count();
+            let location = (0..).find(|l| (*l..*l + count).all(|l| !used.contains(&l))).unwrap();
+            used.extend(location..location + count);
+            location
+        }
+    }).collect()
+}
+
+/// Warns about vertex formats mixing explicit and implicit locations, as the implicit ones no
+/// longer follow declaration order.
+pub fn test_vformat_locations(stages: &BTreeMap<Stage, ShaderStage>, warnings: &Reporter)
+{
+    for vformat in stages.values().filter_map(|v| v.statements.vformat.as_ref()) {
+        let explicit = vformat.props.iter().filter(|v| v.get_location().is_some()).count();
+        if explicit == 0 || explicit == vformat.props.len() {
+            continue;
+        }
+        let implicit: Vec<String> = vformat.props.iter().zip(vformat_locations(vformat))
+            .filter(|(v, _)| v.get_location().is_none())
+            .map(|(v, l)| format!("{} = {}", v.pname, l))
+            .collect();
+        warnings.report(WarningKind::ImplicitLocation, format!("vertex format '{}' mixes explicit and implicit locations, assigned {}", vformat.name, implicit.join(", ")));
+    }
+}
+
+/// Returns the HLSL semantic of each output; outputs without a SEMANTIC_x attribute use
+/// SV_Targetn where n is their slot, so this must run after binding relocation.
+pub fn output_semantics(outputs: &[Slot<Property<usize>>]) -> Result<Vec<String>, Error>
+{
+    let semantics: Vec<String> = outputs.iter().map(|v| match get_semantic(&v.inner) {
+        Some(semantic) => semantic.into(),
+        None => format!("SV_Target{}", v.slot.get())
+    }).collect();
+    test_semantics(outputs.iter().map(|v| &*v.inner.pname).zip(semantics.iter().map(|v| &**v)))?;
+    Ok(semantics)
+}
+
+pub fn test_shared_bindings(stages: &BTreeMap<Stage, ShaderStage>) -> Result<(), Error>
+{
+    let mut slots: HashMap<u32, &str> = HashMap::new();
+    for v in stages.values() {
+        for v in &v.statements.cbuffers {
+            if let Some(slot) = get_shared_slot(&v.inner) {
+                if !is_shared_slot(slot) {
+                    return Err(Error::SharedSlotOutOfRange(v.inner.name.clone(), slot));
+                }
+                if let Some(first) = slots.insert(slot, &v.inner.name) {
+                    if first != v.inner.name {
+                        return Err(Error::SharedSlotConflict {
+                            first: first.into(),
+                            second: v.inner.name.clone(),
+                            slot
+                        });
+                    }
+                }
+            } else if let Some(Attribute::Order(slot)) = &v.inner.attr {
+                if is_shared_slot(*slot) {
+                    return Err(Error::ReservedSlot(v.inner.name.clone(), *slot));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+//Stages which take part in the varying chain, in pipeline order; the order of Stage puts Mesh
+//after Pixel so it can't be used here.
+const VARYING_CHAIN: [Stage; 6] = [Stage::Vertex, Stage::Hull, Stage::Domain, Stage::Geometry, Stage::Mesh, Stage::Pixel];
+
+fn varying_stages(stages: &BTreeMap<Stage, ShaderStage>) -> Vec<Stage>
+{
+    VARYING_CHAIN.into_iter().filter(|v| stages.contains_key(v)).collect()
+}
+
+/// Shares the varyings declared by any stage with all stages and computes the interface blocks of
+/// each stage: the first stage has no input block and the last one has no output block.
+pub fn link_varyings(stages: &mut BTreeMap<Stage, ShaderStage>) -> Result<(), Error>
+{
+    let mut varyings: Vec<Property<usize>> = Vec::new();
+    for v in stages.values() {
+        for v in &v.statements.varyings {
+            match varyings.iter().find(|v1| v1.pname == v.pname) {
+                Some(v1) if v1 != v => return Err(Error::RedefinedVarying(v.pname.clone())),
+                Some(_) => (),
+                None => varyings.push(v.clone())
+            }
+        }
+    }
+    if varyings.is_empty() {
+        return Ok(());
+    }
+    let chain = varying_stages(stages);
+    for (i, stage) in chain.iter().enumerate() {
+        //SAFETY: the chain only contains keys of the map.
+        let statements = &mut stages.get_mut(stage).unwrap().statements;
+        statements.varyings = varyings.clone();
+        statements.varying_interface = VaryingInterface {
+            input: (i > 0).then(|| matches!(stage, Stage::Hull | Stage::Domain | Stage::Geometry)),
+            output: (i + 1 < chain.len()).then(|| matches!(stage, Stage::Hull | Stage::Mesh))
+        };
+    }
+    Ok(())
+}
+
+//Returns the names of the varyings accessed through the given interface block (In or Out).
+fn varying_accesses(stage: &ShaderStage, block: &str) -> HashSet<String>
+{
+    let regex = Regex::new(&format!(r"\b{}\s*(\[[^\]]*\])?\s*\.\s*(\w+)", block)).unwrap();
+    stage.strings.iter()
+        .flat_map(|v| regex.captures_iter(v.code()).map(|v| v[2].to_string()).collect::<Vec<_>>())
+        .collect()
+}
+
+//Returns the type of the variables declared by GLSL code at global scope with the given storage
+//qualifier (in or out); array sizes are ignored as inputs of some stages are arrays of outputs.
+fn glsl_interface(stage: &ShaderStage, storage: &str) -> BTreeMap<String, String>
+{
+    let regex = Regex::new(&format!(
+        r"(?m)^\s*(?:layout\s*\([^)]*\)\s*)?(?:(?:flat|smooth|noperspective|centroid|sample|patch|invariant)\s+)*{}\s+(\w+)\s+(\w+)\s*(?:\[[^\]]*\])?\s*;",
+        storage
+    )).unwrap();
+    stage.strings.iter()
+        .flat_map(|v| regex.captures_iter(v.code()).map(|v| (v[2].to_string(), v[1].to_string())).collect::<Vec<_>>())
+        .collect()
+}
+
+/// Checks that each stage only reads what the previous stage writes: varyings read through the In
+/// block must be written through the Out block and GLSL `in` variables must match an `out`
+/// variable of the same type; must run after [link_varyings].
+pub fn test_interfaces(stages: &BTreeMap<Stage, ShaderStage>) -> Result<(), Error>
+{
+    let chain = varying_stages(stages);
+    for pair in chain.windows(2) {
+        let (producer, consumer) = (&stages[&pair[0]], &stages[&pair[1]]);
+        let missing = |name: String| Error::MissingVarying {
+            name,
+            producer: pair[0],
+            consumer: pair[1]
+        };
+        let writes = varying_accesses(producer, "Out");
+        let mut reads: Vec<String> = varying_accesses(consumer, "In").into_iter().collect();
+        reads.sort();
+        if let Some(name) = reads.into_iter().find(|v| !writes.contains(v)) {
+            error!(target: "translation", "Varying '{}' is read by the {} stage but never written by the {} stage", name, pair[1], pair[0]);
+            return Err(missing(name));
+        }
+        let outputs = glsl_interface(producer, "out");
+        for (name, consumed) in glsl_interface(consumer, "in") {
+            match outputs.get(&name) {
+                None => {
+                    error!(target: "translation", "Input '{}' of the {} stage is not an output of the {} stage", name, pair[1], pair[0]);
+                    return Err(missing(name));
+                },
+                Some(produced) if produced != &consumed => return Err(Error::VaryingTypeMismatch {
+                    name,
+                    producer: pair[0],
+                    produced: produced.clone(),
+                    consumer: pair[1],
+                    consumed
+                }),
+                Some(_) => ()
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Warns about varyings which are written by a stage but not read by the next one, read by a
+/// stage but not written by the previous one or not used at all; must run after
+/// [link_varyings].
+pub fn test_varyings(stages: &BTreeMap<Stage, ShaderStage>, warnings: &Reporter)
+{
+    let chain = varying_stages(stages);
+    let varyings = match chain.first() {
+        Some(stage) => &stages[stage].statements.varyings,
+        None => return
+    };
+    let reads: Vec<HashSet<String>> = chain.iter().map(|v| varying_accesses(&stages[v], "In")).collect();
+    let writes: Vec<HashSet<String>> = chain.iter().map(|v| varying_accesses(&stages[v], "Out")).collect();
+    for v in varyings {
+        let name = &v.pname;
+        if !reads.iter().chain(writes.iter()).any(|v| v.contains(name)) {
+            warnings.report(WarningKind::Varying, format!("varying '{}' is never used", name));
+            continue;
         }
-    }).ok_or_else(|| Error::NoRootConstants)?.1;
+        for (i, stage) in chain.iter().enumerate() {
+            if writes[i].contains(name) && i + 1 < chain.len() && !reads[i + 1].contains(name) {
+                warnings.report(WarningKind::Varying, format!("varying '{}' is written by the {} stage but never read by the {} stage", name, stage, chain[i + 1]));
+            }
+            if reads[i].contains(name) && i > 0 && !writes[i - 1].contains(name) {
+                warnings.report(WarningKind::Varying, format!("varying '{}' is read by the {} stage but never written by the {} stage", name, stage, chain[i - 1]));
+            }
+        }
+    }
+}
+
+pub fn get_root_constants_layout(stages: &mut BTreeMap<Stage, ShaderStage>) -> Result<Struct<usize>, Error>
+{
+    //A rootconst block takes precedence over ORDER_0 constant buffers.
+    let stage = stages.iter().find(|(_, v)| v.statements.root_constants_explicit)
+        .or_else(|| stages.iter().find(|(_, v)| v.statements.root_constants_layout.is_some()))
+        .map(|(k, _)| *k)
+        .ok_or_else(|| Error::NoRootConstants)?;
+    let root_constants_layout = stages.get_mut(&stage).unwrap();
     Ok(root_constants_layout.statements.root_constants_layout.take().unwrap())
 }
+
+#[cfg(test)]
+mod tests
+{
+    use std::collections::{BTreeMap, HashSet};
+    use crate::Stage;
+    use bp3d_sal::ast::tree::{Attribute, BaseType, Property, PropertyType, Span, Struct, VectorType};
+    use crate::targets::basic::{BasicAst, ShaderToSal, Slot};
+    use crate::warnings::Reporter;
+    use crate::targets::basic::sal_compiler::{Error, is_shared_slot, order_cbuffers, output_semantics, relocate_bindings, relocate_outputs, shared_slot, ShaderStage, test_bindings, test_shared_bindings, test_symbols, test_vertex_formats, test_vformat_locations, vformat_locations, vformat_semantics};
+
+    fn cbuffer(name: &str, attr: Option<Attribute>) -> Slot<Struct<usize>>
+    {
+        Slot::new(Struct {
+            name: name.into(),
+            attr,
+            deprecated: None,
+            props: vec![Property {
+                pname: "Value".into(),
+                ptype: PropertyType::Scalar(BaseType::Float),
+                pattr: None,
+                deprecated: None,
+                pformat: None,
+                default: None,
+                span: Default::default()
+            }],
+            span: Default::default()
+        })
+    }
+
+    fn unit(name: &str, stage: Stage, vformats: &[&str]) -> ShaderToSal
+    {
+        ShaderToSal {
+            name: name.into(),
+            strings: Vec::new(),
+            statements: BasicAst::new(),
+            stage,
+            vformats: vformats.iter().map(|v| String::from(*v)).collect(),
+            rootconsts: Vec::new(),
+            dependencies: Vec::new(),
+            source: String::new()
+        }
+    }
+
+    fn stages(cbuffers: Vec<Slot<Struct<usize>>>) -> BTreeMap<Stage, ShaderStage>
+    {
+        let mut statements = BasicAst::new();
+        statements.cbuffers = cbuffers;
+        let mut map = BTreeMap::new();
+        map.insert(Stage::Vertex, ShaderStage {
+            statements,
+            strings: Vec::new(),
+            sources: Vec::new()
+        });
+        map
+    }
+
+    #[test]
+    fn shared_slot_is_deterministic()
+    {
+        assert_eq!(shared_slot("Camera"), shared_slot("Camera"));
+        for name in ["Camera", "Frame", "Lighting", "Time"] {
+            assert!(is_shared_slot(shared_slot(name)));
+        }
+    }
+
+    #[test]
+    fn shared_slot_conflicts()
+    {
+        let slot = shared_slot("Camera");
+        let map = stages(vec![
+            cbuffer("Camera", Some(Attribute::Shared(None))),
+            cbuffer("Other", Some(Attribute::Shared(Some(slot))))
+        ]);
+        assert!(matches!(test_shared_bindings(&map), Err(Error::SharedSlotConflict { slot: s, .. }) if s == slot));
+        let map = stages(vec![cbuffer("Material", Some(Attribute::Order(slot)))]);
+        assert!(matches!(test_shared_bindings(&map), Err(Error::ReservedSlot(_, s)) if s == slot));
+        let map = stages(vec![cbuffer("Camera", Some(Attribute::Shared(Some(1))))]);
+        assert!(matches!(test_shared_bindings(&map), Err(Error::SharedSlotOutOfRange(_, 1))));
+        let map = stages(vec![
+            cbuffer("Camera", Some(Attribute::Shared(None))),
+            cbuffer("Material", Some(Attribute::Order(1)))
+        ]);
+        assert!(test_shared_bindings(&map).is_ok());
+    }
+
+    #[test]
+    fn cbuffer_orders()
+    {
+        let viewport = cbuffer("Viewport", Some(Attribute::Order(1)));
+        let lighting = cbuffer("Lighting", Some(Attribute::Order(2)));
+        let material = cbuffer("Material", None);
+        let camera = cbuffer("Camera", Some(Attribute::Shared(None)));
+        let orders = order_cbuffers([
+            ("pixel.glsl", &lighting.inner),
+            ("vertex.glsl", &viewport.inner),
+            ("pixel.glsl", &material.inner),
+            ("vertex.glsl", &camera.inner),
+            ("vertex.glsl", &material.inner)
+        ]).unwrap();
+        assert_eq!(orders, [("Viewport", 1), ("Lighting", 2), ("Material", 3)]);
+        let root = cbuffer("Root", Some(Attribute::Order(0)));
+        let orders = order_cbuffers([("vertex.glsl", &root.inner), ("vertex.glsl", &viewport.inner), ("pixel.glsl", &material.inner)]).unwrap();
+        assert_eq!(orders, [("Root", 0), ("Viewport", 1), ("Material", 2)]);
+        //Implicit orders skip the slots reserved for shared constant buffers.
+        let buffers: Vec<Slot<Struct<usize>>> = (0..9).map(|i| cbuffer(&format!("Buffer{}", i), None)).collect();
+        let orders = order_cbuffers(buffers.iter().map(|v| ("pixel.glsl", &v.inner))).unwrap();
+        assert_eq!(orders.iter().map(|(_, slot)| *slot).collect::<Vec<u32>>(), [1, 2, 3, 4, 5, 6, 7, 16, 17]);
+        let frame = cbuffer("Frame", Some(Attribute::Order(1)));
+        match order_cbuffers([("vertex.glsl", &viewport.inner), ("pixel.glsl", &frame.inner)]) {
+            Err(Error::DuplicateOrder { order, first, first_unit, second, second_unit }) => {
+                assert_eq!(order, 1);
+                assert_eq!((&*first, &*first_unit), ("Viewport", "vertex.glsl"));
+                assert_eq!((&*second, &*second_unit), ("Frame", "pixel.glsl"));
+            },
+            _ => panic!("expected a duplicate order error")
+        }
+        //Gaps are only an error when some constant buffers have no explicit order.
+        assert_eq!(order_cbuffers([("pixel.glsl", &lighting.inner)]).unwrap(), [("Lighting", 2)]);
+        match order_cbuffers([("pixel.glsl", &lighting.inner), ("vertex.glsl", &material.inner)]) {
+            Err(Error::OrderGap { name, unit, order, expected, implicit, implicit_unit }) => {
+                assert_eq!((&*name, &*unit, order, expected), ("Lighting", "pixel.glsl", 2, 1));
+                assert_eq!((&*implicit, &*implicit_unit), ("Material", "vertex.glsl"));
+            },
+            _ => panic!("expected an order gap error")
+        }
+        let map = stages(vec![material, lighting, viewport]);
+        relocate_bindings(&map, |_, _, existing| existing.unwrap()).unwrap();
+        let slots: Vec<u32> = map[&Stage::Vertex].statements.cbuffers.iter().map(|v| v.slot.get()).collect();
+        assert_eq!(slots, [3, 2, 1]);
+    }
+
+    #[test]
+    fn vformat_wrong_stage()
+    {
+        let shaders = vec![unit("vertex.glsl", Stage::Vertex, &["Vertex"]), unit("pixel.glsl", Stage::Pixel, &["Other"])];
+        match test_vertex_formats(&shaders, true) {
+            Err(Error::VertexFormatStage { name, unit, stage }) => {
+                assert_eq!(name, "Other");
+                assert_eq!(unit, "pixel.glsl");
+                assert_eq!(stage, Stage::Pixel);
+            },
+            _ => panic!("expected VertexFormatStage error")
+        }
+    }
+
+    #[test]
+    fn vformat_missing()
+    {
+        let shaders = vec![unit("vertex.glsl", Stage::Vertex, &[]), unit("pixel.glsl", Stage::Pixel, &[])];
+        assert!(matches!(test_vertex_formats(&shaders, true), Err(Error::MissingVertexFormat)));
+        assert!(test_vertex_formats(&shaders[1..], true).is_ok());
+    }
+
+    #[test]
+    fn vformat_duplicate()
+    {
+        let shaders = vec![unit("a.glsl", Stage::Vertex, &["A"]), unit("b.glsl", Stage::Vertex, &["B"])];
+        match test_vertex_formats(&shaders, true) {
+            Err(Error::DuplicateVertexFormat(list)) => assert_eq!(list, vec!["'A' in a.glsl", "'B' in b.glsl"]),
+            _ => panic!("expected DuplicateVertexFormat error")
+        }
+        assert!(matches!(test_vertex_formats(&shaders, false), Err(Error::DuplicateVertexFormat(_))));
+    }
+
+    #[test]
+    fn vformat_no_vertex_input()
+    {
+        let shaders = vec![unit("vertex.glsl", Stage::Vertex, &[]), unit("pixel.glsl", Stage::Pixel, &[])];
+        assert!(test_vertex_formats(&shaders, false).is_ok());
+        let shaders = vec![unit("vertex.glsl", Stage::Vertex, &["Vertex"])];
+        assert!(test_vertex_formats(&shaders, true).is_ok());
+        assert!(matches!(test_vertex_formats(&shaders, false), Err(Error::UnexpectedVertexFormat(_))));
+    }
+
+    fn prop(name: &str, semantic: Option<&str>) -> Property<usize>
+    {
+        Property {
+            pname: name.into(),
+            ptype: PropertyType::Scalar(BaseType::Float),
+            pattr: semantic.map(|v| Attribute::Semantic(v.into())),
+            deprecated: None,
+            pformat: None,
+            default: None,
+            span: Default::default()
+        }
+    }
+
+    fn vformat(props: Vec<Property<usize>>) -> Struct<usize>
+    {
+        Struct {
+            name: "Vertex".into(),
+            attr: None,
+            deprecated: None,
+            props,
+            span: Default::default()
+        }
+    }
+
+    #[test]
+    fn semantics()
+    {
+        let explicit = vformat(vec![prop("Pos", Some("POSITION")), prop("Normal", Some("NORMAL"))]);
+        assert_eq!(vformat_semantics(&explicit).unwrap(), ["POSITION", "NORMAL"]);
+        //Defaults follow declaration order and skip indices taken by explicit semantics.
+        let mixed = vformat(vec![prop("Pos", Some("POSITION")), prop("Uv", None), prop("Lightmap", Some("texcoord1")), prop("Color", None)]);
+        assert_eq!(vformat_semantics(&mixed).unwrap(), ["POSITION", "TEXCOORD0", "texcoord1", "TEXCOORD2"]);
+        let mut outputs = vec![Slot::new(prop("Color", None)), Slot::new(prop("Normal", Some("SV_Target3")))];
+        outputs[0].slot.set(1);
+        assert_eq!(output_semantics(&outputs).unwrap(), ["SV_Target1", "SV_Target3"]);
+    }
+
+    #[test]
+    fn output_targets()
+    {
+        let target = |name: &str, target: Option<u32>| Slot::with_unit(Property {
+            pattr: target.map(Attribute::Target),
+            deprecated: None,
+            ..prop(name, None)
+        }, "pixel.glsl");
+        //Implicit outputs fill the free render targets in declaration order.
+        let outputs = vec![target("Color", None), target("Albedo", Some(0)), target("Normal", Some(2)), target("Depth", None)];
+        relocate_outputs(&outputs).unwrap();
+        let targets: Vec<u32> = outputs.iter().map(|v| v.slot.get()).collect();
+        assert_eq!(targets, [1, 0, 2, 3]);
+        //Relocation runs once per pass over the bindings and must give the same result.
+        relocate_outputs(&outputs).unwrap();
+        assert_eq!(outputs.iter().map(|v| v.slot.get()).collect::<Vec<u32>>(), targets);
+        let outputs = vec![target("Albedo", Some(1)), target("Normal", Some(1))];
+        match relocate_outputs(&outputs) {
+            Err(Error::DuplicateTarget { name, target, first, .. }) => {
+                assert_eq!(name, "Normal");
+                assert_eq!(target, 1);
+                assert_eq!(first, "Albedo");
+            },
+            _ => panic!("expected a duplicate render target")
+        }
+    }
+
+    #[test]
+    fn locations()
+    {
+        let located = |name: &str, location: Option<u32>, ptype: PropertyType<usize>| Property {
+            pname: name.into(),
+            ptype,
+            pattr: location.map(Attribute::Location),
+            deprecated: None,
+            pformat: None,
+            default: None,
+            span: Default::default()
+        };
+        let implicit = vformat(vec![prop("Pos", None), prop("Normal", None)]);
+        assert_eq!(vformat_locations(&implicit), [0, 1]);
+        //Implicit members fill the lowest free locations; a mat4f needs four consecutive ones.
+        let model = PropertyType::Matrix(VectorType { item: BaseType::Float, size: 4 });
+        let mixed = vformat(vec![
+            located("Pos", Some(1), PropertyType::Scalar(BaseType::Float)),
+            located("Model", None, model),
+            located("Normal", Some(3), PropertyType::Scalar(BaseType::Float)),
+            located("Uv", None, PropertyType::Scalar(BaseType::Float))
+        ]);
+        assert_eq!(vformat_locations(&mixed), [1, 4, 3, 0]);
+        let mut statements = BasicAst::new();
+        statements.vformat = Some(mixed);
+        let mut stages = BTreeMap::new();
+        stages.insert(Stage::Vertex, ShaderStage {
+            statements,
+            strings: Vec::new(),
+            sources: Vec::new()
+        });
+        let warnings = Reporter::new();
+        test_vformat_locations(&stages, &warnings);
+        let records = warnings.records();
+        assert_eq!(records.len(), 1);
+        assert!(records[0].message.ends_with("Model = 4, Uv = 0"));
+    }
+
+    #[test]
+    fn duplicate_semantics()
+    {
+        //Semantics are case-insensitive and a missing index means 0.
+        let duplicate = vformat(vec![prop("Uv", Some("TEXCOORD")), prop("Uv2", Some("texcoord0"))]);
+        match vformat_semantics(&duplicate) {
+            Err(Error::DuplicateSemantic { semantic, first, second }) => {
+                assert_eq!(semantic, "texcoord0");
+                assert_eq!(first, "Uv");
+                assert_eq!(second, "Uv2");
+            },
+            _ => panic!("expected a duplicate semantic error")
+        }
+        let mut outputs = vec![Slot::new(prop("Color", None)), Slot::new(prop("Normal", Some("SV_TARGET0")))];
+        outputs[0].slot.set(0);
+        assert!(matches!(output_semantics(&outputs), Err(Error::DuplicateSemantic { .. })));
+        let mut statements = BasicAst::new();
+        statements.vformat = Some(duplicate);
+        let mut map = BTreeMap::new();
+        map.insert(Stage::Vertex, ShaderStage {
+            statements,
+            strings: Vec::new(),
+            sources: Vec::new()
+        });
+        assert!(matches!(test_symbols(&map), Err(Error::DuplicateSemantic { .. })));
+    }
+
+    #[test]
+    fn error_locations()
+    {
+        let sampler = |name: &str, line: usize| Slot::with_unit(Property {
+            pname: name.into(),
+            ptype: PropertyType::Sampler,
+            pattr: None,
+            deprecated: None,
+            pformat: None,
+            default: None,
+            span: Span::new(line, 21)
+        }, "pixel.glsl");
+        let stages = |names: &[&str]| {
+            let mut statements = BasicAst::new();
+            for (i, name) in names.iter().enumerate() {
+                statements.objects.push(sampler(name, i + 3));
+            }
+            let mut map = BTreeMap::new();
+            map.insert(Stage::Pixel, ShaderStage {
+                statements,
+                strings: Vec::new(),
+                sources: Vec::new()
+            });
+            map
+        };
+        //All samplers are left on slot 0 which may only be used once.
+        let mut used = HashSet::new();
+        let err = test_bindings(&stages(&["Base", "Shadow"]), |_, slot| used.insert(slot)).unwrap_err();
+        assert_eq!(err.to_string(), "pixel.glsl:4:21: 'Shadow' redefines binding 0");
+        let err = test_symbols(&stages(&["Base", "Shadow", "Base"])).unwrap_err();
+        assert_eq!(err.to_string(), "pixel.glsl:5:21: multiple definitions of symbol 'Base'");
+    }
+}
@@ -28,20 +28,24 @@
 
 use std::cell::Cell;
 use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::io::{BufRead, BufReader, Read};
 use std::ops::Deref;
+use std::path::{Path, PathBuf};
 use bp3d_threads::{ScopedThreadManager, ThreadPool};
-use bpx::shader::Stage;
-use log::{debug, info, trace, warn};
-use bp3d_sal::ast::tree::{ArrayItemType, Attribute, BlendfuncStatement, PipelineStatement, Property, PropertyType, Statement, Struct};
-use bp3d_sal::ast::Visitor;
-use bp3d_sal::utils::auto_lexer_parser;
-use crate::targets::basic::preprocessor::BasicPreprocessor;
+use crate::Stage;
+use log::{debug, error, info, trace};
+use bp3d_sal::ast::tree::{ArrayItemType, Attribute, BlendfuncStatement, ConstantInt, PipelineStatement, Property, PropertyType, ResourceStatement, SamplerStateStatement, Span, Statement, Struct};
+use bp3d_symbols::Resource;
+use bp3d_sal::ast::{Strictness, Visitor};
+use bp3d_sal::lexer::Lexer;
+use bp3d_sal::utils::{auto_parser_all, AutoError};
+use crate::targets::basic::preprocessor::{BasicPreprocessor, Section};
 use crate::targets::basic::shaderlib::ShaderLib;
-use crate::targets::basic::useresolver::BasicUseResolver;
+use crate::targets::basic::useresolver::{BasicUseResolver, ModuleCache};
 use bp3d_sal::preprocessor;
 use crate::config::{Config, Unit};
 use crate::targets::basic::ast::Ast;
+use crate::warnings::{Reporter, WarningKind};
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -49,10 +53,35 @@ pub enum VisitorError
 {
     #[error("only 1 vertex format is allowed per shader")]
     DuplicateVertexFormat,
+    #[error("only 1 root constants layout is allowed per shader")]
+    DuplicateRootConstants,
     #[error("only 1 pipeline definition is allowed per shader")]
     DuplicatePipeline,
     #[error("error while resolving use statement: {0}")]
-    Use(crate::targets::basic::useresolver::Error)
+    Use(crate::targets::basic::useresolver::Error),
+    #[error("error while loading resource: {0}")]
+    ShaderLib(crate::targets::basic::shaderlib::Error),
+    #[error("resource '{0}' not found")]
+    ResourceNotFound(String),
+    #[error("resource '{0}' is defined multiple times")]
+    DuplicateResource(String),
+    #[error("sampler state '{0}' is defined multiple times")]
+    DuplicateSamplerState(String),
+    #[error("varying '{0}' is defined multiple times with different types or interpolations")]
+    DuplicateVarying(String),
+    #[error("resource '{name}' is {size} bytes, exceeding the cap of {max}")]
+    ResourceTooLarge
+    {
+        name: String,
+        size: usize,
+        max: usize
+    },
+    #[error("use of deprecated symbol '{name}' is denied: {message}")]
+    Deprecated
+    {
+        name: String,
+        message: String
+    }
 }
 
 #[derive(Debug, Error)]
@@ -66,7 +95,18 @@ pub enum Error {
     #[error("io error: {0}")]
     Io(std::io::Error),
     #[error("preprocessor error: {0}")]
-    Preprocessor(crate::targets::basic::preprocessor::Error)
+    Preprocessor(crate::targets::basic::preprocessor::Error),
+    #[error("{unit}: declared stage '{declared}' does not match '#stage {found}' of the unit")]
+    StageMismatch {
+        unit: String,
+        declared: Stage,
+        found: Stage
+    },
+    #[error("{unit}: declared stage '{stage}' has no '#stage {stage}' section in the unit")]
+    MissingSection {
+        unit: String,
+        stage: Stage
+    }
 }
 
 pub type BasicAst = Ast<
@@ -75,7 +115,7 @@ pub type BasicAst = Ast<
 >;
 
 impl BasicAst {
-    fn insert_struct(&mut self, mut val: Struct<usize>, src: &mut BasicAst) -> Struct<usize> {
+    pub(crate) fn insert_struct(&mut self, mut val: Struct<usize>, src: &mut BasicAst) -> Struct<usize> {
         for p in &mut val.props {
             match p.ptype {
                 PropertyType::StructRef(v) => {
@@ -101,15 +141,52 @@ impl BasicAst {
         val
     }
 
-    pub fn extend(&mut self, mut other: BasicAst) {
-        if other.root_constants_layout.is_some() && self.root_constants_layout.is_some() {
-            unsafe { //Rust has just lost the concept of expressions...
-                warn!("Overwriting root constants layout with '{}'", other.root_constants_layout.as_ref().unwrap_unchecked().name);
+    //Removes the statement imported by a use statement; the imported statement always has the name of
+    //the member.
+    fn remove_use(&mut self, member: &str) {
+        if self.root_constants_layout.as_ref().map(|v| v.name == member).unwrap_or_default() {
+            self.root_constants_layout = None;
+            self.root_constants_explicit = false;
+        }
+        if self.vformat.as_ref().map(|v| v.name == member).unwrap_or_default() {
+            self.vformat = None;
+        }
+        if self.pipeline.as_ref().map(|v| v.name == member).unwrap_or_default() {
+            self.pipeline = None;
+        }
+        self.presets.retain(|v| v.name != member);
+        self.cbuffers.retain(|v| v.inner.name != member);
+        self.root_constants.retain(|v| v.inner.pname != member);
+        self.constant_ints.retain(|v| v.inner.name != member);
+        self.objects.retain(|v| v.inner.pname != member);
+        self.outputs.retain(|v| v.inner.pname != member);
+        self.varyings.retain(|v| v.pname != member);
+        self.blendfuncs.retain(|v| v.name != member);
+        self.samplerstates.retain(|v| v.name != member);
+        self.resources.retain(|v| v.name != member);
+    }
+
+    pub fn extend(&mut self, mut other: BasicAst, warnings: &Reporter) {
+        //Modules used by several includes or units of a stage are only expanded once.
+        for (module, member) in other.take_uses() {
+            if !self.mark_use(&module, &member) {
+                trace!(target: "translation", "Skipping repeated use of {}::{}", module, member);
+                other.remove_use(&member);
             }
         }
         if let Some(v) = other.root_constants_layout.take() {
-            let v = self.insert_struct(v, &mut other);
-            self.root_constants_layout = Some(v);
+            //A rootconst block always takes precedence over an ORDER_0 constant buffer.
+            if self.root_constants_explicit && !other.root_constants_explicit {
+                let name = &self.root_constants_layout.as_ref().unwrap().name;
+                warnings.report(WarningKind::Duplicate, format!("Ignoring ORDER_0 constant buffer '{}' in favor of root constants layout '{}'", v.name, name));
+            } else {
+                if self.root_constants_layout.is_some() {
+                    warnings.report(WarningKind::Duplicate, format!("Overwriting root constants layout with '{}'", v.name));
+                }
+                let v = self.insert_struct(v, &mut other);
+                self.root_constants_layout = Some(v);
+                self.root_constants_explicit |= other.root_constants_explicit;
+            }
         }
         let cbuffers = std::mem::replace(&mut other.cbuffers, Vec::new());
         for mut v in cbuffers {
@@ -118,7 +195,7 @@ impl BasicAst {
         }
         if other.vformat.is_some() && self.vformat.is_some() {
             unsafe { //Rust has just lost the concept of expressions...
-                warn!("Overwriting vertex format with '{}'", other.vformat.as_ref().unwrap_unchecked().name);
+                warnings.report(WarningKind::Duplicate, format!("Overwriting vertex format with '{}'", other.vformat.as_ref().unwrap_unchecked().name));
             }
         }
         if other.vformat.is_some() {
@@ -126,78 +203,151 @@ impl BasicAst {
         }
         if other.pipeline.is_some() && self.pipeline.is_some() {
             unsafe { //Rust has just lost the concept of expressions...
-                warn!("Overwriting pipeline description with '{}'", other.pipeline.as_ref().unwrap_unchecked().name);
+                warnings.report(WarningKind::Duplicate, format!("Overwriting pipeline description with '{}'", other.pipeline.as_ref().unwrap_unchecked().name));
             }
         }
         if other.pipeline.is_some() {
             self.pipeline = other.pipeline;
         }
+        self.presets.extend(other.presets);
         self.blendfuncs.extend(other.blendfuncs);
+        for v in other.samplerstates {
+            match self.samplerstates.iter_mut().find(|r| r.name == v.name) {
+                Some(existing) => {
+                    if *existing != v {
+                        warnings.report(WarningKind::Duplicate, format!("Overwriting sampler state '{}'", v.name));
+                    }
+                    *existing = v;
+                },
+                None => self.samplerstates.push(v)
+            }
+        }
         self.objects.extend(other.objects);
         self.root_constants.extend(other.root_constants);
+        self.constant_ints.extend(other.constant_ints);
         self.outputs.extend(other.outputs);
+        for v in other.varyings {
+            match self.varyings.iter_mut().find(|r| r.pname == v.pname) {
+                Some(existing) => {
+                    if *existing != v {
+                        warnings.report(WarningKind::Duplicate, format!("Overwriting varying '{}'", v.pname));
+                    }
+                    *existing = v;
+                },
+                None => self.varyings.push(v)
+            }
+        }
+        for v in other.resources {
+            match self.resources.iter_mut().find(|r| r.name == v.name) {
+                Some(existing) => {
+                    if existing.data != v.data {
+                        warnings.report(WarningKind::Duplicate, format!("Overwriting resource '{}'", v.name));
+                    }
+                    *existing = v;
+                },
+                None => self.resources.push(v)
+            }
+        }
     }
 }
 
 pub struct AstVisitor<'a> {
-    resolver: BasicUseResolver<'a>
+    resolver: BasicUseResolver<'a>,
+    libs: Vec<ShaderLib<'a>>,
+    max_resource_size: usize,
+    unit: String,
+    /// Module of the statement being visited when it was imported by a use statement.
+    module: Option<String>,
+    deny_deprecated: bool,
+    warnings: Reporter
+}
+
+impl<'a> AstVisitor<'a> {
+    /// Wraps a symbol into a slot recording the unit or module which declared it.
+    fn slot<T>(&self, t: T) -> Slot<T> {
+        Slot::with_unit(t, self.module.as_ref().unwrap_or(&self.unit))
+    }
 }
 
 impl<'a> Visitor<BasicAst> for AstVisitor<'a> {
     type Error = VisitorError;
 
     fn visit_constant(&mut self, ast: &mut BasicAst, val: Property<usize>) -> Result<(), Self::Error> {
-        trace!("Visit constant: {}", val.pname);
+        trace!(target: "translation", "Visit constant: {}", val.pname);
         match val.ptype {
-            PropertyType::Scalar(_) => ast.root_constants.push(Slot::new(val)),
-            PropertyType::Vector(_) => ast.root_constants.push(Slot::new(val)),
-            PropertyType::Matrix(_) => ast.root_constants.push(Slot::new(val)),
-            _ => ast.objects.push(Slot::new(val))
+            PropertyType::Scalar(_) => ast.root_constants.push(self.slot(val)),
+            PropertyType::Vector(_) => ast.root_constants.push(self.slot(val)),
+            PropertyType::Matrix(_) => ast.root_constants.push(self.slot(val)),
+            _ => ast.objects.push(self.slot(val))
         };
         Ok(())
     }
 
+    fn visit_constant_int(&mut self, ast: &mut BasicAst, val: ConstantInt) -> Result<(), Self::Error> {
+        trace!(target: "translation", "Visit integer constant: {}", val.name);
+        ast.constant_ints.push(self.slot(val));
+        Ok(())
+    }
+
     fn visit_output(&mut self, ast: &mut BasicAst, val: Property<usize>) -> Result<(), Self::Error> {
-        trace!("Visit output: {}", val.pname);
-        let slot = Slot::new(val);
-        if let Some(attr) = &slot.inner.pattr {
-            if let Attribute::Order(id) = attr {
-                slot.slot.set(*id);
-                slot.external.set(true);
-            }
+        trace!(target: "translation", "Visit output: {}", val.pname);
+        let slot = self.slot(val);
+        if let Some(id) = slot.inner.pattr.as_ref().and_then(|v| v.get_target().or_else(|| v.get_order())) {
+            slot.slot.set(id);
+            slot.external.set(true);
         }
         ast.outputs.push(slot);
         Ok(())
     }
 
+    fn visit_varying(&mut self, ast: &mut BasicAst, val: Property<usize>) -> Result<(), Self::Error> {
+        trace!(target: "translation", "Visit varying: {}", val.pname);
+        match ast.varyings.iter().find(|v| v.pname == val.pname) {
+            //The same declaration may be imported by several use statements.
+            Some(v) if *v == val => (),
+            Some(_) => return Err(VisitorError::DuplicateVarying(val.pname)),
+            None => ast.varyings.push(val)
+        }
+        Ok(())
+    }
+
     fn visit_constant_buffer(&mut self, ast: &mut BasicAst, val: Struct<usize>) -> Result<(), Self::Error> {
-        trace!("Visit constant buffer: {}", val.name);
+        trace!(target: "translation", "Visit constant buffer: {}", val.name);
         if let Some(attr) = &val.attr {
             match attr {
                 Attribute::Order(o) => {
                     if *o == 0 {
-                        trace!("Constant buffer '{}' is root", val.name);
+                        if ast.root_constants_explicit {
+                            let name = &ast.root_constants_layout.as_ref().unwrap().name;
+                            self.warnings.report_in(WarningKind::Duplicate, &self.unit, format!("Ignoring ORDER_0 constant buffer '{}' in favor of root constants layout '{}'", val.name, name));
+                            return Ok(());
+                        }
+                        trace!(target: "translation", "Constant buffer '{}' is root", val.name);
                         ast.root_constants_layout = Some(val);
                     } else {
-                        trace!("Constant buffer '{}' is at slot #{}", val.name, o);
-                        ast.cbuffers.push(Slot::new(val))
+                        trace!(target: "translation", "Constant buffer '{}' is at slot #{}", val.name, o);
+                        ast.cbuffers.push(self.slot(val))
                     }
                 }
                 Attribute::Pack => {
-                    trace!("Constant buffer '{}' is a packed struct", val.name);
+                    trace!(target: "translation", "Constant buffer '{}' is a packed struct", val.name);
                     ast.push_packed_struct(val.name.clone(), val);
                 }
+                Attribute::Shared(_) => {
+                    trace!(target: "translation", "Constant buffer '{}' is shared", val.name);
+                    ast.cbuffers.push(self.slot(val))
+                }
                 _ => ()
             }
         } else {
-            trace!("Constant buffer '{}' is unbounded", val.name);
-            ast.cbuffers.push(Slot::new(val))
+            trace!(target: "translation", "Constant buffer '{}' is unbounded", val.name);
+            ast.cbuffers.push(self.slot(val))
         }
         Ok(())
     }
 
     fn visit_vertex_format(&mut self, ast: &mut BasicAst, val: Struct<usize>) -> Result<(), Self::Error> {
-        trace!("Visit vertex format: {}", val.name);
+        trace!(target: "translation", "Visit vertex format: {}", val.name);
         if ast.vformat.is_some() {
             return Err(VisitorError::DuplicateVertexFormat);
         }
@@ -205,94 +355,308 @@ impl<'a> Visitor<BasicAst> for AstVisitor<'a> {
         Ok(())
     }
 
+    fn visit_root_constants(&mut self, ast: &mut BasicAst, val: Struct<usize>) -> Result<(), Self::Error> {
+        trace!(target: "translation", "Visit root constants layout: {}", val.name);
+        if ast.root_constants_explicit {
+            return Err(VisitorError::DuplicateRootConstants);
+        }
+        if let Some(existing) = &ast.root_constants_layout {
+            self.warnings.report_in(WarningKind::Duplicate, &self.unit, format!("Ignoring ORDER_0 constant buffer '{}' in favor of root constants layout '{}'", existing.name, val.name));
+        }
+        ast.root_constants_layout = Some(val);
+        ast.root_constants_explicit = true;
+        Ok(())
+    }
+
     fn visit_pipeline(&mut self, ast: &mut BasicAst, val: PipelineStatement) -> Result<(), Self::Error> {
-        trace!("Visit pipeline description: {}", val.name);
-        if ast.pipeline.is_some() {
-            return Err(VisitorError::DuplicatePipeline);
+        trace!(target: "translation", "Visit pipeline description: {}", val.name);
+        if let Some(existing) = &ast.pipeline {
+            //An imported pipeline is only a preset: the unit may still declare its own pipeline.
+            if !ast.presets.iter().any(|v| v.name == existing.name) {
+                return Err(VisitorError::DuplicatePipeline);
+            }
+            trace!(target: "translation", "Replacing imported pipeline '{}' with '{}'", existing.name, val.name);
         }
         ast.pipeline = Some(val);
         Ok(())
     }
 
     fn visit_blendfunc(&mut self, ast: &mut BasicAst, val: BlendfuncStatement) -> Result<(), Self::Error> {
-        trace!("Visit blend function description: {}", val.name);
+        trace!(target: "translation", "Visit blend function description: {}", val.name);
         ast.blendfuncs.push(val);
         Ok(())
     }
 
+    fn visit_samplerstate(&mut self, ast: &mut BasicAst, val: SamplerStateStatement) -> Result<(), Self::Error> {
+        trace!(target: "translation", "Visit sampler state: {}", val.name);
+        if ast.samplerstates.iter().any(|v| v.name == val.name) {
+            return Err(VisitorError::DuplicateSamplerState(val.name));
+        }
+        ast.samplerstates.push(val);
+        Ok(())
+    }
+
+    fn visit_resource(&mut self, ast: &mut BasicAst, val: ResourceStatement) -> Result<(), Self::Error> {
+        trace!(target: "translation", "Visit resource: {} ({})", val.name, val.path);
+        if ast.resources.iter().any(|v| v.name == val.name) {
+            return Err(VisitorError::DuplicateResource(val.name));
+        }
+        //Resources are resolved like includes: through the shader libs.
+        let mut data = None;
+        for lib in &mut self.libs {
+            if let Some(v) = lib.try_load(&val.path).map_err(VisitorError::ShaderLib)? {
+                data = Some(v);
+                break;
+            }
+        }
+        let data = data.ok_or(VisitorError::ResourceNotFound(val.path))?;
+        if data.len() > self.max_resource_size {
+            return Err(VisitorError::ResourceTooLarge {
+                name: val.name,
+                size: data.len(),
+                max: self.max_resource_size
+            });
+        }
+        debug!(target: "translation", "Embedding resource '{}' ({} bytes)", val.name, data.len());
+        ast.resources.push(Resource {
+            name: val.name,
+            data
+        });
+        Ok(())
+    }
+
     fn visit_noop(&mut self, _: &mut BasicAst) -> Result<(), Self::Error> {
-        trace!("Visit noop");
+        trace!(target: "translation", "Visit noop");
         //Do nothing.
         Ok(())
     }
 
-    fn visit_use(&mut self, ast: &mut BasicAst, module: String, member: String) -> Result<(), Self::Error> {
-        trace!("Visit use: {}::{}", module, member);
-        let (stmt, mut ast1) = self.resolver.resolve(module, member)
+    fn visit_use(&mut self, ast: &mut BasicAst, path: Vec<String>, member: String, span: Span) -> Result<(), Self::Error> {
+        let module = path.join("::");
+        trace!(target: "translation", "Visit use: {}::{}", module, member);
+        if !ast.mark_use(&module, &member) {
+            trace!(target: "translation", "Skipping repeated use of {}::{}", module, member);
+            return Ok(());
+        }
+        let name = format!("{}::{}", module, member);
+        let (stmt, mut ast1) = self.resolver.resolve(path, member)
             .map_err(VisitorError::Use)?;
-        match stmt {
+        if let Some(message) = stmt.get_deprecation() {
+            if self.deny_deprecated {
+                return Err(VisitorError::Deprecated {
+                    name,
+                    message: message.into()
+                });
+            }
+            let location = format!("{}:{}", self.unit, span);
+            if message.is_empty() {
+                self.warnings.report_in(WarningKind::Deprecated, &location, format!("use of deprecated symbol '{}'", name));
+            } else {
+                self.warnings.report_in(WarningKind::Deprecated, &location, format!("use of deprecated symbol '{}': {}", name, message));
+            }
+        }
+        //Spans of imported statements point into the module, not into the unit.
+        self.module = Some(module);
+        let res = match stmt {
             Statement::Constant(v) => self.visit_constant(ast, v),
+            Statement::ConstantInt(v) => self.visit_constant_int(ast, v),
             Statement::ConstantBuffer(v) => {
                 let v = ast.insert_struct(v, &mut ast1);
                 self.visit_constant_buffer(ast, v)
             },
             Statement::Output(v) => self.visit_output(ast, v),
+            Statement::Varying(v) => self.visit_varying(ast, v),
             Statement::VertexFormat(v) => self.visit_vertex_format(ast, v),
-            Statement::Pipeline(v) => self.visit_pipeline(ast, v),
+            Statement::RootConstants(v) => self.visit_root_constants(ast, v),
+            Statement::Pipeline(v) => {
+                if ast.pipeline.is_some() {
+                    Err(VisitorError::DuplicatePipeline)
+                } else {
+                    ast.presets.push(v.clone());
+                    self.visit_pipeline(ast, v)
+                }
+            },
             Statement::Blendfunc(v) => self.visit_blendfunc(ast, v),
+            Statement::SamplerState(v) => self.visit_samplerstate(ast, v),
+            Statement::Resource(v) => self.visit_resource(ast, v),
             Statement::Noop => self.visit_noop(ast)
-        }
+        };
+        self.module = None;
+        res
     }
 }
 
+#[derive(Clone)]
 pub struct ShaderToSal
 {
     pub name: String,
     pub strings: Vec<rglslang::shader::Part>,
     pub statements: BasicAst,
-    pub stage: Stage
+    pub stage: Stage,
+    /// Names of all vertex formats declared by this unit and its includes.
+    pub vformats: Vec<String>,
+    /// Names of all rootconst blocks declared by this unit and its includes.
+    pub rootconsts: Vec<String>,
+    /// Files read to build this unit: the unit itself and the shader libs providing its modules.
+    pub dependencies: Vec<PathBuf>,
+    /// Text of the unit as written, empty for includes.
+    pub source: String
 }
 
-fn shader_sal_stage<T: BufRead>(name: String, content: T, config: &Config) -> Result<ShaderToSal, Error>
+/// Decomposes a unit or one of its includes into its SAL statements and the lines of its GLSL
+/// code; the code of the unit itself is not added to the strings of the result.
+fn decompose<T: BufRead>(name: String, content: T, config: &Config, modules: &ModuleCache) -> Result<(ShaderToSal, Vec<Section>, Vec<String>), Error>
 {
     let mut result = ShaderToSal {
         strings: Vec::new(),
         statements: BasicAst::new(),
         name: name.clone(),
-        stage: Stage::Vertex
+        stage: Stage::Vertex,
+        vformats: Vec::new(),
+        rootconsts: Vec::new(),
+        dependencies: Vec::new(),
+        source: String::new()
     };
     let mut preprocessor = BasicPreprocessor::new(&config.libs);
     preprocessor::run(content, &mut preprocessor).map_err(Error::Preprocessor)?;
-    result.stage = preprocessor.stage.unwrap_or_else(|| {
-        warn!("No shader stage specified in shader file, assuming this is a vertex shader by default");
-        Stage::Vertex
-    });
+    preprocessor.check_sections().map_err(Error::Preprocessor)?;
+    result.dependencies.extend(preprocessor.opened.iter().map(|v| v.to_path_buf()));
     for (name, header) in preprocessor.includes {
-        let data = shader_sal_stage(name,header.deref(), config)?;
+        //Sections of includes are ignored: their code goes to the stages of the including unit.
+        let (data, _, code) = decompose(name.clone(), header.deref(), config, modules)?;
         result.strings.extend(data.strings);
-        result.statements.extend(data.statements);
+        result.strings.push(rglslang::shader::Part::new_with_name(code.join("\n"), name));
+        result.statements.extend(data.statements, &config.warnings);
+        result.vformats.extend(data.vformats);
+        result.rootconsts.extend(data.rootconsts);
+        result.dependencies.extend(data.dependencies);
+    }
+    let mut visitor = AstVisitor {
+        resolver: BasicUseResolver::new(&config.libs, &config.defines, modules, config.sources.clone()),
+        libs: config.libs.iter().map(|v| ShaderLib::new(*v)).collect(),
+        max_resource_size: config.max_resource_size,
+        unit: name.clone(),
+        module: None,
+        deny_deprecated: config.deny_deprecated,
+        warnings: config.warnings.clone()
+    };
+    let strictness = match config.strict_sal {
+        true => Strictness::Strict,
+        false => Strictness::Default
+    };
+    let mut lexer = Lexer::with_defines(&config.defines);
+    let ast = lexer.process(&preprocessor.sal_code).map_err(AutoError::Lexer)
+        .and_then(|_| auto_parser_all(lexer, BasicAst::new(), &mut visitor, strictness))
+        .map_err(|e| {
+            //Syntax errors are printed individually, the returned error only counts them.
+            if let AutoError::Syntax(errors) = &e {
+                for v in errors {
+                    error!(target: "translation", "{}:{}:{}: {}", name, v.line, v.col, v.etype);
+                }
+            }
+            Error::Sal(e)
+        })?;
+    result.dependencies.extend(visitor.resolver.opened().iter().map(|v| v.to_path_buf()));
+    if let Some(vformat) = &ast.vformat {
+        result.vformats.push(vformat.name.clone());
+    }
+    if let (Some(layout), true) = (&ast.root_constants_layout, ast.root_constants_explicit) {
+        result.rootconsts.push(layout.name.clone());
+    }
+    result.statements.extend(ast, &config.warnings);
+    Ok((result, preprocessor.sections, preprocessor.src_code))
+}
+
+/// Returns the code of a section: lines of other sections are blanked so that line numbers still
+/// match the unit, and the lines before the first section are shared by all sections.
+fn section_code(code: &[String], sections: &[Section], index: usize) -> String
+{
+    let start = sections[index].line;
+    let end = sections.get(index + 1).map(|v| v.line).unwrap_or(code.len());
+    code.iter().enumerate()
+        .map(|(i, line)| match i < sections[0].line || (start..end).contains(&i) {
+            true => &**line,
+            false => ""
+        })
+        .collect::<Vec<&str>>()
+        .join("\n")
+}
+
+/// Loads a unit; `stage` is the stage declared for the unit, which must agree with its `#stage`
+/// directive if it has one.
+///
+/// A unit with several `#stage` sections produces one entry per section, all sharing the SAL
+/// statements of the unit; a declared stage then selects the matching section.
+fn shader_sal_stage<T: BufRead>(name: String, mut content: T, stage: Option<Stage>, config: &Config, modules: &ModuleCache) -> Result<Vec<ShaderToSal>, Error>
+{
+    //The text is kept for the debug information of the shader pack.
+    let mut source = String::new();
+    content.read_to_string(&mut source).map_err(Error::Io)?;
+    let (mut result, sections, code) = decompose(name.clone(), source.as_bytes(), config, modules)?;
+    result.source = source;
+    if sections.len() > 1 {
+        let selected: Vec<usize> = (0..sections.len())
+            .filter(|i| stage.map(|v| v == sections[*i].stage).unwrap_or(true))
+            .collect();
+        if selected.is_empty() {
+            return Err(Error::MissingSection {
+                unit: name,
+                stage: stage.unwrap()
+            });
+        }
+        //The vertex format only goes to the vertex section, if the unit has one.
+        let has_vertex = selected.iter().any(|i| sections[*i].stage == Stage::Vertex);
+        return Ok(selected.into_iter().map(|i| {
+            let mut unit = result.clone();
+            unit.stage = sections[i].stage;
+            if has_vertex && unit.stage != Stage::Vertex {
+                unit.vformats.clear();
+                unit.statements.vformat = None;
+            }
+            unit.strings.push(rglslang::shader::Part::new_with_name(section_code(&code, &sections, i), name.clone()));
+            unit
+        }).collect());
     }
-    let ast = auto_lexer_parser(&preprocessor.sal_code, BasicAst::new(), AstVisitor { resolver: BasicUseResolver::new(&config.libs) })
-        .map_err(Error::Sal)?;
-    result.statements.extend(ast);
-    result.strings.push(rglslang::shader::Part::new_with_name(preprocessor.src_code.join("\n"), name));
-    Ok(result)
+    result.stage = match (stage, sections.first().map(|v| v.stage)) {
+        (Some(declared), Some(found)) if declared != found => return Err(Error::StageMismatch {
+            unit: name,
+            declared,
+            found
+        }),
+        (Some(stage), _) | (None, Some(stage)) => stage,
+        (None, None) => {
+            config.warnings.report(WarningKind::DefaultStage, format!("{}: no shader stage specified in shader file, assuming this is a vertex shader by default", name));
+            Stage::Vertex
+        }
+    };
+    result.strings.push(rglslang::shader::Part::new_with_name(code.join("\n"), name));
+    Ok(vec![result])
 }
 
-pub fn load_shader_to_sal(unit: &Unit, config: &Config) -> Result<ShaderToSal, Error>
+fn load_path(path: &Path, stage: Option<Stage>, config: &Config, modules: &ModuleCache) -> Result<Vec<ShaderToSal>, Error>
+{
+    info!(target: "translation", "Loading shader {:?}...", path);
+    let reader = BufReader::new(File::open(path).map_err(Error::Io)?);
+    let mut res = shader_sal_stage(path.to_string_lossy().into_owned(),reader, stage, config, modules)?;
+    res.iter_mut().for_each(|v| v.dependencies.push(path.to_path_buf()));
+    Ok(res)
+}
+
+/// Loads a unit, returning one entry per stage section of the unit. Modules used by the unit are
+/// parsed once for all the units sharing `modules`.
+pub fn load_shader_to_sal(unit: &Unit, config: &Config, modules: &ModuleCache) -> Result<Vec<ShaderToSal>, Error>
 {
     let mut libs: Vec<ShaderLib> = config.libs.iter().map(|v| ShaderLib::new(*v)).collect();
     match unit {
-        Unit::Path(path) => {
-            info!("Loading shader {:?}...", path);
-            let reader = BufReader::new(File::open(path).map_err(Error::Io)?);
-            shader_sal_stage(path.to_string_lossy().into_owned(),reader, config)
-        },
+        Unit::Path(path) => load_path(path, None, config, modules),
+        Unit::PathWithStage(stage, path) => load_path(path, Some(*stage), config, modules),
         Unit::Injected(vname) => {
-            info!("Loading injected shader {}...", vname);
+            info!(target: "translation", "Loading injected shader {}...", vname);
             for v in &mut libs {
-                if let Some(data) = v.try_load(vname).map_err(Error::ShaderLib)? {
-                    return shader_sal_stage(String::from(*vname), data.as_slice(), config);
+                if let Some((name, data)) = v.try_load_many([*vname]).map_err(Error::ShaderLib)?.pop() {
+                    let mut res = shader_sal_stage(name, data.as_slice(), None, config, modules)?;
+                    res.iter_mut().for_each(|res| res.dependencies.push(v.path().to_path_buf()));
+                    return Ok(res);
                 }
             }
             Err(Error::InjectionNotFound)
@@ -300,28 +664,48 @@ pub fn load_shader_to_sal(unit: &Unit, config: &Config) -> Result<ShaderToSal, E
     }
 }
 
+/// Loads a unit from a string instead of a file or a shader library.
+pub fn load_source_to_sal(name: &str, source: &str, config: &Config) -> Result<Vec<ShaderToSal>, Error>
+{
+    shader_sal_stage(name.into(), source.as_bytes(), None, config, &ModuleCache::new())
+}
+
 pub fn load_pass(config: &Config) -> Result<Vec<ShaderToSal>, Error>
 {
+    let modules = ModuleCache::new();
     crossbeam::scope(|scope| {
         let manager = ScopedThreadManager::new(scope);
-        let mut pool: ThreadPool<ScopedThreadManager, Result<ShaderToSal, Error>> = ThreadPool::new(config.n_threads);
-        info!("Initialized thread pool with {} max thread(s)", config.n_threads);
+        let mut pool: ThreadPool<ScopedThreadManager, Result<Vec<ShaderToSal>, Error>> = ThreadPool::new(config.n_threads);
+        info!(target: "translation", "Initialized thread pool with {} max thread(s)", config.n_threads);
         for unit in &config.units {
             pool.send(&manager, |_| {
-                debug!("Loading SAL AST for shader unit {:?}...", *unit);
-                load_shader_to_sal(unit, &config)
+                if let Some(v) = config.unit_cache.as_ref().and_then(|v| v.get(unit)) {
+                    debug!(target: "translation", "Reusing cached SAL AST for shader unit {:?}", *unit);
+                    return Ok(v);
+                }
+                debug!(target: "translation", "Loading SAL AST for shader unit {:?}...", *unit);
+                let res = load_shader_to_sal(unit, &config, &modules)?;
+                if let Some(cache) = &config.unit_cache {
+                    cache.insert(unit, &res);
+                }
+                Ok(res)
             });
-            debug!("Dispatch shader unit {:?}", unit);
+            debug!(target: "translation", "Dispatch shader unit {:?}", unit);
         }
-        pool.reduce().map(|v| v.unwrap()).collect()
+        //Sections of a unit are merged like independent units.
+        let units: Result<Vec<Vec<ShaderToSal>>, Error> = pool.reduce().map(|v| v.unwrap()).collect();
+        units.map(|v| v.into_iter().flatten().collect())
     }).unwrap()
 }
 
+#[derive(Clone)]
 pub struct Slot<T>
 {
     pub inner: T,
     pub slot: Cell<u32>,
-    pub external: Cell<bool>
+    pub external: Cell<bool>,
+    /// Unit or module which declared the symbol, None for symbols which do not come from SAL code.
+    pub unit: Option<String>
 }
 
 impl<T> Slot<T>
@@ -331,7 +715,682 @@ impl<T> Slot<T>
         Self {
             inner: t,
             slot: Cell::new(0),
-            external: Cell::new(false)
+            external: Cell::new(false),
+            unit: None
+        }
+    }
+
+    pub fn with_unit(t: T, unit: &str) -> Self
+    {
+        Self {
+            unit: Some(unit.into()),
+            ..Self::new(t)
+        }
+    }
+
+    /// Formats the location of the given span of the symbol as `unit:line:col`.
+    pub fn location(&self, span: Span) -> String
+    {
+        match &self.unit {
+            Some(unit) => format!("{}:{}", unit, span),
+            None => span.to_string()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::fs::File;
+    use std::io::BufWriter;
+    use std::path::Path;
+    use bp3d_sal::ast::error::{Error as AstError, ValueError};
+    use bp3d_sal::ast::StrictnessRule;
+    use bp3d_sal::ast::tree::{AddressMode, CullingMode, Filter, PropertyType};
+    use bp3d_sal::utils::AutoError;
+    use bpx::package;
+    use bpx::package::Package;
+    use crate::cache::UnitCache;
+    use crate::config::{Config, Unit};
+    use crate::depfile::Depfile;
+    use crate::Stage;
+    use crate::targets::basic::shader_to_sal::{Error, load_pass, load_shader_to_sal, ShaderToSal, VisitorError};
+    use crate::targets::basic::ast::VaryingInterface;
+    use crate::targets::basic::useresolver::{Error as UseError, ModuleCache};
+    use crate::targets::basic::preprocessor::Error as PreprocessorError;
+    use crate::targets::layout::{compile_struct, LayoutRule};
+    use crate::targets::basic::sal_compiler::{Error as SalError, get_root_constants_layout, link_varyings, merge_stages, test_interfaces, test_root_constants, test_symbols, test_varyings};
+
+    fn write_lib(path: &Path, files: &[(&str, &[u8])])
+    {
+        let mut lib = Package::create(BufWriter::new(File::create(path).unwrap()), package::Builder::new()
+            .type_code(*b"SL")).unwrap();
+        {
+            let mut objects = lib.objects_mut().unwrap();
+            for (name, data) in files {
+                objects.create(*name, *data).unwrap();
+            }
+        }
+        lib.save().unwrap();
+    }
+
+    fn load_unit(unit: &Unit, config: &Config) -> Result<ShaderToSal, Error>
+    {
+        load_shader_to_sal(unit, config, &ModuleCache::new()).map(|mut v| {
+            assert_eq!(v.len(), 1);
+            v.remove(0)
+        })
+    }
+
+    fn config<'a>(lib: &'a Path, output: &'a Path) -> Config<'a>
+    {
+        Config {
+            libs: vec![lib],
+            max_resource_size: 1024,
+            ..Config::new(Vec::new(), output)
+        }
+    }
+
+    fn load(dir: &Path, config: &Config, sal: &str) -> Result<Vec<bp3d_symbols::Resource>, Error>
+    {
+        let path = dir.join("shader.glsl");
+        std::fs::write(&path, format!("#stage vertex\n\n#sal\n{}\n#sal\n\nvoid main() {{}}\n", sal)).unwrap();
+        load_unit(&Unit::Path(&path), config).map(|v| v.statements.resources)
+    }
+
+    fn visitor_error(res: Result<Vec<bp3d_symbols::Resource>, Error>) -> VisitorError
+    {
+        match res {
+            Err(Error::Sal(AutoError::Ast(AstError::Visitor(e)))) => e,
+            _ => panic!("expected a visitor error")
+        }
+    }
+
+    #[test]
+    fn embed_resources()
+    {
+        let dir = std::env::temp_dir().join(format!("shaderc-resource-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let lut: Vec<u8> = (0..=255).collect();
+        let lib = dir.join("lib.bpx");
+        write_lib(&lib, &[("curves.bin", lut.as_slice()), ("big.bin", &[0; 2048][..])]);
+        let output = dir.join("out.bpx");
+        let config = config(&lib, &output);
+        let resources = load(&dir, &config, "resource \"curves.bin\" as CurveLut;").unwrap();
+        assert_eq!(resources.len(), 1);
+        assert_eq!(resources[0].name, "CurveLut");
+        assert_eq!(resources[0].data, lut);
+        match visitor_error(load(&dir, &config, "resource \"big.bin\" as Big;")) {
+            VisitorError::ResourceTooLarge { name, size, max } => {
+                assert_eq!(name, "Big");
+                assert_eq!(size, 2048);
+                assert_eq!(max, 1024);
+            },
+            e => panic!("unexpected error: {}", e)
+        }
+        assert!(matches!(visitor_error(load(&dir, &config, "resource \"missing.bin\" as Missing;")), VisitorError::ResourceNotFound(_)));
+        assert!(matches!(visitor_error(load(&dir, &config, "resource \"curves.bin\" as A;\nresource \"curves.bin\" as A;")), VisitorError::DuplicateResource(_)));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn varyings()
+    {
+        let dir = std::env::temp_dir().join(format!("shaderc-varyings-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let lib = dir.join("lib.bpx");
+        write_lib(&lib, &[]);
+        let output = dir.join("out.bpx");
+        let config = config(&lib, &output);
+        let unit = |stage: &str, code: &str| {
+            let path = dir.join(format!("{}.glsl", stage));
+            std::fs::write(&path, format!("#stage {}\n{}\n", stage, code)).unwrap();
+            load_unit(&Unit::Path(&path), &config).unwrap()
+        };
+        let vertex = unit("vertex", "#sal\nvarying vec2f Uv;\nvarying vec3f Normal : Flat;\nvarying float Unused;\n#sal\nvoid main() { Out.Uv = vec2(0); Out.Normal = vec3(0); }");
+        //Passthrough geometry stage: reads every input vertex and writes it again.
+        let geometry = unit("geometry", "void main() { for (int i = 0; i < 3; ++i) { Out.Uv = In[i].Uv; Out.Normal = In [i] . Normal; EmitVertex(); } }");
+        let pixel = unit("pixel", "#sal\nvarying vec2f Uv;\n#sal\nvoid main() { vec2 uv = In.Uv; }");
+        let mut stages = merge_stages(vec![vertex, geometry, pixel], &config.warnings);
+        link_varyings(&mut stages).unwrap();
+        for stage in stages.values() {
+            let names: Vec<&str> = stage.statements.varyings.iter().map(|v| &*v.pname).collect();
+            assert_eq!(names, ["Uv", "Normal", "Unused"]);
+        }
+        assert_eq!(stages[&Stage::Vertex].statements.varying_interface, VaryingInterface { input: None, output: Some(false) });
+        assert_eq!(stages[&Stage::Geometry].statements.varying_interface, VaryingInterface { input: Some(true), output: Some(false) });
+        assert_eq!(stages[&Stage::Pixel].statements.varying_interface, VaryingInterface { input: Some(false), output: None });
+        test_interfaces(&stages).unwrap();
+        test_varyings(&stages, &config.warnings);
+        let mut messages: Vec<String> = config.warnings.records().into_iter().map(|v| v.message).collect();
+        messages.sort();
+        assert_eq!(messages, [
+            "varying 'Normal' is written by the geometry stage but never read by the pixel stage",
+            "varying 'Unused' is never used"
+        ]);
+        //Mesh shaders feed the pixel stage directly.
+        let mesh = unit("mesh", "#sal\nvarying vec2f Uv;\n#sal\nvoid main() {}");
+        let mut stages = merge_stages(vec![mesh, unit("pixel", "void main() {}")], &config.warnings);
+        link_varyings(&mut stages).unwrap();
+        assert_eq!(stages[&Stage::Mesh].statements.varying_interface, VaryingInterface { input: None, output: Some(true) });
+        assert_eq!(stages[&Stage::Pixel].statements.varying_interface, VaryingInterface { input: Some(false), output: None });
+        let conflict = unit("pixel", "#sal\nvarying vec3f Uv;\n#sal\nvoid main() {}");
+        let mut stages = merge_stages(vec![unit("vertex", "#sal\nvarying vec2f Uv;\n#sal\nvoid main() {}"), conflict], &config.warnings);
+        assert!(matches!(link_varyings(&mut stages), Err(SalError::RedefinedVarying(name)) if name == "Uv"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn interfaces()
+    {
+        let dir = std::env::temp_dir().join(format!("shaderc-interfaces-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let lib = dir.join("lib.bpx");
+        write_lib(&lib, &[]);
+        let output = dir.join("out.bpx");
+        let config = config(&lib, &output);
+        let stages = |vertex: &str, pixel: &str| {
+            let unit = |stage: &str, code: &str| {
+                let path = dir.join(format!("{}.glsl", stage));
+                std::fs::write(&path, format!("#stage {}\n#sal\nvarying vec2f Uv;\n#sal\n{}\n", stage, code)).unwrap();
+                load_unit(&Unit::Path(&path), &config).unwrap()
+            };
+            let mut stages = merge_stages(vec![unit("vertex", vertex), unit("pixel", pixel)], &config.warnings);
+            link_varyings(&mut stages).unwrap();
+            stages
+        };
+        let vertex = "layout (location = 0) out vec3 Color;\nvoid main() { Out.Uv = vec2(0); Color = vec3(1); }";
+        test_interfaces(&stages(vertex, "layout (location = 0) in vec3 Color;\nvoid main() { vec2 uv = In.Uv; }")).unwrap();
+        let err = test_interfaces(&stages("void main() {}", "void main() { vec2 uv = In.Uv; }")).unwrap_err();
+        assert!(matches!(&err, SalError::MissingVarying { name, .. } if name == "Uv"));
+        assert_eq!(err.to_string(), "'Uv' is read by the pixel stage but never written by the vertex stage (use --no-interface-check to allow it)");
+        let err = test_interfaces(&stages(vertex, "in vec3 Normal;\nvoid main() {}")).unwrap_err();
+        assert!(matches!(err, SalError::MissingVarying { name, producer: Stage::Vertex, consumer: Stage::Pixel } if name == "Normal"));
+        match test_interfaces(&stages(vertex, "flat in vec4 Color;\nvoid main() {}")) {
+            Err(SalError::VaryingTypeMismatch { name, produced, consumed, .. }) => {
+                assert_eq!(name, "Color");
+                assert_eq!(produced, "vec3");
+                assert_eq!(consumed, "vec4");
+            },
+            _ => panic!("expected a type mismatch")
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn root_constants()
+    {
+        let dir = std::env::temp_dir().join(format!("shaderc-rootconst-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let lib = dir.join("lib.bpx");
+        write_lib(&lib, &[]);
+        let output = dir.join("out.bpx");
+        let config = config(&lib, &output);
+        let unit = |stage: &str, sal: &str| {
+            let path = dir.join(format!("{}.glsl", stage));
+            std::fs::write(&path, format!("#stage {}\n#sal\n{}\n#sal\nvoid main() {{}}\n", stage, sal)).unwrap();
+            load_unit(&Unit::Path(&path), &config)
+        };
+        let vertex = unit("vertex", "const struct Legacy : ORDER_0 { float Time; }\nrootconst struct Root { mat4f ModelView; }").unwrap();
+        assert_eq!(vertex.rootconsts, ["Root"]);
+        assert_eq!(vertex.statements.root_constants_layout.as_ref().unwrap().name, "Root");
+        let pixel = unit("pixel", "const struct Legacy : ORDER_0 { float Time; }").unwrap();
+        assert!(test_root_constants(&[pixel, vertex], &config.warnings).is_ok());
+        let warnings = config.warnings.records();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].message, "Ignoring ORDER_0 constant buffer 'Legacy' in favor of root constants layout 'Root'");
+        assert_eq!(warnings[0].contexts.len(), 2);
+        let pixel = unit("pixel", "const struct Legacy : ORDER_0 { float Time; }").unwrap();
+        let vertex = unit("vertex", "rootconst struct Root { mat4f ModelView; }").unwrap();
+        let mut stages = merge_stages(vec![vertex, pixel], &config.warnings);
+        assert_eq!(get_root_constants_layout(&mut stages).unwrap().name, "Root");
+        let vertex = unit("vertex", "rootconst struct Root { mat4f ModelView; }").unwrap();
+        let pixel = unit("pixel", "rootconst struct Other { float Time; }").unwrap();
+        match test_root_constants(&[vertex, pixel], &config.warnings) {
+            Err(SalError::DuplicateRootConstants(list)) => assert_eq!(list.len(), 2),
+            _ => panic!("expected DuplicateRootConstants error")
+        }
+        let res = unit("vertex", "rootconst struct A { float X; }\nrootconst struct B { float Y; }");
+        assert!(matches!(res, Err(Error::Sal(AutoError::Ast(AstError::Visitor(VisitorError::DuplicateRootConstants))))));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn diamond_use()
+    {
+        let dir = std::env::temp_dir().join(format!("shaderc-diamond-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let lib = dir.join("lib.bpx");
+        write_lib(&lib, &[
+            ("D", b"const struct Camera { mat4f View; }\nconst Texture2D:vec4f Noise;"),
+            ("B", b"#stage vertex\n#sal\nuse D::Camera;\nuse D::Noise;\n#sal\n"),
+            ("C", b"#stage vertex\n#sal\nuse D::Camera;\nuse D::Camera;\n#sal\n")
+        ]);
+        let output = dir.join("out.bpx");
+        let config = config(&lib, &output);
+        let unit = |name: &str, code: &str| {
+            let path = dir.join(name);
+            std::fs::write(&path, format!("#stage vertex\n{}\nvoid main() {{}}\n", code)).unwrap();
+            load_unit(&Unit::Path(&path), &config).unwrap()
+        };
+        let a = unit("a.glsl", "#include B\n#include C");
+        assert_eq!(a.statements.cbuffers.len(), 1);
+        assert_eq!(a.statements.objects.len(), 1);
+        let e = unit("e.glsl", "#sal\nuse D::Noise;\nuse D::Camera;\n#sal");
+        let stages = merge_stages(vec![a, e], &config.warnings);
+        test_symbols(&stages).unwrap();
+        let cbuffers: Vec<&str> = stages[&Stage::Vertex].statements.cbuffers.iter().map(|v| &*v.inner.name).collect();
+        assert_eq!(cbuffers, ["Camera"]);
+        assert_eq!(stages[&Stage::Vertex].statements.objects.len(), 1);
+        //A different declaration with the same name is still a conflict.
+        let b = unit("b.glsl", "#include B");
+        let f = unit("f.glsl", "#sal\nconst struct Camera { float Fov; }\n#sal");
+        let stages = merge_stages(vec![b, f], &config.warnings);
+        match test_symbols(&stages) {
+            Err(SalError::RedefinedSymbol { name, location }) => {
+                assert_eq!(name, "Camera");
+                assert_eq!(location, format!("{}:3:20", dir.join("f.glsl").to_string_lossy()));
+            },
+            _ => panic!("expected a redefined symbol error")
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn constant_ints()
+    {
+        let dir = std::env::temp_dir().join(format!("shaderc-constant-ints-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let lib = dir.join("lib.bpx");
+        write_lib(&lib, &[]);
+        let output = dir.join("out.bpx");
+        let config = config(&lib, &output);
+        let unit = |name: &str, sal: &str| {
+            let path = dir.join(name);
+            std::fs::write(&path, format!("#stage pixel\n#sal\n{}\n#sal\nvoid main() {{}}\n", sal)).unwrap();
+            load_unit(&Unit::Path(&path), &config).unwrap()
+        };
+        let a = unit("a.glsl", "const uint MAX_LIGHTS = 4;\nconst struct Lights { vec4f[MAX_LIGHTS] Colors; }");
+        assert_eq!(a.statements.constant_ints.len(), 1);
+        assert_eq!(a.statements.constant_ints[0].inner.value, 4);
+        match &a.statements.cbuffers[0].inner.props[0].ptype {
+            PropertyType::Array(v) => assert_eq!(v.size, 4),
+            _ => panic!("expected an array")
         }
+        let b = unit("b.glsl", "const uint MAX_LIGHTS = 8;");
+        let stages = merge_stages(vec![a, b], &config.warnings);
+        match test_symbols(&stages) {
+            Err(SalError::RedefinedSymbol { name, location }) => {
+                assert_eq!(name, "MAX_LIGHTS");
+                assert_eq!(location, format!("{}:3:11", dir.join("b.glsl").to_string_lossy()));
+            },
+            _ => panic!("expected a redefined symbol error")
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn nested_use()
+    {
+        let dir = std::env::temp_dir().join(format!("shaderc-nested-use-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let lib = dir.join("lib.bpx");
+        write_lib(&lib, &[
+            ("lighting", b"const struct PerLight { float Intensity; }"),
+            ("engine/lighting", b"const struct PerLight { vec3f Color; float Radius; }")
+        ]);
+        let output = dir.join("out.bpx");
+        let config = config(&lib, &output);
+        let unit = |sal: &str| {
+            let path = dir.join("shader.glsl");
+            std::fs::write(&path, format!("#stage vertex\n#sal\n{}\n#sal\nvoid main() {{}}\n", sal)).unwrap();
+            load_unit(&Unit::Path(&path), &config)
+        };
+        let nested = unit("use engine::lighting::PerLight;").unwrap();
+        assert_eq!(nested.statements.cbuffers[0].inner.props.len(), 2);
+        let flat = unit("use lighting::PerLight;").unwrap();
+        assert_eq!(flat.statements.cbuffers[0].inner.props.len(), 1);
+        match visitor_error(unit("use engine::shadows::Cascade;").map(|v| v.statements.resources)) {
+            VisitorError::Use(e) => assert_eq!(e.to_string(), "module not found 'engine::shadows'"),
+            e => panic!("unexpected error: {}", e)
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn module_cache()
+    {
+        let dir = std::env::temp_dir().join(format!("shaderc-module-cache-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let lib = dir.join("lib.bpx");
+        write_lib(&lib, &[
+            ("common", b"const struct Camera { mat4f ViewProj; }\nconst struct Fog { vec4f Color; }"),
+            ("lights", b"use common::Camera;\nconst struct PerLight extends Camera { vec3f Color; }\nconst float Intensity;"),
+            ("a", b"use b::Y;\nconst struct X { float A; }"),
+            ("b", b"const float Pad;\nuse a::X;\nconst struct Y { float B; }")
+        ]);
+        let output = dir.join("out.bpx");
+        let config = config(&lib, &output);
+        let modules = ModuleCache::new();
+        let unit = |sal: &str| {
+            let path = dir.join("shader.glsl");
+            std::fs::write(&path, format!("#stage vertex\n#sal\n{}\n#sal\nvoid main() {{}}\n", sal)).unwrap();
+            load_shader_to_sal(&Unit::Path(&path), &config, &modules).map(|mut v| v.remove(0))
+        };
+        let lights = unit("use lights::PerLight;\nuse lights::Intensity;").unwrap();
+        assert_eq!(lights.statements.cbuffers[0].inner.props.len(), 2);
+        assert_eq!(modules.loads(), 2);
+        //Both modules are cached, resolving other members does not read them again.
+        let fog = unit("use common::Fog;\nuse lights::PerLight;").unwrap();
+        assert_eq!(fog.statements.cbuffers.len(), 2);
+        assert_eq!(modules.loads(), 2);
+        assert_eq!(fog.dependencies.iter().filter(|v| **v == lib).count(), 1);
+        match visitor_error(unit("use a::X;").map(|v| v.statements.resources)) {
+            VisitorError::Use(e) => {
+                assert!(matches!(&e, UseError::Cycle { chain, .. } if chain == &["a", "b", "a"]));
+                assert!(e.to_string().starts_with("circular use chain a -> b -> a (use statement at b:2:"));
+            },
+            e => panic!("unexpected error: {}", e)
+        }
+        match visitor_error(unit("use common::Light;").map(|v| v.statements.resources)) {
+            VisitorError::Use(e) => assert_eq!(e.to_string(), "member not found 'Light' in module 'common' (exported members: Camera, Fog)"),
+            e => panic!("unexpected error: {}", e)
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn dependencies()
+    {
+        let dir = std::env::temp_dir().join(format!("shaderc-dependencies-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let lib = dir.join("lib.bpx");
+        write_lib(&lib, &[("engine/lighting", b"const struct PerLight { float Intensity; }")]);
+        let output = dir.join("out.bpx");
+        let config = config(&lib, &output);
+        let path = dir.join("shader.glsl");
+        std::fs::write(&path, "#stage vertex\n#sal\nuse engine::lighting::PerLight;\n#sal\nvoid main() {}\n").unwrap();
+        let unit = load_unit(&Unit::Path(&path), &config).unwrap();
+        assert!(unit.dependencies.contains(&lib));
+        assert!(unit.dependencies.contains(&path));
+        let depfile = Depfile::new(&dir);
+        unit.dependencies.iter().for_each(|v| depfile.record(v));
+        let makefile = depfile.to_makefile(&output);
+        assert!(makefile.starts_with(&format!("{}:", output.display())));
+        assert!(makefile.contains(&*lib.to_string_lossy()));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn samplerstate()
+    {
+        let dir = std::env::temp_dir().join(format!("shaderc-samplerstate-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let lib = dir.join("lib.bpx");
+        write_lib(&lib, &[]);
+        let output = dir.join("out.bpx");
+        let config = config(&lib, &output);
+        let stages = |sal: &str| {
+            let path = dir.join("shader.glsl");
+            std::fs::write(&path, format!("#stage pixel\n#sal\n{}\n#sal\nvoid main() {{}}\n", sal)).unwrap();
+            merge_stages(vec![load_unit(&Unit::Path(&path), &config).unwrap()], &config.warnings)
+        };
+        let ok = stages("const Sampler BaseSampler;\nsamplerstate BaseSampler { MagFilter = Nearest; AddressV = Clamp; Anisotropy = 16; }");
+        test_symbols(&ok).unwrap();
+        let state = &ok[&Stage::Pixel].statements.samplerstates[0];
+        assert_eq!(state.name, "BaseSampler");
+        assert_eq!(state.mag_filter, Filter::Nearest);
+        assert_eq!(state.address_v, AddressMode::Clamp);
+        assert_eq!(state.anisotropy, 16);
+        let mismatch = stages("const Texture2D:vec4f BaseTexture;\nsamplerstate BaseTexture { MinFilter = Nearest; }");
+        assert!(matches!(test_symbols(&mismatch), Err(SalError::UnknownSamplerState(name)) if name == "BaseTexture"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn coalesced_deprecation()
+    {
+        let dir = std::env::temp_dir().join(format!("shaderc-coalesce-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let lib = dir.join("lib.bpx");
+        write_lib(&lib, &[("M", b"const struct Legacy : deprecated(\"use Camera\") { float Value; }")]);
+        let output = dir.join("out.bpx");
+        let config = config(&lib, &output);
+        for stage in ["vertex", "pixel", "geometry"] {
+            let path = dir.join(format!("{}.glsl", stage));
+            std::fs::write(&path, format!("#stage {}\n#sal\nuse M::Legacy;\n#sal\nvoid main() {{}}\n", stage)).unwrap();
+            load_unit(&Unit::Path(&path), &config).unwrap();
+        }
+        let records = config.warnings.records();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].count, 3);
+        let units: Vec<String> = ["vertex", "pixel", "geometry"].iter()
+            .map(|v| format!("{}:3:4", dir.join(format!("{}.glsl", v)).to_string_lossy()))
+            .collect();
+        //Each occurrence is located at the use statement of its unit.
+        assert_eq!(records[0].to_string(), format!("use of deprecated symbol 'M::Legacy': use Camera [deprecated] (repeated 3 times across {})", units.join(", ")));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn pipeline_preset()
+    {
+        let dir = std::env::temp_dir().join(format!("shaderc-preset-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let lib = dir.join("lib.bpx");
+        write_lib(&lib, &[
+            ("presets", b"pipeline OpaqueDefault\n{\n    DepthWriteEnable = false;\n    CullingMode = FrontFace;\n    ViewportCount = 2;\n}")
+        ]);
+        let output = dir.join("out.bpx");
+        let config = config(&lib, &output);
+        let pipeline = |sal: &str| {
+            let path = dir.join("shader.glsl");
+            std::fs::write(&path, format!("#stage vertex\n\n#sal\n{}\n#sal\n\nvoid main() {{}}\n", sal)).unwrap();
+            load_unit(&Unit::Path(&path), &config).map(|v| v.statements.pipeline)
+        };
+        //Overriding a field back to its default must win over the preset.
+        let main = pipeline("use presets::OpaqueDefault;\npipeline Main : OpaqueDefault { CullingMode = BackFace; }").unwrap().unwrap();
+        assert_eq!(main.name, "Main");
+        assert_eq!(main.culling_mode, CullingMode::BackFace);
+        assert!(!main.depth_write_enable);
+        assert!(main.depth_enable);
+        assert_eq!(main.viewport_count, 2);
+        //Imported alone, the preset is the pipeline of the shader.
+        let preset = pipeline("use presets::OpaqueDefault;").unwrap().unwrap();
+        assert_eq!(preset.name, "OpaqueDefault");
+        assert_eq!(preset.culling_mode, CullingMode::FrontFace);
+        match pipeline("pipeline Main : OpaqueDefault { CullingMode = Disabled; }") {
+            Err(Error::Sal(AutoError::Ast(e))) => assert!(matches!(e.into_inner(), AstError::Value(ValueError::UnknownPreset(_)))),
+            _ => panic!("expected an unknown preset error")
+        }
+        let res = pipeline("use presets::OpaqueDefault;\npipeline A : OpaqueDefault { }\npipeline B : OpaqueDefault { }");
+        assert!(matches!(res, Err(Error::Sal(AutoError::Ast(AstError::Visitor(VisitorError::DuplicatePipeline))))));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn strict_sal()
+    {
+        let dir = std::env::temp_dir().join(format!("shaderc-strict-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let lib = dir.join("lib.bpx");
+        write_lib(&lib, &[]);
+        let output = dir.join("out.bpx");
+        let mut config = config(&lib, &output);
+        let sal = "const struct Material : ORDER_01 { vec4f BaseColor; }";
+        assert!(load(&dir, &config, sal).is_ok());
+        config.strict_sal = true;
+        match load(&dir, &config, sal) {
+            Err(Error::Sal(AutoError::Ast(e))) => assert!(matches!(e.into_inner(), AstError::Strict(e) if e.rule == StrictnessRule::LeadingZeros)),
+            _ => panic!("expected a strict error")
+        }
+        assert!(load(&dir, &config, "const struct Material : ORDER_1 { vec4f BaseColor; }").is_ok());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn defines()
+    {
+        let dir = std::env::temp_dir().join(format!("shaderc-defines-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let lib = dir.join("lib.bpx");
+        write_lib(&lib, &[
+            ("Quality", b"#if HIGH\nconst struct Shadows { mat4f LightSpace; }\n#else\nconst struct Shadows { ?? }\n#endif")
+        ]);
+        let output = dir.join("out.bpx");
+        let mut config = config(&lib, &output);
+        let path = dir.join("shader.glsl");
+        std::fs::write(&path, "#stage vertex\n#sal\n#if HIGH\nuse Quality::Shadows;\nconst Texture2D:float ShadowMap;\n#endif\n#sal\n#if HIGH\nvoid main() {}\n#endif\n").unwrap();
+        let low = load_unit(&Unit::Path(&path), &config).unwrap();
+        assert!(low.statements.cbuffers.is_empty());
+        assert!(low.statements.objects.is_empty());
+        config.defines = vec!["HIGH".into()];
+        let high = load_unit(&Unit::Path(&path), &config).unwrap();
+        let cbuffers: Vec<&str> = high.statements.cbuffers.iter().map(|v| &*v.inner.name).collect();
+        assert_eq!(cbuffers, ["Shadows"]);
+        assert_eq!(high.statements.objects.len(), 1);
+        //GLSL conditionals are left to glslang which receives the defines as a separate part.
+        assert!(high.strings[0].code().contains("#if HIGH\nvoid main() {}\n#endif"));
+        std::fs::write(&path, "#stage vertex\n#sal\n#if HIGH\nconst float Exposure;\n#sal\n").unwrap();
+        match load_unit(&Unit::Path(&path), &config) {
+            Err(Error::Sal(AutoError::Lexer(e))) => assert_eq!((e.line, e.col), (3, 1)),
+            _ => panic!("expected an unterminated #if error")
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn stage_override()
+    {
+        let dir = std::env::temp_dir().join(format!("shaderc-stage-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let lib = dir.join("lib.bpx");
+        write_lib(&lib, &[("deferred", b"#stage pixel\nvoid main() {}\n")]);
+        let output = dir.join("out.bpx");
+        let config = config(&lib, &output);
+        let plain = dir.join("plain.glsl");
+        let pixel = dir.join("pixel.glsl");
+        std::fs::write(&plain, "void main() {}\n").unwrap();
+        std::fs::write(&pixel, "#stage pixel\nvoid main() {}\n").unwrap();
+        assert_eq!(load_unit(&Unit::Path(&plain), &config).unwrap().stage, Stage::Vertex);
+        assert_eq!(load_unit(&Unit::PathWithStage(Stage::Geometry, &plain), &config).unwrap().stage, Stage::Geometry);
+        assert_eq!(load_unit(&Unit::PathWithStage(Stage::Pixel, &pixel), &config).unwrap().stage, Stage::Pixel);
+        let err = load_unit(&Unit::PathWithStage(Stage::Vertex, &pixel), &config).unwrap_err();
+        assert!(matches!(err, Error::StageMismatch { declared: Stage::Vertex, found: Stage::Pixel, .. }));
+        assert_eq!(err.to_string(), format!("{}: declared stage 'vertex' does not match '#stage pixel' of the unit", pixel.to_string_lossy()));
+        //Injected units keep the stage of their directive.
+        assert_eq!(load_unit(&Unit::Injected("deferred"), &config).unwrap().stage, Stage::Pixel);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn stage_sections()
+    {
+        let dir = std::env::temp_dir().join(format!("shaderc-sections-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let lib = dir.join("lib.bpx");
+        write_lib(&lib, &[]);
+        let output = dir.join("out.bpx");
+        let config = config(&lib, &output);
+        let path = dir.join("effect.glsl");
+        let write = |code: &str| std::fs::write(&path, code).unwrap();
+        write("#sal\nvformat struct Vertex { vec3f Position; }\nconst Texture2D:vec4f Albedo;\n#sal\nfloat half(float v) { return v / 2.0; }\n\
+               #stage vertex\nvoid main() {}\n#stage pixel\nvoid main() {}\n");
+        let units = load_shader_to_sal(&Unit::Path(&path), &config, &ModuleCache::new()).unwrap();
+        assert_eq!(units.iter().map(|v| v.stage).collect::<Vec<Stage>>(), [Stage::Vertex, Stage::Pixel]);
+        //SAL statements are shared, the vertex format only goes to the vertex section.
+        assert_eq!(units[0].vformats, ["Vertex"]);
+        assert!(units[1].vformats.is_empty() && units[1].statements.vformat.is_none());
+        assert!(units.iter().all(|v| v.statements.objects.len() == 1));
+        //Lines of other sections are blanked, shared code and line numbers are kept.
+        let vertex: Vec<&str> = units[0].strings.last().unwrap().code().split('\n').collect();
+        let pixel: Vec<&str> = units[1].strings.last().unwrap().code().split('\n').collect();
+        assert_eq!(vertex.len(), pixel.len());
+        assert_eq!(vertex[4], "float half(float v) { return v / 2.0; }");
+        assert_eq!(pixel[4], vertex[4]);
+        assert_eq!(&vertex[5..], ["//#stage vertex", "void main() {}", "", ""]);
+        assert_eq!(&pixel[5..], ["", "", "//#stage pixel", "void main() {}"]);
+        assert_eq!(merge_stages(units, &config.warnings).len(), 2);
+        //A declared stage selects its section.
+        let pixel = load_unit(&Unit::PathWithStage(Stage::Pixel, &path), &config).unwrap();
+        assert_eq!(pixel.stage, Stage::Pixel);
+        let err = load_unit(&Unit::PathWithStage(Stage::Geometry, &path), &config).unwrap_err();
+        assert!(matches!(err, Error::MissingSection { stage: Stage::Geometry, .. }));
+        write("#stage vertex\nvoid main() {}\n#stage fragment\nvoid main() {}\n");
+        let err = load_shader_to_sal(&Unit::Path(&path), &config, &ModuleCache::new()).unwrap_err();
+        assert_eq!(err.to_string(), "preprocessor error: unknown shader stage 'fragment'");
+        write("#stage vertex\nvoid main() {}\n#stage vertex\nvoid main() {}\n");
+        let err = load_shader_to_sal(&Unit::Path(&path), &config, &ModuleCache::new()).unwrap_err();
+        assert_eq!(err.to_string(), "preprocessor error: duplicate '#stage vertex' section");
+        //SAL is global: it may follow the first marker but not GLSL code of a section.
+        write("#stage vertex\n#sal\nconst float Exposure;\n#sal\nvoid main() {}\n#stage pixel\nvoid main() {}\n");
+        assert_eq!(load_shader_to_sal(&Unit::Path(&path), &config, &ModuleCache::new()).unwrap().len(), 2);
+        write("#stage vertex\nvoid main() {}\n#sal\nconst float Exposure;\n#sal\n#stage pixel\nvoid main() {}\n");
+        let err = load_shader_to_sal(&Unit::Path(&path), &config, &ModuleCache::new()).unwrap_err();
+        assert!(matches!(err, Error::Preprocessor(PreprocessorError::LateSal(3))));
+        //Single stage units keep accepting SAL anywhere.
+        write("#stage vertex\nvoid main() {}\n#sal\nconst float Exposure;\n#sal\n");
+        assert_eq!(load_unit(&Unit::Path(&path), &config).unwrap().stage, Stage::Vertex);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn unit_cache()
+    {
+        let dir = std::env::temp_dir().join(format!("shaderc-cache-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let lib = dir.join("lib.bpx");
+        write_lib(&lib, &[]);
+        let output = dir.join("out.bpx");
+        let vertex = dir.join("vertex.glsl");
+        let pixel = dir.join("pixel.glsl");
+        //The vertex unit has no stage directive so that loading it reports a warning.
+        std::fs::write(&vertex, "void main() {}\n").unwrap();
+        std::fs::write(&pixel, "#stage pixel\nvoid main() {}\n").unwrap();
+        let cache = UnitCache::new();
+        let mut config = config(&lib, &output);
+        config.units = vec![Unit::Path(&vertex), Unit::Path(&pixel)];
+        config.unit_cache = Some(cache.clone());
+        assert_eq!(load_pass(&config).unwrap().len(), 2);
+        assert_eq!(config.warnings.records().len(), 1);
+        assert_eq!(cache.len(), 2);
+        assert!(cache.dependencies().contains(&vertex));
+        //Unchanged units are reused without being loaded again.
+        config.warnings = config.warnings.fork();
+        let shaders = load_pass(&config).unwrap();
+        assert_eq!(shaders[0].stage, Stage::Vertex);
+        assert!(config.warnings.records().is_empty());
+        std::fs::write(&vertex, "void main() { }\n").unwrap();
+        assert_eq!(load_pass(&config).unwrap().len(), 2);
+        assert_eq!(config.warnings.records().len(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn struct_inheritance()
+    {
+        let dir = std::env::temp_dir().join(format!("shaderc-extends-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let lib = dir.join("lib.bpx");
+        write_lib(&lib, &[
+            ("common", b"const struct CommonHeader : Pack { mat4f ViewProj; float Time; }"),
+            ("camera", b"const struct Base { float Exposure; }\nconst struct Lens extends Base : Pack { float Fov; }")
+        ]);
+        let output = dir.join("out.bpx");
+        let config = config(&lib, &output);
+        let path = dir.join("shader.glsl");
+        std::fs::write(&path, "#stage vertex\n\n#sal\nuse common::CommonHeader;\nuse camera::Lens;\n\
+            const struct PerView extends CommonHeader : Pack { vec3f CameraPos; }\n\
+            const struct PerObject extends PerView : ORDER_1 { mat4f Model; }\n#sal\n\nvoid main() {}\n").unwrap();
+        let ast = load_unit(&Unit::Path(&path), &config).unwrap().statements;
+        //A base declared in the imported module is resolved in the module.
+        let lens = ast.packed_structs.iter().find(|v| v.name == "Lens").unwrap();
+        assert_eq!(lens.props.iter().map(|v| v.pname.as_str()).collect::<Vec<_>>(), ["Exposure", "Fov"]);
+        assert_eq!(ast.cbuffers.len(), 1);
+        let st = compile_struct(ast.cbuffers[0].inner.clone(), &Vec::new(), LayoutRule::Std140).unwrap();
+        let offsets: Vec<(&str, usize)> = st.props.iter().map(|v| (v.inner.pname.as_str(), v.aligned_offset)).collect();
+        assert_eq!(offsets, [("ViewProj", 0), ("Time", 64), ("CameraPos", 80), ("Model", 96)]);
+        assert_eq!(st.size, 160);
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 }
@@ -27,11 +27,13 @@
 // SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
 use std::{
+    collections::HashMap,
     fs::File,
     io::BufReader,
-    path::Path
+    path::{Path, PathBuf}
 };
 
+use bp3d_symbols::FileKind;
 use bpx::macros::impl_err_conversion;
 use bpx::package::Package;
 use thiserror::Error;
@@ -43,7 +45,18 @@ pub enum Error
     Io(std::io::Error),
 
     #[error("bpx error: {0}")]
-    Bpx(bpx::package::error::Error)
+    Bpx(bpx::package::error::Error),
+
+    #[error("'{}' is {}, but --lib expects a shader library (type SL){}", .0.display(), .1, hint(*.1))]
+    WrongKind(PathBuf, FileKind)
+}
+
+fn hint(kind: FileKind) -> &'static str
+{
+    match kind {
+        FileKind::ShaderPack | FileKind::Assembly => "; did you mean to pass it as an input to shaderl?",
+        _ => ""
+    }
 }
 
 impl_err_conversion!(
@@ -62,6 +75,10 @@ impl ShaderLibDecoder
 {
     pub fn new(path: &Path) -> Result<ShaderLibDecoder, Error>
     {
+        let kind = FileKind::probe(path)?;
+        if kind != FileKind::ShaderLib {
+            return Err(Error::WrongKind(path.into(), kind));
+        }
         let file = File::open(path)?;
         let package = Package::open(BufReader::new(file))?;
         Ok(ShaderLibDecoder {
@@ -69,38 +86,171 @@ impl ShaderLibDecoder
         })
     }
 
-    pub fn try_load(&self, name: &str) -> Result<Option<Vec<u8>>, Error>
+    pub fn load_index(&self) -> Result<HashMap<String, usize>, Error>
     {
-        let mut data = Vec::new();
         let objects = self.package.objects()?;
-        if let Some(obj) = objects.find(name)? {
-            objects.load(obj, &mut data)?;
-            Ok(Some(data))
-        } else {
-            Ok(None)
+        let mut index = HashMap::new();
+        for (i, obj) in objects.iter().enumerate() {
+            index.insert(objects.load_name(obj)?.into(), i);
         }
+        Ok(index)
+    }
+
+    /// Loads the objects at the given positions of the object table in a single pass.
+    pub fn load_many(&self, positions: &[usize]) -> Result<Vec<Vec<u8>>, Error>
+    {
+        let objects = self.package.objects()?;
+        let mut wanted: HashMap<usize, Vec<usize>> = HashMap::new();
+        for (slot, pos) in positions.iter().enumerate() {
+            wanted.entry(*pos).or_default().push(slot);
+        }
+        let mut data = vec![Vec::new(); positions.len()];
+        for (i, obj) in objects.iter().enumerate() {
+            if let Some(slots) = wanted.get(&i) {
+                let mut buffer = Vec::new();
+                objects.load(obj, &mut buffer)?;
+                for slot in slots {
+                    data[*slot] = buffer.clone();
+                }
+            }
+        }
+        Ok(data)
     }
 }
 
 pub struct ShaderLib<'a>
 {
     path: &'a Path,
-    decoder: Option<ShaderLibDecoder>
+    decoder: Option<ShaderLibDecoder>,
+    index: Option<HashMap<String, usize>>
 }
 
 impl<'a> ShaderLib<'a>
 {
     pub fn new(path: &'a Path) -> Self
     {
-        Self { path, decoder: None }
+        Self { path, decoder: None, index: None }
     }
 
-    pub fn try_load(&mut self, name: &str) -> Result<Option<Vec<u8>>, Error>
+    pub fn path(&self) -> &'a Path
+    {
+        self.path
+    }
+
+    fn decoder(&mut self) -> Result<&ShaderLibDecoder, Error>
     {
         if self.decoder.is_none() {
             self.decoder = Some(ShaderLibDecoder::new(self.path)?);
         }
-        let val = unsafe { self.decoder.as_ref().unwrap_unchecked() };
-        val.try_load(name)
+        Ok(unsafe { self.decoder.as_ref().unwrap_unchecked() })
+    }
+
+    /// Returns the position of each object in the library, reading the object table only once.
+    pub fn load_index(&mut self) -> Result<&HashMap<String, usize>, Error>
+    {
+        if self.index.is_none() {
+            let index = self.decoder()?.load_index()?;
+            self.index = Some(index);
+        }
+        Ok(unsafe { self.index.as_ref().unwrap_unchecked() })
+    }
+
+    /// Loads all the given names which exist in this library, in the order they were requested.
+    pub fn try_load_many<'b>(&mut self, names: impl IntoIterator<Item = &'b str>) -> Result<Vec<(String, Vec<u8>)>, Error>
+    {
+        let index = self.load_index()?;
+        let (names, positions): (Vec<String>, Vec<usize>) = names.into_iter()
+            .filter_map(|name| index.get(name).map(|pos| (String::from(name), *pos)))
+            .unzip();
+        if positions.is_empty() {
+            return Ok(Vec::new());
+        }
+        let data = self.decoder()?.load_many(&positions)?;
+        Ok(names.into_iter().zip(data).collect())
+    }
+
+    pub fn try_load(&mut self, name: &str) -> Result<Option<Vec<u8>>, Error>
+    {
+        Ok(self.try_load_many([name])?.pop().map(|(_, data)| data))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::fs::File;
+    use std::io::BufWriter;
+    use std::path::Path;
+    use bpx::package;
+    use bpx::package::Package;
+    use bpx::shader::ShaderPack;
+    use crate::targets::basic::shaderlib::ShaderLib;
+
+    fn write_pack(path: &Path, ty: bpx::shader::Type)
+    {
+        let mut pack = ShaderPack::create(BufWriter::new(File::create(path).unwrap()),
+                                          bpx::shader::Builder::new()
+                                              .ty(ty)
+                                              .target(bpx::shader::Target::GL42));
+        pack.save().unwrap();
+    }
+
+    #[test]
+    fn reject_non_libs()
+    {
+        let dir = std::env::temp_dir().join(format!("shaderc-shaderlib-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let pack = dir.join("foo.bpx");
+        write_pack(&pack, bpx::shader::Type::Pipeline);
+        let err = ShaderLib::new(&pack).try_load("test.glsl").unwrap_err();
+        assert_eq!(err.to_string(), format!("'{}' is a shader pack (type SP), but --lib expects a shader library (type SL); did you mean to pass it as an input to shaderl?", pack.display()));
+        let assembly = dir.join("assembly.bpx");
+        write_pack(&assembly, bpx::shader::Type::Assembly);
+        let err = ShaderLib::new(&assembly).try_load("test.glsl").unwrap_err();
+        assert!(err.to_string().contains("is a shader assembly (type SA), but --lib expects a shader library (type SL)"));
+        let text = dir.join("vertex.glsl");
+        std::fs::write(&text, "#stage vertex\n").unwrap();
+        let err = ShaderLib::new(&text).try_load("test.glsl").unwrap_err();
+        assert!(err.to_string().ends_with("is not a BPX file, but --lib expects a shader library (type SL)"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn batch_lookup()
+    {
+        let dir = std::env::temp_dir().join(format!("shaderc-shaderlib-batch-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("big.bpx");
+        {
+            let mut bpxp = Package::create(BufWriter::new(File::create(&path).unwrap()),
+                                           package::Builder::new()
+                                               .type_code(*b"SL")
+                                               .architecture(package::Architecture::Any)
+                                               .platform(package::Platform::Any)).unwrap();
+            {
+                let mut objects = bpxp.objects_mut().unwrap();
+                for i in 0..1000 {
+                    objects.create(&*format!("shader{}.glsl", i), format!("#stage vertex\n//{}\n", i).as_bytes()).unwrap();
+                }
+            }
+            bpxp.save().unwrap();
+        }
+        let mut lib = ShaderLib::new(&path);
+        let index = lib.load_index().unwrap() as *const _;
+        let names: Vec<String> = (0..1000).rev().step_by(3).map(|i| format!("shader{}.glsl", i)).collect();
+        let loaded = lib.try_load_many(names.iter().map(|v| &**v).chain(["missing.glsl"])).unwrap();
+        assert_eq!(loaded.len(), names.len());
+        for ((name, data), expected) in loaded.iter().zip(&names) {
+            assert_eq!(name, expected);
+            let id = &expected[6..expected.len() - 5];
+            assert_eq!(std::str::from_utf8(data).unwrap(), format!("#stage vertex\n//{}\n", id));
+        }
+        //The index must be reused by every lookup instead of being rebuilt.
+        assert_eq!(lib.load_index().unwrap() as *const _, index);
+        assert_eq!(lib.load_index().unwrap().len(), 1000);
+        assert_eq!(lib.try_load("shader42.glsl").unwrap().unwrap(), b"#stage vertex\n//42\n");
+        assert!(lib.try_load("missing.glsl").unwrap().is_none());
+        assert_eq!(lib.load_index().unwrap() as *const _, index);
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 }
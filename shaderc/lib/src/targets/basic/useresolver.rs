@@ -26,17 +26,21 @@
 // NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
 // SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
-use std::path::Path;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
 
-use log::debug;
+use log::{debug, trace};
 use thiserror::Error;
 use bp3d_sal::ast::tree::Statement;
-use bp3d_sal::ast::tree::{Attribute, BlendfuncStatement, PipelineStatement, Property, Struct};
+use bp3d_sal::ast::tree::{Attribute, BlendfuncStatement, ConstantInt, PipelineStatement, Property, ResourceStatement, SamplerStateStatement, Span, Struct};
 use bp3d_sal::ast::{AstBuilder, Visitor};
 use bp3d_sal::lexer::Lexer;
 use bp3d_sal::parser::error::ParserOrVisitor;
 use bp3d_sal::parser::Parser;
-use crate::targets::basic::BasicAst;
+use crate::repro::Sources;
+use crate::targets::basic::{BasicAst, Slot};
 
 use crate::targets::basic::shaderlib::ShaderLib;
 
@@ -51,123 +55,314 @@ pub enum Error
     Parser(bp3d_sal::parser::error::Error),
     #[error("SAL AST error: {0}")]
     Ast(bp3d_sal::ast::error::Error<usize, ()>),
+    #[error("SAL visitor error: {0}")]
+    Panic(bp3d_sal::parser::error::VisitorPanic),
     #[error("module not found '{0}'")]
     ModuleNotFound(String),
-    #[error("member not found '{0}'")]
-    MemberNotFound(String)
+    #[error("member not found '{member}' in module '{module}' (exported members: {})", .exported.join(", "))]
+    MemberNotFound
+    {
+        module: String,
+        member: String,
+        exported: Vec<String>
+    },
+    /// `location` is the module, line and column of the use statement closing the chain.
+    #[error("circular use chain {}{}", .chain.join(" -> "), .location.as_ref().map(|v| format!(" (use statement at {})", v)).unwrap_or_default())]
+    Cycle
+    {
+        chain: Vec<String>,
+        location: Option<String>
+    }
 }
 
-pub struct EarlyStopVisitor<'a> {
-    member: &'a str
+/// A parsed module: its statements in declaration order and the AST holding the structs and
+/// presets its members may refer to.
+#[derive(Clone)]
+struct Module
+{
+    lib: PathBuf,
+    statements: Vec<Statement<usize>>,
+    ast: BasicAst
 }
 
-impl<'a> Visitor<BasicAst> for EarlyStopVisitor<'a> {
-    type Error = Statement<usize>;
+/// Modules parsed during a build, shared by the use resolvers of all units.
+///
+/// The cache is not locked while a module is parsed, as the use statements of the module resolve
+/// through it; two units resolving the same module at the same time may then both parse it.
+#[derive(Clone, Default)]
+pub struct ModuleCache
+{
+    modules: Arc<Mutex<HashMap<String, Module>>>,
+    loads: Arc<AtomicUsize>
+}
 
-    fn visit_constant(&mut self, _: &mut BasicAst, val: Property<usize>) -> Result<(), Self::Error> {
-        if val.pname == self.member {
-            Err(Statement::Constant(val))
+impl ModuleCache
+{
+    pub fn new() -> ModuleCache
+    {
+        ModuleCache::default()
+    }
+
+    /// Returns the number of modules read and parsed from the shader libraries.
+    pub fn loads(&self) -> usize
+    {
+        self.loads.load(Ordering::Relaxed)
+    }
+
+    fn get(&self, object: &str) -> Option<Module>
+    {
+        self.modules.lock().unwrap().get(object).cloned()
+    }
+
+    fn insert(&self, object: String, module: Module)
+    {
+        self.loads.fetch_add(1, Ordering::Relaxed);
+        self.modules.lock().unwrap().insert(object, module);
+    }
+}
+
+struct ModuleVisitor<'r, 'a>
+{
+    resolver: &'r mut BasicUseResolver<'a>,
+    statements: Vec<Statement<usize>>
+}
+
+impl<'r, 'a> ModuleVisitor<'r, 'a>
+{
+    //Structs are kept so that other members may extend them.
+    fn push_struct(ast: &mut BasicAst, val: Struct<usize>)
+    {
+        let is_packed = val.attr.as_ref().map(|v| v == &Attribute::Pack).unwrap_or_default();
+        if is_packed {
+            ast.push_packed_struct(val.name.clone(), val);
         } else {
-            Ok(())
+            ast.cbuffers.push(Slot::new(val));
         }
     }
+}
+
+impl<'r, 'a> Visitor<BasicAst> for ModuleVisitor<'r, 'a> {
+    type Error = Error;
+
+    fn visit_constant(&mut self, _: &mut BasicAst, val: Property<usize>) -> Result<(), Self::Error> {
+        self.statements.push(Statement::Constant(val));
+        Ok(())
+    }
+
+    fn visit_constant_int(&mut self, _: &mut BasicAst, val: ConstantInt) -> Result<(), Self::Error> {
+        self.statements.push(Statement::ConstantInt(val));
+        Ok(())
+    }
 
     fn visit_output(&mut self, _: &mut BasicAst, val: Property<usize>) -> Result<(), Self::Error> {
-        if val.pname == self.member {
-            Err(Statement::Output(val))
-        } else {
-            Ok(())
-        }
+        self.statements.push(Statement::Output(val));
+        Ok(())
+    }
+
+    fn visit_varying(&mut self, _: &mut BasicAst, val: Property<usize>) -> Result<(), Self::Error> {
+        self.statements.push(Statement::Varying(val));
+        Ok(())
     }
 
     fn visit_constant_buffer(&mut self, ast: &mut BasicAst, val: Struct<usize>) -> Result<(), Self::Error> {
-        if val.name == self.member {
-            Err(Statement::ConstantBuffer(val))
-        } else {
-            let is_packed = val.attr.as_ref().map(|v| v == &Attribute::Pack).unwrap_or_default();
-            if is_packed {
-                ast.push_packed_struct(val.name.clone(), val);
-            }
-            Ok(())
-        }
+        Self::push_struct(ast, val.clone());
+        self.statements.push(Statement::ConstantBuffer(val));
+        Ok(())
     }
 
-    fn visit_vertex_format(&mut self, _: &mut BasicAst, val: Struct<usize>) -> Result<(), Self::Error> {
-        if val.name == self.member {
-            Err(Statement::VertexFormat(val))
-        } else {
-            Ok(())
-        }
+    fn visit_vertex_format(&mut self, ast: &mut BasicAst, val: Struct<usize>) -> Result<(), Self::Error> {
+        ast.vformat = Some(val.clone());
+        self.statements.push(Statement::VertexFormat(val));
+        Ok(())
     }
 
-    fn visit_pipeline(&mut self, _: &mut BasicAst, val: PipelineStatement) -> Result<(), Self::Error> {
-        if val.name == self.member {
-            Err(Statement::Pipeline(val))
-        } else {
-            Ok(())
-        }
+    fn visit_root_constants(&mut self, ast: &mut BasicAst, val: Struct<usize>) -> Result<(), Self::Error> {
+        ast.root_constants_layout = Some(val.clone());
+        self.statements.push(Statement::RootConstants(val));
+        Ok(())
+    }
+
+    fn visit_pipeline(&mut self, ast: &mut BasicAst, val: PipelineStatement) -> Result<(), Self::Error> {
+        ast.presets.push(val.clone());
+        self.statements.push(Statement::Pipeline(val));
+        Ok(())
     }
 
     fn visit_blendfunc(&mut self, _: &mut BasicAst, val: BlendfuncStatement) -> Result<(), Self::Error> {
-        if val.name == self.member {
-            Err(Statement::Blendfunc(val))
-        } else {
-            Ok(())
-        }
+        self.statements.push(Statement::Blendfunc(val));
+        Ok(())
+    }
+
+    fn visit_samplerstate(&mut self, _: &mut BasicAst, _: SamplerStateStatement) -> Result<(), Self::Error> {
+        Ok(()) //Sampler states share the name of their sampler and are not imported by use.
+    }
+
+    fn visit_resource(&mut self, _: &mut BasicAst, val: ResourceStatement) -> Result<(), Self::Error> {
+        self.statements.push(Statement::Resource(val));
+        Ok(())
     }
 
     fn visit_noop(&mut self, _: &mut BasicAst) -> Result<(), Self::Error> {
         Ok(())
     }
 
-    fn visit_use(&mut self, ast: &mut BasicAst, _: String, _: String) -> Result<(), Self::Error> {
-        self.visit_noop(ast) //We don't support use statements in use contexts.
+    //Members imported by a module are not exported again, they only serve as bases of its own
+    //members.
+    fn visit_use(&mut self, ast: &mut BasicAst, path: Vec<String>, member: String, _: Span) -> Result<(), Self::Error> {
+        if !ast.mark_use(&path.join("::"), &member) {
+            return Ok(());
+        }
+        let (stmt, mut src) = self.resolver.resolve(path, member)?;
+        match stmt {
+            Statement::ConstantBuffer(v) => {
+                let v = ast.insert_struct(v, &mut src);
+                Self::push_struct(ast, v);
+            },
+            Statement::VertexFormat(v) => ast.vformat = Some(v),
+            Statement::RootConstants(v) => ast.root_constants_layout = Some(v),
+            Statement::Pipeline(v) => ast.presets.push(v),
+            _ => ()
+        }
+        Ok(())
     }
 }
 
 pub struct BasicUseResolver<'a>
 {
-    shader_libs: Vec<ShaderLib<'a>>
+    shader_libs: Vec<ShaderLib<'a>>,
+    opened: Vec<PathBuf>,
+    defines: Vec<String>,
+    modules: ModuleCache,
+    sources: Option<Sources>,
+    /// Modules being parsed, outermost first.
+    stack: Vec<String>
 }
 
 impl<'a> BasicUseResolver<'a>
 {
-    pub fn new(libs: &Vec<&'a Path>) -> Self
+    pub fn new(libs: &Vec<&'a Path>, defines: &[String], modules: &ModuleCache, sources: Option<Sources>) -> Self
     {
         Self {
-            shader_libs: libs.into_iter().map(|l| ShaderLib::new(l)).collect()
+            shader_libs: libs.into_iter().map(|l| ShaderLib::new(l)).collect(),
+            opened: Vec::new(),
+            defines: defines.into(),
+            modules: modules.clone(),
+            sources,
+            stack: Vec::new()
+        }
+    }
+
+    /// Returns the shader libraries which provided at least one module, in resolution order.
+    pub fn opened(&self) -> &[PathBuf]
+    {
+        &self.opened
+    }
+
+    fn parse(&mut self, module: &str, data: &[u8]) -> Result<(Vec<Statement<usize>>, BasicAst), Error>
+    {
+        let mut lexer = Lexer::with_defines(&self.defines);
+        lexer.process(data).map_err(Error::Lexer)?;
+        let mut parser = Parser::new(lexer);
+        let mut visitor = ModuleVisitor {
+            resolver: self,
+            statements: Vec::new()
+        };
+        let mut builder = AstBuilder::new(BasicAst::new(), &mut visitor);
+        match parser.parse(&mut builder) {
+            Ok(_) => {
+                let ast = builder.into_inner();
+                Ok((visitor.statements, ast))
+            },
+            Err(ParserOrVisitor::Parser(e)) => Err(Error::Parser(e)),
+            Err(ParserOrVisitor::Panic(e)) => Err(Error::Panic(e)),
+            Err(ParserOrVisitor::Visitor(e)) => {
+                let span = e.span();
+                let e = match e.into_inner() {
+                    bp3d_sal::ast::error::Error::Type(e) => bp3d_sal::ast::error::Error::Type(e),
+                    bp3d_sal::ast::error::Error::Value(e) => bp3d_sal::ast::error::Error::Value(e),
+                    bp3d_sal::ast::error::Error::Strict(e) => bp3d_sal::ast::error::Error::Strict(e),
+                    //The innermost module of a cycle is the one containing the use statement closing it.
+                    bp3d_sal::ast::error::Error::Visitor(Error::Cycle { chain, location: None }) => {
+                        let (line, col) = parser.statement_position();
+                        return Err(Error::Cycle {
+                            chain,
+                            location: Some(format!("{}:{}:{}", module, line, col))
+                        });
+                    },
+                    bp3d_sal::ast::error::Error::Visitor(e) => return Err(e),
+                    //Errors are located only once so into_inner never returns a located error
+                    bp3d_sal::ast::error::Error::Located(..) => unreachable!()
+                };
+                Err(Error::Ast(match span {
+                    Some(span) => e.at(span),
+                    None => e
+                }))
+            }
         }
     }
 
-    pub fn resolve(&mut self, module1: String, member: String) -> Result<(Statement<usize>, BasicAst), Error>
+    fn load(&mut self, path: &[String]) -> Result<Module, Error>
     {
+        let name = path.join("::");
+        if let Some(pos) = self.stack.iter().position(|v| *v == name) {
+            let mut chain = self.stack[pos..].to_vec();
+            chain.push(name);
+            return Err(Error::Cycle {
+                chain,
+                location: None
+            });
+        }
+        let object = path.join("/");
+        if let Some(module) = self.modules.get(&object) {
+            trace!(target: "translation", "Reusing parsed module {}", name);
+            return Ok(module);
+        }
+        let mut found = None;
         for v in &mut self.shader_libs {
-            if let Some(module) = v.try_load(&module1).map_err(Error::ShaderLib)? {
-                let mut lexer = Lexer::new();
-                lexer.process(module.as_ref()).map_err(Error::Lexer)?;
-                let mut parser = Parser::new(lexer);
-                let mut builder = AstBuilder::new(BasicAst::new(), EarlyStopVisitor { member: &member });
-                let ast = parser.parse(&mut builder);
-                return match ast {
-                    Ok(_) => Err(Error::MemberNotFound(member.clone())),
-                    Err(err) => {
-                        match err {
-                            ParserOrVisitor::Parser(e) => Err(Error::Parser(e)),
-                            ParserOrVisitor::Visitor(e) => {
-                                match e {
-                                    bp3d_sal::ast::error::Error::Type(e) => Err(Error::Ast(bp3d_sal::ast::error::Error::Type(e))),
-                                    bp3d_sal::ast::error::Error::Value(e) => Err(Error::Ast(bp3d_sal::ast::error::Error::Value(e))),
-                                    bp3d_sal::ast::error::Error::Visitor(stmt) => {
-                                        debug!("Successfully resolved module {} with member {}", module1, member);
-                                        Ok((stmt, builder.into_inner()))
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
+            if let Some(data) = v.try_load(&object).map_err(Error::ShaderLib)? {
+                found = Some((v.path().to_path_buf(), data));
+                break;
             }
         }
-        return Err(Error::ModuleNotFound(module1));
+        let (lib, data) = found.ok_or_else(|| Error::ModuleNotFound(name.clone()))?;
+        if let Some(sources) = &self.sources {
+            sources.record_module(&object, &data);
+        }
+        self.stack.push(name.clone());
+        let res = self.parse(&name, &data);
+        self.stack.pop();
+        let (statements, ast) = res?;
+        let module = Module {
+            lib,
+            statements,
+            ast
+        };
+        self.modules.insert(object, module.clone());
+        Ok(module)
+    }
+
+    /// Resolves `member` in the module at `path`; nested paths map onto subdirectories of the
+    /// shader libraries, e.g. `engine::lighting` is loaded from `engine/lighting`.
+    ///
+    /// Modules are parsed once per build, the use statements of a module are resolved when it is
+    /// parsed and a module which ends up using itself is an error.
+    pub fn resolve(&mut self, path: Vec<String>, member: String) -> Result<(Statement<usize>, BasicAst), Error>
+    {
+        let module1 = path.join("::");
+        let mut module = self.load(&path)?;
+        if !self.opened.contains(&module.lib) {
+            self.opened.push(module.lib.clone());
+        }
+        match module.statements.iter().position(|v| v.get_name() == Some(&*member)) {
+            Some(i) => {
+                debug!(target: "translation", "Successfully resolved module {} with member {}", module1, member);
+                Ok((module.statements.swap_remove(i), module.ast))
+            },
+            None => Err(Error::MemberNotFound {
+                module: module1,
+                member,
+                exported: module.statements.iter().filter_map(|v| v.get_name()).map(String::from).collect()
+            })
+        }
     }
 }
@@ -0,0 +1,73 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use std::collections::BTreeMap;
+use bpx::shader::Target::ES31;
+use crate::config::Config;
+use std::error::Error;
+use bp3d_symbols::TargetInfo;
+use crate::preview::{CompileError, PreviewResult};
+use crate::Stage;
+use crate::targets::basic::{ShaderStage, Target};
+use crate::targets::gl::{EnvInfo, GlTarget};
+use crate::targets::layout::LayoutRule;
+
+pub(crate) fn target() -> GlTarget
+{
+    GlTarget::new("ES31", EnvInfo {
+        gl_version_int: 310,
+        gl_version_str: "310 es",
+        explicit_bindings: true,
+        //Varyings are declared as interface blocks which are only core since ES 3.2.
+        extensions: &["GL_EXT_shader_io_blocks"],
+        max_viewports: 1,
+        layer_extension: None,
+        int64_extension: None,
+        vulkan: false,
+        spirv: false,
+        es: true,
+        max_cbuffer_bindings: Some(24),
+        binding_shifts: &[],
+        layout: LayoutRule::Std140
+    }, ES31)
+}
+
+pub fn build(config: Config) -> Result<(), Box<dyn Error>>
+{
+    target().run(&config)
+}
+
+pub fn info() -> TargetInfo
+{
+    target().info()
+}
+
+pub fn preview(config: &Config, stages: BTreeMap<Stage, ShaderStage>, stage: Stage) -> Result<PreviewResult, CompileError>
+{
+    crate::targets::gl::preview(&target(), config, stages, stage)
+}
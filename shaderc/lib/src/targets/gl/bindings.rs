@@ -26,139 +26,104 @@
 // NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
 // SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
-use std::collections::{BTreeMap, HashMap, HashSet};
-use bpx::shader::Stage;
-use log::warn;
-use crate::targets::basic::{BindingType, relocate_bindings, ShaderStage, test_bindings};
+use std::collections::{BTreeMap, HashSet};
+use crate::Stage;
+use crate::targets::basic::{BindingType, is_shared_slot, relocate_bindings, ShaderStage, test_bindings};
+use crate::targets::basic::sal_compiler::Error;
+use crate::warnings::{Reporter, WarningKind};
 
-//TODO: In VK target ensure that all bindings are unique across all types of bindings
-pub fn gl_relocate_bindings(stages: &mut BTreeMap<Stage, ShaderStage>)
+/// Returns the lowest slot starting `count` consecutive slots which are neither used nor reserved.
+fn free_slot(used: &HashSet<u32>, count: u32, reserved: impl Fn(u32) -> bool) -> u32
+{
+    let mut slot = 0;
+    while (slot..slot + count).any(|v| used.contains(&v) || reserved(v)) {
+        slot += 1;
+    }
+    slot
+}
+
+/// Relocates the bindings for GL targets, `max_cbuffer_bindings` rejects constant buffers relocated
+/// beyond the uniform buffer bindings guaranteed by the API (OpenGL ES).
+///
+/// Textures, samplers and constant buffers have separate binding spaces; symbols without an explicit
+/// slot take the lowest free slots in the order given by [relocate_bindings].
+pub fn gl_relocate_bindings(stages: &BTreeMap<Stage, ShaderStage>, max_cbuffer_bindings: Option<u32>, warnings: &Reporter) -> Result<(), Error>
 {
     let mut cbufs = HashSet::new();
     let mut textures = HashSet::new();
     let mut samplers = HashSet::new();
-    let mut cbufs_name = HashMap::new();
-    let mut samplers_name = HashMap::new();
-    let mut textures_name = HashMap::new();
-    let mut cbuf_counter: u32 = 1;
-    let mut sampler_counter: u32 = 0;
-    let mut texture_counter: u32 = 0;
-    let mut insert_texture = |name, slot| {
-        if !textures.insert(slot) {
-            warn!("Possible duplicate of texture slot {}", slot);
-        }
-        textures_name.insert(slot, name);
-    };
-    let mut insert_sampler = |name, slot| {
-        if !samplers.insert(slot) {
-            warn!("Possible duplicate of sampler slot {}", slot);
-        }
-        samplers_name.insert(slot, name);
-    };
-    let mut insert_cbuffer = |name, slot| {
-        if !cbufs.insert(slot) {
-            warn!("Possible duplicate of constant buffer slot {}", slot);
+    let insert = |used: &mut HashSet<u32>, kind: &str, slot: u32| {
+        if !used.insert(slot) {
+            warnings.report(WarningKind::BindingOverlap, format!("Possible duplicate of {} slot {}", kind, slot));
         }
-        cbufs_name.insert(slot, name);
     };
-    relocate_bindings(stages, |name, t, existing, _| {
+    relocate_bindings(stages, |_, t, existing| {
         match t {
-            BindingType::Texture => {
-                let slot = existing.map(|slot| {
-                    texture_counter = slot + 1;
-                    slot
-                }).unwrap_or_else(|| {
-                    texture_counter += 1;
-                    texture_counter - 1
-                });
-                insert_texture(name, slot);
+            BindingType::Texture(count) => {
+                let slot = existing.unwrap_or_else(|| free_slot(&textures, count, |_| false));
+                //Each element of a texture array consumes its own texture unit.
+                for i in slot..slot + count {
+                    insert(&mut textures, "texture", i);
+                }
                 slot
             },
             BindingType::Sampler => {
-                let slot = existing.map(|slot| {
-                    sampler_counter = slot + 1;
-                    slot
-                }).unwrap_or_else(|| {
-                    sampler_counter += 1;
-                    sampler_counter - 1
-                });
-                insert_sampler(name, slot);
+                let slot = existing.unwrap_or_else(|| free_slot(&samplers, 1, |_| false));
+                insert(&mut samplers, "sampler", slot);
                 slot
             },
             BindingType::CBuf => {
-                let slot = existing.map(|slot| {
-                    cbuf_counter = slot + 1;
-                    slot
-                }).unwrap_or_else(|| {
-                    cbuf_counter += 1;
-                    cbuf_counter - 1
-                });
-                insert_cbuffer(name, slot);
+                //Constant buffer slots are always resolved by order_cbuffers.
+                let slot = existing.unwrap_or_else(|| free_slot(&cbufs, 1, is_shared_slot));
+                insert(&mut cbufs, "constant buffer", slot);
                 slot
             }
         }
-    });
-    relocate_bindings(stages, |name, t, existing, mut current| {
-        match t {
-            BindingType::Texture => {
-                if let Some(slot) = existing {
-                    slot
-                } else {
-                    if let Some(name1) = textures_name.get(&current) {
-                        if name1 == &name {
-                            return current
-                        }
-                    }
-                    while textures.contains(&current) {
-                        current += 1;
-                    }
-                    current
-                }
-            },
-            BindingType::Sampler => {
-                if let Some(slot) = existing {
-                    slot
-                } else {
-                    if let Some(name1) = samplers_name.get(&current) {
-                        if name1 == &name {
-                            return current
-                        }
-                    }
-                    while samplers.contains(&current) {
-                        current += 1;
-                    }
-                    current
-                }
-            },
-            BindingType::CBuf => {
-                if let Some(slot) = existing {
-                    slot
-                } else {
-                    if let Some(name1) = cbufs_name.get(&current) {
-                        if name1 == &name {
-                            return current
-                        }
-                    }
-                    while cbufs.contains(&current) {
-                        current += 1;
-                    }
-                    current
-                }
+    })?;
+    if let Some(max) = max_cbuffer_bindings {
+        for v in stages.values().flat_map(|v| v.statements.cbuffers.iter()) {
+            if v.slot.get() >= max {
+                return Err(Error::CBufferLimit(v.inner.name.clone(), v.slot.get(), max));
             }
         }
-    });
+    }
+    Ok(())
 }
 
-pub fn gl_test_bindings(stages: &BTreeMap<Stage, ShaderStage>) -> Result<(), crate::targets::basic::sal_compiler::Error>
+pub fn gl_test_bindings(stages: &BTreeMap<Stage, ShaderStage>) -> Result<(), Error>
 {
     let mut cbufs = HashSet::new();
     let mut textures = HashSet::new();
     let mut samplers = HashSet::new();
     test_bindings(stages, |t, slot| {
         match t {
-            BindingType::Texture => textures.insert(slot),
+            BindingType::Texture(count) => (slot..slot + count).all(|v| textures.insert(v)),
             BindingType::Sampler => samplers.insert(slot),
             BindingType::CBuf => cbufs.insert(slot),
         }
     })
 }
+
+/// Vulkan descriptor sets share one binding space between all types of bindings, so unlike the
+/// GL targets constant buffers, textures and samplers take their slots from the same space. A texture
+/// array is a single descriptor and therefore occupies one binding.
+///
+/// Binding 0 stays reserved for the root constants to keep ORDER attributes portable across targets,
+/// even though root constants are push constants on Vulkan.
+pub fn vk_relocate_bindings(stages: &BTreeMap<Stage, ShaderStage>, warnings: &Reporter) -> Result<(), Error>
+{
+    let mut bindings = HashSet::new();
+    relocate_bindings(stages, |_, _, existing| {
+        let slot = existing.unwrap_or_else(|| free_slot(&bindings, 1, |v| v == 0 || is_shared_slot(v)));
+        if !bindings.insert(slot) {
+            warnings.report(WarningKind::BindingOverlap, format!("Possible duplicate of binding {}", slot));
+        }
+        slot
+    })
+}
+
+pub fn vk_test_bindings(stages: &BTreeMap<Stage, ShaderStage>) -> Result<(), Error>
+{
+    let mut bindings = HashSet::new();
+    test_bindings(stages, |_, slot| bindings.insert(slot))
+}
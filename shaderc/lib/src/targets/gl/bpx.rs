@@ -26,18 +26,23 @@
 // NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
 // SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
-use std::collections::HashMap;
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap};
 use std::fs::File;
-use std::io::BufWriter;
-use bp3d_sal::ast::tree::{BlendfuncStatement, PipelineStatement, Property, PropertyType, Struct};
-use bp3d_symbols::{BlendfuncObject, ConstantObject, ConstPropType, OutputObject, OutputPropType};
+use std::io::{BufWriter, Read, Write};
+use bp3d_sal::ast::tree::{BlendfuncStatement, Interpolation, PipelineStatement, Property, PropertyType, SamplerStateStatement, Struct, VarlistStatement};
+use bp3d_symbols::{BlendfuncObject, ConstantObject, ConstPropType, DebugInfo, IdCollision, OutputObject, OutputPropType, PayloadKind, Resource, SamplerObject, SizeError, SymbolId, ToBpx, VaryingObject, VaryingPropType};
+use bpx::core::builder::{CompressionMethod, SectionHeaderBuilder};
 use crate::targets::gl::core::{Object, ShaderBytes, Symbols};
 use bpx::shader;
 use bpx::shader::{ShaderPack, Stage, Type};
-use log::{debug, error, info, warn};
+use log::{debug, error, info};
+use crate::config::CompressionPreference;
 use crate::targets::basic::Slot;
+use crate::targets::basic::sal_compiler::{output_semantics, vformat_locations, vformat_semantics};
 use crate::targets::gl::ext_data::{SymbolWriter, ToObject};
-use crate::targets::layout140::StructOffset;
+use crate::targets::layout::StructOffset;
+use crate::warnings::{Reporter, WarningKind};
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -50,13 +55,43 @@ pub enum Error {
     IllegalOutput,
     #[error("illegal constant type")]
     IllegalConstant,
+    #[error("illegal varying type")]
+    IllegalVarying,
     #[error("bpx error: {0}")]
     Bpx(bpx::shader::error::Error),
-    #[error("bpx serde error: {0}")]
-    Serde(bpx::sd::serde::Error)
+    #[error("bpx serde error in symbol '{0}': {1}")]
+    Serde(String, bpx::sd::serde::Error),
+    #[error("failed to measure extended data for symbol '{0}': {1}")]
+    Size(String, SizeError),
+    #[error("extended data for symbol '{name}' is {size} bytes, exceeding the cap of {max} — consider splitting the struct")]
+    ExtDataTooLarge {
+        name: String,
+        size: usize,
+        max: usize
+    },
+    #[error("bpx error while writing symbol '{name}' ({size} bytes of extended data): {error}")]
+    Symbol {
+        name: String,
+        size: usize,
+        error: bpx::shader::error::Error
+    },
+    #[error("shader pack is no longer available")]
+    Closed,
+    #[error("{0}")]
+    IdCollision(IdCollision),
+    #[error("sal error: {0}")]
+    Sal(crate::targets::basic::sal_compiler::Error),
+    #[error("bpx core error: {0}")]
+    Core(bpx::core::error::Error),
+    #[error("bpx section error: {0}")]
+    SectionOpen(bpx::core::error::OpenError),
+    #[error("io error: {0}")]
+    Io(std::io::Error),
+    #[error("stage error: {0}")]
+    Stage(crate::stage::Error)
 }
 
-fn build_blendfunc_lookup_map(blendfuncs: Vec<BlendfuncStatement>) -> HashMap<String, BlendfuncObject>
+pub(super) fn build_blendfunc_lookup_map(blendfuncs: Vec<BlendfuncStatement>) -> HashMap<String, BlendfuncObject>
 {
     let mut map = HashMap::new();
     for fnc in blendfuncs {
@@ -72,52 +107,219 @@ fn build_blendfunc_lookup_map(blendfuncs: Vec<BlendfuncStatement>) -> HashMap<St
     map
 }
 
+fn build_sampler_object(state: SamplerStateStatement) -> SamplerObject
+{
+    SamplerObject {
+        min_filter: state.min_filter,
+        mag_filter: state.mag_filter,
+        address_u: state.address_u,
+        address_v: state.address_v,
+        address_w: state.address_w,
+        anisotropy: state.anisotropy,
+        comparison: false
+    }
+}
+
+pub(super) fn build_samplerstate_lookup_map(samplerstates: Vec<SamplerStateStatement>) -> HashMap<String, SamplerObject>
+{
+    let mut map = HashMap::new();
+    for state in samplerstates {
+        map.insert(state.name.clone(), build_sampler_object(state));
+    }
+    map
+}
+
+/// Returns the extended data of a sampler: comparison samplers always record their kind, with the
+/// default sampler state if no samplerstate block was declared for them.
+pub(super) fn build_sampler_state(states: &mut HashMap<String, SamplerObject>, sampler: &Property<usize>) -> Option<SamplerObject>
+{
+    let state = states.remove(&sampler.pname);
+    match sampler.ptype {
+        PropertyType::SamplerCmp => Some(SamplerObject {
+            comparison: true,
+            ..state.unwrap_or_else(|| build_sampler_object(SamplerStateStatement::new(sampler.pname.clone())))
+        }),
+        _ => state
+    }
+}
+
+fn build_varying_object(p: Property<usize>) -> Result<VaryingObject, Error>
+{
+    Ok(VaryingObject {
+        ty: match p.ptype {
+            PropertyType::Scalar(v) => VaryingPropType::Scalar(v),
+            PropertyType::Vector(v) => VaryingPropType::Vector(v),
+            PropertyType::Matrix(v) => VaryingPropType::Matrix(v),
+            s => {
+                error!(target: "write", "Requested type '{}' for a varying which isn't supported in OpenGL", s);
+                return Err(Error::IllegalVarying);
+            }
+        },
+        interpolation: p.pattr.as_ref().and_then(|v| v.get_interpolation()).unwrap_or(Interpolation::Smooth),
+        name: p.pname
+    })
+}
+
+/// Sizes in bytes of the parts of a written package.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WriteStats
+{
+    /// Extended data of the symbols.
+    pub symbols_bytes: usize,
+    /// Code of each stage, before compression.
+    pub per_stage_bytes: BTreeMap<crate::Stage, usize>,
+    /// Size of the package file.
+    pub total_bytes: usize
+}
+
+impl WriteStats
+{
+    /// Returns the size of everything else in the package: headers, tables, resources, varyings
+    /// and the savings of compressed sections.
+    pub fn other_bytes(&self) -> usize
+    {
+        self.total_bytes.saturating_sub(self.symbols_bytes + self.per_stage_bytes.values().sum::<usize>())
+    }
+}
+
+/// Size of the buffer shaders are copied through.
+const COPY_BUFFER_SIZE: usize = 8192;
+
 pub struct BpxWriter
 {
     debug: bool,
+    vertex_input: bool,
+    max_ext_data_size: usize,
+    ext_data_size: Cell<usize>,
+    resources: Vec<Resource>,
+    varyings: Vec<VaryingObject>,
+    payload: Option<PayloadKind>,
+    debug_info: Option<DebugInfo>,
+    warnings: Reporter,
+    compression: CompressionPreference,
+    per_stage_bytes: BTreeMap<crate::Stage, usize>,
+    file: File,
     bpx: Option<ShaderPack<BufWriter<File>>>
 }
 
 impl BpxWriter {
-    pub fn new(file: File, target: shader::Target, debug: bool) -> BpxWriter {
+    pub fn new(file: File, target: shader::Target, debug: bool, vertex_input: bool, max_ext_data_size: usize, warnings: Reporter) -> Result<BpxWriter, Error> {
+        //Keep a handle on the file to measure the package once written.
+        let handle = file.try_clone().map_err(Error::Io)?;
         let bpx = ShaderPack::create(BufWriter::new(file), shader::Builder::new()
             .ty(Type::Pipeline)
             .target(target));
-        BpxWriter {
+        Ok(BpxWriter {
             debug,
+            vertex_input,
+            max_ext_data_size,
+            ext_data_size: Cell::new(0),
+            resources: Vec::new(),
+            varyings: Vec::new(),
+            payload: None,
+            debug_info: None,
+            warnings,
+            compression: CompressionPreference::None,
+            per_stage_bytes: BTreeMap::new(),
+            file: handle,
             bpx: Some(bpx)
+        })
+    }
+
+    /// Sets the compression of the resource and varying sections; shader and symbol sections are
+    /// managed by the BPX shader pack itself.
+    pub fn set_compression(&mut self, compression: CompressionPreference) {
+        self.compression = compression;
+    }
+
+    /// Records the kind of code written in the shader stages, for payloads which differ from the
+    /// default of the BPX target.
+    pub fn set_payload(&mut self, payload: PayloadKind) {
+        self.payload = Some(payload);
+    }
+
+    /// Embeds the sources and settings of a debug build; packs without debug information are left
+    /// untouched.
+    pub fn set_debug_info(&mut self, info: DebugInfo) {
+        self.debug_info = Some(info);
+    }
+
+    fn section_header(&self, ty: u8) -> SectionHeaderBuilder {
+        let builder = SectionHeaderBuilder::new().ty(ty);
+        match self.compression {
+            CompressionPreference::None => builder,
+            CompressionPreference::Fast => builder.compression(CompressionMethod::Zlib),
+            CompressionPreference::Best => builder.compression(CompressionMethod::Xz)
+        }
+    }
+
+    fn extended_data<O: ToObject<T>, T>(&self, name: &str, obj: O, ctx: &O::Context) -> Result<(bpx::sd::Value, usize), Error>
+    {
+        let obj = match obj.to_object(ctx) {
+            None => return Ok((bpx::sd::Value::Null, 0)),
+            Some(v) => v
+        };
+        //Measure before encoding so that oversized symbols are reported before reaching bpx.
+        let size = bp3d_symbols::encoded_ext_data_size(&obj, self.debug).map_err(|e| Error::Size(name.into(), e))? + bp3d_symbols::ID_SIZE;
+        if size > self.max_ext_data_size {
+            return Err(Error::ExtDataTooLarge {
+                name: name.into(),
+                size,
+                max: self.max_ext_data_size
+            });
         }
+        let mut value = obj.to_bpx(self.debug).map_err(|e| Error::Serde(name.into(), e))?;
+        bp3d_symbols::set_symbol_id(&mut value, SymbolId::from_name(name));
+        self.ext_data_size.set(self.ext_data_size.get() + size);
+        Ok((value, size))
     }
 
-    fn write_objects(&self, bpx: &mut SymbolWriter<BufWriter<File>>, objects: Vec<Object<Property<usize>>>) -> Result<(), Error>
+    fn write(&self, bpx: &mut SymbolWriter<BufWriter<File>>, name: &str, size: usize, builder: shader::symbol::Builder) -> Result<(), Error>
     {
+        bpx.check_id(name).map_err(Error::IdCollision)?;
+        bpx.write(builder).map_err(|error| Error::Symbol {
+            name: name.into(),
+            size,
+            error
+        })
+    }
+
+    fn write_objects(&self, bpx: &mut SymbolWriter<BufWriter<File>>, objects: Vec<Object<Property<usize>>>, samplerstates: Vec<SamplerStateStatement>) -> Result<(), Error>
+    {
+        let mut states = build_samplerstate_lookup_map(samplerstates);
         for sym in objects {
-            let mut builder = shader::symbol::Builder::new(sym.inner.inner.pname);
+            let name = sym.inner.inner.pname.clone();
+            let mut builder = shader::symbol::Builder::new(name.clone());
             let slot = sym.inner.slot.get();
             if slot > 32 {
-                error!("OpenGL limits texture/sampler bindings to 32, got a binding at register {}", slot);
+                error!(target: "write", "OpenGL limits texture/sampler bindings to 32, got a binding at register {}", slot);
                 return Err(Error::UnsupportedBinding);
             } else if slot > 16 {
-                warn!("This shader needs more than 16 bindings, this may not work on all hardware");
+                self.warnings.report(WarningKind::BindingLimit, "This shader needs more than 16 bindings, this may not work on all hardware");
             }
             builder.register(slot as _);
             match sym.inner.inner.ptype {
-                PropertyType::Sampler => builder.ty(shader::symbol::Type::Sampler),
-                PropertyType::Texture2D(_) | PropertyType::Texture3D(_) | PropertyType::Texture2DArray(_)
-                | PropertyType::TextureCube(_) => builder.ty(shader::symbol::Type::Texture),
+                PropertyType::Sampler | PropertyType::SamplerCmp => builder.ty(shader::symbol::Type::Sampler),
+                PropertyType::Texture1D(_) | PropertyType::Texture2D(_) | PropertyType::Texture3D(_) | PropertyType::Texture2DArray(_)
+                | PropertyType::TextureCube(_) | PropertyType::Texture2DMS(..) => builder.ty(shader::symbol::Type::Texture),
+                PropertyType::Array(ref a) if a.item.is_texture() => builder.ty(shader::symbol::Type::Texture),
                 p => {
-                    error!("Unsupported object type: {}", p);
+                    error!(target: "write", "Unsupported object type: {}", p);
                     return Err(Error::IllegalObject);
                 }
             };
-            builder.extended_data(sym.inner.inner.ptype.to_bpx_object(self.debug, &()).map_err(Error::Serde)?);
+            let (data, size) = match build_sampler_state(&mut states, &sym.inner.inner) {
+                Some(state) => self.extended_data(&name, state, &())?,
+                None => self.extended_data(&name, sym.inner.inner.ptype, &())?
+            };
+            builder.extended_data(data);
             if sym.inner.external.get() {
                 builder.external(); //Global binding (goes in the global descriptor set)
             } else {
                 builder.internal(); //Local binding (goes in the local descriptor set)
             }
             crate::targets::gl::ext_data::append_stages!(sym > builder);
-            bpx.write(builder).map_err(Error::Bpx)?;
+            self.write(bpx, &name, size, builder)?;
         }
         Ok(())
     }
@@ -125,16 +327,18 @@ impl BpxWriter {
     fn write_packed_structs(&self, bpx: &mut SymbolWriter<BufWriter<File>>, structs: &Vec<Slot<StructOffset>>) -> Result<(), Error>
     {
         for sym in structs {
-            let mut builder = shader::symbol::Builder::new(sym.inner.name.clone());
+            let name = &sym.inner.name;
+            let (data, size) = self.extended_data(name, &sym.inner, &(&*bpx, structs))?;
+            let mut builder = shader::symbol::Builder::new(name.clone());
             builder
                 .ty(shader::symbol::Type::ConstantBuffer)
-                .extended_data(sym.inner.to_bpx_object(self.debug, &(bpx, structs)).map_err(Error::Serde)?);
+                .extended_data(data);
             if sym.external.get() {
                 builder.external();
             } else {
                 builder.internal();
             }
-            bpx.write(builder).map_err(Error::Bpx)?;
+            self.write(bpx, &name, size, builder)?;
         }
         Ok(())
     }
@@ -142,27 +346,27 @@ impl BpxWriter {
     fn write_cbuffers(&self, bpx: &mut SymbolWriter<BufWriter<File>>, objects: Vec<Object<StructOffset>>, packed_structs: &Vec<Slot<StructOffset>>) -> Result<(), Error>
     {
         for sym in objects {
-            //Unfortunately we must clone because rust is unable to see that sym.inner.inner.name is
-            // not used by to_bpx_object...
-            let mut builder = shader::symbol::Builder::new(sym.inner.inner.name.clone());
+            let name = &sym.inner.inner.name;
+            let mut builder = shader::symbol::Builder::new(name.clone());
             let slot = sym.inner.slot.get();
             if slot > 32 {
-                error!("OpenGL limits texture/sampler bindings to 32, got a binding at register {}", slot);
+                error!(target: "write", "OpenGL limits texture/sampler bindings to 32, got a binding at register {}", slot);
                 return Err(Error::UnsupportedBinding);
             } else if slot > 16 {
-                warn!("This shader needs more than 16 bindings, this may not work on all hardware");
+                self.warnings.report(WarningKind::BindingLimit, "This shader needs more than 16 bindings, this may not work on all hardware");
             }
+            let (data, size) = self.extended_data(name, &sym.inner.inner, &(&*bpx, packed_structs))?;
             builder
                 .register(slot as _)
                 .ty(shader::symbol::Type::ConstantBuffer)
-                .extended_data(sym.inner.inner.to_bpx_object(self.debug, &(bpx, packed_structs)).map_err(Error::Serde)?);
+                .extended_data(data);
             if sym.inner.external.get() {
                 builder.external();
             } else {
                 builder.internal();
             }
             crate::targets::gl::ext_data::append_stages!(sym > builder);
-            bpx.write(builder).map_err(Error::Bpx)?;
+            self.write(bpx, &name, size, builder)?;
         }
         Ok(())
     }
@@ -170,16 +374,18 @@ impl BpxWriter {
     fn write_vformat(&self, bpx: &mut SymbolWriter<BufWriter<File>>, vformat: Option<Struct<usize>>) -> Result<(), Error>
     {
         if let Some(sym) = vformat {
-            //Unfortunately we must clone because rust is unable to see that sym.name is
-            // not used by to_bpx_object...
-            let mut builder = shader::symbol::Builder::new(sym.name.clone());
+            let name = sym.name.clone();
+            let semantics = vformat_semantics(&sym).map_err(Error::Sal)?;
+            let locations = vformat_locations(&sym);
+            let (data, size) = self.extended_data(&name, sym, &(semantics, locations))?;
+            let mut builder = shader::symbol::Builder::new(name.clone());
             builder
                 .external()
                 .ty(shader::symbol::Type::VertexFormat)
-                .extended_data(sym.to_bpx_object(self.debug, &()).map_err(Error::Serde)?);
-            bpx.write(builder).map_err(Error::Bpx)?;
-        } else {
-            warn!("No vertex format was found in shader pack build");
+                .extended_data(data);
+            self.write(bpx, &name, size, builder)?;
+        } else if self.vertex_input {
+            self.warnings.report(WarningKind::Missing, "No vertex format was found in shader pack build");
         }
         Ok(())
     }
@@ -187,16 +393,18 @@ impl BpxWriter {
     fn write_pipeline(&self, bpx: &mut SymbolWriter<BufWriter<File>>, pipeline: Option<PipelineStatement>) -> Result<(), Error>
     {
         if let Some(sym) = pipeline {
-            //Unfortunately we must clone because rust is unable to see that sym.name is
-            // not used by to_bpx_object...
-            let mut builder = shader::symbol::Builder::new(sym.name.clone());
+            let name = sym.name.clone();
+            let (data, size) = self.extended_data(&name, sym, &self.vertex_input)?;
+            let mut builder = shader::symbol::Builder::new(name.clone());
             builder
                 .internal()
                 .ty(shader::symbol::Type::Pipeline)
-                .extended_data(sym.to_bpx_object(self.debug, &()).map_err(Error::Serde)?);
-            bpx.write(builder).map_err(Error::Bpx)?;
+                .extended_data(data);
+            self.write(bpx, &name, size, builder)?;
+        } else if !self.vertex_input {
+            self.warnings.report(WarningKind::Missing, "No pipeline was found in shader pack build, vertex pulling will not be recorded in the pack");
         } else {
-            warn!("No pipeline was found in shader pack build");
+            self.warnings.report(WarningKind::Missing, "No pipeline was found in shader pack build");
         }
         Ok(())
     }
@@ -204,29 +412,35 @@ impl BpxWriter {
     fn write_outputs(&self, bpx: &mut SymbolWriter<BufWriter<File>>, outputs: Vec<Slot<Property<usize>>>, blendfuncs: Vec<BlendfuncStatement>) -> Result<(), Error>
     {
         if outputs.len() <= 0 {
-            warn!("No render target outputs was found in shader pack build");
+            self.warnings.report(WarningKind::Missing, "No render target outputs was found in shader pack build");
             return Ok(());
         }
-        let funcs = build_blendfunc_lookup_map(blendfuncs);
-        for sym in outputs {
+        let mut funcs = build_blendfunc_lookup_map(blendfuncs);
+        let semantics = output_semantics(&outputs).map_err(Error::Sal)?;
+        for (sym, semantic) in outputs.into_iter().zip(semantics) {
             let output = OutputObject {
-                blendfunc: funcs.get(&sym.inner.pname).map(|v| v.clone()),
+                blendfunc: funcs.remove(&sym.inner.pname),
                 ty: match sym.inner.ptype {
                     PropertyType::Scalar(v) => OutputPropType::Scalar(v),
                     PropertyType::Vector(v) => OutputPropType::Vector(v),
                     s => {
-                        error!("Requested type '{}' for a render target which isn't supported in OpenGL", s);
+                        error!(target: "write", "Requested type '{}' for a render target which isn't supported in OpenGL", s);
                         return Err(Error::IllegalOutput);
                     }
-                }
+                },
+                deprecated: sym.inner.deprecated,
+                semantic: Some(semantic),
+                format: sym.inner.pformat
             };
-            let mut builder = shader::symbol::Builder::new(sym.inner.pname);
+            let name = sym.inner.pname;
+            let (data, size) = self.extended_data(&name, output, &())?;
+            let mut builder = shader::symbol::Builder::new(name.clone());
             builder
                 .internal()
                 .ty(shader::symbol::Type::Output)
                 .register(sym.slot.get() as _)
-                .extended_data(output.to_bpx_object(self.debug, &()).map_err(Error::Serde)?);
-            bpx.write(builder).map_err(Error::Bpx)?;
+                .extended_data(data);
+            self.write(bpx, &name, size, builder)?;
         }
         Ok(())
     }
@@ -234,7 +448,8 @@ impl BpxWriter {
     fn write_root_constants(&self, bpx: &mut SymbolWriter<BufWriter<File>>, root_constants_layout: StructOffset) -> Result<(), Error>
     {
         for sym in root_constants_layout.props {
-            let mut builder = shader::symbol::Builder::new(sym.inner.pname);
+            let name = sym.inner.pname;
+            let mut builder = shader::symbol::Builder::new(name.clone());
             builder.ty(shader::symbol::Type::Constant).external();
             let obj = ConstantObject {
                 size: sym.size as _,
@@ -244,19 +459,21 @@ impl BpxWriter {
                     PropertyType::Vector(v) => ConstPropType::Vector(v),
                     PropertyType::Matrix(v) => ConstPropType::Matrix(v),
                     s => {
-                        error!("Requested type '{}' for a constant which isn't supported in OpenGL", s);
+                        error!(target: "write", "Requested type '{}' for a constant which isn't supported in OpenGL", s);
                         return Err(Error::IllegalConstant);
                     }
-                }
+                },
+                deprecated: sym.inner.deprecated
             };
-            builder.extended_data(obj.to_bpx_object(self.debug, &()).map_err(Error::Serde)?);
-            bpx.write(builder).map_err(Error::Bpx)?;
+            let (data, size) = self.extended_data(&name, obj, &())?;
+            builder.extended_data(data);
+            self.write(bpx, &name, size, builder)?;
         }
         Ok(())
     }
 
     fn propagate_external_flag(&self, cbuffers: &Vec<Object<StructOffset>>, packed_structs: &Vec<Slot<StructOffset>>) {
-        info!("Applying external flags on symbol references...");
+        info!(target: "write", "Applying external flags on symbol references...");
         for cbuffer in cbuffers {
             for p in &cbuffer.inner.inner.props {
                 match p.ptype {
@@ -264,7 +481,7 @@ impl BpxWriter {
                         let st = &packed_structs[v];
                         if cbuffer.inner.external.get() {
                             st.external.set(true);
-                            debug!("Set external flag for '{}'", st.inner.name);
+                            debug!(target: "write", "Set external flag for '{}'", st.inner.name);
                         }
                     },
                     PropertyType::Array(v) => {
@@ -273,7 +490,7 @@ impl BpxWriter {
                                 let st = &packed_structs[v];
                                 if cbuffer.inner.external.get() {
                                     st.external.set(true);
-                                    debug!("Set external flag for '{}'", st.inner.name);
+                                    debug!(target: "write", "Set external flag for '{}'", st.inner.name);
                                 }
                             },
                             _ => ()
@@ -285,40 +502,296 @@ impl BpxWriter {
         }
     }
 
-    pub fn write_symbols(&mut self, syms: Symbols) -> Result<(), Error> {
-        //The unwrap should be fine because bpx is initialized in new.
-        // This unwrap may panic if write_symbols panics before putting bpx back.
+    fn write_all_symbols(&self, writer: &mut SymbolWriter<BufWriter<File>>, syms: Symbols) -> Result<(), Error> {
         let packed_structs = syms.packed_structs.into_iter().map(Slot::new).collect();
         self.propagate_external_flag(&syms.cbuffers, &packed_structs);
-        let mut writer = SymbolWriter::new(self.bpx.take().unwrap());
-        self.write_objects(&mut writer, syms.objects)?;
-        self.write_packed_structs(&mut writer, &packed_structs)?;
-        self.write_cbuffers(&mut writer, syms.cbuffers, &packed_structs)?;
-        self.write_vformat(&mut writer, syms.vformat)?;
-        self.write_pipeline(&mut writer, syms.pipeline)?;
-        self.write_outputs(&mut writer, syms.outputs, syms.blendfuncs)?;
-        self.write_root_constants(&mut writer, syms.root_constant_layout)?;
+        self.write_objects(writer, syms.objects, syms.samplerstates)?;
+        self.write_packed_structs(writer, &packed_structs)?;
+        self.write_cbuffers(writer, syms.cbuffers, &packed_structs)?;
+        self.write_vformat(writer, syms.vformat)?;
+        self.write_pipeline(writer, syms.pipeline)?;
+        self.write_outputs(writer, syms.outputs, syms.blendfuncs)?;
+        self.write_root_constants(writer, syms.root_constant_layout)?;
+        Ok(())
+    }
+
+    pub fn write_symbols(&mut self, mut syms: Symbols) -> Result<(), Error> {
+        self.resources = std::mem::take(&mut syms.resources);
+        self.varyings = std::mem::take(&mut syms.varyings).into_iter().map(build_varying_object).collect::<Result<_, _>>()?;
+        let mut writer = SymbolWriter::new(self.bpx.take().ok_or(Error::Closed)?);
+        let res = self.write_all_symbols(&mut writer, syms);
+        //Always put the pack back, even on failure, so that the writer stays usable.
+        let count = writer.symbol_count();
         self.bpx = Some(writer.into_inner());
+        res?;
+        info!(target: "write", "Wrote {} symbol(s) with {} byte(s) of extended data", count, self.ext_data_size.get());
+        if !self.resources.is_empty() {
+            let size: usize = self.resources.iter().map(|v| v.data.len()).sum();
+            info!(target: "write", "Embedding {} resource(s) totaling {} byte(s)", self.resources.len(), size);
+        }
         Ok(())
     }
 
+    /// Returns the total size in bytes of the extended data written so far.
+    pub fn ext_data_size(&self) -> usize {
+        self.ext_data_size.get()
+    }
+
     pub fn write_shaders(&mut self, shaders: Vec<ShaderBytes>) -> Result<(), Error> {
-        //The unwrap should be fine because bpx is initialized in new.
-        // This unwrap may panic if write_symbols panics before putting bpx back.
-        let mut tbl = self.bpx.as_mut().unwrap().shaders_mut();
         for stage in shaders {
-            tbl.create(shader::Shader {
-                stage: stage.stage,
-                data: stage.data
-            }).map_err(Error::Bpx)?;
+            self.write_shader_streamed(stage.stage, stage.data.as_slice(), stage.data.len())?;
         }
         Ok(())
     }
 
-    pub fn save(&mut self) -> Result<(), Error> {
-        //The unwrap should be fine because bpx is initialized in new.
-        // This unwrap may panic if write_symbols panics before putting bpx back.
-        self.bpx.as_mut().unwrap().save().map_err(Error::Bpx)?;
+    /// Writes the code of a stage of `len` bytes read from `reader` through a fixed size buffer.
+    pub fn write_shader_streamed<R: Read>(&mut self, stage: crate::Stage, mut reader: R, len: usize) -> Result<(), Error> {
+        let mut buffer = [0; COPY_BUFFER_SIZE];
+        let mut data = Vec::with_capacity(len);
+        while data.len() < len {
+            let size = (len - data.len()).min(COPY_BUFFER_SIZE);
+            reader.read_exact(&mut buffer[..size]).map_err(Error::Io)?;
+            data.extend_from_slice(&buffer[..size]);
+        }
+        self.bpx.as_mut().ok_or(Error::Closed)?.shaders_mut().create(shader::Shader {
+            stage: stage.to_bpx().map_err(Error::Stage)?,
+            data
+        }).map_err(Error::Bpx)?;
+        *self.per_stage_bytes.entry(stage).or_default() += len;
         Ok(())
     }
+
+    /// Saves the package and returns its sizes; the writer is closed afterwards.
+    pub fn save(&mut self) -> Result<WriteStats, Error> {
+        let mut pack = self.bpx.take().ok_or(Error::Closed)?;
+        pack.save().map_err(Error::Bpx)?;
+        if self.resources.is_empty() && self.varyings.is_empty() && self.payload.is_none() && self.debug_info.is_none() {
+            drop(pack);
+            return self.stats();
+        }
+        //Resources, varyings, the payload kind and debug information do not fit in the symbol table,
+        //they go in their own sections.
+        let mut inner = pack.into_inner();
+        if !self.resources.is_empty() {
+            let handle = inner.sections_mut().create(self.section_header(bp3d_symbols::RESOURCE_SECTION_TYPE));
+            let mut writer = inner.sections().open(handle).map_err(Error::SectionOpen)?;
+            writer.write_all(&bp3d_symbols::encode_resources(&self.resources)).map_err(Error::Io)?;
+        }
+        if !self.varyings.is_empty() {
+            let handle = inner.sections_mut().create(self.section_header(bp3d_symbols::VARYING_SECTION_TYPE));
+            let mut writer = inner.sections().open(handle).map_err(Error::SectionOpen)?;
+            writer.write_all(&bp3d_symbols::encode_varyings(&self.varyings)).map_err(Error::Io)?;
+        }
+        if let Some(payload) = self.payload {
+            let handle = inner.sections_mut().create(self.section_header(bp3d_symbols::PAYLOAD_SECTION_TYPE));
+            let mut writer = inner.sections().open(handle).map_err(Error::SectionOpen)?;
+            writer.write_all(&payload.encode()).map_err(Error::Io)?;
+        }
+        if let Some(info) = &self.debug_info {
+            let handle = inner.sections_mut().create(self.section_header(bp3d_symbols::DEBUG_INFO_SECTION_TYPE));
+            let mut writer = inner.sections().open(handle).map_err(Error::SectionOpen)?;
+            writer.write_all(&info.encode()).map_err(Error::Io)?;
+        }
+        inner.save().map_err(Error::Core)?;
+        //Dropping the container flushes its buffer to the file.
+        drop(inner);
+        self.stats()
+    }
+
+    fn stats(&self) -> Result<WriteStats, Error> {
+        Ok(WriteStats {
+            symbols_bytes: self.ext_data_size.get(),
+            per_stage_bytes: self.per_stage_bytes.clone(),
+            total_bytes: self.file.metadata().map_err(Error::Io)?.len() as usize
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::fs::File;
+    use std::io::{BufReader, Read};
+    use bp3d_sal::ast::tree::{Attribute, BaseType, Interpolation, Property, PropertyType, Struct, VectorType};
+    use bp3d_symbols::{decode_resources, decode_varyings, Resource, RESOURCE_SECTION_TYPE, symbol_id, SymbolId, VARYING_SECTION_TYPE, VaryingPropType};
+    use bpx::shader;
+    use bpx::shader::{ShaderPack, Target};
+    use crate::{CompressionPreference, DEFAULT_MAX_EXT_DATA_SIZE, Stage};
+    use crate::targets::gl::bpx::{BpxWriter, build_varying_object, Error};
+    use crate::targets::gl::ext_data::SymbolWriter;
+    use crate::warnings::Reporter;
+
+    fn vformat(members: usize) -> Struct<usize>
+    {
+        Struct {
+            name: "Huge".into(),
+            attr: None,
+            deprecated: None,
+            props: (0..members).map(|i| Property {
+                ptype: PropertyType::Scalar(BaseType::Float),
+                pname: format!("Member{}", i),
+                pattr: None,
+                deprecated: None,
+                pformat: None,
+                default: None,
+                span: Default::default()
+            }).collect(),
+            span: Default::default()
+        }
+    }
+
+    #[test]
+    fn ext_data_too_large()
+    {
+        let dir = std::env::temp_dir().join(format!("shaderc-bpx-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut bpx = BpxWriter::new(File::create(dir.join("huge.bpx")).unwrap(), Target::GL40, false, true, DEFAULT_MAX_EXT_DATA_SIZE, Reporter::new()).unwrap();
+        let mut writer = SymbolWriter::new(bpx.bpx.take().unwrap());
+        let err = bpx.write_vformat(&mut writer, Some(vformat(4096))).unwrap_err();
+        assert!(err.to_string().contains("consider splitting the struct"));
+        match err {
+            Error::ExtDataTooLarge { name, size, max } => {
+                assert_eq!(name, "Huge");
+                assert!(size > max);
+                assert_eq!(max, DEFAULT_MAX_EXT_DATA_SIZE);
+            },
+            e => panic!("unexpected error: {}", e)
+        }
+        assert_eq!(bpx.ext_data_size(), 0);
+        bpx.write_vformat(&mut writer, Some(vformat(16))).unwrap();
+        assert!(bpx.ext_data_size() > 0);
+        assert_eq!(writer.symbol_count(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn resources_section()
+    {
+        let dir = std::env::temp_dir().join(format!("shaderc-bpx-res-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("res.bpx");
+        let resources = vec![Resource { name: "CurveLut".into(), data: (0..=255).collect() }];
+        let mut bpx = BpxWriter::new(File::create(&path).unwrap(), Target::GL40, false, true, DEFAULT_MAX_EXT_DATA_SIZE, Reporter::new()).unwrap();
+        bpx.resources = resources.clone();
+        bpx.save().unwrap();
+        drop(bpx);
+        let container = ShaderPack::open(BufReader::new(File::open(&path).unwrap())).unwrap().into_inner();
+        let handle = container.sections().find_by_type(RESOURCE_SECTION_TYPE).unwrap();
+        let mut data = Vec::new();
+        container.sections().load(handle).unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(decode_resources(&data).unwrap(), resources);
+        assert!(container.sections().find_by_type(VARYING_SECTION_TYPE).is_none());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn compression_and_stats()
+    {
+        let dir = std::env::temp_dir().join(format!("shaderc-bpx-stats-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        //A synthetic shader and resource padded to compress well.
+        let mut code = b"void main() {}\n".to_vec();
+        code.resize(64 * 1024, b' ');
+        let write = |compression: CompressionPreference| {
+            let path = dir.join(format!("{:?}.bpx", compression));
+            let mut bpx = BpxWriter::new(File::create(&path).unwrap(), Target::GL40, false, true, DEFAULT_MAX_EXT_DATA_SIZE, Reporter::new()).unwrap();
+            bpx.set_compression(compression);
+            bpx.resources = vec![Resource { name: "Padding".into(), data: vec![0; 64 * 1024] }];
+            bpx.write_shader_streamed(Stage::Vertex, code.as_slice(), code.len()).unwrap();
+            bpx.write_shader_streamed(Stage::Pixel, code.as_slice(), 100).unwrap();
+            let stats = bpx.save().unwrap();
+            assert!(matches!(bpx.save(), Err(Error::Closed)));
+            let size = std::fs::metadata(&path).unwrap().len() as usize;
+            assert_eq!(stats.total_bytes, size);
+            assert_eq!(stats.per_stage_bytes[&Stage::Vertex], code.len());
+            assert_eq!(stats.per_stage_bytes[&Stage::Pixel], 100);
+            assert_eq!(stats.symbols_bytes + stats.per_stage_bytes.values().sum::<usize>() + stats.other_bytes(), size);
+            size
+        };
+        assert!(write(CompressionPreference::Best) <= write(CompressionPreference::None));
+        assert!(write(CompressionPreference::Fast) <= write(CompressionPreference::None));
+        //Readers shorter than the announced length are rejected.
+        let mut bpx = BpxWriter::new(File::create(dir.join("short.bpx")).unwrap(), Target::GL40, false, true, DEFAULT_MAX_EXT_DATA_SIZE, Reporter::new()).unwrap();
+        assert!(matches!(bpx.write_shader_streamed(Stage::Vertex, &b"void main() {}"[..], 1024), Err(Error::Io(_))));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn varyings_section()
+    {
+        let dir = std::env::temp_dir().join(format!("shaderc-bpx-varyings-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("varyings.bpx");
+        let mut bpx = BpxWriter::new(File::create(&path).unwrap(), Target::GL40, false, true, DEFAULT_MAX_EXT_DATA_SIZE, Reporter::new()).unwrap();
+        bpx.varyings = vec![
+            build_varying_object(Property {
+                ptype: PropertyType::Vector(VectorType { item: BaseType::Float, size: 2 }),
+                pname: "Uv".into(),
+                pattr: None,
+                deprecated: None,
+                pformat: None,
+                default: None,
+                span: Default::default()
+            }).unwrap(),
+            build_varying_object(Property {
+                ptype: PropertyType::Scalar(BaseType::Uint),
+                pname: "Layer".into(),
+                pattr: Some(Attribute::Interpolation(Interpolation::Flat)),
+                deprecated: None,
+                pformat: None,
+                default: None,
+                span: Default::default()
+            }).unwrap()
+        ];
+        bpx.save().unwrap();
+        drop(bpx);
+        let container = ShaderPack::open(BufReader::new(File::open(&path).unwrap())).unwrap().into_inner();
+        assert!(container.sections().find_by_type(RESOURCE_SECTION_TYPE).is_none());
+        let handle = container.sections().find_by_type(VARYING_SECTION_TYPE).unwrap();
+        let mut data = Vec::new();
+        container.sections().load(handle).unwrap().read_to_end(&mut data).unwrap();
+        let varyings = decode_varyings(&data).unwrap();
+        assert_eq!(varyings.len(), 2);
+        assert_eq!(varyings[0].interpolation, Interpolation::Smooth);
+        assert_eq!(varyings[1].name, "Layer");
+        assert_eq!(varyings[1].ty, VaryingPropType::Scalar(BaseType::Uint));
+        assert_eq!(varyings[1].interpolation, Interpolation::Flat);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn symbol_ids()
+    {
+        let dir = std::env::temp_dir().join(format!("shaderc-bpx-ids-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("ids.bpx");
+        let mut bpx = BpxWriter::new(File::create(&path).unwrap(), Target::GL40, false, true, DEFAULT_MAX_EXT_DATA_SIZE, Reporter::new()).unwrap();
+        let mut writer = SymbolWriter::new(bpx.bpx.take().unwrap());
+        bpx.write_vformat(&mut writer, Some(vformat(4))).unwrap();
+        //"costarring" and "liquid" have the same FNV-1a hash.
+        let constant = |name: &str| {
+            let mut builder = shader::symbol::Builder::new(name);
+            builder.ty(shader::symbol::Type::Constant);
+            builder
+        };
+        bpx.write(&mut writer, "costarring", 0, constant("costarring")).unwrap();
+        match bpx.write(&mut writer, "liquid", 0, constant("liquid")).unwrap_err() {
+            Error::IdCollision(e) => {
+                assert_eq!(e.first, "costarring");
+                assert_eq!(e.second, "liquid");
+                assert!(e.to_string().contains("rename one of them"));
+            },
+            e => panic!("unexpected error: {}", e)
+        }
+        assert_eq!(writer.symbol_count(), 2);
+        bpx.bpx = Some(writer.into_inner());
+        bpx.save().unwrap();
+        drop(bpx);
+        let pack = ShaderPack::open(BufReader::new(File::open(&path).unwrap())).unwrap();
+        let symbols = pack.symbols().unwrap();
+        let sym = symbols.iter().next().unwrap();
+        assert_eq!(symbols.load_name(sym).unwrap(), "Huge");
+        assert_eq!(symbol_id(symbols.load_extended_data(sym).unwrap()), Some(SymbolId::from_name("Huge")));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -0,0 +1,223 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Checking of the symbols of a GL build against an engine-provided symbol contract.
+
+use std::path::Path;
+use bp3d_sal::ast::tree::{ArrayItemType, PropertyType};
+use bp3d_symbols::{Contract, Member, SymbolInfo, SymbolKind};
+use log::error;
+use crate::targets::gl::core::Symbols;
+use crate::targets::layout::StructOffset;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error
+{
+    #[error("io error: {0}")]
+    Io(std::io::Error),
+    #[error("toml error: {0}")]
+    Toml(toml::de::Error),
+    #[error("json error: {0}")]
+    Json(serde_json::Error),
+    #[error("{} symbol contract requirement(s) not met:\n{}", .0.len(), .0.join("\n"))]
+    Unmet(Vec<String>)
+}
+
+/// Loads a symbol contract; files with a `.json` extension are read as JSON, anything else as TOML.
+pub fn load_contract(path: &Path) -> Result<Contract, Error>
+{
+    let data = std::fs::read_to_string(path).map_err(Error::Io)?;
+    match path.extension().and_then(|v| v.to_str()) {
+        Some("json") => serde_json::from_str(&data).map_err(Error::Json),
+        _ => toml::from_str(&data).map_err(Error::Toml)
+    }
+}
+
+pub(super) fn type_name(ty: &PropertyType<usize>, packed_structs: &[StructOffset]) -> String
+{
+    match ty {
+        PropertyType::StructRef(id) => packed_structs[*id].name.clone(),
+        PropertyType::Array(a) => match &a.item {
+            ArrayItemType::StructRef(id) => format!("{}[{}]", packed_structs[*id].name, a.size),
+            item => format!("{}[{}]", item, a.size)
+        },
+        ty => ty.to_string()
+    }
+}
+
+fn symbol(name: &str, kind: SymbolKind, slot: Option<u32>) -> SymbolInfo
+{
+    SymbolInfo {
+        name: name.into(),
+        kind,
+        slot,
+        layout: Vec::new()
+    }
+}
+
+pub fn collect_symbols(syms: &Symbols) -> Vec<SymbolInfo>
+{
+    let mut list = Vec::new();
+    for v in &syms.cbuffers {
+        let st = &v.inner.inner;
+        let mut sym = symbol(&st.name, SymbolKind::ConstantBuffer, Some(v.inner.slot.get()));
+        sym.layout = st.props.iter().map(|p| Member {
+            name: p.inner.pname.clone(),
+            ty: type_name(&p.inner.ptype, &syms.packed_structs),
+            offset: Some(p.aligned_offset as _)
+        }).collect();
+        list.push(sym);
+    }
+    for v in &syms.objects {
+        let prop = &v.inner.inner;
+        let kind = match prop.ptype {
+            PropertyType::Sampler | PropertyType::SamplerCmp => SymbolKind::Sampler,
+            _ => SymbolKind::Texture
+        };
+        list.push(symbol(&prop.pname, kind, Some(v.inner.slot.get())));
+    }
+    for v in &syms.outputs {
+        list.push(symbol(&v.inner.pname, SymbolKind::Output, Some(v.slot.get())));
+    }
+    for v in &syms.root_constant_layout.props {
+        list.push(symbol(&v.inner.pname, SymbolKind::Constant, None));
+    }
+    if let Some(v) = &syms.vformat {
+        let mut sym = symbol(&v.name, SymbolKind::VertexFormat, None);
+        sym.layout = v.props.iter().map(|p| Member {
+            name: p.pname.clone(),
+            ty: type_name(&p.ptype, &syms.packed_structs),
+            offset: None
+        }).collect();
+        list.push(sym);
+    }
+    if let Some(v) = &syms.pipeline {
+        list.push(symbol(&v.name, SymbolKind::Pipeline, None));
+    }
+    for v in &syms.resources {
+        list.push(symbol(&v.name, SymbolKind::Resource, None));
+    }
+    list
+}
+
+pub fn check_contract(contract: &Contract, syms: &Symbols) -> Result<(), Error>
+{
+    let violations: Vec<String> = contract.check(&collect_symbols(syms)).iter().map(|v| v.to_string()).collect();
+    if violations.is_empty() {
+        return Ok(());
+    }
+    for v in &violations {
+        error!(target: "write", "{}", v);
+    }
+    Err(Error::Unmet(violations))
+}
+
+#[cfg(test)]
+mod tests
+{
+    use bp3d_sal::ast::tree::{BaseType, Property, PropertyType, Struct, VectorType};
+    use bp3d_symbols::{Member, SymbolKind};
+    use crate::targets::basic::Slot;
+    use crate::targets::gl::contract::{check_contract, collect_symbols, Error, load_contract};
+    use crate::targets::gl::core::{Object, Symbols};
+    use crate::targets::layout::{compile_struct, LayoutRule};
+
+    fn prop(name: &str, ptype: PropertyType<usize>) -> Property<usize>
+    {
+        Property {
+            pname: name.into(),
+            ptype,
+            pattr: None,
+            deprecated: None,
+            pformat: None,
+            default: None,
+            span: Default::default()
+        }
+    }
+
+    fn symbols() -> Symbols
+    {
+        let per_frame = compile_struct(Struct {
+            name: "PerFrame".into(),
+            attr: None,
+            deprecated: None,
+            props: vec![
+                prop("ViewProj", PropertyType::Matrix(VectorType { item: BaseType::Float, size: 4 })),
+                prop("Time", PropertyType::Scalar(BaseType::Float))
+            ],
+            span: Default::default()
+        }, &Vec::new(), LayoutRule::Std140).unwrap();
+        let root = compile_struct(Struct {
+            name: "__Root".into(),
+            attr: None,
+            deprecated: None,
+            props: Vec::new(),
+            span: Default::default()
+        }, &Vec::new(), LayoutRule::Std140).unwrap();
+        let cbuffer = Slot::new(per_frame);
+        cbuffer.slot.set(1);
+        Symbols {
+            root_constant_layout: root,
+            packed_structs: Vec::new(),
+            cbuffers: vec![Object::new(cbuffer)],
+            outputs: vec![Slot::new(prop("FragColor", PropertyType::Vector(VectorType { item: BaseType::Float, size: 4 })))],
+            varyings: Vec::new(),
+            objects: Vec::new(),
+            pipeline: None,
+            vformat: None,
+            blendfuncs: Vec::new(),
+            samplerstates: Vec::new(),
+            resources: Vec::new()
+        }
+    }
+
+    #[test]
+    fn contract_check()
+    {
+        let dir = std::env::temp_dir().join(format!("shaderc-contract-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let toml = dir.join("contract.toml");
+        std::fs::write(&toml, "forbid = [\"Debug*\"]\n\n[[require]]\nname = \"PerFrame\"\nkind = \"constant_buffer\"\nslot = 1\nlayout = [{ name = \"ViewProj\", type = \"mat4f\", offset = 0 }, { name = \"Time\", type = \"float\", offset = 64 }]\n\n[[require]]\nname = \"FragColor\"\nkind = \"output\"\n").unwrap();
+        let json = dir.join("contract.json");
+        std::fs::write(&json, r#"{"require": [{"name": "PerFrame", "kind": "constant_buffer", "slot": 2}, {"name": "Lighting", "kind": "constant_buffer"}]}"#).unwrap();
+        let syms = symbols();
+        let list = collect_symbols(&syms);
+        assert_eq!(list[0].layout[1], Member { name: "Time".into(), ty: "float".into(), offset: Some(64) });
+        assert_eq!(list[1].kind, SymbolKind::Output);
+        assert!(check_contract(&load_contract(&toml).unwrap(), &syms).is_ok());
+        match check_contract(&load_contract(&json).unwrap(), &syms) {
+            Err(Error::Unmet(list)) => assert_eq!(list, [
+                "'PerFrame' must be at slot 2 but is at slot 1",
+                "missing required constant buffer 'Lighting'"
+            ]),
+            _ => panic!("expected unmet contract")
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
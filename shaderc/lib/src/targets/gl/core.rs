@@ -27,16 +27,31 @@
 // SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
 use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use bp3d_threads::{ScopedThreadManager, ThreadPool};
-use bpx::shader::Stage;
+use crate::Stage;
 use log::{debug, error, info, trace, warn};
-use rglslang::environment::{Client, Environment};
-use rglslang::shader::{Messages, Profile, Shader};
-use bp3d_sal::ast::tree::{BlendfuncStatement, PipelineStatement, Property, Struct};
+use rglslang::diagnostic::{parse_info_log, Diagnostic, Severity, Source};
+use rglslang::environment::{Client, ClientVersion, Environment, TargetVersion};
+use rglslang::program::ReflectionOptions;
+use rglslang::reflection::Reflection;
+use rglslang::shader::{Messages, Part, Profile, ResourceType, Shader, ShaderConfig};
+use rglslang::spirv::SpvBuilder;
+use bp3d_sal::ast::tree::{ArrayItemType, BlendfuncStatement, PipelineStatement, Property, PropertyType, SamplerStateStatement, Struct, TextureType};
+use bp3d_symbols::{DebugSource, DebugStage, Resource};
 use crate::config::Config;
-use crate::targets::basic::{get_root_constants_layout, ShaderStage, Slot};
-use crate::targets::layout140::{compile_packed_structs, compile_struct, StructOffset};
-use crate::targets::sal_to_glsl::translate_sal_to_glsl;
+use crate::repro::Sources;
+use crate::targets::basic::{BasicAst, get_root_constants_layout, ShaderStage, Slot};
+use crate::targets::basic::parts::{assemble_stage_parts, part_display_name, StageSource};
+use crate::targets::gl::GlTarget;
+use crate::targets::gl::duplicates::{SymbolDecl, SymbolTable};
+use crate::targets::gl::include::{expand_includes, resolve_include};
+use crate::targets::layout::{compile_packed_structs, compile_struct, LayoutRule, StructOffset};
+use crate::targets::gl::optimize::optimize_stages;
+use crate::targets::minify::{minify, rename_identifiers, strip_comments};
+use crate::targets::sal_to_glsl::{Bindings, SourceMap, translate_sal_to_glsl_mapped};
+use crate::warnings::{Reporter, WarningKind};
 use thiserror::Error;
 
 const MAX_CBUFFER_SIZE: usize = 65536;
@@ -48,21 +63,115 @@ pub enum Error {
     Compiler,
     #[error("GLSL link error")]
     Linker,
+    #[error("SPIR-V generation error")]
+    Spirv,
     #[error("sal error: {0}")]
     Sal(crate::targets::basic::sal_compiler::Error),
+    #[error("stage error: {0}")]
+    Stage(crate::stage::Error),
     #[error("sal-glsl transpiler error: {0}")]
     Transpiler(crate::targets::sal_to_glsl::Error),
     #[error("constant buffer size overload")]
     BufferSizeOverload,
-    #[error("layout140 compiler error: {0}")]
-    Layout140(crate::targets::layout140::Error)
+    #[error("root constants layout '{0}' takes {1} bytes after alignment, exceeding the limit of 128 bytes")]
+    RootConstantsSizeOverload(String, usize),
+    #[error("layout compiler error: {0}")]
+    Layout(crate::targets::layout::Error),
+    #[error("pipeline requests {0} viewport(s) but the target supports between 1 and {1}")]
+    ViewportCount(u32, u32),
+    #[error("layered rendering requires a geometry stage on this target")]
+    LayeredRendering,
+    #[error("64-bit integer types are not supported by this target")]
+    Int64,
+    #[error("optimization is not supported by Vulkan targets")]
+    Optimize,
+    #[error("{kind} '{name}' is declared differently by the {first_stage} stage (slot {}) and the {second_stage} stage (slot {}){}", display_binding(*.first_slot), display_binding(*.second_slot), .diff.iter().map(|v| format!("\n  {}", v)).collect::<String>())]
+    ConflictingSymbol
+    {
+        kind: &'static str,
+        name: String,
+        first_stage: Stage,
+        first_slot: Option<u32>,
+        second_stage: Stage,
+        second_slot: Option<u32>,
+        diff: Vec<String>
+    },
+    #[error("{} stage(s) failed to compile{}", .0.len(), .0.iter().map(|(stage, e)| format!("\n  {}: {}", stage, e)).collect::<String>())]
+    Stages(Vec<(Stage, Error)>)
 }
 
 pub struct EnvInfo
 {
     pub gl_version_str: &'static str,
     pub gl_version_int: i32,
-    pub explicit_bindings: bool
+    pub explicit_bindings: bool,
+    pub extensions: &'static [&'static str],
+    pub max_viewports: u32,
+    /// Extension allowing gl_Layer/gl_ViewportIndex to be written without a geometry stage.
+    pub layer_extension: Option<&'static str>,
+    /// Extension enabling 64-bit integer types, None if the target does not support them.
+    pub int64_extension: Option<&'static str>,
+    /// Compile for Vulkan: stages are written as SPIR-V instead of GLSL and resources are bound
+    /// through descriptor set 0.
+    pub vulkan: bool,
+    /// Compile for OpenGL with ARB_gl_spirv: stages are written as SPIR-V instead of GLSL but
+    /// resources are bound as for other OpenGL targets.
+    pub spirv: bool,
+    /// Compile for OpenGL ES: stages use the `es` profile and declare precision qualifiers.
+    pub es: bool,
+    /// Maximum number of uniform buffer bindings guaranteed by the API, None if the symbol writer
+    /// limits are enough.
+    pub max_cbuffer_bindings: Option<u32>,
+    /// Base added by glslang to the bindings of each resource type when the program is linked,
+    /// for APIs which do not share a binding space between textures and uniform buffers.
+    pub binding_shifts: &'static [(ResourceType, u32)],
+    /// Layout rule of constant buffers, it must match the layout qualifier of the generated
+    /// uniform blocks.
+    pub layout: LayoutRule
+}
+
+impl EnvInfo
+{
+    pub fn api(&self) -> &'static str
+    {
+        match (self.vulkan, self.es) {
+            (true, _) => "Vulkan",
+            (false, true) => "OpenGL ES",
+            (false, false) => "OpenGL"
+        }
+    }
+
+    pub fn bindings(&self) -> Bindings
+    {
+        match (self.vulkan, self.es) {
+            (true, _) => Bindings::Vulkan,
+            (false, true) => Bindings::Es,
+            (false, false) => self.explicit_bindings.into()
+        }
+    }
+
+    /// Returns true if the stages of this target are written as SPIR-V modules.
+    pub fn spirv_output(&self) -> bool
+    {
+        self.vulkan || self.spirv
+    }
+
+    pub fn profile(&self) -> Profile
+    {
+        match self.es {
+            true => Profile::Es,
+            false => Profile::Core
+        }
+    }
+
+    /// Returns the `#version` directive injected at the start of each stage.
+    pub fn version_directive(&self) -> String
+    {
+        match self.es {
+            true => format!("#version {} es\n", self.gl_version_int),
+            false => format!("#version {} core\n", self.gl_version_int)
+        }
+    }
 }
 
 pub struct Object<T>
@@ -96,7 +205,9 @@ impl<T> Object<T>
             Stage::Hull => self.stage_hull = true,
             Stage::Domain => self.stage_domain = true,
             Stage::Geometry => self.stage_geometry = true,
-            Stage::Pixel => self.stage_pixel = true
+            Stage::Pixel => self.stage_pixel = true,
+            //Compute, task and mesh stages are rejected before symbols are merged.
+            Stage::Compute | Stage::Task | Stage::Mesh => ()
         }
     }
 }
@@ -107,15 +218,19 @@ pub struct Symbols
     pub packed_structs: Vec<StructOffset>,
     pub cbuffers: Vec<Object<StructOffset>>,
     pub outputs: Vec<Slot<Property<usize>>>, //Fragment shader outputs/render target outputs
+    pub varyings: Vec<Property<usize>>,
     pub objects: Vec<Object<Property<usize>>>, //Samplers and textures
     pub pipeline: Option<PipelineStatement>,
     pub vformat: Option<Struct<usize>>,
-    pub blendfuncs: Vec<BlendfuncStatement>
+    pub blendfuncs: Vec<BlendfuncStatement>,
+    pub samplerstates: Vec<SamplerStateStatement>,
+    pub resources: Vec<Resource>
 }
 
 pub struct ShaderData
 {
-    strings: Vec<rglslang::shader::Part>,
+    code: String,
+    sources: Vec<Part>,
     shader: Shader,
     stage: Stage
 }
@@ -123,7 +238,9 @@ pub struct ShaderData
 pub struct ShaderBytes
 {
     pub data: Vec<u8>,
-    pub stage: Stage
+    pub stage: Stage,
+    /// Sources of the stage, only kept by debug builds.
+    pub debug: Option<DebugStage>
 }
 
 pub struct CompiledShaderStage
@@ -131,11 +248,16 @@ pub struct CompiledShaderStage
     pub packed_structs: Vec<StructOffset>,
     pub cbuffers: Vec<Slot<StructOffset>>,
     pub outputs: Vec<Slot<Property<usize>>>, //Fragment shader outputs/render target outputs
+    pub varyings: Vec<Property<usize>>,
     pub objects: Vec<Slot<Property<usize>>>, //Samplers and textures
     pub pipeline: Option<PipelineStatement>,
     pub vformat: Option<Struct<usize>>,
     pub blendfuncs: Vec<BlendfuncStatement>,
-    pub strings: Vec<rglslang::shader::Part>,
+    pub samplerstates: Vec<SamplerStateStatement>,
+    pub resources: Vec<Resource>,
+    pub code: String,
+    /// Units of the stage as written, see [ShaderStage::sources].
+    pub sources: Vec<Part>,
     pub shader: Shader,
     pub stage: Stage
 }
@@ -146,98 +268,346 @@ pub struct CompileOutput
     pub stages: Vec<CompiledShaderStage>
 }
 
-fn build_messages(config: &Config) -> Messages
+fn build_messages(env: &EnvInfo, debug: bool) -> Messages
 {
-    let msgs;
-    if config.debug {
+    let mut msgs;
+    if debug {
         msgs = Messages::new().debug().ast();
     } else {
         msgs = Messages::new();
     }
+    if env.vulkan {
+        msgs = msgs.spirv_rules().vulkan_rules();
+    } else if env.spirv {
+        msgs = msgs.spirv_rules();
+    }
     msgs
 }
 
-pub fn compile_stages(env: &EnvInfo, config: &Config, mut stages: BTreeMap<Stage, ShaderStage>) -> Result<CompileOutput, Error>
+/// Builds the glslang settings shared by all stages of a target; the stage itself is set on
+/// each shader.
+fn build_shader_config(env: &EnvInfo, debug: bool, glsl_includes: &[PathBuf]) -> ShaderConfig
 {
-    let root_constants_layout = get_root_constants_layout(&mut stages).map_err(Error::Sal)?;
-    let stages: Result<Vec<CompiledShaderStage>, Error> = crossbeam::scope(|scope| {
+    let stage = rglslang::environment::Stage::Vertex;
+    let config = match (env.vulkan, env.spirv) {
+        //Varyings and user interfaces don't always have a location which SPIR-V requires.
+        (true, _) => ShaderConfig::new(Environment::new_vulkan(stage, Client::Vulkan, Some(100), ClientVersion::Vulkan11, TargetVersion::Spv13))
+            .auto_map_locations(),
+        (false, true) => ShaderConfig::new(Environment::new_opengl_spirv(stage, Some(100), TargetVersion::Spv10))
+            .auto_map_locations(),
+        (false, false) => ShaderConfig::new(Environment::new_opengl(stage, Client::OpenGL, Some(env.gl_version_int)))
+    };
+    let config = env.binding_shifts.iter()
+        .fold(config, |config, (res, base)| config.shift_binding(*res, *base))
+        .messages(build_messages(env, debug))
+        .entry_point("main")
+        .source_entry_point("main")
+        .default_version(env.gl_version_int)
+        .default_profile(env.profile());
+    match glsl_includes.is_empty() {
+        true => config,
+        false => {
+            let dirs = glsl_includes.to_vec();
+            config.includer(move |name, ty| resolve_include(&dirs, name, ty))
+        }
+    }
+}
+
+/// Validates the layered rendering hints of the pipeline against the target and returns the extension
+/// which must be enabled in the vertex and domain stages, if any.
+pub(crate) fn layered_rendering_extension(env: &EnvInfo, stages: &BTreeMap<Stage, ShaderStage>) -> Result<Option<&'static str>, Error>
+{
+    let pipeline = match stages.values().find_map(|v| v.statements.pipeline.as_ref()) {
+        Some(v) => v,
+        None => return Ok(None)
+    };
+    if pipeline.viewport_count == 0 || pipeline.viewport_count > env.max_viewports {
+        return Err(Error::ViewportCount(pipeline.viewport_count, env.max_viewports));
+    }
+    if (!pipeline.layered_rendering && pipeline.viewport_count == 1) || stages.contains_key(&Stage::Geometry) {
+        return Ok(None);
+    }
+    env.layer_extension.map(Some).ok_or(Error::LayeredRendering)
+}
+
+fn is_int64(p: &PropertyType<usize>) -> bool
+{
+    match p {
+        PropertyType::Scalar(t) => t.is_int64(),
+        PropertyType::Vector(v) | PropertyType::Matrix(v) => v.item.is_int64(),
+        PropertyType::Texture1D(t) | PropertyType::Texture2D(t) | PropertyType::Texture3D(t)
+        | PropertyType::Texture2DArray(t) | PropertyType::TextureCube(t) | PropertyType::Texture2DMS(t, _) => match t {
+            TextureType::Scalar(t) => t.is_int64(),
+            TextureType::Vector(v) => v.item.is_int64()
+        },
+        PropertyType::Array(a) => match a.item {
+            ArrayItemType::Vector(v) | ArrayItemType::Matrix(v) => v.item.is_int64(),
+            ArrayItemType::Texture2D(t) | ArrayItemType::Texture3D(t) | ArrayItemType::TextureCube(t) => match t {
+                TextureType::Scalar(t) => t.is_int64(),
+                TextureType::Vector(v) => v.item.is_int64()
+            },
+            ArrayItemType::StructRef(_) => false
+        },
+        PropertyType::Sampler | PropertyType::SamplerCmp | PropertyType::StructRef(_) => false
+    }
+}
+
+/// Checks whether 64-bit integer types are used and returns the extension which must be enabled in
+/// all stages, if any.
+pub(crate) fn int64_extension(env: &EnvInfo, root_constants_layout: &Struct<usize>, stages: &BTreeMap<Stage, ShaderStage>) -> Result<Option<&'static str>, Error>
+{
+    let used = root_constants_layout.props.iter().any(|p| is_int64(&p.ptype)) || stages.values().any(|v| {
+        let ast = &v.statements;
+        ast.packed_structs.iter().chain(ast.cbuffers.iter().map(|v| &v.inner)).chain(ast.vformat.iter())
+            .flat_map(|v| v.props.iter())
+            .chain(ast.outputs.iter().map(|v| &v.inner))
+            .chain(ast.varyings.iter())
+            .chain(ast.objects.iter().map(|v| &v.inner))
+            .any(|p| is_int64(&p.ptype))
+    });
+    if !used {
+        return Ok(None);
+    }
+    env.int64_extension.map(Some).ok_or(Error::Int64)
+}
+
+/// Data shared by all stages of a build.
+pub struct StageContext<'a>
+{
+    pub root_constants_layout: &'a Struct<usize>,
+    pub layer_extension: Option<&'static str>,
+    pub int64_extension: Option<&'static str>,
+    /// Short names of internal symbols, empty unless optimizing.
+    pub renames: HashMap<String, String>,
+    /// Directories searched by `#include` directives, see [Config::glsl_includes].
+    pub glsl_includes: Vec<PathBuf>,
+    /// Identifiers defined in GLSL code, see [Config::defines].
+    pub defines: Vec<String>,
+    /// See [Config::sources].
+    pub sources: Option<Sources>
+}
+
+impl<'a> StageContext<'a>
+{
+    pub fn new(env: &EnvInfo, config: &Config, root_constants_layout: &'a Struct<usize>, stages: &mut BTreeMap<Stage, ShaderStage>) -> Result<StageContext<'a>, Error>
+    {
+        //Pruning and renaming assume OpenGL semantics, see TargetInfo::supports_optimize.
+        if config.optimize && env.vulkan {
+            return Err(Error::Optimize);
+        }
+        let renames = match config.optimize {
+            true => optimize_stages(stages, env.explicit_bindings),
+            false => HashMap::new()
+        };
+        Ok(StageContext {
+            root_constants_layout,
+            layer_extension: layered_rendering_extension(env, stages)?,
+            int64_extension: int64_extension(env, root_constants_layout, stages)?,
+            renames,
+            glsl_includes: config.glsl_includes.iter().map(|v| v.to_path_buf()).collect(),
+            defines: config.defines.clone(),
+            sources: config.sources.clone()
+        })
+    }
+}
+
+/// Applies the post-compile text passes requested by the configuration to the final code of a stage.
+///
+/// Minification starts by stripping comments, so `strip_comments` is implied by `optimize`.
+fn finish_code(env: &EnvInfo, config: &Config, stage: Stage, code: String) -> String
+{
+    if config.optimize && !env.spirv_output() {
+        debug!(target: "link", "Minifying stage {:?}...", stage);
+        minify(&code)
+    } else if config.strip_comments {
+        debug!(target: "link", "Stripping comments from stage {:?}...", stage);
+        strip_comments(&code)
+    } else {
+        code
+    }
+}
+
+/// Translates the SAL statements of a stage and hands the assembled parts over to glslang.
+///
+/// Returns the SAL translation with the origin of its lines, the final code and the parsed shader
+/// which still needs to be checked by the caller.
+fn parse_stage(target: &GlTarget, shader_config: &ShaderConfig, ctx: &StageContext, stage: Stage, statements: &BasicAst, user: Vec<Part>) -> Result<(String, SourceMap, String, Shader), Error>
+{
+    let env = &target.env;
+    debug!(target: "translation", "Translating SAL AST for stage {:?} to GLSL for {} {}...", stage, env.api(), env.gl_version_str);
+    let (glsl, map) = translate_sal_to_glsl_mapped(env.bindings(), ctx.root_constants_layout, statements)
+        .map_err(Error::Transpiler)?;
+    info!(target: "translation", "Translated GLSL: \n{}", glsl);
+    let mut extensions = env.extensions.to_vec();
+    if let Some(ext) = ctx.layer_extension {
+        if matches!(stage, Stage::Vertex | Stage::Domain) {
+            extensions.push(ext);
+        }
+    }
+    extensions.extend(ctx.int64_extension);
+    let strings = assemble_stage_parts(target, env, stage, StageSource {
+        version: env.version_directive(),
+        extensions: &extensions,
+        defines: &ctx.defines,
+        sal: glsl.clone(),
+        user
+    });
+    let strings: Vec<Part> = match ctx.renames.is_empty() {
+        true => strings,
+        false => strings.into_iter().map(|v| {
+            let code = rename_identifiers(v.code(), &ctx.renames);
+            match v.name() {
+                Some(name) => Part::new_with_name(code, name),
+                None => Part::new(code)
+            }
+        }).collect()
+    };
+    trace!(target: "compile", "Shader strings: \n{:?}", strings);
+    //Keep the final code before the parts are handed over to glslang.
+    let code: String = match ctx.glsl_includes.is_empty() {
+        true => strings.iter().map(|v| v.code()).collect(),
+        false => strings.iter().map(|v| expand_includes(&ctx.glsl_includes, v.code(), v.name().unwrap_or_default())).collect()
+    };
+    if let Some(sources) = &ctx.sources {
+        sources.record_stage(stage, &code);
+    }
+    let rst = stage.to_glslang().map_err(Error::Stage)?;
+    let mut builder = rglslang::shader::Builder::from_config(shader_config).stage(rst);
+    for v in strings {
+        builder = builder.add_part(v);
+    }
+    Ok((glsl, map, code, builder.parse()))
+}
+
+/// Logs each diagnostic individually with the internal parts replaced by their synthetic names.
+fn log_diagnostics(target: &str, diagnostics: Vec<Diagnostic>)
+{
+    for mut v in diagnostics {
+        if let Some(Source::Named(name)) = &mut v.source {
+            *name = part_display_name(name).into();
+        }
+        match v.severity {
+            Severity::Error => error!(target: target, "{}", v),
+            Severity::Warning => warn!(target: target, "{}", v),
+            Severity::Note => info!(target: target, "{}", v)
+        }
+    }
+}
+
+/// Compiles the SAL statements and user code of one stage.
+fn compile_stage(target: &GlTarget, shader_config: &ShaderConfig, ctx: &StageContext, stage: Stage, shader: ShaderStage) -> Result<CompiledShaderStage, Error>
+{
+    let (_, map, code, rshader) = parse_stage(target, shader_config, ctx, stage, &shader.statements, shader.strings)?;
+    //Locations in the SAL translation are meaningless to the author of the shader.
+    log_diagnostics("compile", parse_info_log(&map.remap_log(&rshader.get_info_log())));
+    if !rshader.check() {
+        error!(target: "compile", "GLSL has reported errors");
+        return Err(Error::Compiler);
+    }
+    info!(target: "compile", "Successfully parsed GLSL code");
+    info!(target: "compile", "Shader debug log: \n{}", rshader.get_info_debug_log());
+    let packed_structs = compile_packed_structs(shader.statements.packed_structs, target.env.layout).map_err(Error::Layout)?;
+    let mut cbuffers = Vec::new();
+    for v in shader.statements.cbuffers {
+        let inner = compile_struct(v.inner, &packed_structs, target.env.layout).map_err(Error::Layout)?;
+        debug!(target: "compile", "Size of constant buffer '{}' is {} bytes", inner.name, inner.size);
+        if inner.size > MAX_CBUFFER_SIZE { // Check if UBO exceeds maximum size
+            error!(target: "compile", "The size of a constant buffer cannot exceed 65536 bytes after alignment, however constant buffer '{}' takes {} bytes after alignment", inner.name, inner.size);
+            return Err(Error::BufferSizeOverload);
+        }
+        cbuffers.push(Slot {
+            inner,
+            slot: v.slot,
+            external: v.external,
+            unit: v.unit
+        });
+    }
+    Ok(CompiledShaderStage {
+        cbuffers,
+        packed_structs,
+        outputs: shader.statements.outputs,
+        varyings: shader.statements.varyings,
+        objects: shader.statements.objects,
+        pipeline: shader.statements.pipeline,
+        blendfuncs: shader.statements.blendfuncs,
+        samplerstates: shader.statements.samplerstates,
+        resources: shader.statements.resources,
+        vformat: shader.statements.vformat,
+        code,
+        sources: shader.sources,
+        shader: rshader,
+        stage
+    })
+}
+
+/// Runs `f` for each stage on a thread pool of `n_threads` threads.
+///
+/// The first failure cancels the stages which have not started yet; every error which did occur is
+/// returned as one [Error::Stages], ordered by stage.
+fn run_stages<I, T, F>(n_threads: usize, stages: BTreeMap<Stage, I>, f: F) -> Result<Vec<T>, Error>
+    where I: Send, T: Send, F: Fn(Stage, I) -> Result<T, Error> + Sync
+{
+    let cancelled = AtomicBool::new(false);
+    let results: Vec<(Stage, Option<Result<T, Error>>)> = crossbeam::scope(|scope| {
         let manager = ScopedThreadManager::new(scope);
-        let mut pool: ThreadPool<ScopedThreadManager, Result<CompiledShaderStage, Error>> = ThreadPool::new(config.n_threads);
-        info!("Initialized thread pool with {} max thread(s)", config.n_threads);
-        let root_constants_layout = &root_constants_layout;
-        for (stage, mut shader) in stages {
+        let mut pool: ThreadPool<ScopedThreadManager, (Stage, Option<Result<T, Error>>)> = ThreadPool::new(n_threads);
+        info!(target: "compile", "Initialized thread pool with {} max thread(s)", n_threads);
+        let cancelled = &cancelled;
+        let f = &f;
+        for (stage, item) in stages {
             pool.send(&manager, move |_| {
-                debug!("Translating SAL AST for stage {:?} to GLSL for OpenGL {}...", stage, env.gl_version_str);
-                let glsl = translate_sal_to_glsl(env.explicit_bindings, &root_constants_layout, &shader.statements)
-                    .map_err(Error::Transpiler)?;
-                info!("Translated GLSL: \n{}", glsl);
-                shader.strings.insert(0, rglslang::shader::Part::new_with_name(glsl, "__internal_sal__"));
-                shader.strings.insert(0, rglslang::shader::Part::new_with_name(format!("#version {} core\n", env.gl_version_int), "__internal_glsl_version__"));
-                let strings = shader.strings.clone();
-                trace!("Shader strings: \n{:?}", strings);
-                let rst = match stage {
-                    Stage::Vertex => rglslang::environment::Stage::Vertex,
-                    Stage::Hull => rglslang::environment::Stage::Hull,
-                    Stage::Domain => rglslang::environment::Stage::Domain,
-                    Stage::Geometry => rglslang::environment::Stage::Geometry,
-                    Stage::Pixel => rglslang::environment::Stage::Pixel
-                };
-                let msgs = build_messages(config);
-                let mut builder = rglslang::shader::Builder::new(Environment::new_opengl(rst, Client::OpenGL, Some(env.gl_version_int)))
-                    .messages(msgs)
-                    .entry_point("main")
-                    .source_entry_point("main")
-                    .default_version(env.gl_version_int)
-                    .default_profile(Profile::Core);
-                for v in strings {
-                    builder = builder.add_part(v);
+                if cancelled.load(Ordering::Relaxed) {
+                    debug!(target: "compile", "Skipping stage {:?}: another stage has failed", stage);
+                    return (stage, None);
                 }
-                let rshader = builder.parse();
-                if !rshader.check() {
-                    error!("GLSL has reported the following error: \n{}", rshader.get_info_log());
-                    return Err(Error::Compiler);
+                let res = f(stage, item);
+                if res.is_err() {
+                    cancelled.store(true, Ordering::Relaxed);
                 }
-                info!("Successfully parsed GLSL code");
-                info!("Shader log: \n{}", rshader.get_info_log());
-                info!("Shader debug log: \n{}", rshader.get_info_debug_log());
-                let packed_structs = compile_packed_structs(shader.statements.packed_structs).map_err(Error::Layout140)?;
-                let mut cbuffers = Vec::new();
-                for v in shader.statements.cbuffers {
-                    let inner = compile_struct(v.inner, &packed_structs).map_err(Error::Layout140)?;
-                    debug!("Size of constant buffer '{}' is {} bytes", inner.name, inner.size);
-                    if inner.size > MAX_CBUFFER_SIZE { // Check if UBO exceeds maximum size
-                    error!("The size of a constant buffer cannot exceed 65536 bytes after alignment, however constant buffer '{}' takes {} bytes after alignment", inner.name, inner.size);
-                        return Err(Error::BufferSizeOverload);
-                    }
-                    cbuffers.push(Slot {
-                        inner,
-                        slot: v.slot,
-                        external: v.external
-                    });
-                }
-                let compiled = CompiledShaderStage {
-                    cbuffers,
-                    packed_structs,
-                    outputs: shader.statements.outputs,
-                    objects: shader.statements.objects,
-                    pipeline: shader.statements.pipeline,
-                    blendfuncs: shader.statements.blendfuncs,
-                    vformat: shader.statements.vformat,
-                    strings: shader.strings,
-                    shader: rshader,
-                    stage
-                };
-                Ok(compiled)
+                (stage, Some(res))
             });
-            debug!("Dispatch stage {:?}", stage);
+            debug!(target: "compile", "Dispatch stage {:?}", stage);
         }
         pool.reduce().map(|v| v.unwrap()).collect()
     }).unwrap();
+    let mut outputs = Vec::new();
+    let mut errors = Vec::new();
+    for (stage, res) in results {
+        match res {
+            Some(Ok(v)) => outputs.push(v),
+            Some(Err(e)) => errors.push((stage, e)),
+            None => ()
+        }
+    }
+    if !errors.is_empty() {
+        errors.sort_by_key(|(stage, _)| *stage);
+        return Err(Error::Stages(errors));
+    }
+    Ok(outputs)
+}
+
+pub fn compile_stages(target: &GlTarget, config: &Config, mut stages: BTreeMap<Stage, ShaderStage>) -> Result<CompileOutput, Error>
+{
+    let explicit_root_constants = stages.values().any(|v| v.statements.root_constants_explicit);
+    let root_constants_layout = get_root_constants_layout(&mut stages).map_err(Error::Sal)?;
+    let ctx = StageContext::new(&target.env, config, &root_constants_layout, &mut stages)?;
+    let shader_config = build_shader_config(&target.env, config.debug, &ctx.glsl_includes);
+    let stages = run_stages(config.n_threads, stages, |stage, shader| compile_stage(target, &shader_config, &ctx, stage, shader));
+    //Stages complete in any order; sort them so that the output does not depend on the thread count.
+    let stages = stages.map(|mut v| {
+        v.sort_by_key(|v| v.stage);
+        v
+    });
     let dummy = Vec::new();
-    let compiled_root_constants = compile_struct(root_constants_layout, &dummy).map_err(Error::Layout140)?;
-    debug!("Size of root constants layout is {} bytes", compiled_root_constants.size);
+    let compiled_root_constants = compile_struct(root_constants_layout, &dummy, target.env.layout).map_err(Error::Layout)?;
+    debug!(target: "compile", "Size of root constants layout is {} bytes", compiled_root_constants.size);
     if compiled_root_constants.size > MAX_ROOT_CONSTANTS_SIZE {
-        warn!("Root constants layout size ({} bytes) exceeds the recommended limit of 128 bytes after alignment", compiled_root_constants.size);
+        //The limit is only enforced on layouts declared with rootconst; ORDER_0 constant buffers
+        //predate it.
+        if explicit_root_constants {
+            return Err(Error::RootConstantsSizeOverload(compiled_root_constants.name, compiled_root_constants.size));
+        }
+        config.warnings.report(WarningKind::LayoutSize, format!("Root constants layout size ({} bytes) exceeds the recommended limit of 128 bytes after alignment", compiled_root_constants.size));
     }
     Ok(CompileOutput {
         stages: stages?,
@@ -245,111 +615,395 @@ pub fn compile_stages(env: &EnvInfo, config: &Config, mut stages: BTreeMap<Stage
     })
 }
 
-fn merge_symbols(output: CompileOutput) -> (Symbols, Vec<ShaderData>)
+pub struct StagePreview
 {
-    let mut symbols = HashMap::new();
-    let mut check_insert_symbol = |name: &String, slot| {
-        let mut flag = false;
-        if let Some(s) = symbols.get(name) {
-            if *s != slot {
-                warn!("Duplicate symbol name '{}'", name);
-            }
-            flag = true;
-        }
-        symbols.insert(name.clone(), slot);
-        flag
-    };
+    /// GLSL translation of the SAL statements of the stage.
+    pub glsl: String,
+    /// Final code of the stage, as it would be written in the shader pack.
+    pub code: String,
+    /// Info log reported by glslang.
+    pub log: String,
+    pub success: bool
+}
+
+/// Compiles a single stage of the given stages without linking; the remaining stages only
+/// contribute their symbols.
+pub fn preview_stage(target: &GlTarget, config: &Config, mut stages: BTreeMap<Stage, ShaderStage>, stage: Stage) -> Result<StagePreview, Error>
+{
+    let root_constants_layout = get_root_constants_layout(&mut stages).map_err(Error::Sal)?;
+    let ctx = StageContext::new(&target.env, config, &root_constants_layout, &mut stages)?;
+    let shader = stages.remove(&stage).ok_or(Error::Compiler)?;
+    let shader_config = build_shader_config(&target.env, config.debug, &ctx.glsl_includes);
+    let (glsl, _, mut code, rshader) = parse_stage(target, &shader_config, &ctx, stage, &shader.statements, shader.strings)?;
+    let success = rshader.check();
+    if success {
+        code = finish_code(&target.env, config, stage, code);
+    }
+    Ok(StagePreview {
+        glsl,
+        code,
+        log: rshader.get_info_log().to_string(),
+        success
+    })
+}
+
+/// Merges the symbols of all stages: identical re-declarations share one symbol declared in all
+/// their stages while conflicting ones are an error, unless duplicate symbols are allowed.
+fn merge_symbols(output: CompileOutput, allow_duplicates: bool, warnings: &Reporter) -> Result<(Symbols, Vec<ShaderData>), Error>
+{
+    let mut symbols = SymbolTable::new(allow_duplicates, warnings);
     let mut shaders = Vec::new();
-    let mut cbuffers = HashMap::new(); // Well rust wants to be slow
+    let mut cbuffers = BTreeMap::new(); // Well rust wants to be slow
     // If rust lifetime system wasn't broken &str or &String would have worked!
     let mut outputs = Vec::new();
-    let mut objects = HashMap::new(); // Well rust wants to be slow
+    let mut varyings = Vec::new();
+    let mut objects = BTreeMap::new(); // Well rust wants to be slow
     // If rust lifetime system wasn't broken &str or &String would have worked!
     let mut pipeline = None;
     let mut vformat = None;
     let mut blendfuncs = Vec::new();
+    let mut samplerstates: Vec<SamplerStateStatement> = Vec::new();
+    let mut resources: Vec<Resource> = Vec::new();
     let mut packed_structs = Vec::new();
     for stage in output.stages {
         for v in stage.objects {
+            symbols.declare("object", &v.inner.pname, SymbolDecl::property(stage.stage, Some(v.slot.get()), &v.inner))?;
             let obj = objects.entry(v.inner.pname.clone()).or_insert_with(|| Object::new(v));
             obj.mark_stage(stage.stage);
         }
         for v in stage.outputs {
-            if !check_insert_symbol(&v.inner.pname, v.slot.get()) {
+            if !symbols.declare("output", &v.inner.pname, SymbolDecl::property(stage.stage, Some(v.slot.get()), &v.inner))? {
                 outputs.push(v);
             }
         }
+        //Linked stages all share the same varyings.
+        if varyings.is_empty() {
+            varyings = stage.varyings;
+        }
         for v in stage.cbuffers {
+            symbols.declare("constant buffer", &v.inner.name, SymbolDecl::structure(stage.stage, Some(v.slot.get()), &v.inner, &stage.packed_structs))?;
             let obj = cbuffers.entry(v.inner.name.clone()).or_insert_with(|| Object::new(v));
             obj.mark_stage(stage.stage);
         }
-        for (i, v) in stage.blendfuncs.into_iter().enumerate() {
-            if !check_insert_symbol(&v.name, i as u32) {
+        for v in stage.blendfuncs {
+            if !symbols.declare("blend function", &v.name, SymbolDecl::new(stage.stage, None, vec![format!("{:?}", v)]))? {
                 blendfuncs.push(v);
             }
         }
         if let Some(p) = stage.pipeline {
             if pipeline.is_some() {
-                warn!("Ignoring duplicate pipeline with name '{}'", p.name)
+                warnings.report(WarningKind::Duplicate, format!("Ignoring duplicate pipeline with name '{}'", p.name))
             } else {
                 pipeline = Some(p);
             }
         }
         if let Some(v) = stage.vformat {
             if vformat.is_some() {
-                warn!("Ignoring duplicate vertex format with name '{}'", v.name)
+                warnings.report(WarningKind::Duplicate, format!("Ignoring duplicate vertex format with name '{}'", v.name))
             } else {
                 vformat = Some(v);
             }
         }
-        for (i, v) in stage.packed_structs.into_iter().enumerate() {
-            if !check_insert_symbol(&v.name, i as u32) {
+        for v in stage.samplerstates {
+            match samplerstates.iter().find(|r| r.name == v.name) {
+                Some(existing) if *existing != v => warnings.report(WarningKind::Duplicate, format!("Ignoring duplicate sampler state with name '{}'", v.name)),
+                Some(_) => (),
+                None => samplerstates.push(v)
+            }
+        }
+        for v in stage.resources {
+            if resources.iter().any(|r| r.name == v.name) {
+                warnings.report(WarningKind::Duplicate, format!("Ignoring duplicate resource with name '{}'", v.name))
+            } else {
+                resources.push(v);
+            }
+        }
+        let decls: Vec<SymbolDecl> = stage.packed_structs.iter()
+            .map(|v| SymbolDecl::structure(stage.stage, None, v, &stage.packed_structs))
+            .collect();
+        for (v, decl) in stage.packed_structs.into_iter().zip(decls) {
+            if !symbols.declare("packed structure", &v.name, decl)? {
                 packed_structs.push(v);
             }
         }
         shaders.push(ShaderData {
             shader: stage.shader,
             stage: stage.stage,
-            strings: stage.strings
+            code: stage.code,
+            sources: stage.sources
         });
     }
     let syms = Symbols {
         cbuffers: cbuffers.into_iter().map(|(_, v)| v).collect(),
         packed_structs,
         outputs,
+        varyings,
         objects: objects.into_iter().map(|(_, v)| v).collect(),
         pipeline,
         vformat,
         blendfuncs,
+        samplerstates,
+        resources,
         root_constant_layout: output.root_constant_layout
     };
-    (syms, shaders)
+    Ok((syms, shaders))
+}
+
+fn display_binding(binding: Option<u32>) -> String
+{
+    binding.map(|v| v.to_string()).unwrap_or_else(|| "none".into())
+}
+
+/// Warns about constant buffers and textures which glslang did not bind to the slot relocated
+/// from SAL. Symbols renamed or removed from the code are not reflected and are skipped.
+fn check_bindings(syms: &Symbols, reflection: &Reflection, warnings: &Reporter)
+{
+    for v in &syms.cbuffers {
+        let (name, slot) = (&v.inner.inner.name, v.inner.slot.get());
+        if let Some(block) = reflection.uniform_block(name) {
+            if block.binding != Some(slot) {
+                warnings.report(WarningKind::BindingMismatch, format!("Constant buffer '{}' is bound to slot {} but glslang assigned binding {}", name, slot, display_binding(block.binding)));
+            }
+        }
+    }
+    for v in syms.objects.iter().filter(|v| !matches!(v.inner.inner.ptype, PropertyType::Sampler | PropertyType::SamplerCmp)) {
+        let (name, slot) = (&v.inner.inner.pname, v.inner.slot.get());
+        if let Some(uniform) = reflection.uniform(name) {
+            if uniform.binding != Some(slot) {
+                warnings.report(WarningKind::BindingMismatch, format!("Texture '{}' is bound to slot {} but glslang assigned binding {}", name, slot, display_binding(uniform.binding)));
+            }
+        }
+    }
+}
+
+/// Describes the sources of a stage; the code is recorded before minification and the names of
+/// the sources are mapped as any persisted path.
+fn debug_stage(config: &Config, shader: &ShaderData) -> DebugStage
+{
+    DebugStage {
+        stage: shader.stage.name().into(),
+        sources: shader.sources.iter().map(|v| DebugSource {
+            name: config.map_path(Path::new(v.name().unwrap_or_default())).display().to_string(),
+            text: v.code().into()
+        }).collect(),
+        code: shader.code.clone()
+    }
 }
 
-/// This function links shaders only for pure OpenGL targets; vulkan and SpvCross based targets
-/// aren't supported by this function.
-pub fn gl_link_shaders(config: &Config, output: CompileOutput) -> Result<(Symbols, Vec<ShaderBytes>), Error>
+/// Links shaders for OpenGL and Vulkan targets; for Vulkan and OpenGL SPIR-V targets the returned
+/// stages are SPIR-V modules generated from the linked program. SpvCross based targets aren't supported by this
+/// function.
+pub fn gl_link_shaders(env: &EnvInfo, config: &Config, output: CompileOutput) -> Result<(Symbols, Vec<ShaderBytes>), Error>
 {
-    let (syms, shaders) = merge_symbols(output);
+    let (syms, shaders) = merge_symbols(output, config.allow_duplicate_symbols, &config.warnings)?;
     let mut shaders1 = Vec::with_capacity(shaders.len());
-    let msgs = build_messages(config);
+    let msgs = build_messages(env, config.debug);
     let mut builder = rglslang::program::Builder::new()
         .messages(msgs);
+    if !env.binding_shifts.is_empty() {
+        builder = builder.map_io();
+    }
     for v in shaders {
-        let data = v.strings.into_iter().map(|v| v.into_code()).collect::<Vec<_>>().join("");
+        let debug = match config.debug {
+            true => Some(debug_stage(config, &v)),
+            false => None
+        };
+        let data = finish_code(env, config, v.stage, v.code);
         shaders1.push(ShaderBytes {
             data: data.into_bytes(),
-            stage: v.stage
+            stage: v.stage,
+            debug
         });
         builder = builder.add_shader(v.shader);
     }
-    let prog = builder.link();
+    let mut prog = builder.link();
+    log_diagnostics("link", prog.diagnostics());
     if !prog.check() {
-        error!("GLSL has reported the following error: \n{}", prog.get_info_log());
+        error!(target: "link", "GLSL has reported errors");
         return Err(Error::Linker);
     }
-    info!("Successfully linked GLSL shaders");
-    info!("Shader log: \n{}", prog.get_info_log());
-    info!("Shader debug log: \n{}", prog.get_info_debug_log());
+    info!(target: "link", "Successfully linked GLSL shaders");
+    info!(target: "link", "Shader debug log: \n{}", prog.get_info_debug_log());
+    //Implicit bindings are only assigned by the application at runtime.
+    if env.bindings() != Bindings::Implicit {
+        match prog.reflect(ReflectionOptions::new()) {
+            Some(reflection) => check_bindings(&syms, &reflection, &config.warnings),
+            None => debug!(target: "link", "Unable to reflect the linked program, skipping binding checks")
+        }
+    }
+    if env.spirv_output() {
+        let mut spv = SpvBuilder::new();
+        if config.debug {
+            spv = spv.debug_info();
+        }
+        for v in &mut shaders1 {
+            let spirv = spv.build(&prog, v.stage.to_glslang().map_err(Error::Stage)?).ok_or(Error::Spirv)?;
+            if spirv.as_words().is_empty() {
+                error!(target: "link", "SPIR-V generation has failed for stage {:?}: \n{}", v.stage, spirv.log());
+                return Err(Error::Spirv);
+            }
+            info!(target: "link", "SPIR-V log for stage {:?}: \n{}", v.stage, spirv.log());
+            v.data = spirv.as_bytes().to_vec();
+        }
+        info!(target: "link", "Successfully generated SPIR-V modules");
+    }
     Ok((syms, shaders1))
 }
+
+#[cfg(test)]
+mod tests
+{
+    use std::collections::{BTreeMap, HashMap};
+    use std::path::Path;
+    use std::sync::Barrier;
+    use bp3d_sal::ast::tree::{BaseType, Property, PropertyType, Span, Struct, TextureType, VectorType};
+    use rglslang::shader::Part;
+    use crate::{Config, Stage};
+    use crate::targets::basic::{BasicAst, Slot};
+    use crate::targets::gl::core::{build_shader_config, Error, finish_code, parse_stage, run_stages, StageContext};
+    use crate::targets::{gl42, vk};
+    use crate::targets::sal_to_glsl::{Bindings, translate_sal_to_glsl};
+
+    fn prop(name: &str, ptype: PropertyType<usize>, line: usize) -> Property<usize>
+    {
+        Property {
+            pname: name.into(),
+            ptype,
+            pattr: None,
+            deprecated: None,
+            pformat: None,
+            default: None,
+            span: Span::new(line, 1)
+        }
+    }
+
+    fn root() -> Struct<usize>
+    {
+        Struct {
+            name: "__Root".into(),
+            attr: None,
+            deprecated: None,
+            props: Vec::new(),
+            span: Default::default()
+        }
+    }
+
+    //A stage declaring two outputs on the same slot when invalid.
+    fn outputs(valid: bool) -> BasicAst
+    {
+        let mut ast = BasicAst::new();
+        let color = PropertyType::Vector(VectorType { item: BaseType::Float, size: 4 });
+        ast.outputs.push(Slot::new(prop("FragColor", color.clone(), 1)));
+        ast.outputs.push(Slot::new(prop("FragNormal", color, 2)));
+        ast.outputs[1].slot.set(if valid { 1 } else { 0 });
+        ast
+    }
+
+    #[test]
+    fn stage_errors()
+    {
+        let stages: BTreeMap<Stage, BasicAst> = [(Stage::Vertex, outputs(false)), (Stage::Pixel, outputs(false))].into_iter().collect();
+        //Both stages must be running before either fails, otherwise the second one is cancelled.
+        let barrier = Barrier::new(2);
+        let err = run_stages(2, stages, |_, ast| {
+            barrier.wait();
+            translate_sal_to_glsl(Bindings::Explicit, &root(), &ast).map_err(Error::Transpiler)
+        }).unwrap_err();
+        match &err {
+            Error::Stages(errors) => assert_eq!(errors.iter().map(|(stage, _)| *stage).collect::<Vec<_>>(), vec![Stage::Vertex, Stage::Pixel]),
+            e => panic!("unexpected error: {}", e)
+        }
+        let msg = err.to_string();
+        assert!(msg.starts_with("2 stage(s) failed to compile"), "{}", msg);
+        assert!(msg.contains("\n  vertex: sal-glsl transpiler error: multiple definition of output slot 0"), "{}", msg);
+        assert!(msg.contains("\n  pixel: sal-glsl transpiler error: multiple definition of output slot 0"), "{}", msg);
+    }
+
+    #[test]
+    fn stage_errors_cancel()
+    {
+        for n_threads in [1, 3] {
+            let stages: BTreeMap<Stage, BasicAst> = [
+                (Stage::Vertex, outputs(false)),
+                (Stage::Geometry, outputs(true)),
+                (Stage::Pixel, outputs(true))
+            ].into_iter().collect();
+            //The valid stages may or may not be compiled depending on scheduling, the build must
+            //fail either way.
+            let err = run_stages(n_threads, stages, |_, ast| translate_sal_to_glsl(Bindings::Explicit, &root(), &ast).map_err(Error::Transpiler)).unwrap_err();
+            match err {
+                Error::Stages(errors) => assert_eq!(errors.iter().map(|(stage, _)| *stage).collect::<Vec<_>>(), vec![Stage::Vertex]),
+                e => panic!("unexpected error: {}", e)
+            }
+        }
+    }
+
+    //Compiles a pixel stage which must fail and returns its info log.
+    fn compile(statements: &BasicAst, body: &str) -> String
+    {
+        let target = gl42::target();
+        let root = root();
+        let ctx = StageContext {
+            root_constants_layout: &root,
+            layer_extension: None,
+            int64_extension: None,
+            renames: HashMap::new(),
+            glsl_includes: Vec::new(),
+            defines: Vec::new(),
+            sources: None
+        };
+        let config = build_shader_config(&target.env, false, &[]);
+        let user = vec![Part::new_with_name(body, "shader.glsl")];
+        let (_, map, _, shader) = parse_stage(&target, &config, &ctx, Stage::Pixel, statements, user).unwrap();
+        assert!(!shader.check());
+        map.remap_log(&shader.get_info_log())
+    }
+
+    #[test]
+    fn sal_locations()
+    {
+        let mut statements = BasicAst::new();
+        statements.cbuffers.push(Slot::with_unit(Struct {
+            name: "Material".into(),
+            attr: None,
+            deprecated: None,
+            props: vec![prop("Color", PropertyType::Vector(VectorType { item: BaseType::Float, size: 4 }), 4)],
+            span: Span::new(3, 1)
+        }, "material.sal"));
+        let texture = PropertyType::Texture2D(TextureType::Vector(VectorType { item: BaseType::Float, size: 4 }));
+        statements.objects.push(Slot::with_unit(prop("BaseTexture", texture.clone(), 5), "shader.glsl"));
+        //Lines of the user code are the lines of the original file.
+        let log = compile(&statements, "//#sal\n//const Texture2D:vec4f BaseTexture;\n//#sal\nvoid main()\n{\n    float color = Material_Color;\n}\n");
+        assert!(log.contains("shader.glsl:6:"), "{}", log);
+        //'active' is a reserved word in GLSL: the error is located on the SAL statement.
+        statements.objects.push(Slot::with_unit(prop("active", texture, 7), "shader.glsl"));
+        let log = compile(&statements, "void main() {}\n");
+        assert!(log.contains("shader.glsl:7:"), "{}", log);
+        assert!(!log.contains("__internal_sal__:"), "{}", log);
+    }
+
+    #[test]
+    fn optimize_strip_comments()
+    {
+        let env = gl42::target().env;
+        let code = "// Header\nvoid main()\n{\n    /* Block */ gl_FragColor = vec4(1.0);\n}\n";
+        let output = Path::new("unused.bpx");
+        let finish = |optimize, strip_comments| {
+            let config = Config { optimize, strip_comments, ..Config::new(Vec::new(), output) };
+            finish_code(&env, &config, Stage::Pixel, code.into())
+        };
+        let both = finish(true, true);
+        assert!(!both.contains("Header") && !both.contains("Block"), "{}", both);
+        assert_eq!(both, finish(true, false));
+        assert_eq!(finish(false, true).lines().count(), code.lines().count());
+    }
+
+    #[test]
+    fn optimize_vulkan()
+    {
+        let config = Config { optimize: true, ..Config::new(Vec::new(), Path::new("unused.bpx")) };
+        let root = root();
+        let res = StageContext::new(&vk::target().env, &config, &root, &mut BTreeMap::new());
+        assert!(matches!(res, Err(Error::Optimize)));
+    }
+}
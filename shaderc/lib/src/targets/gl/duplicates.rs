@@ -0,0 +1,228 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use std::collections::HashMap;
+use bp3d_sal::ast::tree::{Attribute, Property};
+use crate::Stage;
+use crate::targets::gl::contract::type_name;
+use crate::targets::gl::core::Error;
+use crate::targets::layout::StructOffset;
+use crate::warnings::{Reporter, WarningKind};
+
+/// Canonical description of a symbol declared by a stage, compared line by line with the
+/// declarations of other stages.
+pub struct SymbolDecl
+{
+    stage: Stage,
+    slot: Option<u32>,
+    lines: Vec<String>
+}
+
+fn attr_line(attr: &Attribute) -> String
+{
+    format!("attribute {:?}", attr)
+}
+
+impl SymbolDecl
+{
+    pub fn new(stage: Stage, slot: Option<u32>, lines: Vec<String>) -> SymbolDecl
+    {
+        SymbolDecl {
+            stage,
+            slot,
+            lines
+        }
+    }
+
+    pub fn property(stage: Stage, slot: Option<u32>, p: &Property<usize>) -> SymbolDecl
+    {
+        let lines = p.pattr.iter().map(attr_line)
+            .chain(std::iter::once(format!("{} {}", p.ptype, p.pname)))
+            .collect();
+        SymbolDecl::new(stage, slot, lines)
+    }
+
+    /// Struct references are described by name as packed structures are numbered per stage.
+    pub fn structure(stage: Stage, slot: Option<u32>, st: &StructOffset, packed_structs: &[StructOffset]) -> SymbolDecl
+    {
+        let lines = st.attr.iter().map(attr_line)
+            .chain(st.props.iter().map(|p| format!("{} {} (offset {})", type_name(&p.inner.ptype, packed_structs), p.inner.pname, p.aligned_offset)))
+            .collect();
+        SymbolDecl::new(stage, slot, lines)
+    }
+}
+
+pub struct SymbolTable<'a>
+{
+    decls: HashMap<(&'static str, String), SymbolDecl>,
+    allow_duplicates: bool,
+    warnings: &'a Reporter
+}
+
+impl<'a> SymbolTable<'a>
+{
+    pub fn new(allow_duplicates: bool, warnings: &'a Reporter) -> SymbolTable<'a>
+    {
+        SymbolTable {
+            decls: HashMap::new(),
+            allow_duplicates,
+            warnings
+        }
+    }
+
+    /// Declares a symbol and returns true if a previous stage already declared it, in which case
+    /// the first declaration is kept. Conflicting re-declarations are an error unless duplicates
+    /// are allowed.
+    pub fn declare(&mut self, kind: &'static str, name: &str, decl: SymbolDecl) -> Result<bool, Error>
+    {
+        let first = match self.decls.get(&(kind, name.into())) {
+            Some(v) => v,
+            None => {
+                self.decls.insert((kind, name.into()), decl);
+                return Ok(false);
+            }
+        };
+        if first.slot == decl.slot && first.lines == decl.lines {
+            return Ok(true);
+        }
+        let mut diff: Vec<String> = first.lines.iter()
+            .filter(|v| !decl.lines.contains(v))
+            .map(|v| format!("- {}", v))
+            .collect();
+        diff.extend(decl.lines.iter().filter(|v| !first.lines.contains(v)).map(|v| format!("+ {}", v)));
+        let err = Error::ConflictingSymbol {
+            kind,
+            name: name.into(),
+            first_stage: first.stage,
+            first_slot: first.slot,
+            second_stage: decl.stage,
+            second_slot: decl.slot,
+            diff
+        };
+        if !self.allow_duplicates {
+            return Err(err);
+        }
+        self.warnings.report(WarningKind::Duplicate, err.to_string());
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use bp3d_sal::ast::tree::{Attribute, BaseType, Property, PropertyType, TextureType, VectorType};
+    use crate::Stage;
+    use crate::targets::gl::core::Error;
+    use crate::targets::gl::duplicates::{SymbolDecl, SymbolTable};
+    use crate::targets::layout::{LayoutRule, Offset, StructOffset};
+    use crate::warnings::{Reporter, WarningKind};
+
+    fn material(color: PropertyType<usize>, offset: usize) -> StructOffset
+    {
+        let prop = |name: &str, ptype, aligned_offset| Offset {
+            inner: Property {
+                pname: name.into(),
+                ptype,
+                pattr: None,
+                deprecated: None,
+                pformat: None,
+                default: None,
+                span: Default::default()
+            },
+            aligned_offset,
+            offset: aligned_offset,
+            size: 16,
+            base_alignment: 16
+        };
+        StructOffset {
+            name: "Material".into(),
+            attr: Some(Attribute::Order(1)),
+            deprecated: None,
+            props: vec![prop("Specular", PropertyType::Scalar(BaseType::Float), 0), prop("Color", color, offset)],
+            size: 32,
+            base_alignment: 16,
+            rule: LayoutRule::Std140
+        }
+    }
+
+    #[test]
+    fn merge()
+    {
+        let vec4 = PropertyType::Vector(VectorType { item: BaseType::Float, size: 4 });
+        let reporter = Reporter::new();
+        let mut table = SymbolTable::new(false, &reporter);
+        let decl = |stage, st: &StructOffset| SymbolDecl::structure(stage, Some(1), st, &[]);
+        assert!(!table.declare("constant buffer", "Material", decl(Stage::Vertex, &material(vec4, 16))).unwrap());
+        assert!(table.declare("constant buffer", "Material", decl(Stage::Pixel, &material(vec4, 16))).unwrap());
+        //Symbols of different kinds never conflict.
+        let output = Property { pname: "Material".into(), ptype: vec4, pattr: None, deprecated: None, pformat: None, default: None, span: Default::default() };
+        assert!(!table.declare("output", "Material", SymbolDecl::property(Stage::Pixel, Some(0), &output)).unwrap());
+    }
+
+    #[test]
+    fn conflict()
+    {
+        let vec4 = PropertyType::Vector(VectorType { item: BaseType::Float, size: 4 });
+        let vec3 = PropertyType::Vector(VectorType { item: BaseType::Float, size: 3 });
+        let reporter = Reporter::new();
+        let mut table = SymbolTable::new(false, &reporter);
+        table.declare("constant buffer", "Material", SymbolDecl::structure(Stage::Vertex, Some(1), &material(vec4, 16), &[])).unwrap();
+        let err = table.declare("constant buffer", "Material", SymbolDecl::structure(Stage::Pixel, Some(2), &material(vec3, 4), &[])).unwrap_err();
+        match &err {
+            Error::ConflictingSymbol { first_stage, first_slot, second_stage, second_slot, diff, .. } => {
+                assert_eq!((*first_stage, *first_slot, *second_stage, *second_slot), (Stage::Vertex, Some(1), Stage::Pixel, Some(2)));
+                assert_eq!(diff, &["- vec4f Color (offset 16)", "+ vec3f Color (offset 4)"]);
+            },
+            _ => panic!("expected a conflicting symbol error")
+        }
+        assert!(err.to_string().contains("constant buffer 'Material'"));
+    }
+
+    #[test]
+    fn allow_duplicates()
+    {
+        let texture = |size| Property {
+            pname: "BaseTexture".into(),
+            ptype: PropertyType::Texture2D(TextureType::Vector(VectorType { item: BaseType::Float, size })),
+            pattr: None,
+            deprecated: None,
+            pformat: None,
+            default: None,
+            span: Default::default()
+        };
+        let reporter = Reporter::new();
+        let mut table = SymbolTable::new(true, &reporter);
+        table.declare("object", "BaseTexture", SymbolDecl::property(Stage::Vertex, Some(0), &texture(4))).unwrap();
+        //The conflict is downgraded to a warning and the first declaration is kept.
+        assert!(table.declare("object", "BaseTexture", SymbolDecl::property(Stage::Pixel, Some(0), &texture(3))).unwrap());
+        assert!(table.declare("object", "BaseTexture", SymbolDecl::property(Stage::Pixel, Some(0), &texture(4))).unwrap());
+        let records = reporter.records();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].kind, WarningKind::Duplicate);
+    }
+}
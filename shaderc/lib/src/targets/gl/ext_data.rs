@@ -26,17 +26,16 @@
 // NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
 // SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
-use std::collections::HashMap;
 use bpx::shader::ShaderPack;
-use bp3d_sal::ast::tree::{PipelineStatement, PropertyType, Struct};
-use bp3d_symbols::{ArrayItemType, ConstantObject, OutputObject, PipelineObject, PropObject, PropType, StructObject, TextureObject, TextureObjectType};
+use bp3d_sal::ast::tree::{Attribute, PipelineStatement, PropertyType, Struct};
+use bp3d_symbols::{ArrayItemType, ConstantObject, IdCollision, OutputObject, PipelineObject, PropObject, PropType, SamplerObject, StructObject, SymbolId, SymbolTable, TextureObject, TextureObjectType};
 use crate::targets::basic::Slot;
-use crate::targets::layout140::{size_of_base_type, StructOffset};
+use crate::targets::layout::{size_of_base_type, StructOffset};
 
 pub struct SymbolWriter<T: std::io::Write + std::io::Seek>
 {
     inner: ShaderPack<T>,
-    map: HashMap<String, u16>
+    map: SymbolTable<u16>
 }
 
 impl<T: std::io::Write + std::io::Seek> SymbolWriter<T> {
@@ -44,7 +43,7 @@ impl<T: std::io::Write + std::io::Seek> SymbolWriter<T> {
     {
         SymbolWriter {
             inner,
-            map: HashMap::new()
+            map: SymbolTable::new()
         }
     }
 
@@ -54,13 +53,25 @@ impl<T: std::io::Write + std::io::Seek> SymbolWriter<T> {
         let mut symbols = self.inner.symbols_mut()
             .ok_or(bpx::shader::error::Error::Open(bpx::core::error::OpenError::SectionNotLoaded))?;
         let index = symbols.create(s)?;
-        self.map.insert(name, index as _);
+        //Collisions are reported by check_id before the symbol is written.
+        let _ = self.map.insert(name, index as _);
         Ok(())
     }
 
+    /// Returns the id of a symbol about to be written, fails if another symbol already uses it.
+    pub fn check_id(&self, name: &str) -> Result<SymbolId, IdCollision>
+    {
+        self.map.check(name)
+    }
+
     pub fn lookup<T1: AsRef<str>>(&self, name: T1) -> u16
     {
-        self.map[name.as_ref()]
+        self.map.by_name(name.as_ref()).copied().expect("symbol has not been written")
+    }
+
+    pub fn symbol_count(&self) -> usize
+    {
+        self.map.len()
     }
 
     pub fn into_inner(self) -> ShaderPack<T> {
@@ -95,17 +106,18 @@ pub trait ToObject<T = ()> where Self: Sized
     type Context;
 
     fn to_object(self, ctx: &Self::Context) -> Option<Self::Object>;
+}
 
-    fn to_bpx_object(self, debug: bool, ctx: &Self::Context) -> Result<bpx::sd::Value, bpx::sd::serde::Error> {
-        use bp3d_symbols::ToBpx;
-        match self.to_object(ctx) {
-            None => Ok(bpx::sd::Value::Null),
-            Some(v) => v.to_bpx(debug)
-        }
+impl ToObject for ConstantObject {
+    type Object = Self;
+    type Context = ();
+
+    fn to_object(self, _: &Self::Context) -> Option<Self::Object> {
+        Some(self)
     }
 }
 
-impl ToObject for ConstantObject {
+impl ToObject for SamplerObject {
     type Object = Self;
     type Context = ();
 
@@ -121,22 +133,56 @@ impl ToObject for PropertyType<usize>
 
     fn to_object(self, _: &()) -> Option<Self::Object> {
         match self {
+            PropertyType::Texture1D(value) => Some(TextureObject {
+                ty: TextureObjectType::T1D,
+                value,
+                array_size: None,
+                samples: None
+            }),
             PropertyType::Texture2D(value) => Some(TextureObject {
                 ty: TextureObjectType::T2D,
-                value
+                value,
+                array_size: None,
+                samples: None
             }),
             PropertyType::Texture3D(value) => Some(TextureObject {
                 ty: TextureObjectType::T3D,
-                value
+                value,
+                array_size: None,
+                samples: None
             }),
             PropertyType::Texture2DArray(value) => Some(TextureObject {
                 ty: TextureObjectType::T2DArray,
-                value
+                value,
+                array_size: None,
+                samples: None
             }),
             PropertyType::TextureCube(value) => Some(TextureObject {
                 ty: TextureObjectType::TCube,
-                value
+                value,
+                array_size: None,
+                samples: None
             }),
+            PropertyType::Texture2DMS(value, samples) => Some(TextureObject {
+                ty: TextureObjectType::T2DMS,
+                value,
+                array_size: None,
+                samples: Some(samples)
+            }),
+            PropertyType::Array(a) => {
+                let (ty, value) = match a.item {
+                    bp3d_sal::ast::tree::ArrayItemType::Texture2D(value) => (TextureObjectType::T2D, value),
+                    bp3d_sal::ast::tree::ArrayItemType::Texture3D(value) => (TextureObjectType::T3D, value),
+                    bp3d_sal::ast::tree::ArrayItemType::TextureCube(value) => (TextureObjectType::TCube, value),
+                    _ => return None
+                };
+                Some(TextureObject {
+                    ty,
+                    value,
+                    array_size: Some(a.size),
+                    samples: None
+                })
+            },
             _ => None
         }
     }
@@ -154,15 +200,26 @@ impl ToObject for OutputObject {
 impl ToObject for PipelineStatement
 {
     type Object = PipelineObject;
-    type Context = ();
+    type Context = bool;
 
-    fn to_object(self, _: &Self::Context) -> Option<Self::Object> {
+    fn to_object(self, vertex_input: &bool) -> Option<Self::Object> {
         Some(PipelineObject {
             depth_enable: self.depth_enable,
             depth_write_enable: self.depth_write_enable,
             scissor_enable: self.scissor_enable,
             render_mode: self.render_mode,
-            culling_mode: self.culling_mode
+            culling_mode: self.culling_mode,
+            vertex_input: *vertex_input,
+            layered_rendering: self.layered_rendering,
+            viewport_count: self.viewport_count,
+            depth_func: self.depth_func,
+            stencil_enable: self.stencil_enable,
+            stencil_func: self.stencil_func,
+            stencil_pass_op: self.stencil_pass_op,
+            stencil_fail_op: self.stencil_fail_op,
+            stencil_ref: self.stencil_ref,
+            depth_bias: self.depth_bias,
+            slope_scaled_depth_bias: self.slope_scaled_depth_bias
         })
     }
 }
@@ -204,7 +261,9 @@ fn new_prop_type<T: std::io::Seek + std::io::Write>(prop: PropertyType<usize>, s
                 bp3d_sal::ast::tree::ArrayItemType::StructRef(v) => {
                     let st = &packed_structs[v];
                     ArrayItemType::StructRef(syms.lookup(&st.inner.name))
-                }
+                },
+                _ => unsafe { std::hint::unreachable_unchecked() } //Texture arrays are forbidden in
+                // constant buffers by the SAL processor
             }
         },
         _ => unsafe { std::hint::unreachable_unchecked() } //That one should never trigger
@@ -235,8 +294,14 @@ impl<'a, T: 'a + std::io::Write + std::io::Seek> ToObject<T> for &'a StructOffse
             props: self.props.iter().map(|v| PropObject {
                 name: v.inner.pname.clone(),
                 offset: v.aligned_offset as _,
-                ty: new_prop_type(v.inner.ptype, syms, packed_structs)
-            }).collect()
+                ty: new_prop_type(v.inner.ptype, syms, packed_structs),
+                semantic: None,
+                default: v.inner.default.clone(),
+                location: None
+            }).collect(),
+            deprecated: self.deprecated.clone(),
+            shared: matches!(self.attr, Some(Attribute::Shared(_))),
+            layout: Some(self.rule)
         })
     }
 }
@@ -244,20 +309,27 @@ impl<'a, T: 'a + std::io::Write + std::io::Seek> ToObject<T> for &'a StructOffse
 impl ToObject for Struct<usize>
 {
     type Object = StructObject;
-    type Context = ();
+    //Semantic and location of each member, see vformat_semantics and vformat_locations.
+    type Context = (Vec<String>, Vec<u32>);
 
-    fn to_object(self, _: &()) -> Option<Self::Object> {
+    fn to_object(self, (semantics, locations): &(Vec<String>, Vec<u32>)) -> Option<Self::Object> {
         let mut st = StructObject {
             size: 0,
-            props: Vec::new()
+            props: Vec::new(),
+            deprecated: self.deprecated.clone(),
+            shared: false,
+            layout: None
         };
-        for prop in self.props {
+        for ((prop, semantic), location) in self.props.into_iter().zip(semantics).zip(locations) {
             let ty = new_prop_type_simple(prop.ptype);
             let size = ty.get_size();
             st.props.push(PropObject {
                 name: prop.pname,
                 ty,
-                offset: st.size
+                offset: st.size,
+                semantic: Some(semantic.clone()),
+                default: None,
+                location: Some(*location)
             });
             st.size += size;
         }
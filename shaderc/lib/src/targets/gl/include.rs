@@ -0,0 +1,91 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+
+use std::path::{Path, PathBuf};
+use regex::Regex;
+use rglslang::include::{IncludeType, ResolvedInclude};
+
+/// Resolves a GLSL include: relative includes are looked up next to the including file, system
+/// includes in each of the include directories.
+pub fn resolve_include(dirs: &[PathBuf], name: &str, ty: IncludeType) -> Option<ResolvedInclude>
+{
+    let candidates: Vec<PathBuf> = match ty {
+        IncludeType::Relative(includer) => Path::new(includer).parent().map(|v| v.join(name)).into_iter().collect(),
+        IncludeType::System => dirs.iter().map(|v| v.join(name)).collect()
+    };
+    candidates.into_iter().find_map(|path| {
+        let content = std::fs::read_to_string(&path).ok()?;
+        Some(ResolvedInclude::new(path.to_string_lossy(), content))
+    })
+}
+
+/// Replaces the `#include` directives of a part with the code they resolve to, as OpenGL drivers
+/// do not support them; missing and cyclic includes were already rejected by glslang.
+pub fn expand_includes(dirs: &[PathBuf], code: &str, name: &str) -> String
+{
+    let regex = Regex::new(r#"^\s*#\s*include\s*(?:"([^"]*)"|<([^>]*)>)"#).unwrap();
+    let mut res = String::with_capacity(code.len());
+    for line in code.lines() {
+        let include = regex.captures(line).and_then(|c| match c.get(1) {
+            Some(header) => resolve_include(dirs, header.as_str(), IncludeType::Relative(name))
+                .or_else(|| resolve_include(dirs, header.as_str(), IncludeType::System)),
+            None => resolve_include(dirs, &c[2], IncludeType::System)
+        });
+        match include {
+            Some(v) => res.push_str(&expand_includes(dirs, &v.content, &v.name)),
+            None => {
+                res.push_str(line);
+                res.push('\n');
+            }
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::targets::gl::include::expand_includes;
+
+    #[test]
+    fn expand()
+    {
+        let dir = std::env::temp_dir().join(format!("shaderc-glsl-include-{}", std::process::id()));
+        let shaders = dir.join("shaders");
+        let common = dir.join("common");
+        std::fs::create_dir_all(&shaders).unwrap();
+        std::fs::create_dir_all(&common).unwrap();
+        std::fs::write(shaders.join("lighting.glsl"), "#include <math.glsl>\nfloat light() { return PI; }\n").unwrap();
+        std::fs::write(common.join("math.glsl"), "#define PI 3.14\n").unwrap();
+        let main = shaders.join("main.glsl");
+        let code = expand_includes(&[common], "#include \"lighting.glsl\"\n#include \"missing.glsl\"\nvoid main() {}", &main.to_string_lossy());
+        assert_eq!(code, "#define PI 3.14\nfloat light() { return PI; }\n#include \"missing.glsl\"\nvoid main() {}\n");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
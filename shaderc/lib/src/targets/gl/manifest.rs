@@ -0,0 +1,236 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+
+//! Binding manifest of a GL build, written next to the pack for engine-side code generation.
+
+use std::path::Path;
+use bp3d_sal::ast::tree::PropertyType;
+use bp3d_symbols::{Manifest, ManifestAttribute, ManifestCBuffer, ManifestOutput, ManifestPipeline, ManifestSampler, ManifestStruct, ManifestTexture, ManifestVertexFormat, Member};
+use crate::targets::basic::sal_compiler::{output_semantics, vformat_locations, vformat_semantics};
+use crate::targets::gl::bpx::{build_blendfunc_lookup_map, build_sampler_state, build_samplerstate_lookup_map};
+use crate::targets::gl::contract::type_name;
+use crate::targets::gl::core::Symbols;
+use crate::targets::gl::ext_data::ToObject;
+use crate::targets::layout::StructOffset;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error
+{
+    #[error("io error: {0}")]
+    Io(std::io::Error),
+    #[error("json error: {0}")]
+    Json(serde_json::Error),
+    #[error("sal error: {0}")]
+    Sal(crate::targets::basic::sal_compiler::Error)
+}
+
+fn members(st: &StructOffset, packed_structs: &[StructOffset]) -> Vec<Member>
+{
+    st.props.iter().map(|p| Member {
+        name: p.inner.pname.clone(),
+        ty: type_name(&p.inner.ptype, packed_structs),
+        offset: Some(p.aligned_offset as _)
+    }).collect()
+}
+
+/// Describes the bindings of a build, using the same objects as the symbols written to the pack.
+pub fn collect_manifest(syms: &Symbols, vertex_input: bool) -> Result<Manifest, Error>
+{
+    let mut manifest = Manifest::default();
+    for v in &syms.cbuffers {
+        let st = &v.inner.inner;
+        manifest.cbuffers.insert(st.name.clone(), ManifestCBuffer {
+            slot: v.inner.slot.get(),
+            size: st.size as _,
+            members: members(st, &syms.packed_structs)
+        });
+    }
+    for st in &syms.packed_structs {
+        manifest.structs.insert(st.name.clone(), ManifestStruct {
+            size: st.size as _,
+            members: members(st, &syms.packed_structs)
+        });
+    }
+    let mut states = build_samplerstate_lookup_map(syms.samplerstates.clone());
+    for v in &syms.objects {
+        let prop = &v.inner.inner;
+        let slot = v.inner.slot.get();
+        if let PropertyType::Sampler | PropertyType::SamplerCmp = prop.ptype {
+            manifest.samplers.insert(prop.pname.clone(), ManifestSampler {
+                slot,
+                state: build_sampler_state(&mut states, prop)
+            });
+        } else if let Some(obj) = prop.ptype.clone().to_object(&()) {
+            manifest.textures.insert(prop.pname.clone(), ManifestTexture::new(slot, obj));
+        }
+    }
+    if let Some(v) = &syms.vformat {
+        let semantics = vformat_semantics(v).map_err(Error::Sal)?;
+        let locations = vformat_locations(v);
+        manifest.vertex_format = Some(ManifestVertexFormat {
+            name: v.name.clone(),
+            attributes: v.props.iter().zip(semantics).zip(locations).map(|((p, semantic), location)| ManifestAttribute {
+                name: p.pname.clone(),
+                ty: type_name(&p.ptype, &syms.packed_structs),
+                location,
+                semantic
+            }).collect()
+        });
+    }
+    let mut funcs = build_blendfunc_lookup_map(syms.blendfuncs.clone());
+    let semantics = output_semantics(&syms.outputs).map_err(Error::Sal)?;
+    for (v, semantic) in syms.outputs.iter().zip(semantics) {
+        manifest.outputs.insert(v.inner.pname.clone(), ManifestOutput {
+            index: v.slot.get(),
+            ty: v.inner.ptype.to_string(),
+            semantic: Some(semantic),
+            blendfunc: funcs.remove(&v.inner.pname)
+        });
+    }
+    if let Some(v) = &syms.pipeline {
+        manifest.pipeline = v.clone().to_object(&vertex_input).map(|state| ManifestPipeline {
+            name: v.name.clone(),
+            state
+        });
+    }
+    Ok(manifest)
+}
+
+/// Writes a manifest as pretty-printed JSON with the keys of every object sorted.
+pub fn write_manifest(path: &Path, manifest: &Manifest) -> Result<(), Error>
+{
+    //Objects of serde_json::Value are ordered maps, going through one sorts the keys of structs.
+    let value = serde_json::to_value(manifest).map_err(Error::Json)?;
+    let mut json = serde_json::to_string_pretty(&value).map_err(Error::Json)?;
+    json.push('\n');
+    std::fs::write(path, json).map_err(Error::Io)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::{Compiler, Config, Unit};
+
+    const VERTEX: &str = "#stage vertex
+
+#sal
+vformat struct Vertex
+{
+    vec3f Position;
+    vec2f UV;
+}
+
+const struct PerFrame
+{
+    mat4f ViewProj;
+    float Time;
+}
+#sal
+
+void main()
+{
+    gl_Position = PerFrame_ViewProj * vec4(Vertex_Position, 1.0);
+}
+";
+
+    const PIXEL: &str = "#stage pixel
+
+#sal
+const Sampler BaseSampler;
+const Texture2D:vec4f BaseTexture : BaseSampler;
+output vec4f FragColor;
+
+blendfunc FragColor
+{
+    SrcColor = SrcAlpha;
+    DstColor = OneMinusSrcAlpha;
+    SrcAlpha = One;
+    DstAlpha = Zero;
+    ColorOp = Add;
+    AlphaOp = Add;
+}
+
+pipeline Main
+{
+    DepthEnable = true;
+    DepthWriteEnable = false;
+    ScissorEnable = false;
+    RenderMode = Triangles;
+    CullingMode = BackFace;
+}
+#sal
+
+void main()
+{
+    FragColor = texture(BaseTexture, vec2(0.5));
+}
+";
+
+    #[test]
+    fn manifest()
+    {
+        let dir = std::env::temp_dir().join(format!("shaderc-manifest-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let vertex = dir.join("vertex.glsl");
+        let pixel = dir.join("pixel.glsl");
+        std::fs::write(&vertex, VERTEX).unwrap();
+        std::fs::write(&pixel, PIXEL).unwrap();
+        let output = dir.join("out.bpx");
+        let manifest = dir.join("out.json");
+        let config = Config {
+            manifest: Some(&manifest),
+            ..Config::new(vec![Unit::Path(&vertex), Unit::Path(&pixel)], &output)
+        };
+        Compiler::get("GL42").unwrap().run(config).unwrap();
+        let text = std::fs::read_to_string(&manifest).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&text).unwrap();
+        let per_frame = &json["cbuffers"]["PerFrame"];
+        assert_eq!(per_frame["size"], 80);
+        assert_eq!(per_frame["members"][1], serde_json::json!({"name": "Time", "type": "float", "offset": 64}));
+        assert_eq!(json["textures"]["BaseTexture"]["dimension"], "T2D");
+        assert_eq!(json["textures"]["BaseTexture"]["format"], serde_json::json!({"Vector": {"item": "Float", "size": 4}}));
+        assert!(json["samplers"]["BaseSampler"]["slot"].is_u64());
+        let attributes = &json["vertex_format"]["attributes"];
+        assert_eq!(attributes[1]["name"], "UV");
+        assert_eq!(attributes[1]["type"], "vec2f");
+        assert_eq!(attributes[1]["location"], 1);
+        assert_eq!(json["outputs"]["FragColor"]["index"], 0);
+        assert_eq!(json["outputs"]["FragColor"]["blendfunc"]["dst_color"], "OneMinusSrcAlpha");
+        assert_eq!(json["pipeline"]["name"], "Main");
+        assert_eq!(json["pipeline"]["state"]["depth_write_enable"], false);
+        //Top-level keys are the only ones indented by exactly two spaces.
+        let keys: Vec<&str> = text.lines().filter(|v| v.starts_with("  \"")).collect();
+        let mut sorted = keys.clone();
+        sorted.sort();
+        assert_eq!(keys, sorted);
+        assert_eq!(keys.len(), 8);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -26,38 +26,49 @@
 // NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
 // SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
-mod core;
+pub(crate) mod core;
 mod bindings;
-mod bpx;
+pub(crate) mod bpx;
 mod ext_data;
+pub(crate) mod contract;
+mod optimize;
+mod include;
+mod duplicates;
+mod manifest;
 
 pub use self::core::EnvInfo;
 
 use std::collections::BTreeMap;
 use std::fs::File;
-use ::bpx::shader::Stage;
-use log::info;
+use crate::Stage;
+use log::{debug, info};
 use crate::config::Config;
+use crate::preview::{CompileError, PreviewResult};
 use crate::targets::basic::{ShaderStage, Target};
-use crate::targets::gl::bindings::{gl_relocate_bindings, gl_test_bindings};
+use crate::targets::gl::bindings::{gl_relocate_bindings, gl_test_bindings, vk_relocate_bindings, vk_test_bindings};
 use crate::targets::gl::bpx::BpxWriter;
 use crate::targets::gl::core::ShaderBytes;
+use crate::warnings::WarningKind;
 
 use std::error::Error;
+use bp3d_symbols::{DebugInfo, PayloadKind, TargetInfo};
 
 use self::core::Symbols;
 use self::core::compile_stages;
 use self::core::gl_link_shaders;
+use self::core::preview_stage;
 
 pub struct GlTarget
 {
+    name: &'static str,
     env: EnvInfo,
     bpx_target: ::bpx::shader::Target
 }
 
 impl GlTarget {
-    pub fn new(env: EnvInfo, bpx_target: ::bpx::shader::Target) -> GlTarget {
+    pub fn new(name: &'static str, env: EnvInfo, bpx_target: ::bpx::shader::Target) -> GlTarget {
         GlTarget {
+            name,
             env,
             bpx_target
         }
@@ -66,31 +77,581 @@ impl GlTarget {
 
 impl Target for GlTarget {
     type CompileOutput = (Symbols, Vec<ShaderBytes>);
+    type Caps = EnvInfo;
 
-    fn relocate_bindings(&self, stages: &mut BTreeMap<Stage, ShaderStage>) -> Result<(), Box<dyn Error>> {
-        gl_relocate_bindings(stages);
+    fn info(&self) -> TargetInfo {
+        TargetInfo {
+            name: self.name.into(),
+            api: Some(self.env.api().into()),
+            version: Some(self.env.gl_version_str.into()),
+            supports_debug: true,
+            supports_optimize: !self.env.vulkan,
+            //Minification runs on the GLSL text, which Vulkan targets never ship.
+            supports_minify: !self.env.vulkan,
+            supports_spirv: self.env.spirv_output(),
+            //Compute, task and mesh stages are rejected by the GL targets.
+            supports_compute: false,
+            supports_int64: self.env.int64_extension.is_some(),
+            payload_format: if self.env.spirv_output() { "SPIR-V" } else { "GLSL" }.into(),
+            max_viewports: self.env.max_viewports,
+            bpx_target: Some(format!("{:?}", self.bpx_target)),
+            supported_stages: Stage::ALL.into_iter()
+                .filter(|v| v.to_bpx().is_ok() && v.to_glslang().is_ok())
+                .map(|v| v.name().into())
+                .collect()
+        }
+    }
+
+    fn relocate_bindings(&self, config: &Config, stages: &mut BTreeMap<Stage, ShaderStage>) -> Result<(), Box<dyn Error>> {
+        relocate(&self.env, stages, config)?;
         Ok(())
     }
 
     fn test_bindings(&self, stages: &BTreeMap<Stage, ShaderStage>) -> Result<(), Box<dyn Error>> {
-        gl_test_bindings(stages)?;
+        test(&self.env, stages)?;
         Ok(())
     }
 
     fn compile_link(&self, config: &Config, stages: BTreeMap<Stage, ShaderStage>) -> Result<Self::CompileOutput, Box<dyn Error>> {
-        rglslang::main(|| {
-            info!("Compiling shaders...");
-            let output = compile_stages(&self.env, &config, stages)?;
-            info!("Linking shaders...");
-            gl_link_shaders(&config, output)
-        }).map_err(Box::from)
+        //Compilation threads share the instance, glslang stays initialized until linking is done.
+        let _glslang = rglslang::instance();
+        if config.minify && self.env.spirv {
+            config.warnings.report(WarningKind::IgnoredOption, format!("--minify has no effect on target {} which writes SPIR-V", self.name));
+        }
+        info!(target: "compile", "Compiling shaders...");
+        let output = compile_stages(self, &config, stages)?;
+        info!(target: "link", "Linking shaders...");
+        let output = gl_link_shaders(&self.env, &config, output)?;
+        Ok(output)
     }
 
-    fn write_finish(&self, config: &Config, (symbols, shaders): Self::CompileOutput) -> Result<(), Box<dyn Error>> {
-        let mut bpx = BpxWriter::new(File::create(config.output)?, self.bpx_target, config.debug);
+    fn write_finish(&self, config: &Config, (symbols, mut shaders): Self::CompileOutput) -> Result<(), Box<dyn Error>> {
+        if let Some(path) = config.require_symbols {
+            info!(target: "write", "Checking symbol contract {:?}...", path);
+            contract::check_contract(&contract::load_contract(path)?, &symbols)?;
+        }
+        //Symbols are consumed by the writer, the manifest is collected beforehand.
+        let manifest = match config.manifest {
+            Some(path) => Some((path, manifest::collect_manifest(&symbols, !config.no_vertex_input)?)),
+            None => None
+        };
+        let mut bpx = BpxWriter::new(File::create(config.output)?, self.bpx_target, config.debug, !config.no_vertex_input, config.max_ext_data_size, config.warnings.clone())?;
+        bpx.set_compression(config.compression);
+        if self.env.spirv {
+            bpx.set_payload(PayloadKind::Spirv);
+        }
+        if config.debug {
+            bpx.set_debug_info(DebugInfo {
+                compiler_version: crate::repro::VERSION.into(),
+                target: self.name.into(),
+                options: config.options(),
+                stages: shaders.iter_mut().filter_map(|v| v.debug.take()).collect()
+            });
+        }
         bpx.write_symbols(symbols)?;
         bpx.write_shaders(shaders)?;
-        bpx.save()?;
+        let stats = bpx.save()?;
+        for (stage, size) in &stats.per_stage_bytes {
+            debug!(target: "write", "Stage {:?}: {} byte(s) of code", stage, size);
+        }
+        info!(target: "write", "Wrote {} byte(s): {} of symbol extended data, {} of shader code and {} of headers, tables and sections",
+              stats.total_bytes, stats.symbols_bytes, stats.per_stage_bytes.values().sum::<usize>(), stats.other_bytes());
+        if let Some((path, manifest)) = manifest {
+            info!(target: "write", "Writing binding manifest {:?}...", path);
+            manifest::write_manifest(path, &manifest)?;
+        }
         Ok(())
     }
 }
+
+fn relocate(env: &EnvInfo, stages: &BTreeMap<Stage, ShaderStage>, config: &Config) -> Result<(), crate::targets::basic::sal_compiler::Error>
+{
+    match env.vulkan {
+        true => vk_relocate_bindings(stages, &config.warnings),
+        false => gl_relocate_bindings(stages, env.max_cbuffer_bindings, &config.warnings)
+    }
+}
+
+fn test(env: &EnvInfo, stages: &BTreeMap<Stage, ShaderStage>) -> Result<(), crate::targets::basic::sal_compiler::Error>
+{
+    match env.vulkan {
+        true => vk_test_bindings(stages),
+        false => gl_test_bindings(stages)
+    }
+}
+
+/// Compiles a single stage for a preview: bindings are relocated and tested as for a full build but
+/// nothing is linked nor written.
+pub fn preview(target: &GlTarget, config: &Config, stages: BTreeMap<Stage, ShaderStage>, stage: Stage) -> Result<PreviewResult, CompileError>
+{
+    if let Err(e) = relocate(&target.env, &stages, config).and_then(|_| test(&target.env, &stages)) {
+        return Ok(PreviewResult::sal_error(e));
+    }
+    match rglslang::main(|| preview_stage(target, config, stages, stage)) {
+        Ok(v) => Ok(PreviewResult::from_glslang(v.glsl, &v.log, v.success.then(|| v.code.into_bytes()))),
+        Err(self::core::Error::Stage(e)) => Err(CompileError::Stage(e)),
+        Err(e) => Ok(PreviewResult::sal_error(e))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::collections::BTreeMap;
+    use crate::Stage;
+    use bp3d_sal::ast::tree::{ArrayItemType, ArrayType, Attribute, BaseType, CullingMode, PipelineStatement, Property, PropertyType, Struct, TextureType, VarlistStatement};
+    use bp3d_symbols::{FromBpx, PipelineObject, ToBpx};
+    use rglslang::shader::Part;
+    use crate::targets::basic::{BasicAst, ShaderStage, Slot};
+    use crate::targets::basic::parts::{assemble_stage_parts, PART_EXTENSIONS, PART_SAL, PART_VERSION, StageSource};
+    use crate::targets::gl::bindings::{gl_relocate_bindings, gl_test_bindings, vk_relocate_bindings, vk_test_bindings};
+    use crate::targets::gl::core::{Error, int64_extension, layered_rendering_extension};
+    use crate::targets::gl::ext_data::ToObject;
+    use crate::targets::basic::sal_compiler::Error as SalError;
+    use crate::targets::sal_to_glsl::{Bindings, translate_sal_to_glsl};
+    use crate::targets::{es31, gl40, gl42, vk};
+    use crate::warnings::Reporter;
+
+    fn stages(layered_rendering: bool, viewport_count: u32, list: &[Stage]) -> BTreeMap<Stage, ShaderStage>
+    {
+        let mut pipeline = PipelineStatement::new("Test".into());
+        pipeline.layered_rendering = layered_rendering;
+        pipeline.viewport_count = viewport_count;
+        let mut map = BTreeMap::new();
+        for stage in list {
+            let mut statements = BasicAst::new();
+            if *stage == Stage::Vertex {
+                statements.pipeline = Some(pipeline.clone());
+            }
+            map.insert(*stage, ShaderStage {
+                statements,
+                strings: Vec::new(),
+                sources: Vec::new()
+            });
+        }
+        map
+    }
+
+    #[test]
+    fn gl_part_order()
+    {
+        for (target, version) in [(gl40::target(), "#version 400 core\n"), (gl42::target(), "#version 420 core\n")] {
+            let parts = assemble_stage_parts(&target, &target.env, Stage::Vertex, StageSource {
+                version: format!("#version {} core\n", target.env.gl_version_int),
+                extensions: target.env.extensions,
+                defines: &[],
+                sal: String::new(),
+                user: vec![Part::new_with_name("void main() {}\n", "vertex.glsl")]
+            });
+            let names: Vec<&str> = parts.iter().map(|v| v.name().unwrap()).collect();
+            assert_eq!(names, [PART_VERSION, PART_SAL, "vertex.glsl"]);
+            assert_eq!(parts[0].code(), version);
+        }
+    }
+
+    #[test]
+    fn gl_layered_rendering()
+    {
+        let gl40 = gl40::target();
+        let gl42 = gl42::target();
+        let plain = stages(false, 1, &[Stage::Vertex, Stage::Pixel]);
+        assert_eq!(layered_rendering_extension(&gl40.env, &plain).unwrap(), None);
+        let layered = stages(true, 1, &[Stage::Vertex, Stage::Pixel]);
+        assert_eq!(layered_rendering_extension(&gl40.env, &layered).unwrap(), gl40.env.layer_extension);
+        let geometry = stages(true, 2, &[Stage::Vertex, Stage::Geometry, Stage::Pixel]);
+        assert_eq!(layered_rendering_extension(&gl42.env, &geometry).unwrap(), None);
+        assert!(matches!(layered_rendering_extension(&gl40.env, &geometry), Err(Error::ViewportCount(2, 1))));
+        let zero = stages(false, 0, &[Stage::Vertex]);
+        assert!(matches!(layered_rendering_extension(&gl42.env, &zero), Err(Error::ViewportCount(0, _))));
+        let mut env = gl42::target().env;
+        env.layer_extension = None;
+        assert!(matches!(layered_rendering_extension(&env, &layered), Err(Error::LayeredRendering)));
+    }
+
+    #[test]
+    fn gl_pipeline_preset()
+    {
+        let mut preset = PipelineStatement::new("OpaqueDefault".into());
+        preset.depth_write_enable = false;
+        preset.culling_mode = CullingMode::FrontFace;
+        let mut main = PipelineStatement::inherit("Main".into(), preset);
+        main.culling_mode = CullingMode::BackFace;
+        let obj = main.to_object(&true).unwrap();
+        for debug in [false, true] {
+            let obj1 = PipelineObject::from_bpx(&obj.to_bpx(debug).unwrap()).unwrap();
+            assert_eq!(obj1.culling_mode, CullingMode::BackFace);
+            assert!(!obj1.depth_write_enable);
+            assert!(obj1.depth_enable);
+            assert!(obj1.vertex_input);
+        }
+    }
+
+    #[test]
+    fn gl_int64()
+    {
+        let prop = Property {
+            pname: "ParticleBaseOffset".into(),
+            ptype: PropertyType::Scalar(BaseType::Uint64),
+            pattr: None,
+            deprecated: None,
+            pformat: None,
+            default: None,
+            span: Default::default()
+        };
+        let layout = Struct {
+            name: "__Root".into(),
+            attr: None,
+            deprecated: None,
+            props: vec![prop.clone()],
+            span: Default::default()
+        };
+        let mut stages = stages(false, 1, &[Stage::Vertex]);
+        let empty = Struct {
+            name: "__Root".into(),
+            attr: None,
+            deprecated: None,
+            props: Vec::new(),
+            span: Default::default()
+        };
+        assert_eq!(int64_extension(&gl42::target().env, &empty, &stages).unwrap(), None);
+        stages.get_mut(&Stage::Vertex).unwrap().statements.root_constants.push(Slot::new(prop));
+        let gl42 = gl42::target();
+        let ext = int64_extension(&gl42.env, &layout, &stages).unwrap();
+        assert_eq!(ext, Some("GL_ARB_gpu_shader_int64"));
+        let glsl = translate_sal_to_glsl(gl42.env.explicit_bindings, &layout, &stages[&Stage::Vertex].statements).unwrap();
+        assert!(glsl.contains("uint64_t ParticleBaseOffset;"));
+        let extensions: Vec<&str> = ext.into_iter().collect();
+        let parts = assemble_stage_parts(&gl42, &gl42.env, Stage::Vertex, StageSource {
+            version: format!("#version {} core\n", gl42.env.gl_version_int),
+            extensions: &extensions,
+            defines: &[],
+            sal: glsl,
+            user: Vec::new()
+        });
+        assert_eq!(parts[1].name(), Some(PART_EXTENSIONS));
+        assert_eq!(parts[1].code(), "#extension GL_ARB_gpu_shader_int64 : require\n");
+        assert!(matches!(int64_extension(&gl40::target().env, &layout, &stages), Err(Error::Int64)));
+    }
+
+    #[test]
+    fn vk_bindings()
+    {
+        let prop = |name: &str, ptype| Property {
+            pname: name.into(),
+            ptype,
+            pattr: None,
+            deprecated: None,
+            pformat: None,
+            default: None,
+            span: Default::default()
+        };
+        let texture = PropertyType::Texture2D(TextureType::Scalar(BaseType::Float));
+        let mut stages = stages(false, 1, &[Stage::Vertex, Stage::Pixel]);
+        let vertex = &mut stages.get_mut(&Stage::Vertex).unwrap().statements;
+        vertex.cbuffers.push(Slot::new(Struct {
+            name: "Material".into(),
+            attr: None,
+            deprecated: None,
+            props: vec![prop("Color", PropertyType::Scalar(BaseType::Float))],
+            span: Default::default()
+        }));
+        vertex.objects.push(Slot::new(prop("Base", texture.clone())));
+        vertex.objects.push(Slot::new(prop("BaseSampler", PropertyType::Sampler)));
+        let pixel = &mut stages.get_mut(&Stage::Pixel).unwrap().statements;
+        pixel.objects.push(Slot::new(prop("Base", texture.clone())));
+        pixel.objects.push(Slot::new(prop("Normals", texture)));
+        vk_relocate_bindings(&stages, &Reporter::new()).unwrap();
+        vk_test_bindings(&stages).unwrap();
+        let vertex = &stages[&Stage::Vertex].statements;
+        let pixel = &stages[&Stage::Pixel].statements;
+        let slots = [vertex.cbuffers[0].slot.get(), vertex.objects[0].slot.get(), vertex.objects[1].slot.get(), pixel.objects[1].slot.get()];
+        //Binding 0 is reserved for root constants and all other bindings share one space.
+        assert_eq!(slots, [1, 2, 3, 4]);
+        assert_eq!(pixel.objects[0].slot.get(), vertex.objects[0].slot.get());
+        let glsl = translate_sal_to_glsl(vk::target().env.bindings(), &Struct {
+            name: "__Root".into(),
+            attr: None,
+            deprecated: None,
+            props: Vec::new(),
+            span: Default::default()
+        }, pixel).unwrap();
+        assert_eq!(glsl, "layout (set = 0, binding = 2) uniform sampler2D Base;\nlayout (set = 0, binding = 4) uniform sampler2D Normals;");
+    }
+
+    #[test]
+    fn texture_array_bindings()
+    {
+        let prop = |name: &str, ptype| Property {
+            pname: name.into(),
+            ptype,
+            pattr: None,
+            deprecated: None,
+            pformat: None,
+            default: None,
+            span: Default::default()
+        };
+        let root = Struct {
+            name: "__Root".into(),
+            attr: None,
+            deprecated: None,
+            props: Vec::new(),
+            span: Default::default()
+        };
+        let texture = TextureType::Scalar(BaseType::Float);
+        let stages = || {
+            let mut stages = stages(false, 1, &[Stage::Pixel]);
+            let pixel = &mut stages.get_mut(&Stage::Pixel).unwrap().statements;
+            pixel.objects.push(Slot::new(prop("ShadowMaps", PropertyType::Array(ArrayType {
+                size: 4,
+                item: ArrayItemType::Texture2D(texture)
+            }))));
+            pixel.objects.push(Slot::new(prop("Normals", PropertyType::Texture2D(texture))));
+            stages
+        };
+        //Each element of a texture array consumes a texture unit on GL...
+        let gl = stages();
+        gl_relocate_bindings(&gl, None, &Reporter::new()).unwrap();
+        gl_test_bindings(&gl).unwrap();
+        let glsl = translate_sal_to_glsl(gl42::target().env.bindings(), &root, &gl[&Stage::Pixel].statements).unwrap();
+        assert_eq!(glsl, "layout (binding = 1) uniform sampler2D ShadowMaps[4];\nlayout (binding = 0) uniform sampler2D Normals;");
+        //...while the whole array is a single descriptor on Vulkan.
+        let vk = stages();
+        vk_relocate_bindings(&vk, &Reporter::new()).unwrap();
+        vk_test_bindings(&vk).unwrap();
+        let glsl = translate_sal_to_glsl(vk::target().env.bindings(), &root, &vk[&Stage::Pixel].statements).unwrap();
+        assert_eq!(glsl, "layout (set = 0, binding = 2) uniform sampler2D ShadowMaps[4];\nlayout (set = 0, binding = 1) uniform sampler2D Normals;");
+    }
+
+    #[test]
+    fn stable_bindings()
+    {
+        let prop = |name: &str, ptype, pattr| Property {
+            pname: name.into(),
+            ptype,
+            pattr,
+            deprecated: None,
+            pformat: None,
+            default: None,
+            span: Default::default()
+        };
+        let texture = PropertyType::Texture2D(TextureType::Scalar(BaseType::Float));
+        let relocate = |extra: Option<(Stage, &str)>| {
+            let mut stages = stages(false, 1, &[Stage::Vertex, Stage::Pixel]);
+            let vertex = &mut stages.get_mut(&Stage::Vertex).unwrap().statements;
+            vertex.objects.push(Slot::new(prop("HeightMap", texture.clone(), None)));
+            vertex.objects.push(Slot::new(prop("HeightSampler", PropertyType::Sampler, None)));
+            let pixel = &mut stages.get_mut(&Stage::Pixel).unwrap().statements;
+            pixel.objects.push(Slot::new(prop("Shadow", texture.clone(), Some(Attribute::Order(2)))));
+            pixel.objects.push(Slot::new(prop("Normals", texture.clone(), None)));
+            pixel.objects.push(Slot::new(prop("Albedo", texture.clone(), None)));
+            pixel.objects.push(Slot::new(prop("BaseSampler", PropertyType::Sampler, None)));
+            pixel.objects.push(Slot::new(prop("HeightMap", texture.clone(), None)));
+            if let Some((stage, name)) = extra {
+                stages.get_mut(&stage).unwrap().statements.objects.push(Slot::new(prop(name, texture.clone(), None)));
+            }
+            gl_relocate_bindings(&stages, None, &Reporter::new()).unwrap();
+            gl_test_bindings(&stages).unwrap();
+            stages.values()
+                .flat_map(|v| v.statements.objects.iter())
+                .map(|v| (v.inner.pname.clone(), v.slot.get()))
+                .collect::<BTreeMap<String, u32>>()
+        };
+        let slots = |list: &[(&str, u32)]| list.iter().map(|(k, v)| (k.to_string(), *v)).collect::<BTreeMap<String, u32>>();
+        //Explicit slots are fixed, other textures and samplers take the lowest free slots by name.
+        let base = relocate(None);
+        assert_eq!(base, slots(&[("Albedo", 0), ("BaseSampler", 0), ("HeightMap", 1), ("HeightSampler", 1), ("Normals", 3), ("Shadow", 2)]));
+        //A texture sorting after all others does not move any existing binding, whatever its stage.
+        let mut expected = base.clone();
+        expected.insert("Specular".into(), 4);
+        assert_eq!(relocate(Some((Stage::Vertex, "Specular"))), expected);
+        assert_eq!(relocate(Some((Stage::Pixel, "Specular"))), expected);
+        //Otherwise only the textures sorting after the new one move.
+        let detail = relocate(Some((Stage::Vertex, "Detail")));
+        assert_eq!(detail, slots(&[("Albedo", 0), ("BaseSampler", 0), ("Detail", 1), ("HeightMap", 3), ("HeightSampler", 1), ("Normals", 4), ("Shadow", 2)]));
+        assert_eq!(relocate(Some((Stage::Pixel, "Detail"))), detail);
+    }
+
+    #[test]
+    fn es31_target()
+    {
+        let es31 = es31::target();
+        assert_eq!(es31.env.version_directive(), "#version 310 es\n");
+        assert_eq!(gl42::target().env.version_directive(), "#version 420 core\n");
+        assert_eq!(es31.env.bindings(), Bindings::Es);
+        let mut stages = stages(false, 1, &[Stage::Pixel]);
+        stages.get_mut(&Stage::Pixel).unwrap().statements.cbuffers.push(Slot::new(Struct {
+            name: "Material".into(),
+            attr: Some(Attribute::Order(30)),
+            deprecated: None,
+            props: vec![Property {
+                pname: "Color".into(),
+                ptype: PropertyType::Scalar(BaseType::Float),
+                pattr: None,
+                deprecated: None,
+                pformat: None,
+                default: None,
+                span: Default::default()
+            }],
+            span: Default::default()
+        }));
+        match gl_relocate_bindings(&stages, es31.env.max_cbuffer_bindings, &Reporter::new()) {
+            Err(SalError::CBufferLimit(name, slot, max)) => assert_eq!((&*name, slot, max), ("Material", 30, 24)),
+            _ => panic!("expected a uniform buffer limit error")
+        }
+        gl_relocate_bindings(&stages, gl42::target().env.max_cbuffer_bindings, &Reporter::new()).unwrap();
+    }
+
+    #[test]
+    fn gl46_spirv()
+    {
+        use std::fs::File;
+        use std::io::{BufReader, Read};
+        use bp3d_symbols::{PayloadKind, PAYLOAD_SECTION_TYPE};
+        use bpx::shader::ShaderPack;
+        use rglslang::spirv::SPIRV_MAGIC;
+        use crate::{Compiler, Config, Unit};
+        use crate::warnings::WarningKind;
+        const VERTEX: &str = "#stage vertex
+
+#sal
+vformat struct Vertex
+{
+    vec3f Position;
+}
+
+const struct PerFrame
+{
+    mat4f ViewProj;
+}
+#sal
+
+void main()
+{
+    gl_Position = PerFrame_ViewProj * vec4(Vertex_Position, 1.0);
+}
+";
+        const PIXEL: &str = "#stage pixel
+
+#sal
+const Sampler BaseSampler;
+const Texture2D:vec4f BaseTexture : BaseSampler;
+output vec4f FragColor;
+#sal
+
+void main()
+{
+    FragColor = texture(BaseTexture, vec2(0.5));
+}
+";
+        let dir = std::env::temp_dir().join(format!("shaderc-gl46-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let vertex = dir.join("vertex.glsl");
+        let pixel = dir.join("pixel.glsl");
+        std::fs::write(&vertex, VERTEX).unwrap();
+        std::fs::write(&pixel, PIXEL).unwrap();
+        let output = dir.join("out.bpx");
+        let warnings = Reporter::new();
+        let config = Config {
+            minify: true,
+            warnings: warnings.clone(),
+            ..Config::new(vec![Unit::Path(&vertex), Unit::Path(&pixel)], &output)
+        };
+        Compiler::get("GL46").unwrap().run(config).unwrap();
+        assert!(warnings.records().iter().any(|v| v.kind == WarningKind::IgnoredOption));
+        let pack = ShaderPack::open(BufReader::new(File::open(&output).unwrap())).unwrap();
+        assert_eq!(pack.get_settings().target, bpx::shader::Target::GL46);
+        {
+            let shaders = pack.shaders();
+            let mut count = 0;
+            for handle in &shaders {
+                let shader = shaders.load(handle).unwrap();
+                assert_eq!(shader.data[..4], SPIRV_MAGIC.to_ne_bytes());
+                count += 1;
+            }
+            assert_eq!(count, 2);
+        }
+        let container = pack.into_inner();
+        let handle = container.sections().find_by_type(PAYLOAD_SECTION_TYPE).unwrap();
+        let mut data = Vec::new();
+        container.sections().load(handle).unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(PayloadKind::decode(&data).unwrap(), PayloadKind::Spirv);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn debug_info()
+    {
+        use std::fs::File;
+        use std::io::{BufReader, Read};
+        use bp3d_symbols::{DebugInfo, DEBUG_INFO_SECTION_TYPE};
+        use bpx::shader::ShaderPack;
+        use crate::{Compiler, Config, Unit};
+        const VERTEX: &str = "#stage vertex
+
+#sal
+vformat struct Vertex
+{
+    vec3f Position;
+}
+#sal
+
+void main()
+{
+    gl_Position = vec4(Vertex_Position, 1.0);
+}
+";
+        const PIXEL: &str = "#stage pixel
+
+#sal
+const struct Material
+{
+    vec4f Color;
+}
+output vec4f FragColor;
+#sal
+
+void main()
+{
+    FragColor = Material_Color;
+}
+";
+        let dir = std::env::temp_dir().join(format!("shaderc-debug-info-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let vertex = dir.join("vertex.glsl");
+        let pixel = dir.join("pixel.glsl");
+        std::fs::write(&vertex, VERTEX).unwrap();
+        std::fs::write(&pixel, PIXEL).unwrap();
+        let load = |debug: bool| {
+            let output = dir.join(format!("out-{}.bpx", debug));
+            let config = Config {
+                debug,
+                defines: vec!["FOG".into()],
+                ..Config::new(vec![Unit::Path(&vertex), Unit::Path(&pixel)], &output)
+            };
+            Compiler::get("GL42").unwrap().run(config).unwrap();
+            let container = ShaderPack::open(BufReader::new(File::open(&output).unwrap())).unwrap().into_inner();
+            container.sections().find_by_type(DEBUG_INFO_SECTION_TYPE).map(|handle| {
+                let mut data = Vec::new();
+                container.sections().load(handle).unwrap().read_to_end(&mut data).unwrap();
+                DebugInfo::decode(&data).unwrap()
+            })
+        };
+        assert!(load(false).is_none());
+        let info = load(true).unwrap();
+        assert_eq!(info.target, "GL42");
+        assert_eq!(info.compiler_version, crate::repro::VERSION);
+        assert_eq!(info.options["debug"], "true");
+        assert_eq!(info.options["defines"], "[\"FOG\"]");
+        let stages: Vec<&str> = info.stages.iter().map(|v| &*v.stage).collect();
+        assert_eq!(stages, ["vertex", "pixel"]);
+        let pixel_stage = info.get_stage("pixel").unwrap();
+        assert_eq!(pixel_stage.sources.len(), 1);
+        assert_eq!(pixel_stage.sources[0].name, pixel.to_string_lossy());
+        assert_eq!(pixel_stage.sources[0].text, PIXEL);
+        //The translated code declares the constant buffer which the source only has in SAL.
+        assert!(pixel_stage.code.contains("Material_Color"));
+        assert!(pixel_stage.code.contains("uniform"));
+        assert_eq!(info.get_stage("vertex").unwrap().sources[0].text, VERTEX);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,198 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use log::debug;
+use bp3d_sal::ast::tree::{Attribute, PropertyType};
+use crate::Stage;
+use crate::targets::basic::ShaderStage;
+use crate::targets::minify::identifiers;
+
+fn is_sampler(p: &PropertyType<usize>) -> bool
+{
+    matches!(p, PropertyType::Sampler | PropertyType::SamplerCmp)
+}
+
+/// Removes the constant buffers and objects which are not referenced by the GLSL code of any
+/// stage; samplers have no GLSL counterpart and are kept as long as a remaining texture uses them.
+fn prune(stages: &mut BTreeMap<Stage, ShaderStage>, used: &HashSet<String>)
+{
+    for (stage, shader) in stages.iter_mut() {
+        let ast = &mut shader.statements;
+        ast.cbuffers.retain(|v| {
+            let flag = v.inner.props.iter().any(|p| used.contains(&[&*v.inner.name, &*p.pname].join("_")));
+            if !flag {
+                debug!(target: "optimize", "Removing unused constant buffer '{}' from stage {:?}", v.inner.name, stage);
+            }
+            flag
+        });
+        ast.objects.retain(|v| {
+            let flag = is_sampler(&v.inner.ptype) || used.contains(&v.inner.pname);
+            if !flag {
+                debug!(target: "optimize", "Removing unused object '{}' from stage {:?}", v.inner.pname, stage);
+            }
+            flag
+        });
+    }
+    let samplers: HashSet<String> = stages.values()
+        .flat_map(|v| v.statements.objects.iter())
+        .filter_map(|v| match &v.inner.pattr {
+            Some(Attribute::Identifier(name)) => Some(name.clone()),
+            _ => None
+        })
+        .collect();
+    for (stage, shader) in stages.iter_mut() {
+        shader.statements.objects.retain(|v| {
+            let flag = !is_sampler(&v.inner.ptype) || samplers.contains(&v.inner.pname) || used.contains(&v.inner.pname);
+            if !flag {
+                debug!(target: "optimize", "Removing unused sampler '{}' from stage {:?}", v.inner.pname, stage);
+            }
+            flag
+        });
+    }
+}
+
+/// Assigns short names to the GLSL identifiers of internal symbols. Members of internal constant
+/// buffers can always be renamed; internal textures only with explicit bindings as they are
+/// otherwise located by name.
+fn short_names(stages: &BTreeMap<Stage, ShaderStage>, used: &HashSet<String>, explicit_bindings: bool) -> HashMap<String, String>
+{
+    let mut taken: HashSet<&str> = used.iter().map(|v| &**v).collect();
+    let mut internal = Vec::new();
+    for shader in stages.values() {
+        let ast = &shader.statements;
+        taken.extend(ast.root_constants.iter().chain(ast.outputs.iter()).chain(ast.objects.iter()).map(|v| &*v.inner.pname));
+        taken.extend(ast.cbuffers.iter().map(|v| &*v.inner.name).chain(ast.packed_structs.iter().map(|v| &*v.name)));
+        for v in ast.cbuffers.iter().filter(|v| !v.external.get()) {
+            internal.extend(v.inner.props.iter().map(|p| [&*v.inner.name, &*p.pname].join("_")));
+        }
+        if explicit_bindings {
+            internal.extend(ast.objects.iter()
+                .filter(|v| !v.external.get() && !is_sampler(&v.inner.ptype))
+                .map(|v| v.inner.pname.clone()));
+        }
+    }
+    let mut names = HashMap::new();
+    let mut next = 0;
+    for name in internal {
+        if names.contains_key(&name) {
+            continue;
+        }
+        let short = loop {
+            let v = format!("_{}", next);
+            next += 1;
+            if !taken.contains(&*v) {
+                break v;
+            }
+        };
+        debug!(target: "optimize", "Renaming '{}' to '{}'", name, short);
+        names.insert(name, short);
+    }
+    names
+}
+
+/// Removes unused SAL symbols from all stages and returns the short names to substitute in the
+/// code of each stage.
+pub fn optimize_stages(stages: &mut BTreeMap<Stage, ShaderStage>, explicit_bindings: bool) -> HashMap<String, String>
+{
+    let used: HashSet<String> = stages.values()
+        .flat_map(|v| v.strings.iter())
+        .flat_map(|v| identifiers(v.code()))
+        .collect();
+    prune(stages, &used);
+    short_names(stages, &used, explicit_bindings)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::fs::File;
+    use std::io::BufReader;
+    use bpx::shader::ShaderPack;
+    use bpx::shader::symbol::Type;
+    use crate::{Compiler, Config, Unit};
+
+    const SHADER: &str = "#stage pixel
+
+#sal
+const struct Used { vec4f Color; }
+const struct Unused { vec4f Color; }
+const Sampler BaseSampler;
+const Texture2D:vec4f BaseTexture : BaseSampler;
+output vec4f FragColor;
+#sal
+
+void main()
+{
+    // Unused_Color is only mentioned in a comment.
+    FragColor = Used_Color;
+}
+";
+
+    // Returns the names of all symbols and of the constant buffers written to the pack.
+    fn symbols(optimize: bool) -> (Vec<String>, Vec<String>)
+    {
+        let dir = std::env::temp_dir().join(format!("shaderc-optimize-{}-{}", std::process::id(), optimize));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("pixel.glsl");
+        std::fs::write(&path, SHADER).unwrap();
+        let output = dir.join("out.bpx");
+        let config = Config {
+            optimize,
+            ..Config::new(vec![Unit::Path(&path)], &output)
+        };
+        Compiler::get("GL42").unwrap().run(config).unwrap();
+        let pack = ShaderPack::open(BufReader::new(File::open(&output).unwrap())).unwrap();
+        let mut names = Vec::new();
+        let mut cbuffers = Vec::new();
+        {
+            let symbols = pack.symbols().unwrap();
+            for info in symbols.iter() {
+                let name: String = symbols.load_name(info).unwrap().into();
+                if matches!(info.ty, Type::ConstantBuffer) {
+                    cbuffers.push(name.clone());
+                }
+                names.push(name);
+            }
+        }
+        drop(pack);
+        std::fs::remove_dir_all(&dir).unwrap();
+        (names, cbuffers)
+    }
+
+    #[test]
+    fn prune_unused_cbuffers()
+    {
+        let (names, cbuffers) = symbols(true);
+        assert_eq!(cbuffers, ["Used"]);
+        assert!(!names.iter().any(|v| v == "BaseTexture" || v == "BaseSampler"));
+        let (names, cbuffers) = symbols(false);
+        assert_eq!(cbuffers.len(), 2);
+        assert!(names.iter().any(|v| v == "BaseTexture"));
+    }
+}
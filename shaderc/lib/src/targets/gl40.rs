@@ -26,12 +26,17 @@
 // NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
 // SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
+use std::collections::BTreeMap;
 use bpx::shader::Target::GL40;
 use crate::config::Config;
 use std::error::Error;
-use crate::targets::basic::Target;
+use bp3d_symbols::TargetInfo;
+use crate::preview::{CompileError, PreviewResult};
+use crate::Stage;
+use crate::targets::basic::{ShaderStage, Target};
 use crate::targets::gl::EnvInfo;
 use crate::targets::gl::GlTarget;
+use crate::targets::layout::LayoutRule;
 
 //TODO: At shader initialization, procedure for each binding:
 // - glUseProgram(prog)
@@ -39,12 +44,36 @@ use crate::targets::gl::GlTarget;
 // for constant buffers - glUniformBlockBinding(prog, location, binding)
 // for objects - glUniform1i(location, binding)
 
-pub fn build(config: Config) -> Result<(), Box<dyn Error>>
+pub(crate) fn target() -> GlTarget
 {
-    let target = GlTarget::new(EnvInfo {
+    GlTarget::new("GL40", EnvInfo {
         gl_version_int: 400,
         gl_version_str: "4.0",
-        explicit_bindings: false
-    }, GL40);
-    target.run(&config)
+        explicit_bindings: false,
+        extensions: &[],
+        max_viewports: 1,
+        layer_extension: Some("GL_ARB_shader_viewport_layer_array"),
+        int64_extension: None,
+        vulkan: false,
+        spirv: false,
+        es: false,
+        max_cbuffer_bindings: None,
+        binding_shifts: &[],
+        layout: LayoutRule::Std140
+    }, GL40)
+}
+
+pub fn build(config: Config) -> Result<(), Box<dyn Error>>
+{
+    target().run(&config)
+}
+
+pub fn info() -> TargetInfo
+{
+    target().info()
+}
+
+pub fn preview(config: &Config, stages: BTreeMap<Stage, ShaderStage>, stage: Stage) -> Result<PreviewResult, CompileError>
+{
+    crate::targets::gl::preview(&target(), config, stages, stage)
 }
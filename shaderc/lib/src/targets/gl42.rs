@@ -26,18 +26,47 @@
 // NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
 // SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
+use std::collections::BTreeMap;
 use bpx::shader::Target::GL42;
 use crate::config::Config;
 use std::error::Error;
-use crate::targets::basic::Target;
+use bp3d_symbols::TargetInfo;
+use crate::preview::{CompileError, PreviewResult};
+use crate::Stage;
+use crate::targets::basic::{ShaderStage, Target};
 use crate::targets::gl::{EnvInfo, GlTarget};
+use crate::targets::layout::LayoutRule;
 
-pub fn build(config: Config) -> Result<(), Box<dyn Error>>
+pub(crate) fn target() -> GlTarget
 {
-    let target = GlTarget::new(EnvInfo {
+    GlTarget::new("GL42", EnvInfo {
         gl_version_int: 420,
         gl_version_str: "4.2",
-        explicit_bindings: true
-    }, GL42);
-    target.run(&config)
+        explicit_bindings: true,
+        extensions: &[],
+        max_viewports: 16,
+        layer_extension: Some("GL_ARB_shader_viewport_layer_array"),
+        int64_extension: Some("GL_ARB_gpu_shader_int64"),
+        vulkan: false,
+        spirv: false,
+        es: false,
+        max_cbuffer_bindings: None,
+        binding_shifts: &[],
+        layout: LayoutRule::Std140
+    }, GL42)
+}
+
+pub fn build(config: Config) -> Result<(), Box<dyn Error>>
+{
+    target().run(&config)
+}
+
+pub fn info() -> TargetInfo
+{
+    target().info()
+}
+
+pub fn preview(config: &Config, stages: BTreeMap<Stage, ShaderStage>, stage: Stage) -> Result<PreviewResult, CompileError>
+{
+    crate::targets::gl::preview(&target(), config, stages, stage)
 }
@@ -0,0 +1,405 @@
+// Copyright (c) 2021, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+
+use std::ops::{Deref, DerefMut};
+use log::{error, warn};
+use bp3d_sal::ast::tree::{ArrayItemType, Attribute, BaseType, Property, PropertyType, Struct, VectorType};
+use thiserror::Error;
+
+pub use bp3d_symbols::LayoutRule;
+
+// Layout rules for paddings
+// https://www.khronos.org/registry/OpenGL/specs/gl/glspec46.core.pdf
+// Section 7.6.2.2 (std140 and std430)
+// https://registry.khronos.org/vulkan/specs/1.3-extensions/html/vkspec.html#interfaces-resources-layout
+// (scalar block layout)
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("attempt to reference undeclared packed struct")]
+    Undeclared
+}
+
+pub fn size_of_base_type(t: BaseType) -> usize
+{
+    match t {
+        BaseType::Int => 4,
+        BaseType::Float => 4,
+        BaseType::Uint => 4,
+        BaseType::Bool => 4,
+        BaseType::Double => 8,
+        BaseType::Int64 => 8,
+        BaseType::Uint64 => 8
+    }
+}
+
+fn vector_alignment(rule: LayoutRule, v: &VectorType) -> usize
+{
+    let item = size_of_base_type(v.item);
+    match (rule, v.size) {
+        (LayoutRule::Scalar, _) => item,
+        (_, 2) => 2 * item,
+        (_, 3 | 4) => 4 * item,
+        _ => 0
+    }
+}
+
+//Arrays and structs are aligned to at least a vec4 under std140.
+fn round_to_vec4(base_alignment: usize) -> usize
+{
+    let vec4 = if base_alignment > 16 { 32 } else { 16 };
+    round_to_base_alignment(base_alignment, vec4)
+}
+
+fn round_to_base_alignment(size: usize, base_alignment: usize) -> usize
+{
+    match base_alignment {
+        0 => size,
+        _ => (size + base_alignment - 1) / base_alignment * base_alignment
+    }
+}
+
+/// Returns the base alignment and the size of an array of `count` items.
+fn array_layout(rule: LayoutRule, base_alignment: usize, size: usize, count: usize) -> (usize, usize)
+{
+    let base_alignment = match rule {
+        LayoutRule::Std140 => round_to_vec4(base_alignment),
+        _ => base_alignment
+    };
+    (base_alignment, count * round_to_base_alignment(size, base_alignment))
+}
+
+/// Returns the base alignment and the size of a column major matrix; it is laid out as an array
+/// of column vectors.
+fn matrix_layout(rule: LayoutRule, m: &VectorType) -> (usize, usize)
+{
+    let size = size_of_base_type(m.item) * m.size as usize;
+    array_layout(rule, vector_alignment(rule, m), size, m.size as usize)
+}
+
+fn vector_layout(rule: LayoutRule, v: &VectorType) -> (usize, usize)
+{
+    (vector_alignment(rule, v), size_of_base_type(v.item) * v.size as usize)
+}
+
+fn handle_layout() -> (usize, usize)
+{
+    warn!(target: "compile", "Attempted to compute size of handle object; object handles are not permitted in constant buffers!");
+    (0, 0)
+}
+
+pub struct Offset<T>
+{
+    pub inner: T,
+    pub aligned_offset: usize,
+    pub offset: usize,
+    pub size: usize,
+    pub base_alignment: usize
+}
+
+impl<T> Deref for Offset<T>
+{
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target
+    {
+        &self.inner
+    }
+}
+
+impl<T> DerefMut for Offset<T>
+{
+    fn deref_mut(&mut self) -> &mut Self::Target
+    {
+        &mut self.inner
+    }
+}
+
+pub struct StructOffset
+{
+    pub name: String,
+    pub attr: Option<Attribute>,
+    pub deprecated: Option<String>,
+    pub props: Vec<Offset<Property<usize>>>,
+    pub size: usize,
+    pub base_alignment: usize,
+    /// Rule used to compute the offsets, recorded in the shader pack.
+    pub rule: LayoutRule
+}
+
+fn find_struct(packed_structs: &Vec<StructOffset>, s: usize) -> Result<&StructOffset, Error>
+{
+    packed_structs.get(s).ok_or_else(|| {
+        error!(target: "compile", "Couldn't find referenced struct '{}', is it declared in the right order?", s);
+        Error::Undeclared
+    })
+}
+
+/// Returns the base alignment and the size of a member.
+fn member_layout(rule: LayoutRule, ptype: &PropertyType<usize>, packed_structs: &Vec<StructOffset>) -> Result<(usize, usize), Error>
+{
+    let layout = match ptype {
+        PropertyType::Scalar(t) => (size_of_base_type(*t), size_of_base_type(*t)),
+        PropertyType::Vector(v) => vector_layout(rule, v),
+        PropertyType::Matrix(m) => matrix_layout(rule, m),
+        PropertyType::StructRef(s) => {
+            let st = find_struct(packed_structs, *s)?;
+            (st.base_alignment, st.size)
+        },
+        PropertyType::Array(a) => {
+            let (base_alignment, size) = match &a.item {
+                ArrayItemType::Vector(v) => vector_layout(rule, v),
+                ArrayItemType::Matrix(m) => matrix_layout(rule, m),
+                ArrayItemType::StructRef(s) => {
+                    let st = find_struct(packed_structs, *s)?;
+                    (st.base_alignment, st.size)
+                },
+                _ => handle_layout()
+            };
+            array_layout(rule, base_alignment, size, a.size as usize)
+        },
+        _ => handle_layout()
+    };
+    Ok(layout)
+}
+
+/// Computes the offset of each member of a struct under the given layout rule; structs referenced
+/// by members must have been compiled with the same rule.
+pub fn compile_struct(st: Struct<usize>, packed_structs: &Vec<StructOffset>, rule: LayoutRule) -> Result<StructOffset, Error>
+{
+    let mut props = Vec::new();
+    let mut cur_offset: usize = 0;
+    let mut max_base_alignment = 0;
+    for v in st.props {
+        let (base_alignment, size) = member_layout(rule, &v.ptype, packed_structs)?;
+        if base_alignment > max_base_alignment {
+            max_base_alignment = base_alignment;
+        }
+        let offset = cur_offset;
+        let aligned_offset = round_to_base_alignment(offset, base_alignment);
+        let offsetprop = Offset {
+            inner: v,
+            offset,
+            aligned_offset,
+            base_alignment,
+            size
+        };
+        //The next member starts after the padding inserted to align this one.
+        cur_offset = aligned_offset + size;
+        props.push(offsetprop);
+    }
+    if rule == LayoutRule::Std140 {
+        max_base_alignment = round_to_vec4(max_base_alignment);
+    }
+    Ok(StructOffset {
+        size: round_to_base_alignment(cur_offset, max_base_alignment),
+        base_alignment: max_base_alignment,
+        attr: st.attr,
+        deprecated: st.deprecated,
+        name: st.name,
+        props,
+        rule
+    })
+}
+
+pub fn compile_packed_structs(mut packed_structs: Vec<Struct<usize>>, rule: LayoutRule) -> Result<Vec<StructOffset>, Error>
+{
+    let mut vec = Vec::new();
+    while packed_structs.len() > 0 {
+        let st = packed_structs.remove(0);
+        let compiled = compile_struct(st, &vec, rule)?;
+        vec.push(compiled);
+    }
+    Ok(vec)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use bp3d_sal::ast::tree::{ArrayItemType, ArrayType, Attribute, BaseType, Property, PropertyType, Struct, VectorType};
+    use crate::targets::layout::{compile_packed_structs, compile_struct, LayoutRule};
+
+    #[test]
+    fn basic()
+    {
+        let lighting = Struct {
+            name: "Lighting".into(),
+            attr: Some(Attribute::Order(2)),
+            deprecated: None,
+            props: vec![
+                Property {
+                    pname: "Count".into(),
+                    ptype: PropertyType::Scalar(BaseType::Uint),
+                    pattr: None,
+                    deprecated: None,
+                    pformat: None,
+                    default: None,
+                    span: Default::default()
+                },
+                Property {
+                    pname: "Lights".into(),
+                    ptype: PropertyType::Array(ArrayType {
+                        size: 32,
+                        item: ArrayItemType::StructRef(0)
+                    }),
+                    pattr: None,
+                    deprecated: None,
+                    pformat: None,
+                    default: None,
+                    span: Default::default()
+                }
+            ],
+            span: Default::default()
+        };
+        let light = Struct {
+            name: "Light".into(),
+            attr: Some(Attribute::Pack),
+            deprecated: None,
+            props: vec![
+                Property {
+                    pname: "Color".into(),
+                    ptype: PropertyType::Vector(VectorType {
+                        size: 4,
+                        item: BaseType::Float
+                    }),
+                    pattr: None,
+                    deprecated: None,
+                    pformat: None,
+                    default: None,
+                    span: Default::default()
+                },
+                Property {
+                    pname: "Attenuation".into(),
+                    ptype: PropertyType::Scalar(BaseType::Float),
+                    pattr: None,
+                    deprecated: None,
+                    pformat: None,
+                    default: None,
+                    span: Default::default()
+                }
+            ],
+            span: Default::default()
+        };
+        let packed_structs = vec!(light);
+        let packed_compiled = compile_packed_structs(packed_structs, LayoutRule::Std140).unwrap();
+        let compiled = compile_struct(lighting, &packed_compiled, LayoutRule::Std140).unwrap();
+        assert_eq!(compiled.size, 1040); //The size of the compiled structure includes ALL required alignments
+        assert_eq!(compiled.base_alignment, 16);
+        let aligned_offsets: Vec<usize> = compiled.props.iter().map(|v| v.aligned_offset).collect();
+        assert_eq!(aligned_offsets, vec![0, 16]);
+        let aligned_offsets: Vec<usize> = packed_compiled[0].props.iter().map(|v| v.aligned_offset).collect();
+        assert_eq!(aligned_offsets, vec![0, 16]);
+    }
+
+    #[test]
+    fn int64()
+    {
+        let prop = |pname: &str, ptype| Property {
+            pname: pname.into(),
+            ptype,
+            pattr: None,
+            deprecated: None,
+            pformat: None,
+            default: None,
+            span: Default::default()
+        };
+        let particles = Struct {
+            name: "Particles".into(),
+            attr: None,
+            deprecated: None,
+            props: vec![
+                prop("Count", PropertyType::Scalar(BaseType::Uint)),
+                prop("BaseOffset", PropertyType::Scalar(BaseType::Uint64)),
+                prop("Scale", PropertyType::Scalar(BaseType::Float)),
+                prop("Range", PropertyType::Vector(VectorType { item: BaseType::Int64, size: 2 })),
+                prop("Seed", PropertyType::Scalar(BaseType::Uint))
+            ],
+            span: Default::default()
+        };
+        let compiled = compile_struct(particles, &Vec::new(), LayoutRule::Std140).unwrap();
+        let aligned_offsets: Vec<usize> = compiled.props.iter().map(|v| v.aligned_offset).collect();
+        assert_eq!(aligned_offsets, vec![0, 8, 16, 32, 48]);
+        assert_eq!(compiled.base_alignment, 16);
+        assert_eq!(compiled.size, 64);
+    }
+
+    fn rules(sample: &Struct<usize>, nested: &Struct<usize>, rule: LayoutRule) -> (Vec<usize>, usize)
+    {
+        let packed = compile_packed_structs(vec![nested.clone()], rule).unwrap();
+        let compiled = compile_struct(sample.clone(), &packed, rule).unwrap();
+        assert_eq!(compiled.rule, rule);
+        (compiled.props.iter().map(|v| v.aligned_offset).collect(), compiled.size)
+    }
+
+    #[test]
+    fn layout_rules()
+    {
+        let prop = |pname: &str, ptype| Property {
+            pname: pname.into(),
+            ptype,
+            pattr: None,
+            deprecated: None,
+            pformat: None,
+            default: None,
+            span: Default::default()
+        };
+        let vec2 = VectorType { item: BaseType::Float, size: 2 };
+        let vec3 = VectorType { item: BaseType::Float, size: 3 };
+        let nested = Struct {
+            name: "Nested".into(),
+            attr: Some(Attribute::Pack),
+            deprecated: None,
+            props: vec![
+                prop("A", PropertyType::Scalar(BaseType::Float)),
+                prop("B", PropertyType::Vector(vec2)),
+                prop("C", PropertyType::Scalar(BaseType::Float))
+            ],
+            span: Default::default()
+        };
+        let sample = Struct {
+            name: "Sample".into(),
+            attr: None,
+            deprecated: None,
+            props: vec![
+                prop("Position", PropertyType::Vector(vec3)),
+                prop("Radius", PropertyType::Scalar(BaseType::Float)),
+                prop("Points", PropertyType::Array(ArrayType { size: 3, item: ArrayItemType::Vector(vec3) })),
+                prop("Inner", PropertyType::StructRef(0)),
+                prop("Tail", PropertyType::Scalar(BaseType::Float))
+            ],
+            span: Default::default()
+        };
+        assert_eq!(compile_struct(nested.clone(), &Vec::new(), LayoutRule::Std140).unwrap().size, 32);
+        assert_eq!(compile_struct(nested.clone(), &Vec::new(), LayoutRule::Std430).unwrap().size, 24);
+        assert_eq!(compile_struct(nested.clone(), &Vec::new(), LayoutRule::Scalar).unwrap().size, 16);
+        assert_eq!(rules(&sample, &nested, LayoutRule::Std140), (vec![0, 12, 16, 64, 96], 112));
+        assert_eq!(rules(&sample, &nested, LayoutRule::Std430), (vec![0, 12, 16, 64, 88], 96));
+        assert_eq!(rules(&sample, &nested, LayoutRule::Scalar), (vec![0, 12, 16, 52, 68], 72));
+    }
+}
@@ -26,20 +26,53 @@
 // NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
 // SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
-use std::{fs::File, io::BufWriter};
+use std::{collections::HashMap, fs::File, io::BufWriter};
 
 use bpx::package;
 use bpx::package::Package;
 use bpx::package::utils::pack_file_vname;
-use log::warn;
 use std::error::Error;
+use bp3d_symbols::TargetInfo;
 
 use crate::config::{Config, Unit};
 use crate::targets::basic::shaderlib::ShaderLib;
+use crate::warnings::WarningKind;
+
+pub fn info() -> TargetInfo
+{
+    TargetInfo {
+        name: "LIB".into(),
+        api: None,
+        version: None,
+        supports_debug: false,
+        supports_optimize: false,
+        supports_minify: false,
+        supports_spirv: false,
+        supports_compute: false,
+        supports_int64: false,
+        payload_format: "SAL source".into(),
+        max_viewports: 0,
+        bpx_target: None,
+        supported_stages: Vec::new()
+    }
+}
 
 pub fn build(config: Config) -> Result<(), Box<dyn Error>>
 {
     let mut libs: Vec<ShaderLib> = config.libs.iter().map(|v| ShaderLib::new(*v)).collect();
+    let injected: Vec<&str> = config.units.iter().filter_map(|v| match v {
+        Unit::Injected(vname) => Some(*vname),
+        _ => None
+    }).collect();
+    //Resolve every injected name with a single pass over each lib.
+    let mut loaded: HashMap<String, Vec<Vec<u8>>> = HashMap::new();
+    if !injected.is_empty() {
+        for v in &mut libs {
+            for (name, data) in v.try_load_many(injected.iter().copied())? {
+                loaded.entry(name).or_default().push(data);
+            }
+        }
+    }
     let mut bpxp = Package::create(BufWriter::new(File::create(config.output)?),
                                    package::Builder::new()
                                        .type_code(*b"SL") //SL for ShaderLib
@@ -47,32 +80,30 @@ pub fn build(config: Config) -> Result<(), Box<dyn Error>>
                                        .platform(package::Platform::Any))?;
     for unit in config.units {
         match unit {
-            Unit::Path(path) => {
+            Unit::Path(path) | Unit::PathWithStage(_, path) => {
                 if let Some(name) = path.file_name() {
                     if let Some(vname) = name.to_str() {
                         pack_file_vname(&mut bpxp, vname, path)?;
                     } else {
-                        warn!(
+                        config.warnings.report(WarningKind::SkippedUnit, format!(
                             "Path '{}' does not contain a valid file name, skipping...",
                             path.display()
-                        );
+                        ));
                         continue;
                     }
                 } else {
-                    warn!(
+                    config.warnings.report(WarningKind::SkippedUnit, format!(
                         "Path '{}' does not contain a valid file name, skipping...",
                         path.display()
-                    );
+                    ));
                     continue;
                 }
             },
             Unit::Injected(vname) => {
                 let mut objects = bpxp.objects_mut()
                     .ok_or(bpx::package::error::Error::Open(bpx::core::error::OpenError::SectionNotLoaded))?;
-                for v in &mut libs {
-                    if let Some(data) = v.try_load(vname)? {
-                        objects.create(vname, data.as_slice())?;
-                    }
+                for data in loaded.remove(vname).unwrap_or_default() {
+                    objects.create(vname, data.as_slice())?;
                 }
             },
         }
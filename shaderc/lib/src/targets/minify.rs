@@ -0,0 +1,494 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+// Text passes over generated GLSL payloads (comment stripping, minification and renaming).
+
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind
+{
+    Code,
+    LineComment,
+    BlockComment
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Token<'a>
+{
+    pub kind: TokenKind,
+    pub text: &'a str
+}
+
+/// Splits GLSL source text into code and comment tokens; concatenating all tokens gives back
+/// the original text.
+pub struct Scanner<'a>
+{
+    code: &'a str,
+    pos: usize
+}
+
+impl<'a> Scanner<'a> {
+    pub fn new(code: &'a str) -> Scanner<'a>
+    {
+        Scanner { code, pos: 0 }
+    }
+}
+
+impl<'a> Iterator for Scanner<'a> {
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Self::Item>
+    {
+        let rest = &self.code[self.pos..];
+        if rest.is_empty() {
+            return None;
+        }
+        let (kind, len) = if rest.starts_with("//") {
+            (TokenKind::LineComment, rest.find('\n').unwrap_or(rest.len()))
+        } else if let Some(body) = rest.strip_prefix("/*") {
+            (TokenKind::BlockComment, body.find("*/").map(|v| v + 4).unwrap_or(rest.len()))
+        } else {
+            let end = [rest.find("//"), rest.find("/*")].into_iter().flatten().min();
+            (TokenKind::Code, end.unwrap_or(rest.len()))
+        };
+        self.pos += len;
+        Some(Token {
+            kind,
+            text: &rest[..len]
+        })
+    }
+}
+
+fn remove_comments(code: &str) -> String
+{
+    let mut res = String::with_capacity(code.len());
+    for token in Scanner::new(code) {
+        match token.kind {
+            TokenKind::Code => res.push_str(token.text),
+            TokenKind::LineComment => (),
+            TokenKind::BlockComment => {
+                let lines = token.text.matches('\n').count();
+                if lines == 0 {
+                    //Keep tokens on both sides of the comment apart.
+                    res.push(' ');
+                } else {
+                    (0..lines).for_each(|_| res.push('\n'));
+                }
+            }
+        }
+    }
+    res
+}
+
+fn parse_directive(line: &str) -> Option<(&str, &str)>
+{
+    let line = line.trim_start().strip_prefix('#')?.trim_start();
+    let end = line.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(line.len());
+    Some((&line[..end], line[end..].trim()))
+}
+
+struct Group
+{
+    branches: Vec<usize>, //Line index of #if/#ifdef/#ifndef, then every #elif/#else.
+    end: Option<usize> //Line index of #endif; None when the group is never closed.
+}
+
+struct Stripper<'a>
+{
+    lines: Vec<&'a str>,
+    blank: Vec<bool>,
+    groups: HashMap<usize, Group>,
+    defined: HashSet<&'a str>,
+    undefined: HashSet<&'a str>,
+    unknown_depth: usize
+}
+
+impl<'a> Stripper<'a> {
+    fn new(code: &'a str) -> Stripper<'a>
+    {
+        let lines: Vec<&str> = code.split('\n').collect();
+        let mut groups = HashMap::new();
+        let mut stack: Vec<(usize, Group)> = Vec::new();
+        for (i, line) in lines.iter().enumerate() {
+            match parse_directive(line) {
+                Some(("if", _)) | Some(("ifdef", _)) | Some(("ifndef", _)) => stack.push((i, Group {
+                    branches: vec![i],
+                    end: None
+                })),
+                Some(("elif", _)) | Some(("else", _)) => {
+                    if let Some((_, group)) = stack.last_mut() {
+                        group.branches.push(i);
+                    }
+                },
+                Some(("endif", _)) => {
+                    if let Some((start, mut group)) = stack.pop() {
+                        group.end = Some(i);
+                        groups.insert(start, group);
+                    }
+                },
+                _ => ()
+            }
+        }
+        for (start, group) in stack {
+            groups.insert(start, group);
+        }
+        Stripper {
+            blank: vec![false; lines.len()],
+            lines,
+            groups,
+            defined: HashSet::new(),
+            undefined: HashSet::new(),
+            unknown_depth: 0
+        }
+    }
+
+    fn eval_defined(&self, name: &str) -> Option<bool>
+    {
+        if self.defined.contains(name) {
+            Some(true)
+        } else if self.undefined.contains(name) {
+            Some(false)
+        } else {
+            //Could still be defined by the driver (GL_ES, extension macros, etc).
+            None
+        }
+    }
+
+    fn eval_if(&self, expr: &str) -> Option<bool>
+    {
+        if let Ok(v) = expr.parse::<i64>() {
+            return Some(v != 0);
+        }
+        let (negate, expr) = match expr.strip_prefix('!') {
+            Some(v) => (true, v.trim_start()),
+            None => (false, expr)
+        };
+        let name = expr.strip_prefix("defined")?.trim();
+        let name = name.strip_prefix('(').and_then(|v| v.strip_suffix(')')).unwrap_or(name).trim();
+        self.eval_defined(name).map(|v| v != negate)
+    }
+
+    // Returns None if the group cannot be fully resolved, otherwise the index of the live branch
+    // (if any).
+    fn resolve(&self, group: &Group) -> Option<Option<usize>>
+    {
+        group.end?;
+        for (index, line) in group.branches.iter().enumerate() {
+            let cond = match parse_directive(self.lines[*line]) {
+                Some(("if", expr)) | Some(("elif", expr)) => self.eval_if(expr),
+                Some(("ifdef", name)) => self.eval_defined(name),
+                Some(("ifndef", name)) => self.eval_defined(name).map(|v| !v),
+                _ => Some(true)
+            };
+            match cond {
+                Some(true) => return Some(Some(index)),
+                Some(false) => continue,
+                None => return None
+            }
+        }
+        Some(None)
+    }
+
+    fn define(&mut self, name: &'a str, defined: bool)
+    {
+        self.defined.remove(name);
+        self.undefined.remove(name);
+        if self.unknown_depth > 0 {
+            //Whether this directive applies depends on an unresolved condition.
+            return;
+        }
+        if defined {
+            self.defined.insert(name);
+        } else {
+            self.undefined.insert(name);
+        }
+    }
+
+    fn walk(&mut self, start: usize, end: usize)
+    {
+        let mut i = start;
+        while i < end {
+            if let Some(group) = self.groups.remove(&i) {
+                i = self.walk_group(group).unwrap_or(end);
+                continue;
+            }
+            match parse_directive(self.lines[i]) {
+                Some(("define", args)) => {
+                    let name = args.split(|c: char| c.is_whitespace() || c == '(').next().unwrap_or("");
+                    self.define(name, true);
+                },
+                Some(("undef", name)) => self.define(name, false),
+                _ => ()
+            }
+            i += 1;
+        }
+    }
+
+    // Returns the index of the first line after the group.
+    fn walk_group(&mut self, group: Group) -> Option<usize>
+    {
+        let last = group.end.unwrap_or(self.lines.len());
+        let ranges: Vec<(usize, usize)> = group.branches.iter().enumerate()
+            .map(|(i, start)| (start + 1, group.branches.get(i + 1).copied().unwrap_or(last)))
+            .collect();
+        match self.resolve(&group) {
+            Some(live) => {
+                for line in group.branches.iter().chain(group.end.iter()) {
+                    self.blank[*line] = true;
+                }
+                for (index, (start, end)) in ranges.into_iter().enumerate() {
+                    if live == Some(index) {
+                        self.walk(start, end);
+                    } else {
+                        self.blank[start..end].iter_mut().for_each(|v| *v = true);
+                    }
+                }
+            },
+            None => {
+                self.unknown_depth += 1;
+                for (start, end) in ranges {
+                    self.walk(start, end);
+                }
+                self.unknown_depth -= 1;
+            }
+        }
+        group.end.map(|v| v + 1)
+    }
+
+    fn finish(self) -> String
+    {
+        let blank = self.blank;
+        self.lines.into_iter()
+            .enumerate()
+            .map(|(i, line)| if blank[i] { "" } else { line })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Removes comments and fully resolved dead preprocessor branches from GLSL source text.
+///
+/// Removed lines are replaced with blank lines so that line numbers reported by drivers still
+/// match the original text.
+pub fn strip_comments(code: &str) -> String
+{
+    let code = remove_comments(code);
+    let mut stripper = Stripper::new(&code);
+    stripper.walk(0, stripper.lines.len());
+    stripper.finish()
+}
+
+// Calls f with the byte offset of every identifier in the given code; identifiers following a
+// `.` are field selections or swizzles and are not reported.
+fn for_each_identifier<'a, F: FnMut(usize, &'a str)>(code: &'a str, mut f: F)
+{
+    let bytes = code.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        let c = bytes[i];
+        if !c.is_ascii_alphanumeric() && c != b'_' {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_') {
+            i += 1;
+        }
+        //Numbers are scanned as a whole so that suffixes and exponents aren't taken as identifiers.
+        if !c.is_ascii_digit() && (start == 0 || bytes[start - 1] != b'.') {
+            f(start, &code[start..i]);
+        }
+    }
+}
+
+/// Returns the identifiers referenced by GLSL source text, comments excluded.
+pub fn identifiers(code: &str) -> HashSet<String>
+{
+    let code = remove_comments(code);
+    let mut set = HashSet::new();
+    for_each_identifier(&code, |_, ident| {
+        set.insert(ident.into());
+    });
+    set
+}
+
+/// Replaces every identifier of GLSL source text found in the given map.
+pub fn rename_identifiers(code: &str, names: &HashMap<String, String>) -> String
+{
+    let mut res = String::with_capacity(code.len());
+    let mut last = 0;
+    for_each_identifier(code, |start, ident| {
+        if let Some(name) = names.get(ident) {
+            res.push_str(&code[last..start]);
+            res.push_str(name);
+            last = start + ident.len();
+        }
+    });
+    res.push_str(&code[last..]);
+    res
+}
+
+const PUNCTUATION: &[char] = &['{', '}', '(', ')', '[', ']', ';', ','];
+
+// Collapses whitespace runs to a single space. Spaces next to punctuation are dropped, except in
+// preprocessor directives where they can be significant (`#define F (x)`).
+fn push_collapsed(res: &mut String, text: &str, directive: bool)
+{
+    let mut space = false;
+    for c in text.trim().chars() {
+        if c.is_whitespace() {
+            space = true;
+            continue;
+        }
+        if space && (directive || !(PUNCTUATION.contains(&c) || res.ends_with(PUNCTUATION))) {
+            res.push(' ');
+        }
+        space = false;
+        res.push(c);
+    }
+}
+
+/// Strips comments and dead preprocessor branches from GLSL source text, then removes all
+/// redundant whitespace; only preprocessor directives are kept on their own line.
+///
+/// Unlike [strip_comments], line numbers are not preserved.
+pub fn minify(code: &str) -> String
+{
+    let code = strip_comments(code);
+    let mut res = String::with_capacity(code.len());
+    let mut pending = String::new();
+    let mut continued = false;
+    for line in code.lines() {
+        if !continued && !line.trim_start().starts_with('#') {
+            pending.push_str(line);
+            pending.push(' ');
+            continue;
+        }
+        push_collapsed(&mut res, &pending, false);
+        pending.clear();
+        if !continued && !res.is_empty() && !res.ends_with('\n') {
+            res.push('\n');
+        }
+        push_collapsed(&mut res, line, true);
+        res.push('\n');
+        continued = line.trim_end().ends_with('\\');
+    }
+    push_collapsed(&mut res, &pending, false);
+    if !res.is_empty() && !res.ends_with('\n') {
+        res.push('\n');
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::collections::HashMap;
+    use rglslang::environment::{Client, Environment, Stage};
+    use rglslang::shader::{Part, Profile};
+    use crate::targets::minify::{identifiers, minify, rename_identifiers, strip_comments};
+
+    const SOURCE: &str = "#version 400 core
+// Copyright notice which must not be shipped.
+#define USE_FOG
+uniform vec4 Color; /* tint */
+/*
+ * Multi-line comment.
+ */
+#if 0
+uniform vec4 Debug;
+#endif
+#ifdef USE_FOG
+uniform float FogDensity;
+#else
+const float FogDensity = 0.0;
+#endif
+#ifdef GL_ES
+precision mediump float;
+#endif
+out vec4 FragColor;
+void main()
+{
+    FragColor = Color * FogDensity; // apply fog
+}
+";
+
+    #[test]
+    fn strip()
+    {
+        let res = strip_comments(SOURCE);
+        assert_eq!(res.lines().count(), SOURCE.lines().count());
+        assert!(!res.contains("//"));
+        assert!(!res.contains("/*"));
+        assert!(!res.contains("Copyright"));
+        assert!(!res.contains("Debug"));
+        assert!(!res.contains("#if 0"));
+        assert!(!res.contains("const float FogDensity"));
+        assert!(res.contains("uniform float FogDensity;"));
+        assert!(res.contains("#ifdef GL_ES"));
+        assert_eq!(res.lines().nth(21), Some("    FragColor = Color * FogDensity; "));
+    }
+
+    fn assert_compiles(code: String)
+    {
+        let shader = rglslang::shader::Builder::new(Environment::new_opengl(Stage::Pixel, Client::OpenGL, Some(400)))
+            .entry_point("main")
+            .source_entry_point("main")
+            .default_version(400)
+            .default_profile(Profile::Core)
+            .add_part(Part::new_with_name(code, "test"))
+            .parse();
+        assert!(shader.check(), "{}", shader.get_info_log());
+    }
+
+    #[test]
+    fn strip_compiles()
+    {
+        assert_compiles(strip_comments(SOURCE));
+    }
+
+    #[test]
+    fn minify_compiles()
+    {
+        let res = minify(SOURCE);
+        assert_eq!(res, "#version 400 core\n#define USE_FOG\nuniform vec4 Color;uniform float FogDensity;\n#ifdef GL_ES\nprecision mediump float;\n#endif\nout vec4 FragColor;void main(){FragColor = Color * FogDensity;}\n");
+        assert_compiles(res);
+    }
+
+    #[test]
+    fn rename()
+    {
+        let code = "uniform vec4 Color; // Color\nvoid main() { vec4 v = Color.xxxx * 1.0e5 + s.Color; }";
+        let used = identifiers(code);
+        assert!(used.contains("Color") && used.contains("main"));
+        assert!(!used.contains("xxxx") && !used.contains("e5"));
+        let names = HashMap::from([("Color".to_string(), "_0".to_string())]);
+        assert_eq!(rename_identifiers(code, &names), "uniform vec4 _0; // _0\nvoid main() { vec4 v = _0.xxxx * 1.0e5 + s.Color; }");
+    }
+}
@@ -26,10 +26,14 @@
 // NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
 // SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
-mod basic;
-mod sal_to_glsl;
-mod gl;
-mod layout140;
+pub(crate) mod basic;
+pub(crate) mod sal_to_glsl;
+pub(crate) mod gl;
+mod layout;
+mod minify;
 pub mod gl40;
 pub mod gl42;
+pub mod gl46;
+pub mod es31;
+pub mod vk;
 pub mod lib;
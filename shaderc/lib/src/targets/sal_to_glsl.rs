@@ -29,8 +29,10 @@
 use std::borrow::Cow;
 use std::collections::HashSet;
 use log::{debug, error};
-use bp3d_sal::ast::tree::{ArrayItemType, Property, PropertyType, Struct, VectorType};
-use crate::targets::basic::{BasicAst, Slot};
+use regex::{Captures, Regex};
+use bp3d_sal::ast::tree::{ArrayItemType, Attribute, BaseType, Interpolation, Property, PropertyType, Span, Struct, TextureType, VectorType};
+use crate::targets::basic::{BasicAst, Slot, vformat_locations};
+use crate::targets::basic::parts::PART_SAL;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -39,37 +41,165 @@ pub enum Error
     #[error("multiple definition of output slot {0}")]
     RedefinedOutput(u32),
     #[error("duplicate slot bindings in one or more constant buffer declaration")]
-    DuplicateSlot
+    DuplicateSlot,
+    #[error("texture '{0}' cannot be sampled with comparison sampler '{1}'")]
+    ShadowSampler(String, String),
+    #[error("texture '{0}' of type {1} is not supported by OpenGL ES")]
+    EsTexture(String, String)
 }
 
-fn get_char(v: VectorType) -> char
+/// How constant buffers, textures and root constants are bound in the generated GLSL.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Bindings
 {
-    let c = v.item.get_char();
-    if c == 'f' {
-        ' '
-    } else {
-        c
+    /// Bindings are assigned by the application at runtime (GL 4.0).
+    Implicit,
+    /// `layout (binding = N)` qualifiers.
+    Explicit,
+    /// `layout (set = 0, binding = N)` qualifiers and root constants as a push constant block.
+    Vulkan,
+    /// `layout (binding = N)` qualifiers plus the precision qualifiers required by OpenGL ES.
+    Es
+}
+
+impl From<bool> for Bindings
+{
+    fn from(explicit_bindings: bool) -> Self
+    {
+        match explicit_bindings {
+            true => Bindings::Explicit,
+            false => Bindings::Implicit
+        }
+    }
+}
+
+/// Origin of the lines of a SAL translation, used to map the diagnostics of glslang on the
+/// `__internal_sal__` part back to the SAL statements they come from.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SourceMap
+{
+    //One entry per line of the translation: unit or module and line of the SAL statement.
+    lines: Vec<Option<(String, usize)>>
+}
+
+impl SourceMap
+{
+    /// Returns the unit or module and the line of the SAL statement a line (starting at 1) of the
+    /// translation was generated from, None for generated code.
+    pub fn origin(&self, line: usize) -> Option<(&str, usize)>
+    {
+        line.checked_sub(1)
+            .and_then(|v| self.lines.get(v))
+            .and_then(|v| v.as_ref())
+            .map(|(unit, line)| (&**unit, *line))
+    }
+
+    /// Rewrites the `__internal_sal__:<line>` locations of a glslang info log into
+    /// `<unit>:<line>` for lines generated from a SAL statement.
+    pub fn remap_log(&self, log: &str) -> String
+    {
+        let regex = Regex::new(&format!(r"{}:(\d+)", regex::escape(PART_SAL))).unwrap();
+        regex.replace_all(log, |c: &Captures| {
+            match c[1].parse().ok().and_then(|v| self.origin(v)) {
+                Some((unit, line)) => format!("{}:{}", unit, line),
+                None => c[0].to_string()
+            }
+        }).into_owned()
+    }
+}
+
+/// A declaration of the translation and the unit and line of the SAL statement it comes from.
+type Line = (String, Option<(String, usize)>);
+
+fn origin<T>(slot: &Slot<T>, span: Span) -> Option<(String, usize)>
+{
+    match &slot.unit {
+        //Spans of generated statements are not set.
+        Some(unit) if span.line > 0 => Some((unit.clone(), span.line)),
+        _ => None
+    }
+}
+
+fn binding_layout(bindings: Bindings, slot: u32) -> Option<String>
+{
+    match bindings {
+        Bindings::Implicit => None,
+        Bindings::Explicit | Bindings::Es => Some(format!("binding = {}", slot)),
+        Bindings::Vulkan => Some(format!("set = 0, binding = {}", slot))
+    }
+}
+
+fn get_char(v: VectorType) -> &'static str
+{
+    match v.item {
+        BaseType::Float => " ",
+        item => item.get_suffix()
+    }
+}
+
+fn get_scalar_name(t: BaseType) -> &'static str
+{
+    match t {
+        BaseType::Int64 => "int64_t",
+        BaseType::Uint64 => "uint64_t",
+        t => t.get_name()
+    }
+}
+
+/// Most sampler types have no default precision in GLSL ES: color textures (float vectors) are
+/// sampled at medium precision, all other textures (depth, data, integers) at high precision.
+fn sampler_precision(ptype: &PropertyType<usize>) -> &'static str
+{
+    let ttype = match ptype {
+        PropertyType::Texture1D(t) | PropertyType::Texture2D(t) | PropertyType::Texture3D(t)
+        | PropertyType::Texture2DArray(t) | PropertyType::TextureCube(t) | PropertyType::Texture2DMS(t, _) => t,
+        PropertyType::Array(a) => match &a.item {
+            ArrayItemType::Texture2D(t) | ArrayItemType::Texture3D(t) | ArrayItemType::TextureCube(t) => t,
+            _ => return "highp"
+        },
+        _ => return "highp"
+    };
+    match ttype {
+        TextureType::Vector(v) if v.item == BaseType::Float => "mediump",
+        _ => "highp"
+    }
+}
+
+/// Returns the comparison sampler a texture is sampled with, if any.
+fn comparison_sampler<'a>(p: &'a Property<usize>, ast: &BasicAst) -> Option<&'a str>
+{
+    match &p.pattr {
+        Some(Attribute::Identifier(sampler))
+            if ast.objects.iter().any(|v| v.inner.pname == *sampler && v.inner.ptype == PropertyType::SamplerCmp) => Some(sampler),
+        _ => None
     }
 }
 
 fn translate_property(p: &Property<usize>, ast: &BasicAst) -> String
 {
     let mut array = None;
+    //Textures sampled with a comparison sampler are declared as shadow samplers.
+    let shadow = if comparison_sampler(p, ast).is_some() { "Shadow" } else { "" };
     let ptype: Cow<str> = match &p.ptype {
-        PropertyType::Scalar(s) => s.get_name().into(),
+        PropertyType::Scalar(s) => get_scalar_name(*s).into(),
         PropertyType::Vector(v) => format!("{}vec{}", get_char(*v), v.size).into(),
         PropertyType::Matrix(m) => format!("{}mat{}", get_char(*m), m.size).into(),
-        PropertyType::Sampler => "".into(),
-        PropertyType::Texture2D(_) => "sampler2D".into(),
+        PropertyType::Sampler | PropertyType::SamplerCmp => "".into(),
+        PropertyType::Texture1D(_) => format!("sampler1D{}", shadow).into(),
+        PropertyType::Texture2D(_) => format!("sampler2D{}", shadow).into(),
         PropertyType::Texture3D(_) => "sampler3D".into(),
-        PropertyType::Texture2DArray(_) => "sampler2DArray".into(),
-        PropertyType::TextureCube(_) => "samplerCube".into(),
+        PropertyType::Texture2DArray(_) => format!("sampler2DArray{}", shadow).into(),
+        PropertyType::TextureCube(_) => format!("samplerCube{}", shadow).into(),
+        PropertyType::Texture2DMS(..) => "sampler2DMS".into(),
         PropertyType::StructRef(s) => (&* ast.get_struct_ref(*s).name).into(),
         PropertyType::Array(a) => {
             let item: Cow<str> = match &a.item {
                 ArrayItemType::Vector(v) => format!("{}vec{}", get_char(*v), v.size).into(),
                 ArrayItemType::Matrix(m) => format!("{}mat{}", get_char(*m), m.size).into(),
-                ArrayItemType::StructRef(s) => (&* ast.get_struct_ref(*s).name).into()
+                ArrayItemType::StructRef(s) => (&* ast.get_struct_ref(*s).name).into(),
+                ArrayItemType::Texture2D(_) => "sampler2D".into(),
+                ArrayItemType::Texture3D(_) => "sampler3D".into(),
+                ArrayItemType::TextureCube(_) => "samplerCube".into()
             };
             array = Some(a.size);
             format!("{}", item).into()
@@ -95,19 +225,23 @@ fn translate_packed_struct(s: &Struct<usize>, ast: &BasicAst) -> String
     str
 }
 
-fn translate_cbuffer(explicit_bindings: bool, s: &Slot<Struct<usize>>, ast: &BasicAst) -> String
+fn translate_cbuffer(bindings: Bindings, s: &Slot<Struct<usize>>, ast: &BasicAst) -> String
 {
     let mut str;
-    if explicit_bindings {
-        str = format!("layout (binding = {}, std140) uniform {} {{", s.slot.get(), s.inner.name);
+    if let Some(layout) = binding_layout(bindings, s.slot.get()) {
+        str = format!("layout ({}, std140) uniform {} {{", layout, s.inner.name);
     } else {
         str = format!("layout (std140) uniform {} {{", s.inner.name);
     }
     for v in &s.inner.props {
         let prop = Property {
             pattr: None,
+            deprecated: None,
             pname: [&*s.inner.name, &*v.pname].join("_"),
-            ptype: v.ptype.clone()
+            ptype: v.ptype.clone(),
+            pformat: None,
+            default: None,
+            span: v.span
         };
         str.push_str(&translate_property(&prop, ast));
     }
@@ -118,11 +252,15 @@ fn translate_cbuffer(explicit_bindings: bool, s: &Slot<Struct<usize>>, ast: &Bas
 fn translate_vformat(s: &Struct<usize>, ast: &BasicAst) -> String
 {
     let mut str= String::new();
-    for (loc, v) in s.props.iter().enumerate() {
+    for (loc, v) in vformat_locations(s).into_iter().zip(s.props.iter()) {
         let prop = Property {
             pattr: None,
+            deprecated: None,
             pname: [&*s.name, &*v.pname].join("_"),
-            ptype: v.ptype.clone()
+            ptype: v.ptype.clone(),
+            pformat: None,
+            default: None,
+            span: v.span
         };
         str.push_str(&format!("layout (location = {}) in {}", loc, translate_property(&prop, ast)));
     }
@@ -142,17 +280,43 @@ fn translate_outputs(ast: &BasicAst) -> Result<String, Error>
     Ok(str)
 }
 
-fn translate_root_consts(explicit_bindings: bool, root_constants_layout: &Struct<usize>, ast: &BasicAst) -> String
+fn translate_varyings(ast: &BasicAst) -> String
 {
-    if ast.root_constants.is_empty() {
+    if ast.varyings.is_empty() {
         return String::default();
     }
-    let mut str;
-    if explicit_bindings {
-        str = String::from("layout (binding = 0, std140) uniform __Root {");
-    } else {
-        str = String::from("layout (std140) uniform __Root {");
+    let mut members = String::new();
+    for v in &ast.varyings {
+        let prop = translate_property(v, ast);
+        match v.pattr.as_ref().and_then(|v| v.get_interpolation()) {
+            Some(Interpolation::Smooth) => members.push_str(&format!("smooth {}", prop.trim_start())),
+            Some(Interpolation::Flat) => members.push_str(&format!("flat {}", prop.trim_start())),
+            Some(Interpolation::NoPerspective) => members.push_str(&format!("noperspective {}", prop.trim_start())),
+            None => members.push_str(&prop)
+        }
+    }
+    let array = |v: bool| if v { "[]" } else { "" };
+    let mut blocks = Vec::new();
+    //Both blocks share the same name so that they match across consecutive stages.
+    if let Some(v) = ast.varying_interface.input {
+        blocks.push(format!("in Varyings {{{}}} In{};", members, array(v)));
+    }
+    if let Some(v) = ast.varying_interface.output {
+        blocks.push(format!("out Varyings {{{}}} Out{};", members, array(v)));
+    }
+    blocks.join("\n")
+}
+
+fn translate_root_consts(bindings: Bindings, root_constants_layout: &Struct<usize>, ast: &BasicAst) -> String
+{
+    if ast.root_constants.is_empty() {
+        return String::default();
     }
+    let mut str = match bindings {
+        Bindings::Implicit => String::from("layout (std140) uniform __Root {"),
+        Bindings::Explicit | Bindings::Es => String::from("layout (binding = 0, std140) uniform __Root {"),
+        Bindings::Vulkan => String::from("layout (push_constant, std140) uniform __Root {")
+    };
     let last_used_prop = root_constants_layout.props.iter().rfind(|p| {
         if ast.root_constants.iter().any(|v| &v.inner == *p) {
             true
@@ -172,13 +336,31 @@ fn translate_root_consts(explicit_bindings: bool, root_constants_layout: &Struct
     str
 }
 
+/// Checks that the textures of a stage exist in the target GLSL flavor and that comparison samplers
+/// are only used with textures which have a shadow sampler type.
+fn test_textures(bindings: Bindings, ast: &BasicAst) -> Result<(), Error>
+{
+    for v in &ast.objects {
+        let p = &v.inner;
+        if let Some(sampler) = comparison_sampler(p, ast) {
+            if matches!(p.ptype, PropertyType::Texture3D(_) | PropertyType::Texture2DMS(..) | PropertyType::Array(_)) {
+                return Err(Error::ShadowSampler(p.pname.clone(), sampler.into()));
+            }
+        }
+        if bindings == Bindings::Es && matches!(p.ptype, PropertyType::Texture1D(_)) {
+            return Err(Error::EsTexture(p.pname.clone(), p.ptype.to_string()));
+        }
+    }
+    Ok(())
+}
+
 fn test_cbuffers_unique_slots(ast: &BasicAst) -> Result<(), Error>
 {
     let mut set = HashSet::new();
     // Extract duplicate binding slots
     let flag = ast.cbuffers.iter().any(|s| {
         if set.contains(&s.slot.get()) {
-            error!("Duplicate slot binding {}", s.slot.get());
+            error!(target: "translation", "Duplicate slot binding {}", s.slot.get());
             return true;
         } else {
             set.insert(s.slot.get());
@@ -191,39 +373,76 @@ fn test_cbuffers_unique_slots(ast: &BasicAst) -> Result<(), Error>
     Ok(())
 }
 
-pub fn translate_sal_to_glsl(explicit_bindings: bool, root_constants_layout: &Struct<usize>, ast: &BasicAst) -> Result<String, Error>
+pub fn translate_sal_to_glsl<B: Into<Bindings>>(bindings: B, root_constants_layout: &Struct<usize>, ast: &BasicAst) -> Result<String, Error>
+{
+    translate_sal_to_glsl_mapped(bindings, root_constants_layout, ast).map(|(glsl, _)| glsl)
+}
+
+/// Same as [translate_sal_to_glsl] but also returns the origin of each line of the translation.
+pub fn translate_sal_to_glsl_mapped<B: Into<Bindings>>(bindings: B, root_constants_layout: &Struct<usize>, ast: &BasicAst) -> Result<(String, SourceMap), Error>
 {
+    let bindings = bindings.into();
+    let precision = match bindings {
+        Bindings::Es => "precision highp float;",
+        _ => ""
+    };
+    let defines: Vec<Line> = ast.constant_ints.iter()
+        .map(|v| (format!("#define {} {}", v.inner.name, v.inner.value), origin(v, v.inner.span)))
+        .collect();
     let vformat = ast.vformat.as_ref().map(|s| translate_vformat(&s, ast)).unwrap_or_default();
-    let constants = translate_root_consts(explicit_bindings, root_constants_layout, ast);
+    let constants = translate_root_consts(bindings, root_constants_layout, ast);
     let outputs = translate_outputs(ast)?;
+    let varyings = translate_varyings(ast);
     test_cbuffers_unique_slots(ast)?;
+    test_textures(bindings, ast)?;
     let structs: Vec<String> = ast.packed_structs.iter().map(|s| translate_packed_struct(s, ast)).collect();
-    let structs = structs.join("\n");
-    let cbuffers: Vec<String> = ast.cbuffers.iter().map(|s| translate_cbuffer(explicit_bindings, s, ast)).collect();
-    let cbuffers = cbuffers.join("\n");
-    let objects: Vec<String> = ast.objects.iter().filter_map(|p| {
-        let sji = translate_property(&p.inner, ast);
+    let cbuffers: Vec<Line> = ast.cbuffers.iter()
+        .map(|s| (translate_cbuffer(bindings, s, ast), origin(s, s.inner.span)))
+        .collect();
+    let objects: Vec<Line> = ast.objects.iter().filter_map(|p| {
+        let mut sji = translate_property(&p.inner, ast);
         if !sji.is_empty() {
-            if explicit_bindings {
-                Some(format!("layout (binding = {}) uniform {}", p.slot.get(), sji))
+            if bindings == Bindings::Es {
+                sji = format!("{} {}", sampler_precision(&p.inner.ptype), sji);
+            }
+            if let Some(layout) = binding_layout(bindings, p.slot.get()) {
+                Some((format!("layout ({}) uniform {}", layout, sji), origin(p, p.inner.span)))
             } else {
-                Some(format!("uniform {}", sji))
+                Some((format!("uniform {}", sji), origin(p, p.inner.span)))
             }
         } else {
             None
         }
     }).collect();
-    let objects = objects.join("\n");
-    debug!("translated vertex format: {}", vformat);
-    debug!("translated root constants: {}", constants);
-    debug!("translated outputs: {}", outputs);
-    debug!("translated structures: {}", structs);
-    debug!("translated constant buffers: {}", cbuffers);
-    debug!("translated objects: {}", objects);
-    let output = [&*vformat, &*constants, &*outputs, &*structs, &*cbuffers, &*objects].iter()
-        .map(|s| *s)
-        .filter(|s| !s.is_empty())
-        .collect::<Vec<&str>>()
-        .join("\n");
-    Ok(output)
+    let join = |lines: &[Line]| lines.iter().map(|(v, _)| &**v).collect::<Vec<&str>>().join("\n");
+    debug!(target: "translation", "translated integer constants: {}", join(&defines));
+    debug!(target: "translation", "translated vertex format: {}", vformat);
+    debug!(target: "translation", "translated root constants: {}", constants);
+    debug!(target: "translation", "translated outputs: {}", outputs);
+    debug!(target: "translation", "translated varyings: {}", varyings);
+    debug!(target: "translation", "translated structures: {}", structs.join("\n"));
+    debug!(target: "translation", "translated constant buffers: {}", join(&cbuffers));
+    debug!(target: "translation", "translated objects: {}", join(&objects));
+    //Outputs share a single line, it is attributed to the first output.
+    let outputs_origin = ast.outputs.first().and_then(|v| origin(v, v.inner.span));
+    let groups: [Vec<Line>; 9] = [
+        vec![(precision.into(), None)],
+        defines,
+        vec![(vformat, None)],
+        vec![(constants, None)],
+        vec![(outputs, outputs_origin)],
+        varyings.lines().map(|v| (v.into(), None)).collect(),
+        structs.into_iter().map(|v| (v, None)).collect(),
+        cbuffers,
+        objects
+    ];
+    let lines: Vec<Line> = groups.into_iter()
+        .filter(|v| v.iter().any(|(v, _)| !v.is_empty()))
+        .flatten()
+        .collect();
+    let output = join(&lines);
+    let map = SourceMap {
+        lines: lines.into_iter().map(|(_, origin)| origin).collect()
+    };
+    Ok((output, map))
 }
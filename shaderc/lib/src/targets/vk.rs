@@ -0,0 +1,64 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use bpx::shader::Target::VK10;
+use crate::config::Config;
+use std::error::Error;
+use bp3d_symbols::TargetInfo;
+use crate::targets::basic::Target;
+use crate::targets::gl::{EnvInfo, GlTarget};
+use crate::targets::layout::LayoutRule;
+
+pub(crate) fn target() -> GlTarget
+{
+    GlTarget::new("VK", EnvInfo {
+        gl_version_int: 450,
+        gl_version_str: "1.1",
+        explicit_bindings: true,
+        extensions: &[],
+        max_viewports: 16,
+        layer_extension: Some("GL_ARB_shader_viewport_layer_array"),
+        int64_extension: Some("GL_EXT_shader_explicit_arithmetic_types_int64"),
+        vulkan: true,
+        spirv: false,
+        es: false,
+        max_cbuffer_bindings: None,
+        binding_shifts: &[],
+        layout: LayoutRule::Std140
+    }, VK10)
+}
+
+pub fn build(config: Config) -> Result<(), Box<dyn Error>>
+{
+    target().run(&config)
+}
+
+pub fn info() -> TargetInfo
+{
+    target().info()
+}
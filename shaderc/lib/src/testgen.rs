@@ -0,0 +1,513 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Deterministic pseudo-random generator of valid shader programs for stress and differential
+//! testing.
+
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use bp3d_sal::ast::tree::{BaseType, Property, PropertyType, Struct};
+use crate::Stage;
+use crate::targets::basic::{BasicAst, merge_stages, ShaderToSal, Slot, test_symbols};
+use crate::warnings::Reporter;
+
+/// SplitMix64 pseudo-random number generator; the sequence only depends on the seed.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng
+    {
+        Rng(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64
+    {
+        self.0 = self.0.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a number in the range [0, n).
+    pub fn below(&mut self, n: u32) -> u32
+    {
+        (self.next_u64() % n.max(1) as u64) as u32
+    }
+
+    /// Returns a number in the range [lo, hi].
+    pub fn range(&mut self, lo: u32, hi: u32) -> u32
+    {
+        lo + self.below(hi - lo + 1)
+    }
+
+    pub fn chance(&mut self, percent: u32) -> bool
+    {
+        self.below(100) < percent
+    }
+
+    pub fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T
+    {
+        &items[self.below(items.len() as u32) as usize]
+    }
+}
+
+/// Upper bounds on the size of generated programs.
+#[derive(Debug, Clone)]
+pub struct Limits
+{
+    pub packed_structs: u32,
+    pub cbuffers: u32,
+    pub props: u32,
+    pub root_constants: u32,
+    pub textures: u32,
+    pub outputs: u32,
+    pub array_size: u32
+}
+
+impl Default for Limits {
+    fn default() -> Self
+    {
+        Limits {
+            packed_structs: 3,
+            cbuffers: 3,
+            props: 6,
+            root_constants: 4,
+            textures: 3,
+            outputs: 3,
+            array_size: 8
+        }
+    }
+}
+
+/// A generated program made of a vertex and a pixel shader.
+pub struct Program
+{
+    pub seed: u64,
+    pub vertex: String,
+    pub pixel: String
+}
+
+impl Program {
+    /// Writes both shaders to the given directory and returns their paths (vertex, pixel).
+    pub fn write(&self, dir: &Path) -> std::io::Result<(PathBuf, PathBuf)>
+    {
+        let vertex = dir.join(format!("{}_vertex.glsl", self.seed));
+        let pixel = dir.join(format!("{}_pixel.glsl", self.seed));
+        std::fs::write(&vertex, &self.vertex)?;
+        std::fs::write(&pixel, &self.pixel)?;
+        Ok((vertex, pixel))
+    }
+}
+
+const BASE_TYPES: &[char] = &['f', 'i', 'u'];
+const BLEND_FACTORS: &[&str] = &["Zero", "One", "SrcColor", "OneMinusSrcColor", "SrcAlpha", "OneMinusSrcAlpha", "DstColor", "OneMinusDstColor", "DstAlpha", "OneMinusDstAlpha"];
+const BLEND_OPS: &[&str] = &["Add", "Sub", "InvSub", "Min", "Max"];
+const RENDER_MODES: &[&str] = &["Triangles", "Wireframe"];
+const CULLING_MODES: &[&str] = &["FrontFace", "BackFace", "Disabled"];
+
+fn base_type_name(c: char) -> &'static str
+{
+    match c {
+        'i' => "int",
+        'u' => "uint",
+        _ => "float"
+    }
+}
+
+struct Stage
+{
+    prefix: &'static str,
+    sal: String,
+    body: String, //GLSL statements accumulating into vec4 'acc'
+    packed: Vec<String>
+}
+
+struct Generator<'a>
+{
+    rng: Rng,
+    limits: &'a Limits,
+    orders: Vec<u32> //Remaining explicit constant buffer slots
+}
+
+impl<'a> Generator<'a> {
+    fn scalar(&mut self) -> String
+    {
+        base_type_name(*self.rng.pick(BASE_TYPES)).into()
+    }
+
+    fn vector(&mut self) -> String
+    {
+        format!("vec{}{}", self.rng.range(2, 4), self.rng.pick(BASE_TYPES))
+    }
+
+    fn matrix(&mut self) -> String
+    {
+        format!("mat{}f", self.rng.range(3, 4))
+    }
+
+    // Returns a random type usable in a struct along with its accumulation expression, if any.
+    fn prop_type(&mut self, packed: &[String], name: &str) -> (String, Option<String>)
+    {
+        match self.rng.below(if packed.is_empty() { 4 } else { 6 }) {
+            0 => {
+                let ty = self.scalar();
+                (ty, Some(format!("vec4(float({}))", name)))
+            },
+            1 => {
+                let ty = self.vector();
+                let expr = if ty == "vec4f" { Some(name.to_string()) } else { None };
+                (ty, expr)
+            },
+            2 => (self.matrix(), None),
+            3 => {
+                let item = if self.rng.chance(50) { self.vector() } else { self.matrix() };
+                (format!("{}[{}]", item, self.rng.range(1, self.limits.array_size)), None)
+            },
+            4 => (self.rng.pick(packed).clone(), None),
+            _ => (format!("{}[{}]", self.rng.pick(packed), self.rng.range(1, self.limits.array_size)), None)
+        }
+    }
+
+    fn packed_struct(&mut self, stage: &mut Stage, index: u32)
+    {
+        let name = format!("{}Packed{}", stage.prefix, index);
+        let _ = writeln!(stage.sal, "const struct {} : Pack\n{{", name);
+        let packed = stage.packed.clone();
+        for i in 0..self.rng.range(1, self.limits.props) {
+            let (ty, _) = self.prop_type(&packed, "");
+            let _ = writeln!(stage.sal, "    {} Member{};", ty, i);
+        }
+        stage.sal.push_str("}\n\n");
+        stage.packed.push(name);
+    }
+
+    fn cbuffer(&mut self, stage: &mut Stage, index: u32)
+    {
+        let name = format!("{}Buffer{}", stage.prefix, index);
+        let attr = if !self.orders.is_empty() && self.rng.chance(30) {
+            //Explicit orders must stay contiguous as they are mixed with implicit ones.
+            let slot = self.orders.remove(0);
+            format!(" : ORDER_{}", slot)
+        } else {
+            String::new()
+        };
+        let _ = writeln!(stage.sal, "const struct {}{}\n{{", name, attr);
+        let packed = stage.packed.clone();
+        for i in 0..self.rng.range(1, self.limits.props) {
+            let prop = format!("Prop{}", i);
+            let (ty, expr) = self.prop_type(&packed, &format!("{}_{}", name, prop));
+            let _ = writeln!(stage.sal, "    {} {};", ty, prop);
+            if let Some(expr) = expr {
+                let _ = writeln!(stage.body, "    acc += {};", expr);
+            }
+        }
+        stage.sal.push_str("}\n\n");
+    }
+
+    fn root_constants(&mut self, stage: &mut Stage)
+    {
+        for i in 0..self.rng.below(self.limits.root_constants + 1) {
+            let name = format!("{}Root{}", stage.prefix, i);
+            if self.rng.chance(70) {
+                let ty = self.scalar();
+                let _ = writeln!(stage.sal, "const {} {};", ty, name);
+                let _ = writeln!(stage.body, "    acc += vec4(float({}));", name);
+            } else {
+                let _ = writeln!(stage.sal, "const vec4f {};", name);
+                let _ = writeln!(stage.body, "    acc += {};", name);
+            }
+        }
+        stage.sal.push('\n');
+    }
+
+    fn textures(&mut self, stage: &mut Stage)
+    {
+        for i in 0..self.rng.below(self.limits.textures + 1) {
+            let sampler = format!("{}Sampler{}", stage.prefix, i);
+            let _ = writeln!(stage.sal, "const Sampler {};", sampler);
+            if self.rng.chance(70) {
+                let _ = writeln!(stage.sal, "const Texture2D:vec4f {}Texture{} : {};", stage.prefix, i, sampler);
+                let _ = writeln!(stage.body, "    acc += texture({}Texture{}, vec2(0.5));", stage.prefix, i);
+            } else {
+                let _ = writeln!(stage.sal, "const Texture3D:vec4f {}Texture{} : {};", stage.prefix, i, sampler);
+                let _ = writeln!(stage.body, "    acc += texture({}Texture{}, vec3(0.5));", stage.prefix, i);
+            }
+        }
+        stage.sal.push('\n');
+    }
+
+    fn common(&mut self, stage: &mut Stage)
+    {
+        for i in 0..self.rng.below(self.limits.packed_structs + 1) {
+            self.packed_struct(stage, i);
+        }
+        for i in 0..self.rng.below(self.limits.cbuffers + 1) {
+            self.cbuffer(stage, i);
+        }
+        self.root_constants(stage);
+        self.textures(stage);
+    }
+
+    fn vertex(&mut self) -> String
+    {
+        let mut stage = Stage {
+            prefix: "V",
+            sal: String::new(),
+            body: String::new(),
+            packed: Vec::new()
+        };
+        self.common(&mut stage);
+        stage.sal.push_str("vformat struct Vertex\n{\n    vec3f Position;\n");
+        for i in 0..self.rng.below(4) {
+            let _ = writeln!(stage.sal, "    vec{}f Attribute{};", self.rng.range(2, 4), i);
+        }
+        stage.sal.push_str("}\n");
+        format!("#stage vertex\n\n#sal\n{}#sal\n\nvoid main()\n{{\n    vec4 acc = vec4(0.0);\n{}    gl_Position = vec4(Vertex_Position, 1.0) + acc * 0.0;\n}}\n", stage.sal, stage.body)
+    }
+
+    fn pixel(&mut self) -> String
+    {
+        let mut stage = Stage {
+            prefix: "P",
+            sal: String::new(),
+            body: String::new(),
+            packed: Vec::new()
+        };
+        self.common(&mut stage);
+        let mut outputs = String::new();
+        for i in 0..self.rng.range(1, self.limits.outputs) {
+            let _ = writeln!(stage.sal, "output vec4f Output{} : ORDER_{};", i, i);
+            if self.rng.chance(50) {
+                let _ = writeln!(stage.sal, "blendfunc Output{}\n{{\n    SrcColor = {};\n    DstColor = {};\n    SrcAlpha = {};\n    DstAlpha = {};\n    ColorOp = {};\n    AlphaOp = {};\n}}",
+                    i, self.rng.pick(BLEND_FACTORS), self.rng.pick(BLEND_FACTORS), self.rng.pick(BLEND_FACTORS),
+                    self.rng.pick(BLEND_FACTORS), self.rng.pick(BLEND_OPS), self.rng.pick(BLEND_OPS));
+            }
+            let _ = writeln!(outputs, "    Output{} = acc;", i);
+        }
+        if self.rng.chance(70) {
+            let _ = writeln!(stage.sal, "\npipeline Pipeline\n{{\n    DepthEnable = {};\n    DepthWriteEnable = {};\n    ScissorEnable = {};\n    RenderMode = {};\n    CullingMode = {};\n}}",
+                self.rng.chance(50), self.rng.chance(50), self.rng.chance(50),
+                self.rng.pick(RENDER_MODES), self.rng.pick(CULLING_MODES));
+        }
+        format!("#stage pixel\n\n#sal\n{}#sal\n\nvoid main()\n{{\n    vec4 acc = vec4(0.0);\n{}{}}}\n", stage.sal, stage.body, outputs)
+    }
+}
+
+/// Generates a random but valid program from the given seed.
+pub fn generate(seed: u64, limits: &Limits) -> Program
+{
+    let mut gen = Generator {
+        rng: Rng::new(seed),
+        limits,
+        orders: (1..8).collect()
+    };
+    let vertex = gen.vertex();
+    let pixel = gen.pixel();
+    Program {
+        seed,
+        vertex,
+        pixel
+    }
+}
+
+/// Synthetic units ready to be merged by [merge].
+pub struct MergeInput(Vec<ShaderToSal>);
+
+fn property(name: String, ptype: PropertyType) -> Property<usize>
+{
+    Property {
+        pname: name,
+        ptype,
+        pattr: None,
+        deprecated: None,
+        pformat: None,
+        default: None,
+        span: Default::default()
+    }
+}
+
+/// Builds `units` units alternating between the vertex and pixel stages, each declaring a
+/// constant buffer and a texture.
+pub fn merge_input(units: usize) -> MergeInput
+{
+    MergeInput((0..units).map(|i| {
+        let mut statements = BasicAst::new();
+        statements.cbuffers.push(Slot::new(Struct {
+            name: format!("Buffer{}", i),
+            attr: None,
+            deprecated: None,
+            props: vec![property("Value".into(), PropertyType::Scalar(BaseType::Float))],
+            span: Default::default()
+        }));
+        statements.objects.push(Slot::new(property(format!("Texture{}", i), PropertyType::Sampler)));
+        ShaderToSal {
+            name: format!("unit{}.glsl", i),
+            strings: Vec::new(),
+            statements,
+            stage: if i % 2 == 0 { Stage::Vertex } else { Stage::Pixel },
+            vformats: Vec::new(),
+            rootconsts: Vec::new(),
+            dependencies: Vec::new(),
+            source: String::new()
+        }
+    }).collect())
+}
+
+/// Merges the units into stages and checks their symbols; returns the number of constant buffers
+/// in the merged vertex stage.
+pub fn merge(input: MergeInput) -> usize
+{
+    let stages = merge_stages(input.0, &Reporter::new());
+    test_symbols(&stages).unwrap();
+    stages[&Stage::Vertex].statements.cbuffers.len()
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::collections::{BTreeMap, HashMap};
+    use std::fs::File;
+    use std::io::BufReader;
+    use std::path::{Path, PathBuf};
+    use bp3d_symbols::{compute_layout, FromBpx, StructLayout, StructObject};
+    use bpx::shader::ShaderPack;
+    use bpx::shader::symbol::Type;
+    use crate::{Compiler, Config, Unit};
+    use crate::targets::basic::Target;
+    use crate::testgen::{generate, Limits, Program};
+
+    const SEEDS: u64 = 16;
+
+    fn temp_dir(name: &str) -> PathBuf
+    {
+        let dir = std::env::temp_dir().join(format!("shaderc-testgen-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn config<'a>(units: &'a (PathBuf, PathBuf), output: &'a Path, n_threads: usize) -> Config<'a>
+    {
+        Config {
+            n_threads,
+            ..Config::new(vec![Unit::Path(&units.0), Unit::Path(&units.1)], output)
+        }
+    }
+
+    #[test]
+    fn deterministic_generation()
+    {
+        for seed in 0..SEEDS {
+            let a = generate(seed, &Limits::default());
+            let b = generate(seed, &Limits::default());
+            assert_eq!(a.vertex, b.vertex, "seed {}", seed);
+            assert_eq!(a.pixel, b.pixel, "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn thread_count_independent()
+    {
+        let dir = temp_dir("threads");
+        for seed in 0..SEEDS {
+            let program: Program = generate(seed, &Limits::default());
+            let units = program.write(&dir).unwrap();
+            let out1 = dir.join(format!("{}_1.bpx", seed));
+            let out4 = dir.join(format!("{}_4.bpx", seed));
+            let compiler = Compiler::get("GL40").unwrap();
+            if let Err(e) = compiler.run(config(&units, &out1, 1)) {
+                panic!("seed {}: {}\n{}\n{}", seed, e, program.vertex, program.pixel);
+            }
+            compiler.run(config(&units, &out4, 4)).unwrap();
+            assert!(std::fs::read(&out1).unwrap() == std::fs::read(&out4).unwrap(), "seed {}: packs differ", seed);
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn shared_layout()
+    {
+        //Offsets written by the layout of shaderc must match the ones recomputed by the symbols
+        //crate from the types recorded in the pack.
+        let dir = temp_dir("layout");
+        for seed in 0..SEEDS {
+            let program = generate(seed, &Limits::default());
+            let units = program.write(&dir).unwrap();
+            let output = dir.join(format!("{}.bpx", seed));
+            Compiler::get("GL40").unwrap().run(config(&units, &output, 1)).unwrap();
+            let pack = ShaderPack::open(BufReader::new(File::open(&output).unwrap())).unwrap();
+            let symbols = pack.symbols().unwrap();
+            //Referenced structs are written before the structs which reference them.
+            let mut layouts: HashMap<u16, StructLayout> = HashMap::new();
+            for (index, info) in symbols.iter().enumerate() {
+                let val = symbols.load_extended_data(info).unwrap();
+                if !matches!(info.ty, Type::ConstantBuffer) || val.is_null() {
+                    continue;
+                }
+                let name: String = symbols.load_name(info).unwrap().into();
+                let obj = StructObject::from_bpx(val).unwrap();
+                let rule = obj.layout.unwrap_or_else(|| panic!("seed {}: struct '{}' has no layout rule", seed, name));
+                let layout = compute_layout(&obj, rule, |v| layouts.get(&v))
+                    .unwrap_or_else(|e| panic!("seed {}: struct '{}': {}", seed, name, e));
+                let offsets: Vec<u32> = obj.props.iter().map(|v| v.offset).collect();
+                assert_eq!(offsets, layout.offsets, "seed {}: offsets of '{}' differ\n{}\n{}", seed, name, program.vertex, program.pixel);
+                assert_eq!(obj.size, layout.size, "seed {}: size of '{}' differs", seed, name);
+                layouts.insert(index as u16, layout);
+            }
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn stable_relocation()
+    {
+        let dir = temp_dir("relocation");
+        let target = crate::targets::gl40::target();
+        for seed in 0..SEEDS {
+            let units = generate(seed, &Limits::default()).write(&dir).unwrap();
+            let output = dir.join("unused.bpx");
+            let relocate = || {
+                let mut stages = target.pre_process(&config(&units, &output, 1)).unwrap();
+                target.relocate_bindings(&config(&units, &output, 1), &mut stages).unwrap();
+                target.test_bindings(&stages).unwrap();
+                let mut slots = BTreeMap::new();
+                for (stage, v) in &stages {
+                    for cbuf in &v.statements.cbuffers {
+                        slots.insert((*stage, cbuf.inner.name.clone()), cbuf.slot.get());
+                    }
+                    for obj in &v.statements.objects {
+                        slots.insert((*stage, obj.inner.pname.clone()), obj.slot.get());
+                    }
+                }
+                slots
+            };
+            assert_eq!(relocate(), relocate(), "seed {}", seed);
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,463 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Warning categories and the reporter all compiler warnings go through.
+
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::sync::{Arc, Mutex};
+use log::{error, warn};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error
+{
+    #[error("unknown warning kind '{0}'")]
+    UnknownKind(String),
+    #[error("unknown warning level '{0}' (expected error, warn or allow)")]
+    UnknownLevel(String),
+    #[error("invalid warning setting '{0}' (expected <kind>=<level>)")]
+    Syntax(String),
+    #[error("{0} warning(s) treated as error(s)")]
+    Denied(usize)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WarningKind
+{
+    /// Two bindings of a stage may end up on the same slot.
+    BindingOverlap,
+    /// The shader needs more bindings than all hardware supports.
+    BindingLimit,
+    /// A binding assigned from SAL differs from the one the compiler reports.
+    BindingMismatch,
+    /// A layout exceeds its recommended size.
+    LayoutSize,
+    /// A symbol, pipeline, vertex format or resource is declared more than once.
+    Duplicate,
+    /// A deprecated symbol is used.
+    Deprecated,
+    /// The shader pack has no vertex format, pipeline or render target output.
+    Missing,
+    /// A shader does not specify its stage.
+    DefaultStage,
+    /// A shader unit was skipped.
+    SkippedUnit,
+    /// A varying is not passed from a stage to the next one.
+    Varying,
+    /// A vertex format mixes explicit and implicit attribute locations.
+    ImplicitLocation,
+    /// An option has no effect on the chosen target.
+    IgnoredOption
+}
+
+impl WarningKind
+{
+    pub const ALL: [WarningKind; 12] = [
+        WarningKind::BindingOverlap,
+        WarningKind::BindingLimit,
+        WarningKind::BindingMismatch,
+        WarningKind::LayoutSize,
+        WarningKind::Duplicate,
+        WarningKind::Deprecated,
+        WarningKind::Missing,
+        WarningKind::DefaultStage,
+        WarningKind::SkippedUnit,
+        WarningKind::Varying,
+        WarningKind::ImplicitLocation,
+        WarningKind::IgnoredOption
+    ];
+
+    pub fn name(&self) -> &'static str
+    {
+        match self {
+            WarningKind::BindingOverlap => "binding-overlap",
+            WarningKind::BindingLimit => "binding-limit",
+            WarningKind::BindingMismatch => "binding-mismatch",
+            WarningKind::LayoutSize => "layout-size",
+            WarningKind::Duplicate => "duplicate",
+            WarningKind::Deprecated => "deprecated",
+            WarningKind::Missing => "missing",
+            WarningKind::DefaultStage => "default-stage",
+            WarningKind::SkippedUnit => "skipped-unit",
+            WarningKind::Varying => "varying",
+            WarningKind::ImplicitLocation => "implicit-location",
+            WarningKind::IgnoredOption => "ignored-option"
+        }
+    }
+}
+
+impl Display for WarningKind
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+    {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level
+{
+    Allow,
+    Warn,
+    Error
+}
+
+impl Level
+{
+    pub fn name(&self) -> &'static str
+    {
+        match self {
+            Level::Allow => "allow",
+            Level::Warn => "warn",
+            Level::Error => "error"
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Warning
+{
+    pub kind: WarningKind,
+    pub level: Level,
+    pub message: String,
+    /// Units the warning was reported for, without duplicates.
+    pub contexts: Vec<String>,
+    /// Number of times the warning was reported.
+    pub count: usize
+}
+
+impl Display for Warning
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+    {
+        if self.count == 1 && self.contexts.len() == 1 {
+            return write!(f, "{}: {} [{}]", self.contexts[0], self.message, self.kind);
+        }
+        write!(f, "{} [{}]", self.message, self.kind)?;
+        if self.count > 1 {
+            write!(f, " (repeated {} times", self.count)?;
+            if !self.contexts.is_empty() {
+                write!(f, " across {}", self.contexts.join(", "))?;
+            }
+            f.write_str(")")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
+struct Records
+{
+    warnings: Vec<Warning>,
+    //Number of warnings already printed by flush.
+    flushed: usize
+}
+
+/// Collects the warnings of a build.
+///
+/// Clones share their records, so a clone kept before running the compiler gives access to the
+/// warnings of the build.
+///
+/// By default identical warnings are coalesced: they are printed once by `flush` with the number
+/// of repetitions and the units they were reported for.
+#[derive(Debug, Clone)]
+pub struct Reporter
+{
+    levels: HashMap<WarningKind, Level>,
+    coalesce: bool,
+    records: Arc<Mutex<Records>>
+}
+
+impl Default for Reporter
+{
+    fn default() -> Self
+    {
+        Reporter {
+            levels: WarningKind::ALL.iter().map(|v| (*v, Level::Warn)).collect(),
+            coalesce: true,
+            records: Arc::new(Mutex::new(Records::default()))
+        }
+    }
+}
+
+fn log_warning(level: Level, line: std::fmt::Arguments)
+{
+    match level {
+        Level::Error => error!("{}", line),
+        _ => warn!("{}", line)
+    }
+}
+
+impl Reporter
+{
+    pub fn new() -> Reporter
+    {
+        Reporter::default()
+    }
+
+    /// Applies a `<kind>=<level>` setting; `all` applies the level to every kind.
+    pub fn configure(&mut self, setting: &str) -> Result<(), Error>
+    {
+        let (kind, level) = setting.split_once('=').ok_or_else(|| Error::Syntax(setting.into()))?;
+        let level = match level.trim() {
+            "allow" => Level::Allow,
+            "warn" => Level::Warn,
+            "error" => Level::Error,
+            v => return Err(Error::UnknownLevel(v.into()))
+        };
+        match kind.trim() {
+            "all" => self.levels.values_mut().for_each(|v| *v = level),
+            name => {
+                let kind = WarningKind::ALL.iter().find(|v| v.name() == name)
+                    .ok_or_else(|| Error::UnknownKind(name.into()))?;
+                self.levels.insert(*kind, level);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns a reporter applying the same levels but recording warnings separately.
+    pub fn fork(&self) -> Reporter
+    {
+        Reporter {
+            levels: self.levels.clone(),
+            coalesce: self.coalesce,
+            records: Arc::new(Mutex::new(Records::default()))
+        }
+    }
+
+    /// Enables or disables coalescing; when disabled every report is printed immediately.
+    pub fn set_coalesce(&mut self, coalesce: bool)
+    {
+        self.coalesce = coalesce;
+    }
+
+    pub fn level(&self, kind: WarningKind) -> Level
+    {
+        self.levels[&kind]
+    }
+
+    /// Returns the settings which differ from the default level.
+    pub fn settings(&self) -> Vec<String>
+    {
+        WarningKind::ALL.iter()
+            .filter(|v| self.level(**v) != Level::Warn)
+            .map(|v| format!("{}={}", v, self.level(*v).name()))
+            .collect()
+    }
+
+    /// Reports a warning; identical warnings (for example emitted once per stage) are only
+    /// recorded once.
+    pub fn report<T: Into<String>>(&self, kind: WarningKind, message: T)
+    {
+        self.record(kind, None, message.into());
+    }
+
+    /// Reports a warning caused by the given unit; identical warnings of different units are
+    /// recorded once along with the list of units.
+    pub fn report_in<T: Into<String>>(&self, kind: WarningKind, unit: &str, message: T)
+    {
+        self.record(kind, Some(unit), message.into());
+    }
+
+    fn record(&self, kind: WarningKind, unit: Option<&str>, message: String)
+    {
+        let level = self.level(kind);
+        if level == Level::Allow {
+            return;
+        }
+        if !self.coalesce {
+            match unit {
+                Some(unit) => log_warning(level, format_args!("{}: {} [{}]", unit, message, kind)),
+                None => log_warning(level, format_args!("{} [{}]", message, kind))
+            }
+        }
+        let mut records = self.records.lock().unwrap();
+        let index = match records.warnings.iter().position(|v| v.kind == kind && v.message == message) {
+            Some(index) => {
+                records.warnings[index].count += 1;
+                index
+            },
+            None => {
+                records.warnings.push(Warning {
+                    kind,
+                    level,
+                    message,
+                    contexts: Vec::new(),
+                    count: 1
+                });
+                records.warnings.len() - 1
+            }
+        };
+        let warning = &mut records.warnings[index];
+        if let Some(unit) = unit {
+            if !warning.contexts.iter().any(|v| v == unit) {
+                warning.contexts.push(unit.into());
+            }
+        }
+    }
+
+    /// Prints the coalesced warnings which were not printed yet; does nothing when coalescing
+    /// is disabled as warnings are then printed as they are reported.
+    pub fn flush(&self)
+    {
+        if !self.coalesce {
+            return;
+        }
+        let mut records = self.records.lock().unwrap();
+        for warning in &records.warnings[records.flushed..] {
+            log_warning(warning.level, format_args!("{}", warning));
+        }
+        records.flushed = records.warnings.len();
+    }
+
+    /// Returns all warnings reported so far.
+    pub fn records(&self) -> Vec<Warning>
+    {
+        self.records.lock().unwrap().warnings.clone()
+    }
+
+    /// Fails if a warning was escalated to an error.
+    pub fn check(&self) -> Result<(), Error>
+    {
+        let count = self.records.lock().unwrap().warnings.iter().filter(|v| v.level == Level::Error).count();
+        if count > 0 {
+            return Err(Error::Denied(count));
+        }
+        Ok(())
+    }
+}
+
+impl Display for Reporter
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+    {
+        let levels: Vec<String> = WarningKind::ALL.iter().map(|v| format!("{}={}", v, self.level(*v).name())).collect();
+        f.write_str(&levels.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::warnings::{Error, Level, Reporter, WarningKind};
+
+    #[test]
+    fn configure()
+    {
+        let mut reporter = Reporter::new();
+        reporter.configure("all=error").unwrap();
+        reporter.configure("missing=allow").unwrap();
+        assert_eq!(reporter.level(WarningKind::Duplicate), Level::Error);
+        assert_eq!(reporter.level(WarningKind::Missing), Level::Allow);
+        assert_eq!(reporter.settings().len(), WarningKind::ALL.len());
+        assert!(matches!(reporter.configure("unused=allow"), Err(Error::UnknownKind(_))));
+        assert!(matches!(reporter.configure("missing=deny"), Err(Error::UnknownLevel(_))));
+        assert!(matches!(reporter.configure("missing"), Err(Error::Syntax(_))));
+    }
+
+    #[test]
+    fn suppression()
+    {
+        let mut reporter = Reporter::new();
+        reporter.configure("missing=allow").unwrap();
+        let records = reporter.clone();
+        reporter.report(WarningKind::Missing, "No pipeline was found in shader pack build");
+        reporter.report(WarningKind::Duplicate, "Duplicate symbol name 'Color'");
+        let records = records.records();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].kind, WarningKind::Duplicate);
+        assert!(reporter.check().is_ok());
+    }
+
+    #[test]
+    fn escalation()
+    {
+        let mut reporter = Reporter::new();
+        reporter.configure("duplicate=error").unwrap();
+        reporter.report(WarningKind::Missing, "No pipeline was found in shader pack build");
+        assert!(reporter.check().is_ok());
+        reporter.report(WarningKind::Duplicate, "Duplicate symbol name 'Color'");
+        assert!(matches!(reporter.check(), Err(Error::Denied(1))));
+    }
+
+    #[test]
+    fn deduplication()
+    {
+        let reporter = Reporter::new();
+        for _ in 0..3 {
+            reporter.report(WarningKind::BindingOverlap, "Possible duplicate of texture slot 1");
+        }
+        reporter.report(WarningKind::BindingOverlap, "Possible duplicate of texture slot 2");
+        assert_eq!(reporter.records().len(), 2);
+    }
+
+    #[test]
+    fn coalescing()
+    {
+        let reporter = Reporter::new();
+        for unit in ["a.glsl", "b.glsl", "a.glsl"] {
+            reporter.report_in(WarningKind::Deprecated, unit, "use of deprecated symbol 'M::Legacy'");
+        }
+        reporter.report_in(WarningKind::Deprecated, "c.glsl", "use of deprecated symbol 'M::Other'");
+        let records = reporter.records();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].count, 3);
+        assert_eq!(records[0].to_string(), "use of deprecated symbol 'M::Legacy' [deprecated] (repeated 3 times across a.glsl, b.glsl)");
+        assert_eq!(records[1].to_string(), "c.glsl: use of deprecated symbol 'M::Other' [deprecated]");
+        reporter.flush();
+        reporter.report(WarningKind::Missing, "No pipeline was found in shader pack build");
+        reporter.report(WarningKind::Missing, "No pipeline was found in shader pack build");
+        assert_eq!(reporter.records()[2].to_string(), "No pipeline was found in shader pack build [missing] (repeated 2 times)");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn build_fails()
+    {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+        use crate::{Compiler, Config, Unit};
+        let dir = std::env::temp_dir().join(format!("shaderc-warnings-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let unit = dir.join(OsStr::from_bytes(b"\xff.sal"));
+        let output = dir.join("out.bpx");
+        let mut warnings = Reporter::new();
+        warnings.configure("skipped-unit=error").unwrap();
+        let config = Config {
+            warnings: warnings.clone(),
+            ..Config::new(vec![Unit::Path(&unit)], &output)
+        };
+        let err = Compiler::get("LIB").unwrap().run(config).unwrap_err();
+        assert_eq!(err.to_string(), Error::Denied(1).to_string());
+        assert!(!output.exists());
+        assert_eq!(warnings.records()[0].kind, WarningKind::SkippedUnit);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,84 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+
+//! Allocation count of the stage merge. The counting allocator is global to the test binary, so
+//! this test lives in its own binary; run with `cargo test -p bp3d-shaderc --features testgen`.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use bp3d_shaderc::testgen::{merge, merge_input};
+
+//Counts allocations made by the current thread so that concurrently running tests do not interfere.
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator
+{
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8
+    {
+        let _ = ALLOCATIONS.try_with(|v| v.set(v.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout)
+    {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8
+    {
+        let _ = ALLOCATIONS.try_with(|v| v.set(v.get() + 1));
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn allocations() -> usize
+{
+    ALLOCATIONS.with(|v| v.get())
+}
+
+#[test]
+fn merge_does_not_clone()
+{
+    const UNITS: usize = 256;
+    let input = merge_input(UNITS);
+    let before = allocations();
+    let cbuffers = merge(input);
+    let count = allocations() - before;
+    assert_eq!(cbuffers, UNITS / 2);
+    //Cloning a single statement costs several allocations (name, members, member names); only the
+    //amortized growth of the per-stage vectors and symbol sets is allowed here.
+    assert!(count < UNITS / 2, "merge made {} allocation(s) for {} unit(s)", count, UNITS);
+}
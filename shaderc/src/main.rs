@@ -26,30 +26,327 @@
 // NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
 // SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
-use std::path::Path;
-use clap::{Arg, Command};
+use std::ffi::OsStr;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use clap::{Arg, ArgMatches, Command};
 use log::{debug, error, info};
-use cli_common::{alloc_verbosity_level, get_out_path, init_bp3d_logger};
-use bp3d_shaderc::Config;
-use bp3d_shaderc::Unit;
-use bp3d_shaderc::Compiler;
+use cli_common::{dedup_libs, expand_response_files, get_out_path, init_logger, interrupt_flag, report_error, run_cli, split_verbose_directives, Category, Directives, Report, Watcher, WatchError};
+use bp3d_shaderc::{CompressionPreference, Config, DEFAULT_MAX_EXT_DATA_SIZE, DEFAULT_MAX_RESOURCE_SIZE};
+use bp3d_shaderc::{Stage, Unit};
+use bp3d_shaderc::{Compiler, Output};
+use bp3d_shaderc::cache::UnitCache;
+use bp3d_shaderc::depfile::Depfile;
+use bp3d_shaderc::failure::{classify, FailureKind};
+use bp3d_shaderc::repro::{Replay, Sources, write_bundle};
+use bp3d_shaderc::warnings::Reporter;
+use bp3d_sal::check::check_module;
+use bp3d_symbols::{TargetInfo, TargetList};
 
 const PROG_NAME: &str = env!("CARGO_PKG_NAME");
 const PROG_VERSION: &str = env!("CARGO_PKG_VERSION");
 
-fn run() -> i32
+#[derive(Debug)]
+enum Error
 {
-    let matches = Command::new(PROG_NAME)
+    Usage(String),
+    TargetNotFound(String),
+    Unsupported(&'static str),
+    Build(Box<dyn std::error::Error>),
+    Replay(bp3d_shaderc::repro::Error),
+    Sign(PathBuf, String),
+    Watch(WatchError),
+    InvalidModules(usize)
+}
+
+impl Display for Error
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+    {
+        match self {
+            Error::Usage(v) => f.write_str(v),
+            Error::TargetNotFound(v) => write!(f, "target not found: {}", v),
+            Error::Unsupported(v) => write!(f, "this build of shaderc does not support {}", v),
+            Error::Build(_) => f.write_str("build failed"),
+            Error::Replay(_) => f.write_str("failed to open reproducer bundle"),
+            Error::Sign(path, e) => write!(f, "failed to sign {}: {}", path.display(), e),
+            Error::Watch(e) => write!(f, "{}", e),
+            Error::InvalidModules(count) => write!(f, "{} module(s) failed to check", count)
+        }
+    }
+}
+
+impl std::error::Error for Error
+{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)>
+    {
+        match self {
+            Error::Build(e) => Some(&**e),
+            Error::Replay(e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+impl Report for Error
+{
+    fn category(&self) -> Category
+    {
+        match self {
+            Error::Usage(_) | Error::TargetNotFound(_) | Error::Unsupported(_) => Category::Usage,
+            Error::Build(e) => match classify(&**e) {
+                FailureKind::Io => Category::Io,
+                FailureKind::Input => Category::Input,
+                FailureKind::Toolchain => Category::Toolchain,
+                FailureKind::Format => Category::Format,
+                FailureKind::Other => Category::Other
+            },
+            Error::Replay(bp3d_shaderc::repro::Error::Io(_)) => Category::Io,
+            Error::Replay(_) => Category::Format,
+            Error::InvalidModules(_) => Category::Input,
+            Error::Sign(_, _) | Error::Watch(_) => Category::Other
+        }
+    }
+}
+
+fn replay(bundle: &Path) -> Result<(), Error>
+{
+    let root = std::env::temp_dir().join(format!("shaderc-replay-{}", std::process::id()));
+    let replay = Replay::open(bundle, &root).map_err(Error::Replay)?;
+    info!("Replaying build of shaderc {} for target {}...", replay.manifest.version, replay.manifest.target);
+    info!("Recorded error: {}", replay.report.message);
+    let res = match Compiler::get(&replay.manifest.target) {
+        Some(compiler) => compiler.run(replay.config()).map_err(Error::Build),
+        None => Err(Error::TargetNotFound(replay.manifest.target.clone()))
+    };
+    if res.is_ok() {
+        info!("Build succeeded; the recorded error was not reproduced");
+    }
+    res.map(|_| ())
+}
+
+fn print_targets()
+{
+    let yes_no = |v: bool| if v { "yes" } else { "no" };
+    println!("{:<6} {:<8} {:<8} {:<6} {:<6} {:<9} {:<7} {:<6} {:<8} {:<6} {:<10} {:<11} {}",
+             "NAME", "API", "VERSION", "BPX", "DEBUG", "OPTIMIZE", "MINIFY", "SPIRV", "COMPUTE", "INT64", "VIEWPORTS", "PAYLOAD", "STAGES");
+    for info in Compiler::list() {
+        let stages = if info.supported_stages.is_empty() {
+            "-".into()
+        } else {
+            info.supported_stages.join(",")
+        };
+        println!("{:<6} {:<8} {:<8} {:<6} {:<6} {:<9} {:<7} {:<6} {:<8} {:<6} {:<10} {:<11} {}",
+                 info.name, info.api.as_deref().unwrap_or("-"), info.version.as_deref().unwrap_or("-"),
+                 info.bpx_target.as_deref().unwrap_or("-"), yes_no(info.supports_debug), yes_no(info.supports_optimize),
+                 yes_no(info.supports_minify), yes_no(info.supports_spirv), yes_no(info.supports_compute),
+                 yes_no(info.supports_int64), info.max_viewports, info.payload_format, stages);
+    }
+}
+
+/// Rejects code generation flags which the chosen target would otherwise silently ignore.
+fn check_target_flags(info: &TargetInfo, debug: bool, optimize: bool, minify: bool, manifest: bool) -> Result<(), Error>
+{
+    let unsupported = [
+        ("--debug", debug && !info.supports_debug),
+        ("--optimize", optimize && !info.supports_optimize),
+        ("--minify", minify && !info.supports_minify),
+        ("--manifest", manifest && info.bpx_target.is_none())
+    ];
+    match unsupported.iter().find(|(_, v)| *v) {
+        Some((flag, _)) => Err(Error::Usage(format!("target {} does not support {}", info.name, flag))),
+        None => Ok(())
+    }
+}
+
+fn check_modules(args: &ArgMatches) -> Result<(), Error>
+{
+    let mut failed = 0;
+    for path in args.values_of_os("module").unwrap().map(Path::new) {
+        let source = match std::fs::read(path) {
+            Ok(v) => v,
+            Err(e) => {
+                error!("Failed to read module {}: {}", path.display(), e);
+                failed += 1;
+                continue;
+            }
+        };
+        let diagnostics = check_module(&path.display().to_string(), source);
+        if diagnostics.is_empty() {
+            info!("Module {} is valid", path.display());
+        } else {
+            failed += 1;
+        }
+        for v in diagnostics {
+            error!("{}", v);
+        }
+    }
+    match failed {
+        0 => Ok(()),
+        n => Err(Error::InvalidModules(n))
+    }
+}
+
+#[cfg(feature = "json-schema")]
+fn schema(args: &ArgMatches) -> Result<(), Error>
+{
+    let name = args.value_of("name").unwrap();
+    let schema = bp3d_symbols::json_schema(name).ok_or_else(|| Error::Usage(format!("unknown schema: {}", name)))?;
+    println!("{}", schema);
+    Ok(())
+}
+
+#[cfg(not(feature = "json-schema"))]
+fn schema(_: &ArgMatches) -> Result<(), Error>
+{
+    Err(Error::Unsupported("JSON schemas (json-schema feature)"))
+}
+
+#[cfg(feature = "signing")]
+fn sign_output(matches: &ArgMatches, output: &Path) -> Result<(), Error>
+{
+    let key = match matches.value_of_os("sign_key") {
+        Some(v) => Path::new(v),
+        None => return Ok(())
+    };
+    let fingerprint = bp3d_symbols::load_signing_key(key).map_err(|e| e.to_string())
+        .and_then(|key| bp3d_symbols::sign_file(output, &key).map_err(|e| e.to_string()))
+        .map_err(|e| Error::Sign(output.into(), e))?;
+    info!("Signed {} with key {}", output.display(), fingerprint);
+    Ok(())
+}
+
+#[cfg(not(feature = "signing"))]
+fn sign_output(matches: &ArgMatches, _: &Path) -> Result<(), Error>
+{
+    if !matches.is_present("sign_key") {
+        return Ok(());
+    }
+    Err(Error::Unsupported("signing (signing feature)"))
+}
+
+fn build(matches: &ArgMatches, compiler: &Compiler, target: &str, config: Config, output: &Path) -> Result<(), Error>
+{
+    let bundle = matches.value_of_os("repro_on_error").map(Path::new);
+    let config = Config {
+        sources: bundle.map(|_| Sources::new()),
+        ..config
+    };
+    let repro = bundle.map(|path| (path, config.clone()));
+    match compiler.run(config) {
+        Ok(Output::Written) => sign_output(matches, output),
+        Ok(Output::Unchanged) => {
+            info!("Output up to date (content unchanged)");
+            sign_output(matches, output)
+        },
+        Err(e) => {
+            if let Some((path, config)) = repro {
+                match write_bundle(path, target, &config, &*e) {
+                    Ok(()) => info!("Reproducer bundle written to {}", path.display()),
+                    Err(e) => error!("Failed to write reproducer bundle: {}", e)
+                }
+            }
+            Err(Error::Build(e))
+        }
+    }
+}
+
+/// Returns all files a build depends on: its units, libs and the files its cached units were
+/// loaded from.
+fn watched_files(config: &Config, cache: &UnitCache) -> Vec<PathBuf>
+{
+    let mut files: Vec<PathBuf> = config.units.iter()
+        .filter_map(|v| match v {
+            Unit::Path(path) | Unit::PathWithStage(_, path) => Some(path.to_path_buf()),
+            Unit::Injected(_) => None
+        })
+        .chain(config.libs.iter().map(|v| v.to_path_buf()))
+        .chain(config.require_symbols.map(Path::to_path_buf))
+        .chain(cache.dependencies())
+        .collect();
+    files.sort();
+    files.dedup();
+    files
+}
+
+/// Rebuilds whenever an input of the build changes, reusing the units which did not change, until
+/// interrupted by Ctrl-C.
+fn watch(matches: &ArgMatches, compiler: &Compiler, target: &str, config: Config, output: &Path) -> Result<(), Error>
+{
+    let interrupted = interrupt_flag().map_err(Error::Watch)?;
+    let cache = UnitCache::new();
+    let config = Config {
+        unit_cache: Some(cache.clone()),
+        ..config
+    };
+    let mut watcher = Watcher::new(interrupted);
+    let mut changed: Option<Vec<PathBuf>> = None;
+    loop {
+        let start = Instant::now();
+        //Each build needs its own warnings and dependencies.
+        let res = build(matches, compiler, target, Config {
+            warnings: config.warnings.fork(),
+            depfile: config.depfile.as_ref().map(|v| Depfile::new(v.path())),
+            ..config.clone()
+        }, output);
+        if let Err(e) = &res {
+            report_error(e);
+        }
+        let status = if res.is_ok() { "succeeded" } else { "failed" };
+        let time = start.elapsed().as_secs_f64();
+        match &changed {
+            None => info!("Build {} in {:.2}s", status, time),
+            Some(files) => info!("Rebuild {} in {:.2}s after {} changed file(s): {}", status, time, files.len(),
+                                 files.iter().map(|v| v.display().to_string()).collect::<Vec<_>>().join(", "))
+        }
+        watcher.watch(watched_files(&config, &cache));
+        info!("Watching {} file(s) for changes, press Ctrl-C to stop...", watcher.paths().count());
+        match watcher.wait() {
+            Some(files) => changed = Some(files),
+            None => {
+                info!("Stopped watching");
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Parses a shader argument, either `path` or `stage:path`.
+fn parse_unit(arg: &OsStr) -> Unit<'_>
+{
+    let stage = arg.to_str()
+        .and_then(|v| v.split_once(':'))
+        .and_then(|(stage, path)| Stage::from_name(stage).map(|stage| (stage, path)));
+    match stage {
+        Some((stage, path)) => Unit::PathWithStage(stage, Path::new(path)),
+        None => Unit::Path(Path::new(arg))
+    }
+}
+
+fn is_identifier(s: &str) -> bool
+{
+    let mut chars = s.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn cli() -> Command<'static>
+{
+    Command::new(PROG_NAME)
         .author("BlockProject 3D")
         .about("BlockProject 3D SDK - Shader Compiler")
         .version(PROG_VERSION)
+        .after_help("Arguments of the form @<file> are replaced by the arguments listed in <file>, one per line.")
         .args([
             Arg::new("verbose").short('v').long("verbose").multiple_occurrences(true)
-                .help("Enable verbose output"),
-            Arg::new("target").short('t').long("--target").takes_value(true).required_unless_present("print_targets")
+                .help("Enable verbose output, or set log levels per module with -v [<module>=]<level>,... (modules: relocation, translation, compile, link, write)"),
+            Arg::new("target").short('t').long("--target").takes_value(true).required_unless_present_any(["print_targets", "print_targets_json", "replay"])
                 .help("Specify the shader package target"),
             Arg::new("print_targets").long("--targets")
-                .help("Print all available shader package targets"),
+                .help("Print all available shader package targets and their capabilities"),
+            Arg::new("print_targets_json").long("targets-json")
+                .help("Print all available shader package targets and their capabilities as JSON"),
             Arg::new("output").short('o').long("output").takes_value(true)
                 .allow_invalid_utf8(true).help("Output shader package file name"),
             Arg::new("lib").short('l').long("lib").takes_value(true).multiple_occurrences(true)
@@ -60,32 +357,95 @@ fn run() -> i32
                 .help("Specify the maximum number of threads to use when processing shaders"),
             Arg::new("minify").short('m').long("minify")
                 .help("Allows minification of source code in targets that do not support actual compilation (ex: GL targets)"),
+            Arg::new("strip_comments").long("strip-comments")
+                .help("Remove comments and dead preprocessor branches from source code in targets that do not support actual compilation (ex: GL targets)"),
             Arg::new("debug").short('d').long("debug")
                 .help("For supported targets, builds shaders with debug info"),
             Arg::new("optimize").short('O').long("optimize")
                 .help("For supported targets, builds shaders with optimizations"),
+            Arg::new("deny_deprecated").long("deny-deprecated")
+                .help("Treat uses of deprecated SAL symbols as errors instead of warnings"),
+            Arg::new("deterministic").long("deterministic")
+                .help("Produce byte-identical outputs regardless of thread count and build time (honors SOURCE_DATE_EPOCH)"),
+            Arg::new("path_prefix_map").long("path-prefix-map").takes_value(true).multiple_occurrences(true)
+                .help("Rewrite paths starting with FROM as starting with TO in outputs (FROM=TO); the last matching mapping wins"),
+            Arg::new("no_vertex_input").long("no-vertex-input")
+                .help("Allow the vertex stage to omit its vertex format (vertex pulling)"),
+            Arg::new("no_interface_check").long("no-interface-check")
+                .help("Allow a stage to declare varyings which the previous stage does not declare with the same type"),
+            Arg::new("strict_sal").long("strict-sal")
+                .help("Reject legacy or ambiguous SAL syntax which is accepted by default"),
+            Arg::new("allow_duplicate_symbols").long("allow-duplicate-symbols")
+                .help("Warn instead of failing when stages declare the same symbol differently, keeping the first declaration"),
+            Arg::new("require_symbols").long("require-symbols").takes_value(true).allow_invalid_utf8(true)
+                .help("Check the built symbols against a symbol contract (TOML or JSON) provided by the engine"),
+            Arg::new("max_ext_data_size").long("max-ext-data-size").takes_value(true)
+                .help("Maximum size in bytes of the extended data of a single symbol (default: 65536)"),
+            Arg::new("max_resource_size").long("max-resource-size").takes_value(true)
+                .help("Maximum size in bytes of a single embedded resource (default: 1048576)"),
+            Arg::new("compression").long("compression").takes_value(true)
+                .possible_values(CompressionPreference::NAMES)
+                .help("Compression of the resource and varying sections of the output (default: none)"),
+            Arg::new("force_write").long("force-write")
+                .help("Rewrite the output even if its content did not change"),
+            Arg::new("warn").short('W').long("warn").takes_value(true).multiple_occurrences(true)
+                .help("Set the level of a warning kind: <kind>=error|warn|allow (kinds: all, binding-overlap, binding-limit, binding-mismatch, layout-size, duplicate, deprecated, missing, default-stage, skipped-unit, varying, implicit-location, ignored-option)"),
+            Arg::new("warnings_as_errors").long("warnings-as-errors")
+                .help("Treat all warnings as errors, same as --warn all=error"),
+            Arg::new("no_coalesce_warnings").long("no-coalesce-warnings")
+                .help("Print every warning as soon as it is reported instead of coalescing identical warnings"),
+            Arg::new("sign_key").long("sign-key").takes_value(true).allow_invalid_utf8(true)
+                .help("Sign the output with an Ed25519 private key (raw 32 bytes or PEM)"),
+            Arg::new("repro_on_error").long("repro-on-error").takes_value(true).allow_invalid_utf8(true)
+                .help("On build failure, write a self-contained reproducer bundle (tar) to the given path"),
+            Arg::new("define").short('D').long("define").takes_value(true).multiple_occurrences(true)
+                .help("Define an identifier for #if blocks of SAL code, also defined to 1 in GLSL code"),
+            Arg::new("glsl_include").long("glsl-include").takes_value(true).multiple_occurrences(true).allow_invalid_utf8(true)
+                .help("Add a directory searched by #include directives in GLSL code"),
+            Arg::new("depfile").long("depfile").takes_value(true).allow_invalid_utf8(true)
+                .help("Write a Makefile dependency file listing all inputs of the build to the given path"),
+            Arg::new("manifest").long("manifest").takes_value(true).allow_invalid_utf8(true)
+                .help("Write a JSON manifest of the bindings of the output (constant buffers, textures, vertex format, outputs and pipeline) to the given path"),
+            Arg::new("watch").long("watch").conflicts_with("replay")
+                .help("After building, watch all inputs and rebuild when they change until interrupted with Ctrl-C"),
+            Arg::new("replay").long("replay").takes_value(true).allow_invalid_utf8(true).conflicts_with("shader")
+                .help("Re-run a build from a reproducer bundle, ignoring the local file system"),
             Arg::new("shader").multiple_values(true).allow_invalid_utf8(true)
-                .help("List of shader files to process")
-        ]).get_matches();
-    alloc_verbosity_level(matches.occurrences_of("verbose"));
+                .help("List of shader files to process, optionally prefixed by their stage (e.g. vertex:deferred.vs.glsl)")
+        ])
+        .subcommand_negates_reqs(true)
+        .subcommand(Command::new("schema").about("JSON schemas of machine-readable outputs")
+            .subcommand_required(true)
+            .subcommand(Command::new("dump").about("Print the JSON Schema of a document")
+                .arg(Arg::new("name").required(true).possible_values(bp3d_symbols::SCHEMAS))))
+        .subcommand(Command::new("check-module").about("Validate SAL library modules without compiling a shader")
+            .arg(Arg::new("module").required(true).multiple_values(true).allow_invalid_utf8(true)
+                .help("List of SAL module files to check")))
+}
+
+fn run(matches: ArgMatches) -> Result<(), Error>
+{
     info!("Initializing BlockProject 3D Shader Compiler...");
-    if matches.is_present("print_targets") {
-        print!("Available targets: ");
-        let count = Compiler::list_targets().count();
-        for (i, name) in Compiler::list_targets().enumerate() {
-            if i == count - 1 {
-                print!("{}", name)
-            } else {
-                print!("{}, ", name)
-            }
-        }
-        println!();
-        0
+    if let Some(("schema", args)) = matches.subcommand() {
+        let (_, args) = args.subcommand().unwrap();
+        schema(args)
+    } else if let Some(("check-module", args)) = matches.subcommand() {
+        check_modules(args)
+    } else if matches.is_present("print_targets") {
+        print_targets();
+        Ok(())
+    } else if matches.is_present("print_targets_json") {
+        let list = TargetList::new(Compiler::list().collect());
+        //The document is plain data, serializing it cannot fail.
+        println!("{}", serde_json::to_string_pretty(&list).unwrap());
+        Ok(())
+    } else if let Some(bundle) = matches.value_of_os("replay") {
+        replay(Path::new(bundle))
     } else {
         let mut units: Vec<Unit> = matches
             .values_of_os("shader")
             .unwrap_or_default()
-            .map(|v| Unit::Path(Path::new(v)))
+            .map(parse_unit)
             .collect();
         let libs: Vec<&Path> = matches
             .values_of_os("lib")
@@ -94,9 +454,44 @@ fn run() -> i32
             .collect();
         let n_threads: usize = matches.value_of_t("threads").unwrap_or(1);
         let minify = matches.is_present("minify");
+        let strip_comments = matches.is_present("strip_comments");
         let optimize = matches.is_present("optimize");
         let debug = matches.is_present("debug");
+        let deny_deprecated = matches.is_present("deny_deprecated");
+        let deterministic = matches.is_present("deterministic");
+        let no_vertex_input = matches.is_present("no_vertex_input");
+        let no_interface_check = matches.is_present("no_interface_check");
+        let strict_sal = matches.is_present("strict_sal");
+        let allow_duplicate_symbols = matches.is_present("allow_duplicate_symbols");
+        let require_symbols = matches.value_of_os("require_symbols").map(Path::new);
+        let max_ext_data_size: usize = matches.value_of_t("max_ext_data_size").unwrap_or(DEFAULT_MAX_EXT_DATA_SIZE);
+        let max_resource_size: usize = matches.value_of_t("max_resource_size").unwrap_or(DEFAULT_MAX_RESOURCE_SIZE);
+        //Values are restricted by clap.
+        let compression = matches.value_of("compression").and_then(CompressionPreference::from_name).unwrap_or_default();
+        let force_write = matches.is_present("force_write");
+        let depfile = matches.value_of_os("depfile").map(|v| Depfile::new(Path::new(v)));
+        let manifest = matches.value_of_os("manifest").map(Path::new);
+        let glsl_includes = matches.values_of_os("glsl_include").unwrap_or_default().map(Path::new).collect();
+        let defines: Vec<String> = matches.values_of("define").unwrap_or_default().map(String::from).collect();
+        if let Some(v) = defines.iter().find(|v| !is_identifier(v)) {
+            return Err(Error::Usage(format!("invalid define '{}': expected an identifier", v)));
+        }
+        let path_prefix_map = matches.values_of("path_prefix_map").unwrap_or_default()
+            .map(|v| v.split_once('=').map(|(from, to)| (Path::new(from), Path::new(to)))
+                .ok_or_else(|| Error::Usage(format!("invalid path prefix mapping '{}': expected FROM=TO", v))))
+            .collect::<Result<Vec<_>, Error>>()?;
+        let mut warnings = Reporter::new();
+        if matches.is_present("warnings_as_errors") {
+            //Set first so that --warn can still relax single kinds.
+            warnings.configure("all=error").unwrap();
+        }
+        for v in matches.values_of("warn").unwrap_or_default() {
+            warnings.configure(v).map_err(|e| Error::Usage(e.to_string()))?;
+        }
+        warnings.set_coalesce(!matches.is_present("no_coalesce_warnings"));
+        debug!("Warning levels: {}", warnings);
         let output = get_out_path(matches.value_of_os("output"));
+        let libs = dedup_libs(&libs, &output).map_err(|e| Error::Usage(e.to_string()))?;
         for v in matches.values_of("injection").unwrap_or_default() {
             units.push(Unit::Injected(v));
         }
@@ -105,27 +500,69 @@ fn run() -> i32
             libs,
             n_threads,
             minify,
+            strip_comments,
             optimize,
             debug,
+            deny_deprecated,
+            deterministic,
+            no_vertex_input,
+            no_interface_check,
+            strict_sal,
+            require_symbols,
+            max_ext_data_size,
+            max_resource_size,
+            compression,
+            force_write,
+            warnings,
+            depfile,
+            manifest,
+            glsl_includes,
+            allow_duplicate_symbols,
+            defines,
+            unit_cache: None,
+            path_prefix_map,
+            source_date_epoch: None,
+            sources: None,
             output: output.as_ref()
         };
         let target = matches.value_of("target").unwrap();
         debug!("Target chosen: {}", target);
+        if let Some(info) = Compiler::info(target) {
+            check_target_flags(&info, debug, optimize, minify, manifest.is_some())?;
+        }
         if let Some(compiler) = Compiler::get(target) {
             info!("Building for target: {}...", target);
-            if let Err(e) = compiler.run(config) {
-                error!("{}", e);
-                1
+            if matches.is_present("watch") {
+                watch(&matches, &compiler, target, config, &output)
             } else {
-                0
+                build(&matches, &compiler, target, config, &output)
             }
         } else {
-            error!("Target not found: {}", target);
-            3
+            Err(Error::TargetNotFound(target.into()))
         }
     }
 }
 
 fn main() {
-    init_bp3d_logger(run);
+    let args = match expand_response_files(std::env::args_os()) {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(e.category().exit_code());
+        }
+    };
+    let (args, scoped) = match split_verbose_directives(args) {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(Category::Usage.exit_code());
+        }
+    };
+    let matches = cli().get_matches_from(args);
+    let mut directives = Directives::new(matches.occurrences_of("verbose"));
+    for v in &scoped {
+        //Already checked when splitting the arguments.
+        directives.add(v).unwrap();
+    }
+    init_logger(directives, || run_cli(|| run(matches)));
 }
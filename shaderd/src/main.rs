@@ -1,80 +1,599 @@
-use std::fs::File;
-use std::io::BufReader;
-use std::path::Path;
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::fs::{File, OpenOptions};
+use std::io::{Cursor, ErrorKind, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use bp3d_symbols::{content_hash, DEBUG_INFO_SECTION_TYPE, DebugInfo, decode_resources, decode_varyings, FromBpx, Origin, Origins, ORIGIN_SECTION_TYPE, PAYLOAD_SECTION_TYPE, PayloadKind, PipelineObject, Resource, RESOURCE_SECTION_TYPE, VARYING_SECTION_TYPE, VaryingObject};
+use bpx::core::Container;
 use bpx::sd::formatting::{Format, IndentType};
 use bpx::shader::ShaderPack;
 use bpx::shader::symbol::{FLAG_ASSEMBLY, FLAG_DOMAIN_STAGE, FLAG_EXTENDED_DATA, FLAG_EXTERNAL, FLAG_GEOMETRY_STAGE, FLAG_HULL_STAGE, FLAG_INTERNAL, FLAG_PIXEL_STAGE, FLAG_REGISTER, FLAG_VERTEX_STAGE};
+use bpx::shader::symbol::Symbol;
 use clap::{Arg, Command};
+use cli_common::{alloc_verbosity_level, init_bp3d_logger, report_error, run_cli, Category, Report};
+use bp3d_shaderl::failure::FailureKind;
+use serde_json::json;
 
+#[derive(Debug)]
 enum Error {
     Io(std::io::Error),
-    Bpx(bpx::shader::error::Error)
+    Bpx(bpx::shader::error::Error),
+    Core(bpx::core::error::Error),
+    Find(bp3d_shaderl::find::Error),
+    InputTooLarge(u64),
+    ResourceNotFound(String),
+    FileExists(PathBuf),
+    DumpFailed(usize),
+    NoDebugInfo,
+    #[cfg(feature = "signing")]
+    Key(bp3d_symbols::KeyError),
+    #[cfg(feature = "signing")]
+    Signature(bp3d_symbols::SignatureError),
+    #[cfg(not(feature = "signing"))]
+    SigningUnsupported
 }
 
-fn disassemble(path: &Path, table: bool) -> Result<(), Error>
+impl Display for Error
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+    {
+        match self {
+            Error::Io(e) => write!(f, "An io error has occured: {}", e),
+            Error::Bpx(e) => write!(f, "A BPX error has occured: {}", e),
+            Error::Core(e) => write!(f, "A BPX error has occured: {}", e),
+            Error::Find(e) => write!(f, "Unable to locate shader pack in assembly: {}", e),
+            Error::InputTooLarge(max) => write!(f, "Standard input exceeds the maximum shader pack size of {} bytes", max),
+            Error::ResourceNotFound(name) => write!(f, "No resource named '{}' in shader pack", name),
+            Error::FileExists(path) => write!(f, "Refusing to overwrite {:?} (use --force)", path),
+            Error::DumpFailed(count) => write!(f, "Failed to dump {} shader stage(s)", count),
+            Error::NoDebugInfo => f.write_str("Shader pack was built without debug information, rebuild it with shaderc --debug to embed its sources"),
+            #[cfg(feature = "signing")]
+            Error::Key(e) => write!(f, "Unable to load public key: {}", e),
+            #[cfg(feature = "signing")]
+            Error::Signature(e) => write!(f, "Signature verification failed: {}", e),
+            #[cfg(not(feature = "signing"))]
+            Error::SigningUnsupported => f.write_str("This build of shaderd does not support signature verification (signing feature)")
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl Report for Error
+{
+    fn category(&self) -> Category
+    {
+        match self {
+            Error::Io(_) => Category::Io,
+            Error::Bpx(_) | Error::Core(_) => Category::Format,
+            Error::Find(e) => match e.kind() {
+                FailureKind::Io => Category::Io,
+                FailureKind::Input => Category::Input,
+                FailureKind::Format => Category::Format
+            },
+            Error::InputTooLarge(_) | Error::NoDebugInfo => Category::Input,
+            Error::ResourceNotFound(_) | Error::FileExists(_) => Category::Usage,
+            #[cfg(not(feature = "signing"))]
+            Error::SigningUnsupported => Category::Usage,
+            _ => Category::Other
+        }
+    }
+}
+
+/// Exit code of `--diff` when the shader packs differ, errors still use their category code.
+const EXIT_DIFFERENT: i32 = 2;
+
+/// Maximum number of bytes accepted from the standard input.
+const MAX_STDIN_SIZE: u64 = 64 * 1024 * 1024;
+
+//Printed in place of missing origins so that scripts always find the field.
+const UNKNOWN_ORIGIN: &str = "unknown (not recorded)";
+
+type Pack = ShaderPack<Cursor<Vec<u8>>>;
+
+enum Input<'a> {
+    Path(&'a Path),
+    Stdin,
+    Assembly(&'a Path, &'a str)
+}
+
+fn read_limited<R: Read>(reader: R, max: u64) -> Result<Vec<u8>, Error>
+{
+    let mut data = Vec::new();
+    reader.take(max + 1).read_to_end(&mut data).map_err(Error::Io)?;
+    if data.len() as u64 > max {
+        return Err(Error::InputTooLarge(max));
+    }
+    Ok(data)
+}
+
+//Packs are loaded in memory so that non seekable inputs such as pipes can be inspected.
+fn load_input(input: &Input) -> Result<Vec<u8>, Error>
+{
+    match input {
+        Input::Path(path) => std::fs::read(path).map_err(Error::Io),
+        Input::Stdin => read_limited(std::io::stdin().lock(), MAX_STDIN_SIZE),
+        Input::Assembly(assembly, name) => bp3d_shaderl::find::locate_pack(assembly, name).map_err(Error::Find)
+    }
+}
+
+#[cfg(feature = "signing")]
+fn verify_signature(data: &[u8], pubkey: &Path) -> Result<(), Error>
+{
+    let key = bp3d_symbols::load_verifying_key(pubkey).map_err(Error::Key)?;
+    let container = Container::open(Cursor::new(data)).map_err(Error::Core)?;
+    let fingerprint = bp3d_symbols::verify(&container, &key).map_err(Error::Signature)?;
+    println!("Valid signature from key {}", fingerprint);
+    Ok(())
+}
+
+#[cfg(not(feature = "signing"))]
+fn verify_signature(_: &[u8], _: &Path) -> Result<(), Error>
+{
+    Err(Error::SigningUnsupported)
+}
+
+fn open(input: &Input, pubkey: Option<&Path>) -> Result<Pack, Error>
+{
+    let data = load_input(input)?;
+    if let Some(pubkey) = pubkey {
+        verify_signature(&data, pubkey)?;
+    }
+    ShaderPack::open(Cursor::new(data)).map_err(Error::Bpx)
+}
+
+fn load_resources<T: Read + Seek>(container: &Container<T>) -> Result<Vec<Resource>, Error>
+{
+    let handle = match container.sections().find_by_type(RESOURCE_SECTION_TYPE) {
+        Some(v) => v,
+        None => return Ok(Vec::new())
+    };
+    let mut data = Vec::new();
+    container.sections().load(handle).map_err(Error::Core)?
+        .read_to_end(&mut data).map_err(Error::Io)?;
+    decode_resources(&data).map_err(Error::Io)
+}
+
+fn load_varyings<T: Read + Seek>(container: &Container<T>) -> Result<Vec<VaryingObject>, Error>
+{
+    let handle = match container.sections().find_by_type(VARYING_SECTION_TYPE) {
+        Some(v) => v,
+        None => return Ok(Vec::new())
+    };
+    let mut data = Vec::new();
+    container.sections().load(handle).map_err(Error::Core)?
+        .read_to_end(&mut data).map_err(Error::Io)?;
+    decode_varyings(&data).map_err(Error::Io)
+}
+
+//Packs without a payload section hold the default payload of their target.
+fn load_payload<T: Read + Seek>(container: &Container<T>, target: bpx::shader::Target) -> Result<PayloadKind, Error>
+{
+    let handle = match container.sections().find_by_type(PAYLOAD_SECTION_TYPE) {
+        Some(v) => v,
+        None => return Ok(PayloadKind::from_target(target))
+    };
+    let mut data = Vec::new();
+    container.sections().load(handle).map_err(Error::Core)?
+        .read_to_end(&mut data).map_err(Error::Io)?;
+    PayloadKind::decode(&data).map_err(Error::Io)
+}
+
+//Only debug builds embed their sources.
+fn load_debug_info<T: Read + Seek>(container: &Container<T>) -> Result<DebugInfo, Error>
+{
+    let handle = container.sections().find_by_type(DEBUG_INFO_SECTION_TYPE).ok_or(Error::NoDebugInfo)?;
+    let mut data = Vec::new();
+    container.sections().load(handle).map_err(Error::Core)?
+        .read_to_end(&mut data).map_err(Error::Io)?;
+    DebugInfo::decode(&data).map_err(Error::Io)
+}
+
+//Packs built without origins (older or stripped packs) are not an error.
+fn load_origins<T: Read + Seek>(container: &Container<T>) -> Result<Option<Origins>, Error>
+{
+    let handle = match container.sections().find_by_type(ORIGIN_SECTION_TYPE) {
+        Some(v) => v,
+        None => return Ok(None)
+    };
+    let mut data = Vec::new();
+    container.sections().load(handle).map_err(Error::Core)?
+        .read_to_end(&mut data).map_err(Error::Io)?;
+    match Origins::decode(&data) {
+        Ok(v) => Ok(Some(v)),
+        Err(e) => {
+            eprintln!("Ignoring unreadable symbol origins: {}", e);
+            Ok(None)
+        }
+    }
+}
+
+fn format_origin(origin: Option<&Origin>) -> String
+{
+    match origin {
+        Some(Origin { file, line: Some(line), .. }) => format!("{}:{}", file, line),
+        Some(Origin { file, line: None, .. }) => file.clone(),
+        None => UNKNOWN_ORIGIN.into()
+    }
+}
+
+fn extract_resource(shader: Pack, name: &str, output: &Path) -> Result<(), Error>
+{
+    let resources = load_resources(&shader.into_inner())?;
+    let res = resources.into_iter().find(|v| v.name == name)
+        .ok_or_else(|| Error::ResourceNotFound(name.into()))?;
+    std::fs::write(output, &res.data).map_err(Error::Io)?;
+    println!("Extracted resource '{}' ({} bytes) to {:?}", name, res.data.len(), output);
+    Ok(())
+}
+
+fn stage_name(stage: bpx::shader::Stage) -> &'static str
+{
+    match stage {
+        bpx::shader::Stage::Vertex => "vertex",
+        bpx::shader::Stage::Hull => "hull",
+        bpx::shader::Stage::Domain => "domain",
+        bpx::shader::Stage::Geometry => "geometry",
+        bpx::shader::Stage::Pixel => "pixel"
+    }
+}
+
+fn write_file(path: &Path, data: &[u8], force: bool) -> Result<(), Error>
+{
+    let file = match force {
+        true => File::create(path),
+        false => OpenOptions::new().write(true).create_new(true).open(path)
+    };
+    let mut file = file.map_err(|e| match e.kind() {
+        ErrorKind::AlreadyExists => Error::FileExists(path.into()),
+        _ => Error::Io(e)
+    })?;
+    file.write_all(data).map_err(Error::Io)
+}
+
+/// Writes the code of each stage to `<stem>.<stage>.glsl` for GLSL payloads, to
+/// `<stem>.<stage>.spv` for SPIR-V payloads and to `<stem>.<stage>.bin` otherwise. A stage which
+/// fails does not prevent the others from being written.
+fn dump_stages(shader: Pack, stem: &str, out_dir: &Path, force: bool) -> Result<(), Error>
+{
+    let target = shader.get_settings().target;
+    let mut stages = Vec::new();
+    {
+        let shaders = shader.shaders();
+        for handle in &shaders {
+            stages.push(shaders.load(handle).map_err(Error::Bpx).map(|v| (v.stage, v.data.clone())));
+        }
+    }
+    let ext = match load_payload(&shader.into_inner(), target)? {
+        PayloadKind::Glsl => "glsl",
+        PayloadKind::Spirv => "spv",
+        PayloadKind::Binary => "bin"
+    };
+    let mut failed = 0;
+    for stage in stages {
+        let res = stage.and_then(|(stage, data)| {
+            let path = out_dir.join(format!("{}.{}.{}", stem, stage_name(stage), ext));
+            write_file(&path, &data, force)?;
+            Ok((path, data.len()))
+        });
+        match res {
+            Ok((path, size)) => println!("Wrote {:?} ({} bytes)", path, size),
+            Err(e) => {
+                report_error(&e);
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        return Err(Error::DumpFailed(failed));
+    }
+    Ok(())
+}
+
+/// Prints the sources embedded by a debug build or, given an output directory, writes the code of
+/// each stage after SAL translation to `<stem>.<stage>.translated.glsl` and its units to
+/// `<stem>.<stage>.source<n>.glsl`.
+fn show_sources(shader: Pack, stem: &str, out_dir: Option<&Path>, force: bool) -> Result<(), Error>
+{
+    let info = load_debug_info(&shader.into_inner())?;
+    let out_dir = match out_dir {
+        Some(v) => v,
+        None => {
+            println!("Compiler version: {}", info.compiler_version);
+            println!("Target: {}", info.target);
+            println!("Options:");
+            for (name, value) in &info.options {
+                println!("    * {}: {}", name, value);
+            }
+            for stage in &info.stages {
+                for source in &stage.sources {
+                    println!();
+                    println!("==> {} stage source: {} <==", stage.stage, source.name);
+                    print!("{}", source.text);
+                }
+                println!();
+                println!("==> {} stage translated code <==", stage.stage);
+                print!("{}", stage.code);
+            }
+            return Ok(());
+        }
+    };
+    for stage in &info.stages {
+        let files = stage.sources.iter().enumerate()
+            .map(|(i, v)| (format!("source{}", i), &v.text))
+            .chain(std::iter::once((String::from("translated"), &stage.code)));
+        for (kind, text) in files {
+            let path = out_dir.join(format!("{}.{}.{}.glsl", stem, stage.stage, kind));
+            write_file(&path, text.as_bytes(), force)?;
+            println!("Wrote {:?} ({} bytes)", path, text.len());
+        }
+    }
+    Ok(())
+}
+
+fn disassemble(shader: Pack, table: bool, origins: bool) -> Result<(), Error>
 {
-    let file = File::open(path).map_err(Error::Io)?;
-    let shader = ShaderPack::open(BufReader::new(file)).map_err(Error::Bpx)?;
     println!("Linked assembly: {:#X}", shader.get_settings().assembly_hash);
-    let symbols = shader.symbols().map_err(Error::Bpx)?;
-    let shaders = shader.shaders();
-    println!("Number of symbols: {}", symbols.len());
-    println!("Target API: {:?}", shader.get_settings().target);
-    println!("Shader type: {:?}", shader.get_settings().ty);
-    println!("Number of shader stages: {}", shaders.len());
+    let target = shader.get_settings().target;
+    let mut rows = Vec::new();
+    {
+        let symbols = shader.symbols().map_err(Error::Bpx)?;
+        let shaders = shader.shaders();
+        println!("Number of symbols: {}", symbols.len());
+        println!("Target API: {:?}", shader.get_settings().target);
+        println!("Shader type: {:?}", shader.get_settings().ty);
+        println!("Number of shader stages: {}", shaders.len());
+        if table {
+            for sym in &symbols {
+                let name: String = symbols.load_name(sym).map_err(Error::Bpx)?.into();
+                let id = match sym.flags & FLAG_EXTENDED_DATA != 0 {
+                    true => bp3d_symbols::symbol_id(symbols.load_extended_data(sym).map_err(Error::Bpx)?),
+                    false => None
+                };
+                //Packs written before ids were stored still get the id a runtime would compute.
+                let id = id.unwrap_or_else(|| bp3d_symbols::SymbolId::from_name(&name));
+                rows.push((name, sym.ty, id));
+            }
+        }
+    }
+    let container = shader.into_inner();
+    println!("Stage payload: {}", load_payload(&container, target)?);
     println!();
     if table {
+        let recorded = match origins {
+            true => load_origins(&container)?,
+            false => None
+        };
         println!("Symbol table:");
-        for sym in &symbols {
-            let name = symbols.load_name(sym).map_err(Error::Bpx)?;
-            println!("    * {}: {:?}", name, sym.ty);
+        for (name, ty, id) in &rows {
+            match origins {
+                true => println!("    * {} [{}]: {:?} ({})", name, id, ty, format_origin(recorded.as_ref().and_then(|v| v.get_symbol(name)))),
+                false => println!("    * {} [{}]: {:?}", name, id, ty)
+            }
+        }
+        if origins {
+            println!();
+            match recorded.as_ref().filter(|v| !v.stages.is_empty()) {
+                Some(recorded) => {
+                    println!("Stage origins:");
+                    for stage in &recorded.stages {
+                        println!("    * {}: {}", stage.stage, stage.file);
+                    }
+                },
+                None => println!("Stage origins: {}", UNKNOWN_ORIGIN)
+            }
+        }
+    }
+    let resources = load_resources(&container)?;
+    if !resources.is_empty() {
+        println!();
+        println!("Resources:");
+        for res in &resources {
+            let obj = res.object();
+            println!("    * {}: {} bytes (hash {:#X})", obj.name, obj.size, obj.hash);
+        }
+    }
+    let varyings = load_varyings(&container)?;
+    if !varyings.is_empty() {
+        println!();
+        println!("Varyings:");
+        for v in &varyings {
+            println!("    * {}: {:?} ({:?})", v.name, v.ty, v.interpolation);
         }
     }
     Ok(())
 }
 
+const FLAG_NAMES: [(u16, &str); 10] = [
+    (FLAG_REGISTER, "Register"),
+    (FLAG_EXTENDED_DATA, "ExtendedData"),
+    (FLAG_ASSEMBLY, "Assembly"),
+    (FLAG_INTERNAL, "Internal"),
+    (FLAG_EXTERNAL, "External"),
+    (FLAG_DOMAIN_STAGE, "DomainStage"),
+    (FLAG_VERTEX_STAGE, "VertexStage"),
+    (FLAG_HULL_STAGE, "HullStage"),
+    (FLAG_PIXEL_STAGE, "PixelStage"),
+    (FLAG_GEOMETRY_STAGE, "GeometryStage")
+];
+
+fn flag_names(flags: u16) -> Vec<&'static str> {
+    FLAG_NAMES.iter().filter(|(flag, _)| flags & flag != 0).map(|(_, name)| *name).collect()
+}
+
 fn flags_to_string(flags: u16) -> String {
-    let mut str = String::new();
-    if flags & FLAG_REGISTER != 0 {
-        str += "Register | "
-    }
-    if flags & FLAG_EXTENDED_DATA != 0 {
-        str += "ExtendedData | "
+    flag_names(flags).join(" | ")
+}
+
+//Values which JSON cannot represent are written as "!<type>:<value>" strings.
+fn tagged(tag: &str, value: impl std::fmt::Display) -> serde_json::Value
+{
+    serde_json::Value::String(format!("!{}:{}", tag, value))
+}
+
+fn float_to_json(tag: &str, value: f64) -> serde_json::Value
+{
+    match serde_json::Number::from_f64(value) {
+        Some(v) => serde_json::Value::Number(v),
+        None => tagged(tag, value)
     }
-    if flags & FLAG_ASSEMBLY != 0 {
-        str += "Assembly | "
+}
+
+//Property names are only known when the object was written with debug symbols, other properties
+//are keyed by the hexadecimal hash of their name. The format version and symbol id are always named.
+fn object_to_json(obj: &bpx::sd::Object) -> serde_json::Value
+{
+    let debug_hash = bpx::utils::hash("__debug__");
+    let mut names = std::collections::HashMap::new();
+    if let Some(bpx::sd::Value::Array(debug)) = obj.raw_get(debug_hash) {
+        for name in debug.iter() {
+            if let bpx::sd::Value::String(name) = name {
+                names.insert(bpx::utils::hash(name), name.clone());
+            }
+        }
     }
-    if flags & FLAG_INTERNAL != 0 {
-        str += "Internal | "
+    names.insert(bpx::utils::hash(bp3d_symbols::VERSION_KEY), bp3d_symbols::VERSION_KEY.into());
+    names.insert(bpx::utils::hash(bp3d_symbols::ID_KEY), bp3d_symbols::ID_KEY.into());
+    let mut map = serde_json::Map::new();
+    for (hash, value) in obj.iter() {
+        if hash == debug_hash {
+            continue;
+        }
+        let name = names.get(&hash).cloned().unwrap_or_else(|| format!("{:#X}", hash));
+        map.insert(name, sd_to_json(value));
     }
-    if flags & FLAG_EXTERNAL != 0 {
-        str += "External | "
+    serde_json::Value::Object(map)
+}
+
+fn sd_to_json(value: &bpx::sd::Value) -> serde_json::Value
+{
+    use bpx::sd::Value;
+    match value {
+        Value::Null => serde_json::Value::Null,
+        Value::Bool(v) => json!(v),
+        Value::Uint8(v) => json!(v),
+        Value::Uint16(v) => json!(v),
+        Value::Uint32(v) => json!(v),
+        Value::Uint64(v) => json!(v),
+        Value::Int8(v) => json!(v),
+        Value::Int16(v) => json!(v),
+        Value::Int32(v) => json!(v),
+        Value::Int64(v) => json!(v),
+        Value::Float(v) => float_to_json("float", *v as f64),
+        Value::Double(v) => float_to_json("double", *v),
+        Value::String(v) => json!(v),
+        Value::Array(v) => serde_json::Value::Array(v.iter().map(sd_to_json).collect()),
+        Value::Object(v) => object_to_json(v),
+        #[allow(unreachable_patterns)]
+        v => tagged("unsupported", format!("{:?}", v))
     }
-    if flags & FLAG_DOMAIN_STAGE != 0 {
-        str += "DomainStage | "
+}
+
+fn symbol_json(name: &str, sym: &Symbol, extended_data: Option<&bpx::sd::Value>) -> serde_json::Value
+{
+    json!({
+        "name": name,
+        "type": format!("{:?}", sym.ty),
+        "register": (sym.flags & FLAG_REGISTER != 0).then(|| sym.register),
+        "flags": flag_names(sym.flags),
+        "extended_data": extended_data.map(sd_to_json)
+    })
+}
+
+/// Builds the JSON document printed by `--json`; keys and array orders are stable so that the
+/// output of two packs can be diffed.
+fn pack_json(shader: &Pack) -> Result<serde_json::Value, Error>
+{
+    let settings = shader.get_settings();
+    let shaders = shader.shaders();
+    let mut stages = Vec::new();
+    for handle in &shaders {
+        let stage = shaders.load(handle).map_err(Error::Bpx)?;
+        stages.push(json!({
+            "stage": stage_name(stage.stage),
+            "size": stage.data.len()
+        }));
     }
-    if flags & FLAG_VERTEX_STAGE != 0 {
-        str += "VertexStage | "
+    let symbols = shader.symbols().map_err(Error::Bpx)?;
+    let mut table = Vec::new();
+    for sym in &symbols {
+        let name = symbols.load_name(sym).map_err(Error::Bpx)?;
+        let extended_data = match sym.flags & FLAG_EXTENDED_DATA != 0 {
+            true => Some(symbols.load_extended_data(sym).map_err(Error::Bpx)?),
+            false => None
+        };
+        table.push(symbol_json(name, sym, extended_data));
     }
-    if flags & FLAG_HULL_STAGE != 0 {
-        str += "HullStage | "
+    Ok(json!({
+        "assembly_hash": settings.assembly_hash,
+        "target": format!("{:?}", settings.target),
+        "type": format!("{:?}", settings.ty),
+        "stages": stages,
+        "symbols": table
+    }))
+}
+
+fn print_json(value: &serde_json::Value)
+{
+    //SAFETY: serializing a serde_json::Value cannot fail.
+    println!("{}", serde_json::to_string_pretty(value).unwrap());
+}
+
+fn show_symbol_json(shader: &Pack, name: &str) -> Result<(), Error>
+{
+    let symbols = shader.symbols().map_err(Error::Bpx)?;
+    for sym in &symbols {
+        if symbols.load_name(sym).map_err(Error::Bpx)? == name {
+            let extended_data = match sym.flags & FLAG_EXTENDED_DATA != 0 {
+                true => Some(symbols.load_extended_data(sym).map_err(Error::Bpx)?),
+                false => None
+            };
+            print_json(&symbol_json(name, sym, extended_data));
+            return Ok(());
+        }
     }
-    if flags & FLAG_PIXEL_STAGE != 0 {
-        str += "PixelStage | "
+    print_json(&serde_json::Value::Null);
+    Ok(())
+}
+
+fn show_symbol(shader: Pack, name: &str) -> Result<(), Error>
+{
+    if !show_symbol_data(&shader, name)? {
+        return Ok(());
     }
-    if flags & FLAG_GEOMETRY_STAGE != 0 {
-        str += "GeometryStage | "
+    let origins = load_origins(&shader.into_inner())?;
+    let origin = origins.as_ref().and_then(|v| v.get_symbol(name));
+    println!();
+    println!("==> Origin <==");
+    println!("File: {}", origin.map(|v| &*v.file).unwrap_or(UNKNOWN_ORIGIN));
+    match origin {
+        Some(Origin { line: Some(line), .. }) => println!("Line: {}", line),
+        _ => println!("Line: {}", UNKNOWN_ORIGIN)
     }
-    if !str.is_empty() {
-        str.truncate(str.len() - 3);
+    match origin {
+        Some(v) if !v.modules.is_empty() => println!("Modules: {}", v.modules.join(" -> ")),
+        Some(_) => println!("Modules: none (local symbol)"),
+        None => println!("Modules: {}", UNKNOWN_ORIGIN)
     }
-    str
+    Ok(())
 }
 
-fn show_symbol(path: &Path, name: &str) -> Result<(), Error>
+fn pipeline_state(obj: &PipelineObject) -> Vec<String>
+{
+    vec![
+        format!("Depth: enable={} write={} func={:?}", obj.depth_enable, obj.depth_write_enable, obj.depth_func),
+        format!("Depth bias: constant={} slope={}", obj.depth_bias, obj.slope_scaled_depth_bias),
+        format!("Stencil: enable={} func={:?} ref={} pass={:?} fail={:?}", obj.stencil_enable, obj.stencil_func, obj.stencil_ref, obj.stencil_pass_op, obj.stencil_fail_op),
+        format!("Scissor: {}", obj.scissor_enable),
+        format!("Render mode: {:?}", obj.render_mode),
+        format!("Culling mode: {:?}", obj.culling_mode),
+        format!("Vertex input: {}", obj.vertex_input),
+        format!("Viewports: {} (layered rendering: {})", obj.viewport_count, obj.layered_rendering)
+    ]
+}
+
+fn show_symbol_data(shader: &Pack, name: &str) -> Result<bool, Error>
 {
-    let file = File::open(path).map_err(Error::Io)?;
-    let shader = ShaderPack::open(BufReader::new(file)).map_err(Error::Bpx)?;
     let symbols = shader.symbols().map_err(Error::Bpx)?;
     for sym in &symbols {
         if symbols.load_name(sym).map_err(Error::Bpx)? == name {
@@ -89,15 +608,207 @@ fn show_symbol(path: &Path, name: &str) -> Result<(), Error>
                 println!();
                 println!("==> Extended data <==");
                 let val = symbols.load_extended_data(sym).map_err(Error::Bpx)?;
+                if let Err(e) = bp3d_symbols::check_version(val) {
+                    eprintln!("Warning: {}", e);
+                }
                 println!("{}", val.as_object().unwrap().format(IndentType::Spaces, 4));
+                //Enums are stored as indices in extended data, decode the pipeline to show their names.
+                if matches!(sym.ty, bpx::shader::symbol::Type::Pipeline) {
+                    if let Ok(obj) = PipelineObject::from_bpx(val) {
+                        println!();
+                        println!("==> Pipeline state <==");
+                        for line in pipeline_state(&obj) {
+                            println!("{}", line);
+                        }
+                    }
+                }
             }
-            return Ok(())
+            return Ok(true)
         }
     }
-    Ok(())
+    Ok(false)
 }
 
-fn main() {
+struct SymbolEntry
+{
+    ty: bpx::shader::symbol::Type,
+    flags: u16,
+    register: Option<u8>,
+    extended_data: Option<serde_json::Value>
+}
+
+fn load_symbol_entries(shader: &Pack) -> Result<BTreeMap<String, SymbolEntry>, Error>
+{
+    let symbols = shader.symbols().map_err(Error::Bpx)?;
+    let mut entries = BTreeMap::new();
+    for sym in &symbols {
+        let name: String = symbols.load_name(sym).map_err(Error::Bpx)?.into();
+        let extended_data = match sym.flags & FLAG_EXTENDED_DATA != 0 {
+            true => Some(sd_to_json(symbols.load_extended_data(sym).map_err(Error::Bpx)?)),
+            false => None
+        };
+        entries.insert(name, SymbolEntry {
+            ty: sym.ty,
+            flags: sym.flags,
+            register: (sym.flags & FLAG_REGISTER != 0).then(|| sym.register),
+            extended_data
+        });
+    }
+    Ok(entries)
+}
+
+fn load_stages(shader: &Pack) -> Result<BTreeMap<&'static str, Vec<u8>>, Error>
+{
+    let shaders = shader.shaders();
+    let mut stages = BTreeMap::new();
+    for handle in &shaders {
+        let stage = shaders.load(handle).map_err(Error::Bpx)?;
+        stages.insert(stage_name(stage.stage), stage.data.clone());
+    }
+    Ok(stages)
+}
+
+fn json_diff(path: &str, a: &serde_json::Value, b: &serde_json::Value, out: &mut Vec<String>)
+{
+    use serde_json::Value;
+    match (a, b) {
+        (Value::Object(a), Value::Object(b)) => {
+            for (key, v) in a {
+                let sub = format!("{}.{}", path, key);
+                match b.get(key) {
+                    Some(w) => json_diff(&sub, v, w, out),
+                    None => out.push(format!("{}: removed", sub))
+                }
+            }
+            for key in b.keys().filter(|k| !a.contains_key(*k)) {
+                out.push(format!("{}.{}: added", path, key));
+            }
+        },
+        (Value::Array(a), Value::Array(b)) => {
+            for (i, (v, w)) in a.iter().zip(b.iter()).enumerate() {
+                json_diff(&format!("{}[{}]", path, i), v, w, out);
+            }
+            if a.len() != b.len() {
+                out.push(format!("{}: {} -> {} element(s)", path, a.len(), b.len()));
+            }
+        },
+        (a, b) if a != b => out.push(format!("{}: {} -> {}", path, a, b)),
+        _ => ()
+    }
+}
+
+fn symbol_diff(name: &str, a: &SymbolEntry, b: &SymbolEntry, out: &mut Vec<String>)
+{
+    let prefix = format!("symbol {}", name);
+    if a.ty != b.ty {
+        out.push(format!("{}: type: {:?} -> {:?}", prefix, a.ty, b.ty));
+    }
+    if a.flags != b.flags {
+        let removed = flag_names(a.flags & !b.flags).into_iter().map(|v| format!("-{}", v));
+        let added = flag_names(b.flags & !a.flags).into_iter().map(|v| format!("+{}", v));
+        out.push(format!("{}: flags: {}", prefix, removed.chain(added).collect::<Vec<_>>().join(" ")));
+    }
+    if a.register != b.register {
+        let register = |v: Option<u8>| v.map(|v| v.to_string()).unwrap_or_else(|| "none".into());
+        out.push(format!("{}: register: {} -> {}", prefix, register(a.register), register(b.register)));
+    }
+    match (&a.extended_data, &b.extended_data) {
+        (Some(v), Some(w)) => json_diff(&format!("{}: extended data", prefix), v, w, out),
+        (Some(_), None) => out.push(format!("{}: extended data removed", prefix)),
+        (None, Some(_)) => out.push(format!("{}: extended data added", prefix)),
+        (None, None) => ()
+    }
+}
+
+//Only rows which differ are printed, prefixed by the shader pack they come from.
+fn hexdump_diff(a: &[u8], b: &[u8], out: &mut Vec<String>)
+{
+    let rows = (a.len().max(b.len()) + 15) / 16;
+    for row in 0..rows {
+        let range = |data: &[u8]| {
+            let start = (row * 16).min(data.len());
+            data[start..(start + 16).min(data.len())].to_vec()
+        };
+        let (v, w) = (range(a), range(b));
+        if v == w {
+            continue;
+        }
+        let hex = |data: &[u8]| data.iter().map(|v| format!("{:02x}", v)).collect::<Vec<_>>().join(" ");
+        out.push(format!("    - {:08x}  {}", row * 16, hex(&v)));
+        out.push(format!("    + {:08x}  {}", row * 16, hex(&w)));
+    }
+}
+
+/// Lists the differences between two shader packs, one per line; an empty list means that both
+/// packs are identical. Symbols are matched by name and stages by type.
+fn diff_packs(a: &Pack, b: &Pack, code: bool) -> Result<Vec<String>, Error>
+{
+    let mut out = Vec::new();
+    let (sa, sb) = (a.get_settings(), b.get_settings());
+    if sa.target != sb.target {
+        out.push(format!("target: {:?} -> {:?}", sa.target, sb.target));
+    }
+    if sa.ty != sb.ty {
+        out.push(format!("type: {:?} -> {:?}", sa.ty, sb.ty));
+    }
+    if sa.assembly_hash != sb.assembly_hash {
+        out.push(format!("assembly hash: {:#X} -> {:#X}", sa.assembly_hash, sb.assembly_hash));
+    }
+    let (syms_a, syms_b) = (load_symbol_entries(a)?, load_symbol_entries(b)?);
+    for (name, sym) in &syms_a {
+        match syms_b.get(name) {
+            Some(other) => symbol_diff(name, sym, other, &mut out),
+            None => out.push(format!("symbol {}: removed ({:?})", name, sym.ty))
+        }
+    }
+    for (name, sym) in syms_b.iter().filter(|(k, _)| !syms_a.contains_key(*k)) {
+        out.push(format!("symbol {}: added ({:?})", name, sym.ty));
+    }
+    let (stages_a, stages_b) = (load_stages(a)?, load_stages(b)?);
+    for (name, data) in &stages_a {
+        match stages_b.get(name) {
+            Some(other) if other != data => {
+                out.push(format!("stage {}: {} bytes (hash {:#X}) -> {} bytes (hash {:#X})", name,
+                                 data.len(), content_hash(data), other.len(), content_hash(other)));
+                if code {
+                    hexdump_diff(data, other, &mut out);
+                }
+            },
+            Some(_) => (),
+            None => out.push(format!("stage {}: removed ({} bytes)", name, data.len()))
+        }
+    }
+    for (name, data) in stages_b.iter().filter(|(k, _)| !stages_a.contains_key(*k)) {
+        out.push(format!("stage {}: added ({} bytes)", name, data.len()));
+    }
+    Ok(out)
+}
+
+fn show_diff(a: &Pack, b: &Pack, code: bool) -> Result<bool, Error>
+{
+    let lines = diff_packs(a, b, code)?;
+    for line in &lines {
+        println!("{}", line);
+    }
+    match lines.is_empty() {
+        true => println!("Shader packs are identical"),
+        false => println!("Shader packs differ")
+    }
+    Ok(lines.is_empty())
+}
+
+//Prefix of the files written by --dump and --sources.
+fn input_stem(input: &Input) -> String
+{
+    match input {
+        Input::Path(path) => path.file_stem().map(|v| v.to_string_lossy()).unwrap_or_default().into_owned(),
+        Input::Stdin => String::from("stdin"),
+        Input::Assembly(_, name) => String::from(*name)
+    }
+}
+
+fn run(identical: &mut bool) -> Result<(), Error> {
+    alloc_verbosity_level(0);
     let matches = Command::new("shaderd")
         .author("BlockProject 3D")
         .about("BlockProject 3D SDK - Shader Disassembler")
@@ -105,24 +816,456 @@ fn main() {
         .args([
             Arg::new("dump").short('d').long("dump")
                 .help("Dump all shader stage code to separate files"),
+            Arg::new("force").long("force")
+                .help("Overwrite existing files when dumping shader stages or sources"),
             Arg::new("symbol").short('s').long("symbol").takes_value(true)
                 .help("Show all data about a symbol"),
             Arg::new("table").short('t').long("table")
                 .help("Show symbol table"),
-            Arg::new("shader").takes_value(true).allow_invalid_utf8(true).required(true)
-                .help("Shader pack file to disassemble")
+            Arg::new("origins").long("origins").requires("table")
+                .help("Show the origin of each symbol and stage in the symbol table"),
+            Arg::new("json").long("json").conflicts_with_all(&["dump", "extract_resource", "table"])
+                .help("Print the shader pack or the symbol given by --symbol as a JSON document"),
+            Arg::new("sources").long("sources")
+                .conflicts_with_all(&["dump", "symbol", "table", "json", "extract_resource", "diff"])
+                .help("Print the sources embedded by a debug build, or write them to the directory given by --output"),
+            Arg::new("extract_resource").short('x').long("extract-resource").takes_value(true)
+                .help("Extract the content of an embedded resource"),
+            Arg::new("output").short('o').long("output").takes_value(true).allow_invalid_utf8(true)
+                .help("Output file for --extract-resource (default: the resource name), output directory for --dump (default: the current directory) or for --sources"),
+            Arg::new("from_assembly").long("from-assembly").takes_value(true).allow_invalid_utf8(true)
+                .requires("pack").conflicts_with("shader")
+                .help("Inspect a shader pack of the given assembly instead of a shader pack file"),
+            Arg::new("pack").long("pack").takes_value(true).requires("from_assembly")
+                .help("Name of the shader pack, or of a symbol it provides, to inspect in the assembly given by --from-assembly"),
+            Arg::new("diff").long("diff").takes_value(true).allow_invalid_utf8(true)
+                .conflicts_with_all(&["dump", "symbol", "table", "json", "extract_resource"])
+                .help("Compare the shader pack with another one, exits with code 2 when they differ"),
+            Arg::new("code").long("code").requires("diff")
+                .help("Hexdump the differing parts of shader stage code with --diff"),
+            Arg::new("verify_signature").long("verify-signature").takes_value(true).allow_invalid_utf8(true)
+                .help("Fail unless the shader pack is signed with the given Ed25519 public key (raw 32 bytes or PEM)"),
+            Arg::new("shader").takes_value(true).allow_invalid_utf8(true).required_unless_present("from_assembly")
+                .help("Shader pack file to disassemble ('-' to read from the standard input)")
         ]).get_matches();
-    let path = matches.value_of_os("shader").map(Path::new).unwrap();
-    let data = if let Some(name) = matches.value_of("symbol") {
-        show_symbol(path, name)
-    } else {
-        disassemble(path, matches.is_present("table"))
+    let input = match matches.value_of_os("from_assembly").map(Path::new) {
+        Some(assembly) => Input::Assembly(assembly, matches.value_of("pack").unwrap()),
+        None => match matches.value_of_os("shader").unwrap() {
+            v if v == "-" => Input::Stdin,
+            v => Input::Path(Path::new(v))
+        }
     };
-    if let Err(e) = data {
-        match e {
-            Error::Io(e) => eprintln!("An io error has occured: {}", e),
-            Error::Bpx(e) => eprintln!("A BPX error has occured: {}", e)
+    let pubkey = matches.value_of_os("verify_signature").map(Path::new);
+    open(&input, pubkey).and_then(|shader| {
+        if let Some(other) = matches.value_of_os("diff") {
+            let other = open(&Input::Path(Path::new(other)), None)?;
+            *identical = show_diff(&shader, &other, matches.is_present("code"))?;
+            Ok(())
+        } else if let Some(name) = matches.value_of("extract_resource") {
+            let output = matches.value_of_os("output").map(Path::new).unwrap_or_else(|| Path::new(name));
+            extract_resource(shader, name, output)
+        } else if matches.is_present("dump") {
+            let out_dir = matches.value_of_os("output").map(Path::new).unwrap_or_else(|| Path::new("."));
+            dump_stages(shader, &input_stem(&input), out_dir, matches.is_present("force"))
+        } else if matches.is_present("sources") {
+            let out_dir = matches.value_of_os("output").map(Path::new);
+            show_sources(shader, &input_stem(&input), out_dir, matches.is_present("force"))
+        } else if let Some(name) = matches.value_of("symbol") {
+            match matches.is_present("json") {
+                true => show_symbol_json(&shader, name),
+                false => show_symbol(shader, name)
+            }
+        } else if matches.is_present("json") {
+            pack_json(&shader).map(|v| print_json(&v))
+        } else {
+            disassemble(shader, matches.is_present("table"), matches.is_present("origins"))
+        }
+    })
+}
+
+fn main() {
+    init_bp3d_logger(|| {
+        let mut identical = true;
+        match run_cli(|| run(&mut identical)) {
+            0 if !identical => EXIT_DIFFERENT,
+            code => code
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::fs::File;
+    use std::io::{BufWriter, Cursor, Write};
+    use std::path::{Path, PathBuf};
+    use bp3d_shaderl::{assembler, extract};
+    use bp3d_symbols::{DEBUG_INFO_SECTION_TYPE, DebugInfo, DebugSource, DebugStage, encode_resources, Origin, Origins, ORIGIN_SECTION_TYPE, PAYLOAD_SECTION_TYPE, PayloadKind, Resource, RESOURCE_SECTION_TYPE, StageOrigin, SymbolOrigin};
+    use bpx::core::builder::SectionHeaderBuilder;
+    use bpx::shader::{Builder, Shader, ShaderPack, Stage, Target, Type};
+    use bp3d_symbols::{FromBpx, PipelineObject, StructObject, ToBpx};
+    use crate::{diff_packs, disassemble, dump_stages, Error, extract_resource, flags_to_string, format_origin, Input, load_origins, load_payload, MAX_STDIN_SIZE, open, pack_json, pipeline_state, read_limited, sd_to_json, show_sources};
+
+    fn lut() -> Vec<u8>
+    {
+        (0..=255).collect()
+    }
+
+    fn origins() -> Origins
+    {
+        Origins {
+            symbols: vec![SymbolOrigin {
+                name: "ModelView".into(),
+                origin: Origin {
+                    file: "common.sal".into(),
+                    line: Some(3),
+                    modules: vec!["Camera".into()]
+                }
+            }],
+            stages: vec![StageOrigin {
+                stage: "Vertex".into(),
+                file: "shader.glsl".into()
+            }]
+        }
+    }
+
+    fn write_pack(path: &Path, origins: Option<&Origins>)
+    {
+        let mut pack = ShaderPack::create(BufWriter::new(File::create(path).unwrap()), Builder::new().ty(Type::Pipeline));
+        pack.save().unwrap();
+        let mut inner = pack.into_inner();
+        {
+            let handle = inner.sections_mut().create(SectionHeaderBuilder::new().ty(RESOURCE_SECTION_TYPE));
+            let mut writer = inner.sections().open(handle).unwrap();
+            writer.write_all(&encode_resources(&[Resource { name: "CurveLut".into(), data: lut() }])).unwrap();
         }
-        std::process::exit(1);
+        if let Some(origins) = origins {
+            let handle = inner.sections_mut().create(SectionHeaderBuilder::new().ty(ORIGIN_SECTION_TYPE));
+            let mut writer = inner.sections().open(handle).unwrap();
+            writer.write_all(&origins.encode()).unwrap();
+        }
+        inner.save().unwrap();
+    }
+
+    fn fixture(tag: &str) -> PathBuf
+    {
+        let dir = std::env::temp_dir().join(format!("shaderd-{}-{}", tag, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        write_pack(&dir.join("pack.bpx"), None);
+        dir
+    }
+
+    #[test]
+    fn extract()
+    {
+        let dir = fixture("extract");
+        let path = dir.join("pack.bpx");
+        let output = dir.join("curves.bin");
+        extract_resource(open(&Input::Path(&path), None).unwrap(), "CurveLut", &output).unwrap();
+        assert_eq!(std::fs::read(&output).unwrap(), lut());
+        assert!(matches!(extract_resource(open(&Input::Path(&path), None).unwrap(), "Missing", &output), Err(Error::ResourceNotFound(_))));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn stdin()
+    {
+        let dir = fixture("stdin");
+        let bytes = std::fs::read(dir.join("pack.bpx")).unwrap();
+        let data = read_limited(Cursor::new(bytes.clone()), MAX_STDIN_SIZE).unwrap();
+        assert_eq!(data, bytes);
+        let output = dir.join("curves.bin");
+        extract_resource(ShaderPack::open(Cursor::new(data)).unwrap(), "CurveLut", &output).unwrap();
+        assert_eq!(std::fs::read(&output).unwrap(), lut());
+        assert!(matches!(read_limited(Cursor::new(bytes), 16), Err(Error::InputTooLarge(16))));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn from_assembly()
+    {
+        let dir = fixture("assembly");
+        let pack = dir.join("pack.bpx");
+        let assembly = dir.join("assembly.bpx");
+        assembler::run(assembler::Config {
+            n_threads: 1,
+            debug: false,
+            output: &assembly,
+            assembly: None,
+            name: "test",
+            verify: true,
+            shaders: [pack.as_path()].into_iter()
+        }).unwrap();
+        //The pack is inspected from the copy in the assembly.
+        std::fs::remove_file(&pack).unwrap();
+        let output = dir.join("curves.bin");
+        extract_resource(open(&Input::Assembly(&assembly, "pack"), None).unwrap(), "CurveLut", &output).unwrap();
+        assert_eq!(std::fs::read(&output).unwrap(), lut());
+        assert!(matches!(open(&Input::Assembly(&assembly, "missing"), None), Err(Error::Find(_))));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn extracted()
+    {
+        let dir = fixture("extracted");
+        let pack = dir.join("stages.bpx");
+        write_symbol_pack(&pack, 1, b"void main() {}\n");
+        let assembly = dir.join("assembly.bpx");
+        assembler::run(assembler::Config {
+            n_threads: 1,
+            debug: false,
+            output: &assembly,
+            assembly: None,
+            name: "test",
+            verify: true,
+            shaders: [pack.as_path()].into_iter()
+        }).unwrap();
+        std::fs::remove_file(&pack).unwrap();
+        let output = dir.join("extracted.bpx");
+        extract::run(extract::Config { assembly: &assembly, name: "ModelView", output: Some(&output) }).unwrap();
+        disassemble(open(&Input::Path(&output), None).unwrap(), true, false).unwrap();
+        let value = pack_json(&open(&Input::Path(&output), None).unwrap()).unwrap();
+        assert_eq!(value["stages"], serde_json::json!([{ "stage": "vertex", "size": 15 }]));
+        assert_eq!(value["symbols"][0]["register"], 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn origins_section()
+    {
+        let dir = fixture("origins");
+        let plain = dir.join("pack.bpx");
+        let recorded = dir.join("origins.bpx");
+        write_pack(&recorded, Some(&origins()));
+        assert!(load_origins(&open(&Input::Path(&plain), None).unwrap().into_inner()).unwrap().is_none());
+        let loaded = load_origins(&open(&Input::Path(&recorded), None).unwrap().into_inner()).unwrap().unwrap();
+        assert_eq!(loaded, origins());
+        assert_eq!(format_origin(loaded.get_symbol("ModelView")), "common.sal:3");
+        assert_eq!(format_origin(loaded.get_symbol("Missing")), "unknown (not recorded)");
+        for path in [&plain, &recorded] {
+            disassemble(open(&Input::Path(path), None).unwrap(), true, true).unwrap();
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn dump()
+    {
+        let dir = fixture("dump");
+        let path = dir.join("stages.bpx");
+        {
+            let mut pack = ShaderPack::create(BufWriter::new(File::create(&path).unwrap()), Builder::new().ty(Type::Pipeline).target(Target::GL42));
+            {
+                let mut shaders = pack.shaders_mut();
+                shaders.create(Shader { stage: Stage::Vertex, data: b"void main() {}\n".to_vec() }).unwrap();
+                shaders.create(Shader { stage: Stage::Pixel, data: lut() }).unwrap();
+            }
+            pack.save().unwrap();
+        }
+        dump_stages(open(&Input::Path(&path), None).unwrap(), "stages", &dir, false).unwrap();
+        assert_eq!(std::fs::read(dir.join("stages.vertex.glsl")).unwrap(), b"void main() {}\n");
+        assert_eq!(std::fs::read(dir.join("stages.pixel.glsl")).unwrap(), lut());
+        std::fs::write(dir.join("stages.pixel.glsl"), b"").unwrap();
+        assert!(matches!(dump_stages(open(&Input::Path(&path), None).unwrap(), "stages", &dir, false), Err(Error::DumpFailed(2))));
+        assert!(std::fs::read(dir.join("stages.pixel.glsl")).unwrap().is_empty());
+        dump_stages(open(&Input::Path(&path), None).unwrap(), "stages", &dir, true).unwrap();
+        assert_eq!(std::fs::read(dir.join("stages.pixel.glsl")).unwrap(), lut());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn spirv_payload()
+    {
+        let dir = fixture("spirv");
+        let path = dir.join("stages.bpx");
+        let module: Vec<u8> = [0x07230203u32, 0x00010000, 0, 1, 0].iter().flat_map(|v| v.to_ne_bytes()).collect();
+        {
+            let mut pack = ShaderPack::create(BufWriter::new(File::create(&path).unwrap()), Builder::new().ty(Type::Pipeline).target(Target::GL46));
+            pack.shaders_mut().create(Shader { stage: Stage::Vertex, data: module.clone() }).unwrap();
+            pack.save().unwrap();
+            let mut inner = pack.into_inner();
+            {
+                let handle = inner.sections_mut().create(SectionHeaderBuilder::new().ty(PAYLOAD_SECTION_TYPE));
+                let mut writer = inner.sections().open(handle).unwrap();
+                writer.write_all(&PayloadKind::Spirv.encode()).unwrap();
+            }
+            inner.save().unwrap();
+        }
+        assert_eq!(load_payload(&open(&Input::Path(&path), None).unwrap().into_inner(), Target::GL46).unwrap(), PayloadKind::Spirv);
+        //Packs without a payload section hold the payload of their target.
+        assert_eq!(load_payload(&open(&Input::Path(&dir.join("pack.bpx")), None).unwrap().into_inner(), Target::GL42).unwrap(), PayloadKind::Glsl);
+        dump_stages(open(&Input::Path(&path), None).unwrap(), "stages", &dir, false).unwrap();
+        assert_eq!(std::fs::read(dir.join("stages.vertex.spv")).unwrap(), module);
+        disassemble(open(&Input::Path(&path), None).unwrap(), true, false).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn sources()
+    {
+        use cli_common::Report;
+        let dir = fixture("sources");
+        let path = dir.join("debug.bpx");
+        let info = DebugInfo {
+            compiler_version: "0.1.0".into(),
+            target: "GL42".into(),
+            options: [("debug".to_string(), "true".to_string())].into_iter().collect(),
+            stages: vec![DebugStage {
+                stage: "vertex".into(),
+                sources: vec![DebugSource {
+                    name: "shader.glsl".into(),
+                    text: "#stage vertex\n\nvoid main() {}\n".into()
+                }],
+                code: "#version 420\nvoid main() {}\n".into()
+            }]
+        };
+        {
+            let mut pack = ShaderPack::create(BufWriter::new(File::create(&path).unwrap()), Builder::new().ty(Type::Pipeline).target(Target::GL42));
+            pack.save().unwrap();
+            let mut inner = pack.into_inner();
+            {
+                let handle = inner.sections_mut().create(SectionHeaderBuilder::new().ty(DEBUG_INFO_SECTION_TYPE));
+                let mut writer = inner.sections().open(handle).unwrap();
+                writer.write_all(&info.encode()).unwrap();
+            }
+            inner.save().unwrap();
+        }
+        show_sources(open(&Input::Path(&path), None).unwrap(), "debug", None, false).unwrap();
+        show_sources(open(&Input::Path(&path), None).unwrap(), "debug", Some(&dir), false).unwrap();
+        assert_eq!(std::fs::read_to_string(dir.join("debug.vertex.source0.glsl")).unwrap(), info.stages[0].sources[0].text);
+        assert_eq!(std::fs::read_to_string(dir.join("debug.vertex.translated.glsl")).unwrap(), info.stages[0].code);
+        assert!(matches!(show_sources(open(&Input::Path(&path), None).unwrap(), "debug", Some(&dir), false), Err(Error::FileExists(_))));
+        let err = show_sources(open(&Input::Path(&dir.join("pack.bpx")), None).unwrap(), "pack", None, false).unwrap_err();
+        assert!(matches!(err, Error::NoDebugInfo));
+        assert_eq!(err.category().exit_code(), 3);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn json()
+    {
+        let dir = fixture("json");
+        let path = dir.join("stages.bpx");
+        {
+            let mut pack = ShaderPack::create(BufWriter::new(File::create(&path).unwrap()), Builder::new().ty(Type::Pipeline).target(Target::GL42));
+            {
+                let mut shaders = pack.shaders_mut();
+                shaders.create(Shader { stage: Stage::Vertex, data: b"void main() {}\n".to_vec() }).unwrap();
+                shaders.create(Shader { stage: Stage::Pixel, data: lut() }).unwrap();
+            }
+            pack.save().unwrap();
+        }
+        let value = pack_json(&open(&Input::Path(&path), None).unwrap()).unwrap();
+        let text = serde_json::to_string_pretty(&value).unwrap();
+        let parsed: serde_json::Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(parsed, value);
+        let keys: Vec<&str> = parsed.as_object().unwrap().keys().map(|v| &**v).collect();
+        assert_eq!(keys, ["assembly_hash", "stages", "symbols", "target", "type"]);
+        assert_eq!(parsed["target"], "GL42");
+        assert_eq!(parsed["type"], "Pipeline");
+        assert_eq!(parsed["stages"], serde_json::json!([
+            { "stage": "vertex", "size": 15 },
+            { "stage": "pixel", "size": 256 }
+        ]));
+        assert!(parsed["symbols"].as_array().unwrap().is_empty());
+        //The same pack always produces the same document.
+        assert_eq!(pack_json(&open(&Input::Path(&path), None).unwrap()).unwrap(), value);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn json_extended_data()
+    {
+        let obj = StructObject {
+            size: 16,
+            props: Vec::new(),
+            deprecated: None,
+            shared: true,
+            layout: None
+        };
+        let value = sd_to_json(&obj.to_bpx(true).unwrap());
+        assert_eq!(value["size"], 16);
+        assert_eq!(value["shared"], true);
+        assert_eq!(value["props"], serde_json::json!([]));
+        assert!(value.get("__debug__").is_none());
+        assert_eq!(value["__version__"], bp3d_symbols::FORMAT_VERSION);
+        //Without debug symbols property names are replaced by their hash.
+        let value = sd_to_json(&obj.to_bpx(false).unwrap());
+        assert_eq!(value["__version__"], bp3d_symbols::FORMAT_VERSION);
+        assert!(value.as_object().unwrap().keys().filter(|v| *v != "__version__").all(|v| v.starts_with("0x")));
+        assert_eq!(sd_to_json(&bpx::sd::Value::Double(f64::NAN)), "!double:NaN");
+        assert_eq!(sd_to_json(&bpx::sd::Value::Float(1.5)), 1.5);
+        assert_eq!(flags_to_string(0), "");
+    }
+
+    #[test]
+    fn pipeline_dump()
+    {
+        let obj: PipelineObject = serde_json::from_value(serde_json::json!({
+            "depth_enable": true,
+            "depth_write_enable": false,
+            "scissor_enable": false,
+            "render_mode": "Triangles",
+            "culling_mode": "BackFace",
+            "depth_func": "GreaterEqual",
+            "stencil_enable": true,
+            "stencil_ref": 7,
+            "depth_bias": -1.0
+        })).unwrap();
+        let obj = PipelineObject::from_bpx(&obj.to_bpx(false).unwrap()).unwrap();
+        let lines = pipeline_state(&obj);
+        assert_eq!(lines[0], "Depth: enable=true write=false func=GreaterEqual");
+        assert_eq!(lines[1], "Depth bias: constant=-1 slope=0");
+        assert_eq!(lines[2], "Stencil: enable=true func=Always ref=7 pass=Keep fail=Keep");
+        assert_eq!(lines[6], "Vertex input: true");
+    }
+
+    fn write_symbol_pack(path: &Path, register: u8, vertex: &[u8])
+    {
+        let mut pack = ShaderPack::create(BufWriter::new(File::create(path).unwrap()), Builder::new().ty(Type::Pipeline).target(Target::GL42));
+        {
+            let mut table = pack.symbols_mut().unwrap();
+            let mut builder = bpx::shader::symbol::Builder::new("ModelView");
+            builder.ty(bpx::shader::symbol::Type::Constant).register(register);
+            table.create(builder).unwrap();
+            let mut builder = bpx::shader::symbol::Builder::new("Albedo");
+            builder.ty(bpx::shader::symbol::Type::Texture).register(0);
+            table.create(builder).unwrap();
+        }
+        pack.shaders_mut().create(Shader { stage: Stage::Vertex, data: vertex.to_vec() }).unwrap();
+        pack.save().unwrap();
+    }
+
+    #[test]
+    fn diff()
+    {
+        let dir = fixture("diff");
+        let (a, b, c) = (dir.join("a.bpx"), dir.join("b.bpx"), dir.join("c.bpx"));
+        write_symbol_pack(&a, 1, b"void main() {}\n");
+        write_symbol_pack(&b, 2, b"void main() {}\n");
+        write_symbol_pack(&c, 1, b"void main() { }\n");
+        let load = |path: &Path| open(&Input::Path(path), None).unwrap();
+        assert!(diff_packs(&load(&a), &load(&a), true).unwrap().is_empty());
+        let lines = diff_packs(&load(&a), &load(&b), true).unwrap();
+        assert_eq!(lines, ["symbol ModelView: register: 1 -> 2"]);
+        let lines = diff_packs(&load(&a), &load(&c), false).unwrap();
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].starts_with("stage vertex: 15 bytes"));
+        let lines = diff_packs(&load(&a), &load(&c), true).unwrap();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].starts_with("    - 00000000  76 6f 69 64"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn exit_codes()
+    {
+        use cli_common::Report;
+        let io = std::io::Error::new(std::io::ErrorKind::NotFound, "missing");
+        assert_eq!(Error::Io(io).category().exit_code(), 6);
+        assert_eq!(Error::Bpx(bpx::shader::error::Error::Open(bpx::core::error::OpenError::SectionNotLoaded)).category().exit_code(), 5);
+        assert_eq!(Error::FileExists("a.glsl".into()).category().exit_code(), 2);
+        assert_eq!(Error::InputTooLarge(MAX_STDIN_SIZE).category().exit_code(), 3);
     }
 }
@@ -28,14 +28,19 @@
 
 use std::fs::File;
 use std::io::{BufReader, BufWriter, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use bp3d_symbols::FileKind;
 use bpx::core::builder::SectionHeaderBuilder;
 use bpx::shader::ShaderPack;
 use bpx::shader::symbol::{FLAG_EXTERNAL, FLAG_INTERNAL, FLAG_REGISTER};
 use bpx::utils::hash;
 use byteorder::{ByteOrder, LittleEndian};
 use log::info;
-use crate::symbols::{check_signature_with_assembly, load_and_sign_symbols};
+use crate::packs::{load_packs, PackError};
+use crate::provenance::{PackCopy, Provenance};
+use crate::shared::check_shared_buffers;
+use crate::symbols::{check_signature_with_assembly, load_and_sign_symbols, sign_symbols};
+use crate::verify::{Issue, verify_packs};
 use thiserror::Error;
 
 pub struct Config<'a, I: Iterator<Item = &'a Path>> {
@@ -44,6 +49,7 @@ pub struct Config<'a, I: Iterator<Item = &'a Path>> {
     pub output: &'a Path,
     pub assembly: Option<&'a Path>,
     pub name: &'a str,
+    pub verify: bool,
     pub shaders: I
 }
 
@@ -60,7 +66,51 @@ pub enum Error {
     #[error("BPX serialization error: {0}")]
     Serde(bpx::sd::serde::Error),
     #[error("section open error: {0}")]
-    SectionOpen(bpx::core::error::OpenError)
+    SectionOpen(bpx::core::error::OpenError),
+    #[error("shared constant buffer error: {0}")]
+    Shared(crate::shared::Error),
+    #[error("provenance error: {0}")]
+    Provenance(crate::provenance::Error),
+    #[error("failed to load {} shader pack(s): {}", .0.len(), .0.iter().map(|v| v.to_string()).collect::<Vec<_>>().join("; "))]
+    Packs(Vec<PackError>),
+    #[error("{} incompatible shader pack issue(s): {}", .0.len(), .0.iter().map(|v| v.to_string()).collect::<Vec<_>>().join("; "))]
+    Verify(Vec<Issue>),
+    #[error("'{}' is {}, but shaderl expects a shader pack (type SP){}", .0.display(), .1, pack_hint(*.1))]
+    NotAPack(PathBuf, FileKind),
+    #[error("'{}' is {}, but --assembly expects a shader assembly (type SA){}", .0.display(), .1, assembly_hint(*.1))]
+    NotAnAssembly(PathBuf, FileKind)
+}
+
+fn pack_hint(kind: FileKind) -> &'static str {
+    match kind {
+        FileKind::ShaderLib => "; did you mean to pass it to shaderc with --lib?",
+        FileKind::Assembly => "; did you mean to pass it with --assembly?",
+        _ => ""
+    }
+}
+
+fn assembly_hint(kind: FileKind) -> &'static str {
+    match kind {
+        FileKind::ShaderLib => "; did you mean to pass it to shaderc with --lib?",
+        FileKind::ShaderPack => "; did you mean to pass it as an input?",
+        _ => ""
+    }
+}
+
+fn check_inputs(assembly: Option<&Path>, shaders: &[&Path]) -> Result<(), Error> {
+    for path in shaders {
+        let kind = FileKind::probe(path).map_err(Error::Io)?;
+        if kind != FileKind::ShaderPack {
+            return Err(Error::NotAPack(path.into(), kind));
+        }
+    }
+    if let Some(path) = assembly {
+        let kind = FileKind::probe(path).map_err(Error::Io)?;
+        if kind != FileKind::Assembly {
+            return Err(Error::NotAnAssembly(path.into(), kind));
+        }
+    }
+    Ok(())
 }
 
 fn get_assembly_hash(file: &Path) -> Result<u64, Error> {
@@ -71,10 +121,34 @@ fn get_assembly_hash(file: &Path) -> Result<u64, Error> {
 
 pub fn run<'a>(config: Config<'a, impl Iterator<Item = &'a Path>>) -> Result<(), Error> {
     info!("Assembling '{}'...", config.name);
+    let mut shaders: Vec<&Path> = config.shaders.collect();
+    //Merge packs by name so that the output does not depend on the order of the command line.
+    shaders.sort();
+    check_inputs(config.assembly, &shaders)?;
+    info!("Loading shader packs...");
+    let packs = load_packs(config.n_threads, &shaders).map_err(Error::Packs)?;
+    if config.verify {
+        info!("Verifying shader pack compatibility...");
+        let parent = config.assembly.map(get_assembly_hash).transpose()?;
+        verify_packs(&shaders, &packs, parent).map_err(Error::Verify)?;
+    }
+    info!("Checking shared constant buffers...");
+    check_shared_buffers(packs.iter().flat_map(|v| v.shared.iter().cloned())).map_err(Error::Shared)?;
+    let mut provenance = Provenance::default();
+    let mut copies = Vec::new();
+    let mut syms = Vec::new();
+    for pack in packs {
+        copies.push(PackCopy {
+            name: pack.info.name.clone(),
+            data: pack.data
+        });
+        provenance.packs.push(pack.info);
+        syms.extend(pack.symbols);
+    }
     let file = File::create(&config.output).map_err(Error::Io)?;
-    info!("Loading and signing shader symbols...");
-    let mut shader_tree = load_and_sign_symbols(config.n_threads, config.shaders)
-        .map_err(Error::Symbol)?;
+    info!("Signing shader symbols...");
+    let mut shader_tree = sign_symbols(config.n_threads, syms)
+        .map_err(crate::symbols::Error::Signing).map_err(Error::Symbol)?;
     shader_tree.mass_set_internal();
     info!("Loading and signing parent assembly symbols...");
     let assembly_tree = config.assembly.map(|v| load_and_sign_symbols(config.n_threads, [v].into_iter()))
@@ -112,10 +186,14 @@ pub fn run<'a>(config: Config<'a, impl Iterator<Item = &'a Path>>) -> Result<(),
         symbols.create(builder).map_err(Error::Shader)?;
     }
     pack.save().map_err(Error::Shader)?;
-    if let Some(assembly) = config.assembly {
-        info!("Writing parent assembly hash...");
-        let mut inner = pack.into_inner();
-        { //Rust is garbage too stupid to see that inner is not used when save is called!
+    let mut inner = pack.into_inner();
+    { //Rust is garbage too stupid to see that inner is not used when save is called!
+        info!("Writing symbol provenance...");
+        provenance.write(&mut inner).map_err(Error::Provenance)?;
+        info!("Writing shader pack copies...");
+        PackCopy::write(&copies, &mut inner).map_err(Error::Provenance)?;
+        if let Some(assembly) = config.assembly {
+            info!("Writing parent assembly hash...");
             let hash = get_assembly_hash(assembly)?;
             let handle = inner.sections_mut().create(SectionHeaderBuilder::new().ty(0xFD));
             let mut writer = inner.sections().open(handle).map_err(Error::SectionOpen)?;
@@ -123,8 +201,199 @@ pub fn run<'a>(config: Config<'a, impl Iterator<Item = &'a Path>>) -> Result<(),
             LittleEndian::write_u64(&mut buf, hash);
             writer.write_all(&buf).map_err(Error::Io)?;
         }
-        inner.save().map_err(Error::Core)?;
     }
+    inner.save().map_err(Error::Core)?;
     info!("Generated assembly '{}' and saved to {:?}", config.name, config.output);
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::BufWriter;
+    use std::path::Path;
+    use std::path::PathBuf;
+    use bpx::package::Package;
+    use bpx::shader::ShaderPack;
+    use bpx::shader::symbol::Type;
+    use crate::assembler;
+
+    fn write_pack(path: &Path, ty: bpx::shader::Type) {
+        let mut pack = ShaderPack::create(BufWriter::new(File::create(path).unwrap()),
+                                          bpx::shader::Builder::new()
+                                              .ty(ty)
+                                              .target(bpx::shader::Target::GL42));
+        pack.save().unwrap();
+    }
+
+    fn write_symbols(path: &Path, symbols: &[(String, Type)]) {
+        write_target_symbols(path, bpx::shader::Target::GL42, symbols);
+    }
+
+    fn write_target_symbols(path: &Path, target: bpx::shader::Target, symbols: &[(String, Type)]) {
+        let mut pack = ShaderPack::create(BufWriter::new(File::create(path).unwrap()),
+                                          bpx::shader::Builder::new()
+                                              .ty(bpx::shader::Type::Pipeline)
+                                              .target(target));
+        {
+            let mut table = pack.symbols_mut().unwrap();
+            for (name, ty) in symbols {
+                let mut builder = bpx::shader::symbol::Builder::new(name.as_str());
+                builder.ty(*ty).external();
+                table.create(builder).unwrap();
+            }
+        }
+        pack.save().unwrap();
+    }
+
+    fn write_packs(dir: &Path, count: usize) -> Vec<PathBuf> {
+        (0..count).map(|i| {
+            let path = dir.join(format!("pack{:02}.bpx", i));
+            write_symbols(&path, &[
+                ("ModelView".into(), Type::Constant),
+                (format!("Texture{}", i), Type::Texture),
+                (format!("Sampler{}", i % 3), Type::Sampler)
+            ]);
+            path
+        }).collect()
+    }
+
+    fn write_lib(path: &Path) {
+        let mut lib = Package::create(BufWriter::new(File::create(path).unwrap()),
+                                      bpx::package::Builder::new().type_code(*b"SL")).unwrap();
+        lib.save().unwrap();
+    }
+
+    fn assemble(assembly: Option<&Path>, shader: &Path) -> String {
+        assembler::run(assembler::Config {
+            n_threads: 1,
+            debug: false,
+            output: &shader.with_extension("out"),
+            assembly,
+            name: "test",
+            verify: true,
+            shaders: [shader].into_iter()
+        }).unwrap_err().to_string()
+    }
+
+    fn assemble_many(dir: &Path, shaders: &[&Path], verify: bool) -> Result<(), assembler::Error> {
+        assembler::run(assembler::Config {
+            n_threads: 1,
+            debug: false,
+            output: &dir.join("assembly.bpx"),
+            assembly: None,
+            name: "test",
+            verify,
+            shaders: shaders.iter().copied()
+        })
+    }
+
+    #[test]
+    fn reject_wrong_inputs() {
+        let dir = std::env::temp_dir().join(format!("shaderl-inputs-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let lib = dir.join("foo.bpx");
+        let pack = dir.join("pack.bpx");
+        let assembly = dir.join("assembly.bpx");
+        write_lib(&lib);
+        write_pack(&pack, bpx::shader::Type::Pipeline);
+        write_pack(&assembly, bpx::shader::Type::Assembly);
+        assert_eq!(assemble(None, &lib), format!("'{}' is a shader library (type SL), but shaderl expects a shader pack (type SP); did you mean to pass it to shaderc with --lib?", lib.display()));
+        assert_eq!(assemble(None, &assembly), format!("'{}' is a shader assembly (type SA), but shaderl expects a shader pack (type SP); did you mean to pass it with --assembly?", assembly.display()));
+        assert_eq!(assemble(Some(&pack), &pack), format!("'{}' is a shader pack (type SP), but --assembly expects a shader assembly (type SA); did you mean to pass it as an input?", pack.display()));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn parallel_deterministic() {
+        let dir = std::env::temp_dir().join(format!("shaderl-parallel-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let packs = write_packs(&dir, 24);
+        let mut outputs = Vec::new();
+        for (n_threads, reverse) in [(1, false), (8, true)] {
+            let output = dir.join(format!("assembly{}.bpx", n_threads));
+            let mut shaders: Vec<&Path> = packs.iter().map(|v| v.as_path()).collect();
+            if reverse {
+                shaders.reverse();
+            }
+            assembler::run(assembler::Config {
+                n_threads,
+                debug: false,
+                output: &output,
+                assembly: None,
+                name: "test",
+                verify: true,
+                shaders: shaders.into_iter()
+            }).unwrap();
+            outputs.push(std::fs::read(&output).unwrap());
+        }
+        assert!(outputs[0] == outputs[1]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reject_mixed_targets() {
+        let dir = std::env::temp_dir().join(format!("shaderl-targets-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let a = dir.join("a.bpx");
+        let b = dir.join("b.bpx");
+        let c = dir.join("c.bpx");
+        write_target_symbols(&a, bpx::shader::Target::GL42, &[("ModelView".into(), Type::Constant)]);
+        write_target_symbols(&b, bpx::shader::Target::GL40, &[("Albedo".into(), Type::Texture)]);
+        write_target_symbols(&c, bpx::shader::Target::GL40, &[("ModelView".into(), Type::Constant)]);
+        let err = assemble_many(&dir, &[&a, &b, &c], true).unwrap_err();
+        assert_eq!(err.to_string(), format!("2 incompatible shader pack issue(s): '{}' targets GL40, but '{}' targets GL42; '{}' targets GL40, but '{}' targets GL42",
+                                            b.display(), a.display(), c.display(), a.display()));
+        assemble_many(&dir, &[&a, &b, &c], false).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reject_conflicting_symbols() {
+        let dir = std::env::temp_dir().join(format!("shaderl-conflicts-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let a = dir.join("a.bpx");
+        let b = dir.join("b.bpx");
+        let c = dir.join("c.bpx");
+        write_symbols(&a, &[("ModelView".into(), Type::Constant), ("Albedo".into(), Type::Texture)]);
+        write_symbols(&b, &[("ModelView".into(), Type::Constant), ("Albedo".into(), Type::Sampler)]);
+        write_symbols(&c, &[("ModelView".into(), Type::ConstantBuffer), ("Albedo".into(), Type::Texture)]);
+        let err = assemble_many(&dir, &[&a, &b, &c], true).unwrap_err();
+        let packs = format!("'{}', '{}', '{}'", a.display(), b.display(), c.display());
+        assert_eq!(err.to_string(), format!("2 incompatible shader pack issue(s): symbol 'Albedo' is exported with conflicting definitions by {}; symbol 'ModelView' is exported with conflicting definitions by {}", packs, packs));
+        //Identical definitions shared by multiple packs are merged, not conflicts.
+        let d = dir.join("d.bpx");
+        write_symbols(&d, &[("ModelView".into(), Type::Constant), ("Albedo".into(), Type::Texture)]);
+        assemble_many(&dir, &[&a, &d], true).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn aggregate_pack_errors() {
+        let dir = std::env::temp_dir().join(format!("shaderl-broken-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let packs = write_packs(&dir, 5);
+        //Keep the BPX header so that the inputs pass the file kind check but fail to load.
+        for path in [&packs[0], &packs[2], &packs[4]] {
+            let data = std::fs::read(path).unwrap();
+            std::fs::write(path, &data[..40]).unwrap();
+        }
+        let err = assembler::run(assembler::Config {
+            n_threads: 4,
+            debug: false,
+            output: &dir.join("assembly.bpx"),
+            assembly: None,
+            name: "test",
+            verify: true,
+            shaders: packs.iter().map(|v| v.as_path())
+        }).unwrap_err();
+        match err {
+            assembler::Error::Packs(errors) => {
+                let paths: Vec<&Path> = errors.iter().map(|v| v.0.as_path()).collect();
+                assert_eq!(paths, [&packs[0], &packs[2], &packs[4]]);
+            },
+            e => panic!("unexpected error: {}", e)
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -28,7 +28,7 @@
 
 use std::collections::HashMap;
 use serde::Serialize;
-use bp3d_symbols::{ConstantObject, OutputObject, PipelineObject, Refs, StructObject, TextureObject, ToBpx};
+use bp3d_symbols::{ConstantObject, OutputObject, PipelineObject, Refs, SamplerObject, StructObject, TextureObject, ToBpx};
 
 pub type ExtDataPtr = Box<dyn ExtData + Send + Sync>;
 
@@ -71,7 +71,7 @@ macro_rules! impl_into_ext_data_with_refs {
     };
 }
 
-impl_into_ext_data!(ConstantObject TextureObject OutputObject PipelineObject);
+impl_into_ext_data!(ConstantObject TextureObject SamplerObject OutputObject PipelineObject);
 impl_into_ext_data_with_refs!(StructObject);
 
 struct ExtDataImplWithRefs<T> {
@@ -0,0 +1,268 @@
+// Copyright (c) 2021, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+
+//! Extraction of a shader pack from the copy embedded in an assembly.
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use bp3d_symbols::FileKind;
+use bpx::shader::ShaderPack;
+use bpx::shader::symbol::{FLAG_ASSEMBLY, FLAG_EXTERNAL};
+use log::info;
+use crate::provenance::{PackCopy, Provenance};
+use thiserror::Error;
+
+/// Maximum number of close matches listed when a name is not found.
+const MAX_SUGGESTIONS: usize = 8;
+
+pub struct Config<'a> {
+    pub assembly: &'a Path,
+    /// Name of a shader pack of the assembly or of a symbol; a symbol selects the pack which
+    /// provides it.
+    pub name: &'a str,
+    /// Output file, `<pack>.bpx` in the current directory if None.
+    pub output: Option<&'a Path>
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("io error: {0}")]
+    Io(std::io::Error),
+    #[error("BPX shader error: {0}")]
+    Shader(bpx::shader::error::Error),
+    #[error("provenance error: {0}")]
+    Provenance(crate::provenance::Error),
+    #[error("'{}' is {}, but extract expects a shader assembly (type SA)", .0.display(), .1)]
+    NotAnAssembly(PathBuf, FileKind),
+    #[error("no shader pack or symbol named '{name}' in the assembly{}", suggestions(.close))]
+    NotFound {
+        name: String,
+        close: Vec<String>
+    },
+    #[error("the assembly does not contain a copy of shader pack '{0}' (it was assembled by an older shaderl)")]
+    NoCopy(String)
+}
+
+bpx::impl_err_conversion!(
+    Error {
+        std::io::Error => Io,
+        bpx::shader::error::Error => Shader,
+        crate::provenance::Error => Provenance
+    }
+);
+
+fn suggestions(close: &[String]) -> String {
+    match close.is_empty() {
+        true => String::new(),
+        false => format!("; did you mean {}?", close.iter().map(|v| format!("'{}'", v)).collect::<Vec<_>>().join(", "))
+    }
+}
+
+//Close matches are names which start with the requested one, or which it starts with, ignoring
+//case.
+fn close_matches<'a>(name: &str, candidates: impl Iterator<Item = &'a str>) -> Vec<String> {
+    let name = name.to_lowercase();
+    let mut close: Vec<String> = candidates
+        .filter(|v| {
+            let v = v.to_lowercase();
+            v.starts_with(&name) || name.starts_with(&v)
+        })
+        .map(String::from)
+        .collect();
+    close.sort();
+    close.dedup();
+    close.truncate(MAX_SUGGESTIONS);
+    close
+}
+
+/// Returns the name of the shader pack selected by a pack or symbol name.
+pub(crate) fn resolve<'a>(provenance: &'a Provenance, name: &str) -> Result<&'a str, Error> {
+    if let Some(pack) = provenance.packs.iter().find(|v| v.name == name) {
+        return Ok(&pack.name);
+    }
+    if let Some(pack) = provenance.packs_of(name).next() {
+        return Ok(&pack.name);
+    }
+    let candidates = provenance.packs.iter()
+        .flat_map(|v| std::iter::once(&v.name).chain(v.symbols.iter()))
+        .map(|v| v.as_str());
+    Err(Error::NotFound {
+        name: name.into(),
+        close: close_matches(name, candidates)
+    })
+}
+
+//The copy is unlinked: references to the assembly become external references again, as they
+//were before the pack was linked.
+fn unlink(path: &Path) -> Result<(), Error> {
+    let mut pack = ShaderPack::open(File::options().read(true).write(true).open(path)?)?;
+    pack.set_assembly(0);
+    let indices: Vec<usize> = pack.symbols()?.iter()
+        .enumerate()
+        .filter(|(_, v)| v.flags & FLAG_ASSEMBLY != 0)
+        .map(|(i, _)| i)
+        .collect();
+    let mut symbols = pack.symbols_mut().unwrap();
+    for index in indices {
+        let sym = symbols.get_mut(index).unwrap();
+        sym.flags = (sym.flags & !FLAG_ASSEMBLY) | FLAG_EXTERNAL;
+    }
+    pack.save()?;
+    Ok(())
+}
+
+/// Writes the shader pack selected by `config.name` back to a standalone file and returns its
+/// path.
+pub fn run(config: Config) -> Result<PathBuf, Error> {
+    let kind = FileKind::probe(config.assembly)?;
+    if kind != FileKind::Assembly {
+        return Err(Error::NotAnAssembly(config.assembly.into(), kind));
+    }
+    let container = ShaderPack::open(BufReader::new(File::open(config.assembly)?))?.into_inner();
+    let provenance = Provenance::read(&container)?.unwrap_or_default();
+    let name = resolve(&provenance, config.name)?;
+    let copy = PackCopy::read(&container)?.unwrap_or_default()
+        .into_iter()
+        .find(|v| v.name == name)
+        .ok_or_else(|| Error::NoCopy(name.into()))?;
+    let output = match config.output {
+        Some(v) => v.to_path_buf(),
+        None => PathBuf::from(format!("{}.bpx", name))
+    };
+    std::fs::write(&output, &copy.data)?;
+    unlink(&output)?;
+    info!("Extracted shader pack '{}' to {:?}", name, output);
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::BufWriter;
+    use std::path::{Path, PathBuf};
+    use bpx::shader::{Shader, ShaderPack, Stage};
+    use bpx::shader::symbol::{FLAG_ASSEMBLY, FLAG_EXTERNAL, Type};
+    use crate::{assembler, extract};
+
+    fn write_pack(path: &Path, symbols: &[(&str, Type)], code: &[u8]) {
+        let mut pack = ShaderPack::create(BufWriter::new(File::create(path).unwrap()),
+                                          bpx::shader::Builder::new()
+                                              .ty(bpx::shader::Type::Pipeline)
+                                              .target(bpx::shader::Target::GL42));
+        {
+            let mut table = pack.symbols_mut().unwrap();
+            for (name, ty) in symbols {
+                let mut builder = bpx::shader::symbol::Builder::new(*name);
+                builder.ty(*ty);
+                table.create(builder).unwrap();
+            }
+        }
+        pack.shaders_mut().create(Shader { stage: Stage::Vertex, data: code.to_vec() }).unwrap();
+        pack.save().unwrap();
+    }
+
+    fn fixture(tag: &str) -> (PathBuf, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("shaderl-{}-{}", tag, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let a = dir.join("lit.bpx");
+        let b = dir.join("unlit.bpx");
+        write_pack(&a, &[("ModelView", Type::Constant), ("Lighting", Type::ConstantBuffer)], b"void main() { lit(); }\n");
+        write_pack(&b, &[("ModelView", Type::Constant), ("Albedo", Type::Texture)], b"void main() {}\n");
+        let output = dir.join("assembly.bpx");
+        assembler::run(assembler::Config {
+            n_threads: 1,
+            debug: false,
+            output: &output,
+            assembly: None,
+            name: "test",
+            verify: true,
+            shaders: [a.as_path(), b.as_path()].into_iter()
+        }).unwrap();
+        //The originals are not needed anymore.
+        std::fs::remove_file(&a).unwrap();
+        std::fs::remove_file(&b).unwrap();
+        (dir, output)
+    }
+
+    #[test]
+    fn extract_pack() {
+        let (dir, assembly) = fixture("extract");
+        let output = dir.join("out.bpx");
+        for name in ["unlit", "Albedo"] {
+            let path = extract::run(extract::Config { assembly: &assembly, name, output: Some(&output) }).unwrap();
+            assert_eq!(path, output);
+            let pack = ShaderPack::open(File::open(&output).unwrap()).unwrap();
+            assert_eq!(pack.get_settings().assembly_hash, 0);
+            assert_eq!(pack.get_settings().target, bpx::shader::Target::GL42);
+            let shaders = pack.shaders();
+            let mut stages = Vec::new();
+            for handle in &shaders {
+                stages.push(shaders.load(handle).unwrap().data.clone());
+            }
+            assert_eq!(stages, [b"void main() {}\n".to_vec()]);
+            let symbols = pack.symbols().unwrap();
+            let names: Vec<String> = symbols.iter().map(|v| symbols.load_name(v).unwrap().into()).collect();
+            assert_eq!(names, ["ModelView", "Albedo"]);
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn unlink() {
+        let dir = std::env::temp_dir().join(format!("shaderl-unlink-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("linked.bpx");
+        write_pack(&path, &[("ModelView", Type::Constant)], b"");
+        {
+            let mut pack = ShaderPack::open(File::options().read(true).write(true).open(&path).unwrap()).unwrap();
+            pack.set_assembly(42);
+            pack.symbols_mut().unwrap().get_mut(0).unwrap().flags |= FLAG_EXTERNAL | FLAG_ASSEMBLY;
+            pack.save().unwrap();
+        }
+        extract::unlink(&path).unwrap();
+        let pack = ShaderPack::open(File::open(&path).unwrap()).unwrap();
+        assert_eq!(pack.get_settings().assembly_hash, 0);
+        let flags = pack.symbols().unwrap().iter().next().unwrap().flags;
+        assert_eq!(flags & (FLAG_EXTERNAL | FLAG_ASSEMBLY), FLAG_EXTERNAL);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn unknown_name() {
+        let (dir, assembly) = fixture("extract-unknown");
+        let err = extract::run(extract::Config { assembly: &assembly, name: "LIT", output: None }).unwrap_err();
+        assert_eq!(err.to_string(), "no shader pack or symbol named 'LIT' in the assembly; did you mean 'lit'?");
+        let err = extract::run(extract::Config { assembly: &assembly, name: "light", output: None }).unwrap_err();
+        assert_eq!(err.to_string(), "no shader pack or symbol named 'light' in the assembly; did you mean 'Lighting'?");
+        let err = extract::run(extract::Config { assembly: &assembly, name: "Missing", output: None }).unwrap_err();
+        assert_eq!(err.to_string(), "no shader pack or symbol named 'Missing' in the assembly");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,147 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Classification of errors, used by the command line to pick an exit code.
+
+use crate::symbols::{Error as SymbolError, LoadError};
+use crate::{assembler, extract, find, linker, provenance, shared};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FailureKind
+{
+    /// A file could not be read or written.
+    Io,
+    /// The given shader packs are valid but cannot be assembled or linked together.
+    Input,
+    /// A BPX file is corrupt or could not be encoded.
+    Format
+}
+
+fn load_kind(e: &LoadError) -> FailureKind
+{
+    match e {
+        LoadError::Io(_) => FailureKind::Io,
+        _ => FailureKind::Format
+    }
+}
+
+fn symbol_kind(e: &SymbolError) -> FailureKind
+{
+    match e {
+        SymbolError::Load(e) => load_kind(e),
+        SymbolError::Signing(_) => FailureKind::Input
+    }
+}
+
+fn provenance_kind(e: &provenance::Error) -> FailureKind
+{
+    match e {
+        provenance::Error::Io(_) => FailureKind::Io,
+        _ => FailureKind::Format
+    }
+}
+
+impl assembler::Error
+{
+    pub fn kind(&self) -> FailureKind
+    {
+        use assembler::Error;
+        match self {
+            Error::Io(_) => FailureKind::Io,
+            Error::Symbol(e) => symbol_kind(e),
+            Error::Shader(_) | Error::Core(_) | Error::Serde(_) | Error::SectionOpen(_) => FailureKind::Format,
+            Error::Shared(shared::Error::Load(e)) => load_kind(e),
+            Error::Provenance(e) => provenance_kind(e),
+            //Only the first pack decides, all failures are printed anyway.
+            Error::Packs(errors) => errors.first().map(|v| load_kind(&v.1)).unwrap_or(FailureKind::Format),
+            Error::Shared(shared::Error::Mismatch(_)) | Error::Verify(_) | Error::NotAPack(_, _)
+            | Error::NotAnAssembly(_, _) => FailureKind::Input
+        }
+    }
+}
+
+impl linker::Error
+{
+    pub fn kind(&self) -> FailureKind
+    {
+        use linker::Error;
+        match self {
+            Error::Io(_) => FailureKind::Io,
+            Error::Symbol(e) => symbol_kind(e),
+            Error::Unresolved(_) => FailureKind::Input,
+            Error::Bpx(_) => FailureKind::Format
+        }
+    }
+}
+
+impl find::Error
+{
+    pub fn kind(&self) -> FailureKind
+    {
+        use find::Error;
+        match self {
+            Error::Io(_) => FailureKind::Io,
+            Error::Shader(_) => FailureKind::Format,
+            Error::Provenance(e) => provenance_kind(e),
+            Error::Resolve(e) => e.kind(),
+            Error::NoCopy(_) => FailureKind::Input
+        }
+    }
+}
+
+impl extract::Error
+{
+    pub fn kind(&self) -> FailureKind
+    {
+        use extract::Error;
+        match self {
+            Error::Io(_) => FailureKind::Io,
+            Error::Shader(_) => FailureKind::Format,
+            Error::Provenance(e) => provenance_kind(e),
+            Error::NotAnAssembly(_, _) | Error::NotFound { .. } | Error::NoCopy(_) => FailureKind::Input
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::failure::FailureKind;
+    use crate::linker;
+    use crate::symbols::{Error, LoadError, SigningError};
+
+    #[test]
+    fn kinds()
+    {
+        let io = || std::io::Error::new(std::io::ErrorKind::NotFound, "missing");
+        assert_eq!(linker::Error::Io(io()).kind(), FailureKind::Io);
+        assert_eq!(linker::Error::Unresolved(vec!["Foo".into()]).kind(), FailureKind::Input);
+        assert_eq!(linker::Error::Symbol(Error::Load(LoadError::Io(io()))).kind(), FailureKind::Io);
+        assert_eq!(linker::Error::Symbol(Error::Signing(SigningError::BrokenReference)).kind(), FailureKind::Input);
+    }
+}
@@ -0,0 +1,265 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Symbol search in shader assemblies.
+
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufReader, Cursor};
+use std::path::Path;
+use bpx::shader::ShaderPack;
+use bpx::shader::symbol::{FLAG_DOMAIN_STAGE, FLAG_EXTERNAL, FLAG_GEOMETRY_STAGE, FLAG_HULL_STAGE, FLAG_PIXEL_STAGE, FLAG_REGISTER, FLAG_VERTEX_STAGE};
+use crate::provenance::{PackCopy, Provenance};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("io error: {0}")]
+    Io(std::io::Error),
+    #[error("BPX shader error: {0}")]
+    Shader(bpx::shader::error::Error),
+    #[error("provenance error: {0}")]
+    Provenance(crate::provenance::Error),
+    #[error("{0}")]
+    Resolve(crate::extract::Error),
+    #[error("the assembly does not contain a copy of shader pack '{0}' (it was assembled by an older shaderl)")]
+    NoCopy(String)
+}
+
+bpx::impl_err_conversion!(
+    Error {
+        std::io::Error => Io,
+        bpx::shader::error::Error => Shader,
+        crate::provenance::Error => Provenance
+    }
+);
+
+pub struct Config<'a> {
+    pub assembly: &'a Path,
+    pub pattern: &'a str,
+    /// Only list symbols which no other pack references.
+    pub unused: bool
+}
+
+pub type Match = bp3d_symbols::SymbolMatch;
+
+/// Matches a symbol name against a glob pattern (`*` and `?`), or a substring if the pattern
+/// has no wildcards.
+pub fn matches(pattern: &str, name: &str) -> bool {
+    if !pattern.contains(['*', '?']) {
+        return name.contains(pattern);
+    }
+    bp3d_symbols::glob_match(pattern, name)
+}
+
+fn stages(flags: u16) -> Vec<String> {
+    [
+        (FLAG_VERTEX_STAGE, "vertex"),
+        (FLAG_HULL_STAGE, "hull"),
+        (FLAG_DOMAIN_STAGE, "domain"),
+        (FLAG_GEOMETRY_STAGE, "geometry"),
+        (FLAG_PIXEL_STAGE, "pixel")
+    ].into_iter().filter(|(flag, _)| flags & flag != 0).map(|(_, name)| name.into()).collect()
+}
+
+//Names of the symbols each pack copy of the assembly declares as external, i.e. references to
+//a symbol provided by another pack.
+fn external_symbols(copies: &[PackCopy]) -> Result<HashMap<&str, HashSet<String>>, Error> {
+    let mut externals = HashMap::new();
+    for copy in copies {
+        let pack = ShaderPack::open(Cursor::new(&copy.data))?;
+        let symbols = pack.symbols()?;
+        let mut names = HashSet::new();
+        for info in symbols.iter().filter(|v| v.flags & FLAG_EXTERNAL != 0) {
+            names.insert(symbols.load_name(info)?.into());
+        }
+        externals.insert(copy.name.as_str(), names);
+    }
+    Ok(externals)
+}
+
+pub fn run(config: Config) -> Result<Vec<Match>, Error> {
+    let pack = ShaderPack::open(BufReader::new(File::open(config.assembly)?))?;
+    let mut matches = Vec::new();
+    {
+        let symbols = pack.symbols()?;
+        for info in symbols.iter() {
+            let name: String = symbols.load_name(info)?.into();
+            if !self::matches(config.pattern, &name) {
+                continue;
+            }
+            matches.push(Match {
+                name,
+                kind: format!("{:?}", info.ty),
+                register: if info.flags & FLAG_REGISTER != 0 { Some(info.register) } else { None },
+                stages: stages(info.flags),
+                provider: None,
+                referenced_by: Vec::new()
+            });
+        }
+    }
+    let container = pack.into_inner();
+    let provenance = Provenance::read(&container)?.unwrap_or_default();
+    let copies = PackCopy::read(&container)?.unwrap_or_default();
+    let externals = external_symbols(&copies)?;
+    for m in &mut matches {
+        //Assemblies without pack copies can't tell references apart: the first pack provides.
+        let (references, providers): (Vec<_>, Vec<_>) = provenance.packs_of(&m.name).enumerate()
+            .partition(|(i, v)| match externals.get(v.name.as_str()) {
+                Some(names) => names.contains(&m.name),
+                None => copies.is_empty() && *i > 0
+            });
+        m.provider = providers.first().map(|(_, v)| v.name.clone());
+        m.referenced_by = references.into_iter().map(|(_, v)| v.name.clone()).collect();
+    }
+    if config.unused {
+        matches.retain(|v| v.referenced_by.is_empty());
+    }
+    Ok(matches)
+}
+
+/// Loads the copy of a shader pack embedded in an assembly, selected by pack or symbol name as
+/// with extract.
+pub fn locate_pack(assembly: &Path, name: &str) -> Result<Vec<u8>, Error> {
+    let container = ShaderPack::open(BufReader::new(File::open(assembly)?))?.into_inner();
+    let provenance = Provenance::read(&container)?.unwrap_or_default();
+    let name = crate::extract::resolve(&provenance, name).map_err(Error::Resolve)?;
+    PackCopy::read(&container)?.unwrap_or_default()
+        .into_iter()
+        .find(|v| v.name == name)
+        .map(|v| v.data)
+        .ok_or_else(|| Error::NoCopy(name.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::BufWriter;
+    use std::path::{Path, PathBuf};
+    use bpx::shader::ShaderPack;
+    use bpx::shader::symbol::Type;
+    use crate::{assembler, find};
+
+    //Names starting with '@' are declared external, i.e. referenced from another pack.
+    fn write_pack(path: &Path, symbols: &[(&str, Type)]) {
+        let mut pack = ShaderPack::create(BufWriter::new(File::create(path).unwrap()),
+                                          bpx::shader::Builder::new()
+                                              .ty(bpx::shader::Type::Pipeline)
+                                              .target(bpx::shader::Target::GL40));
+        {
+            let mut table = pack.symbols_mut().unwrap();
+            for (name, ty) in symbols {
+                let mut builder = bpx::shader::symbol::Builder::new(name.trim_start_matches('@'));
+                builder.ty(*ty);
+                if name.starts_with('@') {
+                    builder.external();
+                }
+                table.create(builder).unwrap();
+            }
+        }
+        pack.save().unwrap();
+    }
+
+    fn fixture(tag: &str) -> (PathBuf, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("shaderl-{}-{}", tag, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let a = dir.join("lit.bpx");
+        let b = dir.join("unlit.bpx");
+        write_pack(&a, &[("ModelView", Type::Constant), ("Lighting", Type::ConstantBuffer), ("FragColor", Type::Output)]);
+        write_pack(&b, &[("@ModelView", Type::Constant), ("FragColor", Type::Output)]);
+        let output = dir.join("assembly.bpx");
+        assembler::run(assembler::Config {
+            n_threads: 1,
+            debug: false,
+            output: &output,
+            assembly: None,
+            name: "test",
+            verify: true,
+            shaders: [a.as_path(), b.as_path()].into_iter()
+        }).unwrap();
+        (dir, output)
+    }
+
+    #[test]
+    fn find_symbols() {
+        assert!(find::matches("Model*", "ModelView"));
+        assert!(find::matches("View", "ModelView"));
+        assert!(!find::matches("?View", "ModelView"));
+        let (dir, assembly) = fixture("find");
+        let res = find::run(find::Config {
+            assembly: &assembly,
+            pattern: "ModelView",
+            unused: false
+        }).unwrap();
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].provider.as_deref(), Some("lit"));
+        assert_eq!(res[0].referenced_by, ["unlit"]);
+        let res = find::run(find::Config {
+            assembly: &assembly,
+            pattern: "*",
+            unused: true
+        }).unwrap();
+        let mut names: Vec<&str> = res.iter().map(|v| v.name.as_str()).collect();
+        names.sort();
+        //Both packs declare FragColor without referencing each other.
+        assert_eq!(names, ["FragColor", "Lighting"]);
+        //The provider is the pack declaring the symbol even when a referencing pack comes first.
+        let base = dir.join("base.bpx");
+        write_pack(&base, &[("@ModelView", Type::Constant)]);
+        let output = dir.join("base-first.bpx");
+        assembler::run(assembler::Config {
+            n_threads: 1,
+            debug: false,
+            output: &output,
+            assembly: None,
+            name: "test",
+            verify: true,
+            shaders: [base.as_path(), dir.join("lit.bpx").as_path()].into_iter()
+        }).unwrap();
+        let res = find::run(find::Config {
+            assembly: &output,
+            pattern: "ModelView",
+            unused: false
+        }).unwrap();
+        assert_eq!(res[0].provider.as_deref(), Some("lit"));
+        assert_eq!(res[0].referenced_by, ["base"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn locate_pack() {
+        let (dir, assembly) = fixture("locate");
+        assert_eq!(find::locate_pack(&assembly, "unlit").unwrap(), std::fs::read(dir.join("unlit.bpx")).unwrap());
+        assert!(matches!(find::locate_pack(&assembly, "other"), Err(find::Error::Resolve(_))));
+        //Packs are read from the assembly, the original files are not needed.
+        let lit = std::fs::read(dir.join("lit.bpx")).unwrap();
+        std::fs::remove_file(dir.join("lit.bpx")).unwrap();
+        assert_eq!(find::locate_pack(&assembly, "lit").unwrap(), lit);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
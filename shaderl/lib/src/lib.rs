@@ -29,5 +29,12 @@
 mod symbols;
 mod tree;
 mod ext_data;
+mod shared;
+mod provenance;
+mod packs;
+mod verify;
 pub mod assembler;
 pub mod linker;
+pub mod find;
+pub mod extract;
+pub mod failure;
@@ -28,7 +28,7 @@
 
 use std::fs::File;
 use std::io::BufReader;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use bp3d_threads::{ScopedThreadManager, ThreadPool};
 use bpx::shader::ShaderPack;
 use bpx::shader::symbol::{FLAG_ASSEMBLY, FLAG_EXTERNAL};
@@ -40,7 +40,9 @@ use crate::symbols::{check_signature_with_assembly, load_and_sign_symbols};
 pub struct Config<'a> {
     pub n_threads: usize,
     pub assembly: &'a Path,
-    pub shaders: Vec<&'a Path>
+    pub shaders: Vec<&'a Path>,
+    /// Relink the packs in place instead of writing `<name>.linked.bpx` next to them.
+    pub in_place: bool
 }
 
 #[derive(Debug, Error)]
@@ -49,8 +51,8 @@ pub enum Error {
     Io(std::io::Error),
     #[error("symbol error: {0}")]
     Symbol(crate::symbols::Error),
-    #[error("unresolved external symbol")]
-    Unresolved,
+    #[error("unresolved external symbol(s): {}", .0.join(", "))]
+    Unresolved(Vec<String>),
     #[error("bpx error: {0}")]
     Bpx(bpx::shader::error::Error)
 }
@@ -61,16 +63,29 @@ fn get_assembly_hash(file: &Path) -> Result<u64, Error> {
     Ok(pack.get_settings().assembly_hash)
 }
 
-fn link_single(path: &Path, new_assembly: u64) -> Result<(), Error> {
-    let mut shader = ShaderPack::open(File::options().read(true).write(true).open(path).map_err(Error::Io)?).map_err(Error::Bpx)?;
-    if shader.get_settings().assembly_hash != 0 {
+/// Returns the path of the relinked copy of a shader pack.
+pub fn linked_path(path: &Path) -> PathBuf {
+    path.with_extension("linked.bpx")
+}
+
+fn link_single(path: &Path, new_assembly: u64, in_place: bool) -> Result<(), Error> {
+    if get_assembly_hash(path)? != 0 {
         warn!("Shader pack {:?} is already linked, skipping...", path);
         return Ok(());
     }
+    let output = match in_place {
+        true => path.to_path_buf(),
+        false => {
+            let output = linked_path(path);
+            std::fs::copy(path, &output).map_err(Error::Io)?;
+            output
+        }
+    };
+    let mut shader = ShaderPack::open(File::options().read(true).write(true).open(&output).map_err(Error::Io)?).map_err(Error::Bpx)?;
     shader.set_assembly(new_assembly);
     let indices: Vec<usize> = shader.symbols().map_err(Error::Bpx)?.iter()
-        .filter(|v| v.flags & FLAG_EXTERNAL != 0)
         .enumerate()
+        .filter(|(_, v)| v.flags & FLAG_EXTERNAL != 0)
         .map(|(i, _)| i)
         .collect();
     let mut symbols = shader.symbols_mut().unwrap();
@@ -78,17 +93,18 @@ fn link_single(path: &Path, new_assembly: u64) -> Result<(), Error> {
         symbols.get_mut(index).unwrap().flags |= FLAG_ASSEMBLY
     }
     shader.save().map_err(Error::Bpx)?;
+    info!("Linked shader pack {:?} to {:?}", path, output);
     Ok(())
 }
 
-fn link(n_threads: usize, assembly: &Path, shaders: Vec<&Path>) -> Result<(), Error> {
+fn link(n_threads: usize, assembly: &Path, shaders: Vec<&Path>, in_place: bool) -> Result<(), Error> {
     let new_assembly = get_assembly_hash(assembly)?;
     crossbeam::scope(|scope| {
         let manager = ScopedThreadManager::new(scope);
         let mut pool: ThreadPool<ScopedThreadManager, Result<(), Error>> = ThreadPool::new(n_threads);
         info!("Initialized thread pool with {} max thread(s)", n_threads);
         for shader in shaders {
-            pool.send(&manager, move |_| link_single(shader, new_assembly));
+            pool.send(&manager, move |_| link_single(shader, new_assembly, in_place));
             debug!("Dispatch shader pack {:?}", shader);
         }
         pool.reduce().map(|v| v.unwrap()).collect()
@@ -106,11 +122,108 @@ pub fn run(config: Config) -> Result<(), Error> {
     info!("Checking signatures against assembly...");
     check_signature_with_assembly(&mut shader_tree, &assembly_tree).map_err(crate::symbols::Error::Signing)
         .map_err(Error::Symbol)?;
-    if !shader_tree.is_external() {
+    let unresolved = shader_tree.unresolved();
+    if !unresolved.is_empty() {
         error!("One or more external symbols were not found in shader assembly; linking cannot continue!");
-        return Err(Error::Unresolved);
+        return Err(Error::Unresolved(unresolved));
     }
     info!("Linking shaders...");
-    link(config.n_threads, config.assembly, config.shaders)?;
+    link(config.n_threads, config.assembly, config.shaders, config.in_place)?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::{BufReader, BufWriter};
+    use std::path::{Path, PathBuf};
+    use bpx::shader::ShaderPack;
+    use bpx::shader::symbol::{FLAG_ASSEMBLY, Type};
+    use crate::{assembler, linker};
+
+    fn write_symbols(path: &Path, symbols: &[(&str, Type)]) {
+        let mut pack = ShaderPack::create(BufWriter::new(File::create(path).unwrap()),
+                                          bpx::shader::Builder::new()
+                                              .ty(bpx::shader::Type::Pipeline)
+                                              .target(bpx::shader::Target::GL42));
+        {
+            let mut table = pack.symbols_mut().unwrap();
+            for (name, ty) in symbols {
+                let mut builder = bpx::shader::symbol::Builder::new(*name);
+                builder.ty(*ty).external();
+                table.create(builder).unwrap();
+            }
+        }
+        pack.save().unwrap();
+    }
+
+    fn setup(dir: &Path) -> (PathBuf, PathBuf, PathBuf) {
+        std::fs::create_dir_all(dir).unwrap();
+        let base = dir.join("base.bpx");
+        let assembly = dir.join("assembly.bpx");
+        let good = dir.join("good.bpx");
+        let bad = dir.join("bad.bpx");
+        write_symbols(&base, &[("ModelView", Type::Constant), ("Albedo", Type::Texture)]);
+        write_symbols(&good, &[("ModelView", Type::Constant)]);
+        write_symbols(&bad, &[("ModelView", Type::Constant), ("Missing", Type::Texture)]);
+        assembler::run(assembler::Config {
+            n_threads: 1,
+            debug: false,
+            output: &assembly,
+            assembly: None,
+            name: "test",
+            verify: true,
+            shaders: [&*base].into_iter()
+        }).unwrap();
+        (assembly, good, bad)
+    }
+
+    fn link(assembly: &Path, shaders: Vec<&Path>, in_place: bool) -> Result<(), linker::Error> {
+        linker::run(linker::Config {
+            n_threads: 2,
+            assembly,
+            shaders,
+            in_place
+        })
+    }
+
+    //Returns the assembly hash of a pack and whether all its symbols are flagged ASSEMBLY.
+    fn read_pack(path: &Path) -> (u64, bool) {
+        let pack = ShaderPack::open(BufReader::new(File::open(path).unwrap())).unwrap();
+        let linked = pack.symbols().unwrap().iter().all(|v| v.flags & FLAG_ASSEMBLY != 0);
+        (pack.get_settings().assembly_hash, linked)
+    }
+
+    #[test]
+    fn link_next_to_original() {
+        let dir = std::env::temp_dir().join(format!("shaderl-link-{}", std::process::id()));
+        let (assembly, good, _) = setup(&dir);
+        link(&assembly, vec![&good], false).unwrap();
+        let expected = linker::get_assembly_hash(&assembly).unwrap();
+        assert_ne!(expected, 0);
+        assert_eq!(read_pack(&linker::linked_path(&good)), (expected, true));
+        assert_eq!(read_pack(&good), (0, false));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn link_in_place() {
+        let dir = std::env::temp_dir().join(format!("shaderl-link-in-place-{}", std::process::id()));
+        let (assembly, good, _) = setup(&dir);
+        link(&assembly, vec![&good], true).unwrap();
+        assert_eq!(read_pack(&good).0, linker::get_assembly_hash(&assembly).unwrap());
+        assert!(!linker::linked_path(&good).exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn report_unresolved() {
+        let dir = std::env::temp_dir().join(format!("shaderl-link-unresolved-{}", std::process::id()));
+        let (assembly, good, bad) = setup(&dir);
+        let err = link(&assembly, vec![&good, &bad], false).unwrap_err();
+        assert_eq!(err.to_string(), "unresolved external symbol(s): Missing");
+        assert!(!linker::linked_path(&good).exists());
+        assert!(!linker::linked_path(&bad).exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
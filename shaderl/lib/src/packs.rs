@@ -0,0 +1,107 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use bpx::shader::ShaderPack;
+use bp3d_threads::{ScopedThreadManager, ThreadPool};
+use log::{debug, error, info};
+use thiserror::Error;
+use crate::provenance::PackInfo;
+use crate::shared::{load_shared_buffers, SharedBuffer};
+use crate::symbols::{load_symbols_single, LoadError};
+use crate::tree::Symbol;
+
+/// Everything the assembler reads from a single shader pack.
+pub struct LoadedPack {
+    pub target: bpx::shader::Target,
+    pub ty: bpx::shader::Type,
+    pub assembly_hash: u64,
+    pub info: PackInfo,
+    /// Content of the file, embedded in the assembly.
+    pub data: Vec<u8>,
+    pub shared: Vec<(String, SharedBuffer)>,
+    pub symbols: Vec<Symbol>
+}
+
+#[derive(Debug, Error)]
+#[error("{}: {}", .0.display(), .1)]
+pub struct PackError(pub PathBuf, pub LoadError);
+
+fn load_pack(shader: &Path) -> Result<LoadedPack, LoadError> {
+    let pack = ShaderPack::open(BufReader::new(File::open(shader)?))?;
+    let settings = pack.get_settings();
+    Ok(LoadedPack {
+        target: settings.target,
+        ty: settings.ty,
+        assembly_hash: settings.assembly_hash,
+        info: PackInfo::load(shader)?,
+        data: std::fs::read(shader)?,
+        shared: load_shared_buffers(shader)?,
+        symbols: load_symbols_single(shader)?
+    })
+}
+
+/// Reads and validates all given shader packs in parallel.
+///
+/// Packs are returned in the order of `shaders` regardless of completion order, and every pack
+/// which failed to load is reported instead of only the first one.
+pub fn load_packs(n_threads: usize, shaders: &[&Path]) -> Result<Vec<LoadedPack>, Vec<PackError>> {
+    let mut results: Vec<(usize, Result<LoadedPack, LoadError>)> = crossbeam::scope(|scope| {
+        let manager = ScopedThreadManager::new(scope);
+        let mut pool: ThreadPool<ScopedThreadManager, (usize, Result<LoadedPack, LoadError>)> = ThreadPool::new(n_threads);
+        info!("Initialized thread pool with {} max thread(s)", n_threads);
+        for shader in shaders {
+            pool.send(&manager, move |index| {
+                let res = load_pack(shader);
+                match &res {
+                    Ok(pack) => info!("Loaded shader pack {:?} ({} symbol(s))", shader, pack.symbols.len()),
+                    Err(e) => error!("Failed to load shader pack {:?}: {}", shader, e)
+                }
+                (index, res)
+            });
+            debug!("Dispatch shader pack {:?}", shader);
+        }
+        pool.reduce().map(|v| v.unwrap()).collect()
+    }).unwrap();
+    results.sort_by_key(|(index, _)| *index);
+    let mut packs = Vec::with_capacity(results.len());
+    let mut errors = Vec::new();
+    for (index, res) in results {
+        match res {
+            Ok(pack) => packs.push(pack),
+            Err(e) => errors.push(PackError(shaders[index].into(), e))
+        }
+    }
+    if errors.is_empty() {
+        Ok(packs)
+    } else {
+        Err(errors)
+    }
+}
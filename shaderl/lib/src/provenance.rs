@@ -0,0 +1,152 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Provenance of the symbols of an assembly: which shader pack contributed which symbols.
+
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, Write};
+use std::path::Path;
+use bpx::core::builder::{CompressionMethod, SectionHeaderBuilder};
+use bpx::core::Container;
+use bpx::shader::ShaderPack;
+use bpx::shader::symbol::FLAG_INTERNAL;
+use serde::{Deserialize, Serialize};
+use crate::symbols::LoadError;
+use thiserror::Error;
+
+/// BPX section type holding the provenance table of an assembly.
+pub const SECTION_TYPE: u8 = 0xFC;
+
+/// BPX section type holding a copy of each shader pack of an assembly.
+pub const COPY_SECTION_TYPE: u8 = 0xF7;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("io error: {0}")]
+    Io(std::io::Error),
+    #[error("BPX core error: {0}")]
+    Core(bpx::core::error::Error),
+    #[error("section open error: {0}")]
+    SectionOpen(bpx::core::error::OpenError),
+    #[error("provenance encoding error: {0}")]
+    Serde(bincode::Error)
+}
+
+bpx::impl_err_conversion!(
+    Error {
+        std::io::Error => Io,
+        bpx::core::error::Error => Core,
+        bpx::core::error::OpenError => SectionOpen,
+        bincode::Error => Serde
+    }
+);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PackInfo {
+    pub name: String,
+    pub symbols: Vec<String>
+}
+
+impl PackInfo {
+    pub fn load(shader: &Path) -> Result<PackInfo, LoadError> {
+        let pack = ShaderPack::open(BufReader::new(File::open(shader)?))?;
+        let symbols = pack.symbols()?;
+        let mut names = Vec::new();
+        for info in symbols.iter() {
+            //Mirror symbol loading: internal symbols of pipelines never reach the assembly.
+            if matches!(pack.get_settings().ty, bpx::shader::Type::Pipeline) && info.flags & FLAG_INTERNAL != 0 {
+                continue;
+            }
+            let name: String = symbols.load_name(info)?.into();
+            names.push(name);
+        }
+        Ok(PackInfo {
+            name: shader.file_stem().unwrap_or(shader.as_os_str()).to_string_lossy().into(),
+            symbols: names
+        })
+    }
+}
+
+/// Content of a shader pack file as it was given to the assembler.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PackCopy {
+    pub name: String,
+    pub data: Vec<u8>
+}
+
+impl PackCopy {
+    pub fn write<T: Write + Seek>(copies: &[PackCopy], container: &mut Container<T>) -> Result<(), Error> {
+        let handle = container.sections_mut().create(SectionHeaderBuilder::new()
+            .ty(COPY_SECTION_TYPE)
+            .compression(CompressionMethod::Zlib));
+        let mut writer = container.sections().open(handle)?;
+        writer.write_all(&bincode::serialize(copies)?)?;
+        Ok(())
+    }
+
+    /// Reads the shader pack copies of an assembly; None if the assembly was built without them.
+    pub fn read<T: Read + Seek>(container: &Container<T>) -> Result<Option<Vec<PackCopy>>, Error> {
+        let handle = match container.sections().find_by_type(COPY_SECTION_TYPE) {
+            Some(v) => v,
+            None => return Ok(None)
+        };
+        let mut data = Vec::new();
+        container.sections().load(handle)?.read_to_end(&mut data)?;
+        Ok(Some(bincode::deserialize(&data)?))
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Provenance {
+    pub packs: Vec<PackInfo>
+}
+
+impl Provenance {
+    pub fn write<T: Write + Seek>(&self, container: &mut Container<T>) -> Result<(), Error> {
+        let handle = container.sections_mut().create(SectionHeaderBuilder::new().ty(SECTION_TYPE));
+        let mut writer = container.sections().open(handle)?;
+        writer.write_all(&bincode::serialize(self)?)?;
+        Ok(())
+    }
+
+    /// Reads the provenance table of an assembly; None if the assembly was built without one.
+    pub fn read<T: Read + Seek>(container: &Container<T>) -> Result<Option<Provenance>, Error> {
+        let handle = match container.sections().find_by_type(SECTION_TYPE) {
+            Some(v) => v,
+            None => return Ok(None)
+        };
+        let mut data = Vec::new();
+        container.sections().load(handle)?.read_to_end(&mut data)?;
+        Ok(Some(bincode::deserialize(&data)?))
+    }
+
+    /// Returns the packs which contain the given symbol, the first one being the provider.
+    pub fn packs_of<'a>(&'a self, symbol: &'a str) -> impl Iterator<Item = &'a PackInfo> + 'a {
+        self.packs.iter().filter(move |v| v.symbols.iter().any(|v| v == symbol))
+    }
+}
@@ -0,0 +1,197 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use bpx::shader::ShaderPack;
+use bpx::shader::symbol::{FLAG_REGISTER, Type};
+use log::{debug, error};
+use bp3d_symbols::{ArrayItemType, FromBpx, PropType, StructObject};
+use thiserror::Error;
+use crate::symbols::LoadError;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("load error: {0}")]
+    Load(LoadError),
+    #[error("shared constant buffers disagree across packs: {}", .0.join(", "))]
+    Mismatch(Vec<String>)
+}
+
+bpx::impl_err_conversion!(
+    Error {
+        LoadError => Load
+    }
+);
+
+/// A shared constant buffer as seen by a single shader pack.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SharedBuffer {
+    pub pack: String,
+    pub register: Option<u8>,
+    pub size: u32,
+    pub layout: Vec<String> // One entry per property: "name: type @ offset".
+}
+
+impl SharedBuffer {
+    fn agrees_with(&self, other: &SharedBuffer) -> bool {
+        self.register == other.register && self.size == other.size && self.layout == other.layout
+    }
+}
+
+fn describe_prop_type(ty: &PropType, names: &[String]) -> String {
+    let lookup = |v: u16| names.get(v as usize).cloned().unwrap_or_else(|| format!("<broken ref {}>", v));
+    match ty {
+        PropType::Scalar(v) => format!("{:?}", v),
+        PropType::Vector(v) => format!("Vector{}<{:?}>", v.size, v.item),
+        PropType::Matrix(v) => format!("Matrix{}<{:?}>", v.size, v.item),
+        PropType::StructRef(v) => lookup(*v),
+        PropType::Array { size, ty } => {
+            let item = match ty {
+                ArrayItemType::Vector(v) => format!("Vector{}<{:?}>", v.size, v.item),
+                ArrayItemType::Matrix(v) => format!("Matrix{}<{:?}>", v.size, v.item),
+                ArrayItemType::StructRef(v) => lookup(*v)
+            };
+            format!("[{}; {}]", item, size)
+        }
+    }
+}
+
+/// Loads all constant buffers flagged shared in the given shader pack.
+pub fn load_shared_buffers(shader: &Path) -> Result<Vec<(String, SharedBuffer)>, LoadError> {
+    debug!("Loading shared constant buffers for shader pack {:?}...", shader);
+    let file = BufReader::new(File::open(shader)?);
+    let shaderpack = ShaderPack::open(file)?;
+    let symbols = shaderpack.symbols()?;
+    let mut names = Vec::new();
+    for info in symbols.iter() {
+        let name: String = symbols.load_name(info)?.into();
+        names.push(name);
+    }
+    let mut buffers = Vec::new();
+    for (index, info) in symbols.iter().enumerate() {
+        if !matches!(info.ty, Type::ConstantBuffer) {
+            continue;
+        }
+        let val = symbols.load_extended_data(info)?;
+        if val.is_null() {
+            continue;
+        }
+        let obj = StructObject::from_bpx(val)?;
+        if !obj.shared {
+            continue;
+        }
+        buffers.push((names[index].clone(), SharedBuffer {
+            pack: shader.to_string_lossy().into(),
+            register: if info.flags & FLAG_REGISTER != 0 { Some(info.register) } else { None },
+            size: obj.size,
+            layout: obj.props.iter()
+                .map(|v| format!("{}: {} @ {}", v.name, describe_prop_type(&v.ty, &names), v.offset))
+                .collect()
+        }));
+    }
+    Ok(buffers)
+}
+
+fn log_diff(name: &str, first: &SharedBuffer, other: &SharedBuffer) {
+    error!("Shared constant buffer '{}' differs between {} and {}", name, first.pack, other.pack);
+    if first.register != other.register {
+        error!("  slot: {:?} != {:?}", first.register, other.register);
+    }
+    if first.size != other.size {
+        error!("  size: {} != {}", first.size, other.size);
+    }
+    let len = first.layout.len().max(other.layout.len());
+    for i in 0..len {
+        let a = first.layout.get(i).map(|v| v.as_str()).unwrap_or("<none>");
+        let b = other.layout.get(i).map(|v| v.as_str()).unwrap_or("<none>");
+        if a != b {
+            error!("  - {}", a);
+            error!("  + {}", b);
+        }
+    }
+}
+
+/// Checks that all packs defining the same shared constant buffer agree on its slot and layout.
+pub fn check_shared_buffers(buffers: impl IntoIterator<Item = (String, SharedBuffer)>) -> Result<(), Error> {
+    let mut by_name: BTreeMap<String, Vec<SharedBuffer>> = BTreeMap::new();
+    for (name, buffer) in buffers {
+        by_name.entry(name).or_default().push(buffer);
+    }
+    let mut mismatches = Vec::new();
+    for (name, list) in &by_name {
+        let first = &list[0];
+        let mut ok = true;
+        for other in &list[1..] {
+            if !first.agrees_with(other) {
+                log_diff(name, first, other);
+                ok = false;
+            }
+        }
+        if !ok {
+            mismatches.push(name.clone());
+        }
+    }
+    if mismatches.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::Mismatch(mismatches))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::shared::{check_shared_buffers, Error, SharedBuffer};
+
+    fn camera(pack: &str, register: u8, layout: &[&str]) -> (String, SharedBuffer) {
+        ("Camera".into(), SharedBuffer {
+            pack: pack.into(),
+            register: Some(register),
+            size: 16 * layout.len() as u32,
+            layout: layout.iter().map(|v| String::from(*v)).collect()
+        })
+    }
+
+    #[test]
+    fn shared_buffers_agree() {
+        let layout = ["View: Matrix4<Float> @ 0", "Projection: Matrix4<Float> @ 64"];
+        let buffers = vec![camera("a.bpx", 9, &layout), camera("b.bpx", 9, &layout)];
+        assert!(check_shared_buffers(buffers).is_ok());
+    }
+
+    #[test]
+    fn shared_buffers_mismatch() {
+        let layout = ["View: Matrix4<Float> @ 0", "Projection: Matrix4<Float> @ 64"];
+        let buffers = vec![camera("a.bpx", 9, &layout), camera("b.bpx", 10, &layout)];
+        assert!(matches!(check_shared_buffers(buffers), Err(Error::Mismatch(v)) if v == ["Camera"]));
+        let buffers = vec![camera("a.bpx", 9, &layout), camera("b.bpx", 9, &layout[..1])];
+        assert!(matches!(check_shared_buffers(buffers), Err(Error::Mismatch(_))));
+    }
+}
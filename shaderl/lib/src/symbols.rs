@@ -34,8 +34,8 @@ use bpx::shader::ShaderPack;
 use bpx::shader::symbol::{FLAG_EXTERNAL, FLAG_INTERNAL, Type};
 use log::{debug, error, info};
 use sha2::Sha512;
-use bp3d_symbols::{ConstantObject, OutputObject, PipelineObject, StructObject, TextureObject};
-use bp3d_symbols::FromBpx;
+use bp3d_symbols::{ConstantObject, OutputObject, PipelineObject, SamplerObject, StructObject, TextureObject};
+use bp3d_symbols::{FromBpx, FromBpxError};
 use sha2::Digest;
 use crate::ext_data::IntoExtData;
 use crate::tree::{Symbol, SymbolTree};
@@ -48,14 +48,17 @@ pub enum LoadError {
     #[error("BPX error: {0}")]
     Bpx(bpx::shader::error::Error),
     #[error("BPX deserialization error: {0}")]
-    Serde(bpx::sd::serde::Error)
+    Serde(bpx::sd::serde::Error),
+    #[error("extended data error: {0}")]
+    ExtData(FromBpxError)
 }
 
 bpx::impl_err_conversion!(
     LoadError {
         std::io::Error => Io,
         bpx::shader::error::Error => Bpx,
-        bpx::sd::serde::Error => Serde
+        bpx::sd::serde::Error => Serde,
+        FromBpxError => ExtData
     }
 );
 
@@ -88,7 +91,7 @@ pub enum Error {
     Signing(SigningError)
 }
 
-fn load_symbols_single(shader: &Path) -> Result<Vec<Symbol>, LoadError>
+pub fn load_symbols_single(shader: &Path) -> Result<Vec<Symbol>, LoadError>
 {
     debug!("Loading symbols for shader pack {:?}...", shader);
     let mut syms = Vec::new();
@@ -113,7 +116,7 @@ fn load_symbols_single(shader: &Path) -> Result<Vec<Symbol>, LoadError>
         if !val.is_null() {
             ext_data = match info.ty {
                 Type::Texture => Some(TextureObject::from_bpx(val)?.into_ext_data()),
-                Type::Sampler => None,
+                Type::Sampler => Some(SamplerObject::from_bpx(val)?.into_ext_data()),
                 Type::ConstantBuffer => Some(StructObject::from_bpx(val)?.into_ext_data()),
                 Type::Constant => Some(ConstantObject::from_bpx(val)?.into_ext_data()),
                 Type::VertexFormat => Some(StructObject::from_bpx(val)?.into_ext_data()),
@@ -146,11 +149,11 @@ pub fn load_symbols<'a>(n_threads: usize, shaders: impl Iterator<Item = &'a Path
 // (they must be paired with signatures of referenced symbols to be complete)
 fn pre_hash(n_threads: usize, syms: Vec<Symbol>) -> Result<Vec<Symbol>, SigningError> {
     let manager = UnscopedThreadManager::new();
-    let mut pool: ThreadPool<UnscopedThreadManager, Result<Symbol, SigningError>> = ThreadPool::new(n_threads);
+    let mut pool: ThreadPool<UnscopedThreadManager, Result<(usize, Symbol), SigningError>> = ThreadPool::new(n_threads);
     info!("Initialized thread pool with {} max thread(s)", n_threads);
     for mut sym in syms {
         debug!("Dispatch symbol {}", sym.name());
-        pool.send(&manager, |_| {
+        pool.send(&manager, |index| {
             let (a, b) = sym.get_coded_info();
             let mut v: Vec<u8> = vec![a, b];
             let refs = sym.ext_data().map(|v| v.refs()).unwrap_or(&[]);
@@ -167,13 +170,16 @@ fn pre_hash(n_threads: usize, syms: Vec<Symbol>) -> Result<Vec<Symbol>, SigningE
             let hash = sha.finalize();
             //let array: [u8; 64] = hash.into();
             sym.set_signature(hash.into());
-            Ok(sym)
+            Ok((index, sym))
         });
     }
-    pool.reduce().map(|v| v.unwrap()).collect()
+    //Keep the input order so that the resulting tree does not depend on thread scheduling.
+    let mut hashed: Vec<(usize, Symbol)> = pool.reduce().map(|v| v.unwrap()).collect::<Result<_, _>>()?;
+    hashed.sort_by_key(|(index, _)| *index);
+    Ok(hashed.into_iter().map(|(_, sym)| sym).collect())
 }
 
-fn sign_symbols(n_threads: usize, symbols: Vec<Symbol>) -> Result<SymbolTree, SigningError>
+pub fn sign_symbols(n_threads: usize, symbols: Vec<Symbol>) -> Result<SymbolTree, SigningError>
 {
     let mut tree = SymbolTree::empty();
     let mut hashed = pre_hash(n_threads, symbols)?;
@@ -132,6 +132,16 @@ impl SymbolTree {
         self.symbols.iter().find(|v| v.info.flags & FLAG_INTERNAL != 0).is_none()
     }
 
+    /// Returns the sorted names of all symbols which are still flagged INTERNAL.
+    pub fn unresolved(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.symbols.iter()
+            .filter(|v| v.info.flags & FLAG_INTERNAL != 0)
+            .map(|v| v.name().into())
+            .collect();
+        names.sort();
+        names
+    }
+
     //Mass set FLAG_INTERNAL on all symbols.
     pub fn mass_set_internal(&mut self) {
         self.iter_mut().for_each(|v| {
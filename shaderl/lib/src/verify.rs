@@ -0,0 +1,147 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Compatibility checks run on the input shader packs before assembling them.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use bpx::shader::symbol::{FLAG_ASSEMBLY, FLAG_REGISTER};
+use thiserror::Error;
+use crate::packs::LoadedPack;
+
+#[derive(Debug, Error)]
+pub enum Issue {
+    #[error("'{}' targets {:?}, but '{}' targets {:?}", .pack.display(), .target, .first.display(), .expected)]
+    MixedTarget {
+        pack: PathBuf,
+        target: bpx::shader::Target,
+        first: PathBuf,
+        expected: bpx::shader::Target
+    },
+    #[error("'{}' is of type {:?}, but '{}' is of type {:?}", .pack.display(), .ty, .first.display(), .expected)]
+    MixedType {
+        pack: PathBuf,
+        ty: bpx::shader::Type,
+        first: PathBuf,
+        expected: bpx::shader::Type
+    },
+    #[error("symbol '{symbol}' is exported with conflicting definitions by {}", display_list(.packs))]
+    ConflictingSymbol {
+        symbol: String,
+        packs: Vec<PathBuf>
+    },
+    #[error("'{}' references symbol(s) {} of assembly {:#X}, but no parent assembly was given", .pack.display(), .symbols.join(", "), .hash)]
+    MissingAssembly {
+        pack: PathBuf,
+        hash: u64,
+        symbols: Vec<String>
+    },
+    #[error("'{}' references symbol(s) {} of assembly {:#X}, but the parent assembly is {:#X}", .pack.display(), .symbols.join(", "), .hash, .expected)]
+    WrongAssembly {
+        pack: PathBuf,
+        hash: u64,
+        expected: u64,
+        symbols: Vec<String>
+    }
+}
+
+fn display_list(packs: &[PathBuf]) -> String {
+    packs.iter().map(|v| format!("'{}'", v.display())).collect::<Vec<_>>().join(", ")
+}
+
+/// Checks that the given shader packs can be assembled together.
+///
+/// `parent` is the assembly hash of the parent assembly, if any. Every issue is reported instead
+/// of only the first one. Symbols exported by multiple packs with the same definition are not
+/// considered conflicts as they are merged when assembling.
+pub fn verify_packs(shaders: &[&Path], packs: &[LoadedPack], parent: Option<u64>) -> Result<(), Vec<Issue>> {
+    let mut issues = Vec::new();
+    let first = match packs.first() {
+        Some(v) => v,
+        None => return Ok(())
+    };
+    for (path, pack) in shaders.iter().zip(packs).skip(1) {
+        if pack.target != first.target {
+            issues.push(Issue::MixedTarget {
+                pack: path.into(),
+                target: pack.target,
+                first: shaders[0].into(),
+                expected: first.target
+            });
+        }
+        if pack.ty != first.ty {
+            issues.push(Issue::MixedType {
+                pack: path.into(),
+                ty: pack.ty,
+                first: shaders[0].into(),
+                expected: first.ty
+            });
+        }
+    }
+    //Use a BTreeMap so that conflicts are reported in a stable order.
+    let mut definitions: BTreeMap<&str, Vec<(&Path, bpx::shader::symbol::Type, Option<u8>)>> = BTreeMap::new();
+    for (path, pack) in shaders.iter().zip(packs) {
+        for sym in &pack.symbols {
+            let info = sym.info();
+            let register = (info.flags & FLAG_REGISTER != 0).then(|| info.register);
+            definitions.entry(sym.name()).or_default().push((*path, info.ty, register));
+        }
+    }
+    for (symbol, defs) in definitions {
+        let (_, ty, register) = defs[0];
+        if defs.iter().any(|(_, ty1, register1)| *ty1 != ty || *register1 != register) {
+            let mut packs: Vec<PathBuf> = defs.iter().map(|(path, _, _)| path.to_path_buf()).collect();
+            packs.dedup();
+            issues.push(Issue::ConflictingSymbol { symbol: symbol.into(), packs });
+        }
+    }
+    for (path, pack) in shaders.iter().zip(packs) {
+        let symbols: Vec<String> = pack.symbols.iter()
+            .filter(|v| v.info().flags & FLAG_ASSEMBLY != 0)
+            .map(|v| format!("'{}'", v.name()))
+            .collect();
+        if symbols.is_empty() {
+            continue;
+        }
+        match parent {
+            None => issues.push(Issue::MissingAssembly { pack: path.into(), hash: pack.assembly_hash, symbols }),
+            Some(expected) if expected != pack.assembly_hash => issues.push(Issue::WrongAssembly {
+                pack: path.into(),
+                hash: pack.assembly_hash,
+                expected,
+                symbols
+            }),
+            _ => ()
+        }
+    }
+    if issues.is_empty() {
+        Ok(())
+    } else {
+        Err(issues)
+    }
+}
@@ -26,16 +26,68 @@
 // NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
 // SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
-use std::path::Path;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
 use clap::{Arg, ArgMatches, Command};
-use bp3d_shaderl::{assembler, linker};
-use log::{error, info};
-use cli_common::{alloc_verbosity_level, get_out_path, init_bp3d_logger};
+use bp3d_shaderl::{assembler, extract, find, linker};
+use bp3d_shaderl::failure::FailureKind;
+use log::info;
+use cli_common::{alloc_verbosity_level, expand_response_files, get_out_path, init_bp3d_logger, run_cli, Category, Report, ResponseError};
 
 const PROG_NAME: &str = env!("CARGO_PKG_NAME");
 const PROG_VERSION: &str = env!("CARGO_PKG_VERSION");
 
-fn assemble(n_threads: usize, args: &ArgMatches) -> i32 {
+#[derive(Debug)]
+enum Error {
+    Assemble(assembler::Error),
+    Link(linker::Error),
+    Find(find::Error),
+    Extract(extract::Error),
+    Json(serde_json::Error),
+    Sign(PathBuf, String),
+    Verify(PathBuf, String),
+    Unsupported(&'static str),
+    Response(ResponseError)
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Assemble(e) => write!(f, "{}", e),
+            Error::Link(e) => write!(f, "{}", e),
+            Error::Find(e) => write!(f, "{}", e),
+            Error::Extract(e) => write!(f, "{}", e),
+            Error::Json(e) => write!(f, "json error: {}", e),
+            Error::Sign(path, e) => write!(f, "failed to sign {:?}: {}", path, e),
+            Error::Verify(path, e) => write!(f, "signature verification of {:?} failed: {}", path, e),
+            Error::Unsupported(v) => write!(f, "this build of shaderl does not support {}", v),
+            Error::Response(e) => write!(f, "{}", e)
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl Report for Error {
+    fn category(&self) -> Category {
+        let kind = match self {
+            Error::Assemble(e) => e.kind(),
+            Error::Link(e) => e.kind(),
+            Error::Find(e) => e.kind(),
+            Error::Extract(e) => e.kind(),
+            Error::Unsupported(_) => return Category::Usage,
+            Error::Response(e) => return e.category(),
+            Error::Json(_) | Error::Sign(_, _) | Error::Verify(_, _) => return Category::Other
+        };
+        match kind {
+            FailureKind::Io => Category::Io,
+            FailureKind::Input => Category::Input,
+            FailureKind::Format => Category::Format
+        }
+    }
+}
+
+fn assemble(n_threads: usize, args: &ArgMatches) -> Result<(), Error> {
     let debug = args.is_present("debug");
     let output = get_out_path(args.value_of_os("output"));
     let assembly = args.value_of_os("assembly").map(Path::new);
@@ -49,17 +101,51 @@ fn assemble(n_threads: usize, args: &ArgMatches) -> i32 {
         output: &output,
         assembly,
         name,
+        verify: !args.is_present("no_verify"),
         shaders
     };
-    if let Err(e) = assembler::run(cfg) {
-        error!("{}", e);
-        1
-    } else {
-        0
+    assembler::run(cfg).map_err(Error::Assemble)?;
+    sign(args, &output)
+}
+
+#[cfg(feature = "signing")]
+fn sign(args: &ArgMatches, output: &Path) -> Result<(), Error> {
+    let key = match args.value_of_os("sign_key") {
+        Some(v) => Path::new(v),
+        None => return Ok(())
+    };
+    let fingerprint = bp3d_symbols::load_signing_key(key).map_err(|e| e.to_string())
+        .and_then(|key| bp3d_symbols::sign_file(output, &key).map_err(|e| e.to_string()))
+        .map_err(|e| Error::Sign(output.into(), e))?;
+    info!("Signed {:?} with key {}", output, fingerprint);
+    Ok(())
+}
+
+#[cfg(not(feature = "signing"))]
+fn sign(args: &ArgMatches, _: &Path) -> Result<(), Error> {
+    if !args.is_present("sign_key") {
+        return Ok(());
     }
+    Err(Error::Unsupported("signing (signing feature)"))
+}
+
+#[cfg(feature = "signing")]
+fn verify(args: &ArgMatches) -> Result<(), Error> {
+    let key = Path::new(args.value_of_os("pubkey").unwrap());
+    let file = Path::new(args.value_of_os("file").unwrap());
+    let fingerprint = bp3d_symbols::load_verifying_key(key).map_err(|e| e.to_string())
+        .and_then(|key| bp3d_symbols::verify_file(file, &key).map_err(|e| e.to_string()))
+        .map_err(|e| Error::Verify(file.into(), e))?;
+    info!("Valid signature from key {}", fingerprint);
+    Ok(())
+}
+
+#[cfg(not(feature = "signing"))]
+fn verify(_: &ArgMatches) -> Result<(), Error> {
+    Err(Error::Unsupported("signature verification (signing feature)"))
 }
 
-fn link(n_threads: usize, args: &ArgMatches) -> i32 {
+fn link(n_threads: usize, args: &ArgMatches) -> Result<(), Error> {
     let assembly = args.value_of_os("assembly").map(Path::new).unwrap();
     let shaders = args.values_of_os("shader")
         .unwrap_or_default()
@@ -67,21 +153,61 @@ fn link(n_threads: usize, args: &ArgMatches) -> i32 {
     let cfg = linker::Config {
         n_threads,
         assembly,
-        shaders: shaders.collect()
+        shaders: shaders.collect(),
+        in_place: args.is_present("in_place")
+    };
+    linker::run(cfg).map_err(Error::Link)
+}
+
+fn find(args: &ArgMatches) -> Result<(), Error> {
+    let cfg = find::Config {
+        assembly: args.value_of_os("assembly").map(Path::new).unwrap(),
+        pattern: args.value_of("pattern").unwrap_or("*"),
+        unused: args.is_present("unused")
     };
-    if let Err(e) = linker::run(cfg) {
-        error!("{}", e);
-        1
-    } else {
-        0
+    let matches = find::run(cfg).map_err(Error::Find)?;
+    if args.is_present("json") {
+        let json = serde_json::to_string_pretty(&bp3d_symbols::SymbolSearch::new(matches)).map_err(Error::Json)?;
+        println!("{}", json);
+        return Ok(());
+    }
+    for m in &matches {
+        print!("{} ({})", m.name, m.kind);
+        if let Some(register) = m.register {
+            print!(" register {}", register);
+        }
+        if !m.stages.is_empty() {
+            print!(" [{}]", m.stages.join(", "));
+        }
+        println!();
+        println!("    provided by: {}", m.provider.as_deref().unwrap_or("<unknown>"));
+        if m.referenced_by.is_empty() {
+            println!("    not referenced by any other pack");
+        } else {
+            println!("    referenced by: {}", m.referenced_by.join(", "));
+        }
     }
+    Ok(())
+}
+
+fn extract(args: &ArgMatches) -> Result<(), Error> {
+    let cfg = extract::Config {
+        assembly: args.value_of_os("assembly").map(Path::new).unwrap(),
+        name: args.value_of("name").unwrap(),
+        output: args.value_of_os("output").map(Path::new)
+    };
+    let output = extract::run(cfg).map_err(Error::Extract)?;
+    println!("{}", output.display());
+    Ok(())
 }
 
-fn run() -> i32 {
+fn run() -> Result<(), Error> {
+    let args = expand_response_files(std::env::args_os()).map_err(Error::Response)?;
     let matches = Command::new(PROG_NAME)
         .author("BlockProject 3D")
         .about("BlockProject 3D SDK - Shader Linker")
         .version(PROG_VERSION)
+        .after_help("Arguments of the form @<file> are replaced by the arguments listed in <file>, one per line.")
         .subcommand_required(true)
         .subcommands([
             Command::new("link").about("Link shader pack(s) to a shader assembly")
@@ -90,7 +216,9 @@ fn run() -> i32 {
                         .takes_value(true).allow_invalid_utf8(true)
                         .help("Path of the shader assembly to link to"),
                     Arg::new("shader").multiple_values(true).allow_invalid_utf8(true)
-                        .help("List of shader pack(s) to link")
+                        .help("List of shader pack(s) to link"),
+                    Arg::new("in_place").long("in-place")
+                        .help("Relink the shader pack(s) in place instead of writing <name>.linked.bpx next to them")
                 ]),
             Command::new("assemble").about("Assemble a shader assembly from shader pack(s)")
                 .args([
@@ -104,8 +232,42 @@ fn run() -> i32 {
                         .allow_invalid_utf8(true).help("Output shader assembly file name"),
                     Arg::new("debug").short('d').long("debug")
                         .help("Build the shader assembly with debug info"),
+                    Arg::new("no_verify").long("no-verify")
+                        .help("Skip the shader pack compatibility checks (target, type, conflicting symbols and parent assembly)"),
+                    Arg::new("sign_key").long("sign-key").takes_value(true).allow_invalid_utf8(true)
+                        .help("Sign the shader assembly with an Ed25519 private key (raw 32 bytes or PEM)"),
                     Arg::new("shader").multiple_values(true).allow_invalid_utf8(true)
                         .help("List of shader pack(s) to assemble")
+                ]),
+            Command::new("find").about("Search symbols in a shader assembly")
+                .args([
+                    Arg::new("assembly").required(true).short('a').long("assembly")
+                        .takes_value(true).allow_invalid_utf8(true)
+                        .help("Path of the shader assembly to search"),
+                    Arg::new("json").long("json")
+                        .help("Print results as JSON"),
+                    Arg::new("unused").long("unused")
+                        .help("Only list symbols which are not referenced by any other pack"),
+                    Arg::new("pattern")
+                        .help("Glob pattern (* and ?) or substring to match against symbol names")
+                ]),
+            Command::new("extract").about("Extract a shader pack from a shader assembly")
+                .args([
+                    Arg::new("assembly").required(true).short('a').long("assembly")
+                        .takes_value(true).allow_invalid_utf8(true)
+                        .help("Path of the shader assembly to extract from"),
+                    Arg::new("name").required(true).short('n').long("name").takes_value(true)
+                        .help("Name of the shader pack, or of a symbol to extract the shader pack providing it"),
+                    Arg::new("output").short('o').long("output").takes_value(true)
+                        .allow_invalid_utf8(true).help("Output shader pack file name (default: <pack>.bpx)")
+                ]),
+            Command::new("verify").about("Verify the signature of a shader assembly or shader pack")
+                .args([
+                    Arg::new("pubkey").required(true).short('k').long("pubkey")
+                        .takes_value(true).allow_invalid_utf8(true)
+                        .help("Ed25519 public key (raw 32 bytes or PEM) the file must be signed with"),
+                    Arg::new("file").required(true).allow_invalid_utf8(true)
+                        .help("Shader assembly or shader pack to verify")
                 ])
         ])
         .args([
@@ -113,7 +275,7 @@ fn run() -> i32 {
                 .help("Enable verbose output"),
             Arg::new("threads").short('n').long("threads").takes_value(true)
                 .help("Specify the maximum number of threads to use when processing shaders")
-        ]).get_matches();
+        ]).get_matches_from(args);
     alloc_verbosity_level(matches.occurrences_of("verbose"));
     info!("Initializing BlockProject 3D Shader Linker...");
     let n_threads: usize = matches.value_of_t("threads").unwrap_or(1);
@@ -123,9 +285,18 @@ fn run() -> i32 {
     if let Some(args) = matches.subcommand_matches("link") {
         return link(n_threads, args);
     }
-    0
+    if let Some(args) = matches.subcommand_matches("find") {
+        return find(args);
+    }
+    if let Some(args) = matches.subcommand_matches("extract") {
+        return extract(args);
+    }
+    if let Some(args) = matches.subcommand_matches("verify") {
+        return verify(args);
+    }
+    Ok(())
 }
 
 fn main() {
-    init_bp3d_logger(run);
+    init_bp3d_logger(|| run_cli(run));
 }
@@ -31,7 +31,7 @@ use serde::Deserialize;
 use bp3d_sal::ast::tree::{BaseType, VectorType};
 use crate::{FromBpx, ToBpx};
 
-#[derive(Copy, Clone, Serialize, Deserialize)]
+#[derive(Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub enum ConstPropType
 {
     Vector(VectorType),
@@ -39,13 +39,75 @@ pub enum ConstPropType
     Matrix(VectorType)
 }
 
-#[derive(Copy, Clone, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub struct ConstantObject
 {
     pub ty: ConstPropType,
     pub offset: u32,
-    pub size: u32
+    pub size: u32,
+    pub deprecated: Option<String>
 }
 
 impl ToBpx for ConstantObject {}
 impl FromBpx for ConstantObject {}
+
+#[cfg(test)]
+mod tests
+{
+    use bp3d_sal::ast::tree::BaseType;
+    use bp3d_sal::ast::tree::VectorType;
+    use crate::{ConstantObject, ConstPropType, FromBpx, ToBpx, validate_roundtrip};
+
+    #[test]
+    fn deprecated_roundtrip()
+    {
+        let obj = ConstantObject {
+            ty: ConstPropType::Scalar(BaseType::Float),
+            offset: 16,
+            size: 4,
+            deprecated: Some("use FrameTime instead".into())
+        };
+        let value = obj.to_bpx(false).unwrap();
+        let obj = ConstantObject::from_bpx(&value).unwrap();
+        assert_eq!(obj.deprecated.as_deref(), Some("use FrameTime instead"));
+        assert_eq!(obj.offset, 16);
+        assert_eq!(obj.size, 4);
+    }
+
+    #[test]
+    fn int64_roundtrip()
+    {
+        let obj = ConstantObject {
+            ty: ConstPropType::Scalar(BaseType::Uint64),
+            offset: 8,
+            size: 8,
+            deprecated: None
+        };
+        let value = obj.to_bpx(false).unwrap();
+        let obj = ConstantObject::from_bpx(&value).unwrap();
+        assert!(matches!(obj.ty, ConstPropType::Scalar(BaseType::Uint64)));
+    }
+
+    #[test]
+    fn roundtrip()
+    {
+        validate_roundtrip(&ConstantObject {
+            ty: ConstPropType::Matrix(VectorType {
+                item: BaseType::Float,
+                size: 4
+            }),
+            offset: 64,
+            size: 64,
+            deprecated: Some("use ViewProjection instead".into())
+        });
+        validate_roundtrip(&ConstantObject {
+            ty: ConstPropType::Vector(VectorType {
+                item: BaseType::Int,
+                size: 2
+            }),
+            offset: 0,
+            size: 8,
+            deprecated: None
+        });
+    }
+}
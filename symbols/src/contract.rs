@@ -0,0 +1,346 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Symbol contracts: requirements an engine places on every shader pack it loads.
+
+use std::fmt::{Display, Formatter};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SymbolKind
+{
+    ConstantBuffer,
+    Constant,
+    Texture,
+    Sampler,
+    Output,
+    VertexFormat,
+    Pipeline,
+    Resource
+}
+
+impl Display for SymbolKind
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+    {
+        match self {
+            SymbolKind::ConstantBuffer => f.write_str("constant buffer"),
+            SymbolKind::Constant => f.write_str("constant"),
+            SymbolKind::Texture => f.write_str("texture"),
+            SymbolKind::Sampler => f.write_str("sampler"),
+            SymbolKind::Output => f.write_str("output"),
+            SymbolKind::VertexFormat => f.write_str("vertex format"),
+            SymbolKind::Pipeline => f.write_str("pipeline"),
+            SymbolKind::Resource => f.write_str("resource")
+        }
+    }
+}
+
+/// A member of a structured symbol; `ty` uses SAL type names (ex: `vec4f`, `mat4f`, `Light[8]`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Member
+{
+    pub name: String,
+    #[serde(rename = "type")]
+    pub ty: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub offset: Option<u32>
+}
+
+impl Display for Member
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+    {
+        match self.offset {
+            Some(offset) => write!(f, "{} {} @ {}", self.ty, self.name, offset),
+            None => write!(f, "{} {}", self.ty, self.name)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Requirement
+{
+    pub name: String,
+    pub kind: SymbolKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub slot: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub layout: Option<Vec<Member>>
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Contract
+{
+    #[serde(default)]
+    pub require: Vec<Requirement>,
+    /// Glob patterns (`*` and `?`) of symbol names which must not appear in a pack.
+    #[serde(default)]
+    pub forbid: Vec<String>
+}
+
+/// A symbol found in a build, as seen by contract checking.
+#[derive(Debug, Clone)]
+pub struct SymbolInfo
+{
+    pub name: String,
+    pub kind: SymbolKind,
+    pub slot: Option<u32>,
+    pub layout: Vec<Member>
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation
+{
+    Missing(String, SymbolKind),
+    WrongKind
+    {
+        name: String,
+        expected: SymbolKind,
+        found: SymbolKind
+    },
+    WrongSlot
+    {
+        name: String,
+        expected: u32,
+        found: Option<u32>
+    },
+    LayoutMismatch
+    {
+        name: String,
+        diff: Vec<String>
+    },
+    Forbidden
+    {
+        name: String,
+        pattern: String
+    }
+}
+
+impl Display for Violation
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+    {
+        match self {
+            Violation::Missing(name, kind) => write!(f, "missing required {} '{}'", kind, name),
+            Violation::WrongKind { name, expected, found } => write!(f, "'{}' must be a {} but is a {}", name, expected, found),
+            Violation::WrongSlot { name, expected, found: Some(found) } => write!(f, "'{}' must be at slot {} but is at slot {}", name, expected, found),
+            Violation::WrongSlot { name, expected, found: None } => write!(f, "'{}' must be at slot {} but has no slot", name, expected),
+            Violation::LayoutMismatch { name, diff } => write!(f, "layout of '{}' does not match:\n    {}", name, diff.join("\n    ")),
+            Violation::Forbidden { name, pattern } => write!(f, "'{}' is forbidden by pattern '{}'", name, pattern)
+        }
+    }
+}
+
+/// Matches a name against a glob pattern (`*` and `?`).
+pub fn glob_match(pattern: &str, name: &str) -> bool
+{
+    fn glob(p: &[char], n: &[char]) -> bool
+    {
+        match (p.first(), n.first()) {
+            (None, None) => true,
+            (Some('*'), _) => glob(&p[1..], n) || (!n.is_empty() && glob(p, &n[1..])),
+            (Some('?'), Some(_)) => glob(&p[1..], &n[1..]),
+            (Some(a), Some(b)) if a == b => glob(&p[1..], &n[1..]),
+            _ => false
+        }
+    }
+    let p: Vec<char> = pattern.chars().collect();
+    let n: Vec<char> = name.chars().collect();
+    glob(&p, &n)
+}
+
+/// Returns a member-level diff of two layouts: `-` for missing members, `+` for unexpected
+/// members and `~` for members with a different type or offset.
+fn diff_layout(expected: &[Member], found: &[Member]) -> Vec<String>
+{
+    let mut diff = Vec::new();
+    for member in expected {
+        match found.iter().find(|v| v.name == member.name) {
+            None => diff.push(format!("- {}", member)),
+            Some(v) if v.ty != member.ty || (member.offset.is_some() && v.offset != member.offset) => {
+                diff.push(format!("~ {} (found {})", member, v))
+            },
+            _ => ()
+        }
+    }
+    for member in found {
+        if !expected.iter().any(|v| v.name == member.name) {
+            diff.push(format!("+ {}", member));
+        }
+    }
+    diff
+}
+
+impl Contract
+{
+    /// Checks the symbols of a build against this contract and returns every unmet requirement.
+    pub fn check(&self, symbols: &[SymbolInfo]) -> Vec<Violation>
+    {
+        let mut violations = Vec::new();
+        for req in &self.require {
+            let sym = match symbols.iter().find(|v| v.name == req.name) {
+                Some(v) => v,
+                None => {
+                    violations.push(Violation::Missing(req.name.clone(), req.kind));
+                    continue;
+                }
+            };
+            if sym.kind != req.kind {
+                violations.push(Violation::WrongKind {
+                    name: req.name.clone(),
+                    expected: req.kind,
+                    found: sym.kind
+                });
+                continue;
+            }
+            if let Some(slot) = req.slot {
+                if sym.slot != Some(slot) {
+                    violations.push(Violation::WrongSlot {
+                        name: req.name.clone(),
+                        expected: slot,
+                        found: sym.slot
+                    });
+                }
+            }
+            if let Some(layout) = &req.layout {
+                let diff = diff_layout(layout, &sym.layout);
+                if !diff.is_empty() {
+                    violations.push(Violation::LayoutMismatch {
+                        name: req.name.clone(),
+                        diff
+                    });
+                }
+            }
+        }
+        for sym in symbols {
+            if let Some(pattern) = self.forbid.iter().find(|v| glob_match(v, &sym.name)) {
+                violations.push(Violation::Forbidden {
+                    name: sym.name.clone(),
+                    pattern: pattern.clone()
+                });
+            }
+        }
+        violations
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::{Contract, glob_match, Member, Requirement, SymbolInfo, SymbolKind, Violation};
+
+    fn member(name: &str, ty: &str, offset: u32) -> Member
+    {
+        Member {
+            name: name.into(),
+            ty: ty.into(),
+            offset: Some(offset)
+        }
+    }
+
+    fn symbols() -> Vec<SymbolInfo>
+    {
+        vec![
+            SymbolInfo {
+                name: "PerFrame".into(),
+                kind: SymbolKind::ConstantBuffer,
+                slot: Some(1),
+                layout: vec![member("ViewProj", "mat4f", 0), member("Time", "float", 64)]
+            },
+            SymbolInfo {
+                name: "FragColor".into(),
+                kind: SymbolKind::Output,
+                slot: Some(0),
+                layout: Vec::new()
+            },
+            SymbolInfo {
+                name: "DebugTint".into(),
+                kind: SymbolKind::Constant,
+                slot: None,
+                layout: Vec::new()
+            }
+        ]
+    }
+
+    fn require(name: &str, kind: SymbolKind) -> Requirement
+    {
+        Requirement {
+            name: name.into(),
+            kind,
+            slot: None,
+            layout: None
+        }
+    }
+
+    #[test]
+    fn passing_contract()
+    {
+        let mut per_frame = require("PerFrame", SymbolKind::ConstantBuffer);
+        per_frame.slot = Some(1);
+        per_frame.layout = Some(vec![member("ViewProj", "mat4f", 0), member("Time", "float", 64)]);
+        let contract = Contract {
+            require: vec![per_frame, require("FragColor", SymbolKind::Output)],
+            forbid: vec!["Legacy*".into()]
+        };
+        assert_eq!(contract.check(&symbols()), []);
+    }
+
+    #[test]
+    fn violations()
+    {
+        let mut per_frame = require("PerFrame", SymbolKind::ConstantBuffer);
+        per_frame.slot = Some(2);
+        per_frame.layout = Some(vec![member("ViewProj", "mat4f", 0), member("Time", "vec2f", 64), member("Frame", "uint", 72)]);
+        let contract = Contract {
+            require: vec![per_frame, require("FragColor", SymbolKind::Texture), require("Lighting", SymbolKind::ConstantBuffer)],
+            forbid: vec!["Debug*".into()]
+        };
+        let violations = contract.check(&symbols());
+        assert_eq!(violations, [
+            Violation::WrongSlot { name: "PerFrame".into(), expected: 2, found: Some(1) },
+            Violation::LayoutMismatch {
+                name: "PerFrame".into(),
+                diff: vec!["~ vec2f Time @ 64 (found float Time @ 64)".into(), "- uint Frame @ 72".into()]
+            },
+            Violation::WrongKind { name: "FragColor".into(), expected: SymbolKind::Texture, found: SymbolKind::Output },
+            Violation::Missing("Lighting".into(), SymbolKind::ConstantBuffer),
+            Violation::Forbidden { name: "DebugTint".into(), pattern: "Debug*".into() }
+        ]);
+        assert_eq!(violations[3].to_string(), "missing required constant buffer 'Lighting'");
+    }
+
+    #[test]
+    fn glob()
+    {
+        assert!(glob_match("Debug*", "DebugTint"));
+        assert!(glob_match("?ebug", "Debug"));
+        assert!(!glob_match("Debug", "DebugTint"));
+    }
+}
@@ -0,0 +1,198 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+
+//! Debug information of a shader pack: the sources of each stage and the settings of the build.
+//!
+//! Debug information is stored in a dedicated BPX section, only written by debug builds: the
+//! compiler version, the target name, a little endian u32 count of option records, the option
+//! records, a u32 count of stage records and the stage records. Each record starts with its u32
+//! length so that readers skip fields appended by newer writers. Strings are stored as a u32
+//! length followed by UTF-8 as sources easily exceed 64 KiB.
+
+use std::collections::BTreeMap;
+use std::io::{Error, ErrorKind};
+use serde::{Deserialize, Serialize};
+use crate::origin::write_record;
+use crate::resource::Cursor;
+
+/// BPX section type holding the debug information of a shader pack.
+pub const DEBUG_INFO_SECTION_TYPE: u8 = 0xF5;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DebugSource
+{
+    /// Name of the unit, usually its path.
+    pub name: String,
+    pub text: String
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DebugStage
+{
+    pub stage: String,
+    /// Units of the stage as written, before SAL translation.
+    pub sources: Vec<DebugSource>,
+    /// Code handed over to the GLSL compiler, after SAL translation.
+    pub code: String
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DebugInfo
+{
+    pub compiler_version: String,
+    pub target: String,
+    pub options: BTreeMap<String, String>,
+    pub stages: Vec<DebugStage>
+}
+
+fn write_text(buf: &mut Vec<u8>, s: &str)
+{
+    buf.extend((s.len() as u32).to_le_bytes());
+    buf.extend(s.as_bytes());
+}
+
+impl<'a> Cursor<'a>
+{
+    fn take_text(&mut self) -> Result<String, Error>
+    {
+        let len = u32::from_le_bytes(self.take_array()?);
+        String::from_utf8(self.take(len as usize)?.into())
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))
+    }
+}
+
+impl DebugInfo
+{
+    pub fn get_stage(&self, stage: &str) -> Option<&DebugStage>
+    {
+        self.stages.iter().find(|v| v.stage == stage)
+    }
+
+    pub fn encode(&self) -> Vec<u8>
+    {
+        let mut buf = Vec::new();
+        write_text(&mut buf, &self.compiler_version);
+        write_text(&mut buf, &self.target);
+        buf.extend((self.options.len() as u32).to_le_bytes());
+        for (name, value) in &self.options {
+            let mut record = Vec::new();
+            write_text(&mut record, name);
+            write_text(&mut record, value);
+            write_record(&mut buf, record);
+        }
+        buf.extend((self.stages.len() as u32).to_le_bytes());
+        for stage in &self.stages {
+            let mut record = Vec::new();
+            write_text(&mut record, &stage.stage);
+            write_text(&mut record, &stage.code);
+            record.extend((stage.sources.len() as u32).to_le_bytes());
+            for source in &stage.sources {
+                write_text(&mut record, &source.name);
+                write_text(&mut record, &source.text);
+            }
+            write_record(&mut buf, record);
+        }
+        buf
+    }
+
+    pub fn decode(data: &[u8]) -> Result<DebugInfo, Error>
+    {
+        let mut cursor = Cursor(data);
+        let mut info = DebugInfo {
+            compiler_version: cursor.take_text()?,
+            target: cursor.take_text()?,
+            ..Default::default()
+        };
+        let count = u32::from_le_bytes(cursor.take_array()?);
+        for _ in 0..count {
+            let mut record = cursor.take_record()?;
+            let name = record.take_text()?;
+            let value = record.take_text()?;
+            info.options.insert(name, value);
+        }
+        let count = u32::from_le_bytes(cursor.take_array()?);
+        for _ in 0..count {
+            let mut record = cursor.take_record()?;
+            let stage = record.take_text()?;
+            let code = record.take_text()?;
+            let count = u32::from_le_bytes(record.take_array()?);
+            let mut sources = Vec::new();
+            for _ in 0..count {
+                let name = record.take_text()?;
+                let text = record.take_text()?;
+                sources.push(DebugSource { name, text });
+            }
+            info.stages.push(DebugStage { stage, sources, code });
+        }
+        Ok(info)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::{DebugInfo, DebugSource, DebugStage};
+
+    #[test]
+    fn round_trip()
+    {
+        //Longer than a u16 length allows.
+        let large = "// padding\n".repeat(8000);
+        let mut info = DebugInfo {
+            compiler_version: "0.1.0".into(),
+            target: "GL42".into(),
+            ..Default::default()
+        };
+        info.options.insert("debug".into(), "true".into());
+        info.options.insert("defines".into(), "SHADOWS,FOG".into());
+        info.stages.push(DebugStage {
+            stage: "Vertex".into(),
+            sources: vec![DebugSource {
+                name: "vertex.glsl".into(),
+                text: "#stage vertex\n\n#sal\nconst float Time;\n#sal\n".into()
+            }],
+            code: "#version 420\nuniform float Time;\n".into()
+        });
+        info.stages.push(DebugStage {
+            stage: "Pixel".into(),
+            sources: vec![DebugSource {
+                name: "pixel.glsl".into(),
+                text: large.clone()
+            }],
+            code: large
+        });
+        let data = info.encode();
+        let decoded = DebugInfo::decode(&data).unwrap();
+        assert_eq!(decoded, info);
+        assert_eq!(decoded.options["defines"], "SHADOWS,FOG");
+        assert_eq!(decoded.get_stage("Vertex").unwrap().sources[0].text, info.stages[0].sources[0].text);
+        assert!(decoded.get_stage("Hull").is_none());
+        assert!(DebugInfo::decode(&data[..data.len() - 1]).is_err());
+    }
+}
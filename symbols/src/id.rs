@@ -0,0 +1,235 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+
+//! Numeric identifiers of symbols.
+//!
+//! A [SymbolId] is the 32-bit FNV-1a hash of the name of a symbol so that runtimes can look
+//! symbols up without comparing strings. shaderc stores the id of each symbol in an `__id__`
+//! property of its extended data and rejects packs in which two symbols share an id. Symbols
+//! without extended data, or written before ids were introduced, use [SymbolId::from_name].
+
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use serde::{Deserialize, Serialize};
+
+/// Name of the property holding the id of a symbol in its extended data.
+pub const ID_KEY: &str = "__id__";
+
+/// Number of bytes added by the id property to the encoding of an object.
+pub const ID_SIZE: usize = 8 + 1 + 4;
+
+const FNV_OFFSET: u32 = 0x811C9DC5;
+const FNV_PRIME: u32 = 0x01000193;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct SymbolId(pub u32);
+
+impl SymbolId
+{
+    pub fn from_name(name: &str) -> SymbolId
+    {
+        let hash = name.bytes().fold(FNV_OFFSET, |hash, b| (hash ^ b as u32).wrapping_mul(FNV_PRIME));
+        SymbolId(hash)
+    }
+}
+
+impl Display for SymbolId
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+    {
+        write!(f, "{:#010X}", self.0)
+    }
+}
+
+/// Returns the id stored in the extended data of a symbol, if any.
+pub fn symbol_id(val: &bpx::sd::Value) -> Option<SymbolId>
+{
+    match val.as_object().and_then(|v| v.raw_get(bpx::utils::hash(ID_KEY))) {
+        Some(bpx::sd::Value::Uint32(v)) => Some(SymbolId(*v)),
+        _ => None
+    }
+}
+
+/// Stores an id in the extended data of a symbol; extended data which is not an object is left
+/// untouched.
+pub fn set_symbol_id(val: &mut bpx::sd::Value, id: SymbolId)
+{
+    if let bpx::sd::Value::Object(obj) = val {
+        obj.set(ID_KEY, bpx::sd::Value::Uint32(id.0));
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdCollision
+{
+    pub id: SymbolId,
+    pub first: String,
+    pub second: String
+}
+
+impl Display for IdCollision
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+    {
+        write!(f, "symbols '{}' and '{}' have the same id {}; rename one of them", self.first, self.second, self.id)
+    }
+}
+
+impl std::error::Error for IdCollision {}
+
+struct Entry<T>
+{
+    id: SymbolId,
+    name: String,
+    value: T
+}
+
+/// Symbols indexed by both id and name, in insertion order.
+pub struct SymbolTable<T>
+{
+    entries: Vec<Entry<T>>,
+    ids: HashMap<SymbolId, usize>,
+    names: HashMap<String, usize>
+}
+
+impl<T> Default for SymbolTable<T>
+{
+    fn default() -> Self
+    {
+        SymbolTable {
+            entries: Vec::new(),
+            ids: HashMap::new(),
+            names: HashMap::new()
+        }
+    }
+}
+
+impl<T> SymbolTable<T>
+{
+    pub fn new() -> SymbolTable<T>
+    {
+        SymbolTable::default()
+    }
+
+    /// Returns the id a symbol would be inserted with, fails if it is already taken by another
+    /// symbol.
+    pub fn check(&self, name: &str) -> Result<SymbolId, IdCollision>
+    {
+        let id = SymbolId::from_name(name);
+        match self.ids.get(&id).map(|v| &self.entries[*v]) {
+            Some(entry) if entry.name != name => Err(IdCollision {
+                id,
+                first: entry.name.clone(),
+                second: name.into()
+            }),
+            _ => Ok(id)
+        }
+    }
+
+    /// Inserts a symbol, replacing the value of a symbol with the same name.
+    pub fn insert(&mut self, name: String, value: T) -> Result<SymbolId, IdCollision>
+    {
+        let id = self.check(&name)?;
+        match self.names.get(&name) {
+            Some(index) => self.entries[*index].value = value,
+            None => {
+                self.ids.insert(id, self.entries.len());
+                self.names.insert(name.clone(), self.entries.len());
+                self.entries.push(Entry { id, name, value });
+            }
+        }
+        Ok(id)
+    }
+
+    pub fn by_id(&self, id: SymbolId) -> Option<&T>
+    {
+        self.ids.get(&id).map(|v| &self.entries[*v].value)
+    }
+
+    pub fn by_name(&self, name: &str) -> Option<&T>
+    {
+        self.names.get(name).map(|v| &self.entries[*v].value)
+    }
+
+    pub fn len(&self) -> usize
+    {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.entries.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (SymbolId, &str, &T)>
+    {
+        self.entries.iter().map(|v| (v.id, &*v.name, &v.value))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::{SymbolId, SymbolTable};
+
+    #[test]
+    fn stable_ids()
+    {
+        //Reference values of 32-bit FNV-1a: ids must never change between builds.
+        assert_eq!(SymbolId::from_name(""), SymbolId(0x811C9DC5));
+        assert_eq!(SymbolId::from_name("a"), SymbolId(0xE40C292C));
+        assert_eq!(SymbolId::from_name("foobar"), SymbolId(0xBF9CF968));
+        assert_eq!(SymbolId(0xBF9CF968).to_string(), "0xBF9CF968");
+        let mut table = SymbolTable::new();
+        let id = table.insert("ModelView".into(), 0).unwrap();
+        table.insert("BaseTexture".into(), 1).unwrap();
+        assert_eq!(id, SymbolId::from_name("ModelView"));
+        assert_eq!(table.by_id(id), Some(&0));
+        assert_eq!(table.by_name("BaseTexture"), Some(&1));
+        assert_eq!(table.by_name("Missing"), None);
+        //Re-inserting a symbol is not a collision.
+        assert_eq!(table.insert("ModelView".into(), 2), Ok(id));
+        assert_eq!(table.by_id(id), Some(&2));
+        assert_eq!(table.iter().map(|(_, name, _)| name).collect::<Vec<_>>(), vec!["ModelView", "BaseTexture"]);
+    }
+
+    #[test]
+    fn collisions()
+    {
+        //Known 32-bit FNV-1a collision.
+        assert_eq!(SymbolId::from_name("costarring"), SymbolId::from_name("liquid"));
+        let mut table = SymbolTable::new();
+        table.insert("costarring".into(), ()).unwrap();
+        let err = table.insert("liquid".into(), ()).unwrap_err();
+        assert_eq!(err.first, "costarring");
+        assert_eq!(err.second, "liquid");
+        assert_eq!(err.to_string(), format!("symbols 'costarring' and 'liquid' have the same id {}; rename one of them", err.id));
+        assert_eq!(table.len(), 1);
+    }
+}
@@ -0,0 +1,120 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Identification of BPX files from their main header, before handing them to a decoder.
+
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+const SIZE_MAIN_HEADER: usize = 40;
+const OFFSET_TYPE_EXT: usize = 24;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FileKind
+{
+    /// A BPXP built by the LIB target.
+    ShaderLib,
+    /// A BPXS of type pipeline, built by the GL/VK targets.
+    ShaderPack,
+    /// A BPXS of type assembly, built by shaderl.
+    Assembly,
+    /// Any other BPXP with its type code.
+    Package([u8; 2]),
+    /// Any other BPX container with its type byte.
+    Bpx(u8),
+    Unknown
+}
+
+impl FileKind
+{
+    pub fn from_header(header: &[u8]) -> FileKind
+    {
+        if header.len() < SIZE_MAIN_HEADER || &header[..3] != b"BPX" {
+            return FileKind::Unknown;
+        }
+        let type_ext = &header[OFFSET_TYPE_EXT..SIZE_MAIN_HEADER];
+        match header[3] {
+            b'P' if &type_ext[2..4] == b"SL" => FileKind::ShaderLib,
+            b'P' => FileKind::Package([type_ext[2], type_ext[3]]),
+            b'S' if type_ext[10] == b'A' => FileKind::Assembly,
+            b'S' if type_ext[10] == b'P' => FileKind::ShaderPack,
+            ty => FileKind::Bpx(ty)
+        }
+    }
+
+    pub fn probe(path: &Path) -> std::io::Result<FileKind>
+    {
+        let mut header = Vec::with_capacity(SIZE_MAIN_HEADER);
+        File::open(path)?.take(SIZE_MAIN_HEADER as u64).read_to_end(&mut header)?;
+        Ok(FileKind::from_header(&header))
+    }
+}
+
+impl Display for FileKind
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+    {
+        match self {
+            FileKind::ShaderLib => f.write_str("a shader library (type SL)"),
+            FileKind::ShaderPack => f.write_str("a shader pack (type SP)"),
+            FileKind::Assembly => f.write_str("a shader assembly (type SA)"),
+            FileKind::Package(code) => write!(f, "a BPX package (type {})", String::from_utf8_lossy(code)),
+            FileKind::Bpx(ty) => write!(f, "a BPX container (type {})", *ty as char),
+            FileKind::Unknown => f.write_str("not a BPX file")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::FileKind;
+
+    fn header(ty: u8, type_ext: &[u8]) -> Vec<u8>
+    {
+        let mut header = vec![0; 40];
+        header[..3].copy_from_slice(b"BPX");
+        header[3] = ty;
+        header[24..24 + type_ext.len()].copy_from_slice(type_ext);
+        header
+    }
+
+    #[test]
+    fn from_header()
+    {
+        assert_eq!(FileKind::from_header(&header(b'P', b"\0\0SL")), FileKind::ShaderLib);
+        assert_eq!(FileKind::from_header(&header(b'P', b"\0\0XY")), FileKind::Package(*b"XY"));
+        assert_eq!(FileKind::from_header(&header(b'S', b"\0\0\0\0\0\0\0\0\0\0P")), FileKind::ShaderPack);
+        assert_eq!(FileKind::from_header(&header(b'S', b"\0\0\0\0\0\0\0\0\0\0A")), FileKind::Assembly);
+        assert_eq!(FileKind::from_header(&header(b'T', b"")), FileKind::Bpx(b'T'));
+        assert_eq!(FileKind::from_header(b"#stage vertex"), FileKind::Unknown);
+        assert_eq!(FileKind::Package(*b"XY").to_string(), "a BPX package (type XY)");
+    }
+}
@@ -0,0 +1,189 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+
+//! Member offsets of buffer structs computed from their symbols.
+//!
+//! This implementation of the layout rules only depends on the symbols of a shader pack so that
+//! readers can check or recompute the offsets recorded in a [StructObject]; it is kept separate
+//! from the one of shaderc so that both can be tested against each other.
+
+use std::fmt::{Display, Formatter};
+use bp3d_sal::ast::tree::{BaseType, VectorType};
+use crate::{ArrayItemType, LayoutRule, PropType, StructObject};
+
+/// Offsets of the members of a struct along with its size and base alignment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StructLayout
+{
+    pub offsets: Vec<u32>,
+    pub size: u32,
+    pub base_alignment: u32
+}
+
+/// A member references a struct whose layout is unknown.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct UnresolvedStruct(pub u16);
+
+impl Display for UnresolvedStruct
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+    {
+        write!(f, "struct symbol {} has no known layout", self.0)
+    }
+}
+
+impl std::error::Error for UnresolvedStruct {}
+
+fn align_to(value: u32, alignment: u32) -> u32
+{
+    match alignment {
+        0 => value,
+        _ => value.div_ceil(alignment) * alignment
+    }
+}
+
+fn base_size(t: BaseType) -> u32
+{
+    match t {
+        BaseType::Double | BaseType::Int64 | BaseType::Uint64 => 8,
+        _ => 4
+    }
+}
+
+//Returns (base alignment, size) of each kind of member.
+fn vector(rule: LayoutRule, v: &VectorType) -> (u32, u32)
+{
+    let item = base_size(v.item);
+    let alignment = match (rule, v.size) {
+        (LayoutRule::Scalar, _) => item,
+        (_, 2) => 2 * item,
+        _ => 4 * item
+    };
+    (alignment, item * v.size as u32)
+}
+
+fn array(rule: LayoutRule, (alignment, size): (u32, u32), count: u32) -> (u32, u32)
+{
+    let alignment = match rule {
+        LayoutRule::Std140 => align_to(alignment, 16),
+        _ => alignment
+    };
+    (alignment, align_to(size, alignment) * count)
+}
+
+//Matrices are arrays of column vectors.
+fn matrix(rule: LayoutRule, m: &VectorType) -> (u32, u32)
+{
+    array(rule, vector(rule, m), m.size as u32)
+}
+
+/// Computes the layout of a struct; `find` returns the layout of the struct symbols referenced by
+/// its members, which must use the same rule.
+pub fn compute_layout<'a, F>(obj: &StructObject, rule: LayoutRule, find: F) -> Result<StructLayout, UnresolvedStruct>
+    where F: Fn(u16) -> Option<&'a StructLayout>
+{
+    let structure = |index: u16| find(index).map(|v| (v.base_alignment, v.size)).ok_or(UnresolvedStruct(index));
+    let mut offsets = Vec::with_capacity(obj.props.len());
+    let mut end = 0;
+    let mut base_alignment = 0;
+    for prop in &obj.props {
+        let (alignment, size) = match &prop.ty {
+            PropType::Scalar(t) => (base_size(*t), base_size(*t)),
+            PropType::Vector(v) => vector(rule, v),
+            PropType::Matrix(m) => matrix(rule, m),
+            PropType::StructRef(index) => structure(*index)?,
+            PropType::Array { size, ty } => {
+                let item = match ty {
+                    ArrayItemType::Vector(v) => vector(rule, v),
+                    ArrayItemType::Matrix(m) => matrix(rule, m),
+                    ArrayItemType::StructRef(index) => structure(*index)?
+                };
+                array(rule, item, *size)
+            }
+        };
+        let offset = align_to(end, alignment);
+        offsets.push(offset);
+        end = offset + size;
+        base_alignment = base_alignment.max(alignment);
+    }
+    if rule == LayoutRule::Std140 {
+        base_alignment = align_to(base_alignment, 16);
+    }
+    Ok(StructLayout {
+        offsets,
+        size: align_to(end, base_alignment),
+        base_alignment
+    })
+}
+
+#[cfg(test)]
+mod tests
+{
+    use bp3d_sal::ast::tree::{BaseType, VectorType};
+    use crate::{ArrayItemType, compute_layout, LayoutRule, PropObject, PropType, StructLayout, StructObject, UnresolvedStruct};
+
+    fn structure(types: &[PropType]) -> StructObject
+    {
+        StructObject {
+            size: 0,
+            props: types.iter().map(|ty| PropObject {
+                name: String::new(),
+                offset: 0,
+                ty: *ty,
+                semantic: None,
+                default: None,
+                location: None
+            }).collect(),
+            deprecated: None,
+            shared: false,
+            layout: None
+        }
+    }
+
+    #[test]
+    fn rules()
+    {
+        let vec3 = VectorType { item: BaseType::Float, size: 3 };
+        let light = structure(&[PropType::Vector(vec3), PropType::Scalar(BaseType::Float)]);
+        let layout = |rule| compute_layout(&light, rule, |_| None).unwrap();
+        assert_eq!(layout(LayoutRule::Std140), StructLayout { offsets: vec![0, 12], size: 16, base_alignment: 16 });
+        assert_eq!(layout(LayoutRule::Scalar), StructLayout { offsets: vec![0, 12], size: 16, base_alignment: 4 });
+        let light = layout(LayoutRule::Std140);
+        let scene = structure(&[
+            PropType::Scalar(BaseType::Uint),
+            PropType::Array { size: 2, ty: ArrayItemType::StructRef(0) },
+            PropType::Matrix(vec3)
+        ]);
+        let scene = compute_layout(&scene, LayoutRule::Std140, |v| (v == 0).then_some(&light)).unwrap();
+        assert_eq!(scene.offsets, [0, 16, 48]);
+        assert_eq!(scene.size, 96);
+        let missing = structure(&[PropType::StructRef(3)]);
+        assert_eq!(compute_layout(&missing, LayoutRule::Std430, |_| None), Err(UnresolvedStruct(3)));
+    }
+}
@@ -31,6 +31,21 @@ mod structs;
 mod pipeline;
 mod outputs;
 mod constants;
+mod kind;
+mod contract;
+mod size;
+mod resource;
+mod schema;
+mod origin;
+mod varying;
+mod version;
+mod manifest;
+mod id;
+mod payload;
+mod debug_info;
+mod layout;
+#[cfg(feature = "signing")]
+mod signature;
 
 use bpx::sd::serde::EnumSize;
 use serde::{Deserialize, Serialize};
@@ -39,24 +54,49 @@ pub use structs::*;
 pub use pipeline::*;
 pub use outputs::*;
 pub use constants::*;
+pub use kind::*;
+pub use contract::*;
+pub use size::*;
+pub use resource::*;
+pub use schema::*;
+pub use origin::*;
+pub use varying::*;
+pub use version::*;
+pub use manifest::*;
+pub use id::*;
+pub use payload::*;
+pub use debug_info::*;
+pub use layout::*;
+#[cfg(feature = "signing")]
+pub use signature::*;
 
 pub trait ToBpx
     where Self: Serialize
 {
     fn to_bpx(&self, debug: bool) -> Result<bpx::sd::Value, bpx::sd::serde::Error> {
-        self.serialize(bpx::sd::serde::Serializer::new(EnumSize::U8, debug))
+        let mut val = self.serialize(bpx::sd::serde::Serializer::new(EnumSize::U8, debug))?;
+        if let bpx::sd::Value::Object(obj) = &mut val {
+            obj.set(VERSION_KEY, bpx::sd::Value::Uint16(FORMAT_VERSION));
+        }
+        Ok(val)
     }
 }
 
 pub trait FromBpx
     where Self: Deserialize<'static>
 {
-    fn from_bpx(val: &bpx::sd::Value) -> Result<Self, bpx::sd::serde::Error> {
+    fn from_bpx(val: &bpx::sd::Value) -> Result<Self, FromBpxError> {
+        check_version(val)?;
         let deserializer = bpx::sd::serde::Deserializer::new_borrowed(EnumSize::U8, val);
-        Self::deserialize(deserializer)
+        Ok(Self::deserialize(deserializer)?)
     }
 }
 
+/// Returns the estimated size in bytes of `value` once encoded by [ToBpx].
+pub fn encoded_ext_data_size<T: ToBpx>(value: &T, debug: bool) -> Result<usize, SizeError> {
+    Ok(encoded_root_size(value, debug)? + VERSION_SIZE)
+}
+
 pub trait Refs {
     fn list_refs(&self) -> Vec<usize>;
     fn has_refs(&self) -> bool {
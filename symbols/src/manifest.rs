@@ -0,0 +1,181 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+
+//! Binding manifest: a JSON description of the bindings of a shader pack used by engines to
+//! generate their binding structs.
+
+use std::collections::BTreeMap;
+use bp3d_sal::ast::tree::TextureType;
+use serde::{Deserialize, Serialize};
+use crate::{BlendfuncObject, Member, PipelineObject, SamplerObject, SCHEMA_VERSION, TextureObject, TextureObjectType};
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestCBuffer
+{
+    pub slot: u32,
+    pub size: u32,
+    pub members: Vec<Member>
+}
+
+/// A packed struct referenced by the members of constant buffers.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestStruct
+{
+    pub size: u32,
+    pub members: Vec<Member>
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestTexture
+{
+    pub slot: u32,
+    pub dimension: TextureObjectType,
+    pub format: TextureType,
+    #[serde(default)]
+    pub array_size: Option<u32>
+}
+
+impl ManifestTexture
+{
+    pub fn new(slot: u32, obj: TextureObject) -> ManifestTexture
+    {
+        ManifestTexture {
+            slot,
+            dimension: obj.ty,
+            format: obj.value,
+            array_size: obj.array_size
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestSampler
+{
+    pub slot: u32,
+    /// State declared by a `samplerstate` block, None if the engine picks the state; always set
+    /// for comparison samplers.
+    #[serde(default)]
+    pub state: Option<SamplerObject>
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestAttribute
+{
+    pub name: String,
+    #[serde(rename = "type")]
+    pub ty: String,
+    pub location: u32,
+    pub semantic: String
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestVertexFormat
+{
+    pub name: String,
+    pub attributes: Vec<ManifestAttribute>
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestOutput
+{
+    pub index: u32,
+    #[serde(rename = "type")]
+    pub ty: String,
+    #[serde(default)]
+    pub semantic: Option<String>,
+    #[serde(default)]
+    pub blendfunc: Option<BlendfuncObject>
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestPipeline
+{
+    pub name: String,
+    pub state: PipelineObject
+}
+
+/// Output of `shaderc --manifest`; symbols are keyed by name and members are listed in declaration
+/// order.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct Manifest
+{
+    pub schema_version: u32,
+    pub cbuffers: BTreeMap<String, ManifestCBuffer>,
+    pub structs: BTreeMap<String, ManifestStruct>,
+    pub textures: BTreeMap<String, ManifestTexture>,
+    pub samplers: BTreeMap<String, ManifestSampler>,
+    pub vertex_format: Option<ManifestVertexFormat>,
+    pub outputs: BTreeMap<String, ManifestOutput>,
+    pub pipeline: Option<ManifestPipeline>
+}
+
+impl Default for Manifest
+{
+    fn default() -> Self
+    {
+        Manifest {
+            schema_version: SCHEMA_VERSION,
+            cbuffers: BTreeMap::new(),
+            structs: BTreeMap::new(),
+            textures: BTreeMap::new(),
+            samplers: BTreeMap::new(),
+            vertex_format: None,
+            outputs: BTreeMap::new(),
+            pipeline: None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use bp3d_sal::ast::tree::{BaseType, TextureType, VectorType};
+    use crate::{Manifest, ManifestCBuffer, ManifestTexture, Member, TextureObject, TextureObjectType};
+
+    #[test]
+    fn manifest_json()
+    {
+        let mut manifest = Manifest::default();
+        manifest.cbuffers.insert("PerFrame".into(), ManifestCBuffer {
+            slot: 1,
+            size: 80,
+            members: vec![Member { name: "Time".into(), ty: "float".into(), offset: Some(64) }]
+        });
+        manifest.textures.insert("Base".into(), ManifestTexture::new(2, TextureObject {
+            ty: TextureObjectType::T2D,
+            value: TextureType::Vector(VectorType { item: BaseType::Float, size: 4 }),
+            array_size: None
+        }));
+        let json = serde_json::to_value(&manifest).unwrap();
+        assert_eq!(json["cbuffers"]["PerFrame"]["members"][0], serde_json::json!({"name": "Time", "type": "float", "offset": 64}));
+        assert_eq!(json["textures"]["Base"]["dimension"], "T2D");
+        assert_eq!(json["textures"]["Base"]["format"], serde_json::json!({"Vector": {"item": "Float", "size": 4}}));
+        assert!(serde_json::from_value::<Manifest>(json).unwrap() == manifest);
+    }
+}
@@ -28,24 +28,105 @@
 
 use serde::Deserialize;
 use serde::Serialize;
-use bp3d_sal::ast::tree::TextureType;
+use bp3d_sal::ast::tree::{AddressMode, Filter, TextureType};
 use crate::{FromBpx, ToBpx};
 
-#[derive(Copy, Clone, Deserialize, Serialize)]
+//Serialized by index in shader pack symbols: new variants must be appended.
+#[derive(Copy, Clone, PartialEq, Deserialize, Serialize)]
 pub enum TextureObjectType
 {
     T3D,
     T2D,
     T2DArray,
-    TCube
+    TCube,
+    T1D,
+    T2DMS
 }
 
-#[derive(Copy, Clone, Deserialize, Serialize)]
+#[derive(Copy, Clone, PartialEq, Deserialize, Serialize)]
 pub struct TextureObject
 {
     pub ty: TextureObjectType,
-    pub value: TextureType
+    pub value: TextureType,
+    /// Number of elements of a texture array, None for a single texture.
+    #[serde(default)]
+    pub array_size: Option<u32>,
+    /// Number of samples of a multisample texture, None for other textures.
+    #[serde(default)]
+    pub samples: Option<u32>
 }
 
 impl ToBpx for TextureObject {}
 impl FromBpx for TextureObject {}
+
+/// Sampler configuration declared by a `samplerstate` block.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SamplerObject
+{
+    pub min_filter: Filter,
+    pub mag_filter: Filter,
+    pub address_u: AddressMode,
+    pub address_v: AddressMode,
+    pub address_w: AddressMode,
+    pub anisotropy: u32,
+    /// True for comparison samplers (`SamplerCmp`), used to sample depth textures.
+    #[serde(default)]
+    pub comparison: bool
+}
+
+impl ToBpx for SamplerObject {}
+impl FromBpx for SamplerObject {}
+
+#[cfg(test)]
+mod tests
+{
+    use bp3d_sal::ast::tree::{AddressMode, BaseType, Filter, TextureType, VectorType};
+    use crate::{FromBpx, SamplerObject, TextureObject, TextureObjectType, ToBpx, validate_roundtrip};
+
+    #[test]
+    fn sampler_roundtrip()
+    {
+        let obj = SamplerObject {
+            min_filter: Filter::Linear,
+            mag_filter: Filter::Nearest,
+            address_u: AddressMode::Repeat,
+            address_v: AddressMode::Clamp,
+            address_w: AddressMode::MirroredRepeat,
+            anisotropy: 16,
+            comparison: false
+        };
+        for debug in [false, true] {
+            assert_eq!(SamplerObject::from_bpx(&obj.to_bpx(debug).unwrap()).unwrap(), obj);
+        }
+        validate_roundtrip(&obj);
+    }
+
+    #[test]
+    fn texture_roundtrip()
+    {
+        validate_roundtrip(&TextureObject {
+            ty: TextureObjectType::T2DArray,
+            value: TextureType::Vector(VectorType {
+                item: BaseType::Float,
+                size: 4
+            }),
+            array_size: Some(8),
+            samples: None
+        });
+        validate_roundtrip(&TextureObject {
+            ty: TextureObjectType::TCube,
+            value: TextureType::Scalar(BaseType::Float),
+            array_size: None,
+            samples: None
+        });
+        validate_roundtrip(&TextureObject {
+            ty: TextureObjectType::T2DMS,
+            value: TextureType::Vector(VectorType {
+                item: BaseType::Float,
+                size: 4
+            }),
+            array_size: None,
+            samples: Some(4)
+        });
+    }
+}
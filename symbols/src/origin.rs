@@ -0,0 +1,239 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Origin of the symbols and stages of a shader pack: the unit file, line and chain of modules
+//! a symbol was imported through.
+//!
+//! Origins are stored in a dedicated BPX section: a little endian u32 count of symbol records,
+//! the symbol records, a u32 count of stage records and the stage records. Each record starts
+//! with its u32 length so that readers skip fields appended by newer writers. Strings are
+//! stored as a u16 length followed by UTF-8.
+
+use std::io::{Error, ErrorKind};
+use serde::{Deserialize, Serialize};
+use crate::resource::Cursor;
+
+/// BPX section type holding the origins of the symbols of a shader pack.
+pub const ORIGIN_SECTION_TYPE: u8 = 0xFA;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+pub struct Origin
+{
+    pub file: String,
+    pub line: Option<u32>,
+    /// Modules the symbol was imported through, outermost first; empty for local symbols.
+    pub modules: Vec<String>
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SymbolOrigin
+{
+    pub name: String,
+    pub origin: Origin
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StageOrigin
+{
+    pub stage: String,
+    pub file: String
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Origins
+{
+    pub symbols: Vec<SymbolOrigin>,
+    pub stages: Vec<StageOrigin>
+}
+
+pub(crate) fn write_str(buf: &mut Vec<u8>, s: &str)
+{
+    buf.extend((s.len() as u16).to_le_bytes());
+    buf.extend(s.as_bytes());
+}
+
+pub(crate) fn write_record(buf: &mut Vec<u8>, record: Vec<u8>)
+{
+    buf.extend((record.len() as u32).to_le_bytes());
+    buf.extend(record);
+}
+
+impl<'a> Cursor<'a>
+{
+    pub(crate) fn take_str(&mut self) -> Result<String, Error>
+    {
+        let len = u16::from_le_bytes(self.take_array()?);
+        String::from_utf8(self.take(len as usize)?.into())
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))
+    }
+
+    pub(crate) fn take_record(&mut self) -> Result<Cursor<'a>, Error>
+    {
+        let len = u32::from_le_bytes(self.take_array()?);
+        Ok(Cursor(self.take(len as usize)?))
+    }
+}
+
+impl Origins
+{
+    pub fn get_symbol(&self, name: &str) -> Option<&Origin>
+    {
+        self.symbols.iter().find(|v| v.name == name).map(|v| &v.origin)
+    }
+
+    pub fn get_stage(&self, stage: &str) -> Option<&str>
+    {
+        self.stages.iter().find(|v| v.stage == stage).map(|v| &*v.file)
+    }
+
+    pub fn encode(&self) -> Vec<u8>
+    {
+        let mut buf = Vec::new();
+        buf.extend((self.symbols.len() as u32).to_le_bytes());
+        for sym in &self.symbols {
+            let mut record = Vec::new();
+            write_str(&mut record, &sym.name);
+            write_str(&mut record, &sym.origin.file);
+            //Line 0 does not exist: it marks an unknown line.
+            record.extend(sym.origin.line.unwrap_or(0).to_le_bytes());
+            record.extend((sym.origin.modules.len() as u16).to_le_bytes());
+            for module in &sym.origin.modules {
+                write_str(&mut record, module);
+            }
+            write_record(&mut buf, record);
+        }
+        buf.extend((self.stages.len() as u32).to_le_bytes());
+        for stage in &self.stages {
+            let mut record = Vec::new();
+            write_str(&mut record, &stage.stage);
+            write_str(&mut record, &stage.file);
+            write_record(&mut buf, record);
+        }
+        buf
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Origins, Error>
+    {
+        let mut cursor = Cursor(data);
+        let mut origins = Origins::default();
+        let count = u32::from_le_bytes(cursor.take_array()?);
+        for _ in 0..count {
+            let mut record = cursor.take_record()?;
+            let name = record.take_str()?;
+            let file = record.take_str()?;
+            let line = match u32::from_le_bytes(record.take_array()?) {
+                0 => None,
+                v => Some(v)
+            };
+            let count = u16::from_le_bytes(record.take_array()?);
+            let mut modules = Vec::new();
+            for _ in 0..count {
+                modules.push(record.take_str()?);
+            }
+            origins.symbols.push(SymbolOrigin {
+                name,
+                origin: Origin { file, line, modules }
+            });
+        }
+        let count = u32::from_le_bytes(cursor.take_array()?);
+        for _ in 0..count {
+            let mut record = cursor.take_record()?;
+            let stage = record.take_str()?;
+            let file = record.take_str()?;
+            origins.stages.push(StageOrigin { stage, file });
+        }
+        Ok(origins)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::{Origin, Origins, StageOrigin, SymbolOrigin};
+
+    #[test]
+    fn round_trip()
+    {
+        let origins = Origins {
+            symbols: vec![
+                SymbolOrigin {
+                    name: "ModelView".into(),
+                    origin: Origin {
+                        file: "common.sal".into(),
+                        line: Some(3),
+                        modules: vec!["Lighting".into(), "Camera".into()]
+                    }
+                },
+                SymbolOrigin {
+                    name: "Color".into(),
+                    origin: Origin {
+                        file: "shader.glsl".into(),
+                        line: None,
+                        modules: Vec::new()
+                    }
+                }
+            ],
+            stages: vec![StageOrigin {
+                stage: "Vertex".into(),
+                file: "shader.glsl".into()
+            }]
+        };
+        let data = origins.encode();
+        assert_eq!(Origins::decode(&data).unwrap(), origins);
+        assert_eq!(origins.get_symbol("ModelView").unwrap().modules.len(), 2);
+        assert_eq!(origins.get_stage("Vertex"), Some("shader.glsl"));
+        assert!(origins.get_symbol("Missing").is_none());
+        assert!(Origins::decode(&data[..data.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn skip_unknown_fields()
+    {
+        //A record of a newer writer with an extra trailing field.
+        let mut data = Vec::new();
+        data.extend(1u32.to_le_bytes());
+        let mut record = Vec::new();
+        record.extend(4u16.to_le_bytes());
+        record.extend(b"Time");
+        record.extend(6u16.to_le_bytes());
+        record.extend(b"a.glsl");
+        record.extend(12u32.to_le_bytes());
+        record.extend(0u16.to_le_bytes());
+        record.extend([0xAB; 5]);
+        data.extend((record.len() as u32).to_le_bytes());
+        data.extend(record);
+        data.extend(0u32.to_le_bytes());
+        let origins = Origins::decode(&data).unwrap();
+        assert_eq!(origins.get_symbol("Time"), Some(&Origin {
+            file: "a.glsl".into(),
+            line: Some(12),
+            modules: Vec::new()
+        }));
+    }
+}
@@ -26,12 +26,12 @@
 // NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
 // SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
-use bp3d_sal::ast::tree::{BaseType, BlendFactor, BlendOperator, VectorType};
+use bp3d_sal::ast::tree::{BaseType, BlendFactor, BlendOperator, TargetFormat, VectorType};
 use serde::Serialize;
 use serde::Deserialize;
 use crate::{FromBpx, ToBpx};
 
-#[derive(Copy, Clone, Serialize, Deserialize)]
+#[derive(Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub struct BlendfuncObject
 {
     pub src_color: BlendFactor,
@@ -42,19 +42,102 @@ pub struct BlendfuncObject
     pub alpha_op: BlendOperator
 }
 
-#[derive(Copy, Clone, Serialize, Deserialize)]
+#[derive(Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub enum OutputPropType
 {
     Vector(VectorType),
     Scalar(BaseType)
 }
 
-#[derive(Copy, Clone, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub struct OutputObject
 {
     pub blendfunc: Option<BlendfuncObject>,
-    pub ty: OutputPropType
+    pub ty: OutputPropType,
+    pub deprecated: Option<String>,
+    /// HLSL semantic of the render target (`SV_Target0`).
+    #[serde(default)]
+    pub semantic: Option<String>,
+    /// Format hint of the render target, used by the engine to validate attachments.
+    #[serde(default)]
+    pub format: Option<TargetFormat>
 }
 
 impl ToBpx for OutputObject {}
 impl FromBpx for OutputObject {}
+
+#[cfg(test)]
+mod tests
+{
+    use bp3d_sal::ast::tree::{BaseType, BlendFactor, BlendOperator, TargetFormat, VectorType};
+    use crate::{BlendfuncObject, FromBpx, OutputObject, OutputPropType, PropObject, PropType, StructObject, ToBpx, validate_roundtrip};
+
+    #[test]
+    fn semantic_roundtrip()
+    {
+        let obj = OutputObject {
+            blendfunc: None,
+            ty: OutputPropType::Vector(VectorType {
+                item: BaseType::Float,
+                size: 4
+            }),
+            deprecated: None,
+            semantic: Some("SV_Target1".into()),
+            format: Some(TargetFormat::Rgba16F)
+        };
+        let obj = OutputObject::from_bpx(&obj.to_bpx(false).unwrap()).unwrap();
+        assert_eq!(obj.semantic.as_deref(), Some("SV_Target1"));
+        assert_eq!(obj.format, Some(TargetFormat::Rgba16F));
+        let vformat = StructObject {
+            size: 12,
+            props: vec![PropObject {
+                name: "Pos".into(),
+                offset: 0,
+                ty: PropType::Vector(VectorType {
+                    item: BaseType::Float,
+                    size: 3
+                }),
+                semantic: Some("POSITION".into()),
+                default: None,
+                location: Some(2)
+            }],
+            deprecated: None,
+            shared: false,
+            layout: None
+        };
+        for debug in [false, true] {
+            let obj = StructObject::from_bpx(&vformat.to_bpx(debug).unwrap()).unwrap();
+            assert_eq!(obj.props[0].semantic.as_deref(), Some("POSITION"));
+            assert_eq!(obj.props[0].location, Some(2));
+        }
+    }
+
+    #[test]
+    fn roundtrip()
+    {
+        validate_roundtrip(&OutputObject {
+            blendfunc: Some(BlendfuncObject {
+                src_color: BlendFactor::SrcAlpha,
+                dst_color: BlendFactor::OneMinusSrcAlpha,
+                src_alpha: BlendFactor::One,
+                dst_alpha: BlendFactor::Zero,
+                color_op: BlendOperator::Add,
+                alpha_op: BlendOperator::Add
+            }),
+            ty: OutputPropType::Vector(VectorType {
+                item: BaseType::Float,
+                size: 4
+            }),
+            deprecated: Some("use Albedo instead".into()),
+            semantic: Some("SV_Target0".into()),
+            format: Some(TargetFormat::Rgba8Srgb)
+        });
+        validate_roundtrip(&OutputObject {
+            blendfunc: None,
+            ty: OutputPropType::Scalar(BaseType::Uint),
+            deprecated: None,
+            semantic: None,
+            format: None
+        });
+    }
+}
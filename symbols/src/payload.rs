@@ -0,0 +1,119 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+
+//! Kind of code stored in the shader stages of a pack.
+//!
+//! Packs whose stages do not hold the default payload of their BPX target (for example SPIR-V
+//! modules for an OpenGL target) record it in a dedicated BPX section holding a single byte.
+
+use std::fmt::{Display, Formatter};
+use std::io::{Error, ErrorKind};
+use bpx::shader::Target;
+
+/// BPX section type holding the payload kind of a shader pack.
+pub const PAYLOAD_SECTION_TYPE: u8 = 0xF6;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PayloadKind
+{
+    Glsl,
+    Spirv,
+    /// Code of an API this crate does not know.
+    Binary
+}
+
+impl PayloadKind
+{
+    /// Returns the payload kind of packs which have no payload section.
+    pub fn from_target(target: Target) -> PayloadKind
+    {
+        match target {
+            Target::GL40 | Target::GL42 | Target::GL46 | Target::ES31 => PayloadKind::Glsl,
+            Target::VK10 => PayloadKind::Spirv,
+            _ => PayloadKind::Binary
+        }
+    }
+
+    pub fn name(&self) -> &'static str
+    {
+        match self {
+            PayloadKind::Glsl => "GLSL",
+            PayloadKind::Spirv => "SPIR-V",
+            PayloadKind::Binary => "binary"
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8>
+    {
+        vec![match self {
+            PayloadKind::Glsl => 0,
+            PayloadKind::Spirv => 1,
+            PayloadKind::Binary => 2
+        }]
+    }
+
+    pub fn decode(data: &[u8]) -> Result<PayloadKind, Error>
+    {
+        match data.first() {
+            Some(0) => Ok(PayloadKind::Glsl),
+            Some(1) => Ok(PayloadKind::Spirv),
+            Some(2) => Ok(PayloadKind::Binary),
+            Some(v) => Err(Error::new(ErrorKind::InvalidData, format!("unknown payload kind {}", v))),
+            None => Err(Error::new(ErrorKind::UnexpectedEof, "empty payload section"))
+        }
+    }
+}
+
+impl Display for PayloadKind
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+    {
+        f.write_str(self.name())
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use bpx::shader::Target;
+    use crate::PayloadKind;
+
+    #[test]
+    fn payloads()
+    {
+        for kind in [PayloadKind::Glsl, PayloadKind::Spirv, PayloadKind::Binary] {
+            assert_eq!(PayloadKind::decode(&kind.encode()).unwrap(), kind);
+        }
+        assert!(PayloadKind::decode(&[]).is_err());
+        assert!(PayloadKind::decode(&[42]).is_err());
+        assert_eq!(PayloadKind::from_target(Target::GL42), PayloadKind::Glsl);
+        assert_eq!(PayloadKind::from_target(Target::VK10), PayloadKind::Spirv);
+        assert_eq!(PayloadKind::Spirv.to_string(), "SPIR-V");
+    }
+}
@@ -27,18 +27,135 @@
 // SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
 use serde::{Serialize, Deserialize};
-use bp3d_sal::ast::tree::{CullingMode, RenderMode};
+use bp3d_sal::ast::tree::{CompareFunc, CullingMode, RenderMode, StencilOp};
 use crate::{FromBpx, ToBpx};
 
-#[derive(Copy, Clone, Serialize, Deserialize)]
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub struct PipelineObject
 {
     pub depth_enable: bool,
     pub depth_write_enable: bool,
     pub scissor_enable: bool,
     pub render_mode: RenderMode,
-    pub culling_mode: CullingMode
+    pub culling_mode: CullingMode,
+    /// False when the pipeline fetches vertices itself (vertex pulling) instead of using a vertex format.
+    #[serde(default = "default_true")]
+    pub vertex_input: bool,
+    /// True when the pipeline writes gl_Layer/gl_ViewportIndex (ex: instanced stereo).
+    #[serde(default)]
+    pub layered_rendering: bool,
+    #[serde(default = "default_viewport_count")]
+    pub viewport_count: u32,
+    #[serde(default = "default_depth_func")]
+    pub depth_func: CompareFunc,
+    #[serde(default)]
+    pub stencil_enable: bool,
+    #[serde(default = "default_stencil_func")]
+    pub stencil_func: CompareFunc,
+    #[serde(default = "default_stencil_op")]
+    pub stencil_pass_op: StencilOp,
+    #[serde(default = "default_stencil_op")]
+    pub stencil_fail_op: StencilOp,
+    #[serde(default)]
+    pub stencil_ref: u32,
+    #[serde(default)]
+    pub depth_bias: f32,
+    #[serde(default)]
+    pub slope_scaled_depth_bias: f32
+}
+
+fn default_true() -> bool
+{
+    true
+}
+
+fn default_viewport_count() -> u32
+{
+    1
+}
+
+fn default_depth_func() -> CompareFunc
+{
+    CompareFunc::Less
+}
+
+fn default_stencil_func() -> CompareFunc
+{
+    CompareFunc::Always
+}
+
+fn default_stencil_op() -> StencilOp
+{
+    StencilOp::Keep
 }
 
 impl ToBpx for PipelineObject {}
 impl FromBpx for PipelineObject {}
+
+#[cfg(test)]
+mod tests
+{
+    use bp3d_sal::ast::tree::{CompareFunc, CullingMode, RenderMode, StencilOp};
+    use crate::{FromBpx, PipelineObject, ToBpx, validate_roundtrip};
+
+    fn pipeline() -> PipelineObject
+    {
+        PipelineObject {
+            depth_enable: true,
+            depth_write_enable: true,
+            scissor_enable: false,
+            render_mode: RenderMode::Triangles,
+            culling_mode: CullingMode::BackFace,
+            vertex_input: true,
+            layered_rendering: false,
+            viewport_count: 1,
+            depth_func: CompareFunc::Less,
+            stencil_enable: false,
+            stencil_func: CompareFunc::Always,
+            stencil_pass_op: StencilOp::Keep,
+            stencil_fail_op: StencilOp::Keep,
+            stencil_ref: 0,
+            depth_bias: 0.0,
+            slope_scaled_depth_bias: 0.0
+        }
+    }
+
+    #[test]
+    fn layered_rendering_round_trip()
+    {
+        let obj = PipelineObject {
+            layered_rendering: true,
+            viewport_count: 2,
+            ..pipeline()
+        };
+        for debug in [false, true] {
+            let val = obj.to_bpx(debug).unwrap();
+            let obj1 = PipelineObject::from_bpx(&val).unwrap();
+            assert!(obj1.layered_rendering);
+            assert_eq!(obj1.viewport_count, 2);
+            assert!(obj1.vertex_input);
+        }
+        validate_roundtrip(&obj);
+    }
+
+    #[test]
+    fn stencil_round_trip()
+    {
+        let obj = PipelineObject {
+            depth_func: CompareFunc::GreaterEqual,
+            stencil_enable: true,
+            stencil_func: CompareFunc::NotEqual,
+            stencil_pass_op: StencilOp::IncrementWrap,
+            stencil_fail_op: StencilOp::Zero,
+            stencil_ref: 3,
+            depth_bias: -1.0,
+            slope_scaled_depth_bias: 2.5,
+            ..pipeline()
+        };
+        for debug in [false, true] {
+            let obj1 = PipelineObject::from_bpx(&obj.to_bpx(debug).unwrap()).unwrap();
+            assert!(obj1 == obj);
+        }
+        validate_roundtrip(&obj);
+    }
+}
@@ -0,0 +1,158 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Binary resources embedded in shader packs (lookup tables, precomputed data...).
+//!
+//! Resources are stored in a dedicated BPX section: a little endian u32 count followed by, for
+//! each resource, its name (u16 length + UTF-8), its content hash (u64), its size (u32) and
+//! its content.
+
+use std::io::{Error, ErrorKind};
+use serde::{Deserialize, Serialize};
+
+/// BPX section type holding the resources of a shader pack.
+pub const RESOURCE_SECTION_TYPE: u8 = 0xFB;
+
+/// Describes a resource without its content.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResourceObject
+{
+    pub name: String,
+    pub size: u32,
+    pub hash: u64
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Resource
+{
+    pub name: String,
+    pub data: Vec<u8>
+}
+
+/// 64 bits FNV-1a hash of a resource content.
+pub fn content_hash(data: &[u8]) -> u64
+{
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for b in data {
+        hash ^= *b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+impl Resource
+{
+    pub fn object(&self) -> ResourceObject
+    {
+        ResourceObject {
+            name: self.name.clone(),
+            size: self.data.len() as u32,
+            hash: content_hash(&self.data)
+        }
+    }
+}
+
+pub fn encode_resources(resources: &[Resource]) -> Vec<u8>
+{
+    let mut buf = Vec::new();
+    buf.extend((resources.len() as u32).to_le_bytes());
+    for res in resources {
+        buf.extend((res.name.len() as u16).to_le_bytes());
+        buf.extend(res.name.as_bytes());
+        buf.extend(content_hash(&res.data).to_le_bytes());
+        buf.extend((res.data.len() as u32).to_le_bytes());
+        buf.extend(&res.data);
+    }
+    buf
+}
+
+pub(crate) struct Cursor<'a>(pub(crate) &'a [u8]);
+
+impl<'a> Cursor<'a>
+{
+    pub(crate) fn take(&mut self, len: usize) -> Result<&'a [u8], Error>
+    {
+        if self.0.len() < len {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "truncated section"));
+        }
+        let (data, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(data)
+    }
+
+    pub(crate) fn take_array<const N: usize>(&mut self) -> Result<[u8; N], Error>
+    {
+        let mut arr = [0; N];
+        arr.copy_from_slice(self.take(N)?);
+        Ok(arr)
+    }
+}
+
+pub fn decode_resources(data: &[u8]) -> Result<Vec<Resource>, Error>
+{
+    let mut cursor = Cursor(data);
+    let count = u32::from_le_bytes(cursor.take_array()?);
+    let mut resources = Vec::new();
+    for _ in 0..count {
+        let len = u16::from_le_bytes(cursor.take_array()?);
+        let name = String::from_utf8(cursor.take(len as usize)?.into())
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        let hash = u64::from_le_bytes(cursor.take_array()?);
+        let size = u32::from_le_bytes(cursor.take_array()?);
+        let data = cursor.take(size as usize)?.to_vec();
+        if content_hash(&data) != hash {
+            return Err(Error::new(ErrorKind::InvalidData, format!("content hash mismatch for resource '{}'", name)));
+        }
+        resources.push(Resource { name, data });
+    }
+    Ok(resources)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::{decode_resources, encode_resources, Resource};
+
+    #[test]
+    fn round_trip()
+    {
+        let resources = vec![
+            Resource { name: "CurveLut".into(), data: (0..=255).collect() },
+            Resource { name: "Empty".into(), data: Vec::new() }
+        ];
+        let data = encode_resources(&resources);
+        assert_eq!(decode_resources(&data).unwrap(), resources);
+        let obj = resources[0].object();
+        assert_eq!(obj.size, 256);
+        assert_ne!(obj.hash, resources[1].object().hash);
+        let mut corrupted = data.clone();
+        corrupted[20] ^= 0xFF;
+        assert!(decode_resources(&corrupted).is_err());
+        assert!(decode_resources(&data[..data.len() - 1]).is_err());
+    }
+}
@@ -0,0 +1,226 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Versioned types of the JSON documents emitted by the SDK tools.
+//!
+//! Every document carries a `schema_version` field holding the major version of its schema.
+//! Within a major version fields may only be added, and new fields must be optional so that
+//! documents written by an older release still deserialize; anything else bumps
+//! [SCHEMA_VERSION].
+
+use serde::{Deserialize, Serialize};
+
+/// Major version of the JSON document schemas.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Names of the documents accepted by [json_schema].
+pub const SCHEMAS: &[&str] = &["error_report", "symbol_search", "target_list"];
+
+fn schema_version() -> u32
+{
+    SCHEMA_VERSION
+}
+
+/// Report of a failed shaderc build, as stored in reproducer bundles.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+pub struct ErrorReport
+{
+    //Reports written before versioning have no schema_version; they match version 1.
+    #[serde(default = "schema_version")]
+    pub schema_version: u32,
+    pub version: String,
+    pub target: String,
+    pub message: String,
+    pub causes: Vec<String>
+}
+
+impl ErrorReport
+{
+    pub fn new(version: &str, target: &str, error: &(dyn std::error::Error + 'static)) -> ErrorReport
+    {
+        let mut causes = Vec::new();
+        let mut source = error.source();
+        while let Some(e) = source {
+            causes.push(e.to_string());
+            source = e.source();
+        }
+        ErrorReport {
+            schema_version: SCHEMA_VERSION,
+            version: version.into(),
+            target: target.into(),
+            message: error.to_string(),
+            causes
+        }
+    }
+}
+
+/// A symbol found in an assembly by `shaderl find`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+pub struct SymbolMatch
+{
+    pub name: String,
+    pub kind: String,
+    pub register: Option<u8>,
+    pub stages: Vec<String>,
+    pub provider: Option<String>,
+    pub referenced_by: Vec<String>
+}
+
+/// Output of `shaderl find --json`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+pub struct SymbolSearch
+{
+    pub schema_version: u32,
+    pub symbols: Vec<SymbolMatch>
+}
+
+impl SymbolSearch
+{
+    pub fn new(symbols: Vec<SymbolMatch>) -> SymbolSearch
+    {
+        SymbolSearch {
+            schema_version: SCHEMA_VERSION,
+            symbols
+        }
+    }
+}
+
+/// Capabilities of a shaderc target.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+pub struct TargetInfo
+{
+    pub name: String,
+    /// Graphics API of the shaders produced by the target, None if it does not produce shaders.
+    pub api: Option<String>,
+    pub version: Option<String>,
+    pub supports_debug: bool,
+    pub supports_optimize: bool,
+    #[serde(default)]
+    pub supports_minify: bool,
+    pub supports_spirv: bool,
+    pub supports_compute: bool,
+    pub supports_int64: bool,
+    /// Format of the stage payloads in the output file.
+    pub payload_format: String,
+    pub max_viewports: u32,
+    /// Target written in the header of output BPX shader packages, None for non-shader outputs.
+    #[serde(default)]
+    pub bpx_target: Option<String>,
+    /// Names of the shader stages accepted by the target.
+    #[serde(default)]
+    pub supported_stages: Vec<String>
+}
+
+/// Output of `shaderc --targets-json`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+pub struct TargetList
+{
+    pub schema_version: u32,
+    pub targets: Vec<TargetInfo>
+}
+
+impl TargetList
+{
+    pub fn new(targets: Vec<TargetInfo>) -> TargetList
+    {
+        TargetList {
+            schema_version: SCHEMA_VERSION,
+            targets
+        }
+    }
+}
+
+/// Returns the JSON Schema of the named document, None if no such document exists.
+#[cfg(feature = "json-schema")]
+pub fn json_schema(name: &str) -> Option<String>
+{
+    let schema = match name {
+        "error_report" => schemars::schema_for!(ErrorReport),
+        "symbol_search" => schemars::schema_for!(SymbolSearch),
+        "target_list" => schemars::schema_for!(TargetList),
+        _ => return None
+    };
+    //A schema is plain data, serializing it cannot fail.
+    Some(serde_json::to_string_pretty(&schema).unwrap())
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::{ErrorReport, SCHEMA_VERSION, SymbolMatch, SymbolSearch};
+
+    //Fixtures are documents of the previous release; they must keep deserializing.
+    const ERROR_REPORT_V1: &str = include_str!("../fixtures/schema/v1/error_report.json");
+    const SYMBOL_SEARCH_V1: &str = include_str!("../fixtures/schema/v1/symbol_search.json");
+
+    #[test]
+    fn fixtures()
+    {
+        let report: ErrorReport = serde_json::from_str(ERROR_REPORT_V1).unwrap();
+        assert_eq!(report.schema_version, 1);
+        assert_eq!(report.causes.len(), 1);
+        let search: SymbolSearch = serde_json::from_str(SYMBOL_SEARCH_V1).unwrap();
+        assert_eq!(search.schema_version, 1);
+        assert_eq!(search.symbols[0].provider.as_deref(), Some("lit"));
+        //Reports of reproducer bundles written before versioning.
+        let legacy: ErrorReport = serde_json::from_str(r#"{"version": "0.1.0", "target": "GL40", "message": "failed", "causes": []}"#).unwrap();
+        assert_eq!(legacy.schema_version, 1);
+    }
+
+    #[test]
+    fn round_trip()
+    {
+        let search = SymbolSearch::new(vec![SymbolMatch {
+            name: "ModelView".into(),
+            kind: "Constant".into(),
+            register: None,
+            stages: vec!["vertex".into()],
+            provider: None,
+            referenced_by: Vec::new()
+        }]);
+        assert_eq!(search.schema_version, SCHEMA_VERSION);
+        let json = serde_json::to_string(&search).unwrap();
+        assert_eq!(serde_json::from_str::<SymbolSearch>(&json).unwrap(), search);
+    }
+
+    #[cfg(feature = "json-schema")]
+    #[test]
+    fn json_schema()
+    {
+        for name in crate::SCHEMAS {
+            let schema = crate::json_schema(name).unwrap();
+            assert!(schema.contains("schema_version"));
+        }
+        assert!(crate::json_schema("missing").is_none());
+    }
+}
@@ -0,0 +1,391 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Estimation of the encoded size of extended data objects without encoding them.
+//!
+//! The estimate follows the BPXSD layout: one type byte per value followed by its payload,
+//! containers store a one byte length, object entries are keyed by a 64 bits hash and debug
+//! builds additionally store the names of the fields of each structure in a `__debug__` array of
+//! strings. The root object of extended data has no type byte.
+//!
+//! The estimate is checked against the real encoding by [validate_roundtrip](crate::validate_roundtrip).
+
+use std::fmt::{Display, Formatter};
+use serde::ser::{
+    SerializeMap, SerializeSeq, SerializeStruct, SerializeStructVariant, SerializeTuple,
+    SerializeTupleStruct, SerializeTupleVariant
+};
+use serde::Serialize;
+
+const SIZE_TYPE: usize = 1;
+const SIZE_CONTAINER: usize = SIZE_TYPE + 1;
+const SIZE_HASH: usize = 8;
+const SIZE_VARIANT: usize = SIZE_TYPE + 1;
+
+#[derive(Debug)]
+pub struct SizeError(String);
+
+impl Display for SizeError
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+    {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for SizeError {}
+
+impl serde::ser::Error for SizeError
+{
+    fn custom<T: Display>(msg: T) -> Self
+    {
+        SizeError(msg.to_string())
+    }
+}
+
+/// Returns the estimated size in bytes of the BPXSD encoding of `value`.
+pub fn encoded_size<T: Serialize + ?Sized>(value: &T, debug: bool) -> Result<usize, SizeError>
+{
+    value.serialize(SizeCounter { debug })
+}
+
+/// Returns the estimated size in bytes of `value` encoded as the root object of extended data,
+/// which is written without its type byte.
+pub fn encoded_root_size<T: Serialize + ?Sized>(value: &T, debug: bool) -> Result<usize, SizeError>
+{
+    Ok(encoded_size(value, debug)? - SIZE_TYPE)
+}
+
+#[derive(Copy, Clone)]
+struct SizeCounter
+{
+    debug: bool
+}
+
+struct Compound
+{
+    debug: bool,
+    size: usize,
+    /// Size of the `__debug__` array of a structure, None if it has no names to store.
+    names: Option<usize>
+}
+
+impl Compound
+{
+    fn new(debug: bool, size: usize) -> Compound
+    {
+        Compound { debug, size, names: None }
+    }
+
+    fn value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SizeError>
+    {
+        self.size += value.serialize(SizeCounter { debug: self.debug })?;
+        Ok(())
+    }
+
+    fn field<T: Serialize + ?Sized>(&mut self, key: &str, value: &T) -> Result<(), SizeError>
+    {
+        self.size += SIZE_HASH;
+        if self.debug {
+            let names = self.names.get_or_insert(SIZE_HASH + SIZE_CONTAINER);
+            *names += SIZE_TYPE + key.len() + 1;
+        }
+        self.value(value)
+    }
+
+    fn finish(self) -> usize
+    {
+        self.size + self.names.unwrap_or(0)
+    }
+}
+
+macro_rules! fixed_size {
+    ($($name: ident($ty: ty) => $size: expr),*) => {
+        $(
+            fn $name(self, _: $ty) -> Result<usize, SizeError>
+            {
+                Ok(SIZE_TYPE + $size)
+            }
+        )*
+    };
+}
+
+impl serde::Serializer for SizeCounter
+{
+    type Ok = usize;
+    type Error = SizeError;
+    type SerializeSeq = Compound;
+    type SerializeTuple = Compound;
+    type SerializeTupleStruct = Compound;
+    type SerializeTupleVariant = Compound;
+    type SerializeMap = Compound;
+    type SerializeStruct = Compound;
+    type SerializeStructVariant = Compound;
+
+    fixed_size!(
+        serialize_bool(bool) => 1,
+        serialize_i8(i8) => 1,
+        serialize_i16(i16) => 2,
+        serialize_i32(i32) => 4,
+        serialize_i64(i64) => 8,
+        serialize_u8(u8) => 1,
+        serialize_u16(u16) => 2,
+        serialize_u32(u32) => 4,
+        serialize_u64(u64) => 8,
+        serialize_f32(f32) => 4,
+        serialize_f64(f64) => 8,
+        serialize_char(char) => 4
+    );
+
+    fn serialize_str(self, v: &str) -> Result<usize, SizeError>
+    {
+        Ok(SIZE_TYPE + v.len() + 1)
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<usize, SizeError>
+    {
+        Ok(SIZE_CONTAINER + v.len() * (SIZE_TYPE + 1))
+    }
+
+    fn serialize_none(self) -> Result<usize, SizeError>
+    {
+        Ok(SIZE_TYPE)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<usize, SizeError>
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<usize, SizeError>
+    {
+        Ok(SIZE_TYPE)
+    }
+
+    fn serialize_unit_struct(self, _: &'static str) -> Result<usize, SizeError>
+    {
+        Ok(SIZE_TYPE)
+    }
+
+    fn serialize_unit_variant(self, _: &'static str, _: u32, _: &'static str) -> Result<usize, SizeError>
+    {
+        Ok(SIZE_VARIANT)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(self, _: &'static str, value: &T) -> Result<usize, SizeError>
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(self, _: &'static str, _: u32, _: &'static str, value: &T) -> Result<usize, SizeError>
+    {
+        Ok(SIZE_CONTAINER + SIZE_VARIANT + value.serialize(self)?)
+    }
+
+    fn serialize_seq(self, _: Option<usize>) -> Result<Compound, SizeError>
+    {
+        Ok(Compound::new(self.debug, SIZE_CONTAINER))
+    }
+
+    fn serialize_tuple(self, _: usize) -> Result<Compound, SizeError>
+    {
+        Ok(Compound::new(self.debug, SIZE_CONTAINER))
+    }
+
+    fn serialize_tuple_struct(self, _: &'static str, _: usize) -> Result<Compound, SizeError>
+    {
+        Ok(Compound::new(self.debug, SIZE_CONTAINER))
+    }
+
+    fn serialize_tuple_variant(self, _: &'static str, _: u32, _: &'static str, _: usize) -> Result<Compound, SizeError>
+    {
+        Ok(Compound::new(self.debug, SIZE_CONTAINER + SIZE_VARIANT + SIZE_CONTAINER))
+    }
+
+    fn serialize_map(self, _: Option<usize>) -> Result<Compound, SizeError>
+    {
+        Ok(Compound::new(self.debug, SIZE_CONTAINER))
+    }
+
+    fn serialize_struct(self, _: &'static str, _: usize) -> Result<Compound, SizeError>
+    {
+        Ok(Compound::new(self.debug, SIZE_CONTAINER))
+    }
+
+    fn serialize_struct_variant(self, _: &'static str, _: u32, _: &'static str, _: usize) -> Result<Compound, SizeError>
+    {
+        Ok(Compound::new(self.debug, SIZE_CONTAINER + SIZE_VARIANT + SIZE_CONTAINER))
+    }
+}
+
+impl SerializeSeq for Compound
+{
+    type Ok = usize;
+    type Error = SizeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SizeError>
+    {
+        self.value(value)
+    }
+
+    fn end(self) -> Result<usize, SizeError>
+    {
+        Ok(self.size)
+    }
+}
+
+impl SerializeTuple for Compound
+{
+    type Ok = usize;
+    type Error = SizeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SizeError>
+    {
+        self.value(value)
+    }
+
+    fn end(self) -> Result<usize, SizeError>
+    {
+        Ok(self.size)
+    }
+}
+
+impl SerializeTupleStruct for Compound
+{
+    type Ok = usize;
+    type Error = SizeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SizeError>
+    {
+        self.value(value)
+    }
+
+    fn end(self) -> Result<usize, SizeError>
+    {
+        Ok(self.size)
+    }
+}
+
+impl SerializeTupleVariant for Compound
+{
+    type Ok = usize;
+    type Error = SizeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SizeError>
+    {
+        self.value(value)
+    }
+
+    fn end(self) -> Result<usize, SizeError>
+    {
+        Ok(self.size)
+    }
+}
+
+impl SerializeMap for Compound
+{
+    type Ok = usize;
+    type Error = SizeError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, _: &T) -> Result<(), SizeError>
+    {
+        //Keys are stored as hashes; their names are only known for structures.
+        self.size += SIZE_HASH;
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SizeError>
+    {
+        self.value(value)
+    }
+
+    fn end(self) -> Result<usize, SizeError>
+    {
+        Ok(self.size)
+    }
+}
+
+impl SerializeStruct for Compound
+{
+    type Ok = usize;
+    type Error = SizeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), SizeError>
+    {
+        self.field(key, value)
+    }
+
+    fn end(self) -> Result<usize, SizeError>
+    {
+        Ok(self.finish())
+    }
+}
+
+impl SerializeStructVariant for Compound
+{
+    type Ok = usize;
+    type Error = SizeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), SizeError>
+    {
+        self.field(key, value)
+    }
+
+    fn end(self) -> Result<usize, SizeError>
+    {
+        Ok(self.finish())
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use serde::Serialize;
+    use crate::encoded_size;
+
+    #[derive(Serialize)]
+    struct Prop
+    {
+        name: String,
+        offset: u32
+    }
+
+    #[test]
+    fn sizes()
+    {
+        assert_eq!(encoded_size(&0u32, false).unwrap(), 5);
+        assert_eq!(encoded_size("abc", false).unwrap(), 5);
+        let prop = Prop { name: "abc".into(), offset: 0 };
+        // container + (hash + string) + (hash + u32)
+        assert_eq!(encoded_size(&prop, false).unwrap(), 2 + 8 + 5 + 8 + 5);
+        // debug adds a __debug__ array with the field names: "name\0" and "offset\0"
+        assert_eq!(encoded_size(&prop, true).unwrap(), 2 + 8 + 5 + 8 + 5 + (8 + 2 + 1 + 5 + 1 + 7));
+        let props: Vec<Prop> = (0..4000).map(|i| Prop { name: format!("Member{}", i), offset: i * 16 }).collect();
+        assert!(encoded_size(&props, false).unwrap() > 4000 * 23);
+    }
+}
@@ -28,10 +28,10 @@
 
 use serde::Deserialize;
 use serde::Serialize;
-use bp3d_sal::ast::tree::{BaseType, VectorType};
+use bp3d_sal::ast::tree::{BaseType, DefaultValue, VectorType};
 use crate::{FromBpx, Refs, ToBpx};
 
-#[derive(Copy, Clone, Serialize, Deserialize)]
+#[derive(Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub enum ArrayItemType
 {
     Vector(VectorType),
@@ -39,7 +39,7 @@ pub enum ArrayItemType
     StructRef(u16), //Index of referenced symbol in symbol table.
 }
 
-#[derive(Copy, Clone, Serialize, Deserialize)]
+#[derive(Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub enum PropType
 {
     Scalar(BaseType),
@@ -52,19 +52,46 @@ pub enum PropType
     }
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub struct PropObject
 {
     pub name: String,
     pub offset: u32,
-    pub ty: PropType
+    pub ty: PropType,
+    /// HLSL semantic of a vertex format member, None for other structs.
+    #[serde(default)]
+    pub semantic: Option<String>,
+    /// Default value of a constant buffer member, None if the member has no default.
+    #[serde(default)]
+    pub default: Option<DefaultValue>,
+    /// Vertex attribute location of a vertex format member, None for other structs.
+    #[serde(default)]
+    pub location: Option<u32>
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+/// Rule used to compute the member offsets of a buffer struct.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LayoutRule
+{
+    /// GLSL std140, used by uniform buffers.
+    Std140,
+    /// GLSL std430, arrays and structs are not padded to a vec4.
+    Std430,
+    /// Every member is aligned to the size of its scalar component.
+    Scalar
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub struct StructObject
 {
     pub size: u32,
-    pub props: Vec<PropObject>
+    pub props: Vec<PropObject>,
+    pub deprecated: Option<String>,
+    #[serde(default)]
+    pub shared: bool,
+    /// Layout rule of the member offsets, None for vertex formats and packs which predate it.
+    #[serde(default)]
+    pub layout: Option<LayoutRule>
 }
 
 impl ToBpx for StructObject {}
@@ -117,7 +144,61 @@ impl Refs for StructObject {
     fn rewrite_refs<F: Fn(u16) -> u16>(&self, f: F) -> Self {
         StructObject {
             size: self.size,
-            props: self.props.iter().map(|v| rewrite_refs(v, &f)).collect()
+            props: self.props.iter().map(|v| rewrite_refs(v, &f)).collect(),
+            deprecated: self.deprecated.clone(),
+            shared: self.shared,
+            layout: self.layout
         }
     }
 }
+
+#[cfg(test)]
+mod tests
+{
+    use bp3d_sal::ast::tree::{BaseType, DefaultValue, VectorType};
+    use crate::{ArrayItemType, LayoutRule, PropObject, PropType, Refs, StructObject, validate_roundtrip};
+
+    fn prop(name: &str, offset: u32, ty: PropType) -> PropObject
+    {
+        PropObject {
+            name: name.into(),
+            offset,
+            ty,
+            semantic: None,
+            default: None,
+            location: None
+        }
+    }
+
+    #[test]
+    fn roundtrip()
+    {
+        let vec4 = VectorType {
+            item: BaseType::Float,
+            size: 4
+        };
+        let obj = StructObject {
+            size: 144,
+            props: vec![
+                PropObject {
+                    default: Some(DefaultValue::Float(vec![1.0, 1.0, 1.0, 1.0])),
+                    ..prop("BaseColor", 0, PropType::Vector(vec4))
+                },
+                prop("Roughness", 16, PropType::Scalar(BaseType::Float)),
+                prop("Transform", 32, PropType::Matrix(vec4)),
+                prop("Light", 96, PropType::StructRef(3)),
+                prop("Weights", 112, PropType::Array {
+                    size: 2,
+                    ty: ArrayItemType::StructRef(5)
+                })
+            ],
+            deprecated: Some("use PerMaterial2 instead".into()),
+            shared: true,
+            layout: Some(LayoutRule::Std430)
+        };
+        validate_roundtrip(&obj);
+        let obj = obj.rewrite_refs(|v| v + 1);
+        assert_eq!(obj.list_refs(), [4, 6]);
+        validate_roundtrip(&obj);
+    }
+}
@@ -0,0 +1,163 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Varyings of a shader pack: the variables passed from a stage to the next one.
+//!
+//! Varyings are stored in a dedicated BPX section: a little endian u32 count followed by the
+//! varying records. Each record starts with its u32 length so that readers skip fields appended
+//! by newer writers and contains the name (u16 length + UTF-8), the type (kind, base type and
+//! size as u8) and the interpolation (u8).
+
+use std::io::{Error, ErrorKind};
+use bp3d_sal::ast::tree::{BaseType, Interpolation, VectorType};
+use serde::{Deserialize, Serialize};
+use crate::origin::{write_record, write_str};
+use crate::resource::Cursor;
+
+/// BPX section type holding the varyings of a shader pack.
+pub const VARYING_SECTION_TYPE: u8 = 0xF8;
+
+//Position in this array is the encoded value of a base type.
+const BASE_TYPES: [BaseType; 7] = [
+    BaseType::Int,
+    BaseType::Float,
+    BaseType::Uint,
+    BaseType::Bool,
+    BaseType::Double,
+    BaseType::Int64,
+    BaseType::Uint64
+];
+
+const INTERPOLATIONS: [Interpolation; 3] = [
+    Interpolation::Smooth,
+    Interpolation::Flat,
+    Interpolation::NoPerspective
+];
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VaryingPropType
+{
+    Scalar(BaseType),
+    Vector(VectorType),
+    Matrix(VectorType)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VaryingObject
+{
+    pub name: String,
+    pub ty: VaryingPropType,
+    pub interpolation: Interpolation
+}
+
+fn encode_base_type(t: BaseType) -> u8
+{
+    //SAFETY: BASE_TYPES lists all base types.
+    BASE_TYPES.iter().position(|v| *v == t).unwrap() as u8
+}
+
+fn decode_index<T: Copy>(values: &[T], index: u8, what: &str) -> Result<T, Error>
+{
+    values.get(index as usize).copied()
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, format!("unknown {} {}", what, index)))
+}
+
+pub fn encode_varyings(varyings: &[VaryingObject]) -> Vec<u8>
+{
+    let mut buf = Vec::new();
+    buf.extend((varyings.len() as u32).to_le_bytes());
+    for v in varyings {
+        let mut record = Vec::new();
+        write_str(&mut record, &v.name);
+        let (kind, item, size) = match v.ty {
+            VaryingPropType::Scalar(t) => (0, t, 1),
+            VaryingPropType::Vector(t) => (1, t.item, t.size),
+            VaryingPropType::Matrix(t) => (2, t.item, t.size)
+        };
+        record.extend([kind, encode_base_type(item), size]);
+        //SAFETY: INTERPOLATIONS lists all interpolations.
+        record.push(INTERPOLATIONS.iter().position(|i| *i == v.interpolation).unwrap() as u8);
+        write_record(&mut buf, record);
+    }
+    buf
+}
+
+pub fn decode_varyings(data: &[u8]) -> Result<Vec<VaryingObject>, Error>
+{
+    let mut cursor = Cursor(data);
+    let count = u32::from_le_bytes(cursor.take_array()?);
+    let mut varyings = Vec::new();
+    for _ in 0..count {
+        let mut record = cursor.take_record()?;
+        let name = record.take_str()?;
+        let [kind, item, size, interpolation] = record.take_array()?;
+        let item = decode_index(&BASE_TYPES, item, "base type")?;
+        let ty = match kind {
+            0 => VaryingPropType::Scalar(item),
+            1 => VaryingPropType::Vector(VectorType { item, size }),
+            2 => VaryingPropType::Matrix(VectorType { item, size }),
+            _ => return Err(Error::new(ErrorKind::InvalidData, format!("unknown varying type {}", kind)))
+        };
+        varyings.push(VaryingObject {
+            name,
+            ty,
+            interpolation: decode_index(&INTERPOLATIONS, interpolation, "interpolation")?
+        });
+    }
+    Ok(varyings)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use bp3d_sal::ast::tree::{BaseType, Interpolation, VectorType};
+    use crate::{decode_varyings, encode_varyings, VaryingObject, VaryingPropType};
+
+    #[test]
+    fn round_trip()
+    {
+        let varyings = vec![
+            VaryingObject {
+                name: "Uv".into(),
+                ty: VaryingPropType::Vector(VectorType { item: BaseType::Float, size: 2 }),
+                interpolation: Interpolation::Smooth
+            },
+            VaryingObject {
+                name: "Layer".into(),
+                ty: VaryingPropType::Scalar(BaseType::Uint),
+                interpolation: Interpolation::Flat
+            }
+        ];
+        let data = encode_varyings(&varyings);
+        assert_eq!(decode_varyings(&data).unwrap(), varyings);
+        assert!(decode_varyings(&data[..data.len() - 1]).is_err());
+        let mut corrupted = data.clone();
+        *corrupted.last_mut().unwrap() = 7;
+        assert!(decode_varyings(&corrupted).is_err());
+    }
+}
@@ -0,0 +1,162 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Format version of the extended data of symbols.
+//!
+//! Every object encoded with [ToBpx] carries the version of the format it was written with in a
+//! `__version__` property. Objects without this property were written before versioning was
+//! introduced and are read as version 0. Fields added to the objects must keep a serde default so
+//! that older versions remain readable; objects written with a newer version are rejected as they
+//! may hold fields which would otherwise be silently dropped.
+
+use std::fmt::{Display, Formatter};
+use crate::{encoded_ext_data_size, FromBpx, ToBpx};
+
+/// Current format version of the extended data of symbols.
+//Version 2 added the layout rule of structs, 1D and multisample textures and comparison samplers.
+pub const FORMAT_VERSION: u16 = 2;
+
+/// Name of the property holding the format version of an object.
+pub const VERSION_KEY: &str = "__version__";
+
+/// Number of bytes added by the version property to the encoding of an object.
+pub const VERSION_SIZE: usize = 8 + 1 + 2;
+
+#[derive(Debug)]
+pub enum FromBpxError
+{
+    Serde(bpx::sd::serde::Error),
+    UnsupportedVersion
+    {
+        found: u16,
+        expected: u16
+    }
+}
+
+impl Display for FromBpxError
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+    {
+        match self {
+            FromBpxError::Serde(e) => write!(f, "BPX deserialization error: {}", e),
+            FromBpxError::UnsupportedVersion { found, expected } => write!(f, "unsupported extended data format version {} (expected at most {}); was the shader built by a newer shaderc?", found, expected)
+        }
+    }
+}
+
+impl std::error::Error for FromBpxError {}
+
+impl From<bpx::sd::serde::Error> for FromBpxError
+{
+    fn from(e: bpx::sd::serde::Error) -> Self
+    {
+        FromBpxError::Serde(e)
+    }
+}
+
+/// Returns the format version an object was written with, 0 if it has none.
+pub fn format_version(val: &bpx::sd::Value) -> u16
+{
+    match val.as_object().and_then(|v| v.raw_get(bpx::utils::hash(VERSION_KEY))) {
+        Some(bpx::sd::Value::Uint16(v)) => *v,
+        _ => 0
+    }
+}
+
+/// Checks that an object can be read by this version of the symbols crate.
+pub fn check_version(val: &bpx::sd::Value) -> Result<(), FromBpxError>
+{
+    let found = format_version(val);
+    if found > FORMAT_VERSION {
+        return Err(FromBpxError::UnsupportedVersion {
+            found,
+            expected: FORMAT_VERSION
+        });
+    }
+    Ok(())
+}
+
+//Number of bytes written for the extended data of a symbol.
+fn encoded_len(value: &bpx::sd::Value) -> usize
+{
+    let mut buffer = Vec::new();
+    if let bpx::sd::Value::Object(obj) = value {
+        obj.write(&mut buffer).expect("failed to write object");
+    }
+    buffer.len()
+}
+
+/// Panics if `value` does not survive an encoding round-trip, with and without debug symbols, or
+/// if [encoded_ext_data_size] does not match the size of its encoding.
+pub fn validate_roundtrip<T: ToBpx + FromBpx + PartialEq>(value: &T)
+{
+    for debug in [false, true] {
+        let encoded = value.to_bpx(debug).expect("failed to encode object");
+        assert_eq!(format_version(&encoded), FORMAT_VERSION, "encoded object is not versioned");
+        let size = encoded_ext_data_size(value, debug).expect("failed to measure object");
+        assert_eq!(size, encoded_len(&encoded), "estimated size differs from the encoding (debug: {})", debug);
+        let decoded = T::from_bpx(&encoded).expect("failed to decode object");
+        assert!(decoded == *value, "object changed after a round-trip (debug: {})", debug);
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use bp3d_sal::ast::tree::{AddressMode, Filter};
+    use crate::{check_version, format_version, FORMAT_VERSION, FromBpx, FromBpxError, SamplerObject, ToBpx, VERSION_KEY};
+
+    #[test]
+    fn versions()
+    {
+        let obj = SamplerObject {
+            min_filter: Filter::Linear,
+            mag_filter: Filter::Linear,
+            address_u: AddressMode::Repeat,
+            address_v: AddressMode::Repeat,
+            address_w: AddressMode::Repeat,
+            anisotropy: 1,
+            comparison: false
+        };
+        let mut value = obj.to_bpx(false).unwrap();
+        assert_eq!(format_version(&value), FORMAT_VERSION);
+        assert!(check_version(&value).is_ok());
+        if let bpx::sd::Value::Object(v) = &mut value {
+            v.set(VERSION_KEY, bpx::sd::Value::Uint16(FORMAT_VERSION + 1));
+        }
+        match SamplerObject::from_bpx(&value) {
+            Err(FromBpxError::UnsupportedVersion { found, expected }) => {
+                assert_eq!(found, FORMAT_VERSION + 1);
+                assert_eq!(expected, FORMAT_VERSION);
+            },
+            _ => panic!("expected an unsupported version error")
+        }
+        //Objects written before versioning are still readable.
+        assert_eq!(format_version(&bpx::sd::Value::Object(bpx::sd::Object::new())), 0);
+    }
+}